@@ -0,0 +1,25 @@
+//! Feeds arbitrary bytes as a single layer archive through `tar_extractor::extract_tar`,
+//! twice in a row to exercise overlay behavior (whiteouts, replaced entries, links).
+//!
+//! Any outcome is fine except a panic or a write outside of the scratch directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oci2git::tar_extractor::extract_tar;
+
+fuzz_target!(|data: &[u8]| {
+    let scratch = tempfile::tempdir().unwrap();
+    let layer = scratch.path().join("layer.tar");
+    std::fs::write(&layer, data).unwrap();
+
+    let rootfs = scratch.path().join("rootfs");
+    let _ = extract_tar(&layer, &rootfs);
+    let _ = extract_tar(&layer, &rootfs);
+
+    // Nothing but the layer and the rootfs may exist next to each other
+    for entry in std::fs::read_dir(scratch.path()).unwrap() {
+        let name = entry.unwrap().file_name();
+        assert!(name == "layer.tar" || name == "rootfs", "escaped: {name:?}");
+    }
+});
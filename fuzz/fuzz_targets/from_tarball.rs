@@ -0,0 +1,30 @@
+//! Feeds arbitrary bytes as a whole image tarball through `ExtractedImage::from_tarball`,
+//! then replays every layer the same way the processor does.
+//!
+//! Any outcome is fine except a panic or a write outside of the scratch directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oci2git::{ExtractedImage, Notifier};
+
+fuzz_target!(|data: &[u8]| {
+    let scratch = tempfile::tempdir().unwrap();
+    let tarball = scratch.path().join("image.tar");
+    std::fs::write(&tarball, data).unwrap();
+
+    let notifier = Notifier::new(0);
+    let Ok(image) = ExtractedImage::from_tarball(&tarball, &notifier) else {
+        return;
+    };
+
+    let _ = image.metadata("fuzz");
+    let rootfs = scratch.path().join("rootfs");
+    if let Ok(layers) = image.layers() {
+        for layer in layers {
+            if let Some(layer_tarball) = &layer.tarball_path {
+                let _ = image.extract_layer_to(layer_tarball, &rootfs);
+            }
+        }
+    }
+});
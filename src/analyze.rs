@@ -0,0 +1,390 @@
+//! `oci2git analyze`: where the bytes of a converted image go.
+//!
+//! [`analyze`] walks the layer commits of each image branch and reports, like `dive`:
+//! - the largest files each layer added or modified (see [`LayerStats::between_top`]),
+//! - wasted space: files a layer added that a later layer deleted or overwrote. They still
+//!   ship with the image, in the layer that added them, without being visible in the
+//!   final filesystem.
+//! - duplicated content: identical files written more than once, by several layers or at
+//!   several paths, with the bytes a single copy would save. It needs the per-layer hash
+//!   lists of a conversion with `--content-hashes` (see [`crate::content_hashes`]).
+//!
+//! [`Analysis::render_markdown`] turns it into `ANALYSIS.md`, which [`commit`] stores next
+//! to `README.md` on [`README_BRANCH`]; the struct itself serializes to JSON.
+
+use crate::content_hashes::{ContentHashes, CONTENT_HASHES_PATH};
+use crate::disk_space::format_bytes;
+use crate::git::GitRepo;
+use crate::image_metadata::ImageMetadata;
+use crate::layer_stats::LayerStats;
+use crate::query::{image_branches, rootfs_tree};
+use crate::repo_readme::{escape_cell, README_BRANCH};
+use crate::trailers;
+use anyhow::{Context, Result};
+use git2::{Delta, Repository, Tree};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Report file on [`README_BRANCH`].
+pub const ANALYSIS_MD: &str = "ANALYSIS.md";
+/// Rows of the wasted space and duplicate tables per image in the Markdown report; the
+/// JSON report lists every file.
+const MAX_FILE_ROWS: usize = 50;
+
+/// Analysis of every analyzed image branch.
+#[derive(Debug, Clone, Serialize)]
+pub struct Analysis {
+    pub images: Vec<ImageAnalysis>,
+}
+
+/// One converted image (branch).
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageAnalysis {
+    pub branch: String,
+    /// Per layer statistics with its largest files
+    pub layers: Vec<LayerStats>,
+    /// Total size of [`ImageAnalysis::wasted_files`]
+    pub wasted_bytes: u64,
+    /// Files deleted or overwritten after being added, largest first
+    pub wasted_files: Vec<WastedFile>,
+    /// Total of [`DuplicateContent::savings`]
+    pub duplicate_bytes: u64,
+    /// Content written more than once, largest savings first; empty unless the image was
+    /// converted with content hashes
+    pub duplicates: Vec<DuplicateContent>,
+}
+
+/// A file one layer added and a later layer deleted or overwrote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WastedFile {
+    /// Path in the image
+    pub path: String,
+    pub size: u64,
+    /// Layer that added this version of the file
+    pub added_in: usize,
+    /// Layer that deleted or overwrote it
+    pub removed_in: usize,
+}
+
+/// Identical content written more than once.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateContent {
+    /// Hex SHA-256 of the content
+    pub sha256: String,
+    pub size: u64,
+    /// Every write of the content, in layer order
+    pub copies: Vec<FileCopy>,
+    /// Bytes saved by writing the content only once
+    pub savings: u64,
+}
+
+/// A path a layer wrote some content to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileCopy {
+    pub path: String,
+    pub layer: usize,
+}
+
+/// Analyzes `branch`, or every image branch, listing the `top` largest files per layer.
+///
+/// # Errors
+/// - `branch` doesn't exist, unparsable metadata, or Git failures.
+pub fn analyze(repo: &GitRepo, branch: Option<&str>, top: usize) -> Result<Analysis> {
+    let mut images = Vec::new();
+    for branch in image_branches(repo, branch)? {
+        images.push(
+            analyze_branch(repo, &branch, top)
+                .with_context(|| format!("Failed to analyze branch '{branch}'"))?,
+        );
+    }
+    Ok(Analysis { images })
+}
+
+fn analyze_branch(repo: &GitRepo, branch: &str, top: usize) -> Result<ImageAnalysis> {
+    let commits = repo.get_branch_commits(branch)?;
+    let metadata = match commits.last() {
+        Some(&tip) => ImageMetadata::read_from_commit(repo, tip)?,
+        None => None,
+    };
+
+    let mut analysis = ImageAnalysis {
+        branch: branch.to_string(),
+        layers: Vec::new(),
+        wasted_bytes: 0,
+        wasted_files: Vec::new(),
+        duplicate_bytes: 0,
+        duplicates: Vec::new(),
+    };
+    let mut contents: BTreeMap<String, DuplicateContent> = BTreeMap::new();
+    // Layer that wrote the current version of each path
+    let mut written_by = HashMap::new();
+    let mut previous = None;
+    for oid in commits {
+        let commit = repo.repo.find_commit(oid)?;
+        let rootfs = rootfs_tree(&repo.repo, &commit.tree()?)?;
+        if let Some(layer) = trailers::parse(commit.message().unwrap_or_default())? {
+            let mut stats =
+                LayerStats::between_top(&repo.repo, previous.as_ref(), rootfs.as_ref(), top)?;
+            stats.index = layer.index;
+            stats.digest = layer.digest;
+            if let Some(recorded) = metadata.as_ref().and_then(|metadata| {
+                layer
+                    .index
+                    .checked_sub(1)
+                    .and_then(|index| metadata.layer_digests.get(index))
+            }) {
+                stats.command = recorded.command.clone();
+            }
+            track_waste(
+                &repo.repo,
+                previous.as_ref(),
+                rootfs.as_ref(),
+                layer.index,
+                &mut written_by,
+                &mut analysis.wasted_files,
+            )?;
+            if let Ok(content) = repo.read_file_from_commit(oid, CONTENT_HASHES_PATH) {
+                for file in ContentHashes::parse(&content)?.entries.into_values() {
+                    // Empty files are all alike and cost nothing
+                    if file.size == 0 {
+                        continue;
+                    }
+                    contents
+                        .entry(file.sha256.clone())
+                        .or_insert_with(|| DuplicateContent {
+                            sha256: file.sha256,
+                            size: file.size,
+                            copies: Vec::new(),
+                            savings: 0,
+                        })
+                        .copies
+                        .push(FileCopy {
+                            path: file.path,
+                            layer: layer.index,
+                        });
+                }
+            }
+            analysis.layers.push(stats);
+        }
+        previous = rootfs;
+    }
+
+    analysis.wasted_bytes = analysis.wasted_files.iter().map(|file| file.size).sum();
+    analysis
+        .wasted_files
+        .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    analysis.duplicates = contents
+        .into_values()
+        .filter(|content| content.copies.len() > 1)
+        .map(|mut content| {
+            content.savings = content.size * (content.copies.len() as u64 - 1);
+            content
+        })
+        .collect();
+    analysis.duplicate_bytes = analysis.duplicates.iter().map(|d| d.savings).sum();
+    analysis.duplicates.sort_by(|a, b| {
+        b.savings
+            .cmp(&a.savings)
+            .then_with(|| a.sha256.cmp(&b.sha256))
+    });
+    Ok(analysis)
+}
+
+/// Records the previous versions of the files `index` deleted or overwrote as wasted, and
+/// the files it wrote in `written_by`.
+fn track_waste(
+    repo: &Repository,
+    old: Option<&Tree>,
+    new: Option<&Tree>,
+    index: usize,
+    written_by: &mut HashMap<String, usize>,
+    wasted: &mut Vec<WastedFile>,
+) -> Result<()> {
+    let odb = repo.odb()?;
+    let diff = repo.diff_tree_to_tree(old, new, None)?;
+    for delta in diff.deltas() {
+        let file = if delta.status() == Delta::Deleted {
+            delta.old_file()
+        } else {
+            delta.new_file()
+        };
+        let Some(path) = file.path() else {
+            continue;
+        };
+        let path = format!("/{}", path.to_string_lossy());
+        if delta.status() != Delta::Added {
+            let (size, _) = odb.read_header(delta.old_file().id())?;
+            wasted.push(WastedFile {
+                path: path.clone(),
+                size: size as u64,
+                added_in: written_by.get(&path).copied().unwrap_or_default(),
+                removed_in: index,
+            });
+        }
+        if delta.status() == Delta::Deleted {
+            written_by.remove(&path);
+        } else {
+            written_by.insert(path, index);
+        }
+    }
+    Ok(())
+}
+
+impl Analysis {
+    /// Markdown report: per image the wasted space and the largest files of each layer.
+    pub fn render_markdown(&self) -> String {
+        let mut markdown = String::from("# Image Analysis\n");
+        for image in &self.images {
+            markdown.push_str(&format!("\n## {}\n\n", image.branch));
+            markdown.push_str(&format!(
+                "Wasted space: {} in {} files deleted or overwritten by later layers\n\n",
+                format_bytes(image.wasted_bytes),
+                image.wasted_files.len()
+            ));
+
+            markdown.push_str("### Largest Files\n\n");
+            markdown.push_str("| Layer | Command | Size | Path |\n");
+            markdown.push_str("|-------|---------|------|------|\n");
+            for layer in &image.layers {
+                for file in &layer.largest_files {
+                    markdown.push_str(&format!(
+                        "| {} | `{}` | {} | `{}` |\n",
+                        layer.index,
+                        escape_cell(&layer.command),
+                        format_bytes(file.size),
+                        escape_cell(&file.path)
+                    ));
+                }
+            }
+
+            if !image.wasted_files.is_empty() {
+                markdown.push_str("\n### Wasted Space\n\n");
+                markdown.push_str("| Size | Path | Added in | Removed in |\n");
+                markdown.push_str("|------|------|----------|------------|\n");
+                for file in image.wasted_files.iter().take(MAX_FILE_ROWS) {
+                    markdown.push_str(&format!(
+                        "| {} | `{}` | {} | {} |\n",
+                        format_bytes(file.size),
+                        escape_cell(&file.path),
+                        file.added_in,
+                        file.removed_in
+                    ));
+                }
+                if image.wasted_files.len() > MAX_FILE_ROWS {
+                    markdown.push_str(&format!(
+                        "\n…and {} more\n",
+                        image.wasted_files.len() - MAX_FILE_ROWS
+                    ));
+                }
+            }
+
+            if !image.duplicates.is_empty() {
+                markdown.push_str("\n### Duplicated Content\n\n");
+                markdown.push_str(&format!(
+                    "Writing each content only once would save {}\n\n",
+                    format_bytes(image.duplicate_bytes)
+                ));
+                markdown.push_str("| Savings | Size | Copies (layer: path) |\n");
+                markdown.push_str("|---------|------|----------------------|\n");
+                for duplicate in image.duplicates.iter().take(MAX_FILE_ROWS) {
+                    let copies: Vec<String> = duplicate
+                        .copies
+                        .iter()
+                        .map(|copy| format!("{}: `{}`", copy.layer, escape_cell(&copy.path)))
+                        .collect();
+                    markdown.push_str(&format!(
+                        "| {} | {} | {} |\n",
+                        format_bytes(duplicate.savings),
+                        format_bytes(duplicate.size),
+                        copies.join(", ")
+                    ));
+                }
+                if image.duplicates.len() > MAX_FILE_ROWS {
+                    markdown.push_str(&format!(
+                        "\n…and {} more\n",
+                        image.duplicates.len() - MAX_FILE_ROWS
+                    ));
+                }
+            }
+        }
+        markdown
+    }
+}
+
+/// Commits the Markdown report as [`ANALYSIS_MD`] on [`README_BRANCH`]. Returns `true`
+/// if it changed.
+///
+/// # Errors
+/// - Commit failures.
+pub fn commit(repo: &GitRepo, analysis: &Analysis) -> Result<bool> {
+    repo.commit_file_to_branch(
+        README_BRANCH,
+        ANALYSIS_MD,
+        &analysis.render_markdown(),
+        "🔍 - Update analysis",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trailers::LayerTrailers;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_wasted_space() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("app")).unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::write(rootfs.join("cache"), "1234567890").unwrap();
+        fs::write(rootfs.join("config"), "abc").unwrap();
+        repo.commit_layer("ADD", &LayerTrailers::new(1, "sha256:1"))
+            .unwrap();
+        fs::write(rootfs.join("config"), "abcd").unwrap();
+        repo.commit_layer("RUN", &LayerTrailers::new(2, "sha256:2"))
+            .unwrap();
+        fs::remove_file(rootfs.join("cache")).unwrap();
+        repo.commit_layer("RUN", &LayerTrailers::new(3, "sha256:3"))
+            .unwrap();
+
+        let analysis = analyze(&repo, None, 1).unwrap();
+        let image = &analysis.images[0];
+        assert_eq!(image.layers.len(), 3);
+        assert_eq!(image.layers[0].largest_files.len(), 1);
+        assert_eq!(image.layers[0].largest_files[0].path, "/cache");
+        assert_eq!(image.wasted_bytes, 13);
+        assert_eq!(
+            image.wasted_files,
+            vec![
+                WastedFile {
+                    path: "/cache".to_string(),
+                    size: 10,
+                    added_in: 1,
+                    removed_in: 3
+                },
+                WastedFile {
+                    path: "/config".to_string(),
+                    size: 3,
+                    added_in: 1,
+                    removed_in: 2
+                }
+            ]
+        );
+
+        assert!(commit(&repo, &analysis).unwrap());
+        let readme_tip = *repo
+            .get_branch_commits(README_BRANCH)
+            .unwrap()
+            .last()
+            .unwrap();
+        let markdown = repo.read_file_from_commit(readme_tip, ANALYSIS_MD).unwrap();
+        assert!(markdown.contains("Wasted space: 13 B in 2 files"));
+        assert!(markdown.contains("| 10 B | `/cache` | 1 | 3 |"));
+        // Nothing changed, nothing to commit
+        assert!(!commit(&repo, &analysis).unwrap());
+    }
+}
@@ -0,0 +1,119 @@
+//! Supply-chain artifacts attached to an image: signatures, attestations and SBOMs.
+//!
+//! Registries store them as separate manifests that point at the image, found through
+//! the attestation manifest BuildKit adds to the image index, through cosign's tag scheme
+//! (`<repo>:sha256-<hex>.sig`, `.att`, `.sbom`, see [`cosign_tag`]) or through the OCI 1.1
+//! referrers API. The registry source writes every
+//! artifact it finds into the image tarball under [`ATTESTATIONS_DIR`], and the metadata
+//! commit copies that directory into the repository (see [`copy`]):
+//!
+//! ```text
+//! attestations/
+//! ├── buildkit/                # attestation manifest of the image index (SLSA provenance)
+//! ├── cosign-sig/              # <repo>:sha256-<hex>.sig
+//! │   ├── manifest.json        # annotations carry the signature and certificate
+//! │   └── <hex>.json           # one file per blob (simple signing payload)
+//! ├── cosign-att/              # DSSE envelopes, e.g. SLSA provenance
+//! ├── cosign-sbom/
+//! └── referrers/<hex>/         # one directory per artifact from the referrers API
+//! ```
+//!
+//! They live in the metadata commit rather than in a layer commit, because layer commits
+//! are shared with every other image built on the same layers.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::blob_cache::digest_hex;
+
+/// Directory of the attached artifacts, in the image tarball and in the repository.
+pub const ATTESTATIONS_DIR: &str = "attestations";
+
+/// Tag suffixes cosign stores signatures, attestations and SBOMs under.
+pub const COSIGN_SUFFIXES: &[&str] = &["sig", "att", "sbom"];
+
+/// Tag cosign attaches `suffix` artifacts of the image `digest` to:
+/// `sha256:abc…` → `sha256-abc….sig`.
+pub fn cosign_tag(digest: &str, suffix: &str) -> Result<String> {
+    Ok(format!("sha256-{}.{suffix}", digest_hex(digest)?))
+}
+
+/// File name of an artifact blob: its digest, with `.json` for JSON media types.
+pub fn blob_file_name(digest: &str, media_type: &str) -> Result<String> {
+    let hex = digest_hex(digest)?;
+    Ok(if media_type.contains("json") {
+        format!("{hex}.json")
+    } else {
+        hex.to_string()
+    })
+}
+
+/// Replaces `to` with the directories and regular files under `from`; only removes `to`
+/// if `from` doesn't exist. Symlinks and special files are skipped.
+pub fn copy(from: &Path, to: &Path) -> Result<()> {
+    if fs::symlink_metadata(to).is_ok() {
+        fs::remove_dir_all(to).with_context(|| format!("Failed to remove {}", to.display()))?;
+    }
+    if !from.is_dir() {
+        return Ok(());
+    }
+    copy_dir(from, to)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    for entry in fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = to.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cosign_tag() {
+        assert_eq!(
+            cosign_tag("sha256:abc123", "sig").unwrap(),
+            "sha256-abc123.sig"
+        );
+        assert!(cosign_tag("latest", "sig").is_err());
+        assert_eq!(
+            blob_file_name("sha256:abc123", "application/vnd.dsse.envelope.v1+json").unwrap(),
+            "abc123.json"
+        );
+        assert_eq!(
+            blob_file_name("sha256:abc123", "application/octet-stream").unwrap(),
+            "abc123"
+        );
+    }
+
+    #[test]
+    fn test_copy_replaces_target() {
+        let dir = tempdir().unwrap();
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+        fs::create_dir_all(from.join("cosign-sig")).unwrap();
+        fs::write(from.join("cosign-sig/manifest.json"), "{}").unwrap();
+        fs::create_dir_all(&to).unwrap();
+        fs::write(to.join("stale.json"), "{}").unwrap();
+
+        copy(&from, &to).unwrap();
+        assert!(to.join("cosign-sig/manifest.json").exists());
+        assert!(!to.join("stale.json").exists());
+
+        copy(&dir.path().join("missing"), &to).unwrap();
+        assert!(!to.exists());
+    }
+}
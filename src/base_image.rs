@@ -0,0 +1,263 @@
+//! Base image lineage: the image an image was built `FROM`.
+//!
+//! An image can name its base through the `org.opencontainers.image.base.name` and
+//! `org.opencontainers.image.base.digest` annotations of its manifest, or labels of the
+//! same name ([`BaseImage::declared`]). Independently of that, an image branch of the
+//! repository is its base when the whole layer chain recorded at the branch tip is a
+//! prefix of the image's layers ([`find_branch`]); the longest such chain wins.
+//!
+//! [`detect`] merges both into the `## Base Image` section of `Image.md` (`base` in
+//! `Image.json`), and the metadata commit gets [`BASE_IMAGE`] and [`BASE_BRANCH`]
+//! trailers. Since the image branches from the layer commits of its base, the layers it
+//! added on top are a plain range:
+//!
+//! ```text
+//! git log --oneline 'ubuntu#24.04#linux-amd64#…'..'app#latest#linux-amd64#…'
+//! ```
+//!
+//! With [`crate::processor::ImageProcessor::with_convert_base`], the declared base is
+//! converted into the repository first, so the image branches from it.
+
+use crate::digest_tracker::DigestTracker;
+use crate::extracted_image::Layer;
+use crate::git::GitRepo;
+use crate::image_metadata::ImageMetadata;
+use crate::layer_records;
+use crate::metadata;
+use crate::query::image_branches;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Annotation (or label) naming the base image, e.g. `docker.io/library/ubuntu:24.04`.
+pub const BASE_NAME: &str = "org.opencontainers.image.base.name";
+/// Annotation (or label) with the manifest digest of the base image.
+pub const BASE_DIGEST: &str = "org.opencontainers.image.base.digest";
+/// Trailer with the base image name.
+pub const BASE_IMAGE: &str = "Base-Image";
+/// Trailer with the branch of the base image.
+pub const BASE_BRANCH: &str = "Base-Branch";
+
+/// What is known about the base of an image.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaseImage {
+    /// Image reference, as declared or as recorded on [`BaseImage::branch`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Manifest digest, as declared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Image branch of the base in the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Number of layers (history entries) that come from the base
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layers: Option<usize>,
+}
+
+impl BaseImage {
+    /// The base `metadata` declares, manifest annotations first; `None` if it names none.
+    pub fn declared(metadata: &metadata::ImageMetadata) -> Option<Self> {
+        let labels = metadata.container_config.labels.as_ref();
+        let value = |key: &str| {
+            metadata
+                .annotations
+                .get(key)
+                .or_else(|| labels.and_then(|labels| labels.get(key)))
+                .filter(|value| !value.is_empty())
+                .cloned()
+        };
+        let name = value(BASE_NAME)?;
+        Some(Self {
+            name: Some(name),
+            digest: value(BASE_DIGEST),
+            ..Self::default()
+        })
+    }
+
+    /// Reference to pull the base with: the name pinned to the digest when both are known.
+    pub fn reference(&self) -> Option<String> {
+        let name = self.name.as_deref()?;
+        Some(match &self.digest {
+            Some(digest) if !name.contains('@') => {
+                // A tag can't be combined with a digest
+                let repository = match name.rsplit_once(':') {
+                    Some((repository, tag)) if !tag.contains('/') => repository,
+                    _ => name,
+                };
+                format!("{repository}@{digest}")
+            }
+            _ => name.to_string(),
+        })
+    }
+
+    /// Trailers for the metadata commit, one line each.
+    pub fn trailers(&self) -> String {
+        [(BASE_IMAGE, &self.name), (BASE_BRANCH, &self.branch)]
+            .into_iter()
+            .filter_map(|(key, value)| Some(format!("{key}: {}\n", value.as_deref()?)))
+            .collect()
+    }
+}
+
+/// The image branch other than `branch` whose layer chain is the longest proper prefix of
+/// `layers`, with the length of that chain.
+///
+/// # Errors
+/// - Git failures; unparseable layer records make a branch unmatched instead.
+pub fn find_branch(
+    repo: &GitRepo,
+    branch: &str,
+    layers: &[Layer],
+) -> Result<Option<(String, usize)>> {
+    let mut found: Option<(String, usize)> = None;
+    for other in image_branches(repo, None)? {
+        if other == branch {
+            continue;
+        }
+        let Some(&tip) = repo.get_branch_commits(&other)?.last() else {
+            continue;
+        };
+        let Ok(Some(layer_digests)) = layer_records::read_chain(repo, tip) else {
+            continue;
+        };
+        let length = layer_digests.len();
+        if length == 0
+            || length >= layers.len()
+            || found.as_ref().is_some_and(|(_, n)| *n >= length)
+        {
+            continue;
+        }
+        let tracker = DigestTracker { layer_digests };
+        if (0..length).all(|position| tracker.layer_matches(position, &layers[position])) {
+            found = Some((other, length));
+        }
+    }
+    Ok(found)
+}
+
+/// The base of the image converted into `branch`, whose metadata and layers are given;
+/// `None` if it declares none and no other branch is its base.
+///
+/// # Errors
+/// - See [`find_branch`].
+pub fn detect(
+    repo: &GitRepo,
+    branch: &str,
+    metadata: &metadata::ImageMetadata,
+    layers: &[Layer],
+) -> Result<Option<BaseImage>> {
+    let declared = BaseImage::declared(metadata);
+    let Some((base_branch, length)) = find_branch(repo, branch, layers)? else {
+        return Ok(declared);
+    };
+    let mut base = declared.unwrap_or_default();
+    if base.name.is_none() {
+        let tip = repo.get_branch_commits(&base_branch)?.last().copied();
+        base.name = tip
+            .and_then(|tip| ImageMetadata::read_from_commit(repo, tip).ok().flatten())
+            .and_then(|metadata| metadata.basic_info)
+            .map(|info| info.name)
+            .filter(|name| !name.is_empty());
+    }
+    base.branch = Some(base_branch);
+    base.layers = Some(length);
+    Ok(Some(base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_metadata::LayerDigest;
+    use crate::trailers::LayerTrailers;
+    use chrono::{TimeZone, Utc};
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn layer(index: usize) -> Layer {
+        Layer {
+            id: format!("sha256:{index}"),
+            command: format!("RUN step {index}"),
+            created_at: Utc.timestamp_opt(index as i64 * 60, 0).unwrap(),
+            is_empty: false,
+            tarball_path: Some(format!("{index}/layer.tar").into()),
+            digest: format!("sha256:{index}"),
+            comment: None,
+            size: 1,
+            diff_id: None,
+        }
+    }
+
+    fn declared_metadata(labels: &[(&str, &str)]) -> metadata::ImageMetadata {
+        let config = serde_json::json!({
+            "Id": "sha256:app",
+            "Created": "",
+            "Config": {"Labels": labels.iter().cloned().collect::<HashMap<_, _>>()},
+            "Architecture": "amd64",
+            "Os": "linux",
+        });
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn test_declared() {
+        let mut metadata = declared_metadata(&[(BASE_NAME, "docker.io/library/alpine:3.20")]);
+        let base = BaseImage::declared(&metadata).unwrap();
+        assert_eq!(base.name.as_deref(), Some("docker.io/library/alpine:3.20"));
+        assert_eq!(base.reference().unwrap(), "docker.io/library/alpine:3.20");
+
+        metadata.annotations.insert(
+            BASE_NAME.to_string(),
+            "localhost:5000/alpine:3.21".to_string(),
+        );
+        metadata
+            .annotations
+            .insert(BASE_DIGEST.to_string(), "sha256:abc".to_string());
+        let base = BaseImage::declared(&metadata).unwrap();
+        assert_eq!(
+            base.reference().unwrap(),
+            "localhost:5000/alpine@sha256:abc"
+        );
+        assert_eq!(base.trailers(), "Base-Image: localhost:5000/alpine:3.21\n");
+
+        assert!(BaseImage::declared(&declared_metadata(&[])).is_none());
+    }
+
+    #[test]
+    fn test_find_branch() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("base")).unwrap();
+        let layers: Vec<_> = (1..=3).map(layer).collect();
+        let rootfs = temp_dir.path().join("rootfs");
+        let mut chain = Vec::new();
+        for layer in &layers[..2] {
+            fs::create_dir_all(&rootfs).unwrap();
+            fs::write(rootfs.join(&layer.digest[7..]), "x").unwrap();
+            chain.push(LayerDigest {
+                digest: layer.digest.clone(),
+                command: layer.command.clone(),
+                created: layer.created_at.to_rfc3339(),
+                is_empty: false,
+                comment: None,
+                verified: None,
+            });
+            layer_records::save(temp_dir.path(), &chain).unwrap();
+            repo.commit_layer(
+                &layer.command,
+                &LayerTrailers::new(chain.len(), &layer.digest),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            find_branch(&repo, "app", &layers).unwrap(),
+            Some(("base".to_string(), 2))
+        );
+        // Neither the image itself nor an image with as many layers is a base
+        assert_eq!(find_branch(&repo, "base", &layers).unwrap(), None);
+        assert_eq!(find_branch(&repo, "app", &layers[..2]).unwrap(), None);
+        let mut other = layers.clone();
+        other[1].id = "sha256:other".to_string();
+        assert_eq!(find_branch(&repo, "app", &other).unwrap(), None);
+    }
+}
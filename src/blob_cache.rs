@@ -0,0 +1,152 @@
+//! Content-addressed cache for downloaded blobs, with resumable partial downloads.
+//!
+//! [`BlobCache`] stores blobs under `<root>/sha256/<hex>`; a file only lands there after
+//! its sha256 matched the digest it is stored under, so cached blobs can be used without
+//! re-verification. Downloads in progress live next to it as `<hex>.partial` and are
+//! resumed from their current length by the next attempt (see
+//! [`BlobCache::partial_path`] and [`BlobCache::complete`]).
+//!
+//! The default location is `$XDG_CACHE_HOME/oci2git/blobs` (or `~/.cache/oci2git/blobs`),
+//! see [`BlobCache::default_dir`].
+
+use anyhow::{anyhow, bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A directory of verified blobs keyed by digest.
+#[derive(Debug, Clone)]
+pub struct BlobCache {
+    root: PathBuf,
+}
+
+impl BlobCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The per-user cache directory, falling back to the system temp dir.
+    pub fn default_dir() -> PathBuf {
+        cache_dir("blobs")
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where the verified blob for `digest` is stored.
+    pub fn path(&self, digest: &str) -> Result<PathBuf> {
+        Ok(self.root.join("sha256").join(digest_hex(digest)?))
+    }
+
+    /// Where a download of `digest` in progress is kept. The parent directory is created.
+    pub fn partial_path(&self, digest: &str) -> Result<PathBuf> {
+        let dir = self.root.join("sha256");
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create blob cache: {}", dir.display()))?;
+        Ok(dir.join(format!("{}.partial", digest_hex(digest)?)))
+    }
+
+    /// The cached blob for `digest`, if present.
+    pub fn get(&self, digest: &str) -> Result<Option<PathBuf>> {
+        let path = self.path(digest)?;
+        Ok(path.is_file().then_some(path))
+    }
+
+    /// Verify the finished partial download of `digest` and move it into the cache.
+    ///
+    /// # Errors
+    /// - The content does not hash to `digest`; the partial file is deleted so the next
+    ///   attempt starts over.
+    pub fn complete(&self, digest: &str) -> Result<PathBuf> {
+        let partial = self.partial_path(digest)?;
+        let actual = sha256_file(&partial)?;
+        if actual != digest {
+            let _ = fs::remove_file(&partial);
+            bail!("Digest mismatch for downloaded blob {digest}: content hashes to {actual}");
+        }
+
+        let path = self.path(digest)?;
+        fs::rename(&partial, &path)
+            .with_context(|| format!("Failed to store blob in cache: {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// `sha256:<hex>` digest of the file at `path`.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to hash {}", path.display()))?;
+    let hex: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(format!("sha256:{hex}"))
+}
+
+/// Hex part of a `sha256:<hex>` digest, rejecting anything that is not a plain file name.
+/// Directory `name` of the per-user oci2git cache, `$XDG_CACHE_HOME/oci2git/<name>` (or
+/// `~/.cache/oci2git/<name>`), falling back to `oci2git-<name>` in the system temp dir.
+pub(crate) fn cache_dir(name: &str) -> PathBuf {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")));
+
+    match cache_home {
+        Some(dir) => dir.join("oci2git").join(name),
+        None => std::env::temp_dir().join(format!("oci2git-{name}")),
+    }
+}
+
+pub(crate) fn digest_hex(digest: &str) -> Result<&str> {
+    digest
+        .strip_prefix("sha256:")
+        .filter(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| anyhow!("Unsupported digest: '{digest}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const HELLO_DIGEST: &str =
+        "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_complete_verifies_digest() {
+        let temp_dir = tempdir().unwrap();
+        let cache = BlobCache::new(temp_dir.path());
+        assert!(cache.get(HELLO_DIGEST).unwrap().is_none());
+
+        fs::write(cache.partial_path(HELLO_DIGEST).unwrap(), "hello").unwrap();
+        let path = cache.complete(HELLO_DIGEST).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        assert_eq!(cache.get(HELLO_DIGEST).unwrap(), Some(path));
+        assert!(!cache.partial_path(HELLO_DIGEST).unwrap().exists());
+
+        let other = format!("sha256:{}", "0".repeat(64));
+        fs::write(cache.partial_path(&other).unwrap(), "hello").unwrap();
+        let err = cache.complete(&other).unwrap_err();
+        assert!(err.to_string().contains("Digest mismatch"));
+        assert!(!cache.partial_path(&other).unwrap().exists());
+        assert!(cache.get(&other).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rejects_path_like_digests() {
+        let cache = BlobCache::new("/cache");
+        assert!(cache.path("sha256:../../etc").is_err());
+        assert!(cache.path("md5:abcd").is_err());
+        assert_eq!(
+            cache.path("sha256:abcd").unwrap(),
+            Path::new("/cache/sha256/abcd")
+        );
+    }
+}
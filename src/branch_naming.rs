@@ -0,0 +1,266 @@
+//! Configurable branch names.
+//!
+//! By default every source names branches its own way ([`crate::sources::Source::branch_name`],
+//! e.g. `nginx#1.25#linux-amd64#1234567890ab`). A [`BranchNamingStrategy`] set with
+//! [`crate::processor::ImageProcessor::with_branch_naming`] replaces that scheme for all
+//! sources. [`BranchTemplate`] is the strategy behind `--branch-template`: slash-separated
+//! templates give hierarchical names that Git and most UIs group like directories:
+//!
+//! ```text
+//! --branch-template '{name}/{tag}/{arch}'   →  library/nginx/1.25/amd64
+//! --branch-template 'images/{name}#{os}-{arch}'  →  images/library/nginx#linux-amd64
+//! ```
+//!
+//! The same templates name the annotated tags of `--tag`/`--tag-template`
+//! ([`DEFAULT_TAG_TEMPLATE`] by default).
+//!
+//! Substituted values are made ref-safe (`localhost:5000` becomes `localhost-5000`); the
+//! template's own text is used as is, so the result must still be a valid branch name.
+
+use crate::metadata::ImageMetadata;
+use crate::sources::extract_short_digest;
+use anyhow::{bail, Result};
+use std::path::Path;
+
+/// Tag names of `--tag` unless `--tag-template` says otherwise, e.g.
+/// `nginx-1.27.0-amd64-sha256-1234567890ab`.
+pub const DEFAULT_TAG_TEMPLATE: &str = "{name}-{tag}-{arch}-sha256-{digest}";
+
+/// Placeholders understood by [`BranchTemplate`].
+pub const PLACEHOLDERS: &[&str] = &["name", "tag", "os", "arch", "digest", "source"];
+
+/// What a [`BranchNamingStrategy`] knows about the image being converted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchNameInput {
+    /// Image reference or tarball path, as given
+    pub image_name: String,
+    /// Name of the [`crate::sources::Source`]
+    pub source: String,
+    /// Repository without tag or digest, e.g. `library/nginx`; the file stem for tarballs
+    /// without repo tags
+    pub name: String,
+    /// Tag, `sha256-<short digest>` for references pinned by digest, `latest` otherwise
+    pub tag: String,
+    pub os: String,
+    pub arch: String,
+    /// Image ID (`sha256:…`)
+    pub digest: String,
+}
+
+impl BranchNameInput {
+    /// Describes `image_name` fetched by `source`. Name and tag come from the first repo
+    /// tag of the image, else from the reference itself.
+    pub fn new(image_name: &str, source: &str, metadata: &ImageMetadata) -> Self {
+        let reference = match metadata.repo_tags.first() {
+            Some(tag) => tag.as_str(),
+            None => image_name,
+        };
+        let (name, tag) = if metadata.repo_tags.is_empty() && Path::new(image_name).is_file() {
+            let stem = Path::new(image_name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "tar-image".to_string());
+            (stem, "latest".to_string())
+        } else {
+            split_reference(reference)
+        };
+        Self {
+            image_name: image_name.to_string(),
+            source: source.to_string(),
+            name,
+            tag,
+            os: metadata.os.clone(),
+            arch: metadata.architecture.clone(),
+            digest: metadata.id.clone(),
+        }
+    }
+}
+
+/// `nginx:1.25` → (`nginx`, `1.25`), `nginx@sha256:abc…` → (`nginx`, `sha256-abc…`)
+fn split_reference(reference: &str) -> (String, String) {
+    if let Some((name, digest)) = reference.split_once('@') {
+        let short = match digest.split_once(':') {
+            Some((algorithm, hex)) => format!("{algorithm}-{}", &hex[..hex.len().min(12)]),
+            None => digest.to_string(),
+        };
+        return (strip_tag(name).to_string(), short);
+    }
+    let name = strip_tag(reference);
+    match reference.get(name.len() + 1..) {
+        Some(tag) if !tag.is_empty() => (name.to_string(), tag.to_string()),
+        _ => (name.to_string(), "latest".to_string()),
+    }
+}
+
+/// The reference without its tag; a tag can only follow the last path component (the
+/// registry may have a port)
+fn strip_tag(reference: &str) -> &str {
+    let last_slash = reference.rfind('/').map_or(0, |i| i + 1);
+    match reference[last_slash..].find(':') {
+        Some(i) => &reference[..last_slash + i],
+        None => reference,
+    }
+}
+
+/// Chooses the branch an image is converted into.
+pub trait BranchNamingStrategy: Send + Sync {
+    /// Branch name for `input`; must be a valid Git branch name.
+    ///
+    /// # Errors
+    /// - The strategy can't name this image.
+    fn branch_name(&self, input: &BranchNameInput) -> Result<String>;
+}
+
+/// Branch names from a template such as `{name}/{tag}/{arch}`. Tag names follow the same
+/// rules, so [`crate::processor::ImageProcessor::with_tag_template`] uses it as well.
+///
+/// Placeholders: `{name}`, `{tag}`, `{os}`, `{arch}`, `{digest}` (first 12 hex digits of
+/// the image ID) and `{source}` (engine). Characters Git doesn't allow in branch names
+/// are replaced with `-` in the substituted values; slashes in `{name}` are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchTemplate {
+    template: String,
+}
+
+impl BranchTemplate {
+    /// # Errors
+    /// - The template is empty or has an unknown or unclosed placeholder.
+    pub fn new(template: &str) -> Result<Self> {
+        if template.trim().is_empty() {
+            bail!("Branch template is empty");
+        }
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                bail!("Unclosed placeholder in branch template '{template}'");
+            };
+            let placeholder = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&placeholder) {
+                bail!(
+                    "Unknown placeholder '{{{placeholder}}}' in branch template '{template}' (expected one of {})",
+                    PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("{{{p}}}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            rest = &rest[start + end + 1..];
+        }
+        Ok(Self {
+            template: template.to_string(),
+        })
+    }
+}
+
+impl std::str::FromStr for BranchTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        Self::new(template)
+    }
+}
+
+impl BranchNamingStrategy for BranchTemplate {
+    fn branch_name(&self, input: &BranchNameInput) -> Result<String> {
+        let digest = extract_short_digest(&input.digest).unwrap_or_else(|| input.digest.clone());
+        let name = input
+            .name
+            .split('/')
+            .map(ref_component)
+            .collect::<Vec<_>>()
+            .join("/");
+        let branch = self
+            .template
+            .replace("{name}", &name)
+            .replace("{tag}", &ref_component(&input.tag))
+            .replace("{os}", &ref_component(&input.os))
+            .replace("{arch}", &ref_component(&input.arch))
+            .replace("{digest}", &ref_component(&digest))
+            .replace("{source}", &ref_component(&input.source));
+        if !git2::Branch::name_is_valid(&branch)? {
+            bail!(
+                "Branch template '{}' gives '{branch}' for '{}', which is not a valid ref name",
+                self.template,
+                input.image_name
+            );
+        }
+        Ok(branch)
+    }
+}
+
+/// `value` as (part of) one path component of a ref: no `/`, no characters Git rejects,
+/// no `..` and no leading `.`
+fn ref_component(value: &str) -> String {
+    let replaced: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '?' | '*' | '[' | '^' | '~' | '@' | '{' => '-',
+            c if c.is_whitespace() || c.is_control() => '-',
+            c => c,
+        })
+        .collect();
+    let mut component = replaced.replace("..", "-");
+    while component.starts_with('.') {
+        component.remove(0);
+    }
+    component.trim_end_matches(".lock").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(image_name: &str, repo_tags: &[&str]) -> BranchNameInput {
+        let metadata: ImageMetadata = serde_json::from_value(serde_json::json!({
+            "Id": "sha256:1234567890abcdef",
+            "RepoTags": repo_tags,
+            "Created": "",
+            "Config": {},
+            "Architecture": "arm64",
+            "Os": "linux",
+        }))
+        .unwrap();
+        BranchNameInput::new(image_name, "registry", &metadata)
+    }
+
+    #[test]
+    fn test_branch_template() {
+        let template: BranchTemplate = "{name}/{tag}/{arch}".parse().unwrap();
+        assert_eq!(
+            template
+                .branch_name(&input("library/nginx:1.25", &[]))
+                .unwrap(),
+            "library/nginx/1.25/arm64"
+        );
+        assert_eq!(
+            template.branch_name(&input("nginx", &[])).unwrap(),
+            "nginx/latest/arm64"
+        );
+        assert_eq!(
+            template
+                .branch_name(&input("localhost:5000/app@sha256:abcdef", &[]))
+                .unwrap(),
+            "localhost-5000/app/sha256-abcdef/arm64"
+        );
+        // Repo tags win over the reference
+        assert_eq!(
+            template
+                .branch_name(&input("/tmp/image.tar", &["app:v2"]))
+                .unwrap(),
+            "app/v2/arm64"
+        );
+
+        let template = BranchTemplate::new("{source}-{name}#{os}-{digest}").unwrap();
+        assert_eq!(
+            template.branch_name(&input("nginx:1.25", &[])).unwrap(),
+            "registry-nginx#linux-1234567890ab"
+        );
+
+        assert!(BranchTemplate::new("{name}/{version}").is_err());
+        assert!(BranchTemplate::new("{name").is_err());
+        assert!(BranchTemplate::new("").is_err());
+        let invalid = BranchTemplate::new("{name}//{tag}").unwrap();
+        assert!(invalid.branch_name(&input("nginx", &[])).is_err());
+    }
+}
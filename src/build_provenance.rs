@@ -0,0 +1,346 @@
+//! Where an image was built from: Dockerfile, build arguments and source revision.
+//!
+//! Images built with BuildKit say a lot about their build, spread over several places:
+//! - history entries with the comment `buildkit.dockerfile.v0`,
+//! - SLSA provenance attestations (v0.2 and v1), attached to the image index as an
+//!   attestation manifest, or through cosign or the referrers API (see
+//!   [`crate::attestations`]), naming the Dockerfile, the build arguments and the Git
+//!   repository and revision of the build context,
+//! - the labels `org.opencontainers.image.source` and `org.opencontainers.image.revision`
+//!   (or their `org.label-schema.vcs-url`/`vcs-ref` predecessors).
+//!
+//! [`BuildProvenance::collect`] merges them, attestations first, into the
+//! `## Build Provenance` section of `Image.md` (and `build` in `Image.json`). The metadata
+//! commit also gets [`BUILD_SOURCE`], [`BUILD_REVISION`] and [`BUILD_DOCKERFILE`]
+//! trailers (see [`BuildProvenance::trailers`]), so the history links back to the commit
+//! the image was built from:
+//!
+//! ```text
+//! git log -1 --format='%(trailers:key=Build-Source,key=Build-Revision)' nginx#latest#…
+//! ```
+
+use crate::image_metadata::ImageMetadata;
+use crate::sources::credentials::base64_decode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Trailer with the repository the image was built from.
+pub const BUILD_SOURCE: &str = "Build-Source";
+/// Trailer with the revision of [`BUILD_SOURCE`] the image was built from.
+pub const BUILD_REVISION: &str = "Build-Revision";
+/// Trailer with the Dockerfile the image was built from.
+pub const BUILD_DOCKERFILE: &str = "Build-Dockerfile";
+
+/// History comment of the layers BuildKit created from a Dockerfile.
+const BUILDKIT_COMMENT: &str = "buildkit.dockerfile";
+/// Key of the BuildKit metadata in SLSA provenance.
+const BUILDKIT_METADATA: &str = "https://mobyproject.org/buildkit@v1#metadata";
+/// Prefix of the build arguments among the BuildKit frontend arguments.
+const BUILD_ARG: &str = "build-arg:";
+
+/// How an image was built, as far as it says.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildProvenance {
+    /// `buildkit`, or the builder id of the provenance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builder: Option<String>,
+    /// Dockerfile path in the build context
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dockerfile: Option<String>,
+    /// Repository of the build context, e.g. `https://github.com/acme/app.git`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Revision (commit) of [`BuildProvenance::source`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// `--build-arg`s of the build, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub build_args: BTreeMap<String, String>,
+}
+
+impl BuildProvenance {
+    /// Provenance of the image described by `metadata`, whose attached artifacts are in
+    /// `attestations_dir` (which needn't exist); `None` if nothing is known.
+    pub fn collect(metadata: &ImageMetadata, attestations_dir: &Path) -> Option<Self> {
+        let mut provenance = Self::default();
+        let mut statements = Vec::new();
+        collect_statements(attestations_dir, &mut statements);
+        for statement in &statements {
+            provenance.merge(Self::from_statement(statement));
+        }
+
+        if let Some(config) = &metadata.container_config {
+            let label = |names: &[&str]| {
+                names
+                    .iter()
+                    .find_map(|name| config.labels.get(*name))
+                    .filter(|value| !value.is_empty())
+                    .cloned()
+            };
+            provenance.merge(Self {
+                source: label(&[
+                    "org.opencontainers.image.source",
+                    "org.label-schema.vcs-url",
+                ]),
+                revision: label(&[
+                    "org.opencontainers.image.revision",
+                    "org.label-schema.vcs-ref",
+                ]),
+                ..Self::default()
+            });
+        }
+        let buildkit = metadata.layer_digests.iter().any(|layer| {
+            layer
+                .comment
+                .as_deref()
+                .is_some_and(|comment| comment.starts_with(BUILDKIT_COMMENT))
+        });
+        if buildkit && provenance.builder.is_none() {
+            provenance.builder = Some("buildkit".to_string());
+        }
+
+        (provenance != Self::default()).then_some(provenance)
+    }
+
+    /// What an in-toto statement with SLSA provenance (v0.2 or v1) says; empty for other
+    /// statements.
+    pub fn from_statement(statement: &Value) -> Self {
+        let predicate_type = statement["predicateType"].as_str().unwrap_or_default();
+        let predicate = &statement["predicate"];
+        let string = |value: &Value| value.as_str().map(str::to_string);
+        let (builder, config_source, args, metadata) =
+            if predicate_type.starts_with("https://slsa.dev/provenance/v0") {
+                (
+                    &predicate["builder"]["id"],
+                    &predicate["invocation"]["configSource"],
+                    &predicate["invocation"]["parameters"]["args"],
+                    &predicate["metadata"][BUILDKIT_METADATA],
+                )
+            } else if predicate_type.starts_with("https://slsa.dev/provenance/v1") {
+                let external = &predicate["buildDefinition"]["externalParameters"];
+                (
+                    &predicate["runDetails"]["builder"]["id"],
+                    &external["configSource"],
+                    &external["request"]["args"],
+                    &predicate["runDetails"]["metadata"][BUILDKIT_METADATA],
+                )
+            } else {
+                return Self::default();
+            };
+
+        let build_args = args
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, value)| {
+                Some((
+                    name.strip_prefix(BUILD_ARG)?.to_string(),
+                    value.as_str()?.to_string(),
+                ))
+            })
+            .collect();
+        let dockerfile = string(&config_source["entryPoint"])
+            .or_else(|| string(&config_source["path"]))
+            .or_else(|| string(&args["filename"]));
+        Self {
+            builder: string(builder).filter(|id| !id.is_empty()),
+            dockerfile,
+            source: string(&metadata["vcs"]["source"]),
+            revision: string(&metadata["vcs"]["revision"]),
+            build_args,
+        }
+    }
+
+    /// Fills what's unknown from `other`.
+    fn merge(&mut self, other: Self) {
+        self.builder = self.builder.take().or(other.builder);
+        self.dockerfile = self.dockerfile.take().or(other.dockerfile);
+        self.source = self.source.take().or(other.source);
+        self.revision = self.revision.take().or(other.revision);
+        for (name, value) in other.build_args {
+            self.build_args.entry(name).or_insert(value);
+        }
+    }
+
+    /// Trailers for the metadata commit, one line each; empty if neither source,
+    /// revision nor Dockerfile are known.
+    pub fn trailers(&self) -> String {
+        [
+            (BUILD_SOURCE, &self.source),
+            (BUILD_REVISION, &self.revision),
+            (BUILD_DOCKERFILE, &self.dockerfile),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some(format!("{key}: {}\n", value.as_deref()?)))
+        .collect()
+    }
+}
+
+/// The in-toto statements in the JSON files below `dir`, unwrapping DSSE envelopes.
+fn collect_statements(dir: &Path, statements: &mut Vec<Value>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_statements(&path, statements);
+            continue;
+        }
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let Ok(value) = fs::read(&path).map(|content| serde_json::from_slice::<Value>(&content))
+        else {
+            continue;
+        };
+        let Ok(value) = value else {
+            continue;
+        };
+        let statement = match value["payload"].as_str() {
+            Some(payload) => base64_decode(payload)
+                .ok()
+                .and_then(|payload| serde_json::from_slice(&payload).ok()),
+            None => Some(value),
+        };
+        if let Some(statement) =
+            statement.filter(|statement| statement["predicateType"].is_string())
+        {
+            statements.push(statement);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_metadata::{ContainerConfig, LayerDigest};
+    use crate::sources::credentials::base64_encode;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    fn metadata(labels: &[(&str, &str)], comment: Option<&str>) -> ImageMetadata {
+        let mut metadata = ImageMetadata::new(
+            None,
+            Some(ContainerConfig {
+                environment_variables: Vec::new(),
+                command: None,
+                entrypoint: None,
+                working_directory: String::new(),
+                exposed_ports: Vec::new(),
+                volumes: Vec::new(),
+                labels: labels
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<HashMap<_, _>>(),
+            }),
+        );
+        metadata.layer_digests.push(LayerDigest {
+            digest: "sha256:1".to_string(),
+            command: "RUN make".to_string(),
+            created: String::new(),
+            is_empty: false,
+            comment: comment.map(str::to_string),
+            verified: None,
+        });
+        metadata
+    }
+
+    #[test]
+    fn test_slsa_v02_statement() {
+        let statement = json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "predicateType": "https://slsa.dev/provenance/v0.2",
+            "predicate": {
+                "builder": {"id": ""},
+                "invocation": {
+                    "configSource": {"entryPoint": "docker/Dockerfile"},
+                    "parameters": {"frontend": "dockerfile.v0", "args": {
+                        "build-arg:VERSION": "1.2.3",
+                        "target": "release",
+                    }},
+                },
+                "metadata": {BUILDKIT_METADATA: {"vcs": {
+                    "source": "https://github.com/acme/app.git",
+                    "revision": "0123456789abcdef",
+                }}},
+            },
+        });
+        let provenance = BuildProvenance::from_statement(&statement);
+        assert_eq!(
+            provenance,
+            BuildProvenance {
+                builder: None,
+                dockerfile: Some("docker/Dockerfile".to_string()),
+                source: Some("https://github.com/acme/app.git".to_string()),
+                revision: Some("0123456789abcdef".to_string()),
+                build_args: BTreeMap::from([("VERSION".to_string(), "1.2.3".to_string())]),
+            }
+        );
+        assert_eq!(
+            provenance.trailers(),
+            "Build-Source: https://github.com/acme/app.git\n\
+             Build-Revision: 0123456789abcdef\n\
+             Build-Dockerfile: docker/Dockerfile\n"
+        );
+    }
+
+    #[test]
+    fn test_collect() {
+        let temp_dir = tempdir().unwrap();
+        // A DSSE envelope of a SLSA v1 statement, as cosign attaches it
+        let statement = json!({
+            "predicateType": "https://slsa.dev/provenance/v1",
+            "predicate": {
+                "buildDefinition": {"externalParameters": {
+                    "configSource": {"path": "Dockerfile"},
+                    "request": {"args": {"build-arg:A": "1"}},
+                }},
+                "runDetails": {"builder": {"id": "https://github.com/actions/runner"}},
+            },
+        });
+        let payload = base64_encode(statement.to_string().as_bytes());
+        let dir = temp_dir.path().join("cosign-att");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("1.json"),
+            json!({"payloadType": "application/vnd.in-toto+json", "payload": payload}).to_string(),
+        )
+        .unwrap();
+        fs::write(dir.join("manifest.json"), "{}").unwrap();
+
+        let labels = [
+            ("org.opencontainers.image.source", "https://example.com/app"),
+            ("org.opencontainers.image.revision", "abc"),
+        ];
+        let provenance = BuildProvenance::collect(
+            &metadata(&labels, Some("buildkit.dockerfile.v0")),
+            temp_dir.path(),
+        )
+        .unwrap();
+        assert_eq!(
+            provenance.builder.as_deref(),
+            Some("https://github.com/actions/runner")
+        );
+        assert_eq!(provenance.dockerfile.as_deref(), Some("Dockerfile"));
+        assert_eq!(
+            provenance.source.as_deref(),
+            Some("https://example.com/app")
+        );
+        assert_eq!(provenance.revision.as_deref(), Some("abc"));
+        assert_eq!(provenance.build_args["A"], "1");
+
+        let missing = temp_dir.path().join("missing");
+        let buildkit =
+            BuildProvenance::collect(&metadata(&[], Some("buildkit.dockerfile.v0")), &missing)
+                .unwrap();
+        assert_eq!(buildkit.builder.as_deref(), Some("buildkit"));
+        assert_eq!(buildkit.trailers(), "");
+        assert!(BuildProvenance::collect(&metadata(&[], None), &missing).is_none());
+    }
+}
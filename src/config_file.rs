@@ -0,0 +1,657 @@
+//! Configuration files and environment variables with defaults for the command line.
+//!
+//! Conversions usually repeat the same options. Instead of repeating them on every
+//! command line they can be set, from lowest to highest precedence, in:
+//! - the global file `$XDG_CONFIG_HOME/oci2git/config.toml` (or
+//!   `~/.config/oci2git/config.toml`, see [`ConfigFile::global_path`]), for corporate
+//!   defaults like signing or temporary directories,
+//! - the repository's own [`CONFIG_FILE`], `.oci2git.toml` in the output directory, which
+//!   CI setups can check in,
+//! - `OCI2GIT_<KEY>` environment variables (see [`ConfigFile::from_env`]),
+//! - the command line, which always wins.
+//!
+//! Keys are named like the command line options:
+//!
+//! ```toml
+//! # Paths left out of every layer (see crate::path_filter)
+//! exclude = [
+//!     "usr/share/doc",
+//!     "**/__pycache__",
+//! ]
+//! max-file-size = 50_000_000
+//! max-temp-bytes = 10_000_000_000
+//! jobs = 4
+//! commit-template = "layer {index}: {command}"
+//! branch-template = "{name}/{tag}/{arch}"
+//! tag-template = "{name}-{tag}"
+//! workdir = "/scratch/oci2git"
+//! no-pull = true
+//! sign-commits = true
+//! ```
+//!
+//! Only the TOML needed for these keys is understood: `key = value` lines with comments,
+//! basic (`"..."`) and literal (`'...'`) strings, integers, booleans and arrays of
+//! strings, which may span several lines. Unknown keys and tables are errors, so a typo
+//! doesn't go unnoticed.
+//!
+//! The repository file is listed in `.git/info/exclude` of the repository, so it never
+//! ends up in an image branch.
+
+use crate::branch_naming::BranchTemplate;
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the repository's file, in the output directory.
+pub const CONFIG_FILE: &str = ".oci2git.toml";
+
+/// Prefix of the environment variables, followed by the key in upper case with `_`
+/// instead of `-`, e.g. `OCI2GIT_MAX_FILE_SIZE`.
+pub const ENV_PREFIX: &str = "OCI2GIT_";
+
+/// Options read from a configuration file or the environment; `None`/empty for keys that
+/// aren't set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigFile {
+    /// `engine`: container engine
+    pub engine: Option<String>,
+    /// `output`: output directory; not allowed in the repository's own file
+    pub output: Option<PathBuf>,
+    /// `namespace`: containerd namespace
+    pub namespace: Option<String>,
+    /// `workdir`: directory for temporary data
+    pub workdir: Option<PathBuf>,
+    /// `blob-cache`: blob cache directory of the registry engine
+    pub blob_cache: Option<PathBuf>,
+    /// `conversion-cache`: conversion cache directory
+    pub conversion_cache: Option<PathBuf>,
+    /// `no-conversion-cache`: whether the conversion cache is left alone
+    pub no_conversion_cache: Option<bool>,
+    /// `offline`: whether network access is forbidden
+    pub offline: Option<bool>,
+    /// `no-pull`: whether the docker, podman and containerd engines must not pull missing images
+    pub no_pull: Option<bool>,
+    /// `no-gc`: whether the repository is left unpacked after a conversion
+    pub no_gc: Option<bool>,
+    /// `exclude`: path patterns left out of every layer
+    pub exclude: Vec<String>,
+    /// `max-file-size`: files larger than this are committed as stubs
+    pub max_file_size: Option<u64>,
+    /// `sparse-files`: how sparse files are committed (`expand`, `stub`)
+    pub sparse_files: Option<String>,
+    /// `symlink-targets`: how symlink targets are committed (`host`, `image`)
+    pub symlink_targets: Option<String>,
+    /// `layer-records`: where layer commits record the layer chain (`files`, `notes`)
+    pub layer_records: Option<String>,
+    /// `max-temp-bytes`: limit of temporary data
+    pub max_temp_bytes: Option<u64>,
+    /// `jobs`: threads writing and hashing the files of a layer
+    pub jobs: Option<usize>,
+    /// `progress`: how progress is shown (`auto`, `plain`, `fancy`, `none`)
+    pub progress: Option<String>,
+    /// `log-format`: format of the log lines (`text`, `json`)
+    pub log_format: Option<String>,
+    /// `layout`: how images are laid out in the repository (`branches`, `subdir`)
+    pub layout: Option<String>,
+    /// `rootfs-dir`: directory holding the image filesystem
+    pub rootfs_dir: Option<String>,
+    /// `report-commit-url`: link of the layer commits in the HTML report
+    pub report_commit_url: Option<String>,
+    /// `commit-template`: subject of layer commits
+    pub commit_template: Option<String>,
+    /// `branch-template`: branch name template
+    pub branch_template: Option<BranchTemplate>,
+    /// `tag-template`: name of the tag created at the tip of each branch
+    pub tag_template: Option<BranchTemplate>,
+    /// `git-author`: author and committer name
+    pub git_author: Option<String>,
+    /// `git-email`: author and committer e-mail
+    pub git_email: Option<String>,
+    /// `sign-commits`: whether commits are signed
+    pub sign_commits: Option<bool>,
+    /// `signing-key`: signing key instead of `user.signingkey`
+    pub signing_key: Option<String>,
+    /// `signing-format`: signing key type (`openpgp`, `ssh`)
+    pub signing_format: Option<String>,
+}
+
+/// A parsed value.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(u64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    /// Text of an environment variable, interpreted according to the key
+    Env(String),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Env(_) => "text",
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Parses the contents of a config file.
+    ///
+    /// # Errors
+    /// - Syntax the parser doesn't understand, unknown or repeated keys, and values of the
+    ///   wrong type; the message names the line.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut config = Self::default();
+        let mut seen = Vec::new();
+        let mut parser = Parser::new(content);
+        while let Some((key, value, line)) = parser.next_entry()? {
+            if seen.contains(&key) {
+                bail!("Line {line}: '{key}' is set twice");
+            }
+            config
+                .set(&key, value)
+                .with_context(|| format!("Line {line}: invalid '{key}'"))?;
+            seen.push(key);
+        }
+        Ok(config)
+    }
+
+    /// Reads the config file at `path`, `None` if there is none.
+    ///
+    /// # Errors
+    /// - The file can't be read or parsed.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&content)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+            .map(Some)
+    }
+
+    /// Location of the global file: `$XDG_CONFIG_HOME/oci2git/config.toml`, or
+    /// `~/.config/oci2git/config.toml`; `None` without a home directory.
+    pub fn global_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("oci2git").join("config.toml"))
+    }
+
+    /// Options from the `OCI2GIT_<KEY>` variables among `vars` (e.g.
+    /// `std::env::vars()`). Integers may contain `_`, booleans are `true`/`false` or
+    /// `1`/`0`, and `OCI2GIT_EXCLUDE` separates patterns with commas. Empty variables and
+    /// variables that don't name a key are ignored.
+    ///
+    /// # Errors
+    /// - A variable whose value doesn't fit its key.
+    pub fn from_env(vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let mut config = Self::default();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let key = key.to_ascii_lowercase().replace('_', "-");
+            if value.is_empty() || !KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            config
+                .set(&key, Value::Env(value))
+                .with_context(|| format!("Invalid {name}"))?;
+        }
+        Ok(config)
+    }
+
+    /// The options of `self`, with those it doesn't set taken from `defaults`.
+    pub fn merge(self, defaults: Self) -> Self {
+        Self {
+            engine: self.engine.or(defaults.engine),
+            output: self.output.or(defaults.output),
+            namespace: self.namespace.or(defaults.namespace),
+            workdir: self.workdir.or(defaults.workdir),
+            blob_cache: self.blob_cache.or(defaults.blob_cache),
+            conversion_cache: self.conversion_cache.or(defaults.conversion_cache),
+            no_conversion_cache: self.no_conversion_cache.or(defaults.no_conversion_cache),
+            offline: self.offline.or(defaults.offline),
+            no_pull: self.no_pull.or(defaults.no_pull),
+            no_gc: self.no_gc.or(defaults.no_gc),
+            exclude: if self.exclude.is_empty() {
+                defaults.exclude
+            } else {
+                self.exclude
+            },
+            max_file_size: self.max_file_size.or(defaults.max_file_size),
+            sparse_files: self.sparse_files.or(defaults.sparse_files),
+            symlink_targets: self.symlink_targets.or(defaults.symlink_targets),
+            layer_records: self.layer_records.or(defaults.layer_records),
+            max_temp_bytes: self.max_temp_bytes.or(defaults.max_temp_bytes),
+            jobs: self.jobs.or(defaults.jobs),
+            progress: self.progress.or(defaults.progress),
+            log_format: self.log_format.or(defaults.log_format),
+            layout: self.layout.or(defaults.layout),
+            rootfs_dir: self.rootfs_dir.or(defaults.rootfs_dir),
+            report_commit_url: self.report_commit_url.or(defaults.report_commit_url),
+            commit_template: self.commit_template.or(defaults.commit_template),
+            branch_template: self.branch_template.or(defaults.branch_template),
+            tag_template: self.tag_template.or(defaults.tag_template),
+            git_author: self.git_author.or(defaults.git_author),
+            git_email: self.git_email.or(defaults.git_email),
+            sign_commits: self.sign_commits.or(defaults.sign_commits),
+            signing_key: self.signing_key.or(defaults.signing_key),
+            signing_format: self.signing_format.or(defaults.signing_format),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<()> {
+        match key {
+            "engine" => self.engine = Some(string(value)?),
+            "output" => self.output = Some(string(value)?.into()),
+            "namespace" => self.namespace = Some(string(value)?),
+            "workdir" => self.workdir = Some(string(value)?.into()),
+            "blob-cache" => self.blob_cache = Some(string(value)?.into()),
+            "conversion-cache" => self.conversion_cache = Some(string(value)?.into()),
+            "no-conversion-cache" => self.no_conversion_cache = Some(boolean(value)?),
+            "offline" => self.offline = Some(boolean(value)?),
+            "no-pull" => self.no_pull = Some(boolean(value)?),
+            "no-gc" => self.no_gc = Some(boolean(value)?),
+            "exclude" => self.exclude = strings(value)?,
+            "max-file-size" => self.max_file_size = Some(integer(value)?),
+            "sparse-files" => self.sparse_files = Some(string(value)?),
+            "symlink-targets" => self.symlink_targets = Some(string(value)?),
+            "layer-records" => self.layer_records = Some(string(value)?),
+            "max-temp-bytes" => self.max_temp_bytes = Some(integer(value)?),
+            "jobs" => match integer(value)? {
+                0 => bail!("needs at least 1 thread"),
+                jobs => self.jobs = Some(usize::try_from(jobs)?),
+            },
+            "progress" => self.progress = Some(string(value)?),
+            "log-format" => self.log_format = Some(string(value)?),
+            "layout" => self.layout = Some(string(value)?),
+            "rootfs-dir" => self.rootfs_dir = Some(string(value)?),
+            "report-commit-url" => self.report_commit_url = Some(string(value)?),
+            "commit-template" => self.commit_template = Some(string(value)?),
+            "branch-template" => {
+                self.branch_template = Some(BranchTemplate::new(&string(value)?)?);
+            }
+            "tag-template" => self.tag_template = Some(BranchTemplate::new(&string(value)?)?),
+            "git-author" => self.git_author = Some(string(value)?),
+            "git-email" => self.git_email = Some(string(value)?),
+            "sign-commits" => self.sign_commits = Some(boolean(value)?),
+            "signing-key" => self.signing_key = Some(string(value)?),
+            "signing-format" => self.signing_format = Some(string(value)?),
+            _ => bail!("unknown key"),
+        }
+        Ok(())
+    }
+}
+
+/// Every key [`ConfigFile`] understands.
+const KEYS: &[&str] = &[
+    "engine",
+    "output",
+    "namespace",
+    "workdir",
+    "blob-cache",
+    "conversion-cache",
+    "no-conversion-cache",
+    "offline",
+    "no-pull",
+    "no-gc",
+    "exclude",
+    "max-file-size",
+    "sparse-files",
+    "symlink-targets",
+    "layer-records",
+    "max-temp-bytes",
+    "jobs",
+    "progress",
+    "log-format",
+    "layout",
+    "rootfs-dir",
+    "report-commit-url",
+    "commit-template",
+    "branch-template",
+    "tag-template",
+    "git-author",
+    "git-email",
+    "sign-commits",
+    "signing-key",
+    "signing-format",
+];
+
+fn integer(value: Value) -> Result<u64> {
+    match value {
+        Value::Integer(integer) => Ok(integer),
+        Value::Env(text) => text
+            .replace('_', "")
+            .parse()
+            .map_err(|_| anyhow!("'{text}' is not a non-negative integer")),
+        other => bail!("expected an integer, found {}", other.kind()),
+    }
+}
+
+fn boolean(value: Value) -> Result<bool> {
+    match value {
+        Value::Boolean(boolean) => Ok(boolean),
+        Value::Env(text) => match text.as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => bail!("'{text}' is not true, false, 1 or 0"),
+        },
+        other => bail!("expected a boolean, found {}", other.kind()),
+    }
+}
+
+fn string(value: Value) -> Result<String> {
+    match value {
+        Value::String(string) | Value::Env(string) => Ok(string),
+        other => bail!("expected a string, found {}", other.kind()),
+    }
+}
+
+fn strings(value: Value) -> Result<Vec<String>> {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(item) => Ok(item),
+                other => Err(anyhow!("expected strings, found {}", other.kind())),
+            })
+            .collect(),
+        Value::Env(text) => Ok(text
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect()),
+        other => bail!("expected an array of strings, found {}", other.kind()),
+    }
+}
+
+/// Reads `key = value` entries one after another.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn new(content: &str) -> Self {
+        Self {
+            chars: content.chars().collect(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    /// Skips spaces and tabs, and with `newlines` also line breaks and comments.
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' => {}
+                '\r' | '\n' if newlines => {}
+                '#' if newlines => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+            self.bump();
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow!("Line {}: {message}", self.line)
+    }
+
+    /// The next entry and the line it starts on, `None` at the end of the file.
+    fn next_entry(&mut self) -> Result<Option<(String, Value, usize)>> {
+        self.skip_whitespace(true);
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+        let line = self.line;
+        if c == '[' {
+            return Err(self.error("tables are not supported, set keys at the top level"));
+        }
+
+        let mut key = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        {
+            key.push(c);
+            self.bump();
+        }
+        if key.is_empty() {
+            return Err(self.error(&format!("expected a key, found '{c}'")));
+        }
+        self.skip_whitespace(false);
+        if self.bump() != Some('=') {
+            return Err(self.error(&format!("expected '=' after '{key}'")));
+        }
+        self.skip_whitespace(false);
+        let value = self.value()?;
+
+        // Only a comment may follow the value on its line
+        self.skip_whitespace(false);
+        match self.peek() {
+            None | Some('\n') | Some('\r') | Some('#') => Ok(Some((key, value, line))),
+            Some(c) => Err(self.error(&format!("unexpected '{c}' after the value"))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some(c) if c.is_ascii_digit() || c == '+' => self.integer(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(c) = self.peek().filter(char::is_ascii_alphabetic) {
+                    word.push(c);
+                    self.bump();
+                }
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => Err(self.error(&format!("unsupported value '{word}'"))),
+                }
+            }
+            Some(c) => Err(self.error(&format!("unsupported value starting with '{c}'"))),
+            None => Err(self.error("missing value")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String> {
+        self.bump();
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(match self.bump() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some(c) => return Err(self.error(&format!("unsupported escape '\\{c}'"))),
+                    None => return Err(self.error("unterminated string")),
+                }),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        self.bump();
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(string),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Result<Value> {
+        if self.peek() == Some('+') {
+            self.bump();
+        }
+        let mut digits = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+        {
+            if c != '_' {
+                digits.push(c);
+            }
+            self.bump();
+        }
+        digits
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| self.error(&format!("'{digits}' is not a non-negative integer")))
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace(true);
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_whitespace(true);
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = ConfigFile::parse(
+            r#"
+# Conversion defaults
+exclude = [
+    "usr/share/doc",  # documentation
+    '**/__pycache__',
+]
+max-file-size = 50_000_000
+jobs = 8
+commit-template = "layer {index}: \"{command}\""
+branch-template = '{name}/{tag}'
+sign-commits = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            ConfigFile {
+                exclude: vec!["usr/share/doc".to_string(), "**/__pycache__".to_string()],
+                max_file_size: Some(50_000_000),
+                jobs: Some(8),
+                commit_template: Some("layer {index}: \"{command}\"".to_string()),
+                branch_template: Some(BranchTemplate::new("{name}/{tag}").unwrap()),
+                sign_commits: Some(true),
+                ..ConfigFile::default()
+            }
+        );
+        assert_eq!(ConfigFile::parse("").unwrap(), ConfigFile::default());
+    }
+
+    #[test]
+    fn test_env_and_merge() {
+        let env = ConfigFile::from_env([
+            ("OCI2GIT_MAX_FILE_SIZE".to_string(), "1_000".to_string()),
+            (
+                "OCI2GIT_EXCLUDE".to_string(),
+                "usr/share/doc, **/*.pyc".to_string(),
+            ),
+            ("OCI2GIT_SIGN_COMMITS".to_string(), "0".to_string()),
+            ("OCI2GIT_OFFLINE".to_string(), "true".to_string()),
+            ("OCI2GIT_ENGINE".to_string(), String::new()),
+            ("OCI2GIT_UNRELATED".to_string(), "x".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ])
+        .unwrap();
+        let global = ConfigFile::parse(
+            "engine = 'registry'\nmax-file-size = 5\nsign-commits = true\nexclude = ['tmp']",
+        )
+        .unwrap();
+        assert_eq!(
+            env.merge(global),
+            ConfigFile {
+                engine: Some("registry".to_string()),
+                exclude: vec!["usr/share/doc".to_string(), "**/*.pyc".to_string()],
+                max_file_size: Some(1000),
+                sign_commits: Some(false),
+                offline: Some(true),
+                ..ConfigFile::default()
+            }
+        );
+
+        let error = ConfigFile::from_env([("OCI2GIT_SIGN_COMMITS".to_string(), "yes".to_string())])
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid OCI2GIT_SIGN_COMMITS");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (content, error) in [
+            ("max-file-sise = 1", "Line 1: invalid 'max-file-sise'"),
+            ("\n[convert]\n", "Line 2: tables are not supported"),
+            ("exclude = [\"a\" \"b\"]", "Line 1: expected ',' or ']'"),
+            ("max-file-size = \"1\"", "Line 1: invalid 'max-file-size'"),
+            (
+                "max-file-size = -1",
+                "Line 1: unsupported value starting with '-'",
+            ),
+            ("tag-template = 'x' y", "Line 1: unexpected 'y'"),
+            ("jobs = 0", "Line 1: invalid 'jobs'"),
+            ("commit-template = \"x", "Line 1: unterminated string"),
+            (
+                "max-temp-bytes = 1\nmax-temp-bytes = 2",
+                "Line 2: 'max-temp-bytes' is set twice",
+            ),
+        ] {
+            let message = ConfigFile::parse(content).unwrap_err().to_string();
+            assert!(message.starts_with(error), "{content}: {message}");
+        }
+    }
+}
@@ -0,0 +1,500 @@
+//! Image config per layer: the `config/` directory (`--config-history`).
+//!
+//! Instructions like `ENV`, `LABEL`, `EXPOSE` or `USER` change the image config but no
+//! file, so their layer commits are empty. With `--config-history` every layer commit
+//! also carries the config as it was after that step of the image history, one file per
+//! setting below [`CONFIG_DIR`]:
+//!
+//! ```text
+//! config/
+//! ├── env.txt            # KEY=value per line, in config order
+//! ├── labels.json        # labels, sorted by key
+//! ├── entrypoint.txt     # exec form, e.g. ["/docker-entrypoint.sh"]
+//! ├── cmd.txt
+//! ├── user.txt
+//! ├── workdir.txt
+//! └── exposed-ports.txt  # one port per line, e.g. 80/tcp
+//! ```
+//!
+//! so `git diff` between two layers shows exactly which variable or label changed.
+//!
+//! The image only stores its final config; the steps before are reconstructed from the
+//! `created_by` commands of the history (see [`history`]). Values the history records
+//! ambiguously (quoting, build arguments) are taken from the final config where it has the
+//! last word, and the last layer always has the final config, including settings the
+//! history doesn't mention at all.
+
+use crate::metadata::ContainerConfig;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Directory holding the config files, relative to the repository root.
+pub const CONFIG_DIR: &str = "config";
+
+/// The image config after one step of the history.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigState {
+    /// `KEY=value` entries, in config order
+    pub env: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+    pub entrypoint: Option<Vec<String>>,
+    pub cmd: Option<Vec<String>>,
+    pub user: Option<String>,
+    pub working_dir: Option<String>,
+    pub exposed_ports: BTreeSet<String>,
+}
+
+/// A config-changing instruction of a history step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Instruction {
+    Env(Vec<(String, String)>),
+    Label(Vec<(String, String)>),
+    Entrypoint(Vec<String>),
+    Cmd(Vec<String>),
+    User(String),
+    Workdir(String),
+    Expose(Vec<String>),
+}
+
+impl ConfigState {
+    /// The final config of an image.
+    pub fn from_config(config: &ContainerConfig) -> Self {
+        Self {
+            env: config.env.clone(),
+            labels: config
+                .labels
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            entrypoint: config.entrypoint.clone(),
+            cmd: config.cmd.clone(),
+            user: config.user.clone().filter(|user| !user.is_empty()),
+            working_dir: config.working_dir.clone().filter(|dir| !dir.is_empty()),
+            exposed_ports: config
+                .exposed_ports
+                .as_ref()
+                .map(|ports| ports.keys().cloned().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn set_env(&mut self, name: &str, value: &str) {
+        let entry = format!("{name}={value}");
+        let prefix = format!("{name}=");
+        match self.env.iter_mut().find(|env| env.starts_with(&prefix)) {
+            Some(existing) => *existing = entry,
+            None => self.env.push(entry),
+        }
+    }
+
+    /// Writes the files below [`CONFIG_DIR`] in `repo_dir`.
+    ///
+    /// # Errors
+    /// - A file can't be written.
+    pub fn save(&self, repo_dir: &Path) -> Result<()> {
+        let dir = repo_dir.join(CONFIG_DIR);
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let lines = |values: &mut dyn Iterator<Item = &String>| {
+            values
+                .map(|value| format!("{}\n", value.replace('\n', "\\n")))
+                .collect::<String>()
+        };
+        let words = |words: &Option<Vec<String>>| match words {
+            Some(words) => format!("{}\n", serde_json::to_string(words).unwrap_or_default()),
+            None => String::new(),
+        };
+        let line = |value: &Option<String>| match value {
+            Some(value) => format!("{value}\n"),
+            None => String::new(),
+        };
+        let files = [
+            ("env.txt", lines(&mut self.env.iter())),
+            (
+                "labels.json",
+                format!("{}\n", serde_json::to_string_pretty(&self.labels)?),
+            ),
+            ("entrypoint.txt", words(&self.entrypoint)),
+            ("cmd.txt", words(&self.cmd)),
+            ("user.txt", line(&self.user)),
+            ("workdir.txt", line(&self.working_dir)),
+            ("exposed-ports.txt", lines(&mut self.exposed_ports.iter())),
+        ];
+        for (name, content) in files {
+            let path = dir.join(name);
+            fs::write(&path, content)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// The config after each of the history steps with the (shell prefix free) `commands`,
+/// ending with the final `config`.
+///
+/// Starts empty and applies the config-changing instructions of the commands in order.
+/// The last instruction setting a variable, label, entrypoint, user or working directory
+/// takes its value from `config`, which has it exactly.
+pub fn history(config: &ContainerConfig, commands: &[&str]) -> Vec<ConfigState> {
+    let final_state = ConfigState::from_config(config);
+    let final_env: HashMap<&str, &str> = final_state
+        .env
+        .iter()
+        .map(|entry| entry.split_once('=').unwrap_or((entry, "")))
+        .collect();
+    // Variables are listed in the order of the final config, which has inherited ones first
+    let env_order: HashMap<&str, usize> = final_state
+        .env
+        .iter()
+        .enumerate()
+        .map(|(position, entry)| {
+            (
+                entry
+                    .split_once('=')
+                    .map_or(entry.as_str(), |(name, _)| name),
+                position,
+            )
+        })
+        .collect();
+    let instructions: Vec<Option<Instruction>> =
+        commands.iter().map(|command| parse(command)).collect();
+
+    // Where each setting is set for the last time
+    let mut last_env = HashMap::new();
+    let mut last_label = HashMap::new();
+    let (mut last_entrypoint, mut last_cmd, mut last_user, mut last_workdir) =
+        (None, None, None, None);
+    for (step, instruction) in instructions.iter().enumerate() {
+        match instruction {
+            Some(Instruction::Env(pairs)) => {
+                for (name, _) in pairs {
+                    last_env.insert(name.as_str(), step);
+                }
+            }
+            Some(Instruction::Label(pairs)) => {
+                for (name, _) in pairs {
+                    last_label.insert(name.as_str(), step);
+                }
+            }
+            // ENTRYPOINT resets CMD
+            Some(Instruction::Entrypoint(_)) => (last_entrypoint, last_cmd) = (Some(step), None),
+            Some(Instruction::Cmd(_)) => last_cmd = Some(step),
+            Some(Instruction::User(_)) => last_user = Some(step),
+            Some(Instruction::Workdir(_)) => last_workdir = Some(step),
+            Some(Instruction::Expose(_)) | None => {}
+        }
+    }
+
+    let mut state = ConfigState::default();
+    let mut states = Vec::with_capacity(commands.len());
+    for (step, instruction) in instructions.iter().enumerate() {
+        let is_last = |last: Option<usize>| last == Some(step);
+        match instruction {
+            Some(Instruction::Env(pairs)) => {
+                for (name, value) in pairs {
+                    let value = match final_env.get(name.as_str()) {
+                        Some(exact) if last_env.get(name.as_str()) == Some(&step) => exact,
+                        _ => value.as_str(),
+                    };
+                    state.set_env(name, value);
+                }
+                state.env.sort_by_key(|entry| {
+                    let name = entry
+                        .split_once('=')
+                        .map_or(entry.as_str(), |(name, _)| name);
+                    env_order.get(name).copied().unwrap_or(usize::MAX)
+                });
+            }
+            Some(Instruction::Label(pairs)) => {
+                for (name, value) in pairs {
+                    let value = match final_state.labels.get(name) {
+                        Some(exact) if last_label.get(name.as_str()) == Some(&step) => exact,
+                        _ => value,
+                    };
+                    state.labels.insert(name.clone(), value.clone());
+                }
+            }
+            Some(Instruction::Entrypoint(words)) => {
+                state.entrypoint = match &final_state.entrypoint {
+                    Some(exact) if is_last(last_entrypoint) => Some(exact.clone()),
+                    _ => Some(words.clone()),
+                };
+                state.cmd = None;
+            }
+            Some(Instruction::Cmd(words)) => {
+                state.cmd = match &final_state.cmd {
+                    Some(exact) if is_last(last_cmd) => Some(exact.clone()),
+                    _ => Some(words.clone()),
+                };
+            }
+            Some(Instruction::User(user)) => {
+                state.user = match &final_state.user {
+                    Some(exact) if is_last(last_user) => Some(exact.clone()),
+                    _ => Some(user.clone()),
+                };
+            }
+            Some(Instruction::Workdir(dir)) => {
+                let dir = match &final_state.working_dir {
+                    Some(exact) if is_last(last_workdir) => exact.clone(),
+                    _ => Path::new(state.working_dir.as_deref().unwrap_or("/"))
+                        .join(dir)
+                        .to_string_lossy()
+                        .into_owned(),
+                };
+                state.working_dir = Some(dir);
+            }
+            Some(Instruction::Expose(ports)) => state.exposed_ports.extend(ports.iter().cloned()),
+            None => {}
+        }
+        states.push(state.clone());
+    }
+    if let Some(last) = states.last_mut() {
+        *last = final_state;
+    }
+    states
+}
+
+/// The config-changing instruction of a history command, as the classic builder
+/// (`ENV A=1`, `EXPOSE 80/tcp`, `CMD ["nginx" "-g" "daemon off;"]`) and BuildKit
+/// (`EXPOSE map[80/tcp:{}]`) record them.
+fn parse(command: &str) -> Option<Instruction> {
+    let command = command.trim().trim_end_matches("# buildkit").trim_end();
+    let (keyword, rest) = command.split_once(char::is_whitespace)?;
+    let rest = rest.trim();
+    let instruction = match keyword.to_ascii_uppercase().as_str() {
+        "ENV" => Instruction::Env(assignments(rest)),
+        "LABEL" => Instruction::Label(assignments(rest)),
+        "ENTRYPOINT" => Instruction::Entrypoint(exec_words(rest)),
+        "CMD" => Instruction::Cmd(exec_words(rest)),
+        "USER" if !rest.is_empty() => Instruction::User(rest.to_string()),
+        "WORKDIR" if !rest.is_empty() => Instruction::Workdir(rest.to_string()),
+        "EXPOSE" => Instruction::Expose(
+            rest.trim_start_matches("map[")
+                .trim_end_matches(']')
+                .split_whitespace()
+                .map(|port| port.trim_end_matches(":{}"))
+                .filter(|port| !port.is_empty())
+                .map(|port| match port.contains('/') {
+                    true => port.to_string(),
+                    false => format!("{port}/tcp"),
+                })
+                .collect(),
+        ),
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+/// `NAME=value` pairs of `ENV`/`LABEL`; words without `=` continue the previous value.
+/// The legacy `ENV NAME value` form gives one pair.
+fn assignments(rest: &str) -> Vec<(String, String)> {
+    let mut words = rest.split(' ').filter(|word| !word.is_empty()).peekable();
+    if words.peek().is_some_and(|first| !first.contains('=')) {
+        let name = words.next().unwrap_or_default().to_string();
+        return vec![(name, unquote(&words.collect::<Vec<_>>().join(" ")))];
+    }
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for word in words {
+        match word.split_once('=') {
+            Some((name, value)) if !name.is_empty() && !name.contains('"') => {
+                pairs.push((name.to_string(), value.to_string()))
+            }
+            _ => {
+                if let Some((_, value)) = pairs.last_mut() {
+                    value.push(' ');
+                    value.push_str(word);
+                }
+            }
+        }
+    }
+    pairs
+        .into_iter()
+        .map(|(name, value)| (name, unquote(&value)))
+        .collect()
+}
+
+fn unquote(value: &str) -> String {
+    let quoted =
+        |quote: char| value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote);
+    if quoted('"') {
+        value[1..value.len() - 1].replace("\\\"", "\"")
+    } else if quoted('\'') {
+        value[1..value.len() - 1].to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Arguments of `CMD`/`ENTRYPOINT`: JSON, Go's `["a" "b"]` formatting, or the shell form.
+fn exec_words(rest: &str) -> Vec<String> {
+    if let Ok(words) = serde_json::from_str::<Vec<String>>(rest) {
+        return words;
+    }
+    if let Some(inner) = rest
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        if let Some(words) = quoted_words(inner) {
+            return words;
+        }
+    }
+    vec!["/bin/sh".to_string(), "-c".to_string(), rest.to_string()]
+}
+
+/// Space separated double-quoted strings, `None` if there is anything else.
+fn quoted_words(inner: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut rest = inner.trim_start();
+    while !rest.is_empty() {
+        rest = rest.strip_prefix('"')?;
+        let mut escaped = false;
+        let end = rest.char_indices().find_map(|(at, c)| match c {
+            _ if escaped => {
+                escaped = false;
+                None
+            }
+            '\\' => {
+                escaped = true;
+                None
+            }
+            '"' => Some(at),
+            _ => None,
+        })?;
+        words.push(serde_json::from_str(&format!("\"{}\"", &rest[..end])).ok()?);
+        rest = rest[end + 1..].trim_start();
+    }
+    Some(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_instructions() {
+        assert_eq!(
+            parse("ENV PATH=/usr/local/bin:/usr/bin LANG=C.UTF-8"),
+            Some(Instruction::Env(vec![
+                ("PATH".to_string(), "/usr/local/bin:/usr/bin".to_string()),
+                ("LANG".to_string(), "C.UTF-8".to_string()),
+            ]))
+        );
+        assert_eq!(
+            parse("ENV GREETING hello world"),
+            Some(Instruction::Env(vec![(
+                "GREETING".to_string(),
+                "hello world".to_string()
+            )]))
+        );
+        assert_eq!(
+            parse("LABEL maintainer=NGINX Docker Maintainers <docker-maint@nginx.com>"),
+            Some(Instruction::Label(vec![(
+                "maintainer".to_string(),
+                "NGINX Docker Maintainers <docker-maint@nginx.com>".to_string()
+            )]))
+        );
+        let nginx = Some(Instruction::Cmd(vec![
+            "nginx".to_string(),
+            "-g".to_string(),
+            "daemon off;".to_string(),
+        ]));
+        assert_eq!(parse(r#"CMD ["nginx" "-g" "daemon off;"]"#), nginx);
+        assert_eq!(parse(r#"CMD ["nginx","-g","daemon off;"]"#), nginx);
+        assert_eq!(
+            parse("ENTRYPOINT echo hi"),
+            Some(Instruction::Entrypoint(vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                "echo hi".to_string()
+            ]))
+        );
+        assert_eq!(
+            parse("EXPOSE map[443/tcp:{} 80/tcp:{}]"),
+            Some(Instruction::Expose(vec![
+                "443/tcp".to_string(),
+                "80/tcp".to_string()
+            ]))
+        );
+        assert_eq!(
+            parse("EXPOSE 53/udp 80"),
+            Some(Instruction::Expose(vec![
+                "53/udp".to_string(),
+                "80/tcp".to_string()
+            ]))
+        );
+        assert_eq!(parse("RUN apt-get update"), None);
+    }
+
+    #[test]
+    fn test_history() {
+        let config = ContainerConfig {
+            env: vec![
+                "PATH=/usr/bin".to_string(),
+                "VERSION=1.2.3".to_string(),
+                "FROM_ELSEWHERE=1".to_string(),
+            ],
+            cmd: None,
+            entrypoint: Some(vec!["/entrypoint.sh".to_string()]),
+            exposed_ports: Some(HashMap::from([(
+                "80/tcp".to_string(),
+                serde_json::Value::Null,
+            )])),
+            working_dir: Some("/app/src".to_string()),
+            volumes: None,
+            labels: Some(HashMap::from([(
+                "version".to_string(),
+                "1.2.3".to_string(),
+            )])),
+            user: Some("app".to_string()),
+        };
+        let states = history(
+            &config,
+            &[
+                "ADD file:1234 in /",
+                "CMD [\"bash\"]",
+                "ENV PATH=/usr/bin",
+                // Recorded with the build argument, the config has it expanded
+                "ENV VERSION=${VERSION}",
+                "WORKDIR /app",
+                "WORKDIR src",
+                "EXPOSE 80",
+                "LABEL version=1.2.3",
+                "USER app",
+                "ENTRYPOINT [\"/entrypoint.sh\"]",
+                "RUN true",
+            ],
+        );
+        assert_eq!(states.len(), 11);
+        assert_eq!(states[0], ConfigState::default());
+        assert_eq!(states[1].cmd, Some(vec!["bash".to_string()]));
+        assert_eq!(states[2].env, vec!["PATH=/usr/bin"]);
+        assert_eq!(states[3].env, vec!["PATH=/usr/bin", "VERSION=1.2.3"]);
+        assert_eq!(states[4].working_dir.as_deref(), Some("/app"));
+        assert_eq!(states[5].working_dir.as_deref(), Some("/app/src"));
+        assert!(states[6].exposed_ports.contains("80/tcp"));
+        assert_eq!(states[7].labels["version"], "1.2.3");
+        assert_eq!(states[8].user.as_deref(), Some("app"));
+        // ENTRYPOINT drops the inherited CMD
+        assert_eq!(states[9].cmd, None);
+        assert_eq!(states[10], ConfigState::from_config(&config));
+        assert!(!states[9].env.contains(&"FROM_ELSEWHERE=1".to_string()));
+
+        let temp_dir = tempdir().unwrap();
+        states[10].save(temp_dir.path()).unwrap();
+        let read = |name: &str| fs::read_to_string(temp_dir.path().join(CONFIG_DIR).join(name));
+        assert_eq!(
+            read("env.txt").unwrap(),
+            "PATH=/usr/bin\nVERSION=1.2.3\nFROM_ELSEWHERE=1\n"
+        );
+        assert_eq!(
+            read("labels.json").unwrap(),
+            "{\n  \"version\": \"1.2.3\"\n}\n"
+        );
+        assert_eq!(read("entrypoint.txt").unwrap(), "[\"/entrypoint.sh\"]\n");
+        assert_eq!(read("cmd.txt").unwrap(), "");
+        assert_eq!(read("exposed-ports.txt").unwrap(), "80/tcp\n");
+    }
+}
@@ -0,0 +1,188 @@
+//! Content hashes of the files a layer wrote: `.oci2git/content-hashes.jsonl`.
+//!
+//! With `--content-hashes` every regular file is hashed while it streams into `rootfs/`,
+//! and each layer commit that writes files carries [`CONTENT_HASHES_PATH`], one JSON
+//! object per file this layer wrote, sorted by path:
+//!
+//! ```text
+//! {"path":"/app/vendor/lib.js","size":48213,"sha256":"9b1f…"}
+//! ```
+//!
+//! Layers that write no files remove it. `oci2git analyze` reads the lists of all layer
+//! commits to find identical content written more than once, e.g. a vendor directory
+//! copied again by a later layer (see [`crate::analyze`]). Stubbed files (see
+//! [`crate::large_files`]) are listed with the size and digest of their original content.
+
+use crate::fsmeta;
+use crate::layer_apply::{LayerObserver, ObservedEntry};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tar_rs as tar;
+
+/// Location of the list, relative to the repository root.
+pub const CONTENT_HASHES_PATH: &str = ".oci2git/content-hashes.jsonl";
+
+/// A file written by a layer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentHash {
+    /// Absolute path inside the image
+    pub path: String,
+    /// Size of the content in bytes
+    pub size: u64,
+    /// Hex SHA-256 of the content
+    pub sha256: String,
+}
+
+/// Files written by the layer being applied, keyed by path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContentHashes {
+    pub entries: BTreeMap<String, ContentHash>,
+}
+
+impl ContentHashes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the contents of a list file.
+    ///
+    /// # Errors
+    /// - A line that isn't a valid entry.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: ContentHash = serde_json::from_str(line).with_context(|| {
+                format!(
+                    "Invalid entry on line {} of {CONTENT_HASHES_PATH}",
+                    number + 1
+                )
+            })?;
+            entries.insert(entry.path.clone(), entry);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Writes the list to `path`, one entry per line in path order, or removes it if the
+    /// layer wrote no files.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.entries.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut content = String::new();
+        for entry in self.entries.values() {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl LayerObserver for ContentHashes {
+    fn entry(&mut self, path: &Path, entry: &ObservedEntry) {
+        let path = fsmeta::image_path(path);
+        if !entry.header.entry_type().is_dir() {
+            fsmeta::remove_descendants(&mut self.entries, &path);
+        }
+        match (&entry.sha256, entry.header.entry_type()) {
+            (Some(sha256), tar::EntryType::Regular | tar::EntryType::GNUSparse) => {
+                let size = entry.size;
+                self.entries.insert(
+                    path.clone(),
+                    ContentHash {
+                        path,
+                        size,
+                        sha256: sha256.clone(),
+                    },
+                );
+            }
+            _ => {
+                self.entries.remove(&path);
+            }
+        }
+    }
+
+    fn removed(&mut self, path: &Path) {
+        let path = fsmeta::image_path(path);
+        fsmeta::remove_descendants(&mut self.entries, &path);
+        self.entries.remove(&path);
+    }
+
+    fn cleared(&mut self, path: &Path) {
+        fsmeta::remove_descendants(&mut self.entries, &fsmeta::image_path(path));
+    }
+
+    fn wants_content_hash(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_apply::{ConflictStrategy, LayerApplier};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_files_are_hashed_while_applied() {
+        let temp_dir = tempdir().unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, data) in [
+            ("app/a", &b"hello"[..]),
+            ("app/b", b"hello"),
+            ("gone", b"x"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, data).unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, ".wh.gone", &[][..])
+            .unwrap();
+        let data = builder.into_inner().unwrap();
+
+        let mut hashes = ContentHashes::new();
+        LayerApplier::new(&temp_dir.path().join("rootfs"), ConflictStrategy::default())
+            .unwrap()
+            .with_observer(&mut hashes)
+            .apply_archive(&mut tar::Archive::new(&data[..]))
+            .unwrap();
+        assert_eq!(
+            hashes.entries.keys().collect::<Vec<_>>(),
+            vec!["/app/a", "/app/b"]
+        );
+        assert_eq!(
+            hashes.entries["/app/a"].sha256,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(
+            fs::read(temp_dir.path().join("rootfs/app/b")).unwrap(),
+            b"hello"
+        );
+
+        // Round trip
+        let list = temp_dir.path().join(CONTENT_HASHES_PATH);
+        hashes.save(&list).unwrap();
+        let content = fs::read_to_string(&list).unwrap();
+        assert_eq!(ContentHashes::parse(&content).unwrap(), hashes);
+        ContentHashes::new().save(&list).unwrap();
+        assert!(!list.exists());
+    }
+}
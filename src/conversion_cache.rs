@@ -0,0 +1,195 @@
+//! Conversions remembered across repositories, keyed by image digest.
+//!
+//! Converting an image again into another repository normally extracts and commits every
+//! layer once more. [`ConversionCache`] remembers, per image digest and platform, the
+//! repository, branch and tip commit of the last conversion, in one JSON file per image
+//! under `$XDG_CACHE_HOME/oci2git/conversions` (or `~/.cache/oci2git/conversions`):
+//!
+//! ```json
+//! {
+//!   "image": "nginx:latest",
+//!   "digest": "sha256:…",
+//!   "platform": "linux-amd64",
+//!   "repo": "/home/me/nginx-repo",
+//!   "branch": "nginx#latest#linux-amd64#…",
+//!   "commit": "9fceb02…"
+//! }
+//! ```
+//!
+//! Before converting the same image into a different repository, [`ConversionCache::seed`]
+//! fetches that branch into a temporary branch ([`SEED_BRANCH_PREFIX`]), and the branch
+//! point search of [`crate::successor_navigator`] picks up its layer commits like those of
+//! any other branch: matched layers are neither extracted nor committed again. Layer
+//! commits are matched by their recorded digests, so a cached branch that was rewritten
+//! or comes from an unrelated conversion only costs the fetch.
+//!
+//! Entries whose repository is gone, or no longer has the branch, are ignored.
+
+use crate::blob_cache::{cache_dir, digest_hex};
+use crate::git::GitRepo;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the temporary branch a cached conversion is fetched into.
+pub const SEED_BRANCH_PREFIX: &str = "oci2git-cache/";
+
+/// A remembered conversion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedConversion {
+    /// The image reference (or tarball path) that was converted
+    pub image: String,
+    /// Image ID, as in `Image.md`
+    pub digest: String,
+    /// `<os>-<arch>` of the image
+    pub platform: String,
+    /// Repository the image was converted into
+    pub repo: PathBuf,
+    pub branch: String,
+    /// Tip of the branch after the conversion
+    pub commit: String,
+}
+
+/// A directory of [`CachedConversion`]s.
+#[derive(Debug, Clone)]
+pub struct ConversionCache {
+    root: PathBuf,
+}
+
+impl ConversionCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The per-user cache directory, falling back to the system temp dir.
+    pub fn default_dir() -> PathBuf {
+        cache_dir("conversions")
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, digest: &str, platform: &str) -> Result<PathBuf> {
+        Ok(self
+            .root
+            .join(format!("{}-{platform}.json", digest_hex(digest)?)))
+    }
+
+    /// The last conversion of image `digest` for `platform`, if any.
+    ///
+    /// # Errors
+    /// - `digest` is no `sha256:` digest, or the entry can't be read or parsed.
+    pub fn get(&self, digest: &str, platform: &str) -> Result<Option<CachedConversion>> {
+        let path = self.path(digest, platform)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content =
+            fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_slice(&content)
+            .map(Some)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Remembers `conversion`, replacing the entry of the same image and platform.
+    ///
+    /// # Errors
+    /// - `conversion.digest` is no `sha256:` digest, or the entry can't be written.
+    pub fn put(&self, conversion: &CachedConversion) -> Result<()> {
+        let path = self.path(&conversion.digest, &conversion.platform)?;
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("Failed to create {}", self.root.display()))?;
+        // Written aside and renamed, so concurrent runs never read half an entry
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec_pretty(conversion)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        fs::rename(&partial, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Fetches the branch of the last conversion of `digest` for `platform` into the
+    /// temporary branch of `repo` it returns, to be deleted once planned. `None` without
+    /// an entry, when `repo` is the cached repository itself, or when the cached
+    /// repository or branch is gone.
+    ///
+    /// # Errors
+    /// - The entry can't be read, or the fetch fails.
+    pub fn seed(&self, repo: &GitRepo, digest: &str, platform: &str) -> Result<Option<String>> {
+        let Some(cached) = self.get(digest, platform)? else {
+            return Ok(None);
+        };
+        let Ok(source) = cached.repo.canonicalize() else {
+            log::debug!("Cached repository {} is gone", cached.repo.display());
+            return Ok(None);
+        };
+        let workdir = repo.repo.workdir().unwrap_or_else(|| repo.repo.path());
+        if workdir.canonicalize().is_ok_and(|own| own == source) {
+            return Ok(None);
+        }
+        let has_branch = git2::Repository::open(&source).is_ok_and(|cached_repo| {
+            cached_repo
+                .find_branch(&cached.branch, git2::BranchType::Local)
+                .is_ok()
+        });
+        if !has_branch {
+            log::debug!(
+                "Cached repository {} no longer has branch '{}'",
+                source.display(),
+                cached.branch
+            );
+            return Ok(None);
+        }
+
+        let seed = format!("{SEED_BRANCH_PREFIX}{}", digest_hex(digest)?);
+        let url = source
+            .to_str()
+            .with_context(|| format!("Unsupported repository path {}", source.display()))?;
+        repo.repo
+            .remote_anonymous(url)?
+            .fetch(
+                &[format!("+refs/heads/{}:refs/heads/{seed}", cached.branch)],
+                None,
+                None,
+            )
+            .with_context(|| {
+                format!(
+                    "Failed to fetch branch '{}' from {}",
+                    cached.branch,
+                    source.display()
+                )
+            })?;
+        Ok(Some(seed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_put_get() {
+        let temp_dir = tempdir().unwrap();
+        let cache = ConversionCache::new(temp_dir.path());
+        let digest = format!("sha256:{}", "ab".repeat(32));
+        assert_eq!(cache.get(&digest, "linux-amd64").unwrap(), None);
+
+        let conversion = CachedConversion {
+            image: "app:1".to_string(),
+            digest: digest.clone(),
+            platform: "linux-amd64".to_string(),
+            repo: temp_dir.path().join("repo"),
+            branch: "app#1".to_string(),
+            commit: "0".repeat(40),
+        };
+        cache.put(&conversion).unwrap();
+        assert_eq!(cache.get(&digest, "linux-amd64").unwrap(), Some(conversion));
+        assert_eq!(cache.get(&digest, "linux-arm64").unwrap(), None);
+        assert!(cache.get("latest", "linux-amd64").is_err());
+
+        // Nothing to fetch from a repository that is gone
+        let repo = GitRepo::init_with_branch(&temp_dir.path().join("out"), None).unwrap();
+        assert_eq!(cache.seed(&repo, &digest, "linux-amd64").unwrap(), None);
+    }
+}
@@ -0,0 +1,201 @@
+//! Per-conversion options for [`crate::processor::ImageProcessor::convert_with_options`].
+//!
+//! [`ImageProcessor`](crate::processor::ImageProcessor)'s `with_*` builders configure
+//! every conversion a processor runs. [`ConvertOptions`] holds what usually differs from
+//! one image to the next, so a single processor can convert several images differently:
+//!
+//! ```no_run
+//! use oci2git::prelude::*;
+//! use std::path::Path;
+//!
+//! let mut processor = ImageProcessor::new(RegistrySource::new()?, Notifier::new(0));
+//! let options = ConvertOptions::new()
+//!     .with_platform(Some("linux/arm64".parse()?))
+//!     .with_branch_name(Some("alpine/arm64".to_string()))
+//!     .with_max_file_size(Some(50 << 20))
+//!     .with_commit_message_template(Some("layer {index}: {command}".to_string()));
+//! processor.convert_with_options("alpine:3.20", Path::new("./alpine"), &options)?;
+//! # anyhow::Ok(())
+//! ```
+
+use crate::extracted_image::Layer;
+use crate::sources::Platform;
+use anyhow::{bail, Result};
+
+/// Subject of layer commits unless [`ConvertOptions::with_commit_message_template`] says
+/// otherwise.
+pub const DEFAULT_COMMIT_MESSAGE_TEMPLATE: &str = "{marker} - {command}";
+
+/// Directory holding the image filesystem unless [`ConvertOptions::with_rootfs_dir`] says
+/// otherwise.
+pub const DEFAULT_ROOTFS_DIR: &str = "rootfs";
+
+/// Names the rootfs directory of commits that don't use [`DEFAULT_ROOTFS_DIR`], so
+/// `export`, `query`, `diff` and `analyze` find it.
+pub const ROOTFS_DIR_PATH: &str = ".oci2git/rootfs-dir";
+
+/// Names the metadata files and directories next to the rootfs directory take.
+pub(crate) const RESERVED_NAMES: &[&str] = &[
+    ".git",
+    ".oci2git",
+    ".oci2git.toml",
+    "attestations",
+    "config",
+    "Image.md",
+    "Image.json",
+    "layers",
+    "LayerStats.md",
+    "LayerStats.json",
+    "PARTIAL.md",
+    "ports.txt",
+    "README.md",
+    "report",
+    "volumes.txt",
+];
+
+/// Options of a single conversion; the defaults convert like
+/// [`crate::processor::ImageProcessor::convert`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConvertOptions {
+    /// Platform to select from a multi-platform image, `None` for the source's choice
+    pub platform: Option<Platform>,
+    /// Branch to convert into instead of the one named by [`crate::sources::Source::branch_name`]
+    pub branch_name: Option<String>,
+    /// Overrides [`crate::processor::ImageProcessor::with_max_file_size`] when set
+    pub max_file_size: Option<u64>,
+    /// Subject of layer commits, see [`ConvertOptions::with_commit_message_template`]
+    pub commit_message_template: Option<String>,
+    /// Skip the filesystem in this conversion (see
+    /// [`crate::processor::ImageProcessor::with_metadata_only`])
+    pub metadata_only: bool,
+    /// Overrides [`crate::processor::ImageProcessor::with_rootfs_dir`] when set
+    pub rootfs_dir: Option<String>,
+}
+
+impl ConvertOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Convert `platform` of a multi-platform image (see [`crate::sources::Source::select_platform`]).
+    pub fn with_platform(mut self, platform: Option<Platform>) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Convert into `branch_name` instead of the source's generated name. It must be a
+    /// valid Git branch name; slashes group branches like `images/alpine/3.20`.
+    pub fn with_branch_name(mut self, branch_name: Option<String>) -> Self {
+        self.branch_name = branch_name;
+        self
+    }
+
+    /// Commit files larger than `max_file_size` bytes as stubs (see
+    /// [`crate::large_files`]) in this conversion.
+    pub fn with_max_file_size(mut self, max_file_size: Option<u64>) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Commit only history and metadata, no filesystem content, in this conversion; see
+    /// [`crate::processor::ImageProcessor::with_metadata_only`].
+    pub fn with_metadata_only(mut self, metadata_only: bool) -> Self {
+        self.metadata_only = metadata_only;
+        self
+    }
+
+    /// Put the image filesystem into the top-level directory `rootfs_dir` instead of
+    /// `rootfs/` in this conversion, e.g. `fs` or `chroot`; see
+    /// [`crate::processor::ImageProcessor::with_rootfs_dir`].
+    pub fn with_rootfs_dir(mut self, rootfs_dir: Option<String>) -> Self {
+        self.rootfs_dir = rootfs_dir;
+        self
+    }
+
+    /// Subject of layer commits, [`DEFAULT_COMMIT_MESSAGE_TEMPLATE`] by default. The
+    /// layer trailers (see [`crate::trailers`]) are appended either way. Placeholders:
+    /// - `{marker}`: 🟢 for layers with a tarball, ⚪️ for empty layers, ⚫ for layers
+    ///   whose tarball is missing,
+    /// - `{command}`: the history entry (`created_by`),
+    /// - `{index}`: 1-based position in the image history,
+    /// - `{digest}`: layer digest,
+    /// - `{created}`: creation time (RFC 3339).
+    pub fn with_commit_message_template(mut self, template: Option<String>) -> Self {
+        self.commit_message_template = template;
+        self
+    }
+
+    /// Subject of the commit for `layer`, at 1-based `index` of the image history.
+    pub fn layer_subject(&self, layer: &Layer, index: usize) -> String {
+        let marker = if layer.tarball_path.is_some() {
+            "🟢"
+        } else if layer.is_empty {
+            "⚪️"
+        } else {
+            "⚫"
+        };
+        self.commit_message_template
+            .as_deref()
+            .unwrap_or(DEFAULT_COMMIT_MESSAGE_TEMPLATE)
+            .replace("{marker}", marker)
+            .replace("{index}", &index.to_string())
+            .replace("{digest}", &layer.digest)
+            .replace("{created}", &layer.created_at.to_rfc3339())
+            .replace("{command}", &layer.command)
+    }
+}
+
+/// Checks that `name` can be the rootfs directory: a single path component that isn't
+/// taken by the metadata files next to it.
+///
+/// # Errors
+/// - Empty, `.`/`..`, containing a slash, or reserved (`.git`, `Image.md`, ...).
+pub fn validate_rootfs_dir(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+        bail!("'{name}' is not a valid rootfs directory name, it must be a single path component");
+    }
+    if RESERVED_NAMES.contains(&name) {
+        bail!("'{name}' can't be the rootfs directory, it is used for the image metadata");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_subject() {
+        let layer = Layer {
+            id: "abc".to_string(),
+            command: "RUN echo {index}".to_string(),
+            created_at: chrono::DateTime::from_timestamp(0, 0).unwrap(),
+            is_empty: true,
+            tarball_path: None,
+            digest: "sha256:abc".to_string(),
+            comment: None,
+            size: 0,
+            diff_id: None,
+        };
+        assert_eq!(
+            ConvertOptions::new().layer_subject(&layer, 3),
+            "⚪️ - RUN echo {index}"
+        );
+
+        let options = ConvertOptions::new()
+            .with_commit_message_template(Some("#{index} {digest} @{created}".to_string()));
+        assert_eq!(
+            options.layer_subject(&layer, 3),
+            "#3 sha256:abc @1970-01-01T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_validate_rootfs_dir() {
+        assert!(validate_rootfs_dir("fs").is_ok());
+        assert!(validate_rootfs_dir("chroot").is_ok());
+        for invalid in ["", "..", "a/b", ".git", "Image.md", ".oci2git"] {
+            assert!(validate_rootfs_dir(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Reading a converted repository from code.
+//!
+//! [`ConvertedRepo`] opens an output directory and answers what the commands reading
+//! branches back (`list`, `query`, `export`, ...) need, without parsing `Image.md` or
+//! branch names by hand:
+//!
+//! - [`ConvertedRepo::images`] — every image branch with image name, tag, digest and
+//!   platform, taken from the metadata at its tip (branch names depend on
+//!   `--branch-template`, so they're never decoded),
+//! - [`ConvertedRepo::metadata`] — the full [`ImageMetadata`] of a branch,
+//! - [`ConvertedRepo::layers`] — the layer commits of a branch with their entries in the
+//!   layer chain; [`ConvertedRepo::layer_commit`] and [`ConvertedRepo::layer_of`] map
+//!   between layer indexes and commits,
+//! - [`ConvertedRepo::read_file`] — a file of the image filesystem as it was after any
+//!   layer.
+//!
+//! ```no_run
+//! use oci2git::prelude::*;
+//!
+//! let repo = ConvertedRepo::open("./nginx-repo")?;
+//! for image in repo.images()? {
+//!     for layer in repo.layers(&image.branch)? {
+//!         let config = repo.read_file(&image.branch, layer.index, "/etc/nginx/nginx.conf")?;
+//!         if let Some(config) = config {
+//!             println!("{} {}: {} bytes", layer.index, layer.layer.command, config.len());
+//!         }
+//!     }
+//! }
+//! # anyhow::Ok(())
+//! ```
+
+use crate::git::GitRepo;
+use crate::image_metadata::{ImageMetadata, LayerDigest};
+use crate::layer_records;
+use crate::list::{self, ListedImage};
+use crate::query::{image_branches, rootfs_tree};
+use crate::trailers;
+use anyhow::{bail, Context, Result};
+use git2::ObjectType;
+use std::path::Path;
+
+/// A converted repository, opened with [`ConvertedRepo::open`].
+pub struct ConvertedRepo {
+    repo: GitRepo,
+}
+
+/// A layer commit of an image branch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerCommit {
+    /// 1-based layer index
+    pub index: usize,
+    pub commit: git2::Oid,
+    /// Entry of the layer in the recorded layer chain: digest, command, creation time, ...
+    pub layer: LayerDigest,
+}
+
+impl ConvertedRepo {
+    /// Opens the repository in the output directory `path`.
+    ///
+    /// # Errors
+    /// - `path` is not a Git repository.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            repo: GitRepo::open(path.as_ref())?,
+        })
+    }
+
+    /// The underlying repository.
+    pub fn git(&self) -> &GitRepo {
+        &self.repo
+    }
+
+    /// Names of all image branches, sorted.
+    ///
+    /// # Errors
+    /// - Branch enumeration failures.
+    pub fn branches(&self) -> Result<Vec<String>> {
+        image_branches(&self.repo, None)
+    }
+
+    /// The converted images, as `oci2git list` shows them; branches without metadata at
+    /// their tip are skipped.
+    ///
+    /// # Errors
+    /// - Branch enumeration failures, or metadata that can't be parsed.
+    pub fn images(&self) -> Result<Vec<ListedImage>> {
+        list::list(&self.repo)
+    }
+
+    /// The metadata at the tip of `branch`, `None` if it has none (e.g. a conversion that
+    /// didn't finish).
+    ///
+    /// # Errors
+    /// - `branch` doesn't exist, or its metadata can't be parsed.
+    pub fn metadata(&self, branch: &str) -> Result<Option<ImageMetadata>> {
+        ImageMetadata::read_from_commit(&self.repo, self.tip(branch)?)
+    }
+
+    /// The layer commits of `branch`, oldest first. Commits without layer trailers (the
+    /// metadata commit, commits added by hand, the one commit of `--squash`) are left out.
+    ///
+    /// # Errors
+    /// - `branch` doesn't exist, or its commits or layer chain can't be read.
+    pub fn layers(&self, branch: &str) -> Result<Vec<LayerCommit>> {
+        let tip = self.tip(branch)?;
+        let chain = layer_records::read_chain(&self.repo, tip)?.unwrap_or_default();
+        let mut layers = Vec::new();
+        for commit in self.repo.get_branch_commits(branch)? {
+            let Some(trailers) = trailers::parse(&self.repo.commit_message(commit)?)? else {
+                continue;
+            };
+            let layer = match trailers.index.checked_sub(1).and_then(|i| chain.get(i)) {
+                Some(recorded) => recorded.clone(),
+                None => LayerDigest {
+                    digest: trailers.digest,
+                    command: String::new(),
+                    created: trailers.created.unwrap_or_default(),
+                    is_empty: trailers.empty.unwrap_or_default(),
+                    comment: None,
+                    verified: None,
+                },
+            };
+            layers.push(LayerCommit {
+                index: trailers.index,
+                commit,
+                layer,
+            });
+        }
+        Ok(layers)
+    }
+
+    /// The commit of layer `index` (1-based) on `branch`, `None` if there's no such layer.
+    ///
+    /// # Errors
+    /// - `branch` doesn't exist, or its commits can't be read.
+    pub fn layer_commit(&self, branch: &str, index: usize) -> Result<Option<git2::Oid>> {
+        for commit in self.repo.get_branch_commits(branch)? {
+            if trailers::parse(&self.repo.commit_message(commit)?)?
+                .is_some_and(|trailers| trailers.index == index)
+            {
+                return Ok(Some(commit));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The 1-based index of the layer `commit` was made for, `None` if it's not a layer
+    /// commit.
+    ///
+    /// # Errors
+    /// - `commit` doesn't exist.
+    pub fn layer_of(&self, commit: git2::Oid) -> Result<Option<usize>> {
+        Ok(trailers::parse(&self.repo.commit_message(commit)?)?.map(|trailers| trailers.index))
+    }
+
+    /// Content of the file at `path` in the image filesystem (`/etc/hosts` or `etc/hosts`)
+    /// after layer `index` of `branch`; `None` if it doesn't exist then or is a
+    /// directory. Symlinks give their target.
+    ///
+    /// # Errors
+    /// - `branch` has no layer `index`, or Git failures.
+    pub fn read_file(&self, branch: &str, index: usize, path: &str) -> Result<Option<Vec<u8>>> {
+        let Some(commit) = self.layer_commit(branch, index)? else {
+            bail!("Branch '{branch}' has no layer {index}");
+        };
+        let tree = self.repo.repo.find_commit(commit)?.tree()?;
+        let Some(rootfs) = rootfs_tree(&self.repo.repo, &tree)? else {
+            return Ok(None);
+        };
+        let path = path.trim_start_matches('/');
+        match rootfs.get_path(Path::new(path)) {
+            Ok(entry) if entry.kind() == Some(ObjectType::Blob) => Ok(Some(
+                self.repo.repo.find_blob(entry.id())?.content().to_vec(),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    fn tip(&self, branch: &str) -> Result<git2::Oid> {
+        self.repo
+            .get_branch_commits(branch)?
+            .last()
+            .copied()
+            .with_context(|| format!("Branch '{branch}' has no commits"))
+    }
+}
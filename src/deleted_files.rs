@@ -0,0 +1,117 @@
+//! Explicit deletions: `.oci2git/deleted-files.txt`.
+//!
+//! Whiteouts remove paths from `rootfs/` and Git only shows them as missing files, which
+//! is easy to overlook, and invisible for paths that never made it into Git (empty
+//! directories, special files). With `--record-deletions` every layer commit that deletes
+//! something also carries [`DELETED_FILES_PATH`], listing what this layer deleted, one
+//! path per line in sorted order:
+//!
+//! ```text
+//! /var/cache/apt
+//! /var/lib/apt/lists/*
+//! ```
+//!
+//! A plain path comes from a whiteout (`.wh.<name>`) and stands for the path and
+//! everything below it; `<dir>/*` comes from an opaque marker (`.wh..wh..opq`) and means
+//! the previous contents of the directory were dropped. Layers that delete nothing remove
+//! the file, so `git log -- .oci2git/deleted-files.txt` lists exactly the deleting layers.
+
+use crate::fsmeta;
+use crate::layer_apply::{LayerObserver, ObservedEntry};
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Location of the list, relative to the repository root.
+pub const DELETED_FILES_PATH: &str = ".oci2git/deleted-files.txt";
+
+/// Paths deleted by the layer being applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeletedFiles {
+    pub paths: BTreeSet<String>,
+}
+
+impl DeletedFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the list to `path`, or removes it if the layer deleted nothing.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.paths.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut content = String::new();
+        for deleted in &self.paths {
+            content.push_str(deleted);
+            content.push('\n');
+        }
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl LayerObserver for DeletedFiles {
+    fn entry(&mut self, _path: &Path, _entry: &ObservedEntry) {}
+
+    fn removed(&mut self, path: &Path) {
+        self.paths.insert(fsmeta::image_path(path));
+    }
+
+    fn cleared(&mut self, path: &Path) {
+        let path = fsmeta::image_path(path);
+        self.paths
+            .insert(format!("{}/*", path.trim_end_matches('/')));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_apply::{ConflictStrategy, LayerApplier};
+    use tar_rs as tar;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_whiteouts_are_listed() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().join("rootfs");
+        fs::create_dir_all(root.join("var/cache/apt")).unwrap();
+        fs::create_dir_all(root.join("etc/old")).unwrap();
+
+        let mut builder = tar::Builder::new(Vec::new());
+        for path in ["var/cache/.wh.apt", "etc/old/.wh..wh..opq", ".wh.missing"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, &[][..]).unwrap();
+        }
+        let data = builder.into_inner().unwrap();
+
+        let mut deleted = DeletedFiles::new();
+        LayerApplier::new(&root, ConflictStrategy::default())
+            .unwrap()
+            .with_observer(&mut deleted)
+            .apply_archive(&mut tar::Archive::new(&data[..]))
+            .unwrap();
+        assert!(!root.join("var/cache/apt").exists());
+
+        let list = temp_dir.path().join(DELETED_FILES_PATH);
+        deleted.save(&list).unwrap();
+        assert_eq!(
+            fs::read_to_string(&list).unwrap(),
+            "/etc/old/*\n/missing\n/var/cache/apt\n"
+        );
+
+        DeletedFiles::new().save(&list).unwrap();
+        assert!(!list.exists());
+    }
+}
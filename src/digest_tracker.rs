@@ -18,6 +18,9 @@ pub struct LayerDigest {
     pub is_empty: bool,
     /// Additional comment for empty layers
     pub comment: Option<String>,
+    /// Whether the layer matched its `diff_id` when it was extracted, `None` if unchecked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,6 +66,7 @@ impl DigestTracker {
             created,
             is_empty,
             comment,
+            verified: None,
         };
 
         // Layers should be added sequentially, so position should equal current length
@@ -77,6 +81,13 @@ impl DigestTracker {
         self.layer_digests.push(layer_digest);
     }
 
+    /// Records the outcome of the digest verification of the layer at `position`
+    pub fn set_verified(&mut self, position: usize, verified: Option<bool>) {
+        if let Some(layer) = self.layer_digests.get_mut(position) {
+            layer.verified = verified;
+        }
+    }
+
     pub fn get_layer(&self, position: usize) -> Option<&LayerDigest> {
         self.layer_digests.get(position)
     }
@@ -92,7 +103,9 @@ impl DigestTracker {
     }
 
     fn layers_match(&self, existing: &LayerDigest, new: &crate::extracted_image::Layer) -> bool {
-        if existing.is_empty != new.is_empty {
+        // Layers without a tarball (metadata-only conversions) are recorded as empty, so
+        // they never stand in for a layer whose files were actually committed
+        if existing.is_empty != (new.is_empty || new.tarball_path.is_none()) {
             return false;
         }
 
@@ -282,6 +295,8 @@ mod tests {
             tarball_path: Some(std::path::PathBuf::from("layer1.tar")),
             digest: "sha256:layer1".to_string(),
             comment: Some("FROM alpine".to_string()),
+            size: 0,
+            diff_id: None,
         };
         assert!(tracker.layer_matches(0, &matching_layer1));
 
@@ -295,6 +310,8 @@ mod tests {
             tarball_path: Some(std::path::PathBuf::from("layer2.tar")),
             digest: "sha256:layer2".to_string(),
             comment: Some("RUN apk add curl".to_string()),
+            size: 0,
+            diff_id: None,
         };
         assert!(tracker.layer_matches(1, &matching_layer2));
 
@@ -309,6 +326,8 @@ mod tests {
             tarball_path: None,
             digest: "empty".to_string(),
             comment: Some("ENV NEWVAR=value".to_string()),
+            size: 0,
+            diff_id: None,
         };
         assert!(!tracker.layer_matches(2, &non_matching_layer));
 
@@ -323,6 +342,8 @@ mod tests {
             tarball_path: None,
             digest: "empty".to_string(),
             comment: Some("ENV PATH=/bin".to_string()),
+            size: 0,
+            diff_id: None,
         };
         assert!(!tracker.layer_matches(2, &timestamp_mismatch_layer));
 
@@ -0,0 +1,183 @@
+//! Pre-flight disk space check.
+//!
+//! A conversion needs scratch space for the exported image tarball and the unpacked image,
+//! and room in the output directory for `rootfs/` plus the Git objects. Running out of
+//! either shows up as `ENOSPC` deep into a long conversion. Before fetching anything,
+//! [`crate::processor::ImageProcessor::with_disk_space_check`] compares a rough estimate
+//! (image size × [`TEMP_FACTOR`] / [`OUTPUT_FACTOR`], see
+//! [`crate::sources::Source::estimate_image_size`]) with the free space that `df` reports
+//! and fails early, or only warns.
+//!
+//! The estimate is deliberately coarse: compression and layers that overwrite each other
+//! make the real numbers differ both ways. Sources that can't estimate skip the check.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Scratch space per byte of image: the exported tarball plus the unpacked image. Sources
+/// reading an existing tarball ([`SizeEstimate::exported`] `false`) only need half.
+pub const TEMP_FACTOR: u64 = 2;
+
+/// Output space per byte of image: `rootfs/` plus the (compressed) Git objects.
+pub const OUTPUT_FACTOR: u64 = 2;
+
+/// What to do before a conversion whose estimated space needs exceed the free space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiskSpaceCheck {
+    /// Don't check
+    #[default]
+    Skip,
+    /// Warn and convert anyway
+    Warn,
+    /// Fail before fetching the image
+    Fail,
+}
+
+/// Size of an image as a source sees it before fetching it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeEstimate {
+    /// Uncompressed size of the image (≈ its tarball)
+    pub image_bytes: u64,
+    /// Whether the source exports the tarball into scratch space first
+    pub exported: bool,
+}
+
+/// Bytes needed in one directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpaceRequirement {
+    /// What the space is for, e.g. `temporary data`
+    pub label: String,
+    pub dir: PathBuf,
+    pub bytes: u64,
+}
+
+impl SizeEstimate {
+    /// Space needed in `temp_dir` and `output_dir`, as one requirement if both are on
+    /// the same filesystem.
+    pub fn requirements(&self, temp_dir: &Path, output_dir: &Path) -> Vec<SpaceRequirement> {
+        let temp_factor = if self.exported {
+            TEMP_FACTOR
+        } else {
+            TEMP_FACTOR - 1
+        };
+        let temp = SpaceRequirement {
+            label: "temporary data".to_string(),
+            dir: temp_dir.to_path_buf(),
+            bytes: self.image_bytes.saturating_mul(temp_factor),
+        };
+        let output = SpaceRequirement {
+            label: "the repository".to_string(),
+            dir: output_dir.to_path_buf(),
+            bytes: self.image_bytes.saturating_mul(OUTPUT_FACTOR),
+        };
+
+        let same_filesystem = match (free_space(temp_dir), free_space(output_dir)) {
+            (Some((temp_mount, _)), Some((output_mount, _))) => temp_mount == output_mount,
+            _ => false,
+        };
+        if same_filesystem {
+            vec![SpaceRequirement {
+                label: format!("{} and {}", temp.label, output.label),
+                dir: output.dir,
+                bytes: temp.bytes.saturating_add(output.bytes),
+            }]
+        } else {
+            vec![temp, output]
+        }
+    }
+}
+
+/// One message per requirement that exceeds the free space of its filesystem; unknown
+/// free space counts as enough.
+pub fn shortfalls(requirements: &[SpaceRequirement]) -> Vec<String> {
+    requirements
+        .iter()
+        .filter_map(|requirement| {
+            let (_, available) = free_space(&requirement.dir)?;
+            (available < requirement.bytes).then(|| {
+                format!(
+                    "{} in {} needs about {}, but only {} are free",
+                    requirement.label,
+                    requirement.dir.display(),
+                    format_bytes(requirement.bytes),
+                    format_bytes(available)
+                )
+            })
+        })
+        .collect()
+}
+
+/// Mount point and free bytes of the filesystem holding `path` (or its nearest existing
+/// ancestor), from `df -Pk`. `None` where `df` isn't available.
+pub fn free_space(path: &Path) -> Option<(PathBuf, u64)> {
+    let existing = path
+        .ancestors()
+        .find(|dir| !dir.as_os_str().is_empty() && dir.exists())
+        .unwrap_or(Path::new("."));
+    let output = Command::new("df").arg("-Pk").arg(existing).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Filesystem 1024-blocks Used Available Capacity Mounted-on
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = stdout.lines().nth(1)?.split_whitespace().collect();
+    let available: u64 = fields.get(3)?.parse().ok()?;
+    let mount = fields.get(5..)?.join(" ");
+    Some((PathBuf::from(mount), available.saturating_mul(1024)))
+}
+
+/// `1536` → `1.5 KiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+    }
+
+    #[test]
+    fn test_shortfalls() {
+        let dir = tempdir().unwrap();
+        let Some((_, available)) = free_space(dir.path()) else {
+            println!("Skipping test: df is not available");
+            return;
+        };
+
+        // A directory that doesn't exist yet is checked on its parent's filesystem
+        let output = dir.path().join("repo/nested");
+        let estimate = SizeEstimate {
+            image_bytes: 1024,
+            exported: true,
+        };
+        let requirements = estimate.requirements(dir.path(), &output);
+        assert_eq!(requirements.len(), 1);
+        assert_eq!(requirements[0].bytes, 1024 * (TEMP_FACTOR + OUTPUT_FACTOR));
+        assert!(shortfalls(&requirements).is_empty());
+
+        let huge = SizeEstimate {
+            image_bytes: available,
+            exported: false,
+        };
+        let shortfalls = shortfalls(&huge.requirements(dir.path(), &output));
+        assert_eq!(shortfalls.len(), 1);
+        assert!(shortfalls[0].starts_with("temporary data and the repository in"));
+    }
+}
@@ -0,0 +1,161 @@
+//! Shell-sourceable image config: `.oci2git/env.sh` and `.oci2git/run.sh`.
+//!
+//! With `--env-scripts` the metadata commit also carries two POSIX shell scripts built
+//! from the image config, for experimenting inside the checked-out `rootfs/` with
+//! `chroot` or `bwrap`:
+//! - [`ENV_SH_PATH`] exports the image environment (`Env`), to be sourced,
+//! - [`RUN_SH_PATH`] additionally changes to `WorkingDir` and execs `Entrypoint` + `Cmd`
+//!   the way a container runtime would; arguments replace `Cmd` like `docker run <image>
+//!   <args>` does.
+//!
+//! ```text
+//! . .oci2git/env.sh
+//! bwrap --bind rootfs / --dev /dev --proc /proc /bin/sh -s < .oci2git/run.sh
+//! ```
+//!
+//! Values come from the image config itself, not from `Image.md`, so arguments keep
+//! their exact boundaries; everything is single-quoted.
+
+use crate::metadata::ContainerConfig;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Location of the environment script, relative to the repository root.
+pub const ENV_SH_PATH: &str = ".oci2git/env.sh";
+/// Location of the entrypoint script, relative to the repository root.
+pub const RUN_SH_PATH: &str = ".oci2git/run.sh";
+
+/// `value` as a single-quoted shell word
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn quote_all(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| quote(value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `export` lines for the `KEY=value` entries of `env`; entries that aren't valid shell
+/// assignments are kept as comments.
+pub(crate) fn exports(env: &[String]) -> String {
+    let mut script = String::new();
+    for entry in env {
+        let (name, value) = entry.split_once('=').unwrap_or((entry, ""));
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if valid {
+            script.push_str(&format!("export {name}={}\n", quote(value)));
+        } else {
+            script.push_str(&format!(
+                "# skipped, not a shell variable: {}\n",
+                entry.replace('\n', " ")
+            ));
+        }
+    }
+    script
+}
+
+/// Contents of [`ENV_SH_PATH`]
+pub fn render_env(config: &ContainerConfig) -> String {
+    format!(
+        "# Environment of the image, generated by oci2git. Source it: . {ENV_SH_PATH}\n{}",
+        exports(&config.env)
+    )
+}
+
+/// Contents of [`RUN_SH_PATH`]
+pub fn render_run(config: &ContainerConfig) -> String {
+    let mut script = String::from(
+        "#!/bin/sh\n\
+         # Entrypoint and command of the image, generated by oci2git. Run it inside the\n\
+         # rootfs; arguments replace the image command.\n",
+    );
+    script.push_str(&exports(&config.env));
+
+    let workdir = config
+        .working_dir
+        .as_deref()
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or("/");
+    script.push_str(&format!("cd {} || exit 1\n", quote(workdir)));
+
+    if let Some(cmd) = config.cmd.as_ref().filter(|cmd| !cmd.is_empty()) {
+        script.push_str(&format!(
+            "if [ \"$#\" -eq 0 ]; then\n    set -- {}\nfi\n",
+            quote_all(cmd)
+        ));
+    }
+    match config.entrypoint.as_ref().filter(|e| !e.is_empty()) {
+        Some(entrypoint) => script.push_str(&format!("exec {} \"$@\"\n", quote_all(entrypoint))),
+        None => script.push_str("exec \"$@\"\n"),
+    }
+    script
+}
+
+/// Writes both scripts below `repo_dir` (`run.sh` executable).
+pub fn write(repo_dir: &Path, config: &ContainerConfig) -> Result<()> {
+    let env_sh = repo_dir.join(ENV_SH_PATH);
+    let run_sh = repo_dir.join(RUN_SH_PATH);
+    if let Some(parent) = env_sh.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&env_sh, render_env(config))
+        .with_context(|| format!("Failed to write {}", env_sh.display()))?;
+    fs::write(&run_sh, render_run(config))
+        .with_context(|| format!("Failed to write {}", run_sh.display()))?;
+
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(&run_sh, fs::Permissions::from_mode(0o755))
+        .with_context(|| format!("Failed to make {} executable", run_sh.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_scripts_keep_arguments_and_environment() {
+        let temp_dir = tempdir().unwrap();
+        let config = ContainerConfig {
+            env: vec![
+                "GREETING=it's a \"test\" $HOME".to_string(),
+                "not-a-name=x".to_string(),
+            ],
+            cmd: Some(vec!["a b".to_string(), "c".to_string()]),
+            entrypoint: Some(vec!["printf".to_string(), "%s|".to_string()]),
+            exposed_ports: None,
+            working_dir: Some(temp_dir.path().to_string_lossy().into_owned()),
+            volumes: None,
+            labels: None,
+            user: None,
+        };
+        write(temp_dir.path(), &config).unwrap();
+
+        let sh = |script: &str, args: &[&str]| {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(script)
+                .arg("sh")
+                .args(args)
+                .current_dir(temp_dir.path())
+                .output()
+                .unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+        assert_eq!(
+            sh(&format!(". ./{ENV_SH_PATH}; printf %s \"$GREETING\""), &[]),
+            "it's a \"test\" $HOME"
+        );
+        assert!(render_env(&config).contains("# skipped, not a shell variable: not-a-name=x"));
+
+        let run = format!("sh ./{RUN_SH_PATH} \"$@\"");
+        assert_eq!(sh(&run, &[]), "a b|c|");
+        assert_eq!(sh(&run, &["x y"]), "x y|");
+    }
+}
@@ -0,0 +1,184 @@
+//! Typed errors of the conversion API, and the process exit codes they map to.
+//!
+//! [`crate::processor::ImageProcessor`] returns [`Oci2GitError`], which says which stage of
+//! the pipeline failed, so callers (and scripts, through the exit code of the command
+//! line) can tell an unreachable registry from a corrupt layer or an image that was
+//! already converted:
+//!
+//! | Exit code | Variant                          | Meaning                                      |
+//! |-----------|----------------------------------|----------------------------------------------|
+//! | 0         |                                  | success                                      |
+//! | 1         | [`Oci2GitError::Other`]          | anything else (disk space, I/O, ...)         |
+//! | 2         | [`Oci2GitError::InvalidInput`]   | invalid option or argument                   |
+//! | 3         | [`Oci2GitError::DuplicateImage`] | image already converted, nothing changed     |
+//! | 4         | [`Oci2GitError::Source`]         | the image couldn't be fetched from its source |
+//! | 5         | [`Oci2GitError::Extraction`]     | a layer couldn't be applied or verified      |
+//! | 6         | [`Oci2GitError::Git`]            | a Git operation failed                       |
+//! | 7         | [`Oci2GitError::Metadata`]       | image metadata couldn't be read or written   |
+//!
+//! Every variant but [`Oci2GitError::DuplicateImage`] wraps the [`anyhow::Error`] with the
+//! full context chain; the internals of the pipeline keep using `anyhow`. An
+//! [`anyhow::Error`] converts into [`Oci2GitError::Git`] if a [`git2::Error`] caused it,
+//! and [`Oci2GitError::Other`] otherwise.
+
+use std::fmt;
+
+/// A failed conversion, by pipeline stage; see the module documentation.
+#[derive(Debug)]
+pub enum Oci2GitError {
+    /// Fetching the image from its source (daemon, registry, tarball) failed.
+    Source(anyhow::Error),
+    /// Applying or verifying a layer failed.
+    Extraction(anyhow::Error),
+    /// A Git operation failed.
+    Git(anyhow::Error),
+    /// Reading or writing the image metadata failed.
+    Metadata(anyhow::Error),
+    /// The image already exists as `branch` with identical content.
+    DuplicateImage { image: String, branch: String },
+    /// An option or argument is invalid.
+    InvalidInput(anyhow::Error),
+    /// Anything else.
+    Other(anyhow::Error),
+}
+
+/// Result of the conversion API.
+pub type Result<T, E = Oci2GitError> = std::result::Result<T, E>;
+
+impl Oci2GitError {
+    /// Process exit code for this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Other(_) => 1,
+            Self::InvalidInput(_) => 2,
+            Self::DuplicateImage { .. } => 3,
+            Self::Source(_) => 4,
+            Self::Extraction(_) => 5,
+            Self::Git(_) => 6,
+            Self::Metadata(_) => 7,
+        }
+    }
+
+    /// The same error with `context` added to the wrapped error.
+    pub fn context<C>(self, context: C) -> Self
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        match self {
+            Self::Source(error) => Self::Source(error.context(context)),
+            Self::Extraction(error) => Self::Extraction(error.context(context)),
+            Self::Git(error) => Self::Git(error.context(context)),
+            Self::Metadata(error) => Self::Metadata(error.context(context)),
+            Self::InvalidInput(error) => Self::InvalidInput(error.context(context)),
+            Self::Other(error) => Self::Other(error.context(context)),
+            duplicate @ Self::DuplicateImage { .. } => duplicate,
+        }
+    }
+
+    fn inner(&self) -> Option<&anyhow::Error> {
+        match self {
+            Self::Source(error)
+            | Self::Extraction(error)
+            | Self::Git(error)
+            | Self::Metadata(error)
+            | Self::InvalidInput(error)
+            | Self::Other(error) => Some(error),
+            Self::DuplicateImage { .. } => None,
+        }
+    }
+}
+
+/// The message of the wrapped error; the rest of its chain is the [`source`](std::error::Error::source).
+impl fmt::Display for Oci2GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateImage { image, branch } => write!(
+                f,
+                "Image '{image}' already exists as branch '{branch}' with identical content"
+            ),
+            _ => match self.inner() {
+                Some(error) => write!(f, "{error}"),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+impl std::error::Error for Oci2GitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner().and_then(|error| error.source())
+    }
+}
+
+impl From<anyhow::Error> for Oci2GitError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Self>() {
+            Ok(typed) => return typed,
+            Err(error) => error,
+        };
+        if error.chain().any(|cause| cause.is::<git2::Error>()) {
+            Self::Git(error)
+        } else {
+            Self::Other(error)
+        }
+    }
+}
+
+impl From<git2::Error> for Oci2GitError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error.into())
+    }
+}
+
+impl From<std::io::Error> for Oci2GitError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Other(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_from_anyhow() {
+        let git = Err::<(), _>(git2::Error::from_str("locked"))
+            .context("Failed to create branch")
+            .unwrap_err();
+        let error = Oci2GitError::from(git);
+        assert!(matches!(error, Oci2GitError::Git(_)));
+        assert_eq!(error.exit_code(), 6);
+        assert_eq!(error.to_string(), "Failed to create branch");
+        assert_eq!(
+            std::error::Error::source(&error).unwrap().to_string(),
+            "locked"
+        );
+
+        let typed = anyhow::Error::new(Oci2GitError::Source(anyhow!("pull failed")));
+        assert!(matches!(Oci2GitError::from(typed), Oci2GitError::Source(_)));
+        assert!(matches!(
+            Oci2GitError::from(anyhow!("disk full")),
+            Oci2GitError::Other(_)
+        ));
+    }
+
+    #[test]
+    fn test_context_and_display() {
+        let error = Oci2GitError::Extraction(anyhow!("bad tar")).context("Layer 2 failed");
+        assert_eq!(
+            format!("{:#}", anyhow::Error::new(error)),
+            "Layer 2 failed: bad tar"
+        );
+
+        let duplicate = Oci2GitError::DuplicateImage {
+            image: "app:1".to_string(),
+            branch: "app#1".to_string(),
+        };
+        assert_eq!(duplicate.exit_code(), 3);
+        assert_eq!(
+            duplicate.context("ignored").to_string(),
+            "Image 'app:1' already exists as branch 'app#1' with identical content"
+        );
+    }
+}
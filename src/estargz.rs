@@ -0,0 +1,351 @@
+//! eStargz layers: gzip layers with a table of contents.
+//!
+//! Lazy-pulling snapshotters (stargz-snapshotter, nerdctl) use layers that are still valid
+//! gzip tarballs, but compressed one gzip member per file, and that carry:
+//! - a landmark file ([`PREFETCH_LANDMARK`] or [`NO_PREFETCH_LANDMARK`]) after the files
+//!   to prefetch,
+//! - a table of contents, the JSON entry [`TOC_NAME`] at the end of the tar stream,
+//!   listing every file of the layer,
+//! - a footer: an empty gzip member whose extra field holds the offset of the member
+//!   holding the TOC, so the TOC can be read without decompressing the layer.
+//!
+//! The landmarks and the TOC are bookkeeping, not image content: [`Bookkeeping`] keeps
+//! them out of `rootfs/`. In `--metadata-only` conversions the TOC is the only way to
+//! know what a layer holds: the TOC is read from the blob ([`keep_toc`]), or fetched on
+//! its own by the registry source, and each layer commit lists its files in
+//! [`LAYER_FILES_PATH`]. The layer digest covers the whole tar stream, TOC included, so full
+//! conversions still decompress every layer end to end.
+
+use crate::blob_cache::digest_hex;
+use crate::extracted_image::Layer;
+use anyhow::{bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tar_rs as tar;
+
+/// Name of the TOC entry, at the root of the layer.
+pub const TOC_NAME: &str = "stargz.index.json";
+/// Landmark following the files a snapshotter should prefetch.
+pub const PREFETCH_LANDMARK: &str = ".prefetch.landmark";
+/// Landmark at the start of layers without files to prefetch.
+pub const NO_PREFETCH_LANDMARK: &str = ".no.prefetch.landmark";
+/// Layer descriptor annotation with the digest of the (uncompressed) TOC JSON.
+pub const TOC_DIGEST_ANNOTATION: &str = "containerd.io/snapshot/stargz/toc.digest";
+/// Size of the eStargz footer; the legacy stargz footer is 4 bytes shorter.
+pub const FOOTER_SIZE: u64 = 51;
+const LEGACY_FOOTER_SIZE: u64 = 47;
+/// Directory of image archives holding the TOCs of layers whose blobs were left out,
+/// one `<hex>.json` per layer digest.
+pub const TOC_DIR: &str = "estargz";
+/// Location of the file listing of metadata-only layer commits, relative to the
+/// repository root.
+pub const LAYER_FILES_PATH: &str = ".oci2git/layer-files.txt";
+
+/// The table of contents of a layer.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Toc {
+    pub version: u32,
+    pub entries: Vec<TocEntry>,
+}
+
+/// An entry of [`Toc`]; large files are split into a `reg` entry and `chunk` entries.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TocEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, rename = "linkName")]
+    pub link_name: String,
+}
+
+impl Toc {
+    /// Parses the TOC JSON.
+    pub fn parse(json: &[u8]) -> Result<Self> {
+        serde_json::from_slice(json).context("Failed to parse eStargz TOC")
+    }
+
+    /// The paths of the layer, sorted, one per line; directories end with `/`. Chunks
+    /// and landmarks are left out, whiteouts are listed as they are.
+    pub fn listing(&self) -> String {
+        let paths: BTreeSet<String> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.kind != "chunk")
+            .filter(|entry| {
+                let name = entry.name.trim_start_matches("./");
+                name != PREFETCH_LANDMARK && name != NO_PREFETCH_LANDMARK
+            })
+            .map(|entry| {
+                let path = format!("/{}", entry.name.trim_start_matches("./").trim_matches('/'));
+                if entry.kind == "dir" && path != "/" {
+                    format!("{path}/")
+                } else {
+                    path
+                }
+            })
+            .collect();
+        paths.into_iter().map(|path| format!("{path}\n")).collect()
+    }
+}
+
+/// The TOC offset stored in `tail`, the last bytes of a layer blob; `None` if they are
+/// no (e)stargz footer.
+pub fn toc_offset(tail: &[u8]) -> Option<u64> {
+    // Gzip header with FEXTRA, XLEN, then either an "SG" subfield (eStargz) or the bare
+    // payload (legacy stargz), an empty stored block and the trailer
+    let parse = |footer: &[u8], xlen: u16, payload_at: usize| {
+        let header_ok = footer.starts_with(&[0x1f, 0x8b, 0x08]) && footer[3] & 0x04 != 0;
+        if !header_ok || footer[10..12] != xlen.to_le_bytes() {
+            return None;
+        }
+        let payload = footer.get(payload_at..payload_at + 22)?;
+        let hex = std::str::from_utf8(payload.strip_suffix(b"STARGZ")?).ok()?;
+        u64::from_str_radix(hex, 16).ok()
+    };
+    let last = |size: u64| tail.len().checked_sub(size as usize).map(|at| &tail[at..]);
+
+    last(FOOTER_SIZE)
+        .filter(|footer| footer[12..16] == [b'S', b'G', 22, 0])
+        .and_then(|footer| parse(footer, 26, 16))
+        .or_else(|| last(LEGACY_FOOTER_SIZE).and_then(|footer| parse(footer, 22, 12)))
+}
+
+/// The TOC offset of the compressed layer blob at `path`, `None` if it has no footer
+/// (plain tarballs, ordinary gzip layers).
+pub fn read_toc_offset(path: &Path) -> Result<Option<u64>> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let len = file.metadata()?.len();
+    if len < FOOTER_SIZE {
+        return Ok(None);
+    }
+    let mut tail = [0u8; FOOTER_SIZE as usize];
+    file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+    file.read_exact(&mut tail)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(toc_offset(&tail).filter(|&offset| offset < len))
+}
+
+/// The TOC JSON in `compressed`, the blob from the TOC offset on (footer included or
+/// not).
+pub fn read_toc_json(compressed: impl Read) -> Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(MultiGzDecoder::new(compressed));
+    for entry in archive.entries().context("Failed to read eStargz TOC")? {
+        let mut entry = entry.context("Failed to read eStargz TOC")?;
+        if entry.path()?.as_os_str() == TOC_NAME {
+            let mut json = Vec::new();
+            entry.read_to_end(&mut json)?;
+            return Ok(json);
+        }
+    }
+    bail!("No {TOC_NAME} at the TOC offset")
+}
+
+/// Checks the TOC JSON against `expected`, the digest annotated on the layer descriptor
+/// ([`TOC_DIGEST_ANNOTATION`]).
+pub fn verify_toc_digest(expected: &str, json: &[u8]) -> Result<()> {
+    let actual = format!("sha256:{:x}", Sha256::digest(json));
+    if actual != expected {
+        bail!("eStargz TOC digest mismatch: expected {expected}, got {actual}");
+    }
+    Ok(())
+}
+
+/// The TOC JSON of the layer blob at `path`, `None` if it is no (e)stargz layer.
+pub fn read_toc(path: &Path) -> Result<Option<Vec<u8>>> {
+    let Some(offset) = read_toc_offset(path)? else {
+        return Ok(None);
+    };
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    file.seek(SeekFrom::Start(offset))?;
+    read_toc_json(file)
+        .map(Some)
+        .with_context(|| format!("Failed to read the eStargz TOC of {}", path.display()))
+}
+
+fn toc_path(image_dir: &Path, layer: &Layer) -> Option<PathBuf> {
+    let hex = digest_hex(&layer.digest).ok()?;
+    Some(image_dir.join(TOC_DIR).join(format!("{hex}.json")))
+}
+
+/// Copies the TOC of the blob of `layer` into [`TOC_DIR`] of the image extracted to
+/// `image_dir`, so that it outlives the blob being dropped from the layer.
+pub fn keep_toc(image_dir: &Path, layer: &Layer) -> Result<()> {
+    let (Some(tarball), Some(path)) = (&layer.tarball_path, toc_path(image_dir, layer)) else {
+        return Ok(());
+    };
+    if let Some(json) = read_toc(tarball)? {
+        fs::create_dir_all(image_dir.join(TOC_DIR))?;
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The TOC [`TOC_DIR`] of the image extracted to `image_dir` holds for `layer`, if any.
+pub fn layer_toc(image_dir: &Path, layer: &Layer) -> Result<Option<Toc>> {
+    match toc_path(image_dir, layer).filter(|path| path.exists()) {
+        Some(path) => {
+            let json =
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            Toc::parse(&json).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Writes the listing of `toc` to `path`, or removes the file for layers without one.
+pub fn save_listing(path: &Path, toc: Option<&Toc>) -> Result<()> {
+    match toc {
+        Some(toc) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, toc.listing())
+                .with_context(|| format!("Failed to write {}", path.display()))
+        }
+        None if path.exists() => {
+            fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Tells the bookkeeping entries of a layer apart from its content, one entry after the
+/// other.
+///
+/// Landmarks are recognized by name and content size (one byte) at the root; they mark
+/// the layer as eStargz, and so does a footer found beforehand ([`Bookkeeping::new`]).
+/// Only then is a root [`TOC_NAME`] taken for the TOC, so plain layers keep such a file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bookkeeping {
+    estargz: bool,
+}
+
+impl Bookkeeping {
+    /// `estargz` tells whether the layer is already known to be one.
+    pub fn new(estargz: bool) -> Self {
+        Self { estargz }
+    }
+
+    /// Whether the regular file (or other entry) at `rel_path` of `size` bytes is
+    /// bookkeeping to skip.
+    pub fn skips(&mut self, rel_path: &Path, regular: bool, size: u64) -> bool {
+        if !regular || rel_path.parent() != Some(Path::new("")) {
+            return false;
+        }
+        let name = rel_path.as_os_str();
+        if (name == PREFETCH_LANDMARK || name == NO_PREFETCH_LANDMARK) && size == 1 {
+            self.estargz = true;
+            return true;
+        }
+        self.estargz && name == TOC_NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn footer(offset: u64) -> Vec<u8> {
+        let mut footer = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 26, 0];
+        footer.extend_from_slice(&[b'S', b'G', 22, 0]);
+        footer.extend_from_slice(format!("{offset:016x}STARGZ").as_bytes());
+        footer.extend_from_slice(&[0x01, 0, 0, 0xff, 0xff]);
+        footer.extend_from_slice(&[0; 8]);
+        footer
+    }
+
+    /// A layer with a landmark, `bin/sh` and its TOC, one gzip member per part
+    fn layer() -> Vec<u8> {
+        let append = |builder: &mut tar::Builder<Vec<u8>>, path: &str, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, data).unwrap();
+        };
+        let mut files = tar::Builder::new(Vec::new());
+        append(&mut files, NO_PREFETCH_LANDMARK, &[0x0f]);
+        append(&mut files, "bin/sh", b"#!");
+        let mut blob = gzip(files.get_ref());
+
+        let toc = serde_json::json!({"version": 1, "entries": [
+            {"name": NO_PREFETCH_LANDMARK, "type": "reg", "size": 1},
+            {"name": "bin/", "type": "dir"},
+            {"name": "bin/sh", "type": "reg", "size": 2},
+            {"name": "bin/sh", "type": "chunk", "offset": 10},
+        ]});
+        let mut toc_tar = tar::Builder::new(Vec::new());
+        append(&mut toc_tar, TOC_NAME, toc.to_string().as_bytes());
+        let offset = blob.len() as u64;
+        blob.extend(gzip(&toc_tar.into_inner().unwrap()));
+        blob.extend(footer(offset));
+        blob
+    }
+
+    #[test]
+    fn test_read_toc() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("layer.tar.gz");
+        fs::write(&path, layer()).unwrap();
+
+        let toc = Toc::parse(&read_toc(&path).unwrap().unwrap()).unwrap();
+        assert_eq!(toc.version, 1);
+        assert_eq!(toc.listing(), "/bin/\n/bin/sh\n");
+
+        // Still a gzip tarball, with every member decoded
+        let mut archive = tar::Archive::new(MultiGzDecoder::new(File::open(&path).unwrap()));
+        let names: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect();
+        assert_eq!(names, [NO_PREFETCH_LANDMARK, "bin/sh", TOC_NAME]);
+
+        // An ordinary gzip layer has no footer
+        fs::write(&path, gzip(b"data")).unwrap();
+        assert!(read_toc(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_legacy_footer() {
+        let mut footer = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 22, 0];
+        footer.extend_from_slice(b"00000000000004d2STARGZ");
+        footer.extend_from_slice(&[0x01, 0, 0, 0xff, 0xff]);
+        footer.extend_from_slice(&[0; 8]);
+        assert_eq!(toc_offset(&footer), Some(1234));
+        assert_eq!(toc_offset(&footer[1..]), None);
+    }
+
+    #[test]
+    fn test_bookkeeping() {
+        let mut plain = Bookkeeping::default();
+        assert!(!plain.skips(Path::new(TOC_NAME), true, 10));
+        assert!(!plain.skips(Path::new("etc/.prefetch.landmark"), true, 1));
+
+        let mut bookkeeping = Bookkeeping::default();
+        assert!(!bookkeeping.skips(Path::new("bin/sh"), true, 2));
+        assert!(bookkeeping.skips(Path::new(PREFETCH_LANDMARK), true, 1));
+        assert!(bookkeeping.skips(Path::new(TOC_NAME), true, 10));
+        assert!(!bookkeeping.skips(Path::new(TOC_NAME), false, 0));
+
+        assert!(Bookkeeping::new(true).skips(Path::new(TOC_NAME), true, 10));
+    }
+}
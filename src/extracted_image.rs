@@ -8,34 +8,63 @@
 //!   - `created_at` (`chrono::DateTime<Utc>`),
 //!   - `is_empty` (from history `empty_layer`),
 //!   - `tarball_path` (`Some` for non-empty),
-//!   - `digest` (`sha256:<hash>` for blobs, `"empty"` for empty).
+//!   - `digest` (`sha256:<hash>` for blobs, `"empty"` for empty),
+//!   - `size` (tarball size in bytes, `0` for empty).
 //!
 //! Key behavior:
 //! - Supports plain `.tar` and gzip (`.tar.gz`) by checking magic bytes, then invoking `tar`.
-//! - Validates expected layout (`manifest.json` required).
+//! - Validates expected layout (`manifest.json` or, for OCI image layouts, `index.json`
+//!   required).
 //! - Loads metadata from `manifest.json`, `index.json`, and the config JSON
-//!   (prefers manifest digest; falls back to config path).
+//!   (prefers manifest digest; falls back to config path). Nested image indexes are
+//!   walked down to the image manifest of the config (see [`crate::oci_index`]); layouts
+//!   without `manifest.json` get their entries from the manifest of one platform.
 //! - Maps history entries to blob layers by walking history in reverse and pairing
-//!   them with manifest `Layers`, then re-reverses to chronological order.
+//!   them with manifest `Layers`, then re-reverses to chronological order. Layers left
+//!   without a history entry (images without `history`, e.g. some `FROM scratch` builds)
+//!   get a synthesized one dated like the image.
 //! - Canonicalizes layer digests via `digest_tracker::DigestTracker::extract_digest_from_tarball_path`.
+//!   Archives of the legacy `docker save` layout (`<id>/layer.tar` next to `<id>/json` and
+//!   `<id>/VERSION`) name layer directories after v1 layer IDs instead, so their layers
+//!   take the `diff_id` from the config, or else the `id` from the layer's `json`; the
+//!   same image saved in either layout then shares its layer commits.
 //!
 //! Public API highlights:
 //! - [`ExtractedImage::from_tarball`] — extract + parse into memory (with progress via [`Notifier`]).
+//! - [`ExtractedImage::from_reader`] — the same for a tarball read from any [`Read`] stream.
 //! - [`ExtractedImage::metadata`] / [ExtractedImage::os] / [ExtractedImage::architecture] — access image facts.
 //! - [`ExtractedImage::layers`] — get the ordered layer list.
-//! - [`ExtractedImage::extract_layer_to`] — unpack a single layer tarball into a directory.
+//! - [`ExtractedImage::image_tags`] / [`ExtractedImage::select`] — list the images of a
+//!   multi-image archive (`docker save img1 img2`) and load another one than the first.
+//! - [`ExtractedImage::select_platform`] — load another platform of a multi-platform OCI
+//!   image layout than the host's.
+//! - [`ExtractedImage::set_layer_mismatch`] / [`ExtractedImage::history_mismatch`] — how
+//!   history entries and layers are paired when their counts differ (see [`LayerMismatch`]).
+//! - [`ExtractedImage::extract_layer_to`] / [`ExtractedImage::extract_layer_with`] — unpack
+//!   a single layer tarball into a directory (optionally with a [`ConflictStrategy`]).
 //! - [`ExtractedImage::extract_dir`] — path to the temporary extraction root.
+//! - [`ExtractedImage::retain`] — move the extraction root to a permanent location, e.g.
+//!   to keep the evidence of a failing layer.
 //!
 //! Errors include malformed manifests/configs, missing files, or `tar` failures.
-//! Temporary extraction is scoped to the instance lifetime via `tempfile::TempDir`.
+//! Temporary extraction is scoped to the instance lifetime via `tempfile::TempDir`
+//! (named `oci2git-image-*` in the system temp directory).
 
+use crate::blob_cache::digest_hex;
+use crate::layer_apply::{ConflictStrategy, LayerObserver, LayerOptions};
 use crate::metadata::{self, ImageMetadata};
 use crate::notifier::Notifier;
+use crate::oci_index::{self, blob_path};
+use crate::sources::Platform;
 use crate::tar_extractor;
-use anyhow::{anyhow, Context, Result};
+use crate::tree_apply::RootfsTree;
+use crate::workdir::Workdir;
+use anyhow::{anyhow, bail, Context, Result};
 use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct Layer {
@@ -46,6 +75,9 @@ pub struct Layer {
     pub tarball_path: Option<std::path::PathBuf>, // Some for non-empty layers, None for empty layers
     pub digest: String, // Always present - either tarball digest or "empty" for empty layers
     pub comment: Option<String>, // Comment from image layer history
+    pub size: u64,      // Size of the layer tarball in bytes, 0 for empty layers
+    /// `diff_id` of the layer from the image config (`rootfs.diff_ids`), if it lists one
+    pub diff_id: Option<String>,
 }
 
 pub struct ExtractedImage {
@@ -53,36 +85,100 @@ pub struct ExtractedImage {
     _temp_dir: tempfile::TempDir,
     metadata: ImageMetadata,
     layers: Vec<Layer>,
+    /// Position of the loaded image in `manifest.json`
+    index: usize,
+    /// Platform loaded from OCI image layouts, `None` for the host's
+    platform: Option<Platform>,
+    /// How history entries are paired with layer tarballs when their counts differ
+    layer_mismatch: LayerMismatch,
+    /// How the history of the loaded image doesn't line up with its layers, if it doesn't
+    history_mismatch: Option<String>,
+}
+
+/// What to do when the non-empty history entries of an image don't line up with the
+/// layers of its manifest, as some builders write them. Images without any history are
+/// no mismatch; their layers always get synthesized entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LayerMismatch {
+    /// Refuse to load the image
+    Error,
+    /// Pair them from the newest layer on; layers left over get synthesized history
+    /// entries, history entries left over get no tarball
+    #[default]
+    BestEffort,
+    /// Ignore the history, with a synthesized entry for every layer of the manifest
+    ManifestOnly,
 }
 
 impl ExtractedImage {
     pub fn from_tarball<P: AsRef<Path>>(tarball_path: P, notifier: &Notifier) -> Result<Self> {
+        Self::from_tarball_in(tarball_path, &Workdir::default(), notifier)
+    }
+
+    /// Like [`ExtractedImage::from_tarball`], unpacking into a temporary directory of
+    /// `workdir`.
+    pub fn from_tarball_in<P: AsRef<Path>>(
+        tarball_path: P,
+        workdir: &Workdir,
+        notifier: &Notifier,
+    ) -> Result<Self> {
         let tarball_path = tarball_path.as_ref();
 
         notifier.debug(&format!("Extracting image tarball: {tarball_path:?}"));
+        Self::extract_with(workdir, notifier, |extract_dir| {
+            Self::extract_tar_file(tarball_path, extract_dir)
+        })
+    }
+
+    /// Like [`ExtractedImage::from_tarball`], but reads the image tarball (plain or gzipped)
+    /// from a stream, e.g. a buffer already in memory or a network body. No copy of the
+    /// tarball is written to disk; only its unpacked contents are.
+    pub fn from_reader<R: Read>(reader: R, notifier: &Notifier) -> Result<Self> {
+        Self::from_reader_in(reader, &Workdir::default(), notifier)
+    }
+
+    /// Like [`ExtractedImage::from_reader`], unpacking into a temporary directory of
+    /// `workdir`.
+    pub fn from_reader_in<R: Read>(
+        reader: R,
+        workdir: &Workdir,
+        notifier: &Notifier,
+    ) -> Result<Self> {
+        notifier.debug("Extracting image tarball from stream");
+        Self::extract_with(workdir, notifier, |extract_dir| {
+            tar_extractor::extract_reader_with(reader, extract_dir, ConflictStrategy::default())
+                .context("Failed to extract image tarball stream")
+        })
+    }
 
+    /// Runs `extract` into a fresh temporary directory and loads the image found there.
+    fn extract_with(
+        workdir: &Workdir,
+        notifier: &Notifier,
+        extract: impl FnOnce(&Path) -> Result<()>,
+    ) -> Result<Self> {
         // Create a temporary directory for extraction
-        let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+        let temp_dir = workdir.tempdir("oci2git-image-")?;
         let extract_dir = temp_dir.path().join("extracted");
         fs::create_dir_all(&extract_dir)?;
 
         // Extract the tarball
-        Self::extract_tar_file(tarball_path, &extract_dir)?;
+        extract(&extract_dir)?;
 
         // Verify the extracted content has the expected OCI structure
-        let manifest_path = extract_dir.join("manifest.json");
-        if !manifest_path.exists() {
+        if !extract_dir.join("manifest.json").exists() && !extract_dir.join("index.json").exists() {
             return Err(anyhow!(
-        "Invalid image tarball: manifest.json not found. This does not appear to be a valid OCI/Docker image tarball."
+        "Invalid image tarball: neither manifest.json nor index.json found. This does not appear to be a valid OCI/Docker image tarball."
       ));
         }
 
         // Load metadata and layers using static helper methods
         notifier.debug("Loading image metadata...");
-        let metadata = Self::load_metadata_from_dir(&extract_dir, "temp")?;
+        let metadata = Self::load_metadata_from_dir(&extract_dir, "temp", 0, None)?;
 
         notifier.debug("Loading image layers...");
-        let layers = Self::load_layers_from_dir(&extract_dir)?;
+        let (layers, history_mismatch) =
+            Self::load_layers_from_dir(&extract_dir, 0, None, LayerMismatch::default())?;
 
         notifier.info(&format!("Successfully loaded {} layers", layers.len()));
 
@@ -91,9 +187,117 @@ impl ExtractedImage {
             _temp_dir: temp_dir,
             metadata,
             layers,
+            index: 0,
+            platform: None,
+            layer_mismatch: LayerMismatch::default(),
+            history_mismatch,
+        })
+    }
+
+    /// Moves the extraction root (manifest, config and layer tarballs) to `destination`,
+    /// replacing whatever is there, so it outlives this instance. Layer tarball paths
+    /// are not updated; only use this when the image is no longer going to be read.
+    ///
+    /// # Errors
+    /// - `destination` cannot be replaced, or is on another filesystem than the temp dir.
+    pub fn retain(&self, destination: &Path) -> Result<()> {
+        if destination.exists() {
+            fs::remove_dir_all(destination)
+                .with_context(|| format!("Failed to remove {}", destination.display()))?;
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs::rename(&self.extract_dir, destination).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                self.extract_dir.display(),
+                destination.display()
+            )
         })
     }
 
+    /// Repo tags of every image in the archive, in `manifest.json` order. `docker save`
+    /// writes several images into one archive when given several names.
+    pub fn image_tags(&self) -> Result<Vec<Vec<String>>> {
+        Ok(
+            Self::read_manifest(&self.extract_dir, self.platform.as_ref())?
+                .iter()
+                .map(|entry| {
+                    entry["RepoTags"]
+                        .as_array()
+                        .map(|tags| {
+                            tags.iter()
+                                .filter_map(|tag| tag.as_str().map(str::to_string))
+                                .collect()
+                        })
+                        .unwrap_or_default()
+                })
+                .collect(),
+        )
+    }
+
+    /// Loads the image tagged `repo_tag` (`name` alone means `name:latest`) instead of the
+    /// first image of the archive.
+    ///
+    /// # Errors
+    /// - No image of the archive has that tag; the message lists the tags there are.
+    pub fn select(&mut self, repo_tag: &str) -> Result<()> {
+        let image_tags = self.image_tags()?;
+        let latest = format!("{repo_tag}:latest");
+        let index = image_tags
+            .iter()
+            .position(|tags| tags.iter().any(|tag| tag == repo_tag || *tag == latest))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Image '{repo_tag}' not found in the archive, it contains: {}",
+                    image_tags.concat().join(", ")
+                )
+            })?;
+        self.index = index;
+        self.reload()
+    }
+
+    /// Loads the image for `platform` from a multi-platform OCI image layout instead of
+    /// the one for the host. Images of `docker save` archives are saved for a single
+    /// platform, listed in `manifest.json`, and stay as they are.
+    ///
+    /// # Errors
+    /// - The layout has no manifest for `platform`; the message lists those it has.
+    pub fn select_platform(&mut self, platform: &Platform) -> Result<()> {
+        self.platform = Some(platform.clone());
+        self.reload()
+    }
+
+    /// Pairs history entries and layers with `layer_mismatch` when their counts differ.
+    ///
+    /// # Errors
+    /// - [`LayerMismatch::Error`] and they do; the message has the counts.
+    pub fn set_layer_mismatch(&mut self, layer_mismatch: LayerMismatch) -> Result<()> {
+        self.layer_mismatch = layer_mismatch;
+        self.reload()
+    }
+
+    /// How the history of the image doesn't line up with its layers, and what was done
+    /// about it; `None` if it does.
+    pub fn history_mismatch(&self) -> Option<&str> {
+        self.history_mismatch.as_deref()
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let platform = self.platform.as_ref();
+        self.metadata =
+            Self::load_metadata_from_dir(&self.extract_dir, "temp", self.index, platform)?;
+        (self.layers, self.history_mismatch) = Self::load_layers_from_dir(
+            &self.extract_dir,
+            self.index,
+            platform,
+            self.layer_mismatch,
+        )?;
+        Ok(())
+    }
+
     pub fn metadata(&self, _image_name: &str) -> Result<ImageMetadata> {
         // Return the metadata as-is, keeping the proper SHA digest as ID
         Ok(self.metadata.clone())
@@ -121,6 +325,55 @@ impl ExtractedImage {
         Self::extract_tar_file(layer_tarball, output_dir)
     }
 
+    /// Same as [`ExtractedImage::extract_layer_to`], resolving entry type changes with
+    /// the given [`ConflictStrategy`].
+    pub fn extract_layer_with<P: AsRef<Path>>(
+        &self,
+        layer_tarball: &Path,
+        output_dir: P,
+        strategy: ConflictStrategy,
+    ) -> Result<()> {
+        let output_dir = output_dir.as_ref();
+        fs::create_dir_all(output_dir)?;
+        tar_extractor::extract_tar_with(layer_tarball, output_dir, strategy)
+            .context(format!("Failed to extract tar file: {layer_tarball:?}"))
+    }
+
+    /// Same as [`ExtractedImage::extract_layer_with`], applying the layer with `options`
+    /// and reporting every change it makes to `observer` and the number of bytes of
+    /// `layer_tarball` read so far to `progress`.
+    ///
+    /// Returns the digest of the uncompressed layer, to compare with [`Layer::diff_id`].
+    pub fn extract_layer_observed<P: AsRef<Path>>(
+        &self,
+        layer_tarball: &Path,
+        output_dir: P,
+        options: &LayerOptions,
+        observer: &mut dyn LayerObserver,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<String> {
+        let output_dir = output_dir.as_ref();
+        fs::create_dir_all(output_dir)?;
+        tar_extractor::extract_tar_observed(layer_tarball, output_dir, options, observer, progress)
+            .context(format!("Failed to extract tar file: {layer_tarball:?}"))
+    }
+
+    /// Same as [`ExtractedImage::extract_layer_observed`], applying the layer to `tree`
+    /// instead of a directory (see [`crate::tree_apply`]).
+    ///
+    /// Returns the digest of the uncompressed layer, to compare with [`Layer::diff_id`].
+    pub fn apply_layer_to_tree(
+        &self,
+        layer_tarball: &Path,
+        tree: &mut RootfsTree,
+        options: &LayerOptions,
+        observer: &mut dyn LayerObserver,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<String> {
+        tar_extractor::apply_tar_to_tree(layer_tarball, tree, options, observer, progress)
+            .context(format!("Failed to apply tar file: {layer_tarball:?}"))
+    }
+
     pub fn extract_dir(&self) -> &Path {
         &self.extract_dir
     }
@@ -130,26 +383,122 @@ impl ExtractedImage {
             .context(format!("Failed to extract tar file: {tar_path:?}"))
     }
 
-    fn load_metadata_from_dir(extract_dir: &Path, image_name: &str) -> Result<ImageMetadata> {
-        // Parse the manifest to get the config file path
-        let manifest_path = extract_dir.join("manifest.json");
-        let manifest_content =
-            fs::read_to_string(&manifest_path).context("Failed to read manifest.json")?;
+    /// Resolves a path referenced by `manifest.json` inside the extracted archive.
+    ///
+    /// Manifests are untrusted input, so absolute paths and `..` components are rejected
+    /// instead of letting them point at arbitrary files on the host.
+    fn resolve_archive_path(extract_dir: &Path, reference: &str) -> Result<PathBuf> {
+        let relative = Path::new(reference);
+        let is_plain = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if reference.is_empty() || !is_plain {
+            return Err(anyhow!(
+                "Invalid path in manifest.json (must stay inside the archive): {reference}"
+            ));
+        }
+        Ok(extract_dir.join(relative))
+    }
 
-        let manifest: Vec<serde_json::Value> =
-            serde_json::from_str(&manifest_content).context("Failed to parse manifest.json")?;
+    /// Entries of `manifest.json`, one per image in the archive. OCI image layouts without
+    /// one get them from `index.json`, for `platform`.
+    fn read_manifest(
+        extract_dir: &Path,
+        platform: Option<&Platform>,
+    ) -> Result<Vec<serde_json::Value>> {
+        let manifest_path = extract_dir.join("manifest.json");
+        let manifest: Vec<serde_json::Value> = if manifest_path.exists() {
+            let manifest_content =
+                fs::read_to_string(&manifest_path).context("Failed to read manifest.json")?;
+            serde_json::from_str(&manifest_content).context("Failed to parse manifest.json")?
+        } else {
+            Self::entries_from_index(extract_dir, platform)?
+        };
 
         if manifest.is_empty() {
             return Err(anyhow!("Empty manifest.json"));
         }
+        Ok(manifest)
+    }
+
+    /// `manifest.json` entries for an OCI image layout without one, e.g. written by
+    /// `buildx --output type=oci`: one per image named in `index.json`, made of the
+    /// manifest for `platform` among the platforms of the image.
+    fn entries_from_index(
+        extract_dir: &Path,
+        platform: Option<&Platform>,
+    ) -> Result<Vec<serde_json::Value>> {
+        let index = Self::read_index(extract_dir)?
+            .ok_or_else(|| anyhow!("Invalid image tarball: index.json not found"))?;
+
+        // The platforms of an image are either nested below one entry, or entries of
+        // their own that share its name
+        let mut images: Vec<(Option<&str>, Vec<oci_index::ImageManifest>)> = Vec::new();
+        for descriptor in index["manifests"].as_array().into_iter().flatten() {
+            let name = descriptor["annotations"]["io.containerd.image.name"]
+                .as_str()
+                .or(descriptor["annotations"]["org.opencontainers.image.ref.name"].as_str())
+                .filter(|name| name.contains([':', '/']));
+            let manifests = oci_index::image_manifests(extract_dir, descriptor)?;
+            match images.iter_mut().find(|(other, _)| *other == name) {
+                Some((_, found)) => found.extend(manifests),
+                None => images.push((name, manifests)),
+            }
+        }
+
+        let mut entries = Vec::new();
+        for (name, manifests) in images {
+            if manifests.is_empty() {
+                continue;
+            }
+            let manifest = oci_index::select(&manifests, platform)?;
+            let config = manifest
+                .config_digest()
+                .ok_or_else(|| anyhow!("Image manifest {} has no config", manifest.digest))?;
+            let layers = manifest
+                .layer_digests()
+                .into_iter()
+                .map(blob_path)
+                .collect::<Result<Vec<_>>>()?;
+            entries.push(serde_json::json!({
+                "Config": blob_path(config)?,
+                "RepoTags": name.into_iter().collect::<Vec<_>>(),
+                "Layers": layers,
+            }));
+        }
+        Ok(entries)
+    }
+
+    /// Contents of `index.json`, `None` if the archive has none.
+    fn read_index(extract_dir: &Path) -> Result<Option<serde_json::Value>> {
+        let index_path = extract_dir.join("index.json");
+        if !index_path.exists() {
+            return Ok(None);
+        }
+        let index_content = fs::read_to_string(&index_path).context("Failed to read index.json")?;
+        serde_json::from_str(&index_content)
+            .map(Some)
+            .context("Failed to parse index.json")
+    }
+
+    fn load_metadata_from_dir(
+        extract_dir: &Path,
+        image_name: &str,
+        index: usize,
+        platform: Option<&Platform>,
+    ) -> Result<ImageMetadata> {
+        let manifest = Self::read_manifest(extract_dir, platform)?;
+        let entry = manifest
+            .get(index)
+            .ok_or_else(|| anyhow!("manifest.json has no image {index}"))?;
 
         // Get the config file name from the manifest
-        let config_file = manifest[0]["Config"]
+        let config_file = entry["Config"]
             .as_str()
             .ok_or_else(|| anyhow!("Invalid manifest format - missing Config"))?;
 
         // Read the config file as JSON
-        let config_path = extract_dir.join(config_file);
+        let config_path = Self::resolve_archive_path(extract_dir, config_file)?;
         let config_content = fs::read_to_string(&config_path)
             .context(format!("Failed to read config file: {config_file}"))?;
 
@@ -161,24 +510,51 @@ impl ExtractedImage {
         let mut metadata = metadata::from_oci_config(&config);
 
         // Extract the image manifest digest from index.json (this matches what docker image inspect shows as ID)
-        let index_path = extract_dir.join("index.json");
-        if index_path.exists() {
-            let index_content =
-                fs::read_to_string(&index_path).context("Failed to read index.json")?;
-            let index: serde_json::Value =
-                serde_json::from_str(&index_content).context("Failed to parse index.json")?;
-
-            if let Some(manifests) = index["manifests"].as_array() {
-                if let Some(first_manifest) = manifests.first() {
-                    if let Some(digest) = first_manifest["digest"].as_str() {
-                        metadata.id = digest.to_string();
+        if let Some(index_json) = Self::read_index(extract_dir)? {
+            let descriptors = index_json["manifests"].as_array();
+
+            // The entry leading to the image manifest with this config, below nested
+            // indexes if need be; its digest is the ID even then, as `docker image inspect`
+            // shows it
+            let mut found = None;
+            for descriptor in descriptors.into_iter().flatten() {
+                let manifests =
+                    oci_index::image_manifests(extract_dir, descriptor).unwrap_or_default();
+                found = manifests
+                    .into_iter()
+                    .find(|manifest| {
+                        manifest
+                            .config_digest()
+                            .and_then(|digest| blob_path(digest).ok())
+                            .is_some_and(|path| path == config_file)
+                    })
+                    .map(|manifest| (descriptor, manifest));
+                if found.is_some() {
+                    break;
+                }
+            }
+
+            if let Some((descriptor, image_manifest)) = found {
+                if let Some(digest) = descriptor["digest"].as_str() {
+                    metadata.id = digest.to_string();
+                }
+                metadata.annotations = image_manifest.annotations;
+            } else if let Some(manifests) = descriptors {
+                // Only trusted to list the images in manifest.json order if it lists as many
+                if index == 0 || manifests.len() == manifest.len() {
+                    if let Some(descriptor) = manifests.get(index) {
+                        if let Some(digest) = descriptor["digest"].as_str() {
+                            metadata.id = digest.to_string();
+                        }
+                        metadata.annotations = Self::read_annotations(extract_dir, descriptor);
                     }
                 }
             }
         }
 
-        // Fallback: Extract digest from config file path (format: blobs/sha256/HASH)
-        if metadata.id.is_empty() {
+        // Fallback: Extract digest from config file path (format: blobs/sha256/HASH, or
+        // HASH.json in the legacy layout); until then the ID is a bare `sha256:`
+        if digest_hex(&metadata.id).is_err() {
             if let Some(digest_hash) = config_file.strip_prefix("blobs/sha256/") {
                 metadata.id = format!("sha256:{digest_hash}");
             } else if let Some(digest_hash) = config_file.strip_suffix(".json") {
@@ -187,7 +563,7 @@ impl ExtractedImage {
         }
 
         // Add repo tags from the manifest (these are not in the config)
-        if let Some(tags) = manifest[0]["RepoTags"].as_array() {
+        if let Some(tags) = entry["RepoTags"].as_array() {
             metadata.repo_tags = tags
                 .iter()
                 .filter_map(|t| t.as_str().map(|s| s.to_string()))
@@ -207,51 +583,171 @@ impl ExtractedImage {
         Ok(metadata)
     }
 
-    fn load_layers_from_dir(extract_dir: &Path) -> Result<Vec<Layer>> {
-        // Parse the manifest to get the config file path
-        let manifest_path = extract_dir.join("manifest.json");
-        let manifest_content =
-            fs::read_to_string(&manifest_path).context("Failed to read manifest.json")?;
+    /// Annotations of the image manifest `descriptor` points at (OCI layouts keep it as a
+    /// blob), over those of the descriptor itself.
+    fn read_annotations(
+        extract_dir: &Path,
+        descriptor: &serde_json::Value,
+    ) -> HashMap<String, String> {
+        let mut annotations = HashMap::new();
+        let mut add = |value: &serde_json::Value| {
+            for (key, value) in value.as_object().into_iter().flatten() {
+                if let Some(value) = value.as_str() {
+                    annotations.insert(key.clone(), value.to_string());
+                }
+            }
+        };
+        add(&descriptor["annotations"]);
+        let manifest = descriptor["digest"]
+            .as_str()
+            .and_then(|digest| digest_hex(digest).ok())
+            .and_then(|hex| fs::read(extract_dir.join("blobs/sha256").join(hex)).ok())
+            .and_then(|content| serde_json::from_slice::<serde_json::Value>(&content).ok());
+        if let Some(manifest) = manifest {
+            add(&manifest["annotations"]);
+        }
+        annotations
+    }
 
-        let manifest: Vec<serde_json::Value> =
-            serde_json::from_str(&manifest_content).context("Failed to parse manifest.json")?;
+    /// ID, tarball, digest, size and `diff_id` of the layer at `position` of the manifest.
+    fn tarball_layer(
+        layer_tarballs: &[PathBuf],
+        diff_ids: &[&str],
+        position: usize,
+    ) -> (String, Option<PathBuf>, String, u64, Option<String>) {
+        let tarball = &layer_tarballs[position];
+        let diff_id = diff_ids.get(position).map(|id| id.to_string());
+
+        // Extract digest from tarball path, and use the filename part of it as the
+        // ID; legacy layers are all named layer.tar
+        let (id, digest) = match Self::legacy_layer_digest(tarball, diff_id.as_deref()) {
+            Some(digest) => (digest.clone(), digest),
+            None => (
+                tarball
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| format!("layer-{position}")),
+                crate::digest_tracker::DigestTracker::extract_digest_from_tarball_path(tarball),
+            ),
+        };
+
+        // Metadata-only archives list layers without shipping their blobs; the
+        // layer keeps its digest but has no filesystem changes to apply
+        if tarball.exists() {
+            let size = fs::metadata(tarball).map(|m| m.len()).unwrap_or(0);
+            (id, Some(tarball.clone()), digest, size, diff_id)
+        } else {
+            (id, None, digest, 0, diff_id)
+        }
+    }
 
-        if manifest.is_empty() {
-            return Err(anyhow!("Empty manifest.json"));
+    /// Digest of a layer of the legacy `docker save` layout, `None` for other layouts.
+    ///
+    /// The directory of a legacy `layer.tar` is named after the v1 ID of the layer, which
+    /// depends on its parents and isn't the digest of its contents; `diff_id` is, when the
+    /// config lists one. Otherwise the `id` recorded in the `json` next to the tarball,
+    /// or the directory name, stands in for it.
+    fn legacy_layer_digest(tarball: &Path, diff_id: Option<&str>) -> Option<String> {
+        if tarball.file_name()? != "layer.tar" {
+            return None;
+        }
+        let dir = tarball.parent()?;
+        if let Some(diff_id) = diff_id {
+            return Some(diff_id.to_string());
         }
+        let v1_id = fs::read(dir.join("json"))
+            .ok()
+            .and_then(|json| serde_json::from_slice::<serde_json::Value>(&json).ok())
+            .and_then(|json| json["id"].as_str().map(str::to_string))
+            .or_else(|| Some(dir.file_name()?.to_string_lossy().into_owned()))?;
+        Some(match v1_id.strip_prefix("sha256:") {
+            Some(_) => v1_id,
+            None => format!("sha256:{v1_id}"),
+        })
+    }
+
+    fn load_layers_from_dir(
+        extract_dir: &Path,
+        index: usize,
+        platform: Option<&Platform>,
+        layer_mismatch: LayerMismatch,
+    ) -> Result<(Vec<Layer>, Option<String>)> {
+        let manifest = Self::read_manifest(extract_dir, platform)?;
+        let entry = manifest
+            .get(index)
+            .ok_or_else(|| anyhow!("manifest.json has no image {index}"))?;
 
         // Get the config file name from the manifest
-        let config_file = manifest[0]["Config"]
+        let config_file = entry["Config"]
             .as_str()
             .ok_or_else(|| anyhow!("Invalid manifest format - missing Config"))?;
 
         // Read the config file as JSON
-        let config_path = extract_dir.join(config_file);
+        let config_path = Self::resolve_archive_path(extract_dir, config_file)?;
         let config_content = fs::read_to_string(&config_path)
             .context(format!("Failed to read config file: {config_file}"))?;
 
         let config: serde_json::Value =
             serde_json::from_str(&config_content).context("Failed to parse image configuration")?;
 
-        // Get history from the config - this contains info about empty layers
-        let history = config["history"]
+        // Get history from the config - this contains info about empty layers. Images
+        // built without a Dockerfile (e.g. `FROM scratch` by some tools) may have none
+        let mut history = config["history"]
             .as_array()
-            .ok_or_else(|| anyhow!("No history found in image configuration"))?;
+            .map(Vec::as_slice)
+            .unwrap_or_default();
 
         // Get the actual layer paths (tarballs) from manifest
-        let layers_list = manifest[0]["Layers"]
+        let layers_list = entry["Layers"]
             .as_array()
             .ok_or_else(|| anyhow!("Invalid manifest format - missing Layers array"))?;
 
+        // The digests of the uncompressed layers, in the same order
+        let diff_ids: Vec<&str> = config["rootfs"]["diff_ids"]
+            .as_array()
+            .map(|ids| ids.iter().filter_map(|id| id.as_str()).collect())
+            .unwrap_or_default();
+
         let mut layer_tarballs = Vec::new();
         for layer_ref in layers_list {
             let layer_path = layer_ref
                 .as_str()
                 .ok_or_else(|| anyhow!("Invalid layer reference"))?;
-            let full_path = extract_dir.join(layer_path);
+            let full_path = Self::resolve_archive_path(extract_dir, layer_path)?;
             layer_tarballs.push(full_path);
         }
 
+        // Non-empty history entries and layers should pair up one to one
+        let non_empty = history
+            .iter()
+            .filter(|entry| !entry["empty_layer"].as_bool().unwrap_or(false))
+            .count();
+        let history_mismatch = if history.is_empty() || non_empty == layer_tarballs.len() {
+            None
+        } else {
+            let counts = format!(
+                "The image history has {non_empty} non-empty entries but the manifest lists {} layers",
+                layer_tarballs.len()
+            );
+            Some(match layer_mismatch {
+                LayerMismatch::Error => {
+                    bail!("{counts}; layers can't be attributed to history entries")
+                }
+                LayerMismatch::ManifestOnly => {
+                    history = &[];
+                    format!("{counts}; ignoring the history")
+                }
+                LayerMismatch::BestEffort if non_empty < layer_tarballs.len() => format!(
+                    "{counts}; pairing them from the newest layer, the oldest {} layers get no history entry",
+                    layer_tarballs.len() - non_empty
+                ),
+                LayerMismatch::BestEffort => format!(
+                    "{counts}; pairing them from the newest layer, the oldest {} non-empty history entries get no layer",
+                    non_empty - layer_tarballs.len()
+                ),
+            })
+        };
+
         // We need to track which history entries have associated layer blobs
         // Since we process history in reverse (newest to oldest), we need to also
         // process tarballs in reverse to maintain correct mapping
@@ -294,21 +790,10 @@ impl ExtractedImage {
             let comment = hist_entry["comment"].as_str().map(|s| s.to_string());
 
             // For non-empty layers, assign a tarball path and digest
-            let (id, tarball_path, digest) = if !is_empty && current_tarball_idx > 0 {
+            let (id, tarball_path, digest, size, diff_id) = if !is_empty && current_tarball_idx > 0
+            {
                 current_tarball_idx -= 1;
-                let tarball = &layer_tarballs[current_tarball_idx];
-
-                // Use the filename part of the tarball path as the ID
-                let id = tarball
-                    .file_name()
-                    .map(|name| name.to_string_lossy().to_string())
-                    .unwrap_or_else(|| format!("layer-{i}"));
-
-                // Extract digest from tarball path
-                let digest =
-                    crate::digest_tracker::DigestTracker::extract_digest_from_tarball_path(tarball);
-
-                (id, Some(tarball.clone()), digest)
+                Self::tarball_layer(&layer_tarballs, &diff_ids, current_tarball_idx)
             } else {
                 // Empty layer or no tarball available
                 let id = format!("<empty-layer-{i}>");
@@ -317,7 +802,7 @@ impl ExtractedImage {
                 } else {
                     "no-tarball".to_string()
                 };
-                (id, None, digest)
+                (id, None, digest, 0, None)
             };
 
             layers.push(Layer {
@@ -328,12 +813,79 @@ impl ExtractedImage {
                 tarball_path,
                 digest,
                 comment,
+                size,
+                diff_id,
+            });
+        }
+
+        // Tarballs left without a history entry (no history, or fewer entries than layers)
+        // get one of their own, dated like the image so conversions stay reproducible
+        let image_created = config["created"]
+            .as_str()
+            .and_then(|created| DateTime::parse_from_rfc3339(created).ok())
+            .map(|created| created.with_timezone(&Utc))
+            .unwrap_or(DateTime::UNIX_EPOCH);
+        while current_tarball_idx > 0 {
+            current_tarball_idx -= 1;
+            let (id, tarball_path, digest, size, diff_id) =
+                Self::tarball_layer(&layer_tarballs, &diff_ids, current_tarball_idx);
+            layers.push(Layer {
+                id,
+                command: format!(
+                    "Layer {}/{} (no history entry)",
+                    current_tarball_idx + 1,
+                    layer_tarballs.len()
+                ),
+                created_at: image_created,
+                is_empty: false,
+                tarball_path,
+                digest,
+                comment: None,
+                size,
+                diff_id,
             });
         }
 
         // Since we processed history in reverse order, reverse the layers to get oldest first
         layers.reverse();
 
-        Ok(layers)
+        Ok((layers, history_mismatch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_archive_path() {
+        let root = Path::new("/tmp/extracted");
+        assert_eq!(
+            ExtractedImage::resolve_archive_path(root, "blobs/sha256/abc").unwrap(),
+            root.join("blobs/sha256/abc")
+        );
+        assert!(ExtractedImage::resolve_archive_path(root, "../../etc/passwd").is_err());
+        assert!(ExtractedImage::resolve_archive_path(root, "/etc/passwd").is_err());
+        assert!(ExtractedImage::resolve_archive_path(root, "").is_err());
+    }
+
+    #[test]
+    fn test_legacy_layer_digest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let v1_id = "a".repeat(64);
+        let dir = temp_dir.path().join(&v1_id);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("VERSION"), "1.0").unwrap();
+        fs::write(dir.join("json"), format!(r#"{{"id":"{v1_id}"}}"#)).unwrap();
+        let tarball = dir.join("layer.tar");
+
+        let digest = |diff_id| ExtractedImage::legacy_layer_digest(&tarball, diff_id);
+        assert_eq!(digest(Some("sha256:diff")).unwrap(), "sha256:diff");
+        assert_eq!(digest(None).unwrap(), format!("sha256:{v1_id}"));
+        fs::remove_file(dir.join("json")).unwrap();
+        assert_eq!(digest(None).unwrap(), format!("sha256:{v1_id}"));
+
+        let blob = temp_dir.path().join("blobs/sha256").join(&v1_id);
+        assert!(ExtractedImage::legacy_layer_digest(&blob, Some("sha256:diff")).is_none());
     }
 }
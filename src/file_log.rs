@@ -0,0 +1,197 @@
+//! `oci2git file-log`: the layers that touched a path.
+//!
+//! [`file_log`] walks the layer commits of a branch (see
+//! [`crate::converted_repo::ConvertedRepo::layers`]) and compares the entry at the path in
+//! the rootfs directory of each with that of the layer before, like
+//! `git log -- rootfs/<path>` with the layer commands and digests attached. A directory
+//! counts as modified whenever anything below it changes. Renames are not followed: a
+//! moved file is deleted at its old path. [`render_table`] prints one row per change:
+//!
+//! ```text
+//! LAYER  CHANGE    TYPE  SIZE  DIGEST        COMMAND
+//! 3      created   file  1007  3b25b682ea82  RUN apt-get install -y nginx
+//! 7      modified  file  1093  a0f81e2b4c9d  COPY nginx.conf /etc/nginx/nginx.conf
+//! ```
+
+use crate::converted_repo::ConvertedRepo;
+use crate::query::rootfs_tree;
+use crate::sources::extract_short_digest;
+use anyhow::Result;
+use git2::Oid;
+use serde::Serialize;
+use std::path::Path;
+
+/// What a layer did to the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathChange {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl PathChange {
+    /// `created`, `modified` or `deleted`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Modified => "modified",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+/// A layer that changed the path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileLogEntry {
+    /// 1-based layer index
+    pub layer: usize,
+    pub commit: String,
+    pub digest: String,
+    pub command: String,
+    pub created: String,
+    pub change: PathChange,
+    /// `file`, `symlink` or `dir` after the layer, or before it for deletions
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Size in bytes after the layer; `None` for directories and deletions
+    pub size: Option<u64>,
+}
+
+/// The changes the layers of `branch` made to `path` (`/etc/nginx/nginx.conf` or
+/// `etc/nginx/nginx.conf`), oldest first.
+///
+/// # Errors
+/// - `branch` doesn't exist, or Git failures.
+pub fn file_log(repo: &ConvertedRepo, branch: &str, path: &str) -> Result<Vec<FileLogEntry>> {
+    let path = Path::new(path.trim_start_matches('/'));
+    let git = &repo.git().repo;
+    let mut entries = Vec::new();
+    let mut previous: Option<(Oid, i32)> = None;
+    for layer in repo.layers(branch)? {
+        let tree = git.find_commit(layer.commit)?.tree()?;
+        let current = match rootfs_tree(git, &tree)? {
+            Some(rootfs) => rootfs
+                .get_path(path)
+                .ok()
+                .map(|entry| (entry.id(), entry.filemode())),
+            None => None,
+        };
+        let change = match (previous, current) {
+            (None, Some(after)) => Some((PathChange::Created, after)),
+            (Some(before), None) => Some((PathChange::Deleted, before)),
+            (Some(before), Some(after)) if before != after => Some((PathChange::Modified, after)),
+            _ => None,
+        };
+        if let Some((change, (id, mode))) = change {
+            let size = match (change, mode) {
+                (PathChange::Deleted, _) | (_, 0o040000) => None,
+                _ => Some(git.find_blob(id)?.size() as u64),
+            };
+            entries.push(FileLogEntry {
+                layer: layer.index,
+                commit: layer.commit.to_string(),
+                digest: layer.layer.digest,
+                command: layer.layer.command,
+                created: layer.layer.created,
+                change,
+                kind: kind(mode).to_string(),
+                size,
+            });
+        }
+        previous = current;
+    }
+    Ok(entries)
+}
+
+/// Name of the type of a tree entry with `mode`.
+fn kind(mode: i32) -> &'static str {
+    match mode {
+        0o040000 => "dir",
+        0o120000 => "symlink",
+        0o160000 => "submodule",
+        _ => "file",
+    }
+}
+
+/// One aligned row per change, digests shortened to 12 characters.
+pub fn render_table(entries: &[FileLogEntry]) -> String {
+    let header = ["LAYER", "CHANGE", "TYPE", "SIZE", "DIGEST", "COMMAND"];
+    let rows: Vec<[String; 6]> = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.layer.to_string(),
+                entry.change.as_str().to_string(),
+                entry.kind.clone(),
+                entry
+                    .size
+                    .map_or_else(|| "-".to_string(), |size| size.to_string()),
+                extract_short_digest(&entry.digest).unwrap_or_else(|| entry.digest.clone()),
+                entry.command.clone(),
+            ]
+        })
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let mut push_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    };
+    push_row(header.to_vec());
+    for row in &rows {
+        push_row(row.iter().map(String::as_str).collect());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let table = render_table(&[
+            FileLogEntry {
+                layer: 3,
+                commit: "c0ffee".to_string(),
+                digest: "sha256:0123456789abcdef".to_string(),
+                command: "RUN apt-get install -y nginx".to_string(),
+                created: "2024-03-01T12:00:00+00:00".to_string(),
+                change: PathChange::Created,
+                kind: "file".to_string(),
+                size: Some(1007),
+            },
+            FileLogEntry {
+                layer: 12,
+                commit: "decaf".to_string(),
+                digest: "sha256:fedcba9876543210".to_string(),
+                command: "RUN rm /etc/nginx/nginx.conf".to_string(),
+                created: "2024-03-01T12:00:00+00:00".to_string(),
+                change: PathChange::Deleted,
+                kind: "file".to_string(),
+                size: None,
+            },
+        ]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "LAYER  CHANGE   TYPE  SIZE  DIGEST        COMMAND",
+                "3      created  file  1007  0123456789ab  RUN apt-get install -y nginx",
+                "12     deleted  file  -     fedcba987654  RUN rm /etc/nginx/nginx.conf",
+            ]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Only-app-layers conversions: leave the base image layers out of `rootfs/`.
+//!
+//! With [`crate::processor::ImageProcessor::with_from_layer`], the layers before a given
+//! one are committed like those of a metadata-only conversion: empty commits carrying
+//! their digests and history entries, without files. The filesystem of the branch then
+//! only holds what the later layers wrote, which is usually a small fraction of the
+//! image. Where the application starts is given as:
+//! - the 1-based index of its first layer ([`FromLayer::Index`], the `Layer-Index` of
+//!   the commit),
+//! - that layer's digest or `diff_id` ([`FromLayer::Digest`]),
+//! - the base image it was built on ([`FromLayer::Base`]): fetched with the same source,
+//!   its layers must be a prefix of the image's, and the application starts right after.
+//!
+//! Skipped layers never match layers whose files were committed, so such branches are
+//! named with [`FROM_LAYER_SUFFIX`] and the layer index, and share their commits only
+//! with conversions skipping the same layers.
+
+use crate::extracted_image::Layer;
+use anyhow::{anyhow, bail, Result};
+use std::str::FromStr;
+
+/// Appended, with the index of the first materialized layer, to the branch name.
+pub const FROM_LAYER_SUFFIX: &str = "#from-layer-";
+
+/// Where the materialized layers of an image start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FromLayer {
+    /// 1-based index of the first layer (history entry) to materialize
+    Index(usize),
+    /// Digest or `diff_id` of the first layer to materialize
+    Digest(String),
+    /// Image whose layers are skipped
+    Base(String),
+}
+
+impl FromStr for FromLayer {
+    type Err = anyhow::Error;
+
+    /// A layer index (`3`) or digest (`sha256:…`); see [`FromLayer::Base`] for images.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.starts_with("sha256:") {
+            return Ok(Self::Digest(s.to_string()));
+        }
+        match s.parse::<usize>() {
+            Ok(0) => bail!("Layer indexes start at 1"),
+            Ok(index) => Ok(Self::Index(index)),
+            Err(_) => bail!("'{s}' is neither a layer index nor a sha256 digest"),
+        }
+    }
+}
+
+impl FromLayer {
+    /// Index into `layers` of the first layer to materialize. `base_layers` fetches the
+    /// layers of a [`FromLayer::Base`] image.
+    ///
+    /// # Errors
+    /// - The index is beyond the image's layers, no layer has the digest, or the base
+    ///   image's layers aren't a prefix of `layers`.
+    /// - `base_layers` fails.
+    pub fn start(
+        &self,
+        layers: &[Layer],
+        base_layers: impl FnOnce(&str) -> Result<Vec<Layer>>,
+    ) -> Result<usize> {
+        match self {
+            Self::Index(index) if *index > layers.len() => bail!(
+                "--from-layer {index} is beyond the {} layers of the image",
+                layers.len()
+            ),
+            Self::Index(index) => Ok(index - 1),
+            Self::Digest(digest) => layers
+                .iter()
+                .position(|layer| &layer.digest == digest || layer.diff_id.as_ref() == Some(digest))
+                .ok_or_else(|| anyhow!("The image has no layer {digest}")),
+            Self::Base(base) => {
+                let base_layers = base_layers(base)?;
+                let is_prefix = base_layers.len() <= layers.len()
+                    && base_layers
+                        .iter()
+                        .zip(layers)
+                        .all(|(base, layer)| same_layer(base, layer));
+                if !is_prefix {
+                    bail!("The image is not built on '{base}': its layers don't start with those of the base");
+                }
+                Ok(base_layers.len())
+            }
+        }
+    }
+}
+
+/// Whether two layers are the same history entry.
+fn same_layer(a: &Layer, b: &Layer) -> bool {
+    a.is_empty == b.is_empty
+        && a.created_at == b.created_at
+        && a.command == b.command
+        && (a.is_empty || a.digest == b.digest)
+}
+
+/// Drops the tarballs of the layers before `start`, so they are committed without files.
+pub fn skip(layers: &mut [Layer], start: usize) {
+    for layer in layers.iter_mut().take(start) {
+        layer.tarball_path = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn layer(index: usize) -> Layer {
+        Layer {
+            id: format!("sha256:{index}"),
+            command: format!("RUN step {index}"),
+            created_at: Utc.timestamp_opt(index as i64 * 60, 0).unwrap(),
+            is_empty: false,
+            tarball_path: Some(format!("{index}/layer.tar").into()),
+            digest: format!("sha256:{index}"),
+            comment: None,
+            size: 1,
+            diff_id: Some(format!("sha256:d{index}")),
+        }
+    }
+
+    #[test]
+    fn test_start() {
+        let layers: Vec<_> = (1..=3).map(layer).collect();
+        let no_base = |_: &str| -> Result<Vec<Layer>> { unreachable!() };
+        let start = |from: &str| from.parse::<FromLayer>().unwrap().start(&layers, no_base);
+        assert_eq!(start("1").unwrap(), 0);
+        assert_eq!(start("3").unwrap(), 2);
+        assert!(start("4").is_err());
+        assert_eq!(start("sha256:2").unwrap(), 1);
+        assert_eq!(start("sha256:d3").unwrap(), 2);
+        assert!(start("sha256:9").is_err());
+        assert!("0".parse::<FromLayer>().is_err());
+        assert!("app".parse::<FromLayer>().is_err());
+
+        let base = FromLayer::Base("base".to_string());
+        assert_eq!(
+            base.start(&layers, |_| Ok(layers[..2].to_vec())).unwrap(),
+            2
+        );
+        let mut other = layers[..2].to_vec();
+        other[1].digest = "sha256:other".to_string();
+        assert!(base.start(&layers, |_| Ok(other)).is_err());
+
+        let mut skipped = layers.clone();
+        skip(&mut skipped, 2);
+        assert!(skipped[1].tarball_path.is_none());
+        assert!(skipped[2].tarball_path.is_some());
+    }
+}
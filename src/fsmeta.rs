@@ -0,0 +1,449 @@
+//! Filesystem metadata sidecar: `.oci2git/fsmeta.jsonl`.
+//!
+//! Git only keeps file contents, the executable bit and symlink targets, so ownership,
+//! full permission bits, timestamps, extended attributes and device nodes are lost when
+//! layers are committed. With `--fs-metadata` every layer commit also carries
+//! [`FSMETA_PATH`], one JSON object per path in `rootfs/`, sorted by path:
+//!
+//! ```text
+//! {"path":"/usr/bin/ping","type":"file","mode":"4755","uid":0,"gid":0,"user":"root","group":"root","mtime":1700000000,"xattrs":{"security.capability":"hex:0100000200200000"}}
+//! {"path":"/dev/null","type":"char","mode":"0666","uid":0,"gid":0,"mtime":1700000000,"device":[1,3]}
+//! ```
+//!
+//! The values come straight from the layer tar headers (PAX records included), not from
+//! the extracted files, so they are exact even when oci2git runs unprivileged. Extended
+//! attributes, such as the file capabilities set by `setcap`, are read from both the
+//! `SCHILY.xattr.*` records of GNU tar and Docker and the `LIBARCHIVE.xattr.*` records of
+//! bsdtar. They are kept as text when printable UTF-8 and as `hex:…` otherwise.
+//!
+//! [`FsMetadata`] is a [`LayerObserver`]: whiteouts drop the deleted paths, so after each
+//! layer the sidecar describes exactly the files of that commit.
+
+use crate::layer_apply::{normalize_tar_path, LayerObserver, ObservedEntry};
+use crate::sources::credentials::base64_decode;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path};
+use tar_rs as tar;
+
+/// Location of the sidecar, relative to the repository root.
+pub const FSMETA_PATH: &str = ".oci2git/fsmeta.jsonl";
+
+/// PAX record prefix for extended attributes.
+const XATTR_PREFIX: &str = "SCHILY.xattr.";
+/// Prefix bsdtar writes next to (or, for names that aren't valid PAX keys, instead of)
+/// [`XATTR_PREFIX`], with a percent-encoded name and a base64 value.
+const LIBARCHIVE_XATTR_PREFIX: &str = "LIBARCHIVE.xattr.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    Hardlink,
+    Char,
+    Block,
+    Fifo,
+}
+
+/// Metadata of one path, as recorded in the layer that last wrote it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsEntry {
+    /// Absolute path inside the image, e.g. `/etc/passwd`
+    pub path: String,
+    #[serde(rename = "type")]
+    pub file_type: FileType,
+    /// Permission bits in octal, including setuid/setgid/sticky (e.g. `4755`)
+    pub mode: String,
+    pub uid: u64,
+    pub gid: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Modification time, seconds since the Unix epoch
+    pub mtime: u64,
+    /// Symlink target as stored, or the absolute path a hardlink points to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// Major and minor number of device nodes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<(u32, u32)>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, String>,
+}
+
+impl FsEntry {
+    /// Builds the entry for `path` from its tar header and PAX records; `None` for entry
+    /// types that don't end up in the filesystem.
+    pub fn from_observed(path: &Path, entry: &ObservedEntry) -> Option<Self> {
+        let header = &entry.header;
+        let file_type = match header.entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => {
+                FileType::File
+            }
+            tar::EntryType::Directory => FileType::Dir,
+            tar::EntryType::Symlink => FileType::Symlink,
+            tar::EntryType::Link => FileType::Hardlink,
+            tar::EntryType::Char => FileType::Char,
+            tar::EntryType::Block => FileType::Block,
+            tar::EntryType::Fifo => FileType::Fifo,
+            _ => return None,
+        };
+
+        let non_empty = |name: Option<&str>| name.filter(|n| !n.is_empty()).map(str::to_string);
+        let mut fs_entry = Self {
+            path: image_path(path),
+            file_type,
+            mode: format!("{:04o}", header.mode().unwrap_or(0) & 0o7777),
+            uid: header.uid().unwrap_or(0),
+            gid: header.gid().unwrap_or(0),
+            user: non_empty(header.username().ok().flatten()),
+            group: non_empty(header.groupname().ok().flatten()),
+            mtime: header.mtime().unwrap_or(0),
+            link: None,
+            device: None,
+            xattrs: BTreeMap::new(),
+        };
+
+        match file_type {
+            FileType::Symlink => {
+                fs_entry.link = entry
+                    .link_name
+                    .as_ref()
+                    .map(|target| target.to_string_lossy().into_owned());
+            }
+            FileType::Hardlink => {
+                fs_entry.link = entry
+                    .link_name
+                    .as_ref()
+                    .map(|target| image_path(&normalize_tar_path(target)));
+            }
+            FileType::Char | FileType::Block => {
+                fs_entry.device = header
+                    .device_major()
+                    .ok()
+                    .flatten()
+                    .zip(header.device_minor().ok().flatten());
+            }
+            _ => {}
+        }
+
+        // PAX records override the (size-limited) ustar fields
+        for (key, value) in &entry.pax {
+            let text = std::str::from_utf8(value).ok();
+            match key.as_str() {
+                "uid" => fs_entry.uid = text.and_then(|t| t.parse().ok()).unwrap_or(fs_entry.uid),
+                "gid" => fs_entry.gid = text.and_then(|t| t.parse().ok()).unwrap_or(fs_entry.gid),
+                "uname" => fs_entry.user = non_empty(text).or(fs_entry.user.take()),
+                "gname" => fs_entry.group = non_empty(text).or(fs_entry.group.take()),
+                _ => {
+                    if let Some(name) = key.strip_prefix(XATTR_PREFIX) {
+                        fs_entry.xattrs.insert(name.to_string(), xattr_value(value));
+                    } else if let Some(name) = key.strip_prefix(LIBARCHIVE_XATTR_PREFIX) {
+                        // The SCHILY record of the same attribute wins
+                        let decoded = text.and_then(|t| base64_decode(t).ok());
+                        if let Some(decoded) = decoded {
+                            fs_entry
+                                .xattrs
+                                .entry(percent_decode(name))
+                                .or_insert_with(|| xattr_value(&decoded));
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(fs_entry)
+    }
+}
+
+/// `etc/passwd` → `/etc/passwd`
+pub(crate) fn image_path(path: &Path) -> String {
+    let mut image_path = String::new();
+    for component in path.components() {
+        if let Component::Normal(name) = component {
+            image_path.push('/');
+            image_path.push_str(&name.to_string_lossy());
+        }
+    }
+    if image_path.is_empty() {
+        image_path.push('/');
+    }
+    image_path
+}
+
+/// `security%2Ecapability` → `security.capability`; invalid escapes are kept as is.
+fn percent_decode(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| name.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Printable UTF-8 as is, anything else (e.g. file capabilities) as `hex:…`
+fn xattr_value(value: &[u8]) -> String {
+    match std::str::from_utf8(value) {
+        Ok(text) if !text.chars().any(char::is_control) => text.to_string(),
+        _ => {
+            let hex: String = value.iter().map(|byte| format!("{byte:02x}")).collect();
+            format!("hex:{hex}")
+        }
+    }
+}
+
+/// Inverse of [`xattr_value`]: the raw bytes of a recorded attribute value.
+pub(crate) fn xattr_bytes(value: &str) -> Result<Vec<u8>> {
+    let Some(hex) = value.strip_prefix("hex:") else {
+        return Ok(value.as_bytes().to_vec());
+    };
+    if hex.len() % 2 != 0 {
+        bail!("Invalid hex xattr value: '{value}'");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .with_context(|| format!("Invalid hex xattr value: '{value}'"))
+        })
+        .collect()
+}
+
+/// The filesystem metadata of a `rootfs/`, keyed by image path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsMetadata {
+    pub entries: BTreeMap<String, FsEntry>,
+}
+
+impl FsMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the contents of a sidecar file.
+    ///
+    /// # Errors
+    /// - A line that isn't a valid entry.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: FsEntry = serde_json::from_str(line).with_context(|| {
+                format!("Invalid entry on line {} of {FSMETA_PATH}", number + 1)
+            })?;
+            entries.insert(entry.path.clone(), entry);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Writes the sidecar to `path`, one entry per line in path order.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut content = String::new();
+        for entry in self.entries.values() {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Drops the entries below image path `path` (but not `path` itself).
+pub(crate) fn remove_descendants<T>(entries: &mut BTreeMap<String, T>, path: &str) {
+    let prefix = if path == "/" {
+        path.to_string()
+    } else {
+        format!("{path}/")
+    };
+    entries.retain(|key, _| key == path || !key.starts_with(&prefix));
+}
+
+impl LayerObserver for FsMetadata {
+    fn entry(&mut self, path: &Path, entry: &ObservedEntry) {
+        let Some(fs_entry) = FsEntry::from_observed(path, entry) else {
+            return;
+        };
+        // A file, link or device replacing a directory takes its contents with it
+        if fs_entry.file_type != FileType::Dir {
+            remove_descendants(&mut self.entries, &fs_entry.path);
+        }
+        self.entries.insert(fs_entry.path.clone(), fs_entry);
+    }
+
+    fn removed(&mut self, path: &Path) {
+        let path = image_path(path);
+        remove_descendants(&mut self.entries, &path);
+        self.entries.remove(&path);
+    }
+
+    fn cleared(&mut self, path: &Path) {
+        remove_descendants(&mut self.entries, &image_path(path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_apply::{ConflictStrategy, LayerApplier};
+    use tempfile::tempdir;
+
+    fn header(entry_type: tar::EntryType, mode: u32, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(size);
+        header.set_uid(1000);
+        header.set_gid(50);
+        header.set_mtime(1_700_000_000);
+        header
+    }
+
+    fn apply(
+        root: &Path,
+        metadata: &mut FsMetadata,
+        build: impl FnOnce(&mut tar::Builder<Vec<u8>>),
+    ) {
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        let data = builder.into_inner().unwrap();
+        LayerApplier::new(root, ConflictStrategy::default())
+            .unwrap()
+            .with_observer(metadata)
+            .apply_archive(&mut tar::Archive::new(data.as_slice()))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_records_layer_metadata() {
+        let temp_dir = tempdir().unwrap();
+        let mut metadata = FsMetadata::new();
+
+        apply(temp_dir.path(), &mut metadata, |b| {
+            let mut dir = header(tar::EntryType::Directory, 0o1777, 0);
+            dir.set_username("root").unwrap();
+            b.append_data(&mut dir, "tmp/", &[][..]).unwrap();
+
+            let mut ping = header(tar::EntryType::Regular, 0o4755, 2);
+            b.append_pax_extensions([
+                ("SCHILY.xattr.security.capability", &[1u8, 0, 0, 2][..]),
+                ("SCHILY.xattr.user.note", &b"hello"[..]),
+                ("uid", &b"100000"[..]),
+            ])
+            .unwrap();
+            b.append_data(&mut ping, "usr/bin/ping", &b"hi"[..])
+                .unwrap();
+
+            // bsdtar: base64 values, percent-encoded names, SCHILY record preferred
+            let mut arping = header(tar::EntryType::Regular, 0o755, 0);
+            b.append_pax_extensions([
+                ("LIBARCHIVE.xattr.security.capability", &b"AQAAAg"[..]),
+                ("LIBARCHIVE.xattr.user.a%3Db", &b"eA=="[..]),
+                ("LIBARCHIVE.xattr.user.note", &b"eA=="[..]),
+                ("SCHILY.xattr.user.note", &b"y"[..]),
+            ])
+            .unwrap();
+            b.append_data(&mut arping, "usr/bin/arping", &[][..])
+                .unwrap();
+
+            let mut link = header(tar::EntryType::Link, 0o755, 0);
+            b.append_link(&mut link, "usr/bin/ping6", "./usr/bin/ping")
+                .unwrap();
+            let mut null = header(tar::EntryType::Char, 0o666, 0);
+            null.set_device_major(1).unwrap();
+            null.set_device_minor(3).unwrap();
+            b.append_data(&mut null, "dev/null", &[][..]).unwrap();
+        });
+
+        let tmp = &metadata.entries["/tmp"];
+        assert_eq!(tmp.file_type, FileType::Dir);
+        assert_eq!(tmp.mode, "1777");
+        assert_eq!(tmp.user.as_deref(), Some("root"));
+
+        let ping = &metadata.entries["/usr/bin/ping"];
+        assert_eq!(ping.mode, "4755");
+        assert_eq!(
+            (ping.uid, ping.gid, ping.mtime),
+            (100000, 50, 1_700_000_000)
+        );
+        assert_eq!(ping.xattrs["security.capability"], "hex:01000002");
+        assert_eq!(ping.xattrs["user.note"], "hello");
+
+        let arping = &metadata.entries["/usr/bin/arping"].xattrs;
+        assert_eq!(arping["security.capability"], "hex:01000002");
+        assert_eq!(arping["user.a=b"], "x");
+        assert_eq!(arping["user.note"], "y");
+
+        let ping6 = &metadata.entries["/usr/bin/ping6"];
+        assert_eq!(ping6.file_type, FileType::Hardlink);
+        assert_eq!(ping6.link.as_deref(), Some("/usr/bin/ping"));
+
+        // Devices are skipped on extraction but still recorded
+        assert!(!temp_dir.path().join("dev/null").exists());
+        assert_eq!(metadata.entries["/dev/null"].device, Some((1, 3)));
+
+        // Round trip through the sidecar file
+        let sidecar = temp_dir.path().join(FSMETA_PATH);
+        metadata.save(&sidecar).unwrap();
+        let content = fs::read_to_string(&sidecar).unwrap();
+        assert_eq!(content.lines().count(), metadata.entries.len());
+        assert!(content.starts_with("{\"path\":\"/dev/null\""));
+        assert_eq!(FsMetadata::parse(&content).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_whiteouts_drop_entries() {
+        let temp_dir = tempdir().unwrap();
+        let mut metadata = FsMetadata::new();
+
+        apply(temp_dir.path(), &mut metadata, |b| {
+            for path in ["a/one", "a/two", "b/three", "c/four"] {
+                let mut file = header(tar::EntryType::Regular, 0o644, 1);
+                b.append_data(&mut file, path, &b"x"[..]).unwrap();
+            }
+        });
+        apply(temp_dir.path(), &mut metadata, |b| {
+            let mut whiteout = header(tar::EntryType::Regular, 0o644, 0);
+            b.append_data(&mut whiteout, "a/.wh.one", &[][..]).unwrap();
+            let mut opaque = header(tar::EntryType::Regular, 0o644, 0);
+            b.append_data(&mut opaque, "b/.wh..wh..opq", &[][..])
+                .unwrap();
+            let mut whiteout = header(tar::EntryType::Regular, 0o644, 0);
+            b.append_data(&mut whiteout, ".wh.c", &[][..]).unwrap();
+        });
+
+        assert_eq!(metadata.entries.keys().collect::<Vec<_>>(), ["/a/two"]);
+    }
+
+    #[test]
+    fn test_xattr_bytes_round_trip() {
+        assert_eq!(xattr_bytes("user.x").unwrap(), b"user.x");
+        assert_eq!(
+            xattr_bytes(&xattr_value(&[1, 0, 0, 2])).unwrap(),
+            vec![1, 0, 0, 2]
+        );
+        assert!(xattr_bytes("hex:0").is_err());
+    }
+}
@@ -3,20 +3,117 @@
 //! [`GitRepo`] exposes common flows you need for building history from layered filesystems:
 //! - [`GitRepo::init_with_branch`] — open or init a repo, set `user.name`/`user.email`, and
 //!   optionally select an **unborn** (orphan) branch; it will materialize on the first commit.
+//! - [`GitRepo::open`] — open an existing repo read-only (no init, no config writes).
 //! - [`GitRepo::create_branch`] — create a branch from an existing commit or select a new unborn
 //!   branch (HEAD attached to a yet-to-be-created ref); resets the worktree if branching from a commit.
 //! - [`GitRepo::commit_all_changes`] — stage everything and commit to `HEAD`; returns `true` if
-//!   there were staged changes, `false` for an “empty” commit.
+//!   there were staged changes, `false` for an “empty” commit. [`GitRepo::commit_layer`] does
+//!   the same for a layer, appending the layer trailers (see [`crate::trailers`]) and dating
+//!   the commit at the layer's creation time.
+//! - [`GitRepo::commit_touched`] — commit after staging only the paths a layer touched (see
+//!   [`crate::staging`]), with [`GitRepo::commit_layer_touched`] for layers.
+//! - [`GitRepo::commit_with_rootfs_tree`] — commit the worktree with a `rootfs/` tree built
+//!   elsewhere (see [`crate::tree_apply`]); [`GitRepo::sync_worktree`] checks the result out.
+//! - [`GitRepo::discard_changes`] — reset the index and worktree to `HEAD`, deleting untracked
+//!   files.
 //! - [`GitRepo::get_branch_commits`] — list commit OIDs oldest → newest for a branch.
 //! - [`GitRepo::get_all_branches`] / [`GitRepo::branch_exists`] / [`GitRepo::exists_and_has_commits`].
+//! - [`GitRepo::delete_branch`] and [`GitRepo::repack`] — drop branches, then pack what is
+//!   still reachable and delete everything else.
 //! - [`GitRepo::read_file_from_commit`] — read a UTF-8 file blob from a specific commit.
 //! - [`GitRepo::get_commit_successors`] — find the next commits after a given commit across branches.
+//! - [`GitRepo::commit_file_to_branch`] — commit a single file to another branch without touching
+//!   `HEAD`, the index or the worktree.
+//! - [`GitRepo::set_branch_description`] — set `branch.<name>.description` (shown by
+//!   `git branch --edit-description` and friends).
+//! - [`GitRepo::graft_as_root`] — make a commit appear parentless to Git via `refs/replace/`.
+//! - [`GitRepo::commit_message`] / [`GitRepo::set_note`] / [`GitRepo::read_note`] — commit
+//!   messages and `git notes`.
+//!
+//! - [`GitRepo::with_identity`] — commit as someone other than `oci2git`, optionally signing
+//!   every commit (see [`crate::signing`]).
 //!
 //! This wrapper is intentionally small; for advanced operations consult [`git2`] / libgit2 docs.
 
-use anyhow::{Context, Result};
+use crate::config_file::CONFIG_FILE;
+use crate::convert_options::DEFAULT_ROOTFS_DIR;
+use crate::signing::CommitSigner;
+use crate::staging::TouchedPaths;
+use crate::symlinks::{self, SymlinkTargets};
+use crate::temp_usage::dir_size;
+use crate::trailers::{self, LayerTrailers};
+use anyhow::{bail, Context, Result};
 use git2::{IndexAddOption, Repository, Signature};
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path of a tree or index entry name.
+fn bytes_path(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        std::ffi::OsStr::from_bytes(bytes).into()
+    }
+    #[cfg(not(unix))]
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Commit time of a layer: its `Layer-Created` time with its UTC offset, if that parses.
+fn layer_time(layer: &LayerTrailers) -> Option<git2::Time> {
+    let created = chrono::DateTime::parse_from_rfc3339(layer.created.as_deref()?).ok()?;
+    Some(git2::Time::new(
+        created.timestamp(),
+        created.offset().local_minus_utc() / 60,
+    ))
+}
+
+/// Git file mode of a regular file in the worktree: executable if its owner may execute it.
+fn worktree_file_mode(metadata: &fs::Metadata) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o100 != 0 {
+            return git2::FileMode::BlobExecutable.into();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    git2::FileMode::Blob.into()
+}
+
+/// Index entry of the worktree file or symlink at `path`, with its blob written to `repo`;
+/// what `git add` would stage for it.
+#[cfg(unix)]
+fn index_entry(repo: &Repository, workdir: &Path, path: &Path) -> Result<git2::IndexEntry> {
+    use std::os::unix::fs::MetadataExt;
+
+    let full = workdir.join(path);
+    let metadata = fs::symlink_metadata(&full)?;
+    let (id, mode) = if metadata.is_symlink() {
+        let target = fs::read_link(&full)?;
+        let blob = repo.blob(target.as_os_str().as_encoded_bytes())?;
+        (blob, git2::FileMode::Link.into())
+    } else {
+        (repo.blob_path(&full)?, worktree_file_mode(&metadata))
+    };
+    let path = path.as_os_str().as_encoded_bytes().to_vec();
+    // Stat data is truncated to 32 bits, as in the index file format
+    Ok(git2::IndexEntry {
+        ctime: git2::IndexTime::new(metadata.ctime() as i32, metadata.ctime_nsec() as u32),
+        mtime: git2::IndexTime::new(metadata.mtime() as i32, metadata.mtime_nsec() as u32),
+        dev: metadata.dev() as u32,
+        ino: metadata.ino() as u32,
+        mode: mode as u32,
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        file_size: metadata.len() as u32,
+        id,
+        flags: path.len().min(0xfff) as u16,
+        flags_extended: 0,
+        path,
+    })
+}
 
 /// A convenience wrapper around [`git2::Repository`] with helper methods for
 /// creating branches, staging all changes, committing, and simple history lookups.
@@ -25,11 +122,50 @@ use std::path::Path;
 /// nor spawns threads. See the upstream `git2` docs for lower-level primitives.
 pub struct GitRepo {
     pub repo: Repository,
+    identity: CommitIdentity,
+    /// Threads hashing files for [`GitRepo::commit_touched`]
+    jobs: usize,
+    /// Top-level directory holding the image filesystem
+    rootfs_dir: String,
+    /// How symlink targets below [`GitRepo::rootfs_dir`] are staged
+    symlink_targets: SymlinkTargets,
 }
 
 const USERNAME: &str = "oci2git";
 const EMAIL: &str = "oci2git@example.com";
 
+/// Author/committer of the commits a [`GitRepo`] creates, and how they are signed.
+///
+/// Defaults to `oci2git <oci2git@example.com>`, unsigned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitIdentity {
+    pub name: String,
+    pub email: String,
+    /// Signs every commit when set (see [`crate::signing`])
+    pub signer: Option<CommitSigner>,
+}
+
+/// Outcome of [`GitRepo::repack`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RepackStats {
+    /// Objects written to the new pack.
+    pub objects: usize,
+    /// Size of `.git/objects` before repacking.
+    pub bytes_before: u64,
+    /// Size of `.git/objects` after repacking.
+    pub bytes_after: u64,
+}
+
+impl Default for CommitIdentity {
+    fn default() -> Self {
+        Self {
+            name: USERNAME.to_string(),
+            email: EMAIL.to_string(),
+            signer: None,
+        }
+    }
+}
+
 impl GitRepo {
     /// Open an existing Git repository at `path` or initialize a new one, then
     /// set `user.name` / `user.email`. If `branch_name` is provided, move `HEAD`
@@ -66,7 +202,28 @@ impl GitRepo {
             .set_str("user.email", EMAIL)
             .context("Failed to set git email")?;
 
-        let git_repo = Self { repo };
+        // The repo-level config file lives in the worktree but belongs to no image branch
+        let exclude = repo.path().join("info").join("exclude");
+        let ignored = fs::read_to_string(&exclude).unwrap_or_default();
+        let entry = format!("/{CONFIG_FILE}");
+        if !ignored.lines().any(|line| line == entry) {
+            fs::create_dir_all(exclude.parent().unwrap_or(repo.path()))?;
+            let separator = if ignored.is_empty() || ignored.ends_with('\n') {
+                ""
+            } else {
+                "\n"
+            };
+            fs::write(&exclude, format!("{ignored}{separator}{entry}\n"))
+                .with_context(|| format!("Failed to write {}", exclude.display()))?;
+        }
+
+        let git_repo = Self {
+            repo,
+            identity: CommitIdentity::default(),
+            jobs: 1,
+            rootfs_dir: DEFAULT_ROOTFS_DIR.to_string(),
+            symlink_targets: SymlinkTargets::default(),
+        };
 
         // Create the custom branch if specified (from beginning, no initial commit)
         if let Some(branch) = branch_name {
@@ -75,12 +232,146 @@ impl GitRepo {
 
         Ok(git_repo)
     }
+
+    /// Open an existing Git repository at `path` without modifying it.
+    ///
+    /// Unlike [`GitRepo::init_with_branch`], this never creates a repository and never
+    /// touches the config, which makes it suitable for read-only inspection.
+    ///
+    /// # Errors
+    /// - `path` is not a Git repository.
+    pub fn open(path: &Path) -> Result<Self> {
+        let repo = Repository::open(path).context("Failed to open existing Git repository")?;
+        Ok(Self {
+            repo,
+            identity: CommitIdentity::default(),
+            jobs: 1,
+            rootfs_dir: DEFAULT_ROOTFS_DIR.to_string(),
+            symlink_targets: SymlinkTargets::default(),
+        })
+    }
+
+    /// Hash the files [`GitRepo::commit_touched`] stages on `jobs` threads instead of one.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Name of the top-level directory holding the image filesystem, which
+    /// [`GitRepo::commit_touched`] and [`GitRepo::commit_with_rootfs_tree`] treat apart
+    /// from the metadata files next to it; [`DEFAULT_ROOTFS_DIR`] unless set.
+    pub fn with_rootfs_dir(mut self, rootfs_dir: &str) -> Self {
+        self.rootfs_dir = rootfs_dir.to_string();
+        self
+    }
+
+    /// See [`GitRepo::with_rootfs_dir`].
+    pub fn rootfs_dir(&self) -> &str {
+        &self.rootfs_dir
+    }
+
+    /// With [`SymlinkTargets::Image`], symlinks below the rootfs directory pointing below
+    /// it are staged with their in-image targets, and [`GitRepo::create_branch`] points
+    /// them below it again after checking out a commit (see [`crate::symlinks`]).
+    pub fn with_symlink_targets(mut self, symlink_targets: SymlinkTargets) -> Self {
+        self.symlink_targets = symlink_targets;
+        self
+    }
+
+    /// Create the following commits (and notes) as `identity`, and record its name and
+    /// e-mail as `user.name` / `user.email` in the repository config.
+    ///
+    /// # Errors
+    /// - Name or e-mail Git can't put in a signature (e.g. containing `<`), or config
+    ///   write failures.
+    pub fn with_identity(mut self, identity: CommitIdentity) -> Result<Self> {
+        Signature::now(&identity.name, &identity.email).with_context(|| {
+            format!(
+                "Invalid commit identity {} <{}>",
+                identity.name, identity.email
+            )
+        })?;
+        let mut config = self.repo.config().context("Failed to get git config")?;
+        config
+            .set_str("user.name", &identity.name)
+            .context("Failed to set git username")?;
+        config
+            .set_str("user.email", &identity.email)
+            .context("Failed to set git email")?;
+        self.identity = identity;
+        Ok(self)
+    }
+
+    /// Signature of [`CommitIdentity`] at the current time
+    fn signature(&self) -> Result<Signature<'static>> {
+        self.signature_at(None)
+    }
+
+    /// Signature of [`CommitIdentity`] at `time`, or at the current time
+    fn signature_at(&self, time: Option<git2::Time>) -> Result<Signature<'static>> {
+        match time {
+            Some(time) => Signature::new(&self.identity.name, &self.identity.email, &time),
+            None => Signature::now(&self.identity.name, &self.identity.email),
+        }
+        .context("Failed to create git signature")
+    }
+
+    /// `Repository::commit`, signing the commit when the identity has a signer.
+    ///
+    /// libgit2 doesn't update references for signed commits, so `update_ref` (`HEAD` or a
+    /// full reference name) is moved here, following a symbolic `HEAD` to its branch.
+    fn create_commit(
+        &self,
+        update_ref: Option<&str>,
+        author: &Signature,
+        committer: &Signature,
+        message: &str,
+        tree: &git2::Tree,
+        parents: &[&git2::Commit],
+    ) -> Result<git2::Oid> {
+        let Some(signer) = &self.identity.signer else {
+            return self
+                .repo
+                .commit(update_ref, author, committer, message, tree, parents)
+                .context("Failed to create commit");
+        };
+
+        let buffer = self
+            .repo
+            .commit_create_buffer(author, committer, message, tree, parents)
+            .context("Failed to create commit buffer")?;
+        let content = buffer
+            .as_str()
+            .context("Commit buffer is not valid UTF-8")?;
+        let signature = signer.sign(content)?;
+        let oid = self
+            .repo
+            .commit_signed(content, &signature, None)
+            .context("Failed to create signed commit")?;
+
+        if let Some(update_ref) = update_ref {
+            let name = match self.repo.find_reference(update_ref) {
+                Ok(reference) => reference
+                    .symbolic_target()
+                    .unwrap_or(update_ref)
+                    .to_string(),
+                Err(_) => update_ref.to_string(),
+            };
+            let summary = message.lines().next().unwrap_or_default();
+            self.repo
+                .reference(&name, oid, true, &format!("commit: {summary}"))
+                .with_context(|| format!("Failed to update {name}"))?;
+        }
+        Ok(oid)
+    }
+
     /// Create/select a local branch and make `HEAD` point to it.
     ///
     /// - `from_commit: Some(oid)` — create `branch_name` at `oid`, set `HEAD` to it,
     ///   and **hard-reset** the worktree to the target commit (deterministic clean start).
     /// - `from_commit: None` — **select an unborn/orphan branch** by setting `HEAD`
-    ///   to `refs/heads/{branch_name}`. The ref will be created on the first commit.
+    ///   to `refs/heads/{branch_name}` and clearing the index and worktree. The ref will
+    ///   be created on the first commit.
     ///
     /// # Errors
     /// - Invalid OID or missing commit when branching from a commit.
@@ -97,6 +388,10 @@ impl GitRepo {
     /// # anyhow::Ok(())
     /// ```
     pub fn create_branch(&self, branch_name: &str, from_commit: Option<git2::Oid>) -> Result<()> {
+        log::debug!(
+            "Creating branch '{branch_name}' from {}",
+            from_commit.map_or("scratch".to_string(), |oid| oid.to_string())
+        );
         match from_commit {
             Some(commit_oid) => {
                 let target = self.repo.find_commit(commit_oid)?;
@@ -118,6 +413,12 @@ impl GitRepo {
                         Some(&mut git2::build::CheckoutBuilder::default()),
                     )
                     .context("Failed to reset working directory to branch point")?;
+                if self.symlink_targets == SymlinkTargets::Image {
+                    let rootfs = self.workdir()?.join(&self.rootfs_dir);
+                    if rootfs.is_dir() {
+                        symlinks::reroot(&rootfs)?;
+                    }
+                }
             }
             None => {
                 // Create orphaned branch by just setting HEAD to point to the new branch
@@ -125,6 +426,8 @@ impl GitRepo {
                 self.repo
                     .set_head(&format!("refs/heads/{branch_name}"))
                     .context("Failed to set HEAD to new branch")?;
+                // Start from an empty tree, not from what the previous branch left behind
+                self.discard_changes()?;
             }
         }
 
@@ -136,11 +439,13 @@ impl GitRepo {
     /// made with an empty tree diff (useful for metadata-only commits).
     ///
     /// Internally, this:
-    /// - creates a signature with `USERNAME`/`EMAIL`,
+    /// - creates a signature for the [`CommitIdentity`] (`oci2git` unless set with
+    ///   [`GitRepo::with_identity`]),
     /// - `add_all(["*"], ...)` to stage paths,
     /// - writes the index and tree,
     /// - looks up the current `HEAD` commit (if any) as the parent,
-    /// - and calls `commit("HEAD", ...)`. (For unborn branches, this becomes the root commit.)
+    /// - and commits to `HEAD`, signed if the identity has a signer. (For unborn branches,
+    ///   this becomes the root commit.)
     ///
     /// # Errors
     /// - Index operations, tree writes, signature creation, or commit creation can fail.
@@ -156,14 +461,20 @@ impl GitRepo {
     /// # anyhow::Ok(())
     /// ```
     pub fn commit_all_changes(&self, message: &str) -> Result<bool> {
-        let signature =
-            Signature::now(USERNAME, EMAIL).context("Failed to create git signature")?;
+        self.commit_all_changes_at(message, None)
+    }
+
+    /// [`GitRepo::commit_all_changes`], dated `time` instead of now.
+    fn commit_all_changes_at(&self, message: &str, time: Option<git2::Time>) -> Result<bool> {
+        let signature = self.signature_at(time)?;
 
         let mut index = self.repo.index().context("Failed to get git index")?;
 
         index
             .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
             .context("Failed to add files to git index")?;
+        let links: Vec<PathBuf> = index.iter().map(|entry| bytes_path(&entry.path)).collect();
+        self.stage_in_image_targets(&mut index, &links)?;
 
         let has_changes = !index.is_empty();
 
@@ -186,18 +497,480 @@ impl GitRepo {
 
         let parent_commits_refs: Vec<&git2::Commit> = parent_commits.iter().collect();
 
+        let commit_oid = self.create_commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parent_commits_refs,
+        )?;
+        log::debug!(
+            "Committed {commit_oid} ({} index entries): {}",
+            index.len(),
+            message.lines().next().unwrap_or_default()
+        );
+
+        Ok(has_changes)
+    }
+
+    /// Commit all changes as a layer commit: `subject` followed by the layer trailers
+    /// (`Layer-Index`, `Layer-Digest`, `Layer-Created`, `Layer-Empty`, see
+    /// [`crate::trailers`]). Returns what [`GitRepo::commit_all_changes`] returns.
+    ///
+    /// Layer commits are authored and committed at the layer's creation time, if it has
+    /// one, so `git log --since` and blame show when the layer was built.
+    ///
+    /// # Errors
+    /// - Same as [`GitRepo::commit_all_changes`].
+    pub fn commit_layer(&self, subject: &str, layer: &LayerTrailers) -> Result<bool> {
+        self.commit_all_changes_at(&trailers::layer_message(subject, layer), layer_time(layer))
+    }
+
+    /// Commit the worktree on `HEAD` with `rootfs` as its `rootfs/` directory, for layers
+    /// applied without a worktree (see [`crate::tree_apply`]). Everything else is taken
+    /// from the worktree as is, ignored files excepted; whatever is in the `rootfs/`
+    /// directory on disk is not looked at. The index is left alone; see
+    /// [`GitRepo::sync_worktree`].
+    ///
+    /// Returns `Ok(true)` if the tree differs from the parent's.
+    ///
+    /// # Errors
+    /// - Reading the worktree, writing blobs and trees, or creating the commit fails.
+    pub fn commit_with_rootfs_tree(
+        &self,
+        message: &str,
+        rootfs: Option<git2::Oid>,
+    ) -> Result<bool> {
+        self.commit_with_rootfs_tree_at(message, rootfs, None)
+    }
+
+    /// [`GitRepo::commit_with_rootfs_tree`], dated `time` instead of now.
+    fn commit_with_rootfs_tree_at(
+        &self,
+        message: &str,
+        rootfs: Option<git2::Oid>,
+        time: Option<git2::Time>,
+    ) -> Result<bool> {
+        let workdir = self
+            .repo
+            .workdir()
+            .context("Repository has no worktree")?
+            .to_path_buf();
+        let mut builder = self.worktree_builder(&workdir, Path::new(""))?;
+        match rootfs {
+            Some(rootfs) => {
+                builder.insert(&self.rootfs_dir, rootfs, git2::FileMode::Tree.into())?;
+            }
+            None => {
+                if builder.get(&self.rootfs_dir)?.is_some() {
+                    builder.remove(&self.rootfs_dir)?;
+                }
+            }
+        }
+        let tree = self.repo.find_tree(builder.write()?)?;
+
+        let (commit_oid, has_changes) = self.commit_tree(message, &tree, time)?;
+        log::debug!(
+            "Committed {commit_oid} with rootfs {}: {}",
+            rootfs.map_or("(empty)".to_string(), |rootfs| rootfs.to_string()),
+            message.lines().next().unwrap_or_default()
+        );
+        Ok(has_changes)
+    }
+
+    /// Commit `tree` on `HEAD`; also returns whether it differs from the parent's tree.
+    fn commit_tree(
+        &self,
+        message: &str,
+        tree: &git2::Tree,
+        time: Option<git2::Time>,
+    ) -> Result<(git2::Oid, bool)> {
+        let parent = self
+            .repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok());
+        let has_changes = parent
+            .as_ref()
+            .is_none_or(|parent| parent.tree_id() != tree.id());
+        let signature = self.signature_at(time)?;
+        let commit_oid = self.create_commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )?;
+        Ok((commit_oid, has_changes))
+    }
+
+    /// Commit on `HEAD` after staging only the `rootfs/` paths in `touched` and everything
+    /// outside `rootfs/` (the small metadata files), instead of comparing the whole worktree
+    /// with the index like [`GitRepo::commit_all_changes`]. The result is the same as long
+    /// as `touched` covers every change below `rootfs/`; see [`crate::staging`].
+    ///
+    /// Returns `Ok(true)` if the tree differs from the parent's.
+    ///
+    /// # Errors
+    /// - Reading the worktree, updating the index, or creating the commit fails.
+    pub fn commit_touched(&self, message: &str, touched: &TouchedPaths) -> Result<bool> {
+        self.commit_touched_at(message, touched, None)
+    }
+
+    /// [`GitRepo::commit_touched`], dated `time` instead of now.
+    fn commit_touched_at(
+        &self,
+        message: &str,
+        touched: &TouchedPaths,
+        time: Option<git2::Time>,
+    ) -> Result<bool> {
+        let workdir = self
+            .repo
+            .workdir()
+            .context("Repository has no worktree")?
+            .to_path_buf();
+        let mut index = self.repo.index().context("Failed to get git index")?;
+
+        // Removals first, then the files to add, hashed in one go
+        let mut added = Vec::new();
+        let rootfs = Path::new(&self.rootfs_dir);
+        for (path, recursive) in touched.paths() {
+            self.stage_path(
+                &mut index,
+                &workdir,
+                &rootfs.join(path),
+                recursive,
+                &mut added,
+            )?;
+        }
+        // Whatever is, or was, next to rootfs/
+        let mut outside = BTreeSet::new();
+        for entry in fs::read_dir(&workdir)
+            .with_context(|| format!("Failed to read {}", workdir.display()))?
+        {
+            outside.insert(PathBuf::from(entry?.file_name()));
+        }
+        if let Ok(tree) = self.repo.head().and_then(|head| head.peel_to_tree()) {
+            for entry in tree.iter() {
+                outside.insert(bytes_path(entry.name_bytes()));
+            }
+        }
+        for path in outside {
+            if path != Path::new(".git") && path != rootfs {
+                self.stage_path(&mut index, &workdir, &path, true, &mut added)?;
+            }
+        }
+        self.add_paths(&mut index, &workdir, &added)?;
+        self.stage_in_image_targets(&mut index, &added)?;
+
+        index.write().context("Failed to write git index")?;
+        let tree_id = index.write_tree().context("Failed to write git tree")?;
+        let tree = self
+            .repo
+            .find_tree(tree_id)
+            .context("Failed to find git tree")?;
+        let (commit_oid, has_changes) = self.commit_tree(message, &tree, time)?;
+        log::debug!(
+            "Committed {commit_oid} ({} touched paths): {}",
+            touched.len(),
+            message.lines().next().unwrap_or_default()
+        );
+        Ok(has_changes)
+    }
+
+    /// [`GitRepo::commit_touched`] for a layer commit, with the layer trailers and dated
+    /// like [`GitRepo::commit_layer`].
+    ///
+    /// # Errors
+    /// - Same as [`GitRepo::commit_touched`].
+    pub fn commit_layer_touched(
+        &self,
+        subject: &str,
+        layer: &LayerTrailers,
+        touched: &TouchedPaths,
+    ) -> Result<bool> {
+        self.commit_touched_at(
+            &trailers::layer_message(subject, layer),
+            touched,
+            layer_time(layer),
+        )
+    }
+
+    /// Make the index match the worktree at `path`: the file or symlink there, or its
+    /// absence, and with `recursive` (or if `path` is no directory) everything below it.
+    /// Like `git add -A`, untracked ignored files are left out and nested `.git`
+    /// directories are skipped. Removals happen right away, paths to add are pushed to
+    /// `added`.
+    fn stage_path(
+        &self,
+        index: &mut git2::Index,
+        workdir: &Path,
+        path: &Path,
+        recursive: bool,
+        added: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        if path
+            .components()
+            .any(|component| component.as_os_str() == ".git")
+        {
+            return Ok(());
+        }
+        let metadata = fs::symlink_metadata(workdir.join(path)).ok();
+        let is_dir = metadata.as_ref().is_some_and(fs::Metadata::is_dir);
+
+        if recursive || !is_dir {
+            // Index entries below the path that are gone from the worktree
+            let mut stale = BTreeSet::new();
+            let mut prefix = path.as_os_str().as_encoded_bytes().to_vec();
+            prefix.push(b'/');
+            if let Ok(start) = index.find_prefix(prefix.as_slice()) {
+                for position in start..index.len() {
+                    match index.get(position) {
+                        Some(entry) if entry.path.starts_with(&prefix) => {
+                            stale.insert(bytes_path(&entry.path));
+                        }
+                        _ => break,
+                    }
+                }
+            }
+            if is_dir {
+                self.stage_dir(workdir, path, &mut stale, added)?;
+            }
+            for stale in stale {
+                index
+                    .remove_path(&stale)
+                    .with_context(|| format!("Failed to unstage {}", stale.display()))?;
+            }
+        }
+
+        let tracked = index.get_path(path, 0).is_some();
+        if metadata.is_some() && !is_dir {
+            if tracked || !self.repo.is_path_ignored(path)? {
+                added.push(path.to_path_buf());
+            }
+        } else if tracked {
+            index
+                .remove_path(path)
+                .with_context(|| format!("Failed to unstage {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Push the files and symlinks below the worktree directory `dir` to `added`, taking
+    /// them out of `stale`.
+    fn stage_dir(
+        &self,
+        workdir: &Path,
+        dir: &Path,
+        stale: &mut BTreeSet<PathBuf>,
+        added: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let full = workdir.join(dir);
+        for entry in
+            fs::read_dir(&full).with_context(|| format!("Failed to read {}", full.display()))?
+        {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name == ".git" {
+                continue;
+            }
+            let path = dir.join(&name);
+            if entry.file_type()?.is_dir() {
+                self.stage_dir(workdir, &path, stale, added)?;
+            } else if stale.remove(&path) || !self.repo.is_path_ignored(&path)? {
+                added.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the worktree files and symlinks at `paths` to `index`; with
+    /// [`GitRepo::with_jobs`] they are hashed on that many threads first.
+    fn add_paths(&self, index: &mut git2::Index, workdir: &Path, paths: &[PathBuf]) -> Result<()> {
+        #[cfg(unix)]
+        if self.jobs > 1 && paths.len() > 1 {
+            use rayon::prelude::*;
+
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(self.jobs)
+                .build()
+                .context("Failed to start the hashing threads")?;
+            let git_dir = self.repo.path();
+            // A repository handle per thread; libgit2 handles can't be shared
+            let entries = pool.install(|| {
+                paths
+                    .par_iter()
+                    .map_init(
+                        || Repository::open(git_dir),
+                        |repo, path| {
+                            let repo = repo.as_ref().map_err(|error| {
+                                anyhow::anyhow!("Failed to open the repository: {error}")
+                            })?;
+                            index_entry(repo, workdir, path)
+                                .with_context(|| format!("Failed to stage {}", path.display()))
+                        },
+                    )
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            for entry in &entries {
+                index.add(entry).context("Failed to add to git index")?;
+            }
+            return Ok(());
+        }
+
+        for path in paths {
+            index
+                .add_path(path)
+                .with_context(|| format!("Failed to stage {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    fn workdir(&self) -> Result<&Path> {
+        self.repo.workdir().context("Repository has no worktree")
+    }
+
+    /// With [`SymlinkTargets::Image`], replace the staged targets of the symlinks at
+    /// `paths` below the rootfs directory by their in-image form.
+    fn stage_in_image_targets(&self, index: &mut git2::Index, paths: &[PathBuf]) -> Result<()> {
+        if self.symlink_targets != SymlinkTargets::Image {
+            return Ok(());
+        }
+        let rootfs = self.workdir()?.join(&self.rootfs_dir);
+        let Ok(root) = rootfs.canonicalize() else {
+            return Ok(());
+        };
+        let root = root.as_os_str().as_encoded_bytes();
+        let prefix = Path::new(&self.rootfs_dir);
+        for path in paths.iter().filter(|path| path.starts_with(prefix)) {
+            let Some(mut entry) = index.get_path(path, 0) else {
+                continue;
+            };
+            if entry.mode != u32::from(git2::FileMode::Link) {
+                continue;
+            }
+            let blob = self.repo.find_blob(entry.id)?;
+            if let Some(target) = symlinks::in_image(root, blob.content()) {
+                entry.id = self.repo.blob(&target)?;
+                // Not what's on disk: checking out HEAD has to replace the symlink
+                entry.file_size = 0;
+                entry.mtime = git2::IndexTime::new(0, 0);
+                index
+                    .add(&entry)
+                    .with_context(|| format!("Failed to stage {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// [`GitRepo::commit_with_rootfs_tree`] for a layer commit, with the layer trailers and
+    /// dated like [`GitRepo::commit_layer`].
+    ///
+    /// # Errors
+    /// - Same as [`GitRepo::commit_with_rootfs_tree`].
+    pub fn commit_layer_with_rootfs_tree(
+        &self,
+        subject: &str,
+        layer: &LayerTrailers,
+        rootfs: Option<git2::Oid>,
+    ) -> Result<bool> {
+        self.commit_with_rootfs_tree_at(
+            &trailers::layer_message(subject, layer),
+            rootfs,
+            layer_time(layer),
+        )
+    }
+
+    /// Tree of the worktree directory `dir` (at `relative` in the repository), without
+    /// `.git`, the top-level `rootfs/` and ignored files.
+    fn worktree_builder(&self, dir: &Path, relative: &Path) -> Result<git2::TreeBuilder<'_>> {
+        let mut builder = self.repo.treebuilder(None)?;
+        for entry in
+            fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let name = entry.file_name();
+            if relative.as_os_str().is_empty()
+                && (name == ".git" || name == self.rootfs_dir.as_str())
+            {
+                continue;
+            }
+            let path = relative.join(&name);
+            if self.repo.is_path_ignored(&path)? {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                let child = self.worktree_builder(&entry.path(), &path)?;
+                if !child.is_empty() {
+                    builder.insert(&name, child.write()?, git2::FileMode::Tree.into())?;
+                }
+            } else if file_type.is_symlink() {
+                let target = fs::read_link(entry.path())?;
+                let blob = self.repo.blob(target.as_os_str().as_encoded_bytes())?;
+                builder.insert(&name, blob, git2::FileMode::Link.into())?;
+            } else {
+                let blob = self
+                    .repo
+                    .blob_path(&entry.path())
+                    .with_context(|| format!("Failed to add {}", path.display()))?;
+                builder.insert(&name, blob, worktree_file_mode(&entry.metadata()?))?;
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Reset the index and worktree to `HEAD`, e.g. after commits made with
+    /// [`GitRepo::commit_with_rootfs_tree`], which leave both behind.
+    ///
+    /// # Errors
+    /// - `HEAD` can't be resolved, or the reset fails.
+    pub fn sync_worktree(&self) -> Result<()> {
+        let head = self
+            .repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .context("Failed to resolve HEAD")?;
         self.repo
-            .commit(
-                Some("HEAD"),
-                &signature,
-                &signature,
-                message,
-                &tree,
-                &parent_commits_refs,
+            .reset(
+                head.as_object(),
+                git2::ResetType::Hard,
+                Some(git2::build::CheckoutBuilder::new().force()),
             )
-            .context("Failed to create commit")?;
+            .context("Failed to check out HEAD")
+    }
 
-        Ok(has_changes)
+    /// Throw away uncommitted changes: reset the index and worktree to `HEAD` (or to an
+    /// empty tree on an unborn branch) and delete untracked files.
+    ///
+    /// # Errors
+    /// - Index, checkout or reset failures.
+    pub fn discard_changes(&self) -> Result<()> {
+        let mut checkout = git2::build::CheckoutBuilder::new();
+        checkout.force().remove_untracked(true);
+
+        match self.repo.head().and_then(|head| head.peel_to_commit()) {
+            Ok(head) => {
+                self.repo
+                    .reset(head.as_object(), git2::ResetType::Mixed, None)
+                    .context("Failed to reset index to HEAD")?;
+                self.repo
+                    .checkout_head(Some(&mut checkout))
+                    .context("Failed to reset worktree to HEAD")?;
+            }
+            Err(_) => {
+                let mut index = self.repo.index().context("Failed to get git index")?;
+                index.clear().context("Failed to clear git index")?;
+                index.write().context("Failed to write git index")?;
+                let empty_tree = self.repo.find_tree(self.repo.treebuilder(None)?.write()?)?;
+                self.repo
+                    .checkout_tree(empty_tree.as_object(), Some(&mut checkout))
+                    .context("Failed to clear worktree")?;
+            }
+        }
+        Ok(())
     }
 
     /// Return all commit OIDs for `branch_name`, ordered **oldest → newest**.
@@ -245,6 +1018,22 @@ impl GitRepo {
         Ok(branch_names)
     }
 
+    /// Delete the local branch `branch_name`, even if `HEAD` points at it. Returns `false`
+    /// if there is no such branch.
+    ///
+    /// # Errors
+    /// - The reference can't be deleted.
+    pub fn delete_branch(&self, branch_name: &str) -> Result<bool> {
+        let Ok(branch) = self.repo.find_branch(branch_name, git2::BranchType::Local) else {
+            return Ok(false);
+        };
+        branch
+            .into_reference()
+            .delete()
+            .with_context(|| format!("Failed to delete branch '{branch_name}'"))?;
+        Ok(true)
+    }
+
     /// Return `true` if a local branch named `branch_name` exists.
     ///
     /// This is a convenience wrapper around `repo.find_branch(...).is_ok()`.
@@ -322,8 +1111,8 @@ impl GitRepo {
         // Get the tree from the commit
         let tree = commit.tree().context("Failed to get tree from commit")?;
 
-        // Look for the file in the tree
-        let entry = tree.get_name(file_path);
+        // Look for the file in the tree (`file_path` may name a nested path)
+        let entry = tree.get_path(Path::new(file_path)).ok();
         match entry {
             Some(entry) => {
                 // Get the blob content
@@ -345,6 +1134,492 @@ impl GitRepo {
         }
     }
 
+    /// Create the annotated tag `tag_name` on commit `target`, signed when the identity
+    /// has a signer (the signature is appended to the message, like `git tag -s`).
+    ///
+    /// Returns `false` without changes if the tag already points at `target`.
+    ///
+    /// # Errors
+    /// - The tag exists and points elsewhere (tags are never moved).
+    /// - Invalid tag name, signing or object/reference creation failures.
+    pub fn create_tag(&self, tag_name: &str, target: git2::Oid, message: &str) -> Result<bool> {
+        let reference = format!("refs/tags/{tag_name}");
+        if let Ok(existing) = self.repo.find_reference(&reference) {
+            let existing = existing
+                .peel_to_commit()
+                .with_context(|| format!("Tag '{tag_name}' doesn't point at a commit"))?;
+            if existing.id() == target {
+                return Ok(false);
+            }
+            bail!(
+                "Tag '{tag_name}' already exists on commit {}, not moving it to {target}",
+                existing.id()
+            );
+        }
+
+        let commit = self
+            .repo
+            .find_object(target, Some(git2::ObjectType::Commit))?;
+        let tagger = self.signature()?;
+        let Some(signer) = &self.identity.signer else {
+            self.repo
+                .tag(tag_name, &commit, &tagger, message, false)
+                .with_context(|| format!("Failed to create tag '{tag_name}'"))?;
+            return Ok(true);
+        };
+
+        let when = tagger.when();
+        let offset = when.offset_minutes();
+        let mut content = format!(
+            "object {target}\ntype commit\ntag {tag_name}\ntagger {} <{}> {} {}{:02}{:02}\n\n{message}",
+            tagger.name().unwrap_or_default(),
+            tagger.email().unwrap_or_default(),
+            when.seconds(),
+            if offset < 0 { '-' } else { '+' },
+            offset.abs() / 60,
+            offset.abs() % 60,
+        );
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&signer.sign(&content)?);
+        let oid = self
+            .repo
+            .odb()?
+            .write(git2::ObjectType::Tag, content.as_bytes())
+            .context("Failed to write signed tag")?;
+        self.repo
+            .reference(&reference, oid, false, &format!("tag: {tag_name}"))
+            .with_context(|| format!("Failed to create tag '{tag_name}'"))?;
+        Ok(true)
+    }
+
+    /// Commit `content` as `file_path` (top-level) on `branch_name`, leaving `HEAD`, the
+    /// index and the worktree alone.
+    ///
+    /// The branch is created as an orphan if it doesn't exist. Returns `false` without
+    /// committing when the file already has exactly this content.
+    ///
+    /// # Errors
+    /// - Blob/tree/commit creation or reference update failures.
+    pub fn commit_file_to_branch(
+        &self,
+        branch_name: &str,
+        file_path: &str,
+        content: &str,
+        message: &str,
+    ) -> Result<bool> {
+        let parent = match self.repo.find_branch(branch_name, git2::BranchType::Local) {
+            Ok(branch) => Some(
+                branch
+                    .get()
+                    .peel_to_commit()
+                    .context("Failed to get branch commit")?,
+            ),
+            Err(_) => None,
+        };
+        let parent_tree = match &parent {
+            Some(commit) => Some(commit.tree().context("Failed to get tree from commit")?),
+            None => None,
+        };
+
+        let blob_id = self
+            .repo
+            .blob(content.as_bytes())
+            .context("Failed to write file blob")?;
+        if parent_tree
+            .as_ref()
+            .and_then(|tree| tree.get_name(file_path))
+            .is_some_and(|entry| entry.id() == blob_id)
+        {
+            return Ok(false);
+        }
+
+        let mut builder = self
+            .repo
+            .treebuilder(parent_tree.as_ref())
+            .context("Failed to create tree builder")?;
+        builder
+            .insert(file_path, blob_id, git2::FileMode::Blob.into())
+            .context("Failed to add file to tree")?;
+        let tree_id = builder.write().context("Failed to write git tree")?;
+        let tree = self
+            .repo
+            .find_tree(tree_id)
+            .context("Failed to find git tree")?;
+
+        let signature = self.signature()?;
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        self.create_commit(
+            Some(&format!("refs/heads/{branch_name}")),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?;
+
+        Ok(true)
+    }
+
+    /// Copy `commits` (oldest first) onto `branch_name`, each with its whole tree moved
+    /// to `dir` in the branch's tree and everything else of the branch kept. Authors,
+    /// committers and messages are kept too, apart from `subject_prefix` in front of the
+    /// message. Returns the copies, in the same order.
+    ///
+    /// The branch is created as an orphan if it doesn't exist; `HEAD`, the index and the
+    /// worktree are left alone.
+    ///
+    /// # Errors
+    /// - Unknown commits, an empty `dir`, or object/reference write failures.
+    pub fn replay_into_dir(
+        &self,
+        commits: &[git2::Oid],
+        branch_name: &str,
+        dir: &Path,
+        subject_prefix: &str,
+    ) -> Result<Vec<git2::Oid>> {
+        let components: Vec<&str> = dir
+            .iter()
+            .map(|component| component.to_str().context("Directory is not valid UTF-8"))
+            .collect::<Result<_>>()?;
+        if components.is_empty() {
+            bail!("Cannot replay commits into an empty directory");
+        }
+        let mut parent = match self.repo.find_branch(branch_name, git2::BranchType::Local) {
+            Ok(branch) => Some(
+                branch
+                    .get()
+                    .peel_to_commit()
+                    .context("Failed to get branch commit")?,
+            ),
+            Err(_) => None,
+        };
+
+        let mut copies = Vec::with_capacity(commits.len());
+        for &oid in commits {
+            let commit = self
+                .repo
+                .find_commit(oid)
+                .context("Failed to find commit")?;
+            let base = match &parent {
+                Some(parent) => Some(parent.tree().context("Failed to get tree from commit")?),
+                None => None,
+            };
+            let tree_id = self.replace_subtree(base.as_ref(), &components, commit.tree_id())?;
+            let tree = self
+                .repo
+                .find_tree(tree_id)
+                .context("Failed to find git tree")?;
+            let message = commit
+                .message()
+                .context("Commit message is not valid UTF-8")?;
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+            let copy = self.create_commit(
+                Some(&format!("refs/heads/{branch_name}")),
+                &commit.author(),
+                &commit.committer(),
+                &format!("{subject_prefix}{message}"),
+                &tree,
+                &parents,
+            )?;
+            copies.push(copy);
+            parent = Some(self.repo.find_commit(copy)?);
+        }
+        Ok(copies)
+    }
+
+    /// `base` (an empty tree for `None`) with the entry at `path` replaced by the tree
+    /// `subtree`.
+    fn replace_subtree(
+        &self,
+        base: Option<&git2::Tree>,
+        path: &[&str],
+        subtree: git2::Oid,
+    ) -> Result<git2::Oid> {
+        let Some((name, rest)) = path.split_first() else {
+            return Ok(subtree);
+        };
+        let child = match base.and_then(|tree| tree.get_name(name)) {
+            Some(entry) if entry.kind() == Some(git2::ObjectType::Tree) => {
+                Some(self.repo.find_tree(entry.id())?)
+            }
+            _ => None,
+        };
+        let id = self.replace_subtree(child.as_ref(), rest, subtree)?;
+        let mut builder = self
+            .repo
+            .treebuilder(base)
+            .context("Failed to create tree builder")?;
+        builder
+            .insert(name, id, git2::FileMode::Tree.into())
+            .context("Failed to add directory to tree")?;
+        builder.write().context("Failed to write git tree")
+    }
+
+    /// Switch `HEAD`, the index and the worktree to the existing branch `branch_name`,
+    /// dropping local changes and untracked files (like the empty directories the
+    /// previous branch leaves behind).
+    ///
+    /// # Errors
+    /// - The branch doesn't exist, or the checkout fails.
+    pub fn checkout_branch(&self, branch_name: &str) -> Result<()> {
+        let tip = self
+            .repo
+            .find_branch(branch_name, git2::BranchType::Local)
+            .with_context(|| format!("Failed to find branch '{branch_name}'"))?
+            .get()
+            .peel_to_commit()
+            .context("Failed to get branch commit")?;
+        // Checking out before moving HEAD removes what only the current branch tracks
+        self.repo
+            .checkout_tree(
+                tip.as_object(),
+                Some(
+                    git2::build::CheckoutBuilder::new()
+                        .force()
+                        .remove_untracked(true),
+                ),
+            )
+            .with_context(|| format!("Failed to check out branch '{branch_name}'"))?;
+        self.repo
+            .set_head(&format!("refs/heads/{branch_name}"))
+            .context("Failed to set HEAD")
+    }
+
+    /// Set the description of `branch_name` (`branch.<name>.description` in the repo config).
+    ///
+    /// # Errors
+    /// - Config access or write failures.
+    pub fn set_branch_description(&self, branch_name: &str, description: &str) -> Result<()> {
+        let mut config = self.repo.config().context("Failed to get git config")?;
+        config
+            .set_str(&format!("branch.{branch_name}.description"), description)
+            .context("Failed to set branch description")
+    }
+
+    /// Full message of commit `commit_oid`.
+    ///
+    /// # Errors
+    /// - Unknown commit or non-UTF-8 message.
+    pub fn commit_message(&self, commit_oid: git2::Oid) -> Result<String> {
+        let commit = self
+            .repo
+            .find_commit(commit_oid)
+            .context("Failed to find commit")?;
+        commit
+            .message()
+            .map(str::to_string)
+            .context("Commit message is not valid UTF-8")
+    }
+
+    /// Make `commit_oid` look like a root commit to Git, the way `git replace --graft <commit>`
+    /// does: a parentless copy of the commit (same tree, author, committer and message) is
+    /// written and `refs/replace/<commit_oid>` points to it. Returns the copy's OID.
+    ///
+    /// The original commit and its ancestors stay untouched; `git replace -d <commit_oid>`
+    /// undoes the graft. libgit2 ignores replace refs, so this repository's own history
+    /// lookups keep seeing the original parents.
+    ///
+    /// # Errors
+    /// - Unknown commit, an existing replacement for it, or object/reference write failures.
+    pub fn graft_as_root(&self, commit_oid: git2::Oid) -> Result<git2::Oid> {
+        let replace_ref = format!("refs/replace/{commit_oid}");
+        if self.repo.find_reference(&replace_ref).is_ok() {
+            bail!("Commit {commit_oid} is already replaced ({replace_ref})");
+        }
+
+        let commit = self
+            .repo
+            .find_commit(commit_oid)
+            .context("Failed to find commit")?;
+        let tree = commit.tree().context("Failed to get tree from commit")?;
+        let message = commit
+            .message()
+            .context("Commit message is not valid UTF-8")?;
+        let graft = self
+            .create_commit(
+                None,
+                &commit.author(),
+                &commit.committer(),
+                message,
+                &tree,
+                &[],
+            )
+            .context("Failed to create replacement commit")?;
+
+        self.repo
+            .reference(&replace_ref, graft, false, "oci2git: graft as root")
+            .context("Failed to create replace reference")?;
+        Ok(graft)
+    }
+
+    /// Attach `note` to `commit_oid` in `notes_ref` (e.g. `refs/notes/oci2git`), replacing
+    /// any existing note.
+    ///
+    /// # Errors
+    /// - Signature creation or note write failures.
+    pub fn set_note(&self, notes_ref: &str, commit_oid: git2::Oid, note: &str) -> Result<()> {
+        let signature = self.signature()?;
+        self.repo
+            .note(
+                &signature,
+                &signature,
+                Some(notes_ref),
+                commit_oid,
+                note,
+                true,
+            )
+            .context("Failed to write git note")?;
+        Ok(())
+    }
+
+    /// Read the note attached to `commit_oid` in `notes_ref`, if any.
+    pub fn read_note(&self, notes_ref: &str, commit_oid: git2::Oid) -> Option<String> {
+        self.repo
+            .find_note(Some(notes_ref), commit_oid)
+            .ok()
+            .and_then(|note| note.message().map(str::to_string))
+    }
+
+    /// Pack every object reachable from a reference, `HEAD` (also of worktrees) or the
+    /// index into a single delta-compressed pack, then delete all loose objects and the
+    /// previous packs — the equivalent of `git gc --prune=now`. Objects only reachable from
+    /// reflogs are dropped too, with their reflog entries, so a deleted branch can't be
+    /// recovered afterwards.
+    ///
+    /// Nothing else may write to the repository meanwhile.
+    ///
+    /// # Errors
+    /// - Reference, revwalk or index failures.
+    /// - Writing the pack or deleting the old objects fails.
+    pub fn repack(&self) -> Result<RepackStats> {
+        let objects_dir = self.repo.path().join("objects");
+        let pack_dir = objects_dir.join("pack");
+        let bytes_before = dir_size(&objects_dir);
+
+        let mut packbuilder = self.repo.packbuilder()?;
+        // One delta search thread per CPU
+        packbuilder.set_threads(0);
+        let mut revwalk = self.repo.revwalk()?;
+        // Every reference that leads to a commit: branches, tags, notes, replacements
+        revwalk
+            .push_glob("*")
+            .context("Failed to walk the references")?;
+        if let Some(head) = self.repo.head().ok().and_then(|head| head.target()) {
+            revwalk.push(head)?;
+        }
+        // Worktrees (`oci2git checkout`) with a detached HEAD
+        for name in self.repo.worktrees()?.iter().flatten() {
+            let head = self
+                .repo
+                .find_worktree(name)
+                .and_then(|worktree| Repository::open_from_worktree(&worktree))
+                .ok()
+                .and_then(|worktree| worktree.head().ok().and_then(|head| head.target()));
+            if let Some(head) = head {
+                revwalk.push(head)?;
+            }
+        }
+        packbuilder
+            .insert_walk(&mut revwalk)
+            .context("Failed to collect reachable objects")?;
+        // Annotated tags are objects of their own
+        for reference in self.repo.references()? {
+            let Some(target) = reference?.target() else {
+                continue;
+            };
+            if self.repo.find_tag(target).is_ok() {
+                packbuilder.insert_recursive(target, None)?;
+            }
+        }
+        // Staged content that isn't committed yet
+        let odb = self.repo.odb()?;
+        for entry in self.repo.index()?.iter() {
+            if odb.exists(entry.id) {
+                packbuilder.insert_object(entry.id, None)?;
+            }
+        }
+
+        let objects = packbuilder.object_count();
+        fs::create_dir_all(&pack_dir)?;
+        packbuilder
+            .write(&pack_dir, 0)
+            .context("Failed to write pack")?;
+        let pack_name = packbuilder
+            .name()
+            .map(|name| format!("pack-{name}"))
+            .context("Pack was written without a name")?;
+
+        // The new pack holds everything reachable; the rest is garbage, except packs
+        // marked with a `.keep` file
+        for entry in fs::read_dir(&pack_dir)? {
+            let path = entry?.path();
+            let stem = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("");
+            if stem != pack_name && !pack_dir.join(format!("{stem}.keep")).exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        for entry in fs::read_dir(&objects_dir)? {
+            let path = entry?.path();
+            let is_fanout = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit()));
+            if is_fanout {
+                fs::remove_dir_all(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        // Would still list the deleted commits
+        let _ = fs::remove_file(objects_dir.join("info").join("commit-graph"));
+        let _ = fs::remove_dir_all(objects_dir.join("info").join("commit-graphs"));
+        // Reflog entries of dropped objects would make `git fsck` complain; a fresh handle,
+        // as this one still has the dropped objects cached
+        let fresh = Repository::open(self.repo.path())?;
+        let odb = fresh.odb()?;
+        let mut logged = vec!["HEAD".to_string()];
+        for reference in self.repo.references()? {
+            if let Some(name) = reference?.name() {
+                logged.push(name.to_string());
+            }
+        }
+        for name in logged {
+            let Ok(mut reflog) = self.repo.reflog(&name) else {
+                continue;
+            };
+            let dropped: Vec<usize> = reflog
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| {
+                    [entry.id_old(), entry.id_new()]
+                        .iter()
+                        .any(|id| !id.is_zero() && !odb.exists(*id))
+                })
+                .map(|(i, _)| i)
+                .collect();
+            if dropped.is_empty() {
+                continue;
+            }
+            for i in dropped.into_iter().rev() {
+                reflog.remove(i, false)?;
+            }
+            reflog
+                .write()
+                .with_context(|| format!("Failed to write the reflog of {name}"))?;
+        }
+
+        Ok(RepackStats {
+            objects,
+            bytes_before,
+            bytes_after: dir_size(&objects_dir),
+        })
+    }
+
     /// Find the **next** commits (successors) after `commit_oid` across all local branches.
     ///
     /// - If `Some(oid)`, returns the commit *immediately after* `oid` on any branch
@@ -470,6 +1745,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_layer_commits_dated_at_layer_creation() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("main")).unwrap();
+        let mut layer = LayerTrailers {
+            index: 1,
+            digest: "sha256:1234".to_string(),
+            created: Some("2023-06-12T18:23:45.5-07:00".to_string()),
+            empty: Some(true),
+        };
+        repo.commit_layer("RUN true", &layer).unwrap();
+        let commit = repo.repo.head().unwrap().peel_to_commit().unwrap();
+        for time in [commit.author().when(), commit.committer().when()] {
+            assert_eq!(time.seconds(), 1_686_619_425);
+            assert_eq!(time.offset_minutes(), -7 * 60);
+        }
+
+        // Without a usable creation time, and for other commits: now
+        layer.created = Some("yesterday".to_string());
+        repo.commit_layer("RUN true", &layer).unwrap();
+        repo.commit_all_changes("metadata").unwrap();
+        let recent = chrono::Utc::now().timestamp() - 60;
+        for _ in 0..2 {
+            let commit = repo.repo.head().unwrap().peel_to_commit().unwrap();
+            assert!(commit.committer().when().seconds() > recent);
+            repo.repo
+                .set_head_detached(commit.parent_id(0).unwrap())
+                .unwrap();
+        }
+    }
+
     #[test]
     fn test_commit_all_changes() {
         let temp_dir = tempdir().unwrap();
@@ -550,6 +1856,17 @@ mod tests {
         assert_eq!(repo.get_commit_count().unwrap(), 1);
     }
 
+    #[test]
+    fn test_open_does_not_create_repo() {
+        let temp_dir = tempdir().unwrap();
+        assert!(GitRepo::open(temp_dir.path()).is_err());
+        assert!(!temp_dir.path().join(".git").exists());
+
+        GitRepo::init_with_branch(temp_dir.path(), Some("main")).unwrap();
+        let repo = GitRepo::open(temp_dir.path()).unwrap();
+        assert!(!repo.exists_and_has_commits());
+    }
+
     #[test]
     fn test_branch_exists() {
         let temp_dir = tempdir().unwrap();
@@ -569,4 +1886,140 @@ mod tests {
         // Non-existent branch should return false
         assert!(!repo.branch_exists("non-existent-branch"));
     }
+
+    #[test]
+    fn test_commit_file_to_branch() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("image")).unwrap();
+        fs::write(temp_dir.path().join("rootfs.txt"), "rootfs").unwrap();
+        repo.commit_all_changes("Image commit").unwrap();
+
+        assert!(repo
+            .commit_file_to_branch("main", "README.md", "v1", "Add README")
+            .unwrap());
+        // Same content is a no-op
+        assert!(!repo
+            .commit_file_to_branch("main", "README.md", "v1", "Add README")
+            .unwrap());
+        assert!(repo
+            .commit_file_to_branch("main", "README.md", "v2", "Update README")
+            .unwrap());
+
+        let commits = repo.get_branch_commits("main").unwrap();
+        assert_eq!(commits.len(), 2);
+        assert_eq!(
+            repo.read_file_from_commit(commits[1], "README.md").unwrap(),
+            "v2"
+        );
+        assert!(repo
+            .read_file_from_commit(commits[1], "rootfs.txt")
+            .is_err());
+
+        // HEAD and the worktree stay on the image branch
+        assert_eq!(repo.get_last_commit_message().unwrap(), "Image commit");
+        assert!(!temp_dir.path().join("README.md").exists());
+
+        repo.set_branch_description("image", "an image").unwrap();
+        let config = repo.repo.config().unwrap();
+        assert_eq!(
+            config.get_string("branch.image.description").unwrap(),
+            "an image"
+        );
+    }
+
+    #[test]
+    fn test_discard_changes() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("image")).unwrap();
+
+        // Unborn branch: everything goes
+        fs::create_dir_all(temp_dir.path().join("rootfs/etc")).unwrap();
+        fs::write(temp_dir.path().join("rootfs/etc/passwd"), "root").unwrap();
+        repo.discard_changes().unwrap();
+        assert!(!temp_dir.path().join("rootfs/etc/passwd").exists());
+
+        fs::write(temp_dir.path().join("Image.md"), "v1").unwrap();
+        repo.commit_all_changes("Layer").unwrap();
+        fs::write(temp_dir.path().join("Image.md"), "v2").unwrap();
+        fs::write(temp_dir.path().join("half-extracted"), "x").unwrap();
+        repo.discard_changes().unwrap();
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("Image.md")).unwrap(),
+            "v1"
+        );
+        assert!(!temp_dir.path().join("half-extracted").exists());
+    }
+
+    #[test]
+    fn test_identity_and_ssh_signing() {
+        let temp_dir = tempdir().unwrap();
+        let key = temp_dir.path().join("key");
+        let keygen = std::process::Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+            .arg(&key)
+            .status();
+        if !keygen.is_ok_and(|status| status.success()) {
+            println!("Skipping test: ssh-keygen is not available");
+            return;
+        }
+
+        let repo_dir = temp_dir.path().join("repo");
+        let identity = CommitIdentity {
+            name: "Build Bot".to_string(),
+            email: "bot@corp.example".to_string(),
+            signer: Some(CommitSigner::new(
+                crate::signing::SigningFormat::Ssh,
+                Some(key.to_string_lossy().into_owned()),
+            )),
+        };
+        let repo = GitRepo::init_with_branch(&repo_dir, Some("image"))
+            .unwrap()
+            .with_identity(identity)
+            .unwrap();
+        fs::write(repo_dir.join("Image.md"), "v1").unwrap();
+        repo.commit_all_changes("First").unwrap();
+        fs::write(repo_dir.join("Image.md"), "v2").unwrap();
+        repo.commit_all_changes("Second").unwrap();
+
+        // Signed commits still advance the branch HEAD points to
+        let commits = repo.get_branch_commits("image").unwrap();
+        assert_eq!(commits.len(), 2);
+        for &oid in &commits {
+            let commit = repo.repo.find_commit(oid).unwrap();
+            assert_eq!(commit.author().name(), Some("Build Bot"));
+            assert_eq!(commit.committer().email(), Some("bot@corp.example"));
+            let (signature, _) = repo.repo.extract_signature(&oid, None).unwrap();
+            assert!(signature
+                .as_str()
+                .unwrap()
+                .starts_with("-----BEGIN SSH SIGNATURE-----"));
+        }
+        let config = repo.repo.config().unwrap();
+        assert_eq!(config.get_string("user.name").unwrap(), "Build Bot");
+
+        // Signed annotated tags, never moved once created
+        let (first, tip) = (commits[0], commits[1]);
+        assert!(repo.create_tag("image/v2", tip, "Image v2").unwrap());
+        assert!(!repo.create_tag("image/v2", tip, "Image v2").unwrap());
+        assert!(repo.create_tag("image/v2", first, "Image v1").is_err());
+        let tag = repo
+            .repo
+            .find_reference("refs/tags/image/v2")
+            .unwrap()
+            .peel_to_tag()
+            .unwrap();
+        assert_eq!(tag.target_id(), tip);
+        assert_eq!(tag.tagger().unwrap().name(), Some("Build Bot"));
+        let message = tag.message().unwrap();
+        assert!(message.starts_with("Image v2\n-----BEGIN SSH SIGNATURE-----"));
+
+        let invalid = CommitIdentity {
+            name: "<bad>".to_string(),
+            ..CommitIdentity::default()
+        };
+        assert!(GitRepo::open(&repo_dir)
+            .unwrap()
+            .with_identity(invalid)
+            .is_err());
+    }
 }
@@ -0,0 +1,772 @@
+//! `oci2git export`: turn a converted branch back into an image tarball.
+//!
+//! [`export_branch`] walks the commits of a branch and turns them back into layers:
+//! - every layer commit (see [`crate::trailers`]) becomes a layer again, with the history
+//!   entry recorded in `Image.md`; layers that were empty stay empty history entries,
+//! - any other commit that changes `rootfs/` (e.g. a config file edited in Git on top of
+//!   the conversion) becomes an extra layer, so editing and exporting yields a patched image.
+//!
+//! Each layer is the diff of `rootfs/` against the previous commit: added and modified
+//! paths become tar entries, deleted ones become whiteouts. The result is an uncompressed
+//! OCI image layout (`oci-layout`, `index.json`, `blobs/sha256/…`) that also carries a
+//! `docker save` style `manifest.json`, so it can be loaded with `docker load` or
+//! converted again with the tar engine.
+//!
+//! Git only keeps contents, the executable bit and symlink targets. When present, the
+//! sidecars of a commit restore the rest:
+//! - `.oci2git/fsmeta.jsonl` ([`crate::fsmeta`]): owner, group, mode, mtime, xattrs and
+//!   empty directories,
+//! - `.oci2git/special-files.jsonl` ([`crate::special_files`]): device nodes and FIFOs,
+//!   with [`ExportOptions::restore_special_files`].
+//!
+//! Without them, files are owned by root and carry the commit time. Hardlinks are
+//! exported as copies. The image config is rebuilt from `Image.md` at the branch tip;
+//! `Image.md` joins `Cmd` and `Entrypoint` with spaces, so they are split on whitespace
+//! again and quoting inside arguments is lost.
+//!
+//! Branches with files replaced by stubs ([`crate::large_files`]) are refused, since
+//! their content isn't in the repository.
+
+use crate::fsmeta::{self, FileType, FsEntry, FsMetadata, FSMETA_PATH};
+use crate::git::GitRepo;
+use crate::image_metadata::{BasicInfo, ImageMetadata, LayerDigest};
+use crate::large_files::LARGE_FILES_PATH;
+use crate::query::rootfs_tree;
+use crate::special_files::{SpecialFiles, SPECIAL_FILES_PATH};
+use crate::trailers;
+use crate::volumes::VOLUME_MARKER;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::DateTime;
+use git2::{Commit, Delta, FileMode, ObjectType, Oid, Repository, Tree};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tar_rs as tar;
+
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Options for [`export_branch`].
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Tag of the exported image (`RepoTags` and the OCI ref name); defaults to the first
+    /// tag in `Image.md`.
+    pub tag: Option<String>,
+    /// Recreate the device nodes and FIFOs listed in `.oci2git/special-files.jsonl`.
+    pub restore_special_files: bool,
+}
+
+/// A layer written by [`export_branch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedLayer {
+    /// Commit the layer was built from
+    pub commit: Oid,
+    /// Digest of the (uncompressed) layer tarball, also its diff ID
+    pub digest: String,
+    pub size: u64,
+}
+
+/// Result of [`export_branch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedImage {
+    pub manifest_digest: String,
+    pub config_digest: String,
+    pub tag: Option<String>,
+    pub layers: Vec<ExportedLayer>,
+}
+
+/// Export `branch` of `repo` as an image tarball at `output`.
+///
+/// # Errors
+/// - The branch doesn't exist, or `Image.md` at its tip is missing or lacks the platform.
+/// - Invalid sidecar files, or symlink targets that aren't UTF-8.
+/// - Git or I/O failures while writing the tarball.
+///
+/// ### Examples
+/// ```no_run
+/// use oci2git::git2oci::{export_branch, ExportOptions};
+/// use oci2git::GitRepo;
+/// use std::path::Path;
+///
+/// let repo = GitRepo::open(Path::new("./alpine"))?;
+/// let image = export_branch(&repo, "alpine#3.20", Path::new("alpine-patched.tar"), &ExportOptions::default())?;
+/// println!("{} layers, manifest {}", image.layers.len(), image.manifest_digest);
+/// # anyhow::Ok(())
+/// ```
+pub fn export_branch(
+    repo: &GitRepo,
+    branch: &str,
+    output: &Path,
+    options: &ExportOptions,
+) -> Result<ExportedImage> {
+    let commits = repo.get_branch_commits(branch)?;
+    let Some(&tip) = commits.last() else {
+        bail!("Branch '{branch}' has no commits");
+    };
+    let metadata = ImageMetadata::read_from_commit(repo, tip)?
+        .with_context(|| format!("No Image.md at the tip of branch '{branch}'"))?;
+    let basic_info = metadata
+        .basic_info
+        .as_ref()
+        .filter(|info| !info.os.is_empty() && !info.architecture.is_empty())
+        .ok_or_else(|| anyhow!("Image.md on branch '{branch}' doesn't name the platform"))?;
+
+    let staging = tempfile::Builder::new()
+        .prefix("oci2git-export")
+        .tempdir()
+        .context("Failed to create staging directory")?;
+
+    let mut layers = Vec::new();
+    let mut history = Vec::new();
+    let mut previous = Snapshot::empty();
+    for oid in commits {
+        let commit = repo.repo.find_commit(oid)?;
+        if commit.tree()?.get_path(Path::new(LARGE_FILES_PATH)).is_ok() {
+            bail!(
+                "Commit {oid} of branch '{branch}' has files replaced by stubs ({LARGE_FILES_PATH}); branches converted with --max-file-size can't be exported"
+            );
+        }
+        let snapshot = Snapshot::load(&repo.repo, &commit)?;
+        let changes = Changes::between(&repo.repo, &previous, &snapshot, options)?;
+
+        let recorded = trailers::parse(commit.message().unwrap_or_default())?
+            .and_then(|layer| layer.index.checked_sub(1))
+            .and_then(|index| metadata.layer_digests.get(index));
+        let keep_layer = match recorded {
+            // Layers of the original image stay layers, even if Git can't see their changes
+            Some(layer) => !layer.is_empty || !changes.is_empty(),
+            None => !changes.is_empty(),
+        };
+        if recorded.is_some() || keep_layer {
+            history.push(history_entry(&commit, recorded, !keep_layer));
+        }
+        if keep_layer {
+            let path = staging.path().join(format!("layer{}.tar", layers.len()));
+            let (digest, size) = changes
+                .write_layer(&repo.repo, &snapshot, &path)
+                .with_context(|| format!("Failed to export commit {oid} as a layer"))?;
+            layers.push((
+                ExportedLayer {
+                    commit: oid,
+                    digest,
+                    size,
+                },
+                path,
+            ));
+        }
+        previous = snapshot;
+    }
+
+    let config = image_config(&metadata, basic_info, &layers, history);
+    let config = serde_json::to_vec(&config)?;
+    let config_digest = sha256(&config);
+
+    let layer_descriptors: Vec<Value> = layers
+        .iter()
+        .map(|(layer, _)| {
+            json!({"mediaType": LAYER_MEDIA_TYPE, "digest": layer.digest, "size": layer.size})
+        })
+        .collect();
+    let manifest = serde_json::to_vec(&json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "config": {"mediaType": CONFIG_MEDIA_TYPE, "digest": config_digest, "size": config.len()},
+        "layers": layer_descriptors,
+    }))?;
+    let manifest_digest = sha256(&manifest);
+
+    let tag = options
+        .tag
+        .clone()
+        .or_else(|| basic_info.tags.first().cloned())
+        .filter(|tag| !tag.is_empty());
+    let mut descriptor = json!({
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "digest": manifest_digest,
+        "size": manifest.len(),
+        "platform": {"os": basic_info.os, "architecture": basic_info.architecture},
+    });
+    if let Some(tag) = &tag {
+        descriptor["annotations"] = json!({"org.opencontainers.image.ref.name": tag});
+    }
+    let index = json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [descriptor],
+    });
+    let docker_manifest = json!([{
+        "Config": blob_path(&config_digest),
+        "RepoTags": tag.iter().collect::<Vec<_>>(),
+        "Layers": layers.iter().map(|(layer, _)| blob_path(&layer.digest)).collect::<Vec<_>>(),
+    }]);
+
+    let file =
+        File::create(output).with_context(|| format!("Failed to create {}", output.display()))?;
+    let mut archive = tar::Builder::new(file);
+    append_bytes(
+        &mut archive,
+        "oci-layout",
+        br#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+    append_bytes(&mut archive, "index.json", &serde_json::to_vec(&index)?)?;
+    append_bytes(
+        &mut archive,
+        "manifest.json",
+        &serde_json::to_vec(&docker_manifest)?,
+    )?;
+    append_bytes(&mut archive, &blob_path(&config_digest), &config)?;
+    append_bytes(&mut archive, &blob_path(&manifest_digest), &manifest)?;
+    let mut written = BTreeSet::new();
+    for (layer, path) in &layers {
+        // Identical layers (e.g. two empty ones) share a blob
+        if !written.insert(layer.digest.clone()) {
+            continue;
+        }
+        let mut header = blob_header(layer.size);
+        archive
+            .append_data(&mut header, blob_path(&layer.digest), File::open(path)?)
+            .with_context(|| format!("Failed to write layer {}", layer.digest))?;
+    }
+    archive
+        .into_inner()?
+        .flush()
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+
+    Ok(ExportedImage {
+        manifest_digest,
+        config_digest,
+        tag,
+        layers: layers.into_iter().map(|(layer, _)| layer).collect(),
+    })
+}
+
+/// `rootfs/` and its sidecars at one commit.
+struct Snapshot<'repo> {
+    rootfs: Option<Tree<'repo>>,
+    fsmeta: Option<FsMetadata>,
+    special_files: SpecialFiles,
+    /// Commit time, the default mtime of its entries
+    time: u64,
+}
+
+impl<'repo> Snapshot<'repo> {
+    fn empty() -> Self {
+        Self {
+            rootfs: None,
+            fsmeta: None,
+            special_files: SpecialFiles::new(),
+            time: 0,
+        }
+    }
+
+    fn load(repo: &'repo Repository, commit: &Commit<'repo>) -> Result<Self> {
+        let tree = commit.tree()?;
+        let rootfs = rootfs_tree(repo, &tree)?;
+        let text = |path: &str| -> Result<Option<String>> {
+            let Ok(entry) = tree.get_path(Path::new(path)) else {
+                return Ok(None);
+            };
+            let blob = repo.find_blob(entry.id())?;
+            Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+        };
+        Ok(Self {
+            rootfs,
+            fsmeta: text(FSMETA_PATH)?
+                .map(|content| FsMetadata::parse(&content))
+                .transpose()?,
+            special_files: text(SPECIAL_FILES_PATH)?
+                .map(|content| SpecialFiles::parse(&content))
+                .transpose()?
+                .unwrap_or_default(),
+            time: u64::try_from(commit.time().seconds()).unwrap_or(0),
+        })
+    }
+
+    /// The `rootfs/` entry at `path`, if any
+    fn entry(&self, path: &Path) -> Option<git2::TreeEntry<'static>> {
+        self.rootfs.as_ref()?.get_path(path).ok()
+    }
+
+    fn fs_entry(&self, path: &str) -> Option<&FsEntry> {
+        self.fsmeta.as_ref()?.entries.get(&format!("/{path}"))
+    }
+
+    /// Whether `path` is a directory, in Git or (if empty) in the metadata sidecar
+    fn is_dir(&self, path: &Path) -> bool {
+        match self.entry(path) {
+            Some(entry) => entry.kind() == Some(ObjectType::Tree),
+            None => self
+                .fs_entry(&path.to_string_lossy())
+                .is_some_and(|entry| entry.file_type == FileType::Dir),
+        }
+    }
+}
+
+/// What a layer writes at a path
+enum Change {
+    Blob { oid: Oid, mode: FileMode },
+    Dir,
+}
+
+/// The filesystem changes between two snapshots, as layer entries.
+#[derive(Default)]
+struct Changes {
+    /// Whiteout entries, e.g. `etc/.wh.motd`
+    whiteouts: BTreeSet<PathBuf>,
+    entries: BTreeMap<PathBuf, Change>,
+    special_files: SpecialFiles,
+}
+
+impl Changes {
+    fn between(
+        repo: &Repository,
+        old: &Snapshot,
+        new: &Snapshot,
+        options: &ExportOptions,
+    ) -> Result<Self> {
+        let mut changes = Self::default();
+        let mut deleted = Vec::new();
+
+        let diff = repo.diff_tree_to_tree(old.rootfs.as_ref(), new.rootfs.as_ref(), None)?;
+        for delta in diff.deltas() {
+            let file = match delta.status() {
+                Delta::Deleted => {
+                    if let Some(path) = delta.old_file().path() {
+                        deleted.push(path.to_path_buf());
+                    }
+                    continue;
+                }
+                _ => delta.new_file(),
+            };
+            let Some(path) = file.path() else { continue };
+            if file.mode() == FileMode::Commit || is_volume_marker(path) {
+                continue;
+            }
+            changes.entries.insert(
+                path.to_path_buf(),
+                Change::Blob {
+                    oid: file.id(),
+                    mode: file.mode(),
+                },
+            );
+        }
+
+        // Metadata-only changes (chmod, touch, new empty directories) aren't Git changes
+        if let Some(fsmeta) = &new.fsmeta {
+            for (image_path, entry) in &fsmeta.entries {
+                let path = Path::new(image_path.trim_start_matches('/'));
+                if path.as_os_str().is_empty()
+                    || changes.entries.contains_key(path)
+                    || old
+                        .fsmeta
+                        .as_ref()
+                        .is_some_and(|old| old.entries.get(image_path) == Some(entry))
+                {
+                    continue;
+                }
+                let change = match (entry.file_type, new.entry(path)) {
+                    (FileType::Dir, _) => Change::Dir,
+                    (FileType::File | FileType::Hardlink | FileType::Symlink, Some(tree_entry))
+                        if tree_entry.kind() == Some(ObjectType::Blob) =>
+                    {
+                        Change::Blob {
+                            oid: tree_entry.id(),
+                            mode: file_mode(tree_entry.filemode()),
+                        }
+                    }
+                    _ => continue,
+                };
+                changes.entries.insert(path.to_path_buf(), change);
+            }
+        }
+
+        if options.restore_special_files {
+            for (image_path, file) in &new.special_files.entries {
+                if old.special_files.entries.get(image_path) != Some(file) {
+                    changes
+                        .special_files
+                        .entries
+                        .insert(image_path.clone(), file.clone());
+                }
+            }
+            for image_path in old.special_files.entries.keys() {
+                if !new.special_files.entries.contains_key(image_path) {
+                    deleted.push(PathBuf::from(image_path.trim_start_matches('/')));
+                }
+            }
+        }
+
+        for path in deleted {
+            if is_volume_marker(&path) {
+                continue;
+            }
+            if let Some(whiteout) = whiteout_for(new, &path, &changes.special_files) {
+                changes.whiteouts.insert(whiteout);
+            }
+        }
+        Ok(changes)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.whiteouts.is_empty()
+            && self.entries.is_empty()
+            && self.special_files.entries.is_empty()
+    }
+
+    /// Writes the changes as a layer tarball to `path`; returns its digest and size.
+    fn write_layer(&self, repo: &Repository, new: &Snapshot, path: &Path) -> Result<(String, u64)> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut builder = tar::Builder::new(DigestWriter::new(file));
+
+        for whiteout in &self.whiteouts {
+            let mut header = default_header(tar::EntryType::Regular, 0o644, new.time);
+            builder.append_data(&mut header, whiteout, io::empty())?;
+        }
+
+        for (path, change) in &self.entries {
+            let fs_entry = new.fs_entry(&path.to_string_lossy());
+            match change {
+                Change::Dir => {
+                    let mut header = default_header(tar::EntryType::Directory, 0o755, new.time);
+                    apply_fs_entry(&mut builder, &mut header, fs_entry, FileType::Dir)?;
+                    builder.append_data(&mut header, path, io::empty())?;
+                }
+                Change::Blob {
+                    oid,
+                    mode: FileMode::Link,
+                } => {
+                    let blob = repo.find_blob(*oid)?;
+                    let target = std::str::from_utf8(blob.content()).with_context(|| {
+                        format!("Symlink target of {} isn't UTF-8", path.display())
+                    })?;
+                    let mut header = default_header(tar::EntryType::Symlink, 0o777, new.time);
+                    apply_fs_entry(&mut builder, &mut header, fs_entry, FileType::Symlink)?;
+                    builder.append_link(&mut header, path, target)?;
+                }
+                Change::Blob { oid, mode } => {
+                    let executable = *mode == FileMode::BlobExecutable;
+                    let blob = repo.find_blob(*oid)?;
+                    let mut header = default_header(
+                        tar::EntryType::Regular,
+                        if executable { 0o755 } else { 0o644 },
+                        new.time,
+                    );
+                    if apply_fs_entry(&mut builder, &mut header, fs_entry, FileType::File)? {
+                        // Git is authoritative for the executable bit (it may have been edited)
+                        let mode = header.mode()?;
+                        match (executable, mode & 0o100 != 0) {
+                            (true, false) => header.set_mode(mode | 0o111),
+                            (false, true) => header.set_mode(mode & !0o111),
+                            _ => {}
+                        }
+                    }
+                    header.set_size(blob.content().len() as u64);
+                    builder.append_data(&mut header, path, blob.content())?;
+                }
+            }
+        }
+
+        self.special_files.append_to(&mut builder)?;
+
+        let mut writer = builder.into_inner()?;
+        writer.flush()?;
+        Ok(writer.finish())
+    }
+}
+
+/// The Git mode of a tree entry's raw `filemode`
+fn file_mode(filemode: i32) -> FileMode {
+    match filemode {
+        0o120000 => FileMode::Link,
+        0o100755 => FileMode::BlobExecutable,
+        _ => FileMode::Blob,
+    }
+}
+
+/// Whether `path` is a marker keeping an (empty) volume directory in Git
+fn is_volume_marker(path: &Path) -> bool {
+    path.file_name() == Some(VOLUME_MARKER.as_ref())
+}
+
+/// The whiteout removing deleted `path`: placed at its topmost ancestor that is gone too,
+/// `None` if that ancestor is replaced by another entry type in the same layer.
+fn whiteout_for(new: &Snapshot, path: &Path, special_files: &SpecialFiles) -> Option<PathBuf> {
+    let mut prefix = PathBuf::new();
+    for component in path.components() {
+        prefix.push(component);
+        if new.is_dir(&prefix) {
+            continue;
+        }
+        let replaced = new.entry(&prefix).is_some()
+            || special_files
+                .entries
+                .contains_key(&fsmeta::image_path(&prefix));
+        if replaced {
+            return None;
+        }
+        let name = prefix.file_name()?.to_string_lossy();
+        return Some(prefix.with_file_name(format!(".wh.{name}")));
+    }
+    None
+}
+
+/// Header of an entry owned by root
+fn default_header(entry_type: tar::EntryType, mode: u32, mtime: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(mode);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(mtime);
+    header.set_size(0);
+    header
+}
+
+/// Applies the recorded metadata to `header` (xattrs as PAX records preceding the entry)
+/// if it describes the same kind of entry; returns whether it did.
+fn apply_fs_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    header: &mut tar::Header,
+    fs_entry: Option<&FsEntry>,
+    file_type: FileType,
+) -> Result<bool> {
+    let Some(entry) = fs_entry else {
+        return Ok(false);
+    };
+    let same_type = match file_type {
+        FileType::File => matches!(entry.file_type, FileType::File | FileType::Hardlink),
+        _ => entry.file_type == file_type,
+    };
+    if !same_type {
+        return Ok(false);
+    }
+
+    header.set_mode(
+        u32::from_str_radix(&entry.mode, 8)
+            .with_context(|| format!("Invalid mode of {}: {}", entry.path, entry.mode))?,
+    );
+    header.set_uid(entry.uid);
+    header.set_gid(entry.gid);
+    header.set_mtime(entry.mtime);
+    // Names longer than the ustar fields are dropped, the numeric IDs remain
+    if let Some(user) = &entry.user {
+        let _ = header.set_username(user);
+    }
+    if let Some(group) = &entry.group {
+        let _ = header.set_groupname(group);
+    }
+
+    if !entry.xattrs.is_empty() {
+        let mut records = Vec::new();
+        for (name, value) in &entry.xattrs {
+            records.push((format!("SCHILY.xattr.{name}"), fsmeta::xattr_bytes(value)?));
+        }
+        builder.append_pax_extensions(
+            records
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_slice())),
+        )?;
+    }
+    Ok(true)
+}
+
+/// History entry for `commit`: as recorded in `Image.md` for layer commits, derived from
+/// the commit otherwise.
+fn history_entry(commit: &Commit, recorded: Option<&LayerDigest>, empty_layer: bool) -> Value {
+    let mut entry = match recorded {
+        Some(layer) => {
+            let mut entry = json!({"created": layer.created, "created_by": layer.command});
+            if let Some(comment) = &layer.comment {
+                entry["comment"] = json!(comment);
+            }
+            entry
+        }
+        None => json!({
+            "created": DateTime::from_timestamp(commit.time().seconds(), 0)
+                .unwrap_or_default()
+                .to_rfc3339(),
+            "created_by": format!("oci2git export: {}", commit.summary().unwrap_or_default()),
+            "comment": format!("commit {}", commit.id()),
+        }),
+    };
+    if empty_layer {
+        entry["empty_layer"] = json!(true);
+    }
+    entry
+}
+
+/// The image config: platform and container config from `Image.md`, the exported layers
+fn image_config(
+    metadata: &ImageMetadata,
+    basic_info: &BasicInfo,
+    layers: &[(ExportedLayer, PathBuf)],
+    history: Vec<Value>,
+) -> Value {
+    let mut config = Map::new();
+    if let Some(container_config) = &metadata.container_config {
+        let split = |value: &Option<String>| {
+            value
+                .as_ref()
+                .map(|value| json!(value.split_whitespace().collect::<Vec<_>>()))
+        };
+        let set = |values: &[String]| {
+            json!(values
+                .iter()
+                .map(|value| (value.clone(), json!({})))
+                .collect::<Map<_, _>>())
+        };
+        if !container_config.environment_variables.is_empty() {
+            config.insert("Env".into(), json!(container_config.environment_variables));
+        }
+        if let Some(cmd) = split(&container_config.command) {
+            config.insert("Cmd".into(), cmd);
+        }
+        if let Some(entrypoint) = split(&container_config.entrypoint) {
+            config.insert("Entrypoint".into(), entrypoint);
+        }
+        if !container_config.working_directory.is_empty() {
+            config.insert(
+                "WorkingDir".into(),
+                json!(container_config.working_directory),
+            );
+        }
+        if !container_config.exposed_ports.is_empty() {
+            config.insert("ExposedPorts".into(), set(&container_config.exposed_ports));
+        }
+        if !container_config.volumes.is_empty() {
+            config.insert("Volumes".into(), set(&container_config.volumes));
+        }
+        if !container_config.labels.is_empty() {
+            let labels: BTreeMap<_, _> = container_config.labels.iter().collect();
+            config.insert("Labels".into(), json!(labels));
+        }
+    }
+
+    let mut image = json!({
+        "architecture": basic_info.architecture,
+        "os": basic_info.os,
+        "config": config,
+        "rootfs": {
+            "type": "layers",
+            "diff_ids": layers.iter().map(|(layer, _)| &layer.digest).collect::<Vec<_>>(),
+        },
+        "history": history,
+    });
+    if !basic_info.created.is_empty() {
+        image["created"] = json!(basic_info.created);
+    }
+    image
+}
+
+/// `blobs/sha256/<hex>` of `digest`
+fn blob_path(digest: &str) -> String {
+    format!("blobs/sha256/{}", digest.trim_start_matches("sha256:"))
+}
+
+fn blob_header(size: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o644);
+    header.set_size(size);
+    header
+}
+
+fn append_bytes<W: Write>(archive: &mut tar::Builder<W>, path: &str, data: &[u8]) -> Result<()> {
+    archive
+        .append_data(&mut blob_header(data.len() as u64), path, data)
+        .with_context(|| format!("Failed to write {path}"))
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("sha256:{}", hex(&Sha256::digest(data)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Hashes and counts everything written through it.
+struct DigestWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> DigestWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    /// `sha256:<hex>` digest and size of the written data
+    fn finish(self) -> (String, u64) {
+        (
+            format!("sha256:{}", hex(&self.hasher.finalize())),
+            self.size,
+        )
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whiteout_at_topmost_deleted_ancestor() {
+        let new = Snapshot::empty();
+        let none = SpecialFiles::new();
+        assert_eq!(
+            whiteout_for(&new, Path::new("opt/app/bin/tool"), &none),
+            Some(PathBuf::from(".wh.opt"))
+        );
+
+        let mut fsmeta = FsMetadata::new();
+        for (path, file_type) in [("/opt", FileType::Dir), ("/opt/app", FileType::Dir)] {
+            fsmeta.entries.insert(
+                path.to_string(),
+                FsEntry {
+                    path: path.to_string(),
+                    file_type,
+                    mode: "0755".to_string(),
+                    uid: 0,
+                    gid: 0,
+                    user: None,
+                    group: None,
+                    mtime: 0,
+                    link: None,
+                    device: None,
+                    xattrs: BTreeMap::new(),
+                },
+            );
+        }
+        let new = Snapshot {
+            fsmeta: Some(fsmeta),
+            ..Snapshot::empty()
+        };
+        // Empty directories recorded in the sidecar survive
+        assert_eq!(
+            whiteout_for(&new, Path::new("opt/app/bin/tool"), &none),
+            Some(PathBuf::from("opt/app/.wh.bin"))
+        );
+    }
+}
@@ -0,0 +1,348 @@
+//! A self-contained HTML page summarizing a converted image: `report/index.html`.
+//!
+//! With [`crate::processor::ImageProcessor::with_html_report`] the metadata commit carries
+//! a single page, without scripts or external resources, that can be shared with people
+//! who won't clone the repository (attached to a ticket, served from CI artifacts, ...):
+//!
+//! - the image name, tags, digest, platform and entrypoint,
+//! - a bar chart of the bytes every layer commit added, with its command and commit
+//!   (a link if [`crate::processor::ImageProcessor::with_report_commit_url`] is set, e.g.
+//!   `https://github.com/acme/images/commit/{commit}`),
+//! - the [`BIGGEST_FILES`] biggest files of the final filesystem,
+//! - the environment variables and labels.
+//!
+//! Layer sizes come from [`LayerStatsReport`], so layers reused from other branches are
+//! covered and a `--squash` branch has no layer chart.
+
+use crate::disk_space::format_bytes;
+use crate::git::GitRepo;
+use crate::image_metadata::ImageMetadata;
+use crate::layer_stats::{FileSize, LayerStatsReport};
+use crate::query::rootfs_tree;
+use crate::trailers;
+use anyhow::{Context, Result};
+use git2::{ObjectType, TreeWalkMode, TreeWalkResult};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Directory of the report, relative to the repository root.
+pub const REPORT_DIR: &str = "report";
+/// The report, relative to the repository root.
+pub const REPORT_PATH: &str = "report/index.html";
+/// How many of the biggest files of the image are listed.
+pub const BIGGEST_FILES: usize = 20;
+
+/// A layer commit in the report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportLayer {
+    /// 1-based layer index
+    pub index: usize,
+    pub commit: String,
+    pub command: String,
+    pub bytes_added: u64,
+}
+
+/// What `report/index.html` shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlReport {
+    pub metadata: ImageMetadata,
+    pub layers: Vec<ReportLayer>,
+    /// Largest first
+    pub biggest_files: Vec<FileSize>,
+}
+
+impl HtmlReport {
+    /// Collects the layer commits of `branch` and the biggest files at its tip; the rest
+    /// comes from `metadata`.
+    ///
+    /// # Errors
+    /// - `branch` doesn't exist, or Git failures.
+    pub fn collect(repo: &GitRepo, branch: &str, metadata: &ImageMetadata) -> Result<Self> {
+        let commits = repo.get_branch_commits(branch)?;
+        let mut layer_commits = BTreeMap::new();
+        for &commit in &commits {
+            if let Some(layer) = trailers::parse(&repo.commit_message(commit)?)? {
+                layer_commits.insert(layer.index, commit.to_string());
+            }
+        }
+        let layers = LayerStatsReport::collect(repo, branch, metadata)?
+            .layers
+            .into_iter()
+            .map(|stats| ReportLayer {
+                index: stats.index,
+                commit: layer_commits.remove(&stats.index).unwrap_or_default(),
+                command: stats.command,
+                bytes_added: stats.bytes_added,
+            })
+            .collect();
+
+        let mut biggest_files = Vec::new();
+        if let Some(&tip) = commits.last() {
+            let tree = repo.repo.find_commit(tip)?.tree()?;
+            if let Some(rootfs) = rootfs_tree(&repo.repo, &tree)? {
+                let odb = repo.repo.odb()?;
+                let mut error = None;
+                rootfs.walk(TreeWalkMode::PreOrder, |root, entry| {
+                    // Regular files only; symlink blobs hold their target
+                    if entry.kind() != Some(ObjectType::Blob) || entry.filemode() == 0o120000 {
+                        return TreeWalkResult::Ok;
+                    }
+                    match odb.read_header(entry.id()) {
+                        Ok((size, _)) => biggest_files.push(FileSize {
+                            path: format!("/{root}{}", String::from_utf8_lossy(entry.name_bytes())),
+                            size: size as u64,
+                        }),
+                        Err(e) => {
+                            error = Some(e);
+                            return TreeWalkResult::Abort;
+                        }
+                    }
+                    TreeWalkResult::Ok
+                })?;
+                if let Some(error) = error {
+                    return Err(error.into());
+                }
+            }
+        }
+        biggest_files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        biggest_files.truncate(BIGGEST_FILES);
+
+        Ok(Self {
+            metadata: metadata.clone(),
+            layers,
+            biggest_files,
+        })
+    }
+
+    /// The page; commits link to `commit_url` with `{commit}` replaced by the commit id,
+    /// if given.
+    pub fn render(&self, commit_url: Option<&str>) -> String {
+        let basic_info = self.metadata.basic_info.as_ref();
+        let title = basic_info.map_or("Image", |info| info.name.as_str());
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            escape(title),
+            escape(title)
+        );
+
+        html.push_str("<table class=\"info\">\n");
+        if let Some(info) = basic_info {
+            row(&mut html, &["Tags", &info.tags.join(", ")]);
+            row(&mut html, &["Image ID", &info.id]);
+            row(&mut html, &["Created", &info.created]);
+            row(
+                &mut html,
+                &["Platform", &format!("{}/{}", info.os, info.architecture)],
+            );
+        }
+        if let Some(config) = &self.metadata.container_config {
+            for (name, value) in [
+                ("Entrypoint", &config.entrypoint),
+                ("Command", &config.command),
+            ] {
+                if let Some(value) = value {
+                    row(&mut html, &[name, value]);
+                }
+            }
+            if !config.working_directory.is_empty() {
+                row(&mut html, &["Working directory", &config.working_directory]);
+            }
+            if !config.exposed_ports.is_empty() {
+                row(
+                    &mut html,
+                    &["Exposed ports", &config.exposed_ports.join(", ")],
+                );
+            }
+        }
+        html.push_str("</table>\n");
+
+        if !self.layers.is_empty() {
+            let total: u64 = self.layers.iter().map(|layer| layer.bytes_added).sum();
+            let largest = self.layers.iter().map(|layer| layer.bytes_added).max();
+            let _ = write!(
+                html,
+                "<h2>Layers</h2>\n<p>{} layers, {} added in total.</p>\n<table class=\"layers\">\n\
+                 <tr><th>Layer</th><th>Size</th><th></th><th>Command</th><th>Commit</th></tr>\n",
+                self.layers.len(),
+                format_bytes(total)
+            );
+            for layer in &self.layers {
+                let width = match largest {
+                    Some(largest) if largest > 0 => layer.bytes_added * 100 / largest,
+                    _ => 0,
+                };
+                let short = &layer.commit[..layer.commit.len().min(12)];
+                let commit = match commit_url {
+                    Some(url) if !layer.commit.is_empty() => format!(
+                        "<a href=\"{}\"><code>{short}</code></a>",
+                        escape(&url.replace("{commit}", &layer.commit))
+                    ),
+                    _ => format!("<code title=\"{}\">{short}</code>", layer.commit),
+                };
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td class=\"size\">{}</td>\
+                     <td class=\"bar\"><div style=\"width: {width}%\"></div></td>\
+                     <td><code>{}</code></td><td>{commit}</td></tr>",
+                    layer.index,
+                    format_bytes(layer.bytes_added),
+                    escape(&layer.command)
+                );
+            }
+            html.push_str("</table>\n");
+        }
+
+        if !self.biggest_files.is_empty() {
+            html.push_str("<h2>Biggest files</h2>\n<table>\n<tr><th>Size</th><th>Path</th></tr>\n");
+            for file in &self.biggest_files {
+                let _ = writeln!(
+                    html,
+                    "<tr><td class=\"size\">{}</td><td><code>{}</code></td></tr>",
+                    format_bytes(file.size),
+                    escape(&file.path)
+                );
+            }
+            html.push_str("</table>\n");
+        }
+
+        if let Some(config) = &self.metadata.container_config {
+            if !config.environment_variables.is_empty() {
+                html.push_str(
+                    "<h2>Environment</h2>\n<table>\n<tr><th>Name</th><th>Value</th></tr>\n",
+                );
+                for variable in &config.environment_variables {
+                    let (name, value) = variable.split_once('=').unwrap_or((variable, ""));
+                    row(&mut html, &[name, value]);
+                }
+                html.push_str("</table>\n");
+            }
+            if !config.labels.is_empty() {
+                html.push_str("<h2>Labels</h2>\n<table>\n<tr><th>Name</th><th>Value</th></tr>\n");
+                for (name, value) in config.labels.iter().collect::<BTreeMap<_, _>>() {
+                    row(&mut html, &[name, value]);
+                }
+                html.push_str("</table>\n");
+            }
+        }
+
+        html.push_str(
+            "<footer>Generated by <a href=\"https://github.com/Virviil/oci2git\">oci2git</a></footer>\n\
+             </body>\n</html>\n",
+        );
+        html
+    }
+
+    /// Writes [`REPORT_PATH`] below `dir`.
+    pub fn save(&self, dir: &Path, commit_url: Option<&str>) -> Result<()> {
+        let path = dir.join(REPORT_PATH);
+        fs::create_dir_all(dir.join(REPORT_DIR))?;
+        fs::write(&path, self.render(commit_url))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:64em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1.5em}\
+th,td{text-align:left;padding:.25em .75em;border-bottom:1px solid #ddd;vertical-align:top}\
+code{word-break:break-all}\
+.info th,.info td:first-child{font-weight:bold}\
+.size{text-align:right;white-space:nowrap}\
+.bar{width:30%}.bar div{background:#4a90d9;height:1em;min-width:1px}\
+footer{color:#888;font-size:.8em}";
+
+/// A table row of `cells`, escaped.
+fn row(html: &mut String, cells: &[&str]) {
+    html.push_str("<tr>");
+    for cell in cells {
+        let _ = write!(html, "<td>{}</td>", escape(cell));
+    }
+    html.push_str("</tr>\n");
+}
+
+/// `text` with the HTML special characters escaped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_metadata::{BasicInfo, ContainerConfig};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_render() {
+        let metadata = ImageMetadata::new(
+            Some(BasicInfo {
+                name: "app<1>".to_string(),
+                id: "sha256:abc".to_string(),
+                tags: vec!["app:latest".to_string()],
+                created: "2024-03-01T12:00:00Z".to_string(),
+                architecture: "amd64".to_string(),
+                os: "linux".to_string(),
+            }),
+            Some(ContainerConfig {
+                environment_variables: vec!["PATH=/usr/bin".to_string()],
+                command: Some("run".to_string()),
+                entrypoint: None,
+                working_directory: String::new(),
+                exposed_ports: Vec::new(),
+                volumes: Vec::new(),
+                labels: HashMap::from([("maintainer".to_string(), "a & b".to_string())]),
+            }),
+        );
+        let report = HtmlReport {
+            metadata,
+            layers: vec![
+                ReportLayer {
+                    index: 1,
+                    commit: "0123456789abcdef".to_string(),
+                    command: "ADD rootfs.tar /".to_string(),
+                    bytes_added: 2048,
+                },
+                ReportLayer {
+                    index: 2,
+                    commit: "fedcba9876543210".to_string(),
+                    command: "RUN echo \"<hi>\"".to_string(),
+                    bytes_added: 512,
+                },
+            ],
+            biggest_files: vec![FileSize {
+                path: "/usr/bin/big".to_string(),
+                size: 1024,
+            }],
+        };
+
+        let html = report.render(Some("https://example.com/commit/{commit}"));
+        assert!(html.contains("<h1>app&lt;1&gt;</h1>"));
+        assert!(html.contains("<div style=\"width: 100%\">"));
+        assert!(html.contains("<div style=\"width: 25%\">"));
+        assert!(html.contains("<code>RUN echo &quot;&lt;hi&gt;&quot;</code>"));
+        assert!(html.contains(
+            "<a href=\"https://example.com/commit/0123456789abcdef\"><code>0123456789ab</code></a>"
+        ));
+        assert!(html.contains("<td>PATH</td><td>/usr/bin</td>"));
+        assert!(html.contains("<td>maintainer</td><td>a &amp; b</td>"));
+        assert!(html.contains("<code>/usr/bin/big</code>"));
+
+        let html = report.render(None);
+        assert!(html.contains("<code title=\"0123456789abcdef\">0123456789ab</code>"));
+        assert!(!html.contains("<a href=\"https://example.com"));
+    }
+}
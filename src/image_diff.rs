@@ -0,0 +1,545 @@
+//! `oci2git diff`: compare two converted images.
+//!
+//! Both images are converted into branches of the same repository, so layers they share
+//! are the very same commits. [`diff_branches`] builds an [`ImageDiff`] from there:
+//! - the number of shared leading layers, and the layers after them on either side with
+//!   their change statistics (see [`crate::query`]),
+//! - the files added, removed and changed between the two `rootfs/` trees, with sizes,
+//! - differences in the image metadata: basic fields, command and entrypoint, working
+//!   directory, ports, volumes, environment variables and labels.
+//!
+//! [`ImageDiff::render_markdown`] turns it into the `DIFF.md` report; the struct itself
+//! serializes to JSON.
+
+use crate::git::GitRepo;
+use crate::image_metadata::ImageMetadata;
+use crate::query::{self, LayerRecord};
+use crate::repo_readme::escape_cell;
+use anyhow::{Context, Result};
+use git2::{Delta, ObjectType, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Rows of the file table in the Markdown report; the JSON report lists every file.
+const MAX_FILE_ROWS: usize = 200;
+
+/// Differences between two converted images.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageDiff {
+    pub branch_a: String,
+    pub branch_b: String,
+    /// Leading layers both branches share (same commits)
+    pub shared_layers: usize,
+    /// Layers of A after the shared ones
+    pub layers_a: Vec<LayerRecord>,
+    /// Layers of B after the shared ones
+    pub layers_b: Vec<LayerRecord>,
+    /// Total size of the files in `rootfs/` of A
+    pub size_a: u64,
+    /// Total size of the files in `rootfs/` of B
+    pub size_b: u64,
+    pub files: FileChanges,
+    /// Basic fields and configuration that differ
+    pub metadata: Vec<FieldChange>,
+    pub env: MapChanges,
+    pub labels: MapChanges,
+}
+
+/// Files of `rootfs/` that differ between A and B.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FileChanges {
+    pub added: Vec<FileChange>,
+    pub removed: Vec<FileChange>,
+    pub changed: Vec<FileChange>,
+}
+
+/// A file that differs, with its size on either side (`None` where it's missing).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    /// Absolute path inside the image
+    pub path: String,
+    pub size_a: Option<u64>,
+    pub size_b: Option<u64>,
+}
+
+/// A metadata field whose value differs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub a: String,
+    pub b: String,
+}
+
+/// Differences between two key/value maps (environment, labels).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MapChanges {
+    /// Only in B
+    pub added: BTreeMap<String, String>,
+    /// Only in A
+    pub removed: BTreeMap<String, String>,
+    /// In both, with the value of A and of B
+    pub changed: BTreeMap<String, (String, String)>,
+}
+
+impl MapChanges {
+    fn between(a: &BTreeMap<String, String>, b: &BTreeMap<String, String>) -> Self {
+        let mut changes = Self::default();
+        for (key, value) in a {
+            match b.get(key) {
+                None => {
+                    changes.removed.insert(key.clone(), value.clone());
+                }
+                Some(other) if other != value => {
+                    changes
+                        .changed
+                        .insert(key.clone(), (value.clone(), other.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        for (key, value) in b {
+            if !a.contains_key(key) {
+                changes.added.insert(key.clone(), value.clone());
+            }
+        }
+        changes
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares the tips of `branch_a` and `branch_b`.
+///
+/// # Errors
+/// - Either branch doesn't exist, unparsable metadata, or Git failures.
+pub fn diff_branches(repo: &GitRepo, branch_a: &str, branch_b: &str) -> Result<ImageDiff> {
+    let image_a = single_image(repo, branch_a)?;
+    let image_b = single_image(repo, branch_b)?;
+
+    let shared_layers = image_a
+        .layers
+        .iter()
+        .zip(&image_b.layers)
+        .take_while(|(a, b)| a.commit == b.commit)
+        .count();
+
+    let tree_a = rootfs_at_tip(repo, branch_a)?;
+    let tree_b = rootfs_at_tip(repo, branch_b)?;
+    let files = file_changes(&repo.repo, tree_a.as_ref(), tree_b.as_ref())?;
+
+    let metadata_a = image_a
+        .metadata
+        .unwrap_or_else(|| ImageMetadata::new(None, None));
+    let metadata_b = image_b
+        .metadata
+        .unwrap_or_else(|| ImageMetadata::new(None, None));
+    let (env_a, labels_a) = env_and_labels(&metadata_a);
+    let (env_b, labels_b) = env_and_labels(&metadata_b);
+
+    Ok(ImageDiff {
+        branch_a: branch_a.to_string(),
+        branch_b: branch_b.to_string(),
+        shared_layers,
+        layers_a: image_a.layers[shared_layers..].to_vec(),
+        layers_b: image_b.layers[shared_layers..].to_vec(),
+        size_a: tree_size(&repo.repo, tree_a.as_ref())?,
+        size_b: tree_size(&repo.repo, tree_b.as_ref())?,
+        files,
+        metadata: field_changes(&metadata_a, &metadata_b),
+        env: MapChanges::between(&env_a, &env_b),
+        labels: MapChanges::between(&labels_a, &labels_b),
+    })
+}
+
+fn single_image(repo: &GitRepo, branch: &str) -> Result<query::ImageRecord> {
+    query::collect(repo, Some(branch))?
+        .images
+        .pop()
+        .with_context(|| format!("Branch '{branch}' has no image"))
+}
+
+fn rootfs_at_tip<'repo>(repo: &'repo GitRepo, branch: &str) -> Result<Option<Tree<'repo>>> {
+    match repo.get_branch_commits(branch)?.last() {
+        Some(&tip) => query::rootfs_tree(&repo.repo, &repo.repo.find_commit(tip)?.tree()?),
+        None => Ok(None),
+    }
+}
+
+fn file_changes(repo: &Repository, a: Option<&Tree>, b: Option<&Tree>) -> Result<FileChanges> {
+    let odb = repo.odb()?;
+    let size = |oid: git2::Oid| -> Result<Option<u64>> {
+        if oid.is_zero() {
+            return Ok(None);
+        }
+        Ok(Some(odb.read_header(oid)?.0 as u64))
+    };
+
+    let mut changes = FileChanges::default();
+    let diff = repo.diff_tree_to_tree(a, b, None)?;
+    for delta in diff.deltas() {
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|path| format!("/{}", path.to_string_lossy()))
+            .unwrap_or_default();
+        let change = FileChange {
+            path,
+            size_a: size(delta.old_file().id())?,
+            size_b: size(delta.new_file().id())?,
+        };
+        match delta.status() {
+            Delta::Added => changes.added.push(change),
+            Delta::Deleted => changes.removed.push(change),
+            _ => changes.changed.push(change),
+        }
+    }
+    Ok(changes)
+}
+
+/// Sum of the blob sizes below `tree`
+fn tree_size(repo: &Repository, tree: Option<&Tree>) -> Result<u64> {
+    let Some(tree) = tree else {
+        return Ok(0);
+    };
+    let odb = repo.odb()?;
+    let mut total = 0;
+    let mut error = None;
+    tree.walk(TreeWalkMode::PreOrder, |_, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            match odb.read_header(entry.id()) {
+                Ok((size, _)) => total += size as u64,
+                Err(e) => {
+                    error = Some(e);
+                    return TreeWalkResult::Abort;
+                }
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+    match error {
+        Some(error) => Err(error.into()),
+        None => Ok(total),
+    }
+}
+
+type Map = BTreeMap<String, String>;
+
+fn env_and_labels(metadata: &ImageMetadata) -> (Map, Map) {
+    let Some(config) = &metadata.container_config else {
+        return (Map::new(), Map::new());
+    };
+    let env = config
+        .environment_variables
+        .iter()
+        .map(|entry| {
+            let (key, value) = entry.split_once('=').unwrap_or((entry, ""));
+            (key.to_string(), value.to_string())
+        })
+        .collect();
+    let labels = config
+        .labels
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    (env, labels)
+}
+
+fn field_changes(a: &ImageMetadata, b: &ImageMetadata) -> Vec<FieldChange> {
+    let fields = |metadata: &ImageMetadata| -> Vec<(&'static str, String)> {
+        let info = metadata.basic_info.as_ref();
+        let config = metadata.container_config.as_ref();
+        vec![
+            ("name", info.map(|i| i.name.clone()).unwrap_or_default()),
+            ("id", info.map(|i| i.id.clone()).unwrap_or_default()),
+            ("tags", info.map(|i| i.tags.join(", ")).unwrap_or_default()),
+            (
+                "created",
+                info.map(|i| i.created.clone()).unwrap_or_default(),
+            ),
+            (
+                "platform",
+                info.map(|i| format!("{}/{}", i.os, i.architecture))
+                    .unwrap_or_default(),
+            ),
+            (
+                "command",
+                config.and_then(|c| c.command.clone()).unwrap_or_default(),
+            ),
+            (
+                "entrypoint",
+                config
+                    .and_then(|c| c.entrypoint.clone())
+                    .unwrap_or_default(),
+            ),
+            (
+                "working_directory",
+                config
+                    .map(|c| c.working_directory.clone())
+                    .unwrap_or_default(),
+            ),
+            (
+                "exposed_ports",
+                config
+                    .map(|c| c.exposed_ports.join(", "))
+                    .unwrap_or_default(),
+            ),
+            (
+                "volumes",
+                config.map(|c| c.volumes.join(", ")).unwrap_or_default(),
+            ),
+        ]
+    };
+    fields(a)
+        .into_iter()
+        .zip(fields(b))
+        .filter(|((_, a), (_, b))| a != b)
+        .map(|((field, a), (_, b))| FieldChange {
+            field: field.to_string(),
+            a,
+            b,
+        })
+        .collect()
+}
+
+/// `+1234` / `-1234`
+fn signed(delta: i128) -> String {
+    if delta >= 0 {
+        format!("+{delta}")
+    } else {
+        delta.to_string()
+    }
+}
+
+fn optional_size(size: Option<u64>) -> String {
+    size.map_or_else(|| "-".to_string(), |size| size.to_string())
+}
+
+impl ImageDiff {
+    /// The `DIFF.md` report.
+    pub fn render_markdown(&self) -> String {
+        let mut markdown = String::from("# Image diff\n\n");
+        markdown.push_str("| | A | B |\n|---|---|---|\n");
+        markdown.push_str(&format!(
+            "| Branch | `{}` | `{}` |\n",
+            escape_cell(&self.branch_a),
+            escape_cell(&self.branch_b)
+        ));
+        markdown.push_str(&format!(
+            "| Layers | {} | {} |\n",
+            self.shared_layers + self.layers_a.len(),
+            self.shared_layers + self.layers_b.len()
+        ));
+        markdown.push_str(&format!(
+            "| rootfs size | {} | {} ({}) |\n\n",
+            self.size_a,
+            self.size_b,
+            signed(self.size_b as i128 - self.size_a as i128)
+        ));
+        markdown.push_str(&format!(
+            "The first {} layers are shared.\n\n",
+            self.shared_layers
+        ));
+
+        markdown.push_str("## Layers\n\n");
+        for (side, layers) in [("A", &self.layers_a), ("B", &self.layers_b)] {
+            markdown.push_str(&format!("### Only in {side}\n\n"));
+            if layers.is_empty() {
+                markdown.push_str("None.\n\n");
+                continue;
+            }
+            markdown.push_str(
+                "| # | Command | Added | Modified | Deleted | Bytes added |\n\
+                 |---|---|---|---|---|---|\n",
+            );
+            for layer in layers {
+                markdown.push_str(&format!(
+                    "| {} | `{}` | {} | {} | {} | {} |\n",
+                    layer.index,
+                    escape_cell(&layer.command),
+                    layer.files_added,
+                    layer.files_modified,
+                    layer.files_deleted,
+                    layer.bytes_added
+                ));
+            }
+            markdown.push('\n');
+        }
+
+        markdown.push_str("## Files\n\n");
+        let files = &self.files;
+        markdown.push_str(&format!(
+            "{} added, {} removed, {} changed.\n\n",
+            files.added.len(),
+            files.removed.len(),
+            files.changed.len()
+        ));
+        let rows: Vec<_> = [
+            ("added", &files.added),
+            ("removed", &files.removed),
+            ("changed", &files.changed),
+        ]
+        .into_iter()
+        .flat_map(|(kind, changes)| changes.iter().map(move |change| (kind, change)))
+        .collect();
+        if !rows.is_empty() {
+            markdown.push_str("| Change | Path | Size A | Size B |\n|---|---|---|---|\n");
+            for (kind, change) in rows.iter().take(MAX_FILE_ROWS) {
+                markdown.push_str(&format!(
+                    "| {kind} | `{}` | {} | {} |\n",
+                    escape_cell(&change.path),
+                    optional_size(change.size_a),
+                    optional_size(change.size_b)
+                ));
+            }
+            if rows.len() > MAX_FILE_ROWS {
+                markdown.push_str(&format!(
+                    "\n… and {} more (see the JSON report).\n",
+                    rows.len() - MAX_FILE_ROWS
+                ));
+            }
+            markdown.push('\n');
+        }
+
+        markdown.push_str("## Metadata\n\n");
+        if self.metadata.is_empty() {
+            markdown.push_str("No differences.\n\n");
+        } else {
+            markdown.push_str("| Field | A | B |\n|---|---|---|\n");
+            for change in &self.metadata {
+                markdown.push_str(&format!(
+                    "| {} | {} | {} |\n",
+                    change.field,
+                    escape_cell(&change.a),
+                    escape_cell(&change.b)
+                ));
+            }
+            markdown.push('\n');
+        }
+
+        for (title, changes) in [("Environment", &self.env), ("Labels", &self.labels)] {
+            markdown.push_str(&format!("## {title}\n\n"));
+            if changes.is_empty() {
+                markdown.push_str("No differences.\n\n");
+                continue;
+            }
+            markdown.push_str("| Key | A | B |\n|---|---|---|\n");
+            let rows = changes
+                .removed
+                .iter()
+                .map(|(key, a)| (key, a.as_str(), "-"))
+                .chain(changes.added.iter().map(|(key, b)| (key, "-", b.as_str())))
+                .chain(
+                    changes
+                        .changed
+                        .iter()
+                        .map(|(key, (a, b))| (key, a.as_str(), b.as_str())),
+                );
+            for (key, a, b) in rows {
+                markdown.push_str(&format!(
+                    "| `{}` | {} | {} |\n",
+                    escape_cell(key),
+                    escape_cell(a),
+                    escape_cell(b)
+                ));
+            }
+            markdown.push('\n');
+        }
+        markdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_metadata::{BasicInfo, ContainerConfig, MetadataFormat};
+    use crate::trailers::LayerTrailers;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn save_metadata(dir: &Path, env: &[&str], labels: &[(&str, &str)]) {
+        let metadata = ImageMetadata::new(
+            Some(BasicInfo {
+                name: "app".to_string(),
+                id: "sha256:1".to_string(),
+                tags: vec!["latest".to_string()],
+                created: "2024-01-01".to_string(),
+                architecture: "amd64".to_string(),
+                os: "linux".to_string(),
+            }),
+            Some(ContainerConfig {
+                environment_variables: env.iter().map(|e| e.to_string()).collect(),
+                command: None,
+                entrypoint: None,
+                working_directory: String::new(),
+                exposed_ports: Vec::new(),
+                volumes: Vec::new(),
+                labels: labels
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<HashMap<_, _>>(),
+            }),
+        );
+        metadata.save(dir, MetadataFormat::Json).unwrap();
+    }
+
+    #[test]
+    fn test_diff_shared_base_and_changes() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path();
+        let repo = GitRepo::init_with_branch(dir, Some("a")).unwrap();
+        let rootfs = dir.join("rootfs");
+
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::write(rootfs.join("base"), "base").unwrap();
+        fs::write(rootfs.join("common"), "1").unwrap();
+        repo.commit_layer("ADD base", &LayerTrailers::new(1, "sha256:base"))
+            .unwrap();
+        let base = *repo.get_branch_commits("a").unwrap().last().unwrap();
+
+        fs::write(rootfs.join("only-a"), "aaa").unwrap();
+        repo.commit_layer("RUN a", &LayerTrailers::new(2, "sha256:a"))
+            .unwrap();
+        save_metadata(dir, &["PATH=/bin", "MODE=a"], &[("version", "1")]);
+        repo.commit_all_changes("Metadata").unwrap();
+
+        repo.create_branch("b", Some(base)).unwrap();
+        fs::write(rootfs.join("common"), "22").unwrap();
+        fs::write(rootfs.join("only-b"), "bbbbb").unwrap();
+        repo.commit_layer("RUN b", &LayerTrailers::new(2, "sha256:b"))
+            .unwrap();
+        save_metadata(dir, &["PATH=/bin", "MODE=b", "NEW=1"], &[]);
+        repo.commit_all_changes("Metadata").unwrap();
+
+        let diff = diff_branches(&repo, "a", "b").unwrap();
+        assert_eq!(diff.shared_layers, 1);
+        assert_eq!((diff.layers_a.len(), diff.layers_b.len()), (1, 1));
+        assert_eq!((diff.size_a, diff.size_b), (8, 11));
+        assert_eq!(
+            diff.files.added,
+            vec![FileChange {
+                path: "/only-b".to_string(),
+                size_a: None,
+                size_b: Some(5),
+            }]
+        );
+        assert_eq!(diff.files.removed[0].path, "/only-a");
+        assert_eq!(
+            (diff.files.changed[0].size_a, diff.files.changed[0].size_b),
+            (Some(1), Some(2))
+        );
+        assert!(diff.metadata.is_empty());
+        assert_eq!(diff.env.changed["MODE"], ("a".to_string(), "b".to_string()));
+        assert_eq!(diff.env.added["NEW"], "1");
+        assert_eq!(diff.labels.removed["version"], "1");
+
+        let markdown = diff.render_markdown();
+        assert!(markdown.contains("| rootfs size | 8 | 11 (+3) |"));
+        assert!(markdown.contains("| added | `/only-b` | - | 5 |"));
+        assert!(markdown.contains("| `MODE` | a | b |"));
+    }
+}
@@ -2,9 +2,9 @@
 //!
 //! This module defines a structured representation of `Image.md`:
 //! - [`ImageMetadata`] — the root document with optional [`BasicInfo`] and
-//!   [`ContainerConfig`], plus ordered layer history (`Vec<`[`crate::digest_tracker::LayerDigest`]`>`).
+//!   [`ContainerConfig`], plus ordered layer history (`Vec<`[`LayerDigest`]`>`).
 //! - [`BasicInfo`] — name, id (digest), tags, created, architecture, OS.
-//! - [`ContainerConfig`] — env, command, entrypoint, workdir, exposed ports, labels.
+//! - [`ContainerConfig`] — env, command, entrypoint, workdir, exposed ports, volumes, labels.
 //!
 //! Capabilities:
 //! - Render to Markdown: [`ImageMetadata::render_markdown`] (includes a “Layer History” table,
@@ -12,6 +12,11 @@
 //! - Parse from Markdown: [`ImageMetadata::parse_markdown`] (robust to code blocks/tables;
 //!   unescapes `\|` back to `|`).
 //! - File I/O helpers: [`ImageMetadata::save_markdown`] and [`ImageMetadata::load_markdown`].
+//! - JSON (`Image.json`), the machine-readable format: [`ImageMetadata::render_json`],
+//!   [`ImageMetadata::parse_json`] and their file helpers. [`ImageMetadata::save`] writes
+//!   the files selected by a [`MetadataFormat`]; [`ImageMetadata::read_from_commit`] prefers
+//!   `Image.json`, falls back to parsing `Image.md` and then to the layer files of layer
+//!   commits ([`crate::layer_records`]).
 //! - Populate layer rows directly from a [`crate::digest_tracker::DigestTracker`]
 //!   via [`ImageMetadata::update_layer_digests`] or build from a legacy metadata struct with
 //!   [`ImageMetadata::from_legacy`].
@@ -19,22 +24,62 @@
 //! The format is designed for stable diffs in Git and faithful round-trips between
 //! the in-memory model and `Image.md`.
 
-use crate::digest_tracker::{DigestTracker, LayerDigest};
+use crate::base_image::BaseImage;
+use crate::build_provenance::BuildProvenance;
+use crate::digest_tracker::DigestTracker;
+pub use crate::digest_tracker::LayerDigest;
+use crate::git::GitRepo;
+use crate::layer_records;
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
+/// Markdown report, relative to the repository root.
+pub const IMAGE_MD: &str = "Image.md";
+/// JSON report, relative to the repository root.
+pub const IMAGE_JSON: &str = "Image.json";
+
+/// Which metadata files a conversion writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetadataFormat {
+    /// `Image.md` only
+    #[default]
+    Markdown,
+    /// `Image.json` only
+    Json,
+    /// Both files
+    Both,
+}
+
+impl MetadataFormat {
+    pub fn writes_markdown(self) -> bool {
+        matches!(self, Self::Markdown | Self::Both)
+    }
+
+    pub fn writes_json(self) -> bool {
+        matches!(self, Self::Json | Self::Both)
+    }
+}
+
 /// Complete structured representation of Image.md content
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ImageMetadata {
     pub basic_info: Option<BasicInfo>,
     pub container_config: Option<ContainerConfig>,
     pub layer_digests: Vec<LayerDigest>,
+    /// Dockerfile, build arguments and source revision, if the image records them (see
+    /// [`crate::build_provenance`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildProvenance>,
+    /// The image it was built on, if known (see [`crate::base_image`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<BaseImage>,
 }
 
 /// Basic image information section
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BasicInfo {
     pub name: String,
     pub id: String,
@@ -45,16 +90,24 @@ pub struct BasicInfo {
 }
 
 /// Container configuration section
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ContainerConfig {
     pub environment_variables: Vec<String>,
     pub command: Option<String>,
     pub entrypoint: Option<String>,
     pub working_directory: String,
     pub exposed_ports: Vec<String>,
+    /// Declared `VOLUME` paths, sorted
+    pub volumes: Vec<String>,
+    #[serde(serialize_with = "sorted")]
     pub labels: HashMap<String, String>,
 }
 
+/// Serializes a map in key order, keeping `Image.json` diffs stable
+fn sorted<S: Serializer>(map: &HashMap<String, String>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
 impl ImageMetadata {
     /// Create a new ImageMetadata instance
     pub fn new(basic_info: Option<BasicInfo>, container_config: Option<ContainerConfig>) -> Self {
@@ -62,6 +115,8 @@ impl ImageMetadata {
             basic_info,
             container_config,
             layer_digests: Vec::new(),
+            build: None,
+            base: None,
         }
     }
 
@@ -108,6 +163,16 @@ impl ImageMetadata {
                 .as_ref()
                 .map(|ports| ports.keys().cloned().collect())
                 .unwrap_or_default(),
+            volumes: {
+                let mut volumes: Vec<String> = legacy
+                    .container_config
+                    .volumes
+                    .as_ref()
+                    .map(|volumes| volumes.keys().cloned().collect())
+                    .unwrap_or_default();
+                volumes.sort();
+                volumes
+            },
             labels: legacy.container_config.labels.clone().unwrap_or_default(),
         };
 
@@ -115,6 +180,8 @@ impl ImageMetadata {
             basic_info: Some(basic_info),
             container_config: Some(container_config),
             layer_digests: digest_tracker.layer_digests.clone(),
+            build: None,
+            base: None,
         }
     }
 
@@ -188,6 +255,14 @@ impl ImageMetadata {
                 markdown.push('\n');
             }
 
+            if !container_config.volumes.is_empty() {
+                markdown.push_str("### Volumes\n\n");
+                for volume in &container_config.volumes {
+                    markdown.push_str(&format!("- `{volume}`\n"));
+                }
+                markdown.push('\n');
+            }
+
             if !container_config.labels.is_empty() {
                 markdown.push_str("### Labels\n\n");
                 markdown.push_str("| Key | Value |\n");
@@ -199,11 +274,66 @@ impl ImageMetadata {
             }
         }
 
+        if let Some(base) = &self.base {
+            markdown.push_str("## Base Image\n\n");
+            if let Some(name) = &base.name {
+                markdown.push_str(&format!("- **Image**: {name}\n"));
+            }
+            if let Some(digest) = &base.digest {
+                markdown.push_str(&format!("- **Digest**: `{digest}`\n"));
+            }
+            if let Some(branch) = &base.branch {
+                markdown.push_str(&format!("- **Branch**: `{branch}`\n"));
+            }
+            if let Some(layers) = base.layers {
+                markdown.push_str(&format!("- **Layers**: {layers}\n"));
+            }
+            markdown.push('\n');
+        }
+
+        if let Some(build) = &self.build {
+            markdown.push_str("## Build Provenance\n\n");
+            for (name, value, code) in [
+                ("Builder", &build.builder, false),
+                ("Dockerfile", &build.dockerfile, true),
+                ("Source", &build.source, false),
+                ("Revision", &build.revision, true),
+            ] {
+                match (value, code) {
+                    (Some(value), true) => markdown.push_str(&format!("- **{name}**: `{value}`\n")),
+                    (Some(value), false) => markdown.push_str(&format!("- **{name}**: {value}\n")),
+                    (None, _) => {}
+                }
+            }
+            markdown.push('\n');
+
+            if !build.build_args.is_empty() {
+                markdown.push_str("### Build Arguments\n\n");
+                markdown.push_str("| Argument | Value |\n");
+                markdown.push_str("|----------|-------|\n");
+                for (name, value) in &build.build_args {
+                    markdown.push_str(&format!(
+                        "| `{}` | `{}` |\n",
+                        name.replace('|', "\\|"),
+                        value.replace('|', "\\|")
+                    ));
+                }
+                markdown.push('\n');
+            }
+        }
+
         // Layer History
         if !self.layer_digests.is_empty() {
+            // The Verified column only appears once layers were checked against their diff_ids
+            let verified = self.layer_digests.iter().any(|l| l.verified.is_some());
             markdown.push_str("## Layer History\n\n");
-            markdown.push_str("| Created | Command | Comment | Digest | Empty |\n");
-            markdown.push_str("|---------|---------|---------|--------|-------|\n");
+            if verified {
+                markdown.push_str("| Created | Command | Comment | Digest | Empty | Verified |\n");
+                markdown.push_str("|---------|---------|---------|--------|-------|----------|\n");
+            } else {
+                markdown.push_str("| Created | Command | Comment | Digest | Empty |\n");
+                markdown.push_str("|---------|---------|---------|--------|-------|\n");
+            }
 
             for layer in &self.layer_digests {
                 let comment = layer.comment.as_deref().unwrap_or("");
@@ -212,9 +342,14 @@ impl ImageMetadata {
                 let escaped_comment = comment.replace("|", "\\|");
 
                 markdown.push_str(&format!(
-                    "| {} | `{}` | {} | `{}` | {} |\n",
+                    "| {} | `{}` | {} | `{}` | {} |",
                     layer.created, escaped_command, escaped_comment, layer.digest, layer.is_empty
                 ));
+                if verified {
+                    let cell = layer.verified.map(|v| v.to_string()).unwrap_or_default();
+                    markdown.push_str(&format!(" {cell} |"));
+                }
+                markdown.push('\n');
             }
             markdown.push('\n');
         }
@@ -239,10 +374,13 @@ impl ImageMetadata {
             entrypoint: None,
             working_directory: "/".to_string(),
             exposed_ports: Vec::new(),
+            volumes: Vec::new(),
             labels: HashMap::new(),
         };
 
         let mut layer_digests = Vec::new();
+        let mut build = BuildProvenance::default();
+        let mut base = BaseImage::default();
 
         let lines: Vec<&str> = content.lines().collect();
         let mut i = 0;
@@ -317,6 +455,16 @@ impl ImageMetadata {
                 }
                 i -= 1; // Adjust for loop increment
             }
+            // Parse volumes
+            else if line == "### Volumes" {
+                i += 2; // Skip to content
+                while i < lines.len() && lines[i].trim().starts_with("- `") {
+                    let volume = lines[i].trim().replace("- `", "").replace("`", "");
+                    container_config.volumes.push(volume);
+                    i += 1;
+                }
+                i -= 1; // Adjust for loop increment
+            }
             // Parse labels table
             else if line == "### Labels" {
                 i += 3; // Skip header and separator
@@ -336,6 +484,41 @@ impl ImageMetadata {
                 }
                 i -= 1; // Adjust for loop increment
             }
+            // Parse base image
+            else if let Some(name) = line.strip_prefix("- **Image**: ") {
+                base.name = Some(name.to_string());
+            } else if let Some(digest) = line.strip_prefix("- **Digest**: ") {
+                base.digest = Some(digest.trim_matches('`').to_string());
+            } else if let Some(branch) = line.strip_prefix("- **Branch**: ") {
+                base.branch = Some(branch.trim_matches('`').to_string());
+            } else if let Some(layers) = line.strip_prefix("- **Layers**: ") {
+                base.layers = layers.parse().ok();
+            }
+            // Parse build provenance
+            else if let Some(builder) = line.strip_prefix("- **Builder**: ") {
+                build.builder = Some(builder.to_string());
+            } else if let Some(dockerfile) = line.strip_prefix("- **Dockerfile**: ") {
+                build.dockerfile = Some(dockerfile.trim_matches('`').to_string());
+            } else if let Some(source) = line.strip_prefix("- **Source**: ") {
+                build.source = Some(source.to_string());
+            } else if let Some(revision) = line.strip_prefix("- **Revision**: ") {
+                build.revision = Some(revision.trim_matches('`').to_string());
+            } else if line == "### Build Arguments" {
+                i += 4; // Skip blank line, header and separator
+                while i < lines.len() && lines[i].trim().starts_with("| `") {
+                    let row = lines[i]
+                        .trim()
+                        .trim_start_matches("| `")
+                        .trim_end_matches("` |");
+                    if let Some((name, value)) = row.split_once("` | `") {
+                        build
+                            .build_args
+                            .insert(name.replace("\\|", "|"), value.replace("\\|", "|"));
+                    }
+                    i += 1;
+                }
+                i -= 1; // Adjust for loop increment
+            }
             // Parse layer history table (now contains digest info)
             else if line == "## Layer History" {
                 i += 2; // Skip to table header
@@ -357,18 +540,18 @@ impl ImageMetadata {
                 {
                     let line_content = lines[i];
 
-                    // Find unescaped pipe positions using regex approach
+                    // Find unescaped pipe positions (byte offsets, so slicing stays on
+                    // char boundaries even for non-ASCII commands)
                     // Look for pipes that are either at start or not preceded by backslash
                     let mut unescaped_pipe_positions = Vec::new();
-                    let chars: Vec<char> = line_content.chars().collect();
-
-                    for (pos, &ch) in chars.iter().enumerate() {
-                        if ch == '|' {
-                            // Pipe is unescaped if it's at the start or not preceded by backslash
-                            if pos == 0 || chars[pos - 1] != '\\' {
-                                unescaped_pipe_positions.push(pos);
-                            }
+                    let mut previous = None;
+
+                    for (pos, ch) in line_content.char_indices() {
+                        // Pipe is unescaped if it's at the start or not preceded by backslash
+                        if ch == '|' && previous != Some('\\') {
+                            unescaped_pipe_positions.push(pos);
                         }
+                        previous = Some(ch);
                     }
 
                     // Split by unescaped pipe positions
@@ -388,6 +571,11 @@ impl ImageMetadata {
                         let comment = parts[3].trim().replace("\\|", "|");
                         let digest = parts[4].trim().replace("`", "");
                         let is_empty = parts[5].trim() == "true";
+                        let verified = match parts.get(6).map(|part| part.trim()) {
+                            Some("true") => Some(true),
+                            Some("false") => Some(false),
+                            _ => None,
+                        };
 
                         if !created.is_empty() && !digest.is_empty() {
                             layer_digests.push(LayerDigest {
@@ -400,6 +588,7 @@ impl ImageMetadata {
                                 } else {
                                     Some(comment)
                                 },
+                                verified,
                             });
                         }
                     }
@@ -424,6 +613,7 @@ impl ImageMetadata {
             && container_config.entrypoint.is_none()
             && container_config.working_directory == "/"
             && container_config.exposed_ports.is_empty()
+            && container_config.volumes.is_empty()
             && container_config.labels.is_empty()
         {
             None
@@ -435,6 +625,8 @@ impl ImageMetadata {
             basic_info: basic_info_option,
             container_config: container_config_option,
             layer_digests,
+            build: (build != BuildProvenance::default()).then_some(build),
+            base: (base != BaseImage::default()).then_some(base),
         })
     }
 
@@ -450,6 +642,79 @@ impl ImageMetadata {
         let content = fs::read_to_string(path).context("Failed to read markdown file")?;
         Self::parse_markdown(&content)
     }
+
+    /// Render as pretty-printed JSON (`Image.json`)
+    pub fn render_json(&self) -> Result<String> {
+        let mut json = serde_json::to_string_pretty(self).context("Failed to render JSON")?;
+        json.push('\n');
+        Ok(json)
+    }
+
+    /// Parse JSON content produced by [`ImageMetadata::render_json`]
+    pub fn parse_json(content: &str) -> Result<Self> {
+        serde_json::from_str(content).context("Failed to parse image metadata JSON")
+    }
+
+    /// Save as JSON file
+    pub fn save_json(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.render_json()?).context("Failed to write JSON file")
+    }
+
+    /// Load from JSON file
+    pub fn load_json(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).context("Failed to read JSON file")?;
+        Self::parse_json(&content)
+    }
+
+    /// Write the files selected by `format` into `dir`, removing the other one so a stale
+    /// report never outlives a format switch.
+    pub fn save(&self, dir: &Path, format: MetadataFormat) -> Result<()> {
+        for (name, selected) in [
+            (IMAGE_MD, format.writes_markdown()),
+            (IMAGE_JSON, format.writes_json()),
+        ] {
+            let path = dir.join(name);
+            if selected {
+                if name == IMAGE_MD {
+                    self.save_markdown(&path)?;
+                } else {
+                    self.save_json(&path)?;
+                }
+            } else if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The metadata recorded at `commit`: `Image.json` if present, `Image.md` otherwise,
+    /// or just the layer chain of a layer commit ([`crate::layer_records`]); `None` if the
+    /// commit has none of them.
+    ///
+    /// # Errors
+    /// - A file exists but can't be parsed.
+    pub fn read_from_commit(repo: &GitRepo, commit: git2::Oid) -> Result<Option<Self>> {
+        if let Ok(content) = repo.read_file_from_commit(commit, IMAGE_JSON) {
+            return Self::parse_json(&content)
+                .with_context(|| format!("Failed to parse {IMAGE_JSON} of commit {commit}"))
+                .map(Some);
+        }
+        match repo.read_file_from_commit(commit, IMAGE_MD) {
+            Ok(content) => Self::parse_markdown(&content)
+                .with_context(|| format!("Failed to parse {IMAGE_MD} of commit {commit}"))
+                .map(Some),
+            Err(_) => Ok(
+                layer_records::read_from_commit(repo, commit)?.map(|layer_digests| Self {
+                    basic_info: None,
+                    container_config: None,
+                    layer_digests,
+                    build: None,
+                    base: None,
+                }),
+            ),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -478,6 +743,7 @@ mod tests {
             entrypoint: Some("docker-entrypoint.sh".to_string()),
             working_directory: "/app".to_string(),
             exposed_ports: vec!["80/tcp".to_string()],
+            volumes: vec!["/data".to_string(), "/var/log/app".to_string()],
             labels,
         };
 
@@ -488,6 +754,7 @@ mod tests {
                 created: "2023-01-01T00:00:00Z".to_string(),
                 is_empty: false,
                 comment: None,
+                verified: None,
             },
             LayerDigest {
                 digest: "sha256:def456".to_string(),
@@ -495,6 +762,7 @@ mod tests {
                 created: "2023-01-01T00:00:00Z".to_string(),
                 is_empty: true,
                 comment: None,
+                verified: None,
             },
         ];
 
@@ -502,6 +770,8 @@ mod tests {
             basic_info: Some(basic_info),
             container_config: Some(container_config),
             layer_digests,
+            build: None,
+            base: None,
         }
     }
 
@@ -523,6 +793,7 @@ mod tests {
         assert!(result.contains("PATH=/usr/local/sbin"));
         assert!(result.contains("### Command"));
         assert!(result.contains("bash"));
+        assert!(result.contains("### Volumes\n\n- `/data`\n- `/var/log/app`\n"));
 
         // Verify layer history (now includes digests)
         assert!(result.contains("## Layer History"));
@@ -551,6 +822,38 @@ mod tests {
             loaded.basic_info.as_ref().unwrap().architecture,
             original.basic_info.as_ref().unwrap().architecture
         );
+        assert_eq!(
+            loaded.container_config.as_ref().unwrap().volumes,
+            original.container_config.as_ref().unwrap().volumes
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip_and_format_switch() {
+        let temp_dir = tempdir().unwrap();
+        let mut original = create_test_metadata();
+        // Markdown tables can't hold this faithfully, JSON must
+        original.layer_digests[0].command =
+            "RUN set -eux; \\\n  apk add --no-cache curl | tee `log`".to_string();
+
+        let json = original.render_json().unwrap();
+        assert!(json.contains("\"basic_info\": {"));
+        assert_eq!(ImageMetadata::parse_json(&json).unwrap(), original);
+
+        original
+            .save(temp_dir.path(), MetadataFormat::Both)
+            .unwrap();
+        assert!(temp_dir.path().join(IMAGE_MD).exists());
+        assert_eq!(
+            ImageMetadata::load_json(&temp_dir.path().join(IMAGE_JSON)).unwrap(),
+            original
+        );
+
+        original
+            .save(temp_dir.path(), MetadataFormat::Markdown)
+            .unwrap();
+        assert!(temp_dir.path().join(IMAGE_MD).exists());
+        assert!(!temp_dir.path().join(IMAGE_JSON).exists());
     }
 
     #[test]
@@ -570,6 +873,7 @@ mod tests {
             entrypoint: Some("docker-entrypoint.sh".to_string()),
             working_directory: "/app".to_string(),
             exposed_ports: vec![],
+            volumes: vec![],
             labels: HashMap::new(),
         };
 
@@ -579,12 +883,15 @@ mod tests {
             created: "2023-01-01T00:00:00Z".to_string(),
             is_empty: false,
             comment: Some("comment | with | pipes".to_string()),
+            verified: None,
         }];
 
         let metadata = ImageMetadata {
             basic_info: Some(basic_info),
             container_config: Some(container_config),
             layer_digests,
+            build: None,
+            base: None,
         };
 
         let result = metadata.render_markdown().unwrap();
@@ -605,6 +912,93 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_non_ascii_layer_history_round_trip() {
+        let metadata = ImageMetadata {
+            basic_info: None,
+            container_config: None,
+            layer_digests: vec![LayerDigest {
+                digest: "sha256:abc123".to_string(),
+                command: "RUN echo 'héllo → wörld' | tee /motd".to_string(),
+                created: "2023-01-01T00:00:00Z".to_string(),
+                is_empty: false,
+                comment: Some("café".to_string()),
+                verified: None,
+            }],
+            build: None,
+            base: None,
+        };
+
+        let rendered = metadata.render_markdown().unwrap();
+        let parsed = ImageMetadata::parse_markdown(&rendered).unwrap();
+
+        assert_eq!(parsed.layer_digests, metadata.layer_digests);
+    }
+
+    #[test]
+    fn test_verified_column_round_trip() {
+        let layer = |digest: &str, verified| LayerDigest {
+            digest: digest.to_string(),
+            command: "ADD rootfs.tar /".to_string(),
+            created: "2023-01-01T00:00:00Z".to_string(),
+            is_empty: false,
+            comment: None,
+            verified,
+        };
+        let mut metadata = ImageMetadata::new(None, None);
+        metadata.layer_digests = vec![layer("sha256:abc123", None)];
+        assert!(!metadata.render_markdown().unwrap().contains("Verified"));
+
+        metadata.layer_digests = vec![
+            layer("sha256:abc123", None),
+            layer("sha256:def456", Some(true)),
+            layer("sha256:789abc", Some(false)),
+        ];
+        let rendered = metadata.render_markdown().unwrap();
+        assert!(rendered.contains("| Empty | Verified |"));
+        assert!(rendered.contains("| `sha256:def456` | false | true |"));
+        let parsed = ImageMetadata::parse_markdown(&rendered).unwrap();
+        assert_eq!(parsed.layer_digests, metadata.layer_digests);
+    }
+
+    #[test]
+    fn test_build_provenance_round_trip() {
+        let mut metadata = ImageMetadata::new(None, None);
+        assert!(!metadata
+            .render_markdown()
+            .unwrap()
+            .contains("Build Provenance"));
+
+        metadata.build = Some(BuildProvenance {
+            builder: Some("buildkit".to_string()),
+            dockerfile: Some("docker/Dockerfile".to_string()),
+            source: Some("https://github.com/example/app".to_string()),
+            revision: Some("0123456789abcdef".to_string()),
+            build_args: [("VERSION".to_string(), "1.2.3".to_string())].into(),
+        });
+        let rendered = metadata.render_markdown().unwrap();
+        assert!(rendered.contains("- **Revision**: `0123456789abcdef`"));
+        assert!(rendered.contains("| `VERSION` | `1.2.3` |"));
+        let parsed = ImageMetadata::parse_markdown(&rendered).unwrap();
+        assert_eq!(parsed.build, metadata.build);
+    }
+
+    #[test]
+    fn test_base_image_round_trip() {
+        let mut metadata = ImageMetadata::new(None, None);
+        metadata.base = Some(BaseImage {
+            name: Some("docker.io/library/alpine:3.20".to_string()),
+            digest: Some("sha256:abc123".to_string()),
+            branch: Some("alpine#3.20#linux-amd64#abc123".to_string()),
+            layers: Some(2),
+        });
+        let rendered = metadata.render_markdown().unwrap();
+        assert!(rendered.contains("## Base Image\n\n- **Image**: docker.io/library/alpine:3.20\n"));
+        let parsed = ImageMetadata::parse_markdown(&rendered).unwrap();
+        assert_eq!(parsed.base, metadata.base);
+        assert_eq!(parsed.build, None);
+    }
+
     #[test]
     fn test_real_world_round_trip() {
         // Test with real data from alp/Image.md that contains complex commands with pipes
@@ -631,6 +1025,7 @@ mod tests {
             entrypoint: Some("docker-entrypoint.sh".to_string()),
             working_directory: "/".to_string(),
             exposed_ports: vec!["5432/tcp".to_string()],
+            volumes: vec![],
             labels,
         };
 
@@ -642,6 +1037,7 @@ mod tests {
                 created: "2025-02-14T03:28:36+00:00".to_string(),
                 is_empty: false,
                 comment: Some("buildkit.dockerfile.v0".to_string()),
+                verified: None,
             },
             LayerDigest {
                 digest: "sha256:7253dfc6422805ac3c15fda3414a5e3fb679f89df5a9ecfb3b80db788b4e8dcf".to_string(),
@@ -649,6 +1045,7 @@ mod tests {
                 created: "2025-06-06T18:27:47+00:00".to_string(),
                 is_empty: false,
                 comment: Some("buildkit.dockerfile.v0".to_string()),
+                verified: None,
             },
             LayerDigest {
                 digest: "sha256:c9d81a483d3df409a38c9a58f1a0aed7d439f67b1200e39485beee626b61b66e".to_string(),
@@ -656,6 +1053,7 @@ mod tests {
                 created: "2025-06-06T18:27:47+00:00".to_string(),
                 is_empty: false,
                 comment: Some("buildkit.dockerfile.v0".to_string()),
+                verified: None,
             },
             LayerDigest {
                 digest: "sha256:d5b0bb61acee74b02675e9f87df8e6c1f747d93dc7e017908aae89187f4180e9".to_string(),
@@ -663,6 +1061,7 @@ mod tests {
                 created: "2025-06-06T18:27:47+00:00".to_string(),
                 is_empty: false,
                 comment: Some("buildkit.dockerfile.v0".to_string()),
+                verified: None,
             },
         ];
 
@@ -670,6 +1069,8 @@ mod tests {
             basic_info: Some(basic_info),
             container_config: Some(container_config),
             layer_digests,
+            build: None,
+            base: None,
         };
 
         // Test the round-trip: render to markdown, then parse back
@@ -0,0 +1,201 @@
+//! `oci2git inspect`: the layer history of an image, without converting it.
+//!
+//! [`Inspection::new`] reads an [`ExtractedImage`] — the same data `Image.md` shows in its
+//! "Layer History" table — so there is no need to combine `docker history --no-trunc`
+//! with `jq`. [`Inspection::render_table`] prints it as an aligned text table; it is also
+//! [`Serialize`] for JSON output:
+//!
+//! ```text
+//! Image:    nginx:1.27
+//! Id:       sha256:…
+//! Platform: linux/amd64
+//! Created:  2024-03-01T00:00:00Z
+//!
+//! #  CREATED               SIZE      DIGEST     COMMENT                 COMMAND
+//! 1  2024-02-28T10:00:00Z  74.8 MiB  sha256:…                          /bin/sh -c #(nop) ADD file:… in /
+//! 2  2024-02-28T10:00:01Z  0 B       empty      buildkit.dockerfile.v0  CMD ["nginx"]
+//! ```
+
+use crate::disk_space::format_bytes;
+use crate::extracted_image::ExtractedImage;
+use anyhow::Result;
+use serde::Serialize;
+
+/// Image basics and layer history of an image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Inspection {
+    /// The image reference (or tarball path) that was inspected.
+    pub image: String,
+    pub id: String,
+    pub tags: Vec<String>,
+    pub created: String,
+    pub os: String,
+    pub architecture: String,
+    /// Layers in chronological order (oldest → newest), empty ones included.
+    pub layers: Vec<InspectedLayer>,
+}
+
+/// One entry of the layer history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InspectedLayer {
+    /// 1-based layer index
+    pub index: usize,
+    /// RFC 3339 creation time
+    pub created: String,
+    pub command: String,
+    pub comment: Option<String>,
+    /// Digest of the layer tarball, `empty` for layers without one.
+    pub digest: String,
+    /// `diff_id` from the image config, if it lists one.
+    pub diff_id: Option<String>,
+    /// Size of the layer tarball in bytes.
+    pub size: u64,
+    pub empty: bool,
+}
+
+impl Inspection {
+    /// Reads the metadata and layers of `extracted_image`, fetched for `image`.
+    ///
+    /// # Errors
+    /// - The image metadata can't be read.
+    pub fn new(image: &str, extracted_image: &ExtractedImage) -> Result<Self> {
+        let metadata = extracted_image.metadata(image)?;
+        let layers = extracted_image
+            .layers()?
+            .into_iter()
+            .enumerate()
+            .map(|(i, layer)| InspectedLayer {
+                index: i + 1,
+                created: layer.created_at.to_rfc3339(),
+                command: layer.command,
+                comment: layer.comment,
+                digest: layer.digest,
+                diff_id: layer.diff_id,
+                size: layer.size,
+                empty: layer.is_empty,
+            })
+            .collect();
+        Ok(Self {
+            image: image.to_string(),
+            id: metadata.id,
+            tags: metadata.repo_tags,
+            created: metadata.created,
+            os: metadata.os,
+            architecture: metadata.architecture,
+            layers,
+        })
+    }
+
+    /// Image basics followed by one aligned row per layer; commands are printed in full,
+    /// with line breaks folded into spaces.
+    pub fn render_table(&self) -> String {
+        let mut out = format!("Image:    {}\n", self.image);
+        out.push_str(&format!("Id:       {}\n", self.id));
+        if !self.tags.is_empty() {
+            out.push_str(&format!("Tags:     {}\n", self.tags.join(", ")));
+        }
+        out.push_str(&format!("Platform: {}/{}\n", self.os, self.architecture));
+        out.push_str(&format!("Created:  {}\n\n", self.created));
+
+        let header = ["#", "CREATED", "SIZE", "DIGEST", "COMMENT", "COMMAND"];
+        let rows: Vec<[String; 6]> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                [
+                    layer.index.to_string(),
+                    layer.created.clone(),
+                    format_bytes(layer.size),
+                    layer.digest.clone(),
+                    layer.comment.clone().unwrap_or_default(),
+                    layer
+                        .command
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ]
+            })
+            .collect();
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let mut push_row = |cells: Vec<&str>| {
+            let last = cells.len() - 1;
+            let line: Vec<String> = cells
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    if i == last {
+                        cell.to_string()
+                    } else {
+                        format!("{cell:<width$}", width = widths[i])
+                    }
+                })
+                .collect();
+            out.push_str(line.join("  ").trim_end());
+            out.push('\n');
+        };
+        push_row(header.to_vec());
+        for row in &rows {
+            push_row(row.iter().map(String::as_str).collect());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let layer = |index, command: &str, size, comment: Option<&str>| InspectedLayer {
+            index,
+            created: "2024-03-01T00:00:00+00:00".to_string(),
+            command: command.to_string(),
+            comment: comment.map(str::to_string),
+            digest: if size == 0 { "empty" } else { "sha256:aa" }.to_string(),
+            diff_id: None,
+            size,
+            empty: size == 0,
+        };
+        let inspection = Inspection {
+            image: "app:latest".to_string(),
+            id: "sha256:cc".to_string(),
+            tags: vec!["app:latest".to_string()],
+            created: "2024-03-01T00:00:00Z".to_string(),
+            os: "linux".to_string(),
+            architecture: "amd64".to_string(),
+            layers: vec![
+                layer(1, "ADD rootfs.tar /", 2048, None),
+                layer(
+                    2,
+                    "RUN apk add \\\n    curl",
+                    0,
+                    Some("buildkit.dockerfile.v0"),
+                ),
+            ],
+        };
+
+        let table = inspection.render_table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[2], "Tags:     app:latest");
+        assert_eq!(lines[3], "Platform: linux/amd64");
+        assert_eq!(
+            lines[6],
+            "#  CREATED                    SIZE     DIGEST     COMMENT                 COMMAND"
+        );
+        assert_eq!(
+            lines[7],
+            "1  2024-03-01T00:00:00+00:00  2.0 KiB  sha256:aa                          ADD rootfs.tar /"
+        );
+        assert_eq!(
+            lines[8],
+            "2  2024-03-01T00:00:00+00:00  0 B      empty      buildkit.dockerfile.v0  RUN apk add \\ curl"
+        );
+    }
+}
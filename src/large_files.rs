@@ -0,0 +1,205 @@
+//! Large file stubs: `.oci2git/large-files.jsonl`.
+//!
+//! With `--max-file-size <BYTES>` regular files larger than the limit are not written to
+//! `rootfs/`. A small text stub takes their place, so the tree keeps its structure and
+//! `git diff` still shows that something changed:
+//!
+//! ```text
+//! oci2git stub: file omitted by --max-file-size
+//! path: /usr/local/cuda/lib64/libcublasLt.so.12
+//! size: 532718080
+//! sha256: 4f5c…
+//! ```
+//!
+//! Every commit whose filesystem contains stubs also carries [`LARGE_FILES_PATH`], one
+//! JSON object per stub, sorted by path:
+//!
+//! ```text
+//! {"path":"/usr/local/cuda/lib64/libcublasLt.so.12","size":532718080,"sha256":"4f5c…"}
+//! ```
+//!
+//! Sparse files become stubs as well with `--sparse-files stub`, or when their apparent
+//! size exceeds the limit; their stubs and entries also name the bytes of `data` outside
+//! the holes (see [`crate::sparse`]).
+//!
+//! The digest is computed while the file streams past, so the content never touches the
+//! disk. Hardlinks to a stubbed file become copies of the stub and aren't listed. Branches
+//! with stubs can't be exported back into an image (see [`crate::git2oci`]).
+
+use crate::fsmeta;
+use crate::layer_apply::{LayerObserver, ObservedEntry};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Location of the manifest, relative to the repository root.
+pub const LARGE_FILES_PATH: &str = ".oci2git/large-files.jsonl";
+
+/// A file replaced by a stub.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStub {
+    /// Absolute path inside the image
+    pub path: String,
+    /// Size of the original file in bytes
+    pub size: u64,
+    /// Hex SHA-256 of the original content
+    pub sha256: String,
+    /// Bytes of data outside the holes of a sparse file (see [`crate::sparse`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_size: Option<u64>,
+}
+
+impl FileStub {
+    /// Contents of the stub written in place of the file
+    pub fn render(&self) -> String {
+        match self.data_size {
+            Some(data_size) => format!(
+                "oci2git stub: sparse file omitted\npath: {}\nsize: {}\ndata: {}\nsha256: {}\n",
+                self.path, self.size, data_size, self.sha256
+            ),
+            None => format!(
+                "oci2git stub: file omitted by --max-file-size\npath: {}\nsize: {}\nsha256: {}\n",
+                self.path, self.size, self.sha256
+            ),
+        }
+    }
+}
+
+/// The stubs of a `rootfs/`, keyed by image path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LargeFiles {
+    pub entries: BTreeMap<String, FileStub>,
+}
+
+impl LargeFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the contents of a manifest file.
+    ///
+    /// # Errors
+    /// - A line that isn't a valid entry.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: FileStub = serde_json::from_str(line).with_context(|| {
+                format!("Invalid entry on line {} of {LARGE_FILES_PATH}", number + 1)
+            })?;
+            entries.insert(entry.path.clone(), entry);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Writes the manifest to `path`, one entry per line in path order, or removes it if
+    /// there are no stubs (left).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.entries.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut content = String::new();
+        for entry in self.entries.values() {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl LayerObserver for LargeFiles {
+    fn entry(&mut self, path: &Path, entry: &ObservedEntry) {
+        let path = fsmeta::image_path(path);
+        // A directory keeps its contents, anything else replaces the whole subtree
+        if !entry.header.entry_type().is_dir() {
+            fsmeta::remove_descendants(&mut self.entries, &path);
+        }
+        self.entries.remove(&path);
+    }
+
+    fn stubbed(&mut self, stub: &FileStub) {
+        self.entries.insert(stub.path.clone(), stub.clone());
+    }
+
+    fn removed(&mut self, path: &Path) {
+        let path = fsmeta::image_path(path);
+        fsmeta::remove_descendants(&mut self.entries, &path);
+        self.entries.remove(&path);
+    }
+
+    fn cleared(&mut self, path: &Path) {
+        fsmeta::remove_descendants(&mut self.entries, &fsmeta::image_path(path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_apply::{ConflictStrategy, LayerApplier};
+    use tar_rs as tar;
+    use tempfile::tempdir;
+
+    fn apply(root: &Path, large_files: &mut LargeFiles, files: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        let data = builder.into_inner().unwrap();
+        LayerApplier::new(root, ConflictStrategy::default())
+            .unwrap()
+            .with_max_file_size(Some(4))
+            .with_observer(large_files)
+            .apply_archive(&mut tar::Archive::new(&data[..]))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_oversized_files_become_stubs() {
+        let temp_dir = tempdir().unwrap();
+        let mut large_files = LargeFiles::new();
+
+        apply(
+            temp_dir.path(),
+            &mut large_files,
+            &[("small", b"1234"), ("opt/big", b"12345")],
+        );
+        assert_eq!(fs::read(temp_dir.path().join("small")).unwrap(), b"1234");
+        let stub = &large_files.entries["/opt/big"];
+        assert_eq!(stub.size, 5);
+        assert_eq!(
+            stub.sha256,
+            "5994471abb01112afcc18159f6cc74b4f511b99806da59b3caf5a9c173cacfc5"
+        );
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("opt/big")).unwrap(),
+            stub.render()
+        );
+
+        // Manifest round trip
+        let manifest = temp_dir.path().join(LARGE_FILES_PATH);
+        large_files.save(&manifest).unwrap();
+        let content = fs::read_to_string(&manifest).unwrap();
+        assert_eq!(LargeFiles::parse(&content).unwrap(), large_files);
+
+        // A smaller replacement is stored as is and drops the stub
+        apply(temp_dir.path(), &mut large_files, &[("opt/big", b"1")]);
+        assert!(large_files.entries.is_empty());
+        large_files.save(&manifest).unwrap();
+        assert!(!manifest.exists());
+    }
+}
@@ -0,0 +1,1545 @@
+//! Layer application engine: replays the entries of one OCI layer onto a rootfs.
+//!
+//! [`LayerApplier`] is the implementation of overlay semantics used everywhere a layer is
+//! unpacked (see [`crate::tar_extractor`]); [`crate::tree_apply`] mirrors it for layers
+//! committed without a worktree:
+//! - whiteouts (`.wh.<name>`) and opaque directories (`.wh..wh..opq`),
+//! - entry type changes, resolved by [`apply_entry`] according to a [`ConflictStrategy`],
+//! - symlinks, resolved inside the root (falling back to copying the target),
+//! - hardlinks, created after all other entries (falling back to copying),
+//! - permission fixes so that the result stays readable and writable for Git.
+//!
+//! With [`LayerApplier::with_jobs`] regular files are written on a thread pool while the
+//! archive is read on; entries that remove, replace or link paths wait for the writes
+//! before them, so the result doesn't depend on the number of threads.
+//!
+//! Device nodes and FIFOs can't be created (or committed), so they only replace whatever
+//! was at their path; a [`LayerObserver`] such as [`crate::special_files::SpecialFiles`]
+//! records them.
+//!
+//! Layers are untrusted input: no entry may read, write or delete anything outside the
+//! root, even through symlinks left behind by earlier layers.
+//!
+//! The applier works on any [`tar::Archive`], so callers decide where the bytes come from
+//! (a file on disk, a gzip stream, a network response, …). A [`LayerObserver`] can be
+//! attached to learn which paths a layer created, updated or deleted, together with the
+//! tar headers (owner, mode, PAX records) that don't survive extraction.
+
+use crate::estargz::Bookkeeping;
+use crate::fsmeta;
+use crate::large_files::FileStub;
+use crate::layer_verification::HashingReader;
+use crate::path_filter::PathFilter;
+use crate::sparse::{self, SparseEntry, SparseFiles};
+use crate::symlinks::{self, SymlinkTargets};
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use tar_rs as tar;
+
+/// Regular files up to this size are written by the thread pool of
+/// [`LayerApplier::with_jobs`]; larger ones are streamed to disk directly instead of being
+/// buffered in memory.
+const PARALLEL_WRITE_MAX: u64 = 4 * 1024 * 1024;
+
+/// Normalizes a path from a tar archive to be safe for extraction
+/// Removes any attempts to escape the root directory
+pub(crate) fn normalize_tar_path(p: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+
+    for comp in p.components() {
+        match comp {
+            Component::CurDir => { /* skip "." */ }
+            Component::ParentDir => {
+                // Don't allow escaping the rootfs
+                out.pop();
+            }
+            Component::Normal(c) => out.push(c),
+            // Ignore absolute paths and Windows prefixes
+            Component::RootDir | Component::Prefix(_) => { /* ignore */ }
+        }
+    }
+
+    out
+}
+
+/// Verifies that `path` stays inside `root` once symlinks in its existing ancestors are
+/// followed. `root` must already be canonical.
+///
+/// Layer archives are untrusted input: a symlink such as `lib -> ../../../etc` followed by
+/// an entry `lib/passwd` would otherwise make us write (or delete, or copy from) files on
+/// the host outside of the extraction directory.
+pub(crate) fn ensure_within_root(root: &Path, path: &Path) -> Result<()> {
+    // Find the deepest ancestor that already exists on disk
+    let mut probe = path;
+    while fs::symlink_metadata(probe).is_err() {
+        match probe.parent() {
+            Some(parent) => probe = parent,
+            None => return Ok(()),
+        }
+    }
+
+    let resolved = probe
+        .canonicalize()
+        .with_context(|| format!("Refusing to follow dangling link: {}", probe.display()))?;
+
+    if !resolved.starts_with(root) {
+        anyhow::bail!(
+            "Refusing to access {} - it resolves outside of the extraction root ({})",
+            path.display(),
+            resolved.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Attempts to create a hardlink, falling back to copy if hardlinks aren't supported
+/// Returns Ok(()) if successful, Err if the target doesn't exist (caller should skip)
+fn try_link_or_copy(target: &Path, dest: &Path) -> Result<()> {
+    if !target.exists() {
+        // Target doesn't exist - this can happen if:
+        // 1. The target was removed by a whiteout in this layer
+        // 2. The target was replaced/removed in a previous layer
+        // 3. The tar archive has broken hardlinks
+        // We should skip this hardlink rather than failing
+        return Err(anyhow::anyhow!(
+            "hardlink target missing: {}",
+            target.display()
+        ));
+    }
+
+    // Try to create a hardlink first
+    if let Err(e) = fs::hard_link(target, dest) {
+        // Filesystem might not support hardlinks - fall back to copy
+        log::debug!(
+            "hardlink failed ({}), falling back to copy: {} -> {}",
+            e,
+            target.display(),
+            dest.display()
+        );
+        fs::copy(target, dest).with_context(|| {
+            format!("Failed to copy {} to {}", target.display(), dest.display())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// How to resolve a layer entry whose path is already occupied by an entry of a different
+/// type (e.g. a directory replaced by a file, or a file replaced by a symlink).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictStrategy {
+    /// The newer entry wins and whatever is in the way is removed, as specified for OCI
+    /// layers. A directory entry over an existing directory merges into it.
+    #[default]
+    Replace,
+    /// Like [`ConflictStrategy::Replace`], but a directory entry over a symlink that points
+    /// to a directory keeps the symlink and merges through it (e.g. usr-merged
+    /// `lib -> usr/lib`).
+    FollowSymlinks,
+    /// Any change of entry type is an error.
+    Abort,
+}
+
+/// The type of entry a layer wants to create at a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Directory,
+    File,
+    Symlink,
+    Hardlink,
+}
+
+/// Outcome of [`apply_entry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryAction {
+    /// The path is free; create the new entry.
+    Create,
+    /// An existing directory is reused; only update its metadata.
+    Merge,
+}
+
+/// Applies the overlay rules for an `incoming` entry at `dest`.
+///
+/// Removes whatever is in the way according to `strategy` and tells the caller whether to
+/// create the entry or merge into an existing directory. Entries of the same type are
+/// always replaced (files, links) or merged (directories).
+pub fn apply_entry(
+    dest: &Path,
+    incoming: EntryKind,
+    strategy: ConflictStrategy,
+) -> Result<EntryAction> {
+    let Ok(metadata) = fs::symlink_metadata(dest) else {
+        return Ok(EntryAction::Create);
+    };
+
+    let existing = if metadata.is_dir() {
+        EntryKind::Directory
+    } else if metadata.is_symlink() {
+        EntryKind::Symlink
+    } else {
+        EntryKind::File
+    };
+
+    if incoming == EntryKind::Directory {
+        if existing == EntryKind::Directory {
+            return Ok(EntryAction::Merge);
+        }
+        if existing == EntryKind::Symlink
+            && strategy == ConflictStrategy::FollowSymlinks
+            && dest.is_dir()
+        {
+            return Ok(EntryAction::Merge);
+        }
+    }
+
+    // Hardlinks are regular files once created
+    let same_type =
+        existing == incoming || (existing == EntryKind::File && incoming == EntryKind::Hardlink);
+    if !same_type && strategy == ConflictStrategy::Abort {
+        anyhow::bail!(
+            "Layer replaces {existing:?} with {incoming:?} at {}",
+            dest.display()
+        );
+    }
+
+    log::debug!(
+        "Replacing existing {existing:?} with {incoming:?} at: {}",
+        dest.display()
+    );
+    remove_path(dest)?;
+    Ok(EntryAction::Create)
+}
+
+/// Removes a file, symlink or directory tree without following symlinks.
+fn remove_path(path: &Path) -> Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+    let removed = if metadata.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    removed.with_context(|| format!("Failed to remove existing entry: {}", path.display()))
+}
+
+/// Observes what a [`LayerApplier`] does to the root, e.g. to record file metadata that the
+/// extracted tree (and Git) cannot hold. Paths are relative to the root.
+///
+/// A `Vec<&mut dyn LayerObserver>` forwards to each of its observers in turn.
+pub trait LayerObserver {
+    /// An entry was applied at `path`.
+    fn entry(&mut self, path: &Path, entry: &ObservedEntry);
+    /// `path` and everything below it were deleted by a whiteout.
+    fn removed(&mut self, path: &Path);
+    /// Everything below the directory `path` was deleted by an opaque whiteout.
+    fn cleared(&mut self, path: &Path);
+    /// The file just reported to [`LayerObserver::entry`] was written as a stub (see
+    /// [`LayerApplier::with_max_file_size`]).
+    fn stubbed(&mut self, _stub: &FileStub) {}
+    /// Whether regular files should be hashed while they are written, for
+    /// [`ObservedEntry::sha256`].
+    fn wants_content_hash(&self) -> bool {
+        false
+    }
+}
+
+impl LayerObserver for Vec<&mut dyn LayerObserver> {
+    fn entry(&mut self, path: &Path, entry: &ObservedEntry) {
+        for observer in self.iter_mut() {
+            observer.entry(path, entry);
+        }
+    }
+
+    fn removed(&mut self, path: &Path) {
+        for observer in self.iter_mut() {
+            observer.removed(path);
+        }
+    }
+
+    fn cleared(&mut self, path: &Path) {
+        for observer in self.iter_mut() {
+            observer.cleared(path);
+        }
+    }
+
+    fn stubbed(&mut self, stub: &FileStub) {
+        for observer in self.iter_mut() {
+            observer.stubbed(stub);
+        }
+    }
+
+    fn wants_content_hash(&self) -> bool {
+        self.iter().any(|observer| observer.wants_content_hash())
+    }
+}
+
+/// What a [`LayerObserver`] learns about an applied entry.
+pub struct ObservedEntry {
+    pub header: tar::Header,
+    /// Link target, including GNU long link names the header can't hold
+    pub link_name: Option<PathBuf>,
+    /// PAX extended header records, e.g. `SCHILY.xattr.security.capability`
+    pub pax: Vec<(String, Vec<u8>)>,
+    /// Size of a regular file's content, including the holes of sparse files (see
+    /// [`crate::sparse`])
+    pub size: u64,
+    /// Hex SHA-256 of a regular file's content, if the observer
+    /// [wants it](LayerObserver::wants_content_hash) or the file was stubbed
+    pub sha256: Option<String>,
+}
+
+impl ObservedEntry {
+    /// `entry` with its PAX records (see [`pax_records`]) and the `size` of its content.
+    pub(crate) fn new<R: Read>(
+        entry: &tar::Entry<R>,
+        pax: Vec<(String, Vec<u8>)>,
+        size: u64,
+    ) -> Result<Self> {
+        Ok(Self {
+            header: entry.header().clone(),
+            link_name: entry
+                .link_name()
+                .context("Invalid link name")?
+                .map(|link_name| link_name.into_owned()),
+            pax,
+            size,
+            sha256: None,
+        })
+    }
+}
+
+/// The PAX extended header records of `entry`, in order.
+pub(crate) fn pax_records<R: Read>(entry: &mut tar::Entry<R>) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut pax = Vec::new();
+    if let Some(extensions) = entry
+        .pax_extensions()
+        .context("Failed to read PAX header")?
+    {
+        for extension in extensions {
+            let extension = extension.context("Invalid PAX record")?;
+            let key = extension.key().context("Invalid PAX record key")?;
+            pax.push((key.to_string(), extension.value_bytes().to_vec()));
+        }
+    }
+    Ok(pax)
+}
+
+struct PendingHardlink {
+    dest: PathBuf,
+    target: PathBuf,
+}
+
+struct PendingSymlink {
+    dest: PathBuf,
+    target: PathBuf,
+}
+
+/// Sets the mode of a regular file from a layer, keeping it readable by its owner for Git.
+fn set_file_mode(dest: &Path, mode: Option<u32>) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mut mode) = mode {
+            // Ensure owner can read (add 0o400 if not present)
+            if mode & 0o400 == 0 {
+                mode |= 0o400;
+                log::debug!(
+                    "Fixed unreadable file during extraction: {}",
+                    dest.display()
+                );
+            }
+            let perms = fs::Permissions::from_mode(mode);
+            let _ = fs::set_permissions(dest, perms);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (dest, mode);
+}
+
+/// Files being written, and the first failure
+#[derive(Default)]
+struct WriteState {
+    in_flight: Vec<PathBuf>,
+    error: Option<anyhow::Error>,
+}
+
+/// Writes regular files on a thread pool (see [`LayerApplier::with_jobs`]).
+struct ParallelWriter {
+    pool: rayon::ThreadPool,
+    state: Arc<(Mutex<WriteState>, Condvar)>,
+    /// Bounds the memory held by buffered files
+    max_in_flight: usize,
+}
+
+impl ParallelWriter {
+    fn new(jobs: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .thread_name(|index| format!("oci2git-write-{index}"))
+            .build()
+            .context("Failed to start the file writing threads")?;
+        Ok(Self {
+            pool,
+            state: Arc::default(),
+            max_in_flight: jobs * 4,
+        })
+    }
+
+    /// Writes `data` to `dest` in the background, once earlier writes to `dest` are done.
+    fn write(&self, dest: PathBuf, data: Vec<u8>, mode: Option<u32>) -> Result<()> {
+        {
+            let mut state = self.wait_while(|state| {
+                state.in_flight.len() >= self.max_in_flight || state.in_flight.contains(&dest)
+            })?;
+            state.in_flight.push(dest.clone());
+        }
+
+        let shared = Arc::clone(&self.state);
+        self.pool.spawn(move || {
+            let written = fs::write(&dest, &data)
+                .with_context(|| format!("Failed to write file: {}", dest.display()));
+            if written.is_ok() {
+                set_file_mode(&dest, mode);
+            }
+            let (lock, changed) = &*shared;
+            let mut state = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            state.in_flight.retain(|path| *path != dest);
+            if let Err(error) = written {
+                state.error.get_or_insert(error);
+            }
+            changed.notify_all();
+        });
+        Ok(())
+    }
+
+    /// Waits for the writes to `path` and to its ancestors.
+    fn wait_for(&self, path: &Path) -> Result<()> {
+        self.wait_while(|state| state.in_flight.iter().any(|file| path.starts_with(file)))
+            .map(drop)
+    }
+
+    /// Waits for all writes.
+    fn wait_all(&self) -> Result<()> {
+        self.wait_while(|state| !state.in_flight.is_empty())
+            .map(drop)
+    }
+
+    /// Blocks while `condition` holds; fails with the first failed write.
+    fn wait_while(
+        &self,
+        mut condition: impl FnMut(&WriteState) -> bool,
+    ) -> Result<std::sync::MutexGuard<'_, WriteState>> {
+        let (lock, changed) = &*self.state;
+        let mut state = changed
+            .wait_while(
+                lock.lock().unwrap_or_else(PoisonError::into_inner),
+                |state| state.error.is_none() && condition(state),
+            )
+            .unwrap_or_else(PoisonError::into_inner);
+        match state.error.take() {
+            Some(error) => Err(error),
+            None => Ok(state),
+        }
+    }
+}
+
+impl Drop for ParallelWriter {
+    /// Nothing may still be writing into the root once the applier is gone, even after an
+    /// error.
+    fn drop(&mut self) {
+        let (lock, changed) = &*self.state;
+        drop(changed.wait_while(
+            lock.lock().unwrap_or_else(PoisonError::into_inner),
+            |state| !state.in_flight.is_empty(),
+        ));
+    }
+}
+
+/// How layers are applied, for callers that pass the settings of a conversion on to
+/// every [`LayerApplier`] (see [`LayerOptions::applier`]).
+#[derive(Debug, Clone, Default)]
+pub struct LayerOptions {
+    pub strategy: ConflictStrategy,
+    /// See [`LayerApplier::with_max_file_size`].
+    pub max_file_size: Option<u64>,
+    /// See [`LayerApplier::with_exclude`].
+    pub exclude: PathFilter,
+    /// See [`LayerApplier::with_jobs`]; layers applied to a tree (see
+    /// [`crate::tree_apply`]) don't write files and ignore it.
+    pub jobs: usize,
+    /// See [`LayerApplier::with_sparse_files`].
+    pub sparse_files: SparseFiles,
+    /// See [`LayerApplier::with_symlink_targets`].
+    pub symlink_targets: SymlinkTargets,
+}
+
+impl LayerOptions {
+    /// An applier for `root` with these options.
+    ///
+    /// # Errors
+    /// - See [`LayerApplier::new`] and [`LayerApplier::with_jobs`].
+    pub fn applier<'a>(&self, root: &Path) -> Result<LayerApplier<'a>> {
+        LayerApplier::new(root, self.strategy)?
+            .with_max_file_size(self.max_file_size)
+            .with_sparse_files(self.sparse_files)
+            .with_symlink_targets(self.symlink_targets)
+            .with_exclude(self.exclude.clone())
+            .with_jobs(self.jobs)
+    }
+}
+
+/// Applies layer entries onto a root directory with overlay semantics.
+///
+/// Feed entries with [`LayerApplier::apply`] and call [`LayerApplier::finish`] once the
+/// layer is exhausted to create deferred hardlinks and symlink fallbacks, or use
+/// [`LayerApplier::apply_archive`] for a whole archive.
+pub struct LayerApplier<'a> {
+    root: PathBuf,
+    strategy: ConflictStrategy,
+    pending_hardlinks: Vec<PendingHardlink>,
+    pending_symlinks: Vec<PendingSymlink>,
+    observer: Option<&'a mut dyn LayerObserver>,
+    max_file_size: Option<u64>,
+    sparse_files: SparseFiles,
+    symlink_targets: SymlinkTargets,
+    exclude: PathFilter,
+    estargz: Bookkeeping,
+    writer: Option<ParallelWriter>,
+}
+
+impl<'a> LayerApplier<'a> {
+    /// Creates an applier for `root`, creating the directory if needed.
+    pub fn new(root: &Path, strategy: ConflictStrategy) -> Result<Self> {
+        fs::create_dir_all(root)
+            .with_context(|| format!("Failed to create directory: {}", root.display()))?;
+        let root = root.canonicalize().with_context(|| {
+            format!("Failed to resolve extraction directory: {}", root.display())
+        })?;
+
+        Ok(Self {
+            root,
+            strategy,
+            pending_hardlinks: Vec::new(),
+            pending_symlinks: Vec::new(),
+            observer: None,
+            max_file_size: None,
+            sparse_files: SparseFiles::default(),
+            symlink_targets: SymlinkTargets::default(),
+            exclude: PathFilter::default(),
+            estargz: Bookkeeping::default(),
+            writer: None,
+        })
+    }
+
+    /// Reports every change to the root to `observer`.
+    pub fn with_observer(mut self, observer: &'a mut dyn LayerObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Writes regular files larger than `max_file_size` bytes as stubs naming their size
+    /// and SHA-256 instead of their content (see [`crate::large_files`]).
+    pub fn with_max_file_size(mut self, max_file_size: Option<u64>) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Writes sparse files with their holes and their full content, or as stubs naming
+    /// their size and SHA-256 (see [`crate::sparse`]).
+    pub fn with_sparse_files(mut self, sparse_files: SparseFiles) -> Self {
+        self.sparse_files = sparse_files;
+        self
+    }
+
+    /// Creates symlinks with relative targets as the layer has them where that's safe, for
+    /// [`SymlinkTargets::Image`]; all other targets point below the root (see
+    /// [`crate::symlinks`]).
+    pub fn with_symlink_targets(mut self, symlink_targets: SymlinkTargets) -> Self {
+        self.symlink_targets = symlink_targets;
+        self
+    }
+
+    /// Skips entries whose path matches `exclude`, as if the layer didn't contain them.
+    /// Whiteouts still apply.
+    pub fn with_exclude(mut self, exclude: PathFilter) -> Self {
+        self.exclude = exclude;
+        self
+    }
+
+    /// Treats the layer as an eStargz layer from the start, e.g. because its blob has a
+    /// footer; landmarks mark it as one otherwise (see [`crate::estargz::Bookkeeping`]).
+    pub fn with_estargz(mut self, estargz: bool) -> Self {
+        self.estargz = Bookkeeping::new(estargz);
+        self
+    }
+
+    /// Writes regular files (up to 4 MiB) on `jobs` threads while the next entries are
+    /// read; `jobs` of 0 or 1 writes everything in order on the calling thread.
+    ///
+    /// # Errors
+    /// - The threads can't be started.
+    pub fn with_jobs(mut self, jobs: usize) -> Result<Self> {
+        self.writer = if jobs > 1 {
+            Some(ParallelWriter::new(jobs)?)
+        } else {
+            None
+        };
+        Ok(self)
+    }
+
+    /// Waits for background writes; all of them with `all`, otherwise those to `path` and
+    /// its ancestors.
+    fn wait_for_writes(&self, path: &Path, all: bool) -> Result<()> {
+        match &self.writer {
+            Some(writer) if all => writer.wait_all(),
+            Some(writer) => writer.wait_for(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies every entry of `archive` and finishes the layer.
+    pub fn apply_archive<R: Read>(mut self, archive: &mut tar::Archive<R>) -> Result<()> {
+        for entry_result in archive.entries()? {
+            let mut entry = entry_result.context("Failed to read tar entry")?;
+            self.apply(&mut entry)?;
+        }
+        self.finish()
+    }
+
+    /// Applies a single entry. Hardlinks and symlinks that could not be created yet are
+    /// queued for [`LayerApplier::finish`].
+    pub fn apply<R: Read>(&mut self, entry: &mut tar::Entry<R>) -> Result<()> {
+        let pax = pax_records(entry)?;
+        let sparse = SparseEntry::detect(entry.header(), &pax)?;
+        let size = sparse.as_ref().map_or(entry.size(), |sparse| sparse.size);
+        let mut observed = match self.observer {
+            Some(_) => Some(ObservedEntry::new(entry, pax, size)?),
+            None => None,
+        };
+        let hash_content = self
+            .observer
+            .as_deref()
+            .is_some_and(|observer| observer.wants_content_hash());
+        let header = entry.header().clone();
+        let entry_type = match sparse {
+            Some(_) => tar::EntryType::Regular,
+            None => header.entry_type(),
+        };
+        let mut stub = None;
+
+        let tar_path = match sparse.as_ref().and_then(|sparse| sparse.name.clone()) {
+            Some(name) => name,
+            None => entry
+                .path()
+                .context("Failed to get entry path")?
+                .into_owned(),
+        };
+        let rel_path = normalize_tar_path(&tar_path);
+
+        // Entries like "./" or "../" point at the extraction root itself - nothing to do
+        if rel_path.as_os_str().is_empty() {
+            log::debug!("Skipping entry that resolves to the root: {tar_path:?}");
+            return Ok(());
+        }
+
+        // Check for whiteout files (overlay filesystem markers)
+        if let Some(file_name) = rel_path.file_name().and_then(|n| n.to_str()) {
+            if file_name.starts_with(".wh.") {
+                // Deletions must not race with files still being written
+                self.wait_for_writes(&self.root, true)?;
+            }
+            if file_name == ".wh..wh..opq" {
+                // Opaque directory marker - remove all contents of parent directory
+                if let Some(parent) = rel_path.parent() {
+                    let opaque_dir = self.root.join(parent);
+                    ensure_within_root(&self.root, &opaque_dir)?;
+                    if opaque_dir.exists() && opaque_dir.is_dir() {
+                        log::debug!(
+                            "Found opaque directory marker, clearing: {}",
+                            opaque_dir.display()
+                        );
+                        for entry in fs::read_dir(&opaque_dir)? {
+                            remove_path(&entry?.path())?;
+                        }
+                    }
+                    if let Some(observer) = self.observer.as_deref_mut() {
+                        observer.cleared(parent);
+                    }
+                }
+                return Ok(()); // Skip the marker file itself
+            } else if let Some(deleted_name) = file_name.strip_prefix(".wh.") {
+                // Whiteout marker - delete the target file/directory
+                // Remove ".wh." prefix
+                if deleted_name.is_empty() || deleted_name == "." || deleted_name == ".." {
+                    anyhow::bail!("Invalid whiteout entry: {}", rel_path.display());
+                }
+                if let Some(parent) = rel_path.parent() {
+                    let parent_dir = self.root.join(parent);
+                    ensure_within_root(&self.root, &parent_dir)?;
+                    let deleted_path = parent_dir.join(deleted_name);
+                    log::debug!(
+                        "Found whiteout marker, deleting: {}",
+                        deleted_path.display()
+                    );
+                    // Does not follow symlinks, so dangling ones are whited out as well
+                    remove_path(&deleted_path)?;
+                    if let Some(observer) = self.observer.as_deref_mut() {
+                        observer.removed(&parent.join(deleted_name));
+                    }
+                }
+                return Ok(()); // Skip the whiteout marker itself
+            }
+        }
+
+        if self.exclude.matches(&rel_path) {
+            log::debug!("Excluding entry: {}", rel_path.display());
+            return Ok(());
+        }
+        if self
+            .estargz
+            .skips(&rel_path, entry_type == tar::EntryType::Regular, size)
+        {
+            log::debug!("Skipping eStargz entry: {}", rel_path.display());
+            return Ok(());
+        }
+
+        let dest = self.root.join(&rel_path);
+
+        // Anything but a new file or directory may remove or link files still being
+        // written, and so may replacing a directory
+        let replaces_dir = entry_type != tar::EntryType::Directory
+            && fs::symlink_metadata(&dest).is_ok_and(|metadata| metadata.is_dir());
+        let removes = !matches!(
+            entry_type,
+            tar::EntryType::Regular | tar::EntryType::Directory
+        ) || replaces_dir;
+        self.wait_for_writes(&dest, removes)?;
+
+        // Create parent directories and ensure they're writable
+        if let Some(parent) = dest.parent() {
+            ensure_within_root(&self.root, parent)?;
+            log::debug!("Creating parent directory: {}", parent.display());
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+
+            // Verify parent exists after creation
+            if !parent.exists() {
+                anyhow::bail!(
+                    "Parent directory doesn't exist after create_dir_all: {}",
+                    parent.display()
+                );
+            }
+
+            // Always set writable permissions on parent (simple and safe)
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let perms_result = fs::set_permissions(parent, fs::Permissions::from_mode(0o755));
+                if let Err(e) = perms_result {
+                    log::warn!("Failed to set permissions on {}: {}", parent.display(), e);
+                }
+
+                // Verify parent is actually a directory
+                if !parent.is_dir() {
+                    anyhow::bail!(
+                        "Parent path exists but is not a directory: {}",
+                        parent.display()
+                    );
+                }
+            }
+        }
+
+        match entry_type {
+            tar::EntryType::Directory => {
+                apply_entry(&dest, EntryKind::Directory, self.strategy)?;
+                fs::create_dir_all(&dest)
+                    .with_context(|| format!("Failed to create directory: {}", dest.display()))?;
+
+                // Always set writable permissions on directories (0755 minimum)
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mode = header.mode().unwrap_or(0o755);
+                    // Ensure directory is at least writable/executable by owner
+                    let safe_mode = mode | 0o700;
+                    let _ = fs::set_permissions(&dest, fs::Permissions::from_mode(safe_mode));
+                }
+            }
+            tar::EntryType::Regular => {
+                // Get mode before consuming entry
+                let mode = header.mode().ok();
+
+                // Replace whatever exists at this path (overlay behavior)
+                apply_entry(&dest, EntryKind::File, self.strategy)?;
+
+                let stubbed = self.max_file_size.is_some_and(|max| size > max)
+                    || (sparse.is_some() && self.sparse_files == SparseFiles::Stub);
+                let mut sparse_reader;
+                let (content, data_size): (&mut dyn Read, _) = match &sparse {
+                    Some(sparse) => {
+                        sparse_reader = sparse.open_entry(entry)?;
+                        let data_size = sparse_reader.data_size();
+                        (&mut sparse_reader, Some(data_size))
+                    }
+                    None => (entry, None),
+                };
+                if let Some(writer) = self
+                    .writer
+                    .as_ref()
+                    .filter(|_| !stubbed && data_size.is_none() && size <= PARALLEL_WRITE_MAX)
+                {
+                    let mut data = Vec::with_capacity(size as usize);
+                    if hash_content {
+                        let mut reader = HashingReader::new(&mut *content);
+                        reader
+                            .read_to_end(&mut data)
+                            .with_context(|| format!("Failed to read file: {}", dest.display()))?;
+                        if let Some(observed) = observed.as_mut() {
+                            observed.sha256 = Some(reader.finish_hex());
+                        }
+                    } else {
+                        content
+                            .read_to_end(&mut data)
+                            .with_context(|| format!("Failed to read file: {}", dest.display()))?;
+                    }
+                    // At its real location, so a path through a symlinked parent waits
+                    // for earlier writes to the same file as well
+                    let target = dest
+                        .parent()
+                        .zip(dest.file_name())
+                        .and_then(|(parent, name)| Some(parent.canonicalize().ok()?.join(name)))
+                        .unwrap_or_else(|| dest.clone());
+                    log::debug!("Writing file in the background: {}", target.display());
+                    writer.write(target, data, mode)?;
+                } else {
+                    log::debug!("Creating file: {}", dest.display());
+                    let mut out_file = File::create(&dest).with_context(|| {
+                        let parent_info = if let Some(parent) = dest.parent() {
+                            format!(
+                                " (parent: {}, exists: {}, is_dir: {})",
+                                parent.display(),
+                                parent.exists(),
+                                parent.is_dir()
+                            )
+                        } else {
+                            String::from(" (no parent)")
+                        };
+                        format!("Failed to create file: {}{}", dest.display(), parent_info)
+                    })?;
+
+                    // Sparse files keep their holes on disk
+                    let copy = |reader: &mut dyn Read, out_file: &mut File| match data_size {
+                        Some(_) => sparse::write_sparse(reader, out_file),
+                        None => std::io::copy(reader, out_file),
+                    };
+                    if stubbed {
+                        let mut hasher = Sha256::new();
+                        std::io::copy(content, &mut hasher)
+                            .with_context(|| format!("Failed to read file: {}", dest.display()))?;
+                        let file_stub = FileStub {
+                            path: fsmeta::image_path(&rel_path),
+                            size,
+                            sha256: format!("{:x}", hasher.finalize()),
+                            data_size,
+                        };
+                        log::debug!("Writing stub for {} ({size} bytes)", file_stub.path);
+                        out_file
+                            .write_all(file_stub.render().as_bytes())
+                            .with_context(|| format!("Failed to write file: {}", dest.display()))?;
+                        stub = Some(file_stub);
+                    } else if hash_content {
+                        let mut reader = HashingReader::new(&mut *content);
+                        copy(&mut reader, &mut out_file)
+                            .with_context(|| format!("Failed to write file: {}", dest.display()))?;
+                        if let Some(observed) = observed.as_mut() {
+                            observed.sha256 = Some(reader.finish_hex());
+                        }
+                    } else {
+                        copy(content, &mut out_file)
+                            .with_context(|| format!("Failed to write file: {}", dest.display()))?;
+                    }
+
+                    // Set permissions - ensure file is at least readable by owner for git
+                    set_file_mode(&dest, mode);
+                }
+            }
+            tar::EntryType::Symlink => {
+                let link_name = header
+                    .link_name()
+                    .context("Failed to get symlink target")?
+                    .ok_or_else(|| anyhow::anyhow!("Symlink without target"))?;
+
+                // ALWAYS resolve target path relative to the (canonical) root as ABSOLUTE path.
+                // Both kinds are normalized so that `..` can never climb above the root,
+                // mirroring how container runtimes resolve links inside a rootfs.
+                let link_parent = rel_path.parent().unwrap_or(Path::new(""));
+                let absolute_target = if link_name.is_absolute() {
+                    // Absolute symlink like /usr/share/foo -> root/usr/share/foo
+                    self.root.join(normalize_tar_path(&link_name))
+                } else {
+                    // Relative symlink - resolve from the symlink's parent directory
+                    self.root
+                        .join(normalize_tar_path(&link_parent.join(&link_name)))
+                };
+
+                apply_entry(&dest, EntryKind::Symlink, self.strategy)?;
+
+                // Relative targets that stay inside the image can be kept as they are
+                // (see crate::symlinks), as long as the link's directory is no symlink
+                let keep_relative = self.symlink_targets == SymlinkTargets::Image
+                    && link_name.is_relative()
+                    && symlinks::keeps_relative(&link_name, link_parent.components().count())
+                    && dest
+                        .parent()
+                        .and_then(|parent| parent.canonicalize().ok())
+                        .is_some_and(|parent| parent == self.root.join(link_parent));
+                let disk_target = if keep_relative {
+                    link_name.to_path_buf()
+                } else {
+                    absolute_target.clone()
+                };
+
+                #[cfg(unix)]
+                {
+                    if let Err(e) = std::os::unix::fs::symlink(&disk_target, &dest) {
+                        log::debug!(
+                            "Failed to create symlink {} -> {}: {}. Will try to copy target...",
+                            dest.display(),
+                            absolute_target.display(),
+                            e
+                        );
+
+                        // Save for retry after all files are extracted
+                        self.pending_symlinks.push(PendingSymlink {
+                            dest,
+                            target: absolute_target,
+                        });
+                    }
+                }
+
+                #[cfg(not(unix))]
+                {
+                    log::warn!(
+                        "Symlink support not implemented on this platform: {}",
+                        dest.display()
+                    );
+                }
+            }
+            tar::EntryType::Link => {
+                // Hardlink - save for second pass
+                let link_name = header
+                    .link_name()
+                    .context("Failed to get hardlink target")?
+                    .ok_or_else(|| anyhow::anyhow!("Hardlink without target"))?;
+
+                let target_rel = normalize_tar_path(&link_name);
+                let target = self.root.join(&target_rel);
+                ensure_within_root(&self.root, &target)?;
+                apply_entry(&dest, EntryKind::Hardlink, self.strategy)?;
+
+                self.pending_hardlinks
+                    .push(PendingHardlink { dest, target });
+            }
+            tar::EntryType::Char | tar::EntryType::Block | tar::EntryType::Fifo => {
+                // Not extracted, but the node still takes the place of the previous entry
+                log::debug!(
+                    "Not extracting special file: {entry_type:?} {}",
+                    dest.display()
+                );
+                remove_path(&dest)?;
+            }
+            _ => {
+                log::debug!("Skipping unsupported entry type: {entry_type:?}");
+            }
+        }
+
+        if let (Some(observer), Some(mut observed)) = (self.observer.as_deref_mut(), observed) {
+            if let Some(stub) = &stub {
+                observed.sha256 = Some(stub.sha256.clone());
+            }
+            observer.entry(&rel_path, &observed);
+            if let Some(stub) = &stub {
+                observer.stubbed(stub);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates queued hardlinks (retrying once, as targets may appear later in the
+    /// layer) and copies symlink targets where symlinks could not be created.
+    pub fn finish(self) -> Result<()> {
+        // Links may point at files still being written
+        self.wait_for_writes(&self.root, true)?;
+
+        // Second pass: create hardlinks (with retry queue for missing targets)
+        let mut failed_hardlinks = Vec::new();
+        for hardlink in self.pending_hardlinks {
+            let linked = ensure_within_root(&self.root, &hardlink.target)
+                .and_then(|_| try_link_or_copy(&hardlink.target, &hardlink.dest));
+            if let Err(e) = linked {
+                log::debug!(
+                    "Hardlink target not found yet, will retry: {} -> {}: {}",
+                    hardlink.dest.display(),
+                    hardlink.target.display(),
+                    e
+                );
+                // Add to dead letter queue - target might be extracted later
+                failed_hardlinks.push(hardlink);
+            }
+        }
+
+        // Third pass: retry failed hardlinks (targets might now exist)
+        for hardlink in failed_hardlinks {
+            let linked = ensure_within_root(&self.root, &hardlink.target)
+                .and_then(|_| try_link_or_copy(&hardlink.target, &hardlink.dest));
+            if let Err(e) = linked {
+                log::warn!(
+                    "Skipping broken hardlink (target still missing): {} -> {}: {}",
+                    hardlink.dest.display(),
+                    hardlink.target.display(),
+                    e
+                );
+                // Skip this hardlink - the target truly doesn't exist
+            }
+        }
+
+        // Fourth pass: retry failed symlinks (copy target files)
+        for symlink in self.pending_symlinks {
+            if let Err(e) = ensure_within_root(&self.root, &symlink.target) {
+                log::warn!("Not copying symlink target: {e}");
+                continue;
+            }
+            if symlink.target.exists() {
+                log::debug!(
+                    "Retrying symlink by copying: {} -> {}",
+                    symlink.target.display(),
+                    symlink.dest.display()
+                );
+                if let Err(e) = fs::copy(&symlink.target, &symlink.dest) {
+                    log::warn!(
+                        "Failed to copy symlink target {} -> {}: {}. Skipping.",
+                        symlink.target.display(),
+                        symlink.dest.display(),
+                        e
+                    );
+                }
+            } else {
+                log::debug!(
+                    "Symlink target still does not exist: {} -> {}. Skipping.",
+                    symlink.dest.display(),
+                    symlink.target.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    /// Appends an entry with a raw (unvalidated) path
+    fn append_raw(
+        builder: &mut tar::Builder<Vec<u8>>,
+        path: &str,
+        entry_type: tar::EntryType,
+        link_name: Option<&str>,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        if let Some(link_name) = link_name {
+            header.set_link_name(link_name).unwrap();
+        }
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    /// Builds a layer in memory and applies it onto `root`
+    fn apply_layer(
+        root: &Path,
+        strategy: ConflictStrategy,
+        build: impl FnOnce(&mut tar::Builder<Vec<u8>>),
+    ) -> Result<()> {
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        let mut archive = tar::Archive::new(Cursor::new(builder.into_inner().unwrap()));
+        LayerApplier::new(root, strategy)?.apply_archive(&mut archive)
+    }
+
+    fn layer(root: &Path, build: impl FnOnce(&mut tar::Builder<Vec<u8>>)) -> Result<()> {
+        apply_layer(root, ConflictStrategy::default(), build)
+    }
+
+    #[test]
+    fn test_regular_files_and_directories() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        layer(&rootfs, |b| {
+            append_raw(b, "etc/", tar::EntryType::Directory, None, b"");
+            append_raw(b, "etc/hostname", tar::EntryType::Regular, None, b"box");
+            // Parent directories are created even without an explicit entry
+            append_raw(b, "usr/bin/tool", tar::EntryType::Regular, None, b"bin");
+        })
+        .unwrap();
+
+        assert!(rootfs.join("etc").is_dir());
+        assert_eq!(
+            fs::read_to_string(rootfs.join("etc/hostname")).unwrap(),
+            "box"
+        );
+        assert_eq!(
+            fs::read_to_string(rootfs.join("usr/bin/tool")).unwrap(),
+            "bin"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_file_is_made_readable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        layer(&rootfs, |b| {
+            let mut header = tar::Header::new_gnu();
+            header.set_path("shadow").unwrap();
+            header.set_size(1);
+            header.set_mode(0o000);
+            header.set_cksum();
+            b.append(&header, &b"x"[..]).unwrap();
+        })
+        .unwrap();
+
+        let mode = fs::metadata(rootfs.join("shadow"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o400, 0o400);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_resolve_inside_root() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        layer(&rootfs, |b| {
+            append_raw(b, "usr/lib/libc.so", tar::EntryType::Regular, None, b"libc");
+            append_raw(b, "lib", tar::EntryType::Symlink, Some("usr/lib"), b"");
+            append_raw(
+                b,
+                "abs",
+                tar::EntryType::Symlink,
+                Some("/usr/lib/libc.so"),
+                b"",
+            );
+        })
+        .unwrap();
+
+        let root = rootfs.canonicalize().unwrap();
+        assert_eq!(
+            fs::read_link(rootfs.join("lib")).unwrap(),
+            root.join("usr/lib")
+        );
+        assert_eq!(
+            fs::read_link(rootfs.join("abs")).unwrap(),
+            root.join("usr/lib/libc.so")
+        );
+        assert_eq!(
+            fs::read_to_string(rootfs.join("lib/libc.so")).unwrap(),
+            "libc"
+        );
+    }
+
+    #[test]
+    fn test_relative_symlinks_kept_for_in_image_targets() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        let mut builder = tar::Builder::new(Vec::new());
+        let b = &mut builder;
+        append_raw(b, "usr/lib/libc.so", tar::EntryType::Regular, None, b"libc");
+        append_raw(b, "lib", tar::EntryType::Symlink, Some("usr/lib"), b"");
+        append_raw(b, "lib64", tar::EntryType::Symlink, Some("/usr/lib"), b"");
+        append_raw(b, "usr/lib/up", tar::EntryType::Symlink, Some("../.."), b"");
+        // Its directory is a symlink (lib -> usr/lib), so the target is made absolute
+        append_raw(
+            b,
+            "lib/libc.so.6",
+            tar::EntryType::Symlink,
+            Some("libc.so"),
+            b"",
+        );
+        let mut archive = tar::Archive::new(Cursor::new(builder.into_inner().unwrap()));
+        LayerApplier::new(&rootfs, ConflictStrategy::default())
+            .unwrap()
+            .with_symlink_targets(SymlinkTargets::Image)
+            .apply_archive(&mut archive)
+            .unwrap();
+
+        let root = rootfs.canonicalize().unwrap();
+        assert_eq!(
+            fs::read_link(rootfs.join("lib")).unwrap(),
+            Path::new("usr/lib")
+        );
+        assert_eq!(
+            fs::read_link(rootfs.join("lib64")).unwrap(),
+            root.join("usr/lib")
+        );
+        assert_eq!(
+            fs::read_link(rootfs.join("usr/lib/up")).unwrap(),
+            Path::new("../..")
+        );
+        assert_eq!(
+            fs::read_link(rootfs.join("usr/lib/libc.so.6")).unwrap(),
+            root.join("lib/libc.so")
+        );
+    }
+
+    #[test]
+    fn test_hardlinks_are_created_after_their_targets() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        layer(&rootfs, |b| {
+            // Link appears before its target in the archive
+            append_raw(b, "bin/sh", tar::EntryType::Link, Some("bin/bash"), b"");
+            append_raw(b, "bin/bash", tar::EntryType::Regular, None, b"bash");
+            append_raw(
+                b,
+                "bin/broken",
+                tar::EntryType::Link,
+                Some("bin/missing"),
+                b"",
+            );
+        })
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(rootfs.join("bin/sh")).unwrap(), "bash");
+        assert!(!rootfs.join("bin/broken").exists());
+    }
+
+    #[test]
+    fn test_whiteouts_and_opaque_directories() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        layer(&rootfs, |b| {
+            append_raw(b, "a/keep", tar::EntryType::Regular, None, b"");
+            append_raw(b, "a/gone", tar::EntryType::Regular, None, b"");
+            append_raw(b, "b/old/nested", tar::EntryType::Regular, None, b"");
+        })
+        .unwrap();
+
+        layer(&rootfs, |b| {
+            append_raw(b, "a/.wh.gone", tar::EntryType::Regular, None, b"");
+            append_raw(b, "b/.wh..wh..opq", tar::EntryType::Regular, None, b"");
+            append_raw(b, "b/new", tar::EntryType::Regular, None, b"");
+        })
+        .unwrap();
+
+        assert!(rootfs.join("a/keep").exists());
+        assert!(!rootfs.join("a/gone").exists());
+        assert!(!rootfs.join("a/.wh.gone").exists());
+        assert!(!rootfs.join("b/old").exists());
+        assert!(rootfs.join("b/new").exists());
+        assert!(!rootfs.join("b/.wh..wh..opq").exists());
+    }
+
+    #[test]
+    fn test_unsupported_entries_are_skipped() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        fs::create_dir_all(rootfs.join("dev")).unwrap();
+        fs::write(rootfs.join("dev/null"), "placeholder").unwrap();
+        layer(&rootfs, |b| {
+            append_raw(b, "dev/null", tar::EntryType::Char, None, b"");
+            append_raw(b, "run/fifo", tar::EntryType::Fifo, None, b"");
+            append_raw(b, "./", tar::EntryType::Directory, None, b"");
+        })
+        .unwrap();
+
+        assert!(!rootfs.join("dev/null").exists());
+        assert!(!rootfs.join("run/fifo").exists());
+    }
+
+    #[test]
+    fn test_directory_replaced_by_file_and_back() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+
+        layer(&rootfs, |b| {
+            append_raw(b, "a/", tar::EntryType::Directory, None, b"");
+            append_raw(b, "a/child", tar::EntryType::Regular, None, b"x");
+        })
+        .unwrap();
+
+        layer(&rootfs, |b| {
+            append_raw(b, "a", tar::EntryType::Regular, None, b"file");
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(rootfs.join("a")).unwrap(), "file");
+
+        layer(&rootfs, |b| {
+            append_raw(b, "a/", tar::EntryType::Directory, None, b"");
+        })
+        .unwrap();
+        assert!(rootfs.join("a").is_dir());
+        assert!(!rootfs.join("a/child").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_replaced_by_symlink() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+
+        layer(&rootfs, |b| {
+            append_raw(b, "target", tar::EntryType::Regular, None, b"target");
+            append_raw(b, "link", tar::EntryType::Regular, None, b"old");
+        })
+        .unwrap();
+
+        layer(&rootfs, |b| {
+            append_raw(b, "link", tar::EntryType::Symlink, Some("target"), b"");
+        })
+        .unwrap();
+
+        assert!(fs::symlink_metadata(rootfs.join("link"))
+            .unwrap()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(rootfs.join("link")).unwrap(), "target");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_replaced_by_directory() {
+        let base = |b: &mut tar::Builder<Vec<u8>>| {
+            append_raw(b, "usr/lib/", tar::EntryType::Directory, None, b"");
+            append_raw(b, "lib", tar::EntryType::Symlink, Some("usr/lib"), b"");
+        };
+        let update = |b: &mut tar::Builder<Vec<u8>>| {
+            append_raw(b, "lib/", tar::EntryType::Directory, None, b"");
+            append_raw(b, "lib/libc.so", tar::EntryType::Regular, None, b"x");
+        };
+
+        // Replace: the directory entry wins over the symlink
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        layer(&rootfs, base).unwrap();
+        layer(&rootfs, update).unwrap();
+        assert!(!fs::symlink_metadata(rootfs.join("lib"))
+            .unwrap()
+            .is_symlink());
+        assert!(rootfs.join("lib/libc.so").exists());
+        assert!(!rootfs.join("usr/lib/libc.so").exists());
+
+        // FollowSymlinks: merge through the symlink
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        layer(&rootfs, base).unwrap();
+        apply_layer(&rootfs, ConflictStrategy::FollowSymlinks, update).unwrap();
+        assert!(fs::symlink_metadata(rootfs.join("lib"))
+            .unwrap()
+            .is_symlink());
+        assert!(rootfs.join("usr/lib/libc.so").exists());
+    }
+
+    #[test]
+    fn test_abort_strategy_rejects_type_changes() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        fs::create_dir_all(rootfs.join("a")).unwrap();
+        fs::write(rootfs.join("b"), "old").unwrap();
+
+        // Same type is fine
+        assert_eq!(
+            apply_entry(
+                &rootfs.join("a"),
+                EntryKind::Directory,
+                ConflictStrategy::Abort
+            )
+            .unwrap(),
+            EntryAction::Merge
+        );
+        assert_eq!(
+            apply_entry(
+                &rootfs.join("b"),
+                EntryKind::Hardlink,
+                ConflictStrategy::Abort
+            )
+            .unwrap(),
+            EntryAction::Create
+        );
+        assert!(!rootfs.join("b").exists());
+
+        // Type change is an error and leaves the entry alone
+        assert!(apply_entry(&rootfs.join("a"), EntryKind::File, ConflictStrategy::Abort).is_err());
+        assert!(rootfs.join("a").is_dir());
+    }
+
+    /// Paths below `root` with their type, content and mode
+    fn snapshot(root: &Path) -> Vec<(PathBuf, String, u32)> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut entries = Vec::new();
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let metadata = fs::symlink_metadata(&path).unwrap();
+                let content = if metadata.is_symlink() {
+                    format!("-> {}", fs::read_link(&path).unwrap().display())
+                } else if metadata.is_dir() {
+                    dirs.push(path.clone());
+                    "dir".to_string()
+                } else {
+                    fs::read_to_string(&path).unwrap()
+                };
+                let relative = path.strip_prefix(root).unwrap().to_path_buf();
+                entries.push((relative, content, metadata.permissions().mode()));
+            }
+        }
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn test_parallel_writes_match_sequential() {
+        let build = |b: &mut tar::Builder<Vec<u8>>| {
+            for i in 0..200 {
+                let path = format!("usr/share/{}/file{i}", i % 7);
+                append_raw(b, &path, tar::EntryType::Regular, None, path.as_bytes());
+            }
+            // Written twice, the second one wins
+            append_raw(b, "etc/config", tar::EntryType::Regular, None, b"first");
+            append_raw(b, "etc/config", tar::EntryType::Regular, None, b"second");
+            // Through a symlinked parent
+            append_raw(b, "lib", tar::EntryType::Symlink, Some("usr/share/1"), b"");
+            append_raw(b, "lib/file1", tar::EntryType::Regular, None, b"via lib");
+            // Linked, deleted and replaced right after being written
+            append_raw(b, "bin/tool", tar::EntryType::Regular, None, b"tool");
+            append_raw(b, "bin/alias", tar::EntryType::Link, Some("bin/tool"), b"");
+            append_raw(b, "tmp/gone", tar::EntryType::Regular, None, b"gone");
+            append_raw(b, "tmp/.wh.gone", tar::EntryType::Regular, None, b"");
+            append_raw(b, "usr/share/2", tar::EntryType::Regular, None, b"no dir");
+        };
+        let temp_dir = tempdir().unwrap();
+        let sequential = temp_dir.path().join("sequential");
+        let parallel = temp_dir.path().join("parallel");
+        layer(&sequential, build).unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        let mut archive = tar::Archive::new(Cursor::new(builder.into_inner().unwrap()));
+        LayerApplier::new(&parallel, ConflictStrategy::default())
+            .unwrap()
+            .with_jobs(4)
+            .unwrap()
+            .apply_archive(&mut archive)
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(sequential.join("usr/share/1/file1")).unwrap(),
+            "via lib"
+        );
+        assert_eq!(
+            snapshot(&parallel)
+                .into_iter()
+                .filter(|(path, ..)| path != Path::new("lib"))
+                .collect::<Vec<_>>(),
+            snapshot(&sequential)
+                .into_iter()
+                .filter(|(path, ..)| path != Path::new("lib"))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// A layer with `disk.img` (12 KiB, "data" at 8 KiB) as GNU sparse entry and
+    /// `vm/disk.img` (the same, PAX 1.0 format)
+    fn sparse_layer() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let octal = |field: &mut [u8; 12], value: u64| {
+            field.copy_from_slice(format!("{value:011o}\0").as_bytes());
+        };
+        let mut header = tar::Header::new_gnu();
+        header.set_path("disk.img").unwrap();
+        header.set_entry_type(tar::EntryType::GNUSparse);
+        header.set_mode(0o644);
+        header.set_size(4);
+        let gnu = header.as_gnu_mut().unwrap();
+        octal(&mut gnu.sparse[0].offset, 8192);
+        octal(&mut gnu.sparse[0].numbytes, 4);
+        // GNU tar ends the map with an empty block at the end of the file
+        octal(&mut gnu.sparse[1].offset, 12288);
+        octal(&mut gnu.sparse[1].numbytes, 0);
+        octal(&mut gnu.realsize, 12288);
+        header.set_cksum();
+        builder.append(&header, &b"data"[..]).unwrap();
+
+        builder
+            .append_pax_extensions([
+                ("GNU.sparse.major", &b"1"[..]),
+                ("GNU.sparse.minor", &b"0"[..]),
+                ("GNU.sparse.name", &b"vm/disk.img"[..]),
+                ("GNU.sparse.realsize", &b"12288"[..]),
+            ])
+            .unwrap();
+        let mut data = b"1\n8192\n4\n".to_vec();
+        data.resize(512, 0);
+        data.extend_from_slice(b"data");
+        let mut header = tar::Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "vm/GNUSparseFile.0/disk.img", &data[..])
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_sparse_files() {
+        let mut expected = vec![0u8; 12288];
+        expected[8192..8196].copy_from_slice(b"data");
+
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        LayerApplier::new(&rootfs, ConflictStrategy::default())
+            .unwrap()
+            .apply_archive(&mut tar::Archive::new(&sparse_layer()[..]))
+            .unwrap();
+        assert_eq!(fs::read(rootfs.join("disk.img")).unwrap(), expected);
+        assert_eq!(fs::read(rootfs.join("vm/disk.img")).unwrap(), expected);
+        assert!(!rootfs.join("vm/GNUSparseFile.0").exists());
+
+        let rootfs = temp_dir.path().join("stubbed");
+        let mut large_files = crate::large_files::LargeFiles::new();
+        LayerApplier::new(&rootfs, ConflictStrategy::default())
+            .unwrap()
+            .with_sparse_files(SparseFiles::Stub)
+            .with_observer(&mut large_files)
+            .apply_archive(&mut tar::Archive::new(&sparse_layer()[..]))
+            .unwrap();
+        let sha256 = format!("{:x}", Sha256::digest(&expected));
+        for path in ["/disk.img", "/vm/disk.img"] {
+            let stub = &large_files.entries[path];
+            assert_eq!((stub.size, stub.data_size), (12288, Some(4)));
+            assert_eq!(stub.sha256, sha256);
+            assert_eq!(
+                fs::read_to_string(rootfs.join(&path[1..])).unwrap(),
+                stub.render()
+            );
+        }
+    }
+}
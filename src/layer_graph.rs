@@ -0,0 +1,174 @@
+//! Mermaid diagram of the layer chain in `Image.md`.
+//!
+//! With [`crate::processor::ImageProcessor::with_layer_graph`] the `Image.md` of the metadata
+//! commit ends with a `## Layer Graph` section that GitHub and GitLab render as a diagram:
+//! one node per layer commit with its command and the bytes it added (see
+//! [`crate::layer_stats`]), and a dashed edge from the last layer shared with every other
+//! image branch of the repository to a node for that branch:
+//!
+//! ````text
+//! ```mermaid
+//! graph TD
+//!     L1["1: ADD rootfs.tar /<br/>72.4 MiB"]
+//!     L2["2: RUN apt-get update<br/>41.0 MiB"]
+//!     L1 --> L2
+//!     B1(["nginx#35;1.25#35;linux-amd64#35;3b25b682ea82<br/>shares 2 layers"])
+//!     L2 -.-> B1
+//! ```
+//! ````
+//!
+//! Only branches that exist when the image is converted are shown; their own graphs aren't
+//! updated. The section is ignored when `Image.md` is read back.
+
+use crate::disk_space::format_bytes;
+use crate::git::GitRepo;
+use crate::image_metadata::ImageMetadata;
+use crate::layer_stats::LayerStatsReport;
+use crate::query::image_branches;
+use crate::trailers;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+/// Heading of the section.
+pub const LAYER_GRAPH_HEADING: &str = "## Layer Graph";
+/// Commands are cut to this many characters in the nodes.
+const MAX_COMMAND: usize = 48;
+
+/// The `## Layer Graph` section for `branch`, whose layers are described by `metadata`;
+/// `None` if it has no layer commits (`--squash`).
+///
+/// # Errors
+/// - `branch` doesn't exist, or Git failures.
+pub fn render(repo: &GitRepo, branch: &str, metadata: &ImageMetadata) -> Result<Option<String>> {
+    let mut layers = Vec::new();
+    for commit in repo.get_branch_commits(branch)? {
+        if let Some(layer) = trailers::parse(&repo.commit_message(commit)?)? {
+            layers.push((layer.index, commit));
+        }
+    }
+    if layers.is_empty() {
+        return Ok(None);
+    }
+    let stats = LayerStatsReport::collect(repo, branch, metadata)?;
+
+    let mut graph = format!("{LAYER_GRAPH_HEADING}\n\n```mermaid\ngraph TD\n");
+    for (index, _) in &layers {
+        let layer = stats.layers.iter().find(|layer| layer.index == *index);
+        let command = layer.map_or("", |layer| layer.command.as_str());
+        let command = match command.char_indices().nth(MAX_COMMAND) {
+            Some((end, _)) => format!("{}…", &command[..end]),
+            None => command.to_string(),
+        };
+        graph.push_str(&format!(
+            "    L{index}[\"{index}: {}<br/>{}\"]\n",
+            escape(&command),
+            format_bytes(layer.map_or(0, |layer| layer.bytes_added))
+        ));
+    }
+    for pair in layers.windows(2) {
+        graph.push_str(&format!("    L{} --> L{}\n", pair[0].0, pair[1].0));
+    }
+
+    let mut shared = 0;
+    for other in image_branches(repo, None)? {
+        if other == branch {
+            continue;
+        }
+        let commits: HashSet<_> = repo.get_branch_commits(&other)?.into_iter().collect();
+        let common = layers
+            .iter()
+            .take_while(|(_, commit)| commits.contains(commit))
+            .count();
+        if common == 0 {
+            continue;
+        }
+        shared += 1;
+        let plural = if common == 1 { "" } else { "s" };
+        graph.push_str(&format!(
+            "    B{shared}([\"{}<br/>shares {common} layer{plural}\"])\n    L{} -.-> B{shared}\n",
+            escape(&other),
+            layers[common - 1].0
+        ));
+    }
+    graph.push_str("```\n");
+    Ok(Some(graph))
+}
+
+/// Appends the section [`render`]s for `branch` to the `Image.md` at `path`.
+///
+/// # Errors
+/// - See [`render`]; write failures.
+pub fn append(repo: &GitRepo, branch: &str, metadata: &ImageMetadata, path: &Path) -> Result<()> {
+    let Some(graph) = render(repo, branch, metadata)? else {
+        return Ok(());
+    };
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.write_all(format!("\n{graph}").as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// `text` usable in a quoted Mermaid label: quotes and `#` become entity codes.
+fn escape(text: &str) -> String {
+    text.replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_metadata::LayerDigest;
+    use crate::trailers::LayerTrailers;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_render() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("app#1")).unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::write(rootfs.join("base"), "1234").unwrap();
+        repo.commit_layer("ADD", &LayerTrailers::new(1, "sha256:1"))
+            .unwrap();
+        let base = repo.get_branch_commits("app#1").unwrap()[0];
+        fs::write(rootfs.join("app"), "12").unwrap();
+        repo.commit_layer("COPY", &LayerTrailers::new(2, "sha256:2"))
+            .unwrap();
+        repo.create_branch("app#2", Some(base)).unwrap();
+        repo.create_branch("other", None).unwrap();
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::write(rootfs.join("other"), "1").unwrap();
+        repo.commit_all_changes("unrelated").unwrap();
+
+        let mut metadata = ImageMetadata::new(None, None);
+        for command in ["ADD rootfs.tar /", "COPY \"app\" /"] {
+            metadata.layer_digests.push(LayerDigest {
+                digest: String::new(),
+                command: command.to_string(),
+                created: String::new(),
+                is_empty: false,
+                comment: None,
+                verified: None,
+            });
+        }
+        let graph = render(&repo, "app#1", &metadata).unwrap().unwrap();
+        assert_eq!(
+            graph,
+            "## Layer Graph\n\n```mermaid\ngraph TD\n\
+             \x20   L1[\"1: ADD rootfs.tar /<br/>4 B\"]\n\
+             \x20   L2[\"2: COPY #quot;app#quot; /<br/>2 B\"]\n\
+             \x20   L1 --> L2\n\
+             \x20   B1([\"app#35;2<br/>shares 1 layer\"])\n\
+             \x20   L1 -.-> B1\n\
+             ```\n"
+        );
+        assert!(render(&repo, "other", &metadata).unwrap().is_none());
+    }
+}
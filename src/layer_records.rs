@@ -0,0 +1,341 @@
+//! One metadata file per layer: `layers/0001.json`, `layers/0002.json`, ...
+//!
+//! Layer commits record the layer chain so far (digest, command, created, empty, comment,
+//! verification), which [`crate::successor_navigator`] reads to find branch points. Each
+//! layer commit adds the file of its own layer and leaves the others alone, so its
+//! metadata diff is that one file:
+//!
+//! ```json
+//! {
+//!   "digest": "sha256:…",
+//!   "command": "RUN apt-get update",
+//!   "created": "2024-01-01T00:00:00+00:00",
+//!   "is_empty": false,
+//!   "comment": null,
+//!   "verified": true
+//! }
+//! ```
+//!
+//! The full report (`Image.md`/`Image.json`, see [`crate::image_metadata`]) is only
+//! written by the metadata commit at the tip, which keeps the files. Layer commits of
+//! earlier versions carry `Image.md` with only the layer chain instead;
+//! [`crate::image_metadata::ImageMetadata::read_from_commit`] reads both.
+//!
+//! Branch point lookups go by [`read_chain`], which prefers these files over the report:
+//! they are only written by oci2git, while a hand-edited `Image.md` may no longer parse,
+//! or parse into a different chain.
+//!
+//! With [`LayerRecords::Notes`] the worktree stays free of these files: each layer commit
+//! gets the same JSON object as a `git note` in [`LAYER_NOTES_REF`] instead, and the chain
+//! at a commit is made of the notes of its first-parent ancestors
+//! (`git log --notes=oci2git` shows them). Branches of the two kinds don't share layer
+//! commits.
+
+use crate::digest_tracker::LayerDigest;
+use crate::git::GitRepo;
+use crate::image_metadata::ImageMetadata;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Directory holding the layer files, relative to the repository root.
+pub const LAYERS_DIR: &str = "layers";
+
+/// Notes ref holding the layer entries of [`LayerRecords::Notes`].
+pub const LAYER_NOTES_REF: &str = "refs/notes/oci2git";
+
+/// Where layer commits record the layer chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LayerRecords {
+    /// Files in [`LAYERS_DIR`]
+    #[default]
+    Files,
+    /// A note per layer commit in [`LAYER_NOTES_REF`]
+    Notes,
+}
+
+impl LayerRecords {
+    /// How `commit` records the layer chain; `None` for commits with neither files nor a
+    /// note, like those of earlier versions.
+    ///
+    /// # Errors
+    /// - The commit can't be read.
+    pub(crate) fn of_commit(repo: &GitRepo, commit: git2::Oid) -> Result<Option<Self>> {
+        let tree = repo.repo.find_commit(commit)?.tree()?;
+        if tree.get_path(Path::new(LAYERS_DIR)).is_ok() {
+            Ok(Some(Self::Files))
+        } else if repo.read_note(LAYER_NOTES_REF, commit).is_some() {
+            Ok(Some(Self::Notes))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Before a layer commit: writes the files of `layers`, the chain up to the layer being
+    /// committed, with [`LayerRecords::Files`].
+    pub(crate) fn save(self, repo_dir: &Path, layers: &[LayerDigest]) -> Result<()> {
+        match self {
+            Self::Files => save(repo_dir, layers),
+            Self::Notes => Ok(()),
+        }
+    }
+
+    /// After a layer commit: attaches the last entry of `layers`, the layer just
+    /// committed, to the new `HEAD` with [`LayerRecords::Notes`].
+    pub(crate) fn annotate(self, repo: &GitRepo, layers: &[LayerDigest]) -> Result<()> {
+        match (self, layers.last()) {
+            (Self::Files, _) | (Self::Notes, None) => Ok(()),
+            (Self::Notes, Some(layer)) => {
+                let head = repo.repo.head()?.peel_to_commit()?.id();
+                let mut note = serde_json::to_string_pretty(layer)?;
+                note.push('\n');
+                repo.set_note(LAYER_NOTES_REF, head, &note)
+            }
+        }
+    }
+}
+
+/// Name of the file of the layer at 1-based `index` in [`LAYERS_DIR`].
+pub fn file_name(index: usize) -> String {
+    format!("{index:04}.json")
+}
+
+/// Writes one file per entry of `layers` below [`LAYERS_DIR`] in `repo_dir`, and removes
+/// files of layers beyond them. Files whose content is already right stay untouched.
+///
+/// # Errors
+/// - A file can't be written or removed.
+pub fn save(repo_dir: &Path, layers: &[LayerDigest]) -> Result<()> {
+    let dir = repo_dir.join(LAYERS_DIR);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for (position, layer) in layers.iter().enumerate() {
+        let path = dir.join(file_name(position + 1));
+        let mut content = serde_json::to_string_pretty(layer)?;
+        content.push('\n');
+        if fs::read_to_string(&path).ok().as_deref() != Some(content.as_str()) {
+            fs::write(&path, content)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+    for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let beyond = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_suffix(".json"))
+            .and_then(|index| index.parse::<usize>().ok())
+            .is_some_and(|index| index > layers.len());
+        if beyond {
+            fs::remove_file(entry.path())
+                .with_context(|| format!("Failed to remove {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// The layer chain recorded in [`LAYERS_DIR`] at `commit`, `None` if the commit has no
+/// such directory.
+///
+/// # Errors
+/// - The commit can't be read, or a file can't be parsed.
+pub fn read_from_commit(repo: &GitRepo, commit: git2::Oid) -> Result<Option<Vec<LayerDigest>>> {
+    let tree = repo.repo.find_commit(commit)?.tree()?;
+    let Ok(entry) = tree.get_path(Path::new(LAYERS_DIR)) else {
+        return Ok(None);
+    };
+    let Ok(dir) = repo.repo.find_tree(entry.id()) else {
+        return Ok(None);
+    };
+    let mut files: Vec<(usize, git2::Oid)> = dir
+        .iter()
+        .filter_map(|entry| {
+            let index = entry.name()?.strip_suffix(".json")?.parse().ok()?;
+            Some((index, entry.id()))
+        })
+        .collect();
+    files.sort();
+    files
+        .into_iter()
+        .map(|(index, blob)| {
+            let blob = repo.repo.find_blob(blob)?;
+            serde_json::from_slice(blob.content()).with_context(|| {
+                format!(
+                    "Failed to parse {LAYERS_DIR}/{} of commit {commit}",
+                    file_name(index)
+                )
+            })
+        })
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+/// The layer chain in the notes of `commit` and its first-parent ancestors, oldest first;
+/// `None` if none of them has a note.
+///
+/// # Errors
+/// - A commit can't be read, or a note can't be parsed.
+pub fn read_notes(repo: &GitRepo, commit: git2::Oid) -> Result<Option<Vec<LayerDigest>>> {
+    let mut layers = Vec::new();
+    let mut next = Some(repo.repo.find_commit(commit)?);
+    while let Some(commit) = next {
+        if let Some(note) = repo.read_note(LAYER_NOTES_REF, commit.id()) {
+            let layer = serde_json::from_str(&note).with_context(|| {
+                format!(
+                    "Failed to parse the {LAYER_NOTES_REF} note of commit {}",
+                    commit.id()
+                )
+            })?;
+            layers.push(layer);
+        }
+        next = commit.parents().next();
+    }
+    if layers.is_empty() {
+        return Ok(None);
+    }
+    layers.reverse();
+    Ok(Some(layers))
+}
+
+/// Attaches the [`LAYER_NOTES_REF`] notes of `originals` to their `copies` at the same
+/// positions, e.g. after replaying commits elsewhere.
+///
+/// # Errors
+/// - A note can't be written.
+pub fn copy_notes(repo: &GitRepo, originals: &[git2::Oid], copies: &[git2::Oid]) -> Result<()> {
+    for (original, copy) in originals.iter().zip(copies) {
+        if let Some(note) = repo.read_note(LAYER_NOTES_REF, *original) {
+            repo.set_note(LAYER_NOTES_REF, *copy, &note)?;
+        }
+    }
+    Ok(())
+}
+
+/// The layer chain recorded at `commit`, as branch point lookups see it: [`LAYERS_DIR`]
+/// if the commit has it, else the notes ([`read_notes`]), else the layer history of
+/// `Image.json`/`Image.md` (commits of earlier versions). A report that can't be parsed, e.g. after a hand edit, counts as no
+/// chain, so the commit is just not matched; `None` if there is no chain.
+///
+/// # Errors
+/// - The commit can't be read, or a file in [`LAYERS_DIR`] or a note can't be parsed.
+pub fn read_chain(repo: &GitRepo, commit: git2::Oid) -> Result<Option<Vec<LayerDigest>>> {
+    if let Some(layers) = read_from_commit(repo, commit)? {
+        return Ok(Some(layers));
+    }
+    if let Some(layers) = read_notes(repo, commit)? {
+        return Ok(Some(layers));
+    }
+    match ImageMetadata::read_from_commit(repo, commit) {
+        Ok(metadata) => Ok(metadata.map(|metadata| metadata.layer_digests)),
+        Err(err) => {
+            tracing::warn!("Ignoring the layer history of commit {commit}: {err:#}");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_metadata::{IMAGE_JSON, IMAGE_MD};
+    use tempfile::tempdir;
+
+    fn layer(digest: &str) -> LayerDigest {
+        LayerDigest {
+            digest: digest.to_string(),
+            command: "RUN true".to_string(),
+            created: "2024-01-01T00:00:00+00:00".to_string(),
+            is_empty: false,
+            comment: None,
+            verified: Some(true),
+        }
+    }
+
+    #[test]
+    fn test_save_and_read_from_commit() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("main")).unwrap();
+        let layers: Vec<_> = (1..=11).map(|i| layer(&format!("sha256:{i}"))).collect();
+
+        save(temp_dir.path(), &layers[..2]).unwrap();
+        repo.commit_all_changes("two").unwrap();
+        let two = repo.repo.head().unwrap().target().unwrap();
+        save(temp_dir.path(), &layers).unwrap();
+        repo.commit_all_changes("eleven").unwrap();
+        let eleven = repo.repo.head().unwrap().target().unwrap();
+
+        assert!(temp_dir.path().join("layers/0011.json").is_file());
+        assert_eq!(read_from_commit(&repo, two).unwrap().unwrap(), layers[..2]);
+        assert_eq!(read_from_commit(&repo, eleven).unwrap().unwrap(), layers);
+
+        // Back to fewer layers
+        save(temp_dir.path(), &layers[..1]).unwrap();
+        assert!(!temp_dir.path().join("layers/0002.json").exists());
+        assert!(temp_dir.path().join("layers/0001.json").exists());
+
+        fs::remove_dir_all(temp_dir.path().join(LAYERS_DIR)).unwrap();
+        repo.commit_all_changes("none").unwrap();
+        let none = repo.repo.head().unwrap().target().unwrap();
+        assert_eq!(read_from_commit(&repo, none).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_chain() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("main")).unwrap();
+        let layers = vec![layer("sha256:1"), layer("sha256:2")];
+
+        // An earlier version's layer commit: the chain is in Image.md
+        let mut metadata = ImageMetadata::new(None, None);
+        metadata.layer_digests = layers.clone();
+        metadata
+            .save_markdown(&temp_dir.path().join(IMAGE_MD))
+            .unwrap();
+        repo.commit_all_changes("old").unwrap();
+        let old = repo.repo.head().unwrap().target().unwrap();
+        assert_eq!(read_chain(&repo, old).unwrap().unwrap(), layers);
+
+        // The layer files win over a hand-edited report
+        save(temp_dir.path(), &layers).unwrap();
+        fs::write(temp_dir.path().join(IMAGE_MD), "# edited by hand\n").unwrap();
+        fs::write(temp_dir.path().join(IMAGE_JSON), "{").unwrap();
+        repo.commit_all_changes("edited").unwrap();
+        let edited = repo.repo.head().unwrap().target().unwrap();
+        assert!(ImageMetadata::read_from_commit(&repo, edited).is_err());
+        assert_eq!(read_chain(&repo, edited).unwrap().unwrap(), layers);
+
+        // Without them, a broken report is no chain at all
+        fs::remove_dir_all(temp_dir.path().join(LAYERS_DIR)).unwrap();
+        repo.commit_all_changes("broken").unwrap();
+        let broken = repo.repo.head().unwrap().target().unwrap();
+        assert_eq!(read_chain(&repo, broken).unwrap(), None);
+    }
+
+    #[test]
+    fn test_notes() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("main")).unwrap();
+        let layers = vec![layer("sha256:1"), layer("sha256:2")];
+        for i in 1..=layers.len() {
+            repo.commit_all_changes("layer").unwrap();
+            LayerRecords::Notes.annotate(&repo, &layers[..i]).unwrap();
+        }
+        repo.commit_all_changes("metadata").unwrap();
+        let commits = repo.get_branch_commits("main").unwrap();
+
+        assert_eq!(read_notes(&repo, commits[0]).unwrap().unwrap(), layers[..1]);
+        assert_eq!(read_notes(&repo, commits[2]).unwrap().unwrap(), layers);
+        assert_eq!(read_chain(&repo, commits[2]).unwrap().unwrap(), layers);
+        assert_eq!(
+            LayerRecords::of_commit(&repo, commits[1]).unwrap(),
+            Some(LayerRecords::Notes)
+        );
+        assert_eq!(LayerRecords::of_commit(&repo, commits[2]).unwrap(), None);
+
+        // Copies carry the notes of their originals
+        repo.create_branch("copy", None).unwrap();
+        repo.commit_all_changes("copy").unwrap();
+        let copy = repo.get_branch_commits("copy").unwrap()[0];
+        copy_notes(&repo, &commits[1..], &[copy]).unwrap();
+        assert_eq!(read_notes(&repo, copy).unwrap().unwrap(), layers[1..]);
+    }
+}
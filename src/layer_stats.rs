@@ -0,0 +1,266 @@
+//! Per-layer size statistics: `LayerStats.md` and `LayerStats.json`.
+//!
+//! With [`crate::processor::ImageProcessor::with_layer_stats`] the metadata commit carries a
+//! report of what every layer commit of the branch did to `rootfs/`: how many files it
+//! added, modified and deleted, how many bytes it added and deleted, and its largest files.
+//! The numbers come from the diff of each layer commit against its parent (see
+//! [`LayerStats::between`]), so layers reused from another branch are covered as well:
+//!
+//! ```text
+//! | Layer | Command              | Added | Modified | Deleted | Bytes added | Bytes deleted |
+//! |-------|----------------------|-------|----------|---------|-------------|---------------|
+//! | 1     | `ADD rootfs.tar /`   | 3201  | 0        | 0       | 72.4 MiB    | 0 B           |
+//! | 2     | `RUN apt-get update` | 12    | 3        | 1       | 41.0 MiB    | 12.0 KiB      |
+//! ```
+//!
+//! Sizes are those of the committed blobs: files committed as stubs (see
+//! [`crate::large_files`]) count with the size of their stub. A `--squash` branch has no
+//! layer commits and therefore no report.
+
+use crate::disk_space::format_bytes;
+use crate::git::GitRepo;
+use crate::image_metadata::{ImageMetadata, MetadataFormat};
+use crate::query::rootfs_tree;
+use crate::trailers;
+use anyhow::{Context, Result};
+use git2::{Delta, Repository, Tree};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Markdown report, relative to the repository root.
+pub const LAYER_STATS_MD: &str = "LayerStats.md";
+/// JSON report, relative to the repository root.
+pub const LAYER_STATS_JSON: &str = "LayerStats.json";
+/// How many of its largest files are listed per layer.
+pub const LARGEST_FILES: usize = 5;
+
+/// A file and the size of its blob.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSize {
+    /// Path in the image, e.g. `/usr/lib/libfoo.so`
+    pub path: String,
+    pub size: u64,
+}
+
+/// What one layer changed in `rootfs/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerStats {
+    /// 1-based layer index
+    pub index: usize,
+    pub digest: String,
+    pub command: String,
+    pub files_added: usize,
+    pub files_modified: usize,
+    pub files_deleted: usize,
+    /// Size of the added and modified files
+    pub bytes_added: u64,
+    /// Size the deleted files had before this layer
+    pub bytes_deleted: u64,
+    /// Largest added or modified files, largest first
+    pub largest_files: Vec<FileSize>,
+}
+
+impl LayerStats {
+    /// Statistics of the changes from the `rootfs/` tree `old` to `new`, with the
+    /// [`LARGEST_FILES`] largest files; index, digest and command are left empty.
+    pub fn between(repo: &Repository, old: Option<&Tree>, new: Option<&Tree>) -> Result<Self> {
+        Self::between_top(repo, old, new, LARGEST_FILES)
+    }
+
+    /// Same as [`LayerStats::between`], listing the `top` largest files.
+    pub fn between_top(
+        repo: &Repository,
+        old: Option<&Tree>,
+        new: Option<&Tree>,
+        top: usize,
+    ) -> Result<Self> {
+        let odb = repo.odb()?;
+        let diff = repo.diff_tree_to_tree(old, new, None)?;
+        let mut stats = Self::default();
+        let mut files = Vec::new();
+        for delta in diff.deltas() {
+            if delta.status() == Delta::Deleted {
+                let (size, _) = odb.read_header(delta.old_file().id())?;
+                stats.files_deleted += 1;
+                stats.bytes_deleted += size as u64;
+                continue;
+            }
+            if delta.status() == Delta::Added {
+                stats.files_added += 1;
+            } else {
+                stats.files_modified += 1;
+            }
+            let (size, _) = odb.read_header(delta.new_file().id())?;
+            stats.bytes_added += size as u64;
+            if let Some(path) = delta.new_file().path() {
+                files.push(FileSize {
+                    path: format!("/{}", path.to_string_lossy()),
+                    size: size as u64,
+                });
+            }
+        }
+        files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        files.truncate(top);
+        stats.largest_files = files;
+        Ok(stats)
+    }
+}
+
+/// Statistics of every layer commit of a branch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerStatsReport {
+    pub layers: Vec<LayerStats>,
+}
+
+impl LayerStatsReport {
+    /// Collects the statistics of the layer commits (those with layer trailers) of
+    /// `branch`; commands come from `metadata`.
+    ///
+    /// # Errors
+    /// - `branch` doesn't exist, or Git failures.
+    pub fn collect(repo: &GitRepo, branch: &str, metadata: &ImageMetadata) -> Result<Self> {
+        let mut layers = Vec::new();
+        let mut previous = None;
+        for oid in repo.get_branch_commits(branch)? {
+            let commit = repo.repo.find_commit(oid)?;
+            let rootfs = rootfs_tree(&repo.repo, &commit.tree()?)?;
+            if let Some(layer) = trailers::parse(commit.message().unwrap_or_default())? {
+                let mut stats =
+                    LayerStats::between(&repo.repo, previous.as_ref(), rootfs.as_ref())?;
+                stats.index = layer.index;
+                stats.command = layer
+                    .index
+                    .checked_sub(1)
+                    .and_then(|index| metadata.layer_digests.get(index))
+                    .map(|recorded| recorded.command.clone())
+                    .unwrap_or_default();
+                stats.digest = layer.digest;
+                layers.push(stats);
+            }
+            previous = rootfs;
+        }
+        Ok(Self { layers })
+    }
+
+    /// Markdown tables: one row per layer, then the largest files of each layer.
+    pub fn render_markdown(&self) -> String {
+        let mut markdown = String::from("# Layer Statistics\n\n");
+        markdown.push_str(
+            "| Layer | Command | Added | Modified | Deleted | Bytes added | Bytes deleted |\n",
+        );
+        markdown.push_str(
+            "|-------|---------|-------|----------|---------|-------------|---------------|\n",
+        );
+        for layer in &self.layers {
+            markdown.push_str(&format!(
+                "| {} | `{}` | {} | {} | {} | {} | {} |\n",
+                layer.index,
+                layer.command.replace('|', "\\|"),
+                layer.files_added,
+                layer.files_modified,
+                layer.files_deleted,
+                format_bytes(layer.bytes_added),
+                format_bytes(layer.bytes_deleted)
+            ));
+        }
+
+        markdown.push_str("\n## Largest Files\n\n");
+        markdown.push_str("| Layer | Size | Path |\n");
+        markdown.push_str("|-------|------|------|\n");
+        for layer in &self.layers {
+            for file in &layer.largest_files {
+                markdown.push_str(&format!(
+                    "| {} | {} | `{}` |\n",
+                    layer.index,
+                    format_bytes(file.size),
+                    file.path.replace('|', "\\|")
+                ));
+            }
+        }
+        markdown
+    }
+
+    /// Writes the files selected by `format` into `dir` and removes the other one; writes
+    /// nothing (and removes both) if there are no layers.
+    pub fn save(&self, dir: &Path, format: MetadataFormat) -> Result<()> {
+        for (name, selected) in [
+            (LAYER_STATS_MD, format.writes_markdown()),
+            (LAYER_STATS_JSON, format.writes_json()),
+        ] {
+            let path = dir.join(name);
+            if selected && !self.layers.is_empty() {
+                let content = if name == LAYER_STATS_MD {
+                    self.render_markdown()
+                } else {
+                    serde_json::to_string_pretty(self)? + "\n"
+                };
+                fs::write(&path, content)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            } else if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trailers::LayerTrailers;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_layer_stats_report() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("app")).unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+
+        fs::create_dir_all(rootfs.join("usr/bin")).unwrap();
+        fs::write(rootfs.join("usr/bin/big"), "1234567890").unwrap();
+        fs::write(rootfs.join("small"), "1").unwrap();
+        repo.commit_layer("ADD", &LayerTrailers::new(1, "sha256:1"))
+            .unwrap();
+        fs::remove_file(rootfs.join("usr/bin/big")).unwrap();
+        fs::write(rootfs.join("small"), "12").unwrap();
+        repo.commit_layer("RUN", &LayerTrailers::new(2, "sha256:2"))
+            .unwrap();
+
+        let report =
+            LayerStatsReport::collect(&repo, "app", &ImageMetadata::new(None, None)).unwrap();
+        assert_eq!(report.layers.len(), 2);
+        assert_eq!(
+            report.layers[0].largest_files,
+            vec![
+                FileSize {
+                    path: "/usr/bin/big".to_string(),
+                    size: 10
+                },
+                FileSize {
+                    path: "/small".to_string(),
+                    size: 1
+                }
+            ]
+        );
+        let second = &report.layers[1];
+        assert_eq!(
+            (
+                second.files_modified,
+                second.files_deleted,
+                second.bytes_added,
+                second.bytes_deleted
+            ),
+            (1, 1, 2, 10)
+        );
+
+        report
+            .save(temp_dir.path(), MetadataFormat::Markdown)
+            .unwrap();
+        let markdown = fs::read_to_string(temp_dir.path().join(LAYER_STATS_MD)).unwrap();
+        assert!(markdown.contains("| 2 | `` | 0 | 1 | 1 | 2 B | 10 B |"));
+        assert!(markdown.contains("| 1 | 10 B | `/usr/bin/big` |"));
+        assert!(!temp_dir.path().join(LAYER_STATS_JSON).exists());
+    }
+}
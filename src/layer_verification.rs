@@ -0,0 +1,76 @@
+//! Layer digest verification.
+//!
+//! The digest a layer is recorded under comes from its file name in the image archive (see
+//! [`crate::digest_tracker::DigestTracker::extract_digest_from_tarball_path`]), which
+//! nothing checks. Every layer is hashed while it is extracted (see
+//! [`crate::tar_extractor::extract_tar_observed`]); with
+//! [`crate::processor::ImageProcessor::with_digest_verification`] the sha256 of its
+//! uncompressed tar stream must also equal the layer's `diff_id` from the image config
+//! (`rootfs.diff_ids`). The outcome is recorded per layer in the `Verified` column of
+//! `Image.md` (`verified` in `Image.json`); layers the config lists no `diff_id` for
+//! can't be verified and record nothing.
+
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+/// What to do when a layer doesn't match its `diff_id`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DigestVerification {
+    /// Don't compare, record nothing
+    #[default]
+    Skip,
+    /// Warn, record the layer as not verified and convert anyway
+    Warn,
+    /// Fail the conversion at the first mismatching layer
+    Fail,
+}
+
+/// Reader that hashes everything read through it.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// `sha256:<hex>` digest of the bytes read so far.
+    pub fn finish(self) -> String {
+        format!("sha256:{}", self.finish_hex())
+    }
+
+    /// Hex SHA-256 of the bytes read so far.
+    pub fn finish_hex(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashing_reader() {
+        let mut reader = HashingReader::new(io::Cursor::new(b"hello"));
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "hello");
+        assert_eq!(
+            reader.finish(),
+            "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+}
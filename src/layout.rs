@@ -0,0 +1,145 @@
+//! Repository layouts (`--layout`).
+//!
+//! By default ([`Layout::Branches`]) every image is converted into a branch of its own,
+//! with `rootfs/` and `Image.md` at the top. [`Layout::Subdir`] keeps all images
+//! side by side on the single branch [`SUBDIR_BRANCH`] instead, each in a directory below
+//! [`SUBDIR_ROOT`], so they can be compared with plain directory diffs:
+//!
+//! ```text
+//! images/
+//! ├── nginx-1.27/
+//! │   ├── Image.md
+//! │   └── rootfs/
+//! └── redis-7.4/
+//!     ├── Image.md
+//!     └── rootfs/
+//! ```
+//!
+//! The image is still converted layer by layer on a branch of its own first; its commits
+//! are then copied onto [`SUBDIR_BRANCH`] with their tree moved to the image's directory
+//! (see [`crate::git::GitRepo::replay_into_dir`]) and the branch is deleted, unless it
+//! existed before. So the branch gets one commit per layer, with the image directory as
+//! subject prefix (`nginx-1.27: 🟢 - RUN …`) and the usual trailers. A directory converted
+//! again is rebuilt from the first layer on; if its content doesn't change, nothing is
+//! committed. Images converted the same run are checked out one after another, so large
+//! batches are slower than with separate branches.
+//!
+//! Commands working on image branches (`list`, `query`, `prune`, `squash-history`, ...)
+//! don't look into [`SUBDIR_BRANCH`].
+
+use crate::branch_naming::BranchNameInput;
+use crate::git::GitRepo;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Branch holding the images of [`Layout::Subdir`].
+pub const SUBDIR_BRANCH: &str = "images";
+/// Directory holding the image directories of [`Layout::Subdir`].
+pub const SUBDIR_ROOT: &str = "images";
+
+/// How images are laid out in the repository.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// One branch per image
+    #[default]
+    Branches,
+    /// One directory per image on [`SUBDIR_BRANCH`]
+    Subdir,
+}
+
+/// Name of the directory of an image below [`SUBDIR_ROOT`]: `nginx:1.27` → `nginx-1.27`,
+/// with `-<os>-<arch>` appended for `with_platform`. Slashes and colons become `-`.
+pub fn image_dir(input: &BranchNameInput, with_platform: bool) -> String {
+    let mut dir = format!("{}-{}", input.name, input.tag);
+    if with_platform {
+        dir = format!("{dir}-{}-{}", input.os, input.arch);
+    }
+    dir.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '@' => '-',
+            c if c.is_whitespace() || c.is_control() => '-',
+            c => c,
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+/// Path of the image directory `dir` in the tree of [`SUBDIR_BRANCH`].
+pub fn dir_path(dir: &str) -> PathBuf {
+    PathBuf::from(SUBDIR_ROOT).join(dir)
+}
+
+/// Subject prefix of the commits copied into `dir`.
+pub fn subject_prefix(dir: &str) -> String {
+    format!("{dir}: ")
+}
+
+/// Whether `dir` on [`SUBDIR_BRANCH`] already holds exactly the tree of `commit`.
+///
+/// # Errors
+/// - The branch or commit can't be read.
+pub fn is_placed(repo: &GitRepo, dir: &str, commit: git2::Oid) -> Result<bool> {
+    let Ok(branch) = repo
+        .repo
+        .find_branch(SUBDIR_BRANCH, git2::BranchType::Local)
+    else {
+        return Ok(false);
+    };
+    let tree = branch.get().peel_to_tree()?;
+    let placed = tree.get_path(&dir_path(dir)).ok().map(|entry| entry.id());
+    Ok(placed == Some(repo.repo.find_commit(commit)?.tree_id()))
+}
+
+/// The commits of the latest copy of an image of `count` commits into `dir` on
+/// [`SUBDIR_BRANCH`], oldest first: the last `count` of the last run of commits with its
+/// subject prefix (copies of the same image follow each other when it changed).
+///
+/// # Errors
+/// - The branch or its commits can't be read.
+pub fn placed_commits(repo: &GitRepo, dir: &str, count: usize) -> Result<Vec<git2::Oid>> {
+    if !repo.branch_exists(SUBDIR_BRANCH) {
+        return Ok(Vec::new());
+    }
+    let prefix = subject_prefix(dir);
+    let mut placed = Vec::new();
+    for oid in repo.get_branch_commits(SUBDIR_BRANCH)?.into_iter().rev() {
+        if placed.len() == count {
+            break;
+        }
+        if repo.commit_message(oid)?.starts_with(&prefix) {
+            placed.push(oid);
+        } else if !placed.is_empty() {
+            break;
+        }
+    }
+    placed.reverse();
+    Ok(placed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(name: &str, tag: &str) -> BranchNameInput {
+        BranchNameInput {
+            image_name: format!("{name}:{tag}"),
+            source: "tar".to_string(),
+            name: name.to_string(),
+            tag: tag.to_string(),
+            os: "linux".to_string(),
+            arch: "arm64".to_string(),
+            digest: "sha256:1234".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_image_dir() {
+        assert_eq!(image_dir(&input("nginx", "1.27"), false), "nginx-1.27");
+        assert_eq!(
+            image_dir(&input("localhost:5000/org/app", "sha256-abc"), true),
+            "localhost-5000-org-app-sha256-abc-linux-arm64"
+        );
+        assert_eq!(dir_path("nginx-1.27"), PathBuf::from("images/nginx-1.27"));
+    }
+}
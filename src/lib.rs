@@ -11,19 +11,115 @@
 //!
 //! # Usage
 //!
-//! `oci2git [OPTIONS] <IMAGE>`
+//! `oci2git [OPTIONS] <IMAGE>...`
+//!
+//! `oci2git <COMMAND>`
+//!
+//! Commands:
+//! - `squash-history <REPO> <BRANCH> --layers <N>` Show layers 1..=N of a converted branch as a single commit (reversible `git replace` graft)
+//! - `export <REPO> <BRANCH> -o <FILE> [--tag <TAG>] [--restore-special-files]` Rebuild an image tarball from a converted branch, one layer per commit (see [`git2oci`])
+//! - `query <REPO> [--branch <BRANCH>] [--jq <FILTER>] [-r]` Print the image metadata and per-layer change statistics as JSON, optionally filtered through `jq` (see [`query`])
+//! - `diff <IMAGE_A> <IMAGE_B> [-o <DIR>] [-e <ENGINE>] [--platform <PLATFORM>] [--format md|json] [-r <FILE>]` Convert two images into one repository and report how they differ (see [`image_diff`])
+//! - `analyze <REPO|IMAGE> [--branch <BRANCH>] [--top <N>] [--format md|json]` Report the largest files of every layer and the space wasted on files later layers delete or overwrite, committed as `ANALYSIS.md` on the `main` branch (see [`analyze`]); an image is converted first
+//! - `inspect <IMAGE> [-e <ENGINE>] [--platform <PLATFORM>] [--format table|json]` Print the layer history of an image (the table of `Image.md`) without converting it (see [`inspect`])
+//! - `list [-o <DIR>] [--format table|json]` List the images converted into a repository: name, tag, digest, platform, layer count and conversion date (see [`list`])
+//! - `file-log [-o <DIR>] [--branch <BRANCH>] <PATH> [--format table|json]` Show which layers of a converted image created, modified or deleted a path, with their commands and digests (see [`file_log`])
+//! - `prune [-o <DIR>] [--keep-last <N>] [--older-than <AGE>] [--dry-run]` Delete old image branches and repack the repository to free their space (see [`prune`])
+//! - `checkout [-o <DIR>] <BRANCH> --to <DIR> | --list | --remove <DIR> | --remove-all` Check out an image branch into a directory of its own, as a Git worktree managed by oci2git (see [`worktrees`])
 //!
 //! Arguments:
-//! - `<IMAGE>` Image name to convert (e.g., 'ubuntu:latest') or path to tarball when using the tar engine
+//! - `<IMAGE>...` Images to convert (e.g., 'ubuntu:latest', 'registry://nginx@sha256:...') or paths to tarballs when using the tar engine; several images become branches of the same repository
 //! - Options:
 //!     - `-o` `--output` `<o>`  Output directory for Git repository `[default: ./container_repo]`
-//!     - `-e` `--engine` `<ENGINE>`  Container engine to use (docker, nerdctl, tar) `[default: docker]`
+//!     - `--config` `<FILE>`  Repository config file with defaults for the conversion options (exclude, max-file-size, commit-template, ...), over those of `~/.config/oci2git/config.toml`; environment variables and command line options win (see [`config_file`]) `[default: <OUTPUT>/.oci2git.toml]`
+//!     - `-e` `--engine` `<ENGINE>`  Container engine to use (docker, podman, nerdctl, tar, registry, containerd) `[default: docker]`
+//!     - `--namespace` `<NAMESPACE>`  containerd namespace to export images from (containerd engine; Kubernetes uses k8s.io) `[default: default]`
+//!     - `--offline`  Never access the network: only convert images already in the local daemon, tarballs and OCI directories, and fail instead of pulling
+//!     - `--no-pull`  Fail instead of pulling an image missing from the local daemon, and export the local image for `--platform` instead of pulling that platform (docker, podman and containerd engines)
+//!     - `--no-gc`  Don't repack the repository after the conversion; faster, but the repository takes more space
+//!     - `--max-temp-bytes` `<BYTES>`  Abort if temporary data (image tarball + unpacked layers) would exceed this size
+//!     - `--workdir` `<DIR>`  Directory for temporary data (exported image tarballs, unpacked images), e.g. on a large fast disk [default: `$TMPDIR`]
+//!     - `--keep-workdir`  Keep the temporary data in the work directory after the conversion, for debugging, and print its location
+//!     - `--parallel` `<N>`  Download the blobs of up to N images at once before converting them (registry engine) `[default: 1]`
+//!     - `--username` `<USER>`  Registry user name (registry engine); without it credentials come from `docker login` (`~/.docker/config.json` and credential helpers; see [`sources::credentials`])
+//!     - `--password-stdin`  Read the registry password or token for `--username` from stdin
+//!     - `--registry-ca` `<PEM>`  Also trust the CA certificates in this PEM file for registry connections, e.g. a corporate or self-signed CA (registry engine); repeat for several (see [`sources::transport`])
+//!     - `--insecure-registry` `<HOST>`  Connect to this registry (`host[:port]`) without verifying its TLS certificate, falling back to plain HTTP (registry engine); repeat for several
+//!     - `--blob-cache` `<DIR>`  Blob cache directory of the registry engine, e.g. one shared by successive runs `[default: ~/.cache/oci2git/blobs]`
+//!     - `--conversion-cache` `<DIR>`  Conversion cache directory: images converted before into another repository start from the layer commits fetched from there `[default: ~/.cache/oci2git/conversions]`
+//!     - `--no-conversion-cache`  Neither use nor update the conversion cache
+//!     - `--force`  Convert images again whose branch already exists, also with identical content (the branch is recreated from scratch), and convert even if the estimated disk space needs (temporary data and repository) exceed the free space (only warn)
+//!     - `--conflict-strategy` `<STRATEGY>`  How to apply a layer that changes an entry's type (replace, follow-symlinks, abort) `[default: replace]`
+//!     - `--layer-mismatch` `<STRATEGY>`  What to do when the non-empty history entries of an image don't line up with its layers: error, best-effort (pair them from the newest layer) or manifest-only (ignore the history); a mismatch is always warned about (see [`extracted_image::LayerMismatch`]) `[default: best-effort]`
+//!     - `--verify-digests` `<MODE>`  Check the sha256 of every layer against its diff_id from the image config while extracting it: fail, warn or off; the result is recorded in Image.md `[default: warn]`
+//!     - `--platform` `<PLATFORM>`  Platform to convert from a multi-platform image (e.g., linux/arm64, linux/arm/v7)
+//!     - `--all-platforms`  Convert every platform of a multi-platform image, each into its own branch
+//!     - `--select` `<REPO:TAG>`  Image to convert from an archive holding several (`docker save img1 img2`) `[default: the first one]`
+//!     - `--metadata-only`  Skip the filesystem: layers become empty commits carrying their digests, with `Image.md` and the history only (registry engine: no layer blobs are downloaded)
+//!     - `--from-layer` `<N|DIGEST>`  Commit the layers before layer N (1-based, as in `Image.md`) or the layer with DIGEST as empty commits carrying their digests, so `rootfs/` only holds the application layers; the branch name ends in `#from-layer-N` (see [`from_layer`])
+//!     - `--skip-base` `<IMAGE>`  Commit the layers of the base image IMAGE (fetched with the same engine) as empty commits carrying their digests, so `rootfs/` only holds the layers added on top of it; like `--from-layer`
+//!     - `--squash`  Apply all layers and commit only the final filesystem (plus the metadata commit), into a branch ending in #squashed
+//!     - `--direct-commits`  Build layer commits straight from the layer tarballs instead of unpacking every layer into `rootfs/` and staging it; `rootfs/` is checked out once at the end
+//!     - `--layout` `<LAYOUT>`  How images are laid out: branches (a branch per image) or subdir (`images/<name>-<tag>/` with `rootfs/` and `Image.md`, all on the branch `images`; see [`layout`]) [default: branches]
+//!     - `--rootfs-dir` `<NAME>`  Name of the directory holding the image filesystem, e.g. fs or chroot (recorded in `.oci2git/rootfs-dir`; see [`convert_options::ConvertOptions::with_rootfs_dir`]) [default: rootfs]
+//!     - `-j, --jobs` `<N>`  Write and hash the files of a layer on N threads (default: number of CPUs)
+//!     - `--commit-partial`  If a layer fails to extract, finish the branch at the last good layer with a metadata commit marked PARTIAL (`PARTIAL.md` describes the failure)
+//!     - `--fs-metadata`  Record owner, group, mode, mtime and xattrs of every path in `.oci2git/fsmeta.jsonl`, committed with each layer
+//!     - `--record-deletions`  Commit `.oci2git/deleted-files.txt` with every layer that deletes paths (whiteouts, opaque directories), listing what it deleted
+//!     - `--content-hashes`  Commit `.oci2git/content-hashes.jsonl` with every layer: path, size and sha256 of each file it wrote; the `analyze` command reports duplicated content from it
+//!     - `--metadata-format` `<FORMAT>`  Image metadata files to commit: md (`Image.md`), json (`Image.json`) or both `[default: md]`
+//!     - `--env-scripts`  Commit `.oci2git/env.sh` (image environment) and `.oci2git/run.sh` (workdir, entrypoint and command) with the metadata commit
+//!     - `--materialize-config`  Write the image environment into `rootfs/etc/profile.d/oci2git-env.sh`, and exposed ports and volumes into `ports.txt` and `volumes.txt`, with the metadata commit (see [`materialize_config`])
+//!     - `--config-history`  Commit the image config (environment, labels, entrypoint, command, user, workdir, exposed ports) as it was after each layer to `config/` with every layer commit
+//!     - `--layer-stats`  Commit `LayerStats.md` (`LayerStats.json` with `--metadata-format json|both`) with the metadata commit: files and bytes each layer added and deleted, and its largest files
+//!     - `--layer-graph`  End `Image.md` with a Mermaid diagram of the layer chain: the bytes every layer added and the layers shared with the other image branches of the repository (see [`layer_graph`])
+//!     - `--emit-readme`  Commit a `README.md` with the metadata commit summarizing the image (name, digest, platform, layer count, largest layers, entrypoint), shown by forges on the branch page
+//!     - `--html-report`  Commit `report/index.html` with the metadata commit: a self-contained page with a chart of the layer sizes, the biggest files, the environment and labels, and the layer commits (see [`html_report`])
+//!     - `--report-commit-url` `<URL>`  Link the layer commits of `--html-report` to URL, with `{commit}` replaced by the commit id, e.g. `https://github.com/acme/images/commit/{commit}`
+//!     - `--convert-base`  Convert the base image the image declares (`org.opencontainers.image.base.name` annotation or label) into the repository first, so the image branches from its layer commits (see [`base_image`])
+//!     - `--max-file-size` `<BYTES>`  Commit files larger than this as stubs (path, size, sha256) listed in `.oci2git/large-files.jsonl`
+//!     - `--sparse-files` `<MODE>`  How sparse files (VM images, preallocated database files) are committed: expand (full content, holes kept on disk) or stub (a stub with the apparent size and bytes of data, listed in `.oci2git/large-files.jsonl`) `[default: expand]`
+//!     - `--symlink-targets` `<MODE>`  How symlink targets are committed: host (absolute paths below `rootfs/` of the converting machine) or image (as in the image, e.g. `/usr/bin/python3`; recorded in `.oci2git/symlink-targets`) `[default: host]`
+//!     - `--layer-records` `<WHERE>`  Where layer commits record the layer digests and history entries: files (`layers/0001.json`, ...) or notes (git notes in `refs/notes/oci2git`, keeping the worktree clean; see [`layer_records`]) `[default: files]`
+//!     - `--exclude` `<PATTERN>`  Leave paths matching PATTERN out of every layer, e.g. `usr/share/doc` or `**/__pycache__` (`*` and `?` match within a path component, `**` any number of components; see [`path_filter`]); repeat for several
+//!     - `--commit-template` `<TEMPLATE>`  Subject of layer commits (placeholders: `{marker}`, `{command}`, `{index}`, `{digest}`, `{created}`) `[default: {marker} - {command}]`
+//!     - `--branch-template` `<TEMPLATE>`  Branch name template, e.g. `{name}/{tag}/{arch}` (placeholders: `{name}`, `{tag}`, `{os}`, `{arch}`, `{digest}`, `{source}`; see [`branch_naming`]) `[default: the engine's image#tag#os-arch#digest scheme]`
+//!     - `--tag`  Create an annotated tag at the tip of the converted branch, named like `nginx-1.27.0-amd64-sha256-<digest>`
+//!     - `--tag-template` `<TEMPLATE>`  Name of the tag, same placeholders as `--branch-template` (implies `--tag`) `[default: {name}-{tag}-{arch}-sha256-{digest}]`
+//!     - `--git-author` `<NAME>`  Author and committer name of the created commits `[default: oci2git]`
+//!     - `--git-email` `<EMAIL>`  Author and committer e-mail of the created commits `[default: oci2git@example.com]`
+//!     - `--sign-commits`  Sign every commit with the key configured for git (`gpg.format`, `user.signingkey`)
+//!     - `--signing-key` `<KEY>`  Signing key instead of `user.signingkey`: GPG key ID, or SSH key file with `--signing-format ssh`
+//!     - `--signing-format` `<FORMAT>`  Signing key type instead of `gpg.format` (openpgp, ssh)
+//!     - `--keep-failed`  Keep the extraction directory of a layer that fails to extract (under `$TMPDIR/oci2git-failed`) and print its location
+//!     - `-v` `--verbose...`  Verbose mode (-v for info, -vv for debug, -vvv for trace). Also switches to text-based progress
+//!     - `-q` `--quiet`  Print nothing but errors; stdout only gets the branch of every converted image, one per line (the JSON summary instead with `--summary`)
+//!     - `--summary`  Print a JSON summary of the run to stdout when done: branch, layer commits and digests, skipped layers, bytes processed and elapsed time of every image (see [`summary`])
+//!     - `--summary-file` `<FILE>`  Write the JSON summary of the run (see `--summary`) to this file
+//!     - `--perf-report` `<FILE>`  Write the time spent per phase (pull, unpack, analyze, apply and commit of every layer, metadata, gc) of every image to this JSON file (see [`perf`])
+//!     - `--log` `<FILTER>`  Per-subsystem log levels, e.g. `extract=trace,git=warn` or just `extract` (subsystems: source, extract, git, metadata, convert; `RUST_LOG`-style module paths work too)
+//!     - `--progress` `<MODE>`  How progress is shown: fancy (spinner and progress bars), plain (log lines, also without `-v`, e.g. for CI logs), none, or auto (fancy without `-v` and `--log`) `[default: auto]` (see [`notifier`])
+//!     - `--log-format` `<FORMAT>`  Format of the log lines: text, or json (one object per line with timestamp, level, target, event and fields; progress is reported as events instead of bars) `[default: text]`
 //!     - `-h` `--help`  Print help information
 //!     - `-V` `--version` Print version information
 //!
 //! - Environment Variables:
+//!     - `RUST_LOG`  Additional log filter directives in `tracing_subscriber` `EnvFilter` syntax (e.g. `oci2git::tar_extractor=trace`, or `[extract]=debug` for the events inside a span); `--log` takes precedence.
+//!     - `OCI2GIT_<KEY>`  Default for a conversion option, e.g. `OCI2GIT_ENGINE`, `OCI2GIT_OUTPUT` or `OCI2GIT_MAX_FILE_SIZE`; overrides the config files, the command line overrides it (see [`config_file`]).
+//!     - `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY`, `NO_PROXY`  Proxy of the registry engine and the hosts reached without it
+//!     - `OTEL_EXPORTER_OTLP_ENDPOINT`  With the `otel` feature: export the conversion spans as OpenTelemetry traces to this OTLP/HTTP endpoint (see `telemetry`)
 //!     - `TMPDIR`  Set this environment variable to change the default location used for intermediate data processing. This is platform-dependent (e.g., TMPDIR on Unix/macOS, TEMP or TMP on Windows).
 //!
+//! - Exit Codes (see [`error`]):
+//!     - `0`  Success
+//!     - `1`  Any other failure (disk space, I/O, ...)
+//!     - `2`  Invalid option or argument
+//!     - `3`  Every image was already converted with identical content; nothing changed (`--force` converts them again)
+//!     - `4`  The image couldn't be fetched from its source (daemon, registry, tarball)
+//!     - `5`  A layer couldn't be applied or verified
+//!     - `6`  A Git operation failed
+//!     - `7`  Image metadata couldn't be read or written
+//!
 //! # Example
 //!
 //! ```oci2git ubuntu:latest```
@@ -42,27 +138,115 @@
 //! ```text
 //! container_repo/
 //! ├── .git/
-//! ├── Image.md     # Complete image metadata
-//! └── rootfs/      # Filesystem content from the container
+//! ├── .oci2git.toml  # Conversion defaults, read before converting and never committed (see [`config_file`])
+//! ├── attestations/  # Signatures, attestations and SBOMs attached to the image (registry engine)
+//! ├── config/      # Image config after each layer: env.txt, labels.json, entrypoint.txt, ... (--config-history)
+//! ├── .oci2git/    # special-files.jsonl (devices, FIFOs), rootfs-dir (--rootfs-dir), fsmeta.jsonl (--fs-metadata), env.sh/run.sh (--env-scripts), large-files.jsonl (--max-file-size, --sparse-files), symlink-targets (--symlink-targets), deleted-files.txt (--record-deletions), content-hashes.jsonl (--content-hashes), layer-files.txt (--metadata-only, eStargz layers)
+//! ├── Image.md     # Complete image metadata (Image.json with --metadata-format json|both)
+//! ├── layers/      # 0001.json, 0002.json, ...: digest and history entry of every layer so far (git notes with --layer-records notes; see [`layer_records`])
+//! ├── README.md    # Image summary for forges (--emit-readme)
+//! ├── LayerStats.md  # Per-layer file counts, sizes and largest files (--layer-stats)
+//! ├── ports.txt    # Exposed ports, one per line (--materialize-config)
+//! ├── report/      # index.html: shareable summary with layer sizes, biggest files, environment and labels (--html-report)
+//! ├── volumes.txt  # Declared volumes, one per line (--materialize-config)
+//! └── rootfs/      # Filesystem content from the container (named with --rootfs-dir; etc/profile.d/oci2git-env.sh with --materialize-config)
 //! ```
+//!
+//! # Library
+//!
+//! `use oci2git::prelude::*;` brings in the semver-stable API (processor, sources, plans
+//! and report types); see [`prelude`] for what is covered. Converted repositories are read
+//! back with [`converted_repo::ConvertedRepo`]. Plugins implementing
+//! [`plugins::LayerProcessor`] see every layer of a conversion and can commit files of their
+//! own with it (SBOMs, lint reports, custom indexes).
 
+pub mod analyze;
+pub mod attestations;
+pub mod base_image;
+pub mod blob_cache;
+pub mod branch_naming;
+pub mod build_provenance;
+pub mod config_file;
+pub mod config_history;
+pub mod content_hashes;
+pub mod conversion_cache;
+pub mod convert_options;
+pub mod converted_repo;
+pub mod deleted_files;
+#[doc(hidden)]
 pub mod digest_tracker;
+pub mod disk_space;
+pub mod env_scripts;
+pub mod error;
+pub mod estargz;
 pub mod extracted_image;
+pub mod file_log;
+pub mod from_layer;
+pub mod fsmeta;
 pub mod git;
+pub mod git2oci;
+pub mod html_report;
+pub mod image_diff;
 pub mod image_metadata;
+pub mod inspect;
+pub mod large_files;
+pub mod layer_apply;
+pub mod layer_graph;
+pub mod layer_records;
+pub mod layer_stats;
+pub mod layer_verification;
+pub mod layout;
+pub mod list;
+pub mod materialize_config;
+#[doc(hidden)]
 pub mod metadata;
 pub mod notifier;
+pub mod oci_index;
+pub mod path_filter;
+pub mod perf;
+pub mod plan;
+pub mod plugins;
+pub mod prelude;
 pub mod processor;
+pub mod progress;
+pub mod prune;
+pub mod query;
+pub mod repo_readme;
+pub mod rootfs;
+pub mod signing;
 pub mod sources;
+pub mod sparse;
+pub mod special_files;
+pub mod squash;
+pub mod staging;
+#[doc(hidden)]
 pub mod successor_navigator;
+pub mod summary;
+pub mod symlinks;
+#[doc(hidden)]
 pub mod tar_extractor;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod temp_usage;
+pub mod trailers;
+pub mod tree_apply;
+pub mod volumes;
+pub mod workdir;
+pub mod worktrees;
 
-// Re-exports for easy access
+// Re-exports for easy access; new code should prefer `prelude`
+pub use convert_options::ConvertOptions;
 pub use extracted_image::{ExtractedImage, Layer};
 pub use git::GitRepo;
 pub use notifier::Notifier;
+pub use plan::ImagePlan;
 pub use processor::ImageProcessor;
+pub use rootfs::{materialize_rootfs, RootfsOptions};
+pub use sources::ContainerdSource;
 pub use sources::DockerSource;
 pub use sources::NerdctlSource;
+pub use sources::PodmanSource;
+pub use sources::RegistrySource;
 pub use sources::Source;
+pub use sources::StreamSource;
 pub use sources::TarSource;
@@ -0,0 +1,182 @@
+//! `oci2git list`: the images converted into a repository.
+//!
+//! [`list`] reads the metadata (`Image.json` or `Image.md`) at the tip of every image
+//! branch and reports image name and tag, digest, platform, layer count and when the
+//! branch was converted (the committer time of its tip). [`render_table`] prints one
+//! aligned row per image:
+//!
+//! ```text
+//! IMAGE  TAG   DIGEST        PLATFORM     LAYERS  CONVERTED                  BRANCH
+//! nginx  1.27  3b25b682ea82  linux/amd64  7       2024-03-01T12:00:00+00:00  nginx#1.27#linux-amd64#3b25b682ea82
+//! ```
+
+use crate::git::GitRepo;
+use crate::image_metadata::ImageMetadata;
+use crate::query::image_branches;
+use crate::sources::extract_short_digest;
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use serde::Serialize;
+
+/// A converted image, as recorded at the tip of its branch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ListedImage {
+    pub branch: String,
+    /// Image name without the tag, e.g. `nginx` or `ghcr.io/org/app`.
+    pub image: String,
+    /// From the first repo tag for references by digest and tarball paths; `None` if the
+    /// image has no tag at all.
+    pub tag: Option<String>,
+    /// Image ID (config digest).
+    pub digest: String,
+    /// `os/architecture`
+    pub platform: String,
+    /// Layers of the image, empty ones included.
+    pub layers: usize,
+    /// RFC 3339 committer time of the branch tip.
+    pub converted_at: String,
+}
+
+/// Lists the image branches of `repo`, sorted by branch; branches without metadata at
+/// their tip are skipped.
+///
+/// # Errors
+/// - Branch enumeration failures, or metadata that can't be parsed.
+pub fn list(repo: &GitRepo) -> Result<Vec<ListedImage>> {
+    let mut images = Vec::new();
+    for branch in image_branches(repo, None)? {
+        let Some(&tip) = repo.get_branch_commits(&branch)?.last() else {
+            continue;
+        };
+        let Some(metadata) = ImageMetadata::read_from_commit(repo, tip)
+            .with_context(|| format!("Failed to read the metadata of branch '{branch}'"))?
+        else {
+            continue;
+        };
+        let Some(info) = metadata.basic_info else {
+            continue;
+        };
+        // Tarballs and digest references are named without a tag; their first repo tag has one
+        let (image, tag) = match (split_reference(&info.name), info.tags.first()) {
+            ((_, None), Some(first)) => split_reference(first),
+            (reference, _) => reference,
+        };
+        let time = repo.repo.find_commit(tip)?.committer().when();
+        images.push(ListedImage {
+            branch,
+            image,
+            tag,
+            digest: info.id,
+            platform: format!("{}/{}", info.os, info.architecture),
+            layers: metadata.layer_digests.len(),
+            converted_at: DateTime::from_timestamp(time.seconds(), 0)
+                .map(|time| time.to_rfc3339())
+                .unwrap_or_default(),
+        });
+    }
+    Ok(images)
+}
+
+/// One aligned row per image, digests shortened to 12 characters.
+pub fn render_table(images: &[ListedImage]) -> String {
+    let header = [
+        "IMAGE",
+        "TAG",
+        "DIGEST",
+        "PLATFORM",
+        "LAYERS",
+        "CONVERTED",
+        "BRANCH",
+    ];
+    let rows: Vec<[String; 7]> = images
+        .iter()
+        .map(|image| {
+            [
+                image.image.clone(),
+                image.tag.clone().unwrap_or_else(|| "-".to_string()),
+                extract_short_digest(&image.digest).unwrap_or_else(|| image.digest.clone()),
+                image.platform.clone(),
+                image.layers.to_string(),
+                image.converted_at.clone(),
+                image.branch.clone(),
+            ]
+        })
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let mut push_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    };
+    push_row(header.to_vec());
+    for row in &rows {
+        push_row(row.iter().map(String::as_str).collect());
+    }
+    out
+}
+
+/// `nginx:1.27` → (`nginx`, `1.27`); the port of a registry host isn't a tag.
+fn split_reference(name: &str) -> (String, Option<String>) {
+    if name.contains('@') {
+        return (name.to_string(), None);
+    }
+    match name.rsplit_once(':') {
+        Some((image, tag)) if !tag.contains('/') => (image.to_string(), Some(tag.to_string())),
+        _ => (name.to_string(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_reference() {
+        assert_eq!(
+            split_reference("nginx:1.27"),
+            ("nginx".to_string(), Some("1.27".to_string()))
+        );
+        assert_eq!(
+            split_reference("localhost:5000/app"),
+            ("localhost:5000/app".to_string(), None)
+        );
+        assert_eq!(
+            split_reference("localhost:5000/app:v1"),
+            ("localhost:5000/app".to_string(), Some("v1".to_string()))
+        );
+        assert_eq!(split_reference("app@sha256:aa").1, None);
+    }
+
+    #[test]
+    fn test_render_table() {
+        let table = render_table(&[ListedImage {
+            branch: "app#1#linux-amd64".to_string(),
+            image: "app".to_string(),
+            tag: None,
+            digest: "sha256:0123456789abcdef".to_string(),
+            platform: "linux/amd64".to_string(),
+            layers: 3,
+            converted_at: "2024-03-01T12:00:00+00:00".to_string(),
+        }]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines[0],
+            "IMAGE  TAG  DIGEST        PLATFORM     LAYERS  CONVERTED                  BRANCH"
+        );
+        assert_eq!(
+            lines[1],
+            "app    -    0123456789ab  linux/amd64  3       2024-03-01T12:00:00+00:00  app#1#linux-amd64"
+        );
+    }
+}
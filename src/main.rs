@@ -1,23 +1,273 @@
-use anyhow::{anyhow, Result};
-use clap::{Parser, ValueEnum};
-use std::path::PathBuf;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::fs;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-use oci2git::{DockerSource, ImageProcessor, NerdctlSource, Notifier, TarSource};
+use oci2git::analyze;
+use oci2git::blob_cache::BlobCache;
+use oci2git::branch_naming::{BranchNamingStrategy, BranchTemplate, DEFAULT_TAG_TEMPLATE};
+use oci2git::config_file::{ConfigFile, CONFIG_FILE};
+use oci2git::conversion_cache::ConversionCache;
+use oci2git::converted_repo::ConvertedRepo;
+use oci2git::disk_space::{format_bytes, DiskSpaceCheck};
+use oci2git::error::Oci2GitError;
+use oci2git::extracted_image::LayerMismatch;
+use oci2git::file_log;
+use oci2git::from_layer::FromLayer;
+use oci2git::git::CommitIdentity;
+use oci2git::git2oci::{export_branch, ExportOptions};
+use oci2git::image_diff;
+use oci2git::image_metadata::MetadataFormat;
+use oci2git::inspect::Inspection;
+use oci2git::layer_apply::ConflictStrategy;
+use oci2git::layer_records::LayerRecords;
+use oci2git::layer_verification::DigestVerification;
+use oci2git::layout::Layout;
+use oci2git::list;
+use oci2git::notifier::{LogFormat, ProgressMode};
+use oci2git::path_filter::PathFilter;
+use oci2git::perf::PerfReport;
+use oci2git::prune::{self, PruneOptions};
+use oci2git::query;
+use oci2git::signing::{CommitSigner, SigningFormat};
+use oci2git::sources::credentials::{CredentialStore, Credentials};
+use oci2git::sources::registry::REGISTRY_SCHEME;
+use oci2git::sources::transport::Transport;
+use oci2git::sources::{Platform, Source};
+use oci2git::sparse::SparseFiles;
+use oci2git::squash::squash_history;
+use oci2git::summary::{ConversionStatus, ConversionSummary, RunSummary};
+use oci2git::symlinks::SymlinkTargets;
+use oci2git::workdir::Workdir;
+use oci2git::worktrees;
+use oci2git::{
+    ContainerdSource, DockerSource, GitRepo, ImageProcessor, NerdctlSource, Notifier, PodmanSource,
+    RegistrySource, TarSource,
+};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Engine {
     Docker,
+    Podman,
     Nerdctl,
     Tar,
+    Registry,
+    Containerd,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Conflict {
+    Replace,
+    FollowSymlinks,
+    Abort,
+}
+
+impl From<Conflict> for ConflictStrategy {
+    fn from(conflict: Conflict) -> Self {
+        match conflict {
+            Conflict::Replace => ConflictStrategy::Replace,
+            Conflict::FollowSymlinks => ConflictStrategy::FollowSymlinks,
+            Conflict::Abort => ConflictStrategy::Abort,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Mismatch {
+    Error,
+    BestEffort,
+    ManifestOnly,
+}
+
+impl From<Mismatch> for LayerMismatch {
+    fn from(mismatch: Mismatch) -> Self {
+        match mismatch {
+            Mismatch::Error => LayerMismatch::Error,
+            Mismatch::BestEffort => LayerMismatch::BestEffort,
+            Mismatch::ManifestOnly => LayerMismatch::ManifestOnly,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum MetadataFileFormat {
+    Md,
+    Json,
+    Both,
+}
+
+impl From<MetadataFileFormat> for MetadataFormat {
+    fn from(format: MetadataFileFormat) -> Self {
+        match format {
+            MetadataFileFormat::Md => MetadataFormat::Markdown,
+            MetadataFileFormat::Json => MetadataFormat::Json,
+            MetadataFileFormat::Both => MetadataFormat::Both,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Verification {
+    Off,
+    Warn,
+    Fail,
+}
+
+impl From<Verification> for DigestVerification {
+    fn from(verification: Verification) -> Self {
+        match verification {
+            Verification::Off => DigestVerification::Skip,
+            Verification::Warn => DigestVerification::Warn,
+            Verification::Fail => DigestVerification::Fail,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum KeyFormat {
+    Openpgp,
+    Ssh,
+}
+
+impl From<KeyFormat> for SigningFormat {
+    fn from(format: KeyFormat) -> Self {
+        match format {
+            KeyFormat::Openpgp => SigningFormat::Openpgp,
+            KeyFormat::Ssh => SigningFormat::Ssh,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Progress {
+    Auto,
+    Plain,
+    Fancy,
+    None,
+}
+
+impl From<Progress> for ProgressMode {
+    fn from(progress: Progress) -> Self {
+        match progress {
+            Progress::Auto => ProgressMode::Auto,
+            Progress::Plain => ProgressMode::Plain,
+            Progress::Fancy => ProgressMode::Fancy,
+            Progress::None => ProgressMode::None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum RepoLayout {
+    Branches,
+    Subdir,
+}
+
+impl From<RepoLayout> for Layout {
+    fn from(layout: RepoLayout) -> Self {
+        match layout {
+            RepoLayout::Branches => Layout::Branches,
+            RepoLayout::Subdir => Layout::Subdir,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum SparseMode {
+    Expand,
+    Stub,
+}
+
+impl From<SparseMode> for SparseFiles {
+    fn from(mode: SparseMode) -> Self {
+        match mode {
+            SparseMode::Expand => SparseFiles::Expand,
+            SparseMode::Stub => SparseFiles::Stub,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum SymlinkMode {
+    Host,
+    Image,
+}
+
+impl From<SymlinkMode> for SymlinkTargets {
+    fn from(mode: SymlinkMode) -> Self {
+        match mode {
+            SymlinkMode::Host => SymlinkTargets::Host,
+            SymlinkMode::Image => SymlinkTargets::Image,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum LayerRecordsMode {
+    Files,
+    Notes,
+}
+
+impl From<LayerRecordsMode> for LayerRecords {
+    fn from(mode: LayerRecordsMode) -> Self {
+        match mode {
+            LayerRecordsMode::Files => LayerRecords::Files,
+            LayerRecordsMode::Notes => LayerRecords::Notes,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum LogLines {
+    Text,
+    Json,
+}
+
+impl From<LogLines> for LogFormat {
+    fn from(format: LogLines) -> Self {
+        match format {
+            LogLines::Text => LogFormat::Text,
+            LogLines::Json => LogFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    Md,
+    Json,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum TableFormat {
+    Table,
+    Json,
 }
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(
-        help = "Image name to convert (e.g., ubuntu:latest) or path to tarball when using tar engine"
+        required = true,
+        value_name = "IMAGE",
+        help = "Images to convert (e.g., ubuntu:latest, registry://nginx@sha256:...) or paths to tarballs when using tar engine; several images become branches of the same repository"
     )]
-    image: String,
+    images: Vec<String>,
 
     #[arg(
         short,
@@ -27,15 +277,431 @@ struct Cli {
     )]
     output: PathBuf,
 
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Repository config file with defaults for the conversion options (exclude, max-file-size, commit-template, ...), over those of ~/.config/oci2git/config.toml; environment variables and command line options win [default: <OUTPUT>/.oci2git.toml]"
+    )]
+    config: Option<PathBuf>,
+
     #[arg(
         short,
         long,
         value_enum,
         default_value = "docker",
-        help = "Container engine to use (docker, nerdctl, tar)"
+        help = "Container engine to use (docker, podman, nerdctl, tar, registry, containerd)"
     )]
     engine: Engine,
 
+    #[arg(
+        long,
+        default_value = "default",
+        help = "containerd namespace to export images from (containerd engine; Kubernetes uses k8s.io)"
+    )]
+    namespace: String,
+
+    #[arg(
+        long,
+        help = "Never access the network: only convert images already in the local daemon, tarballs and OCI directories, and fail instead of pulling"
+    )]
+    offline: bool,
+
+    #[arg(
+        long,
+        help = "Fail instead of pulling an image missing from the local daemon, and export the local image for --platform instead of pulling that platform (docker, podman and containerd engines)"
+    )]
+    no_pull: bool,
+
+    #[arg(
+        long,
+        help = "Don't repack the repository after the conversion; faster, but the repository takes more space"
+    )]
+    no_gc: bool,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Abort if temporary data (image tarball + unpacked layers) would exceed this size"
+    )]
+    max_temp_bytes: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Convert images again whose branch already exists, also with identical content (the branch is recreated from scratch), and convert even if the estimated disk space needs exceed the free space (only warn)"
+    )]
+    force: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Directory for temporary data (exported image tarballs, unpacked images), e.g. on a large fast disk [default: $TMPDIR]"
+    )]
+    workdir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        help = "Download the blobs of up to N images at once before converting them (registry engine)"
+    )]
+    parallel: usize,
+
+    #[arg(
+        long,
+        value_name = "USER",
+        requires = "password_stdin",
+        help = "Registry user name (registry engine); without it credentials come from docker login (~/.docker/config.json and credential helpers)"
+    )]
+    username: Option<String>,
+
+    #[arg(
+        long,
+        requires = "username",
+        help = "Read the registry password or token for --username from stdin"
+    )]
+    password_stdin: bool,
+
+    #[arg(
+        long,
+        value_name = "PEM",
+        help = "Also trust the CA certificates in this PEM file for registry connections, e.g. a corporate or self-signed CA (registry engine); repeat for several"
+    )]
+    registry_ca: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "Connect to this registry (host[:port]) without verifying its TLS certificate, falling back to plain HTTP (registry engine); repeat for several"
+    )]
+    insecure_registry: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Blob cache directory of the registry engine, e.g. one shared by successive runs [default: ~/.cache/oci2git/blobs]"
+    )]
+    blob_cache: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Conversion cache directory: images converted before into another repository start from the layer commits fetched from there [default: ~/.cache/oci2git/conversions]"
+    )]
+    conversion_cache: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "conversion_cache",
+        help = "Neither use nor update the conversion cache"
+    )]
+    no_conversion_cache: bool,
+
+    #[arg(
+        long,
+        help = "Keep the temporary data in the work directory after the conversion, for debugging, and print its location"
+    )]
+    keep_workdir: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "replace",
+        help = "How to apply a layer that changes an entry's type (replace, follow-symlinks, abort)"
+    )]
+    conflict_strategy: Conflict,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "STRATEGY",
+        default_value = "best-effort",
+        help = "What to do when the non-empty history entries of an image don't line up with its layers: error, best-effort (pair them from the newest layer) or manifest-only (ignore the history); a mismatch is always warned about"
+    )]
+    layer_mismatch: Mismatch,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value = "warn",
+        help = "Check the sha256 of every layer against its diff_id from the image config while extracting it: fail, warn or off; the result is recorded in Image.md"
+    )]
+    verify_digests: Verification,
+
+    #[arg(
+        long,
+        value_name = "PLATFORM",
+        help = "Platform to convert from a multi-platform image (e.g., linux/arm64, linux/arm/v7)"
+    )]
+    platform: Option<Platform>,
+
+    #[arg(
+        long,
+        conflicts_with = "platform",
+        help = "Convert every platform of a multi-platform image, each into its own branch"
+    )]
+    all_platforms: bool,
+
+    #[arg(
+        long,
+        value_name = "REPO:TAG",
+        help = "Image to convert from an archive holding several (docker save img1 img2) [default: the first one]"
+    )]
+    select: Option<String>,
+
+    #[arg(
+        long,
+        help = "Skip the filesystem: layers become empty commits carrying their digests, with Image.md and the history only (registry engine: no layer blobs are downloaded)"
+    )]
+    metadata_only: bool,
+
+    #[arg(
+        long,
+        value_name = "N|DIGEST",
+        help = "Commit the layers before layer N (1-based, as in Image.md) or the layer with DIGEST as empty commits carrying their digests, so rootfs/ only holds the application layers; the branch name ends in #from-layer-N"
+    )]
+    from_layer: Option<FromLayer>,
+
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        conflicts_with = "from_layer",
+        help = "Commit the layers of the base image IMAGE (fetched with the same engine) as empty commits carrying their digests, so rootfs/ only holds the layers added on top of it; like --from-layer"
+    )]
+    skip_base: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "metadata_only",
+        help = "Apply all layers and commit only the final filesystem (plus the metadata commit), into a branch ending in #squashed"
+    )]
+    squash: bool,
+
+    #[arg(
+        long,
+        help = "Build layer commits straight from the layer tarballs instead of unpacking every layer into rootfs/ and staging it; rootfs/ is checked out once at the end"
+    )]
+    direct_commits: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "LAYOUT",
+        help = "How images are laid out: branches (a branch per image) or subdir (images/<name>-<tag>/ with rootfs/ and Image.md, all on the branch 'images') [default: branches]"
+    )]
+    layout: Option<RepoLayout>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Name of the directory holding the image filesystem, e.g. fs or chroot [default: rootfs]"
+    )]
+    rootfs_dir: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "N",
+        help = "Write and hash the files of a layer on N threads [default: number of CPUs]"
+    )]
+    jobs: Option<NonZeroUsize>,
+
+    #[arg(
+        long,
+        help = "If a layer fails to extract, finish the branch at the last good layer with a metadata commit marked PARTIAL (PARTIAL.md describes the failure)"
+    )]
+    commit_partial: bool,
+
+    #[arg(
+        long,
+        help = "Record owner, group, mode, mtime and xattrs of every path in .oci2git/fsmeta.jsonl, committed with each layer"
+    )]
+    fs_metadata: bool,
+
+    #[arg(
+        long,
+        help = "Commit .oci2git/deleted-files.txt with every layer that deletes paths (whiteouts, opaque directories), listing what it deleted"
+    )]
+    record_deletions: bool,
+
+    #[arg(
+        long,
+        help = "Commit .oci2git/content-hashes.jsonl with every layer: path, size and sha256 of each file it wrote; the analyze command reports duplicated content from it"
+    )]
+    content_hashes: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value = "md",
+        help = "Image metadata files to commit: md (Image.md), json (Image.json) or both"
+    )]
+    metadata_format: MetadataFileFormat,
+
+    #[arg(
+        long,
+        help = "Commit .oci2git/env.sh (image environment) and .oci2git/run.sh (workdir, entrypoint and command) with the metadata commit"
+    )]
+    env_scripts: bool,
+
+    #[arg(
+        long,
+        help = "Write the image environment into rootfs/etc/profile.d/oci2git-env.sh, and exposed ports and volumes into ports.txt and volumes.txt"
+    )]
+    materialize_config: bool,
+
+    #[arg(
+        long,
+        help = "Commit the image config (environment, labels, entrypoint, command, user, workdir, exposed ports) as it was after each layer to config/ with every layer commit"
+    )]
+    config_history: bool,
+
+    #[arg(
+        long,
+        help = "Commit LayerStats.md (LayerStats.json with --metadata-format json|both) with the metadata commit: files and bytes each layer added and deleted, and its largest files"
+    )]
+    layer_stats: bool,
+
+    #[arg(
+        long,
+        help = "End Image.md with a Mermaid diagram of the layer chain: the bytes every layer added and the layers shared with the other image branches of the repository"
+    )]
+    layer_graph: bool,
+
+    #[arg(
+        long,
+        help = "Commit a README.md with the metadata commit summarizing the image (name, digest, platform, layer count, largest layers, entrypoint), shown by forges on the branch page"
+    )]
+    emit_readme: bool,
+
+    #[arg(
+        long,
+        help = "Commit report/index.html with the metadata commit: a self-contained page with a chart of the layer sizes, the biggest files, the environment and labels, and the layer commits"
+    )]
+    html_report: bool,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Link the layer commits of --html-report to URL, with {commit} replaced by the commit id, e.g. https://github.com/acme/images/commit/{commit}"
+    )]
+    report_commit_url: Option<String>,
+
+    #[arg(
+        long,
+        help = "Convert the base image the image declares (org.opencontainers.image.base.name annotation or label) into the repository first, so the image branches from its layer commits"
+    )]
+    convert_base: bool,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Commit files larger than this as stubs (path, size, sha256) listed in .oci2git/large-files.jsonl"
+    )]
+    max_file_size: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        help = "How sparse files (VM images, preallocated database files) are committed: expand (full content, holes kept on disk) or stub (a stub with the apparent size and bytes of data, listed in .oci2git/large-files.jsonl) [default: expand]"
+    )]
+    sparse_files: Option<SparseMode>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        help = "How symlink targets are committed: host (absolute paths below rootfs/ of the converting machine) or image (as in the image, e.g. /usr/bin/python3; recorded in .oci2git/symlink-targets) [default: host]"
+    )]
+    symlink_targets: Option<SymlinkMode>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHERE",
+        help = "Where layer commits record the layer digests and history entries: files (layers/0001.json, ...) or notes (git notes in refs/notes/oci2git, keeping the worktree clean) [default: files]"
+    )]
+    layer_records: Option<LayerRecordsMode>,
+
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        help = "Leave paths matching PATTERN out of every layer, e.g. usr/share/doc or '**/__pycache__' (* and ? match within a path component, ** any number of components); repeat for several"
+    )]
+    exclude: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Subject of layer commits (placeholders: {marker}, {command}, {index}, {digest}, {created}) [default: {marker} - {command}]"
+    )]
+    commit_template: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Branch name template, e.g. '{name}/{tag}/{arch}' (placeholders: {name}, {tag}, {os}, {arch}, {digest}, {source}) [default: the engine's image#tag#os-arch#digest scheme]"
+    )]
+    branch_template: Option<BranchTemplate>,
+
+    #[arg(
+        long,
+        help = "Create an annotated tag at the tip of the converted branch, named like nginx-1.27.0-amd64-sha256-<digest>"
+    )]
+    tag: bool,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Name of the tag, same placeholders as --branch-template (implies --tag) [default: {name}-{tag}-{arch}-sha256-{digest}]"
+    )]
+    tag_template: Option<BranchTemplate>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Author and committer name of the created commits [default: oci2git]"
+    )]
+    git_author: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "EMAIL",
+        help = "Author and committer e-mail of the created commits [default: oci2git@example.com]"
+    )]
+    git_email: Option<String>,
+
+    #[arg(
+        long,
+        help = "Sign every commit with the key configured for git (gpg.format, user.signingkey)"
+    )]
+    sign_commits: bool,
+
+    #[arg(
+        long,
+        value_name = "KEY",
+        requires = "sign_commits",
+        help = "Signing key instead of user.signingkey: GPG key ID, or SSH key file with --signing-format ssh"
+    )]
+    signing_key: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        requires = "sign_commits",
+        help = "Signing key type instead of gpg.format (openpgp, ssh)"
+    )]
+    signing_format: Option<KeyFormat>,
+
+    #[arg(
+        long,
+        help = "Keep the extraction directory of a layer that fails to extract (under $TMPDIR/oci2git-failed) and print its location"
+    )]
+    keep_failed: bool,
+
     #[arg(
         short,
         long,
@@ -43,62 +709,1330 @@ struct Cli {
         help = "Verbose mode (-v for info, -vv for debug, -vvv for trace). Also switches to text-based progress"
     )]
     verbose: u8,
+
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["verbose", "log", "progress"],
+        help = "Print nothing but errors; stdout only gets the branch of every converted image, one per line (the JSON summary instead with --summary)"
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        value_name = "FILTER",
+        help = "Per-subsystem log levels, e.g. extract=trace,git=warn or just extract (subsystems: source, extract, git, metadata, convert; RUST_LOG-style module paths work too). Switches to text-based progress"
+    )]
+    log: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        help = "How progress is shown: fancy (spinner and progress bars), plain (log lines, also without -v, e.g. for CI logs), none, or auto (fancy without -v and --log) [default: auto]"
+    )]
+    progress: Option<Progress>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Format of the log lines: text, or json (one object per line with timestamp, level, target, event and fields; progress is reported as events instead of bars) [default: text]"
+    )]
+    log_format: Option<LogLines>,
+
+    #[arg(
+        long,
+        help = "Print a JSON summary of the run to stdout when done: branch, layer commits and digests, skipped layers, bytes processed and elapsed time of every image"
+    )]
+    summary: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the JSON summary of the run (see --summary) to this file"
+    )]
+    summary_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the time spent per phase (pull, unpack, analyze, apply and commit of every layer, metadata, gc) of every image to this JSON file"
+    )]
+    perf_report: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the first layers of a converted branch as a single commit (reversible `git replace` graft)
+    SquashHistory {
+        #[arg(help = "Converted Git repository")]
+        repo: PathBuf,
+
+        #[arg(help = "Branch to squash")]
+        branch: String,
+
+        #[arg(
+            long,
+            value_name = "N",
+            help = "Squash layers 1..=N into one commit (e.g. all base image layers)"
+        )]
+        layers: usize,
+    },
+    /// Rebuild an image tarball from a converted branch, one layer per commit (including commits made on top)
+    Export {
+        #[arg(help = "Converted Git repository")]
+        repo: PathBuf,
+
+        #[arg(help = "Branch to export")]
+        branch: String,
+
+        #[arg(
+            short,
+            long,
+            help = "Image tarball to write (OCI layout, loadable with docker load)"
+        )]
+        output: PathBuf,
+
+        #[arg(
+            long,
+            help = "Tag of the exported image [default: first tag in Image.md]"
+        )]
+        tag: Option<String>,
+
+        #[arg(
+            long,
+            help = "Recreate device nodes and FIFOs from .oci2git/special-files.jsonl"
+        )]
+        restore_special_files: bool,
+    },
+    /// Print image metadata and per-layer change statistics as JSON, optionally filtered through jq
+    Query {
+        #[arg(help = "Converted Git repository")]
+        repo: PathBuf,
+
+        #[arg(long, help = "Only this branch [default: every image branch]")]
+        branch: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "FILTER",
+            help = "jq filter to apply, e.g. '.images[].layers[] | select(.bytes_added > 50e6)' (requires jq)"
+        )]
+        jq: Option<String>,
+
+        #[arg(short, long, requires = "jq", help = "Pass --raw-output to jq")]
+        raw_output: bool,
+    },
+    /// Convert two images into one repository and report how they differ (layers, files, sizes, metadata)
+    Diff {
+        #[arg(help = "First image (A)")]
+        image_a: String,
+
+        #[arg(help = "Second image (B)")]
+        image_b: String,
+
+        #[arg(
+            short,
+            long,
+            default_value = "./container_repo",
+            help = "Output directory for Git repository"
+        )]
+        output: PathBuf,
+
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value = "docker",
+            help = "Container engine to use (docker, podman, nerdctl, tar, registry, containerd)"
+        )]
+        engine: Engine,
+
+        #[arg(
+            long,
+            default_value = "default",
+            help = "containerd namespace to export images from (containerd engine)"
+        )]
+        namespace: String,
+
+        #[arg(
+            long,
+            value_name = "PLATFORM",
+            help = "Platform to convert from multi-platform images (e.g., linux/arm64)"
+        )]
+        platform: Option<Platform>,
+
+        #[arg(
+            long,
+            help = "Never access the network; fail instead of pulling a missing image"
+        )]
+        offline: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "md",
+            help = "Report format: md (DIFF.md) or json"
+        )]
+        format: ReportFormat,
+
+        #[arg(
+            short,
+            long,
+            value_name = "FILE",
+            help = "Write the report to this file (e.g. DIFF.md) instead of stdout"
+        )]
+        report: Option<PathBuf>,
+    },
+    /// Report the largest files of every layer and the space wasted on files later layers delete or overwrite
+    Analyze {
+        #[arg(help = "Converted Git repository, or an image to convert first")]
+        target: String,
+
+        #[arg(
+            long,
+            help = "Only print this branch [default: every image branch; the converted one for an image]"
+        )]
+        branch: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 10,
+            help = "Largest files to list per layer"
+        )]
+        top: usize,
+
+        #[arg(
+            short,
+            long,
+            default_value = "./container_repo",
+            help = "Output directory for Git repository (when converting an image)"
+        )]
+        output: PathBuf,
+
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value = "docker",
+            help = "Container engine to use (docker, podman, nerdctl, tar, registry, containerd)"
+        )]
+        engine: Engine,
+
+        #[arg(
+            long,
+            default_value = "default",
+            help = "containerd namespace to export images from (containerd engine)"
+        )]
+        namespace: String,
+
+        #[arg(
+            long,
+            value_name = "PLATFORM",
+            help = "Platform to convert from multi-platform images (e.g., linux/arm64)"
+        )]
+        platform: Option<Platform>,
+
+        #[arg(
+            long,
+            help = "Never access the network; fail instead of pulling a missing image"
+        )]
+        offline: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "md",
+            help = "Output format: md (ANALYSIS.md) or json"
+        )]
+        format: ReportFormat,
+    },
+    /// Print the layer history of an image (the table of Image.md) without converting it
+    Inspect {
+        #[arg(help = "Image to inspect (or tarball path with the tar engine)")]
+        image: String,
+
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value = "docker",
+            help = "Container engine to use (docker, podman, nerdctl, tar, registry, containerd)"
+        )]
+        engine: Engine,
+
+        #[arg(
+            long,
+            default_value = "default",
+            help = "containerd namespace to export images from (containerd engine)"
+        )]
+        namespace: String,
+
+        #[arg(
+            long,
+            value_name = "PLATFORM",
+            help = "Platform to inspect from multi-platform images (e.g., linux/arm64)"
+        )]
+        platform: Option<Platform>,
+
+        #[arg(
+            long,
+            help = "Never access the network; fail instead of pulling a missing image"
+        )]
+        offline: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "table",
+            help = "Output format: table or json"
+        )]
+        format: TableFormat,
+    },
+    /// List the images converted into a repository: name, tag, digest, platform, layers and conversion date
+    List {
+        #[arg(
+            short,
+            long,
+            default_value = "./container_repo",
+            help = "Converted Git repository"
+        )]
+        output: PathBuf,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "table",
+            help = "Output format: table or json"
+        )]
+        format: TableFormat,
+    },
+    /// Show which layers of a converted image created, modified or deleted a path
+    FileLog {
+        #[arg(
+            short,
+            long,
+            default_value = "./container_repo",
+            help = "Converted Git repository"
+        )]
+        output: PathBuf,
+
+        #[arg(long, help = "Image branch [default: the only image branch]")]
+        branch: Option<String>,
+
+        #[arg(help = "Path in the image filesystem, e.g. /etc/nginx/nginx.conf")]
+        path: String,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "table",
+            help = "Output format: table or json"
+        )]
+        format: TableFormat,
+    },
+    /// Delete old image branches and repack the repository to free their space
+    Prune {
+        #[arg(
+            short,
+            long,
+            default_value = "./container_repo",
+            help = "Converted Git repository"
+        )]
+        output: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "N",
+            help = "Keep the N most recently converted branches of every image and platform"
+        )]
+        keep_last: Option<usize>,
+
+        #[arg(
+            long,
+            value_name = "AGE",
+            help = "Only delete branches converted longer ago than this, e.g. 90d (units: s, m, h, d, w)"
+        )]
+        older_than: Option<String>,
+
+        #[arg(
+            long,
+            help = "Print the branches that would be deleted, change nothing"
+        )]
+        dry_run: bool,
+    },
+    /// Check out an image branch into a directory of its own (a Git worktree managed by oci2git)
+    Checkout {
+        #[arg(
+            short,
+            long,
+            default_value = "./container_repo",
+            help = "Converted Git repository"
+        )]
+        output: PathBuf,
+
+        #[arg(
+            requires = "to",
+            required_unless_present_any = ["list", "remove", "remove_all"],
+            help = "Branch to check out"
+        )]
+        branch: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "DIR",
+            requires = "branch",
+            help = "Directory to check the branch out into (new or empty)"
+        )]
+        to: Option<PathBuf>,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["branch", "remove", "remove_all"],
+            help = "List the worktrees added by oci2git"
+        )]
+        list: bool,
+
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["branch", "remove_all"],
+            help = "Delete the worktree checked out into DIR, with the directory"
+        )]
+        remove: Option<PathBuf>,
+
+        #[arg(
+            long,
+            conflicts_with = "branch",
+            help = "Delete every worktree added by oci2git, with their directories"
+        )]
+        remove_all: bool,
+    },
+}
+
+/// Exits with the code of the [`Oci2GitError`] that caused the failure, 1 for any other
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            ExitCode::from(
+                error
+                    .downcast_ref::<Oci2GitError>()
+                    .map_or(1, Oci2GitError::exit_code),
+            )
+        }
+    }
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn run() -> Result<()> {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    // Exports the conversion spans with the otel feature and an OTLP endpoint
+    #[cfg(feature = "otel")]
+    let telemetry = oci2git::telemetry::init_from_env()?;
+    #[cfg(not(feature = "otel"))]
+    let telemetry: Option<()> = None;
+
+    if let Some(command) = &cli.command {
+        with_telemetry(Notifier::silent(), telemetry.as_ref()).install();
+        return run_command(command);
+    }
+    apply_config(&mut cli, &matches)?;
+
+    // `registry://<image>` selects the registry engine, e.g. for pinned digests
+    let images: Vec<(Engine, String)> = cli
+        .images
+        .iter()
+        .map(|image| match image.strip_prefix(REGISTRY_SCHEME) {
+            Some(stripped) => (Engine::Registry, stripped.to_string()),
+            None => (cli.engine, image.clone()),
+        })
+        .collect();
+    if images.is_empty() {
+        return Err(Oci2GitError::InvalidInput(anyhow!("No image given")).into());
+    }
+    if cli.all_platforms && images.iter().any(|(engine, _)| *engine == Engine::Tar) {
+        return Err(Oci2GitError::InvalidInput(anyhow!(
+            "--all-platforms is not supported by the tar engine; the tarball holds a single image"
+        ))
+        .into());
+    }
+
+    if cli.offline {
+        if let Some((_, image)) = images
+            .iter()
+            .find(|(engine, _)| *engine == Engine::Registry)
+        {
+            return Err(offline_registry_error().context(format!("Cannot convert '{image}'")));
+        }
+    }
+
+    let registry = registry_access(&cli)?;
+    if cli.parallel > 1 {
+        prefetch(&cli, &images, &registry)?;
+    }
 
-    // Create notifier with verbosity level
-    let notifier = Notifier::new(cli.verbose);
+    // Consecutive images of the same engine share one processor; all of them are converted
+    // into the same repository, so later images reuse the layers of earlier ones
+    let started = Instant::now();
+    let mut summary = RunSummary::new();
+    let mut failed = Vec::new();
+    let mut first_error = None;
+    for group in images.chunk_by(|(a, _), (b, _)| a == b) {
+        let engine = group[0].0;
 
-    notifier.debug(&format!("Output directory: {}", cli.output.display()));
-    notifier.debug(&format!("Engine: {:?}", cli.engine));
-    notifier.debug(&format!(
-        "Beautiful progress: {}",
-        notifier.use_beautiful_progress()
-    ));
+        // Create notifier with verbosity level
+        let notifier = if cli.quiet {
+            Notifier::silent()
+        } else {
+            Notifier::with_output(
+                cli.verbose,
+                cli.log.as_deref().unwrap_or_default(),
+                cli.progress.unwrap_or(Progress::Auto).into(),
+                cli.log_format.unwrap_or(LogLines::Text).into(),
+            )?
+        };
+        let notifier = with_telemetry(notifier, telemetry.as_ref());
+        // The first one also takes the `log` macros and spans of the whole run
+        notifier.install();
 
-    match cli.engine {
-        Engine::Docker => {
-            notifier.info(&format!(
-                "Starting oci2git with Docker engine, image: {}",
-                cli.image
-            ));
-            notifier.debug("Initializing Docker source");
+        notifier.debug(&format!("Output directory: {}", cli.output.display()));
+        notifier.debug(&format!("Engine: {engine:?}"));
+        notifier.debug(&format!(
+            "Beautiful progress: {}",
+            notifier.use_beautiful_progress()
+        ));
 
-            let source = DockerSource::new()
-                .map_err(|e| anyhow!("Failed to initialize Docker source: {e}"))?;
+        let source = create_source(
+            engine,
+            &cli.namespace,
+            cli.platform.clone(),
+            cli.metadata_only,
+            cli.blob_cache.as_deref(),
+            pull_policy(cli.offline, cli.no_pull),
+            &registry,
+        )?;
+        // A batch is repacked once at the end instead of after every image
+        let mut processor = configure(ImageProcessor::new(source, notifier), &cli)?
+            .with_gc(!cli.no_gc && images.len() == 1);
+        for (_, image) in group {
+            match convert(&mut processor, image, &cli) {
+                Ok(summaries) => summaries.into_iter().for_each(|s| summary.push(s)),
+                Err(error) => {
+                    let error = anyhow::Error::new(error);
+                    summary.push_failed(image, &error);
+                    // A single image fails as it always did; a batch converts the rest first
+                    if images.len() == 1 {
+                        summary.elapsed_seconds = started.elapsed().as_secs_f64();
+                        write_summary(&summary, &cli)?;
+                        write_perf_report(&summary, Duration::ZERO, &cli)?;
+                        return Err(error);
+                    }
+                    failed.push(format!("{image}: {error:#}"));
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+    }
+    let mut batch_gc = Duration::ZERO;
+    if !cli.no_gc
+        && images.len() > 1
+        && summary
+            .images
+            .iter()
+            .any(|image| image.status == ConversionStatus::Converted)
+    {
+        let repacking = Instant::now();
+        let stats = GitRepo::open(&cli.output)?.repack()?;
+        batch_gc = repacking.elapsed();
+        log::info!(
+            "Repacked {} objects: {} -> {}",
+            stats.objects,
+            format_bytes(stats.bytes_before),
+            format_bytes(stats.bytes_after)
+        );
+    }
+    summary.elapsed_seconds = started.elapsed().as_secs_f64();
+    write_summary(&summary, &cli)?;
+    write_perf_report(&summary, batch_gc, &cli)?;
+    if cli.quiet && !cli.summary {
+        for image in &summary.images {
+            println!("{}", image.branch);
+        }
+    }
 
-            let processor = ImageProcessor::new(source, notifier);
-            processor.convert(&cli.image, &cli.output)?;
+    // A batch exits with the code of its first failure
+    if let Some(error) = first_error {
+        return Err(error.context(format!(
+            "Failed to convert {} of {} images:\n  {}",
+            failed.len(),
+            images.len(),
+            failed.join("\n  ")
+        )));
+    }
+    // Nothing changed: scripts tell this apart from a conversion by the exit code
+    if let Some(duplicate) = summary.images.first().filter(|_| {
+        summary
+            .images
+            .iter()
+            .all(|image| image.status == ConversionStatus::SkippedDuplicate)
+    }) {
+        return Err(Oci2GitError::DuplicateImage {
+            image: duplicate.image.clone(),
+            branch: duplicate.branch.clone(),
         }
-        Engine::Nerdctl => {
-            notifier.info(&format!(
-                "Starting oci2git with nerdctl engine, image: {}",
-                cli.image
-            ));
-            notifier.debug("Initializing nerdctl source");
+        .into());
+    }
+    Ok(())
+}
 
-            let source = NerdctlSource::new()
-                .map_err(|e| anyhow!("Failed to initialize nerdctl source: {e}"))?;
+/// Downloads the blobs of the registry images with `--parallel` workers
+fn prefetch(cli: &Cli, images: &[(Engine, String)], registry: &RegistryAccess) -> Result<()> {
+    let registry_images: Vec<String> = images
+        .iter()
+        .filter(|(engine, _)| *engine == Engine::Registry)
+        .map(|(_, image)| image.clone())
+        .collect();
+    if registry_images.len() < 2 {
+        return Ok(());
+    }
 
-            let processor = ImageProcessor::new(source, notifier);
-            processor.convert(&cli.image, &cli.output)?;
+    let source = registry_source(
+        cli.platform.clone(),
+        cli.metadata_only,
+        cli.blob_cache.as_deref(),
+        registry,
+    )?;
+    log::info!(
+        "Prefetching {} images with {} workers",
+        registry_images.len(),
+        cli.parallel
+    );
+    for (image, result) in registry_images
+        .iter()
+        .zip(source.prefetch(&registry_images, cli.parallel))
+    {
+        // The conversion of the image reports the error again
+        if let Err(error) = result {
+            log::warn!("{image}: {error:#}");
         }
-        Engine::Tar => {
-            notifier.info(&format!(
-                "Starting oci2git with tar engine, tarball: {}",
-                cli.image
-            ));
-            notifier.debug("Initializing tar source");
+    }
+    Ok(())
+}
+
+/// How the registry engine logs in and connects
+#[derive(Default)]
+struct RegistryAccess {
+    credentials: CredentialStore,
+    /// `None` for the proxy from the environment and the built-in CA roots
+    transport: Option<Transport>,
+}
+
+/// Credentials from `--username` and the password on stdin (or those of `docker login`),
+/// CA certificates from `--registry-ca` and `--insecure-registry` hosts
+fn registry_access(cli: &Cli) -> Result<RegistryAccess> {
+    let transport = Transport::new()?
+        .with_ca_certificates(cli.registry_ca.clone())?
+        .with_insecure_registries(cli.insecure_registry.clone());
+    let Some(username) = &cli.username else {
+        return Ok(RegistryAccess {
+            credentials: CredentialStore::new(),
+            transport: Some(transport),
+        });
+    };
+    let mut password = String::new();
+    std::io::stdin()
+        .read_to_string(&mut password)
+        .context("Failed to read the registry password from stdin")?;
+    let password = password.trim_end_matches(['\n', '\r']);
+    if password.is_empty() {
+        bail!("--password-stdin: no password on stdin");
+    }
+    Ok(RegistryAccess {
+        credentials: CredentialStore::new()
+            .with_credentials(Some(Credentials::new(username, password))),
+        transport: Some(transport),
+    })
+}
+
+fn registry_source(
+    platform: Option<Platform>,
+    metadata_only: bool,
+    blob_cache: Option<&Path>,
+    registry: &RegistryAccess,
+) -> Result<RegistrySource> {
+    let source = RegistrySource::new()
+        .map_err(|e| anyhow!("Failed to initialize registry source: {e}"))?
+        .with_platform(platform)
+        .with_metadata_only(metadata_only)
+        .with_credentials(registry.credentials.clone());
+    let source = match &registry.transport {
+        Some(transport) => source.with_transport(transport.clone()),
+        None => source,
+    };
+    Ok(match blob_cache {
+        Some(dir) => source.with_blob_cache(BlobCache::new(dir)),
+        None => source,
+    })
+}
+
+/// The registry engine always downloads
+fn offline_registry_error() -> anyhow::Error {
+    anyhow!("The registry engine downloads images, which --offline forbids; convert an image of the local daemon, a tarball or an OCI directory instead")
+}
 
-            let source =
-                TarSource::new().map_err(|e| anyhow!("Failed to initialize tar source: {e}"))?;
+/// When the docker, podman and containerd engines may pull
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PullPolicy {
+    /// Images missing locally, and the platform asked for
+    IfMissing,
+    /// Never (`--no-pull`)
+    Never,
+    /// Never, and no other network access either (`--offline`)
+    Offline,
+}
 
-            let processor = ImageProcessor::new(source, notifier);
-            processor.convert(&cli.image, &cli.output)?;
+/// `notifier`, also exporting its spans and events with `telemetry`
+#[cfg(feature = "otel")]
+fn with_telemetry(
+    notifier: Notifier,
+    telemetry: Option<&oci2git::telemetry::Telemetry>,
+) -> Notifier {
+    match telemetry {
+        Some(telemetry) => {
+            notifier.with_tracing_layer(telemetry.layer(), oci2git::telemetry::MAX_LEVEL)
         }
+        None => notifier,
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn with_telemetry(notifier: Notifier, _telemetry: Option<&()>) -> Notifier {
+    notifier
+}
+
+fn pull_policy(offline: bool, no_pull: bool) -> PullPolicy {
+    match (offline, no_pull) {
+        (true, _) => PullPolicy::Offline,
+        (false, true) => PullPolicy::Never,
+        (false, false) => PullPolicy::IfMissing,
     }
+}
+
+/// The source for `engine`, fetching `platform` where supported and pulling as `pull` allows
+fn create_source(
+    engine: Engine,
+    namespace: &str,
+    platform: Option<Platform>,
+    metadata_only: bool,
+    blob_cache: Option<&Path>,
+    pull: PullPolicy,
+    registry: &RegistryAccess,
+) -> Result<Box<dyn Source>> {
+    log::debug!("Initializing {engine:?} source");
+    let offline = pull == PullPolicy::Offline;
+    let pull = pull == PullPolicy::IfMissing;
+    Ok(match engine {
+        Engine::Docker => Box::new(
+            DockerSource::new()
+                .map_err(|e| anyhow!("Failed to initialize Docker source: {e}"))?
+                .with_platform(platform)
+                .with_offline(offline)
+                .with_pull(pull),
+        ),
+        Engine::Podman => Box::new(
+            PodmanSource::new()
+                .map_err(|e| anyhow!("Failed to initialize Podman source: {e}"))?
+                .with_platform(platform)
+                .with_offline(offline)
+                .with_pull(pull),
+        ),
+        Engine::Nerdctl => Box::new(
+            NerdctlSource::new()
+                .map_err(|e| anyhow!("Failed to initialize nerdctl source: {e}"))?
+                .with_platform(platform),
+        ),
+        Engine::Tar => Box::new(
+            TarSource::new()
+                .map_err(|e| anyhow!("Failed to initialize tar source: {e}"))?
+                .with_platform(platform),
+        ),
+        Engine::Registry if offline => return Err(offline_registry_error()),
+        Engine::Registry => Box::new(registry_source(
+            platform,
+            metadata_only,
+            blob_cache,
+            registry,
+        )?),
+        Engine::Containerd => {
+            log::debug!("containerd namespace: {namespace}");
+            Box::new(
+                ContainerdSource::new()
+                    .map_err(|e| anyhow!("Failed to initialize containerd source: {e}"))?
+                    .with_namespace(namespace.to_string())
+                    .with_platform(platform)
+                    .with_offline(offline)
+                    .with_pull(pull),
+            )
+        }
+    })
+}
 
+/// Fills the options not given on the command line from the environment, the repository's
+/// config file (`--config`, or `.oci2git.toml` in the output directory) and the global
+/// config file, in this order
+fn apply_config(cli: &mut Cli, matches: &ArgMatches) -> Result<()> {
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let global = match ConfigFile::global_path() {
+        Some(path) => ConfigFile::load(&path)?.unwrap_or_default(),
+        None => ConfigFile::default(),
+    };
+    let env = ConfigFile::from_env(std::env::vars())?;
+
+    // The repository's file is found through the output directory
+    if !given("output") {
+        if let Some(output) = env.output.clone().or_else(|| global.output.clone()) {
+            cli.output = output;
+        }
+    }
+    let path = cli
+        .config
+        .clone()
+        .unwrap_or_else(|| cli.output.join(CONFIG_FILE));
+    let repository = match ConfigFile::load(&path)? {
+        Some(config) if config.output.is_some() => bail!(
+            "Invalid config file {}: 'output' can only be set in the global config file or OCI2GIT_OUTPUT",
+            path.display()
+        ),
+        Some(config) => config,
+        None if cli.config.is_some() => bail!("Config file {} not found", path.display()),
+        None => ConfigFile::default(),
+    };
+    let config = env.merge(repository.merge(global));
+
+    if !given("engine") {
+        if let Some(engine) = &config.engine {
+            cli.engine = Engine::from_str(engine, true)
+                .map_err(|_| anyhow!("Invalid engine '{engine}' in the configuration"))?;
+        }
+    }
+    if !given("namespace") {
+        if let Some(namespace) = config.namespace {
+            cli.namespace = namespace;
+        }
+    }
+    if cli.exclude.is_empty() {
+        cli.exclude = config.exclude;
+    }
+    cli.workdir = cli.workdir.take().or(config.workdir);
+    cli.blob_cache = cli.blob_cache.take().or(config.blob_cache);
+    if !cli.no_conversion_cache {
+        cli.no_conversion_cache =
+            cli.conversion_cache.is_none() && config.no_conversion_cache.unwrap_or_default();
+    }
+    cli.conversion_cache = cli.conversion_cache.take().or(config.conversion_cache);
+    if !cli.offline {
+        cli.offline = config.offline.unwrap_or_default();
+    }
+    if !cli.no_pull {
+        cli.no_pull = config.no_pull.unwrap_or_default();
+    }
+    if !cli.no_gc {
+        cli.no_gc = config.no_gc.unwrap_or_default();
+    }
+    cli.max_file_size = cli.max_file_size.or(config.max_file_size);
+    cli.rootfs_dir = cli.rootfs_dir.take().or(config.rootfs_dir);
+    cli.report_commit_url = cli.report_commit_url.take().or(config.report_commit_url);
+    cli.jobs = cli.jobs.or(config.jobs.and_then(NonZeroUsize::new));
+    cli.max_temp_bytes = cli.max_temp_bytes.or(config.max_temp_bytes);
+    cli.commit_template = cli.commit_template.take().or(config.commit_template);
+    cli.branch_template = cli.branch_template.take().or(config.branch_template);
+    cli.tag_template = cli.tag_template.take().or(config.tag_template);
+    cli.git_author = cli.git_author.take().or(config.git_author);
+    cli.git_email = cli.git_email.take().or(config.git_email);
+    if !cli.sign_commits {
+        cli.sign_commits = config.sign_commits.unwrap_or_default();
+    }
+    cli.signing_key = cli.signing_key.take().or(config.signing_key);
+    if cli.progress.is_none() {
+        if let Some(progress) = &config.progress {
+            cli.progress =
+                Some(Progress::from_str(progress, true).map_err(|_| {
+                    anyhow!("Invalid progress mode '{progress}' in the configuration")
+                })?);
+        }
+    }
+    if cli.log_format.is_none() {
+        if let Some(format) = &config.log_format {
+            cli.log_format = Some(
+                LogLines::from_str(format, true)
+                    .map_err(|_| anyhow!("Invalid log format '{format}' in the configuration"))?,
+            );
+        }
+    }
+    if cli.sparse_files.is_none() {
+        if let Some(mode) = &config.sparse_files {
+            cli.sparse_files =
+                Some(SparseMode::from_str(mode, true).map_err(|_| {
+                    anyhow!("Invalid sparse files mode '{mode}' in the configuration")
+                })?);
+        }
+    }
+    if cli.symlink_targets.is_none() {
+        if let Some(mode) = &config.symlink_targets {
+            cli.symlink_targets = Some(SymlinkMode::from_str(mode, true).map_err(|_| {
+                anyhow!("Invalid symlink targets mode '{mode}' in the configuration")
+            })?);
+        }
+    }
+    if cli.layer_records.is_none() {
+        if let Some(mode) = &config.layer_records {
+            cli.layer_records = Some(LayerRecordsMode::from_str(mode, true).map_err(|_| {
+                anyhow!("Invalid layer records mode '{mode}' in the configuration")
+            })?);
+        }
+    }
+    if cli.layout.is_none() {
+        if let Some(layout) = &config.layout {
+            cli.layout = Some(
+                RepoLayout::from_str(layout, true)
+                    .map_err(|_| anyhow!("Invalid layout '{layout}' in the configuration"))?,
+            );
+        }
+    }
+    if cli.signing_format.is_none() {
+        if let Some(format) = &config.signing_format {
+            cli.signing_format =
+                Some(KeyFormat::from_str(format, true).map_err(|_| {
+                    anyhow!("Invalid signing format '{format}' in the configuration")
+                })?);
+        }
+    }
+    Ok(())
+}
+
+/// Applies the conversion options from `cli`
+fn configure<S: Source>(processor: ImageProcessor<S>, cli: &Cli) -> Result<ImageProcessor<S>> {
+    Ok(processor
+        .with_workdir(Workdir::new(cli.workdir.clone()).with_keep(cli.keep_workdir))
+        .with_max_temp_bytes(cli.max_temp_bytes)
+        .with_disk_space_check(if cli.force {
+            DiskSpaceCheck::Warn
+        } else {
+            DiskSpaceCheck::Fail
+        })
+        .with_force(cli.force)
+        .with_conflict_strategy(cli.conflict_strategy.into())
+        .with_layer_mismatch(cli.layer_mismatch.into())
+        .with_digest_verification(cli.verify_digests.into())
+        .with_fs_metadata(cli.fs_metadata)
+        .with_record_deletions(cli.record_deletions)
+        .with_content_hashes(cli.content_hashes)
+        .with_selected_image(cli.select.clone())
+        .with_metadata_format(cli.metadata_format.into())
+        .with_env_scripts(cli.env_scripts)
+        .with_materialize_config(cli.materialize_config)
+        .with_config_history(cli.config_history)
+        .with_layer_stats(cli.layer_stats)
+        .with_layer_graph(cli.layer_graph)
+        .with_emit_readme(cli.emit_readme)
+        .with_html_report(cli.html_report)
+        .with_report_commit_url(cli.report_commit_url.clone())
+        .with_convert_base(cli.convert_base)
+        .with_max_file_size(cli.max_file_size)
+        .with_sparse_files(cli.sparse_files.unwrap_or(SparseMode::Expand).into())
+        .with_symlink_targets(cli.symlink_targets.unwrap_or(SymlinkMode::Host).into())
+        .with_layer_records(cli.layer_records.unwrap_or(LayerRecordsMode::Files).into())
+        .with_exclude(PathFilter::new(&cli.exclude))
+        .with_commit_message_template(cli.commit_template.clone())
+        .with_metadata_only(cli.metadata_only)
+        .with_from_layer(
+            cli.skip_base
+                .clone()
+                .map(FromLayer::Base)
+                .or_else(|| cli.from_layer.clone()),
+        )
+        .with_squash(cli.squash)
+        .with_conversion_cache((!cli.no_conversion_cache).then(|| {
+            ConversionCache::new(
+                cli.conversion_cache
+                    .clone()
+                    .unwrap_or_else(ConversionCache::default_dir),
+            )
+        }))
+        .with_direct_commits(cli.direct_commits)
+        .with_layout(cli.layout.unwrap_or(RepoLayout::Branches).into())
+        .with_rootfs_dir(cli.rootfs_dir.clone())
+        .with_jobs(
+            cli.jobs
+                .or_else(|| std::thread::available_parallelism().ok())
+                .map_or(1, NonZeroUsize::get),
+        )
+        .with_commit_partial(cli.commit_partial)
+        .with_commit_identity(commit_identity(cli)?)
+        .with_branch_naming(
+            cli.branch_template
+                .clone()
+                .map(|template| Box::new(template) as Box<dyn BranchNamingStrategy>),
+        )
+        .with_tag_template(tag_template(cli)?)
+        .with_keep_failed(
+            cli.keep_failed
+                .then(ImageProcessor::<S>::failed_extractions_dir),
+        ))
+}
+
+/// Converts `image` with a processor from [`configure`]; one summary per branch
+fn convert<S: Source>(
+    processor: &mut ImageProcessor<S>,
+    image: &str,
+    cli: &Cli,
+) -> Result<Vec<ConversionSummary>, Oci2GitError> {
+    if cli.all_platforms {
+        Ok(processor.convert_all_platforms(image, &cli.output)?.1)
+    } else {
+        Ok(vec![processor.convert(image, &cli.output)?])
+    }
+}
+
+/// Prints `summary` with `--summary` and writes it to `--summary-file`
+fn write_summary(summary: &RunSummary, cli: &Cli) -> Result<()> {
+    if !cli.summary && cli.summary_file.is_none() {
+        return Ok(());
+    }
+    let json = serde_json::to_string_pretty(summary)? + "\n";
+    if let Some(path) = &cli.summary_file {
+        fs::write(path, &json).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    if cli.summary {
+        print!("{json}");
+    }
+    Ok(())
+}
+
+/// Writes the phase timings of `summary` to `--perf-report`; `batch_gc` is the repack
+/// after a batch
+fn write_perf_report(summary: &RunSummary, batch_gc: Duration, cli: &Cli) -> Result<()> {
+    let Some(path) = &cli.perf_report else {
+        return Ok(());
+    };
+    let json = serde_json::to_string_pretty(&PerfReport::new(summary, batch_gc))? + "\n";
+    fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Tag names from `--tag-template`, or the default ones with `--tag`
+fn tag_template(cli: &Cli) -> Result<Option<BranchTemplate>> {
+    match &cli.tag_template {
+        Some(template) => Ok(Some(template.clone())),
+        None if cli.tag => Ok(Some(BranchTemplate::new(DEFAULT_TAG_TEMPLATE)?)),
+        None => Ok(None),
+    }
+}
+
+/// Commit author and signer from `--git-author`, `--git-email` and `--sign-commits`
+fn commit_identity(cli: &Cli) -> Result<CommitIdentity> {
+    let mut identity = CommitIdentity::default();
+    if let Some(name) = &cli.git_author {
+        identity.name = name.clone();
+    }
+    if let Some(email) = &cli.git_email {
+        identity.email = email.clone();
+    }
+    if cli.sign_commits {
+        identity.signer = Some(CommitSigner::from_git_config(
+            cli.signing_format.map(Into::into),
+            cli.signing_key.clone(),
+        )?);
+    }
+    Ok(identity)
+}
+
+fn run_command(command: &Command) -> Result<()> {
+    match command {
+        Command::SquashHistory {
+            repo,
+            branch,
+            layers,
+        } => {
+            let squashed = squash_history(&GitRepo::open(repo)?, branch, *layers)?;
+            println!(
+                "Squashed layers 1-{layers} of '{branch}' into {} (undo with `git replace -d {}`)",
+                squashed.commit, squashed.commit
+            );
+        }
+        Command::Export {
+            repo,
+            branch,
+            output,
+            tag,
+            restore_special_files,
+        } => {
+            let options = ExportOptions {
+                tag: tag.clone(),
+                restore_special_files: *restore_special_files,
+            };
+            let image = export_branch(&GitRepo::open(repo)?, branch, output, &options)?;
+            println!(
+                "Exported '{branch}' as {} layers to {} (manifest {})",
+                image.layers.len(),
+                output.display(),
+                image.manifest_digest
+            );
+        }
+        Command::Query {
+            repo,
+            branch,
+            jq,
+            raw_output,
+        } => {
+            let document = query::collect(&GitRepo::open(repo)?, branch.as_deref())?;
+            match jq {
+                Some(filter) => {
+                    let args: &[&str] = if *raw_output { &["-r"] } else { &[] };
+                    print!("{}", query::jq(&document, filter, args)?);
+                }
+                None => println!("{}", serde_json::to_string_pretty(&document)?),
+            }
+        }
+        Command::Diff {
+            image_a,
+            image_b,
+            output,
+            engine,
+            namespace,
+            platform,
+            offline,
+            format,
+            report,
+        } => {
+            let mut branches = Vec::new();
+            for image in [image_a, image_b] {
+                let (engine, image) = match image.strip_prefix(REGISTRY_SCHEME) {
+                    Some(stripped) => (Engine::Registry, stripped),
+                    None => (*engine, image.as_str()),
+                };
+                let source = create_source(
+                    engine,
+                    namespace,
+                    platform.clone(),
+                    false,
+                    None,
+                    pull_policy(*offline, false),
+                    &RegistryAccess::default(),
+                )?;
+                let processor = ImageProcessor::new(source, Notifier::new(0));
+                let plan = processor.analyze(image, output)?;
+                processor.materialize(&plan, output)?;
+                branches.push(plan.branch.name);
+            }
+
+            let diff =
+                image_diff::diff_branches(&GitRepo::open(output)?, &branches[0], &branches[1])?;
+            let rendered = match format {
+                ReportFormat::Md => diff.render_markdown(),
+                ReportFormat::Json => serde_json::to_string_pretty(&diff)? + "\n",
+            };
+            match report {
+                Some(path) => {
+                    fs::write(path, rendered)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    println!(
+                        "Compared '{}' and '{}', report written to {}",
+                        branches[0],
+                        branches[1],
+                        path.display()
+                    );
+                }
+                None => print!("{rendered}"),
+            }
+        }
+        Command::Analyze {
+            target,
+            branch,
+            top,
+            output,
+            engine,
+            namespace,
+            platform,
+            offline,
+            format,
+        } => {
+            let (repo_dir, branch) = if Path::new(target).is_dir() {
+                (PathBuf::from(target), branch.clone())
+            } else {
+                let (engine, image) = match target.strip_prefix(REGISTRY_SCHEME) {
+                    Some(stripped) => (Engine::Registry, stripped),
+                    None => (*engine, target.as_str()),
+                };
+                let source = create_source(
+                    engine,
+                    namespace,
+                    platform.clone(),
+                    false,
+                    None,
+                    pull_policy(*offline, false),
+                    &RegistryAccess::default(),
+                )?;
+                let processor = ImageProcessor::new(source, Notifier::new(0));
+                let plan = processor.analyze(image, output)?;
+                processor.materialize(&plan, output)?;
+                (
+                    output.clone(),
+                    Some(branch.clone().unwrap_or(plan.branch.name)),
+                )
+            };
+
+            // ANALYSIS.md always covers every image of the repository
+            let repo = GitRepo::open(&repo_dir)?;
+            let mut analysis = analyze::analyze(&repo, None, *top)?;
+            analyze::commit(&repo, &analysis)?;
+            if let Some(branch) = &branch {
+                analysis.images.retain(|image| &image.branch == branch);
+                if analysis.images.is_empty() {
+                    bail!("Branch '{branch}' doesn't exist");
+                }
+            }
+            match format {
+                ReportFormat::Md => print!("{}", analysis.render_markdown()),
+                ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&analysis)?),
+            }
+        }
+        Command::Inspect {
+            image,
+            engine,
+            namespace,
+            platform,
+            offline,
+            format,
+        } => {
+            let (engine, image) = match image.strip_prefix(REGISTRY_SCHEME) {
+                Some(stripped) => (Engine::Registry, stripped),
+                None => (*engine, image.as_str()),
+            };
+            let source = create_source(
+                engine,
+                namespace,
+                platform.clone(),
+                false,
+                None,
+                pull_policy(*offline, false),
+                &RegistryAccess::default(),
+            )?;
+            // Analyzing writes nothing; the branch is planned against an empty directory
+            let scratch = tempfile::tempdir()?;
+            let plan =
+                ImageProcessor::new(source, Notifier::new(0)).analyze(image, scratch.path())?;
+            let inspection = Inspection::new(image, plan.extracted_image())?;
+            match format {
+                TableFormat::Table => print!("{}", inspection.render_table()),
+                TableFormat::Json => println!("{}", serde_json::to_string_pretty(&inspection)?),
+            }
+        }
+        Command::List { output, format } => {
+            let images = list::list(&GitRepo::open(output)?)?;
+            match format {
+                TableFormat::Table => print!("{}", list::render_table(&images)),
+                TableFormat::Json => println!("{}", serde_json::to_string_pretty(&images)?),
+            }
+        }
+        Command::FileLog {
+            output,
+            branch,
+            path,
+            format,
+        } => {
+            let repo = ConvertedRepo::open(output)?;
+            let branch = match branch {
+                Some(branch) => branch.clone(),
+                None => match repo.branches()?.as_slice() {
+                    [branch] => branch.clone(),
+                    [] => bail!("{} has no image branches", output.display()),
+                    branches => bail!(
+                        "{} has {} image branches, choose one with --branch",
+                        output.display(),
+                        branches.len()
+                    ),
+                },
+            };
+            let entries = file_log::file_log(&repo, &branch, path)?;
+            match format {
+                TableFormat::Table => print!("{}", file_log::render_table(&entries)),
+                TableFormat::Json => println!("{}", serde_json::to_string_pretty(&entries)?),
+            }
+        }
+        Command::Prune {
+            output,
+            keep_last,
+            older_than,
+            dry_run,
+        } => {
+            let options = PruneOptions {
+                keep_last: *keep_last,
+                older_than: older_than.as_deref().map(prune::parse_age).transpose()?,
+                dry_run: *dry_run,
+            };
+            let report = prune::prune(&GitRepo::open(output)?, &options, chrono::Utc::now())?;
+            let verb = if *dry_run { "Would delete" } else { "Deleted" };
+            for branch in &report.deleted {
+                println!("{verb} {branch}");
+            }
+            println!(
+                "{verb} {} branches, kept {}",
+                report.deleted.len(),
+                report.kept.len()
+            );
+            if let Some(repack) = report.repack {
+                println!(
+                    "Repacked {} objects: {} -> {}",
+                    repack.objects,
+                    format_bytes(repack.bytes_before),
+                    format_bytes(repack.bytes_after)
+                );
+            }
+        }
+        Command::Checkout {
+            output,
+            branch,
+            to,
+            list,
+            remove,
+            remove_all,
+        } => {
+            let repo = GitRepo::open(output)?;
+            if *list {
+                for worktree in worktrees::list(&repo)? {
+                    println!(
+                        "{}  {}  {}",
+                        worktree.path.display(),
+                        worktree.branch.as_deref().unwrap_or("-"),
+                        worktree
+                            .commit
+                            .as_deref()
+                            .map_or("-", |commit| &commit[..12])
+                    );
+                }
+            } else if let Some(path) = remove {
+                let removed = worktrees::remove(&repo, path)?;
+                println!("Removed worktree {}", removed.path.display());
+            } else if *remove_all {
+                let removed = worktrees::remove_all(&repo)?;
+                println!("Removed {} worktrees", removed.len());
+            } else if let (Some(branch), Some(to)) = (branch, to) {
+                let worktree = worktrees::checkout(&repo, branch, to)?;
+                println!(
+                    "Checked out '{branch}' into {} (image filesystem in {})",
+                    worktree.path.display(),
+                    worktree
+                        .rootfs
+                        .as_deref()
+                        .unwrap_or(&worktree.path)
+                        .display()
+                );
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,183 @@
+//! Runtime config applied to the tree: environment, exposed ports and volumes.
+//!
+//! Environment variables, exposed ports and volumes only exist in the image config, so
+//! tooling that reads nothing but the converted tree (linters, scanners, `grep`) never
+//! sees them. With `--materialize-config` the metadata commit puts them where such tools
+//! look:
+//! - [`PROFILE_PATH`] in `rootfs/` exports the image environment for login shells,
+//! - [`PORTS_TXT`] lists the exposed ports, one per line,
+//! - [`VOLUMES_TXT`] lists the declared volumes, one per line.
+//!
+//! The lists are sorted and only written when the image declares anything.
+
+use crate::env_scripts;
+use crate::layer_apply::{ensure_within_root, EntryKind};
+use crate::metadata::ContainerConfig;
+use crate::tree_apply::RootfsTree;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Location of the environment profile, relative to the rootfs directory.
+pub const PROFILE_PATH: &str = "etc/profile.d/oci2git-env.sh";
+/// Exposed ports, at the repository root.
+pub const PORTS_TXT: &str = "ports.txt";
+/// Declared volumes, at the repository root.
+pub const VOLUMES_TXT: &str = "volumes.txt";
+
+/// Contents of [`PROFILE_PATH`]
+pub fn render_profile(config: &ContainerConfig) -> String {
+    format!(
+        "# Environment of the image, generated by oci2git\n{}",
+        env_scripts::exports(&config.env)
+    )
+}
+
+/// The keys of a config map, sorted, one per line; `None` when there are none.
+fn render_list(entries: Option<&HashMap<String, serde_json::Value>>) -> Option<String> {
+    let mut keys: Vec<_> = entries?.keys().collect();
+    if keys.is_empty() {
+        return None;
+    }
+    keys.sort();
+    Some(keys.into_iter().map(|key| format!("{key}\n")).collect())
+}
+
+/// Writes [`PORTS_TXT`] and [`VOLUMES_TXT`] below `repo_dir`, and [`PROFILE_PATH`] into
+/// `tree` or, without one, below `rootfs`.
+///
+/// The profile is skipped with a warning when `etc/profile.d` is not a directory in the
+/// image.
+pub fn write(
+    repo_dir: &Path,
+    rootfs: &Path,
+    tree: Option<&mut RootfsTree>,
+    config: &ContainerConfig,
+) -> Result<()> {
+    for (name, list) in [
+        (PORTS_TXT, render_list(config.exposed_ports.as_ref())),
+        (VOLUMES_TXT, render_list(config.volumes.as_ref())),
+    ] {
+        if let Some(list) = list {
+            let path = repo_dir.join(name);
+            fs::write(&path, list)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+
+    if config.env.is_empty() {
+        return Ok(());
+    }
+    let profile = render_profile(config);
+    // etc, then etc/profile.d: both must be directories (or missing)
+    let mut dirs: Vec<_> = Path::new(PROFILE_PATH)
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    dirs.reverse();
+    match tree {
+        Some(tree) => {
+            for dir in dirs {
+                if tree
+                    .entry_kind(dir)?
+                    .is_some_and(|kind| kind != EntryKind::Directory)
+                {
+                    log::warn!(
+                        "Skipping {PROFILE_PATH}, {} is not a directory",
+                        dir.display()
+                    );
+                    return Ok(());
+                }
+            }
+            tree.add_file(Path::new(PROFILE_PATH), profile.as_bytes())
+                .with_context(|| format!("Failed to add {PROFILE_PATH}"))
+        }
+        None => {
+            let root = rootfs
+                .canonicalize()
+                .with_context(|| format!("Failed to resolve rootfs: {}", rootfs.display()))?;
+            for dir in dirs {
+                if fs::symlink_metadata(root.join(dir)).is_ok_and(|metadata| !metadata.is_dir()) {
+                    log::warn!(
+                        "Skipping {PROFILE_PATH}, {} is not a directory",
+                        dir.display()
+                    );
+                    return Ok(());
+                }
+            }
+            let path = root.join(PROFILE_PATH);
+            ensure_within_root(&root, &path)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            fs::write(&path, profile).with_context(|| format!("Failed to write {PROFILE_PATH}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn config() -> ContainerConfig {
+        let keys = |keys: &[&str]| {
+            Some(
+                keys.iter()
+                    .map(|key| (key.to_string(), serde_json::json!({})))
+                    .collect(),
+            )
+        };
+        ContainerConfig {
+            env: vec!["PATH=/usr/bin:/bin".to_string()],
+            cmd: None,
+            entrypoint: None,
+            exposed_ports: keys(&["8080/tcp", "53/udp"]),
+            working_dir: None,
+            volumes: keys(&["/data", "/cache"]),
+            labels: None,
+            user: None,
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        fs::create_dir_all(&rootfs).unwrap();
+        write(temp_dir.path(), &rootfs, None, &config()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join(PORTS_TXT)).unwrap(),
+            "53/udp\n8080/tcp\n"
+        );
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join(VOLUMES_TXT)).unwrap(),
+            "/cache\n/data\n"
+        );
+        assert!(fs::read_to_string(rootfs.join(PROFILE_PATH))
+            .unwrap()
+            .contains("export PATH='/usr/bin:/bin'\n"));
+
+        // Nothing declared, nothing written; a file in the way of the profile is kept
+        let other = tempdir().unwrap();
+        let rootfs = other.path().join("rootfs");
+        fs::create_dir_all(rootfs.join("etc")).unwrap();
+        fs::write(rootfs.join("etc/profile.d"), "x").unwrap();
+        let empty = ContainerConfig {
+            exposed_ports: None,
+            volumes: Some(HashMap::new()),
+            ..config()
+        };
+        write(other.path(), &rootfs, None, &empty).unwrap();
+        assert!(!other.path().join(PORTS_TXT).exists());
+        assert!(!other.path().join(VOLUMES_TXT).exists());
+        assert_eq!(
+            fs::read_to_string(rootfs.join("etc/profile.d")).unwrap(),
+            "x"
+        );
+    }
+}
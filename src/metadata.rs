@@ -3,7 +3,7 @@
 //! Types mirror Docker/`docker inspect` JSON (via serde renames) for
 //! backwards compatibility:
 //! - [`ImageMetadata`] — top-level fields (Id, RepoTags, Created, Architecture, Os, Config, History).
-//! - [`ContainerConfig`] — Env/Cmd/Entrypoint/ExposedPorts/WorkingDir/Volumes/Labels.
+//! - [`ContainerConfig`] — Env/Cmd/Entrypoint/ExposedPorts/WorkingDir/Volumes/Labels/User.
 //! - [`HistoryEntry`] — created/created_by/comment/empty_layer.
 //!
 //! Conversion & formatting:
@@ -44,6 +44,13 @@ pub struct ImageMetadata {
     pub architecture: String,
     #[serde(rename = "Os")]
     pub os: String,
+    /// Annotations of the image manifest, if the source kept them
+    #[serde(
+        default,
+        rename = "Annotations",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub annotations: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,6 +69,8 @@ pub struct ContainerConfig {
     pub volumes: Option<HashMap<String, serde_json::Value>>,
     #[serde(default, rename = "Labels")]
     pub labels: Option<HashMap<String, String>>,
+    #[serde(default, rename = "User")]
+    pub user: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,6 +119,7 @@ pub fn from_oci_config(config: &ImageConfiguration) -> ImageMetadata {
         working_dir: config_obj.and_then(|c| c.working_dir().clone()),
         volumes,
         labels: config_obj.and_then(|c| c.labels().clone()),
+        user: config_obj.and_then(|c| c.user().clone()),
     };
 
     // Convert history entries
@@ -138,6 +148,7 @@ pub fn from_oci_config(config: &ImageConfiguration) -> ImageMetadata {
         history,
         architecture: config.architecture().to_string(),
         os: config.os().to_string(),
+        annotations: HashMap::new(),
     }
 }
 
@@ -294,6 +305,7 @@ mod tests {
                 working_dir: Some("/app".to_string()),
                 volumes: Some(volumes),
                 labels: Some(labels),
+                user: None,
             },
             history: vec![
                 HistoryEntry {
@@ -311,6 +323,7 @@ mod tests {
             ],
             architecture: "amd64".to_string(),
             os: "linux".to_string(),
+            annotations: HashMap::new(),
         }
     }
 
@@ -1,31 +1,106 @@
 //! Unified logging and progress UI.
 //!
-//! [`Notifier`] wraps `env_logger` (text logs) and `indicatif` (spinners/bars) under a single
-//! verbosity switch:
-//! - [`VerbosityLevel::Quiet`] → no text logs; shows a live spinner and optional progress bars.
+//! [`Notifier`] logs through `tracing`, printing the lines with a `tracing_subscriber::fmt`
+//! layer, and draws spinners/bars with `indicatif`. The verbosity decides which log lines are printed, the same way in every [`ProgressMode`]:
+//! - [`VerbosityLevel::Quiet`] → warnings only.
 //! - [`VerbosityLevel::Info`]/[`VerbosityLevel::Debug`]/[`VerbosityLevel::Trace`] → standard logs.
 //!
+//! The [`ProgressMode`] (`--progress`) decides how progress is shown on top of that:
+//! - [`ProgressMode::Fancy`] → a live spinner and progress bars, log lines printed above
+//!   them.
+//! - [`ProgressMode::Plain`] → progress as info log lines, shown even when Quiet, for CI
+//!   logs.
+//! - [`ProgressMode::None`] → no progress at all, just the log lines.
+//! - [`ProgressMode::Auto`] (default) → `fancy` when Quiet without log filters, otherwise
+//!   the text logs alone. `indicatif` draws nothing when stderr isn't a terminal.
+//!
+//! With [`LogFormat::Json`] every log line is a JSON object instead:
+//! `{"timestamp": "<RFC 3339>", "level": "info", "target": "oci2git::git", "event":
+//! "message", "fields": {"message": "..."}}`. [`Notifier::info_event`] logs other events
+//! with their own fields (the [`ProgressEvent`](crate::progress::ProgressEvent)s do), and
+//! since bars would break the lines, `auto` and `fancy` report progress like `plain`.
+//!
 //! What you get:
 //! - [`Notifier::info`]/[`Notifier::debug`]/[`Notifier::warn`]/[`Notifier::trace`] — emit logs
-//!   (or update the Quiet-mode spinner message for `info`).
-//! - [`Notifier::create_progress_bar`] — add a pretty progress bar (Quiet mode only).
-//! - [`Notifier::progress`] — periodic textual progress for non-Quiet modes.
+//!   (`info` also updates the spinner message).
+//! - [`Notifier::create_progress_bar`] — add a pretty progress bar (fancy mode only).
+//! - [`Notifier::progress`] — periodic textual progress without bars.
+//! - [`Notifier::byte_progress`] — byte-based progress with throughput and ETA: a bar, or
+//!   a text line every few seconds.
 //! - [`Notifier::use_beautiful_progress`] — check if UI bars are active.
 //! - [`Notifier::verbosity_level`] — read the current level.
 //!
-//! Levels map to `env_logger` filters; Quiet suppresses logs (≥ Warn) while rendering
-//! spinners/bars via an internal `MultiProgress`.
+//! Every message is a `tracing` event, emitted inside the spans of the conversion phases
+//! (see [`crate::processor`]) and passed through a single [`LogFilter`] before it reaches
+//! the log lines or a layer added with [`Notifier::with_tracing_layer`], like the
+//! OpenTelemetry exporter of the `otel` feature. [`Notifier::install`] makes the notifier
+//! the global `tracing` subscriber and routes the `log` macros used inside the library
+//! into it, so they are filtered and formatted the same way.
+//!
+//! Per-subsystem filters: messages are logged with the module that emitted them as their
+//! target (`oci2git::git`, `oci2git::tar_extractor`, …), so `RUST_LOG`-style directives
+//! apply. [`Notifier::with_log_filter`] accepts such directives plus the subsystem names
+//! of [`SUBSYSTEMS`] (`extract=trace,git=warn`; a bare name means `trace`). Selecting
+//! subsystems switches `auto` to text logs, with everything else at the `-v` level
+//! (warnings only by default).
 
-use env_logger::Env;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use log::{Level, LevelFilter, Log, Record};
+use crate::disk_space::format_bytes;
+use anyhow::{bail, Result};
+use chrono::{SecondsFormat, Utc};
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+use serde_json::json;
 use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::panic::Location;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tracing::field::{Field, FieldSet, Visit};
+use tracing::metadata::Kind;
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Level, Metadata, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter};
+use tracing_subscriber::layer::{Context, Filter, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+use tracing_subscriber::Layer;
+
+/// A `tracing` layer receiving the events and spans of a [`Notifier`].
+pub type TracingLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// How often [`Notifier::byte_progress`] logs a line without a progress bar.
+const BYTE_PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How a [`Notifier`] formats log lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `[<timestamp> <LEVEL> <target>] <message>` text lines
+    #[default]
+    Text,
+    /// One JSON object per line, see the module documentation
+    Json,
+}
+
+/// How a [`Notifier`] shows progress; see the module documentation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// `Fancy` when Quiet without log filters, `None` otherwise
+    #[default]
+    Auto,
+    /// Progress as info log lines
+    Plain,
+    /// Spinner and progress bars
+    Fancy,
+    /// Only log lines
+    None,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VerbosityLevel {
-    Quiet = 0, // Beautiful progress, no text logs
+    Quiet = 0, // Warnings only
     Info = 1,  // Text logs at info level
     Debug = 2, // Text logs at debug level
     Trace = 3, // Text logs at trace level
@@ -45,31 +120,439 @@ impl From<u8> for VerbosityLevel {
 impl VerbosityLevel {
     fn to_log_level(self) -> LevelFilter {
         match self {
-            VerbosityLevel::Quiet => LevelFilter::Warn,
-            VerbosityLevel::Info => LevelFilter::Info,
-            VerbosityLevel::Debug => LevelFilter::Debug,
-            VerbosityLevel::Trace => LevelFilter::Trace,
+            VerbosityLevel::Quiet => LevelFilter::WARN,
+            VerbosityLevel::Info => LevelFilter::INFO,
+            VerbosityLevel::Debug => LevelFilter::DEBUG,
+            VerbosityLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Subsystem names accepted by [`Notifier::with_log_filter`] and the modules they cover.
+pub const SUBSYSTEMS: &[(&str, &[&str])] = &[
+    ("source", &["oci2git::sources", "oci2git::blob_cache"]),
+    (
+        "extract",
+        &[
+            "oci2git::tar_extractor",
+            "oci2git::layer_apply",
+            "oci2git::extracted_image",
+            "oci2git::temp_usage",
+        ],
+    ),
+    (
+        "git",
+        &[
+            "oci2git::git",
+            "oci2git::successor_navigator",
+            "oci2git::digest_tracker",
+            "oci2git::repo_readme",
+            "oci2git::squash",
+        ],
+    ),
+    (
+        "metadata",
+        &[
+            "oci2git::image_metadata",
+            "oci2git::metadata",
+            "oci2git::volumes",
+        ],
+    ),
+    (
+        "convert",
+        &[
+            "oci2git::processor",
+            "oci2git::plan",
+            "oci2git::progress",
+            "oci2git::main",
+        ],
+    ),
+];
+
+/// Expands subsystem names in `spec` into [`LogFilter`] directives.
+///
+/// `extract,git=warn` → `oci2git::tar_extractor=trace,…,oci2git::git=warn,…`. Module paths
+/// (`oci2git::rootfs=debug`) and bare levels (`info`) are passed through unchanged.
+///
+/// # Errors
+/// - Names that are neither a subsystem nor a module path, or unknown levels.
+pub fn expand_log_filter(spec: &str) -> Result<String> {
+    let mut directives = Vec::new();
+
+    for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let (name, level) = match directive.split_once('=') {
+            Some((name, level)) => (name.trim(), level.trim()),
+            None if directive.parse::<LevelFilter>().is_ok() => {
+                directives.push(directive.to_string());
+                continue;
+            }
+            None => (directive, "trace"),
+        };
+        if level.parse::<LevelFilter>().is_err() {
+            bail!("Unknown log level '{level}' in '{directive}'");
         }
+
+        if let Some((_, modules)) = SUBSYSTEMS.iter().find(|(subsystem, _)| *subsystem == name) {
+            directives.extend(modules.iter().map(|module| format!("{module}={level}")));
+        } else if name.contains("::") || name == "oci2git" {
+            directives.push(format!("{name}={level}"));
+        } else {
+            let names: Vec<_> = SUBSYSTEMS.iter().map(|(subsystem, _)| *subsystem).collect();
+            bail!(
+                "Unknown subsystem '{name}' (expected one of {} or a module path)",
+                names.join(", ")
+            );
+        }
+    }
+
+    Ok(directives.join(","))
+}
+
+/// `log` target of the module in source file `file`, e.g. `src/sources/registry.rs` →
+/// `oci2git::sources::registry`.
+fn target_for_file(file: &str) -> String {
+    let file = file.replace('\\', "/");
+    let module = file
+        .rsplit_once("src/")
+        .map_or(file.as_str(), |(_, module)| module)
+        .trim_end_matches(".rs")
+        .trim_end_matches("/mod")
+        .replace('/', "::");
+    match module.as_str() {
+        "lib" => "oci2git".to_string(),
+        _ => format!("oci2git::{module}"),
+    }
+}
+
+/// Which events are logged: `RUST_LOG` directives, applied by an [`EnvFilter`] to the
+/// module that logged each event rather than to the [`Notifier`] itself.
+pub struct LogFilter(EnvFilter);
+
+impl LogFilter {
+    /// Events matching `directives` (`info`, `oci2git::git=trace`, …); invalid ones are
+    /// ignored.
+    pub fn new(directives: &str) -> Self {
+        Self(EnvFilter::builder().parse_lossy(directives))
+    }
+
+    /// `RUST_LOG`, with the level at `level`, then `directives` on top. A later directive
+    /// for the same target wins; `level` is a directive of its own rather than the default
+    /// of the [`EnvFilter`], which would only apply without any module directive.
+    pub fn env_directives(level: LevelFilter, directives: &str) -> String {
+        let env = std::env::var("RUST_LOG").unwrap_or_default();
+        [env.as_str(), &level.to_string(), directives]
+            .into_iter()
+            .filter(|spec| !spec.is_empty())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Spans always pass, so layers see the phases even when their events are filtered.
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Filter<S> for LogFilter {
+    fn enabled(&self, meta: &Metadata<'_>, _: &Context<'_, S>) -> bool {
+        let max_level = Filter::<S>::max_level_hint(&self.0).unwrap_or(LevelFilter::TRACE);
+        meta.is_span() || *meta.level() <= max_level
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, cx: &Context<'_, S>) -> bool {
+        let fields = EventFields::of(event);
+        let normalized = event.normalized_metadata();
+        let meta = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        let meta = Metadata::new(
+            meta.name(),
+            fields.target(meta),
+            *meta.level(),
+            meta.file(),
+            meta.line(),
+            meta.module_path(),
+            FieldSet::new(&[], meta.callsite()),
+            Kind::EVENT,
+        );
+        Filter::<S>::enabled(&self.0, &meta, cx)
+    }
+
+    /// Events are decided one by one, once their module is known; span directives
+    /// (`[extract]=debug`) are registered with the spans.
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        if !meta.is_span() {
+            return Interest::sometimes();
+        }
+        Filter::<S>::callsite_enabled(&self.0, meta);
+        Interest::always()
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Filter::<S>::max_level_hint(&self.0)
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, cx: Context<'_, S>) {
+        Filter::<S>::on_new_span(&self.0, attrs, id, cx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, cx: Context<'_, S>) {
+        Filter::<S>::on_record(&self.0, id, values, cx);
+    }
+
+    fn on_enter(&self, id: &Id, cx: Context<'_, S>) {
+        Filter::<S>::on_enter(&self.0, id, cx);
+    }
+
+    fn on_exit(&self, id: &Id, cx: Context<'_, S>) {
+        Filter::<S>::on_exit(&self.0, id, cx);
+    }
+
+    fn on_close(&self, id: Id, cx: Context<'_, S>) {
+        Filter::<S>::on_close(&self.0, id, cx);
     }
 }
 
+/// The fields of a [`Notifier`] event (or a `log` record).
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    /// Module that logged the event
+    module: Option<String>,
+    /// Event name and JSON fields of [`Notifier::info_event`]
+    event: Option<String>,
+    fields: Option<String>,
+}
+
+impl EventFields {
+    fn of(event: &Event<'_>) -> Self {
+        let mut fields = Self::default();
+        event.record(&mut fields);
+        fields
+    }
+
+    fn target<'a>(&'a self, meta: &'a Metadata<'_>) -> &'a str {
+        self.module.as_deref().unwrap_or(meta.target())
+    }
+}
+
+impl Visit for EventFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "module" => self.module = Some(value.to_string()),
+            "event" => self.event = Some(value.to_string()),
+            "fields" => self.fields = Some(value.to_string()),
+            "message" => self.message = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            "fields" => self.fields = Some(format!("{value:?}")),
+            _ => {}
+        }
+    }
+}
+
+/// Formats the log lines in [`LogFormat`].
+struct LineFormat {
+    format: LogFormat,
+    /// Color the level of text lines
+    ansi: bool,
+}
+
+impl<S, N> FormatEvent<S, N> for LineFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let fields = EventFields::of(event);
+        let normalized = event.normalized_metadata();
+        let meta = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        let (level, target) = (*meta.level(), fields.target(meta));
+        match self.format {
+            LogFormat::Text => {
+                let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+                let level_name = format!("{:<5}", level.as_str());
+                let level_name = match (self.ansi, level) {
+                    (false, _) => level_name,
+                    (true, Level::ERROR) => format!("\x1b[31m{level_name}\x1b[0m"),
+                    (true, Level::WARN) => format!("\x1b[33m{level_name}\x1b[0m"),
+                    (true, Level::INFO) => format!("\x1b[32m{level_name}\x1b[0m"),
+                    (true, Level::DEBUG) => format!("\x1b[34m{level_name}\x1b[0m"),
+                    (true, _) => format!("\x1b[36m{level_name}\x1b[0m"),
+                };
+                writeln!(
+                    writer,
+                    "[{timestamp} {level_name} {target}] {}",
+                    fields.message
+                )
+            }
+            LogFormat::Json => {
+                let values = fields
+                    .fields
+                    .as_deref()
+                    .and_then(|values| serde_json::from_str(values).ok())
+                    .unwrap_or_else(|| json!({ "message": fields.message }));
+                let event = fields.event.as_deref().unwrap_or("message");
+                writeln!(writer, "{}", json_line(level, target, event, values))
+            }
+        }
+    }
+}
+
+/// Standard error, written between redraws of the spinner and bars, if any.
+#[derive(Clone)]
+struct Stderr(Option<Arc<MultiProgress>>);
+
+impl<'a> MakeWriter<'a> for Stderr {
+    type Writer = Stderr;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.0 {
+            Some(multi_progress) => multi_progress.suspend(|| io::stderr().write(buf)),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// The subscriber of a [`Notifier`]: `layer` (if any, up to `layer_level`) and the log
+/// lines, both behind the [`LogFilter`] of `directives`.
+fn dispatch(
+    directives: &str,
+    format: LogFormat,
+    multi_progress: Option<Arc<MultiProgress>>,
+    layer: Option<(TracingLayer, LevelFilter)>,
+) -> Dispatch {
+    let lines = tracing_subscriber::fmt::layer()
+        .event_format(LineFormat {
+            format,
+            ansi: format == LogFormat::Text && io::stderr().is_terminal(),
+        })
+        .with_writer(Stderr(multi_progress))
+        .boxed();
+    let layer = layer.map(|(layer, level)| layer.with_filter(level).boxed());
+    let layers: Vec<TracingLayer> = std::iter::once(lines).chain(layer).collect();
+    Dispatch::new(Registry::default().with(layers.with_filter(LogFilter::new(directives))))
+}
+
 pub struct Notifier {
     verbosity: VerbosityLevel,
-    logger: env_logger::Logger,
+    /// Spinner and progress bars
+    beautiful: bool,
+    mode: ProgressMode,
+    format: LogFormat,
+    /// Directives of the [`LogFilter`]
+    log_filter: String,
+    dispatch: Dispatch,
     multi_progress: Option<Arc<MultiProgress>>,
     active_spinner: RefCell<Option<ProgressBar>>,
+    byte_progress: RefCell<Option<ByteProgress>>,
+}
+
+/// The operation [`Notifier::byte_progress`] currently reports.
+struct ByteProgress {
+    message: String,
+    bar: Option<ProgressBar>,
+    started: Instant,
+    /// When the last line was logged, without a bar
+    logged: Instant,
 }
 
 impl Notifier {
     pub fn new(verbosity_level: u8) -> Self {
-        let verbosity = VerbosityLevel::from(verbosity_level);
+        Self::build(verbosity_level, "", ProgressMode::Auto, LogFormat::Text)
+    }
+
+    /// Prints nothing: no spinner, bars or log lines, not even warnings (`--quiet`).
+    /// `RUST_LOG` is ignored, and the `log` macros of the library stay silent too.
+    pub fn silent() -> Self {
+        let log_filter = LevelFilter::OFF.to_string();
+        Self {
+            dispatch: dispatch(&log_filter, LogFormat::Text, None, None),
+            log_filter,
+            ..Self::build(0, "", ProgressMode::None, LogFormat::Text)
+        }
+    }
 
-        // Create logger instance
-        let logger = env_logger::Builder::from_env(Env::default())
-            .filter_level(verbosity.to_log_level())
-            .build();
+    /// Also sends the events (up to `max_level`) and spans to `layer`, e.g. an exporter;
+    /// replaces the layer of an earlier call.
+    pub fn with_tracing_layer(mut self, layer: TracingLayer, max_level: LevelFilter) -> Self {
+        self.dispatch = dispatch(
+            &self.log_filter,
+            self.format,
+            self.multi_progress.clone(),
+            Some((layer, max_level)),
+        );
+        self
+    }
+
+    /// Makes this notifier the global `tracing` subscriber, and routes the `log` macros of
+    /// the library to it, so spans and log lines from anywhere in the process share its
+    /// filter, format and layer. Only the first notifier installed takes effect; returns
+    /// whether this one did.
+    pub fn install(&self) -> bool {
+        if tracing::dispatcher::set_global_default(self.dispatch.clone()).is_err() {
+            return false;
+        }
+        // Fails only if another `log` logger is set, which then keeps the records
+        let _ = tracing_log::LogTracer::init();
+        true
+    }
+
+    /// Like [`Notifier::new`], with per-subsystem log levels (see [`expand_log_filter`]).
+    ///
+    /// # Errors
+    /// - Invalid filter specification.
+    pub fn with_log_filter(verbosity_level: u8, filter: &str) -> Result<Self> {
+        Self::with_output(verbosity_level, filter, ProgressMode::Auto, LogFormat::Text)
+    }
 
-        let multi_progress = if verbosity == VerbosityLevel::Quiet {
+    /// Like [`Notifier::with_log_filter`], showing progress as `mode` says and writing log
+    /// lines in `format`.
+    ///
+    /// # Errors
+    /// - Invalid filter specification.
+    pub fn with_output(
+        verbosity_level: u8,
+        filter: &str,
+        mode: ProgressMode,
+        format: LogFormat,
+    ) -> Result<Self> {
+        let directives = expand_log_filter(filter)?;
+        Ok(Self::build(verbosity_level, &directives, mode, format))
+    }
+
+    fn build(verbosity_level: u8, directives: &str, mode: ProgressMode, format: LogFormat) -> Self {
+        let verbosity = VerbosityLevel::from(verbosity_level);
+        // Bars would break the JSON lines; progress becomes events instead
+        let mode = match (format, mode) {
+            (LogFormat::Json, ProgressMode::Auto | ProgressMode::Fancy) => ProgressMode::Plain,
+            (_, mode) => mode,
+        };
+        let beautiful = match mode {
+            ProgressMode::Auto => verbosity == VerbosityLevel::Quiet && directives.is_empty(),
+            ProgressMode::Fancy => true,
+            ProgressMode::Plain | ProgressMode::None => false,
+        };
+
+        let log_filter = LogFilter::env_directives(
+            Self::effective_verbosity(verbosity, mode).to_log_level(),
+            directives,
+        );
+        let multi_progress = if beautiful {
             Some(Arc::new(MultiProgress::new()))
         } else {
             None
@@ -77,85 +560,103 @@ impl Notifier {
 
         Self {
             verbosity,
-            logger,
+            beautiful,
+            mode,
+            format,
+            dispatch: dispatch(&log_filter, format, multi_progress.clone(), None),
+            log_filter,
             multi_progress,
             active_spinner: RefCell::new(None),
+            byte_progress: RefCell::new(None),
         }
     }
 
+    /// The plain mode shows progress at the info level even when Quiet
+    fn effective_verbosity(verbosity: VerbosityLevel, mode: ProgressMode) -> VerbosityLevel {
+        match verbosity {
+            VerbosityLevel::Quiet if mode == ProgressMode::Plain => VerbosityLevel::Info,
+            verbosity => verbosity,
+        }
+    }
+
+    #[track_caller]
     pub fn info(&self, message: &str) {
-        match self.verbosity {
-            VerbosityLevel::Quiet => {
-                // Lazy initialize spinner on first info call
-                if self.active_spinner.borrow().is_none() {
-                    if let Some(multi_progress) = &self.multi_progress {
-                        let spinner_style = ProgressStyle::default_spinner()
-                            .template("{spinner:.green} {msg}")
-                            .unwrap();
-
-                        let spinner = multi_progress.add(ProgressBar::new_spinner());
-                        spinner.set_style(spinner_style);
-                        spinner.enable_steady_tick(Duration::from_millis(100));
-
-                        *self.active_spinner.borrow_mut() = Some(spinner);
-                    }
-                }
-
-                // Update spinner message
-                if let Some(spinner) = self.active_spinner.borrow().as_ref() {
-                    spinner.set_message(message.to_string());
-                }
-            }
-            _ => {
-                self.logger.log(
-                    &Record::builder()
-                        .args(format_args!("{message}"))
-                        .level(Level::Info)
-                        .target(module_path!())
-                        .build(),
-                );
+        self.log(Level::INFO, message);
+        self.show_on_spinner(message);
+    }
+
+    /// Like [`Notifier::info`], logged as `event` with `fields` in the JSON format.
+    #[track_caller]
+    pub fn info_event(&self, event: &str, fields: serde_json::Value, message: &str) {
+        match self.format {
+            LogFormat::Text => self.log(Level::INFO, message),
+            LogFormat::Json => self.log_json(event, fields, message),
+        }
+        self.show_on_spinner(message);
+    }
+
+    fn show_on_spinner(&self, message: &str) {
+        if !self.beautiful {
+            return;
+        }
+
+        // Lazy initialize spinner on first info call
+        if self.active_spinner.borrow().is_none() {
+            if let Some(multi_progress) = &self.multi_progress {
+                let spinner_style = ProgressStyle::default_spinner()
+                    .template("{spinner:.green} {msg}")
+                    .unwrap();
+
+                let spinner = multi_progress.add(ProgressBar::new_spinner());
+                spinner.set_style(spinner_style);
+                spinner.enable_steady_tick(Duration::from_millis(100));
+
+                *self.active_spinner.borrow_mut() = Some(spinner);
             }
         }
+
+        // Update spinner message
+        if let Some(spinner) = self.active_spinner.borrow().as_ref() {
+            spinner.set_message(message.to_string());
+        }
     }
 
+    #[track_caller]
     pub fn debug(&self, message: &str) {
-        if self.verbosity != VerbosityLevel::Quiet {
-            self.logger.log(
-                &Record::builder()
-                    .args(format_args!("{message}"))
-                    .level(Level::Debug)
-                    .target(module_path!())
-                    .build(),
-            );
-        }
+        self.log(Level::DEBUG, message);
     }
 
+    #[track_caller]
     pub fn warn(&self, message: &str) {
-        if self.verbosity != VerbosityLevel::Quiet {
-            self.logger.log(
-                &Record::builder()
-                    .args(format_args!("{message}"))
-                    .level(Level::Warn)
-                    .target(module_path!())
-                    .build(),
-            );
-        }
+        self.log(Level::WARN, message);
     }
 
+    #[track_caller]
     pub fn trace(&self, message: &str) {
-        if self.verbosity != VerbosityLevel::Quiet {
-            self.logger.log(
-                &Record::builder()
-                    .args(format_args!("{message}"))
-                    .level(Level::Trace)
-                    .target(module_path!())
-                    .build(),
-            );
-        }
+        self.log(Level::TRACE, message);
+    }
+
+    /// Logs `message` with the caller's module as target, so per-module filters apply;
+    /// above the spinner and bars, if any
+    #[track_caller]
+    fn log(&self, level: Level, message: &str) {
+        let module = target_for_file(Location::caller().file());
+        tracing::dispatcher::with_default(&self.dispatch, || trace_event(level, &module, message));
+    }
+
+    /// Logs `event` with `fields` at the info level, a JSON line of its own, with the
+    /// caller's module as target
+    #[track_caller]
+    fn log_json(&self, event: &str, fields: serde_json::Value, message: &str) {
+        let module = target_for_file(Location::caller().file());
+        tracing::dispatcher::with_default(
+            &self.dispatch,
+            || tracing::info!(module, event, fields = %fields, "{message}"),
+        );
     }
 
     pub fn create_progress_bar(&self, length: u64, message: &str) -> Option<ProgressBar> {
-        if self.verbosity == VerbosityLevel::Quiet {
+        if self.beautiful {
             if let Some(multi_progress) = &self.multi_progress {
                 let progress_style = ProgressStyle::default_bar()
                     .template(
@@ -173,17 +674,354 @@ impl Notifier {
         None
     }
 
+    #[track_caller]
     pub fn progress(&self, current: u64, total: u64, message: &str) {
-        if self.verbosity != VerbosityLevel::Quiet && (current % 100 == 0 || current == total) {
+        if self.shows_text_progress() && (current.is_multiple_of(100) || current == total) {
             self.info(&format!("{message}: {current}/{total}"));
         }
     }
 
+    /// Reports that `done` of `total` bytes of the operation `message` are processed: a
+    /// progress bar with throughput and ETA, or an info line every few seconds. A different
+    /// `message` starts a new operation; [`Notifier::finish_byte_progress`] ends it.
+    #[track_caller]
+    pub fn byte_progress(&self, done: u64, total: u64, message: &str) {
+        let mut current = self.byte_progress.borrow_mut();
+        if current
+            .as_ref()
+            .is_none_or(|progress| progress.message != message)
+        {
+            if let Some(bar) = current.take().and_then(|progress| progress.bar) {
+                bar.finish_and_clear();
+            }
+            let now = Instant::now();
+            *current = Some(ByteProgress {
+                message: message.to_string(),
+                bar: self.create_byte_progress_bar(total, message),
+                started: now,
+                logged: now,
+            });
+        }
+        let Some(progress) = current.as_mut() else {
+            return;
+        };
+
+        match &progress.bar {
+            Some(bar) => {
+                bar.set_length(total);
+                bar.set_position(done);
+            }
+            None if self.shows_text_progress()
+                && progress.logged.elapsed() >= BYTE_PROGRESS_LOG_INTERVAL =>
+            {
+                progress.logged = Instant::now();
+                let elapsed = progress.started.elapsed();
+                let line = format!(
+                    "{message}: {}",
+                    describe_byte_progress(done, total, elapsed)
+                );
+                let (rate, eta) = rate_and_eta(done, total, elapsed).unzip();
+                let fields = json!({
+                    "operation": message,
+                    "done": done,
+                    "total": total,
+                    "bytes_per_sec": rate.map(|rate| rate as u64),
+                    "eta_secs": eta.map(|eta| eta.as_secs()),
+                });
+                drop(current);
+                self.info_event("byte_progress", fields, &line);
+            }
+            None => {}
+        }
+    }
+
+    /// Ends the operation of [`Notifier::byte_progress`], removing its progress bar.
+    pub fn finish_byte_progress(&self) {
+        if let Some(bar) = self
+            .byte_progress
+            .borrow_mut()
+            .take()
+            .and_then(|progress| progress.bar)
+        {
+            bar.finish_and_clear();
+        }
+    }
+
+    fn create_byte_progress_bar(&self, total: u64, message: &str) -> Option<ProgressBar> {
+        let multi_progress = self.multi_progress.as_ref().filter(|_| self.beautiful)?;
+        let style = ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {bytes}/{total_bytes} {binary_bytes_per_sec} ETA {eta} {msg}",
+            )
+            .unwrap()
+            .progress_chars("=> ");
+        let bar = multi_progress.add(ProgressBar::new(total));
+        bar.set_style(style);
+        bar.set_message(message.to_string());
+        Some(bar)
+    }
+
+    /// Progress as log lines: in plain mode, or in auto mode with text logs
+    fn shows_text_progress(&self) -> bool {
+        match self.mode {
+            ProgressMode::Plain => true,
+            ProgressMode::Auto => !self.beautiful,
+            ProgressMode::Fancy | ProgressMode::None => false,
+        }
+    }
+
+    pub fn progress_mode(&self) -> ProgressMode {
+        self.mode
+    }
+
+    pub fn log_format(&self) -> LogFormat {
+        self.format
+    }
+
     pub fn use_beautiful_progress(&self) -> bool {
-        self.verbosity == VerbosityLevel::Quiet
+        self.beautiful
     }
 
     pub fn verbosity_level(&self) -> VerbosityLevel {
         self.verbosity
     }
 }
+
+/// Emits `message` as a `tracing` event of `module` in the current span.
+fn trace_event(level: Level, module: &str, message: &str) {
+    match level {
+        Level::ERROR => tracing::error!(module, "{message}"),
+        Level::WARN => tracing::warn!(module, "{message}"),
+        Level::INFO => tracing::info!(module, "{message}"),
+        Level::DEBUG => tracing::debug!(module, "{message}"),
+        _ => tracing::trace!(module, "{message}"),
+    }
+}
+
+/// Bytes per second and remaining time, once something is done.
+fn rate_and_eta(done: u64, total: u64, elapsed: Duration) -> Option<(f64, Duration)> {
+    let seconds = elapsed.as_secs_f64();
+    if done == 0 || seconds <= 0.0 {
+        return None;
+    }
+    let rate = done as f64 / seconds;
+    Some((
+        rate,
+        Duration::from_secs_f64(total.saturating_sub(done) as f64 / rate),
+    ))
+}
+
+/// A line of the JSON log format.
+fn json_line(level: Level, target: &str, event: &str, fields: serde_json::Value) -> String {
+    json!({
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "level": level.as_str().to_ascii_lowercase(),
+        "target": target,
+        "event": event,
+        "fields": fields,
+    })
+    .to_string()
+}
+
+/// `done` of `total` bytes after `elapsed`, with throughput and ETA, e.g.
+/// `512.0 MiB/2.0 GiB (128.0 MiB/s, ETA 12 seconds)`.
+fn describe_byte_progress(done: u64, total: u64, elapsed: Duration) -> String {
+    let progress = format!("{}/{}", format_bytes(done), format_bytes(total));
+    let Some((rate, eta)) = rate_and_eta(done, total, elapsed) else {
+        return progress;
+    };
+    format!(
+        "{progress} ({}/s, ETA {})",
+        format_bytes(rate as u64),
+        HumanDuration(eta)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_expand_log_filter() {
+        assert_eq!(
+            expand_log_filter("git").unwrap(),
+            "oci2git::git=trace,oci2git::successor_navigator=trace,oci2git::digest_tracker=trace,\
+             oci2git::repo_readme=trace,oci2git::squash=trace"
+        );
+        assert_eq!(
+            expand_log_filter("source=debug, oci2git::rootfs=warn,info").unwrap(),
+            "oci2git::sources=debug,oci2git::blob_cache=debug,oci2git::rootfs=warn,info"
+        );
+        assert_eq!(expand_log_filter("").unwrap(), "");
+        assert!(expand_log_filter("gti").is_err());
+        assert!(expand_log_filter("git=loud").is_err());
+    }
+
+    #[test]
+    fn test_target_for_file() {
+        assert_eq!(target_for_file("src/git.rs"), "oci2git::git");
+        assert_eq!(
+            target_for_file("/build/oci2git/src/sources/registry.rs"),
+            "oci2git::sources::registry"
+        );
+        assert_eq!(target_for_file("src\\sources\\mod.rs"), "oci2git::sources");
+        assert_eq!(target_for_file("src/lib.rs"), "oci2git");
+    }
+
+    #[test]
+    fn test_subsystem_filter_switches_to_text_logs() {
+        assert!(Notifier::new(0).use_beautiful_progress());
+        let notifier = Notifier::with_log_filter(0, "extract").unwrap();
+        assert!(!notifier.use_beautiful_progress());
+        assert_eq!(notifier.verbosity_level(), VerbosityLevel::Quiet);
+    }
+
+    #[test]
+    fn test_describe_byte_progress() {
+        assert_eq!(
+            describe_byte_progress(512 << 20, 2 << 30, Duration::from_secs(4)),
+            "512.0 MiB/2.0 GiB (128.0 MiB/s, ETA 12 seconds)"
+        );
+        assert_eq!(
+            describe_byte_progress(0, 1024, Duration::ZERO),
+            "0 B/1.0 KiB"
+        );
+    }
+
+    #[test]
+    fn test_progress_modes() {
+        let notifier = |verbosity, mode| Notifier::build(verbosity, "", mode, LogFormat::Text);
+        assert!(notifier(0, ProgressMode::Auto).use_beautiful_progress());
+        assert!(!notifier(1, ProgressMode::Auto).use_beautiful_progress());
+        assert!(notifier(2, ProgressMode::Fancy).use_beautiful_progress());
+        assert!(!notifier(0, ProgressMode::None).use_beautiful_progress());
+        assert!(!notifier(0, ProgressMode::None).shows_text_progress());
+
+        // Plain progress is visible without -v
+        let plain = notifier(0, ProgressMode::Plain);
+        assert!(!plain.use_beautiful_progress());
+        assert!(plain.shows_text_progress());
+        assert!(passes(&plain.log_filter, Level::INFO, "oci2git::processor"));
+        assert_eq!(plain.verbosity_level(), VerbosityLevel::Quiet);
+
+        // No bars between JSON lines
+        let json = Notifier::build(0, "", ProgressMode::Fancy, LogFormat::Json);
+        assert!(!json.use_beautiful_progress());
+        assert_eq!(json.progress_mode(), ProgressMode::Plain);
+    }
+
+    #[test]
+    fn test_silent() {
+        let notifier = Notifier::silent();
+        assert!(!notifier.use_beautiful_progress());
+        assert!(!notifier.shows_text_progress());
+        assert!(!passes(&notifier.log_filter, Level::ERROR, "oci2git"));
+    }
+
+    /// How many of the events `emit` logs get through the [`LogFilter`] of `directives`
+    fn count(directives: &str, emit: impl FnOnce()) -> usize {
+        struct Count(Arc<AtomicUsize>);
+        impl<S: Subscriber> Layer<S> for Count {
+            fn on_event(&self, _: &Event<'_>, _: Context<'_, S>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let count = Arc::new(AtomicUsize::new(0));
+        let layer = Count(count.clone()).with_filter(LogFilter::new(directives));
+        tracing::subscriber::with_default(Registry::default().with(layer), emit);
+        count.load(Ordering::SeqCst)
+    }
+
+    /// Whether a notifier event at `level` of `target` gets through the [`LogFilter`] of
+    /// `directives`
+    fn passes(directives: &str, level: Level, target: &str) -> bool {
+        count(directives, || trace_event(level, target, "message")) == 1
+    }
+
+    #[test]
+    fn test_log_filter() {
+        // Directives apply to the module that logged the event; the last bare level wins,
+        // as when -v follows RUST_LOG
+        let directives = "debug,warn,oci2git::git=trace,oci2git::git::x=off";
+        assert!(passes(directives, Level::TRACE, "oci2git::git"));
+        assert!(!passes(directives, Level::ERROR, "oci2git::git::x"));
+        assert!(!passes(directives, Level::INFO, "oci2git::processor"));
+        assert!(passes(directives, Level::WARN, "ureq"));
+        assert!(!passes("off", Level::ERROR, "oci2git"));
+
+        // Span directives select the events inside the span
+        let logged = count("warn,[extract]=debug", || {
+            trace_event(Level::DEBUG, "oci2git::layer_apply", "outside");
+            tracing::info_span!("extract").in_scope(|| {
+                trace_event(Level::DEBUG, "oci2git::layer_apply", "inside");
+                trace_event(Level::TRACE, "oci2git::layer_apply", "too detailed");
+            });
+        });
+        assert_eq!(logged, 1);
+    }
+
+    #[test]
+    fn test_log_lines() {
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<std::sync::Mutex<Vec<u8>>>);
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let lines = |format| {
+            let buffer = Buffer::default();
+            let writer = buffer.clone();
+            let layer = tracing_subscriber::fmt::layer()
+                .event_format(LineFormat {
+                    format,
+                    ansi: false,
+                })
+                .with_writer(move || writer.clone())
+                .with_filter(LogFilter::new("debug,oci2git::git=warn"));
+            tracing::subscriber::with_default(Registry::default().with(layer), || {
+                trace_event(Level::WARN, "oci2git::git", "careful");
+                trace_event(Level::INFO, "oci2git::git", "hidden");
+                let fields = json!({ "index": 1 });
+                tracing::info!(module = "oci2git::processor", event = "layer", fields = %fields, "Layer 1");
+            });
+            let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            output.lines().map(str::to_string).collect::<Vec<_>>()
+        };
+
+        let text = lines(LogFormat::Text);
+        assert_eq!(text.len(), 2);
+        assert!(text[0].ends_with(" WARN  oci2git::git] careful"));
+        assert!(text[1].ends_with(" INFO  oci2git::processor] Layer 1"));
+
+        let json = lines(LogFormat::Json);
+        let values: Vec<serde_json::Value> = json
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(values[0]["fields"]["message"], "careful");
+        assert_eq!(values[0]["target"], "oci2git::git");
+        assert_eq!(values[1]["event"], "layer");
+        assert_eq!(values[1]["fields"]["index"], 1);
+    }
+
+    #[test]
+    fn test_json_line() {
+        let line = json_line(
+            Level::WARN,
+            "oci2git::git",
+            "message",
+            json!({ "message": "hi" }),
+        );
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "warn");
+        assert_eq!(value["target"], "oci2git::git");
+        assert_eq!(value["event"], "message");
+        assert_eq!(value["fields"]["message"], "hi");
+        assert!(chrono::DateTime::parse_from_rfc3339(value["timestamp"].as_str().unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,259 @@
+//! Walking the `index.json` of OCI image layouts.
+//!
+//! `docker save` (Docker 25+), `nerdctl save` and `buildx --output type=oci` write OCI image
+//! layouts whose `index.json` doesn't always point at image manifests directly: a
+//! multi-platform image is a nested image index with one manifest per platform, often
+//! next to BuildKit attestation manifests. [`image_manifests`] flattens such a tree into
+//! the image manifests it holds, and [`select`] picks the one of a platform.
+//!
+//! Archives usually only carry the blobs of the platforms that were saved, so entries
+//! whose blob is missing are skipped rather than failing the whole walk.
+
+use crate::blob_cache::digest_hex;
+use crate::sources::Platform;
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Nesting depth beyond which an index is rejected as malformed (or cyclic).
+const MAX_DEPTH: usize = 8;
+
+/// Annotation BuildKit puts on the index entries of attestation manifests.
+const REFERENCE_TYPE: &str = "vnd.docker.reference.type";
+
+/// An image manifest found in an OCI image layout.
+#[derive(Debug, Clone)]
+pub struct ImageManifest {
+    /// Digest of the manifest blob
+    pub digest: String,
+    /// Platform of its index entry, if the index lists one
+    pub platform: Option<Platform>,
+    /// Annotations of the index entries leading to the manifest and of the manifest
+    /// itself, inner ones winning
+    pub annotations: HashMap<String, String>,
+    /// The manifest itself
+    pub manifest: Value,
+}
+
+impl ImageManifest {
+    /// Digest of the image config blob.
+    pub fn config_digest(&self) -> Option<&str> {
+        self.manifest["config"]["digest"].as_str()
+    }
+
+    /// Digests of the layer blobs, base layer first.
+    pub fn layer_digests(&self) -> Vec<&str> {
+        self.manifest["layers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|layer| layer["digest"].as_str())
+            .collect()
+    }
+}
+
+/// Path of blob `digest` relative to the layout root, e.g. `blobs/sha256/<hex>`.
+///
+/// # Errors
+/// - `digest` is not a plain `sha256:<hex>` digest.
+pub fn blob_path(digest: &str) -> Result<String> {
+    Ok(format!("blobs/sha256/{}", digest_hex(digest)?))
+}
+
+/// The JSON blob `digest` of the layout at `layout`, `None` if the archive lacks it.
+fn read_blob(layout: &Path, digest: &str) -> Result<Option<Value>> {
+    let path = layout.join(blob_path(digest)?);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read(&path).with_context(|| format!("Failed to read blob {digest}"))?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .with_context(|| format!("Failed to parse blob {digest}"))
+}
+
+fn annotations(value: &Value) -> impl Iterator<Item = (String, String)> + '_ {
+    value["annotations"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+}
+
+fn platform(descriptor: &Value) -> Option<Platform> {
+    let platform = &descriptor["platform"];
+    Some(Platform {
+        os: platform["os"].as_str()?.to_string(),
+        architecture: platform["architecture"].as_str()?.to_string(),
+        variant: platform["variant"].as_str().map(str::to_string),
+    })
+}
+
+/// Whether an index entry is an attestation manifest rather than an image.
+fn is_attestation(descriptor: &Value) -> bool {
+    descriptor["annotations"][REFERENCE_TYPE].as_str() == Some("attestation-manifest")
+        || descriptor["platform"]["os"].as_str() == Some("unknown")
+}
+
+/// The image manifests `descriptor` (an entry of `index.json`) leads to, in index order.
+///
+/// # Errors
+/// - Indexes nest deeper than a few levels, or a blob present in the archive doesn't parse.
+pub fn image_manifests(layout: &Path, descriptor: &Value) -> Result<Vec<ImageManifest>> {
+    let mut found = Vec::new();
+    walk(layout, descriptor, &HashMap::new(), 0, &mut found)?;
+    Ok(found)
+}
+
+fn walk(
+    layout: &Path,
+    descriptor: &Value,
+    inherited: &HashMap<String, String>,
+    depth: usize,
+    found: &mut Vec<ImageManifest>,
+) -> Result<()> {
+    if depth > MAX_DEPTH {
+        bail!("Image index nested more than {MAX_DEPTH} levels deep");
+    }
+    if is_attestation(descriptor) {
+        return Ok(());
+    }
+    let digest = descriptor["digest"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Image index entry without digest"))?;
+    let Some(blob) = read_blob(layout, digest)? else {
+        return Ok(());
+    };
+
+    let mut annotations = inherited.clone();
+    annotations.extend(self::annotations(descriptor));
+    match blob["manifests"].as_array() {
+        Some(manifests) => {
+            for manifest in manifests {
+                walk(layout, manifest, &annotations, depth + 1, found)?;
+            }
+        }
+        None => {
+            annotations.extend(self::annotations(&blob));
+            found.push(ImageManifest {
+                digest: digest.to_string(),
+                platform: platform(descriptor),
+                annotations,
+                manifest: blob,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The manifest of `manifests` for `platform`, or for the host platform when `None`;
+/// failing that, the first one. Manifests without a listed platform match any.
+///
+/// # Errors
+/// - There are no manifests, or none for the requested `platform`.
+pub fn select<'a>(
+    manifests: &'a [ImageManifest],
+    platform: Option<&Platform>,
+) -> Result<&'a ImageManifest> {
+    let matching = |wanted: &Platform| {
+        manifests.iter().find(|manifest| {
+            manifest
+                .platform
+                .as_ref()
+                .is_none_or(|p| wanted.matches(&p.os, &p.architecture, p.variant.as_deref()))
+        })
+    };
+    match platform {
+        Some(wanted) => matching(wanted).ok_or_else(|| {
+            let available: Vec<_> = manifests
+                .iter()
+                .filter_map(|manifest| manifest.platform.as_ref().map(Platform::to_string))
+                .collect();
+            anyhow!(
+                "Image has no manifest for platform {wanted} (available: {})",
+                available.join(", ")
+            )
+        }),
+        None => matching(&Platform::host())
+            .or(manifests.first())
+            .ok_or_else(|| anyhow!("The image index lists no image manifest")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn add_blob(layout: &Path, name: &str, blob: &Value) -> String {
+        let hex = format!("{:0>64}", name);
+        let dir = layout.join("blobs/sha256");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(&hex), serde_json::to_vec(blob).unwrap()).unwrap();
+        format!("sha256:{hex}")
+    }
+
+    #[test]
+    fn test_nested_index() {
+        let temp_dir = tempdir().unwrap();
+        let layout = temp_dir.path();
+        let manifest = |config: &str| {
+            json!({
+                "config": {"digest": format!("sha256:{config:0>64}")},
+                "layers": [{"digest": format!("sha256:{:0>64}", "1")}],
+            })
+        };
+        let amd64 = add_blob(layout, "a", &manifest("ca"));
+        let arm64 = add_blob(layout, "b", &manifest("cb"));
+        let attestation = add_blob(layout, "c", &json!({"layers": []}));
+        let index = add_blob(
+            layout,
+            "d",
+            &json!({"manifests": [
+                {"digest": amd64, "platform": {"os": "linux", "architecture": "amd64"}},
+                {"digest": arm64, "platform": {"os": "linux", "architecture": "arm64"},
+                 "annotations": {"org.opencontainers.image.base.name": "alpine"}},
+                {"digest": format!("sha256:{:0>64}", "e"),
+                 "platform": {"os": "linux", "architecture": "s390x"}},
+                {"digest": attestation, "platform": {"os": "unknown", "architecture": "unknown"},
+                 "annotations": {REFERENCE_TYPE: "attestation-manifest"}},
+            ]}),
+        );
+        let top = json!({
+            "digest": index,
+            "annotations": {"io.containerd.image.name": "docker.io/library/app:1"},
+        });
+
+        let manifests = image_manifests(layout, &top).unwrap();
+        // The s390x blob isn't in the archive, the attestation isn't an image
+        assert_eq!(manifests.len(), 2);
+        assert_eq!(manifests[1].digest, arm64);
+        assert_eq!(
+            manifests[1].annotations["io.containerd.image.name"],
+            "docker.io/library/app:1"
+        );
+        assert_eq!(
+            manifests[1].annotations["org.opencontainers.image.base.name"],
+            "alpine"
+        );
+        assert_eq!(
+            manifests[0].config_digest(),
+            Some(format!("sha256:{:0>64}", "ca").as_str())
+        );
+        assert_eq!(manifests[0].layer_digests().len(), 1);
+
+        let arm: Platform = "linux/arm64".parse().unwrap();
+        assert_eq!(select(&manifests, Some(&arm)).unwrap().digest, arm64);
+        let s390x: Platform = "linux/s390x".parse().unwrap();
+        assert!(select(&manifests, Some(&s390x)).is_err());
+        assert!(select(&manifests, None).is_ok());
+
+        // An index pointing at itself
+        let cyclic = json!({"digest": format!("sha256:{:0>64}", "f")});
+        add_blob(layout, "f", &json!({"manifests": [cyclic]}));
+        assert!(image_manifests(layout, &cyclic).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Glob patterns for paths inside an image, as used by `--exclude`.
+//!
+//! Patterns are matched against image paths one component at a time, like
+//! `.dockerignore`: `*` matches any part of a single component, `?` a single character and
+//! a `**` component any number of components. A leading `/` is optional. A pattern that
+//! matches a directory also matches everything below it:
+//!
+//! ```text
+//! usr/share/doc        /usr/share/doc and everything in it
+//! **/__pycache__       every __pycache__ directory
+//! var/log/*.log        log files directly in /var/log
+//! ```
+
+use std::path::{Component, Path};
+
+/// A set of patterns; a path matches if any of them does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathFilter {
+    patterns: Vec<Vec<String>>,
+}
+
+impl PathFilter {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        Self {
+            patterns: patterns
+                .iter()
+                .map(|pattern| {
+                    pattern
+                        .as_ref()
+                        .split('/')
+                        .filter(|component| !component.is_empty() && *component != ".")
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .filter(|pattern| !pattern.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether `path` (relative to the image root, or absolute inside the image) or one of
+    /// its parent directories matches a pattern.
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let components: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let components: Vec<&str> = components.iter().map(String::as_str).collect();
+        (1..=components.len()).any(|len| {
+            self.patterns
+                .iter()
+                .any(|pattern| match_components(pattern, &components[..len]))
+        })
+    }
+}
+
+fn match_components(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_components(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                match_component(first.as_bytes(), name.as_bytes())
+                    && match_components(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// `*` and `?` wildcards within a single component.
+fn match_component(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| match_component(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && match_component(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && match_component(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        let filter = PathFilter::new(&["/usr/share/doc", "**/__pycache__", "var/log/*.lo?"]);
+        assert!(filter.matches(Path::new("usr/share/doc")));
+        assert!(filter.matches(Path::new("/usr/share/doc/bash/README")));
+        assert!(!filter.matches(Path::new("usr/share/docs")));
+        assert!(filter.matches(Path::new("app/lib/__pycache__/x.pyc")));
+        assert!(filter.matches(Path::new("__pycache__")));
+        assert!(filter.matches(Path::new("var/log/dpkg.log")));
+        assert!(!filter.matches(Path::new("var/log/apt/term.log")));
+        assert!(!PathFilter::default().matches(Path::new("usr")));
+    }
+}
@@ -0,0 +1,200 @@
+//! Per-phase timings of conversions (`--perf-report`).
+//!
+//! Every [`ConversionSummary`] carries the [`PhaseTimings`] of its conversion: getting the
+//! image from the source (`docker save`, registry download, ...), unpacking the image
+//! tarball, analyzing the layers, applying and committing every layer, the metadata commit
+//! and repacking. A [`PerfReport`] puts those of a run together, with the totals of every
+//! phase, so CI setups can tell whether the source, tar extraction or Git dominates:
+//!
+//! ```text
+//! {"images": [{
+//!   "image": "nginx:1.27",
+//!   "branch": "nginx#1.27#linux-amd64#…",
+//!   "elapsed_seconds": 4.2,
+//!   "phases": {"pull": 1.9, "unpack": 0.4, "analyze": 0.1, "apply": 1.1, "commit": 0.5, "metadata": 0.1, "gc": 0.1},
+//!   "layers": [{"index": 1, "digest": "sha256:…", "bytes": 29154304, "apply_seconds": 0.8, "commit_seconds": 0.3}, …]
+//! }],
+//!  "phases": {"pull": 1.9, …},
+//!  "elapsed_seconds": 4.3}
+//! ```
+//!
+//! Layers reused from an existing branch aren't applied and have no entry.
+
+use crate::summary::{ConversionSummary, RunSummary};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Time spent in each phase of one conversion.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PhaseTimings {
+    /// Getting the image from the source; streamed images are unpacked at the same time.
+    pub pull_seconds: f64,
+    /// Unpacking the image tarball.
+    pub unpack_seconds: f64,
+    /// Reading the layers and metadata, finding the branch point.
+    pub analyze_seconds: f64,
+    /// Layers applied, in order.
+    pub layers: Vec<LayerTiming>,
+    /// Metadata commit, README and tags.
+    pub metadata_seconds: f64,
+    /// Repacking the repository after the conversion.
+    pub gc_seconds: f64,
+}
+
+/// Time spent on one layer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LayerTiming {
+    /// 1-based layer index
+    pub index: usize,
+    pub digest: String,
+    /// Size of the layer tarball.
+    pub bytes: u64,
+    /// Applying the layer tarball to `rootfs/` (or the tree), digest verification included.
+    pub apply_seconds: f64,
+    /// Staging and committing the layer.
+    pub commit_seconds: f64,
+}
+
+/// Seconds per phase, summed over layers or images.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PhaseTotals {
+    pub pull: f64,
+    pub unpack: f64,
+    pub analyze: f64,
+    pub apply: f64,
+    pub commit: f64,
+    pub metadata: f64,
+    pub gc: f64,
+}
+
+impl PhaseTimings {
+    /// The phases of this conversion, layers summed up.
+    pub fn totals(&self) -> PhaseTotals {
+        PhaseTotals {
+            pull: self.pull_seconds,
+            unpack: self.unpack_seconds,
+            analyze: self.analyze_seconds,
+            apply: self.layers.iter().map(|layer| layer.apply_seconds).sum(),
+            commit: self.layers.iter().map(|layer| layer.commit_seconds).sum(),
+            metadata: self.metadata_seconds,
+            gc: self.gc_seconds,
+        }
+    }
+
+    /// The timing of layer `index`, added if it isn't there yet.
+    pub(crate) fn layer(&mut self, index: usize, digest: &str, bytes: u64) -> &mut LayerTiming {
+        if self.layers.last().is_none_or(|layer| layer.index != index) {
+            self.layers.push(LayerTiming {
+                index,
+                digest: digest.to_string(),
+                bytes,
+                ..LayerTiming::default()
+            });
+        }
+        self.layers.last_mut().unwrap()
+    }
+}
+
+impl PhaseTotals {
+    fn add(&mut self, other: &PhaseTotals) {
+        self.pull += other.pull;
+        self.unpack += other.unpack;
+        self.analyze += other.analyze;
+        self.apply += other.apply;
+        self.commit += other.commit;
+        self.metadata += other.metadata;
+        self.gc += other.gc;
+    }
+}
+
+/// The timings of an image in a [`PerfReport`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImagePerf {
+    pub image: String,
+    pub branch: String,
+    pub elapsed_seconds: f64,
+    pub phases: PhaseTotals,
+    pub layers: Vec<LayerTiming>,
+}
+
+/// Timings of all conversions of a run; see the module documentation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PerfReport {
+    pub images: Vec<ImagePerf>,
+    /// Sum over [`PerfReport::images`], plus the repack of a batch after its last image.
+    pub phases: PhaseTotals,
+    pub elapsed_seconds: f64,
+}
+
+impl PerfReport {
+    /// The timings of the images of `run`; `batch_gc` is the time of a repack after all
+    /// of them.
+    pub fn new(run: &RunSummary, batch_gc: Duration) -> Self {
+        let mut report = Self {
+            elapsed_seconds: run.elapsed_seconds,
+            ..Self::default()
+        };
+        for summary in &run.images {
+            let image = ImagePerf::new(summary);
+            report.phases.add(&image.phases);
+            report.images.push(image);
+        }
+        report.phases.gc += batch_gc.as_secs_f64();
+        report
+    }
+}
+
+impl ImagePerf {
+    fn new(summary: &ConversionSummary) -> Self {
+        Self {
+            image: summary.image.clone(),
+            branch: summary.branch.clone(),
+            elapsed_seconds: summary.elapsed_seconds,
+            phases: summary.timings.totals(),
+            layers: summary.timings.layers.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perf_report_totals() {
+        let mut timings = PhaseTimings {
+            pull_seconds: 2.0,
+            unpack_seconds: 0.5,
+            ..PhaseTimings::default()
+        };
+        timings.layer(1, "sha256:aa", 10).apply_seconds = 1.0;
+        timings.layer(1, "sha256:aa", 10).commit_seconds = 0.25;
+        timings.layer(2, "sha256:bb", 20).apply_seconds = 3.0;
+        assert_eq!(timings.layers.len(), 2);
+
+        let mut run = RunSummary::new();
+        for image in ["a", "b"] {
+            run.push(ConversionSummary {
+                image: image.to_string(),
+                branch: image.to_string(),
+                commit: String::new(),
+                status: crate::summary::ConversionStatus::Converted,
+                layers: Vec::new(),
+                layers_skipped: 0,
+                bytes_processed: 0,
+                elapsed_seconds: 7.0,
+                timings: timings.clone(),
+            });
+        }
+        let report = PerfReport::new(&run, Duration::from_secs(1));
+        assert_eq!(report.images[0].phases.apply, 4.0);
+        assert_eq!(report.images[0].phases.commit, 0.25);
+        assert_eq!(report.phases.pull, 4.0);
+        assert_eq!(report.phases.apply, 8.0);
+        assert_eq!(report.phases.gc, 1.0);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["images"][0]["layers"][1]["digest"], "sha256:bb");
+        assert_eq!(json["phases"]["unpack"], 1.0);
+    }
+}
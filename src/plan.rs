@@ -0,0 +1,94 @@
+//! Inspectable conversion plans.
+//!
+//! [`ImagePlan`] is the result of [`crate::processor::ImageProcessor::analyze`]: everything
+//! that is known about an image conversion **before** anything is written to the output
+//! directory:
+//! - image metadata and the ordered [`Layer`] list (with tarball sizes),
+//! - the [`BranchPlan`] — target branch, branch point and how many layers are already
+//!   present in the repository,
+//! - human-readable warnings collected during analysis,
+//! - the temporary disk usage ([`TempUsage`]) needed to hold the unpacked image.
+//!
+//! Callers may inspect or edit a plan (e.g. drop layers) and then hand it to
+//! [`crate::processor::ImageProcessor::materialize`]. The plan owns the extracted image,
+//! so layer tarballs stay on disk for as long as the plan is alive.
+
+use crate::extracted_image::{ExtractedImage, Layer};
+use crate::metadata::ImageMetadata;
+use crate::perf::PhaseTimings;
+use crate::temp_usage::TempUsage;
+
+/// Where and how the converted image will land in the Git repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchPlan {
+    /// Branch that will be created for the image.
+    pub name: String,
+    /// Existing commit to branch from, `None` for an orphan branch.
+    pub start_from: Option<git2::Oid>,
+    /// Number of leading layers already materialized at `start_from`.
+    pub skip_layers: usize,
+    /// `true` when the branch already exists with identical content.
+    pub is_duplicate: bool,
+    /// `true` when an existing branch of that name is deleted and converted again from
+    /// scratch ([`crate::processor::ImageProcessor::with_force`]).
+    pub recreate: bool,
+}
+
+/// A fully analyzed, not yet materialized image conversion.
+pub struct ImagePlan {
+    /// The image reference (or tarball path) the plan was built for.
+    pub image_name: String,
+    /// Image metadata read from the image config.
+    pub metadata: ImageMetadata,
+    /// Layers in chronological order (oldest → newest).
+    ///
+    /// Layers may be removed before materializing; note that
+    /// [`BranchPlan::skip_layers`] always counts from the front of this list.
+    pub layers: Vec<Layer>,
+    /// Target branch and branch point.
+    pub branch: BranchPlan,
+    /// Non-fatal findings, e.g. layers without a tarball.
+    pub warnings: Vec<String>,
+    /// Temporary disk usage recorded while analyzing the image.
+    pub temp_usage: TempUsage,
+    /// Time spent fetching and analyzing the image (see [`crate::perf`]).
+    pub timings: PhaseTimings,
+    extracted_image: ExtractedImage,
+}
+
+impl ImagePlan {
+    pub(crate) fn new(
+        image_name: &str,
+        metadata: ImageMetadata,
+        layers: Vec<Layer>,
+        branch: BranchPlan,
+        extracted_image: ExtractedImage,
+        temp_usage: TempUsage,
+    ) -> Self {
+        Self {
+            image_name: image_name.to_string(),
+            metadata,
+            layers,
+            branch,
+            warnings: Vec::new(),
+            temp_usage,
+            timings: PhaseTimings::default(),
+            extracted_image,
+        }
+    }
+
+    /// The extracted image backing this plan.
+    pub fn extracted_image(&self) -> &ExtractedImage {
+        &self.extracted_image
+    }
+
+    /// Total size in bytes of all layer tarballs in the plan.
+    pub fn total_size(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.size).sum()
+    }
+
+    /// Number of layers that still have to be applied and committed.
+    pub fn layers_to_process(&self) -> usize {
+        self.layers.len().saturating_sub(self.branch.skip_layers)
+    }
+}
@@ -0,0 +1,227 @@
+//! Custom per-layer processing without forking the crate.
+//!
+//! A [`LayerProcessor`] registered with
+//! [`ImageProcessor::with_layer_processor`](crate::processor::ImageProcessor::with_layer_processor)
+//! follows every layer of a conversion, like the built-in sidecars (`--fs-metadata`,
+//! `--content-hashes`, ...) do:
+//!
+//! 1. [`LayerProcessor::on_layer_start`] before the layer is applied,
+//! 2. [`LayerProcessor::on_file`] for every entry the layer applies (not for empty layers),
+//!    and [`LayerProcessor::on_removed`] for every path it deletes,
+//! 3. [`LayerProcessor::on_layer_commit`] once it is applied; the [`PluginFile`]s returned
+//!    are written into the repository and committed with the layer.
+//!
+//! An SBOM generator, a linter or a custom index is a few lines:
+//!
+//! ```no_run
+//! use oci2git::plugins::{LayerInfo, LayerProcessor, PluginFile};
+//! use oci2git::prelude::*;
+//! use std::path::Path;
+//!
+//! /// Lists the shell scripts each layer adds in `scripts.txt`
+//! #[derive(Default)]
+//! struct Scripts(Vec<String>);
+//!
+//! impl LayerProcessor for Scripts {
+//!     fn name(&self) -> &str {
+//!         "scripts"
+//!     }
+//!
+//!     fn on_file(&mut self, path: &Path, _entry: &oci2git::layer_apply::ObservedEntry) {
+//!         if path.extension().is_some_and(|extension| extension == "sh") {
+//!             self.0.push(path.display().to_string());
+//!         }
+//!     }
+//!
+//!     fn on_layer_commit(&mut self, _layer: &LayerInfo) -> anyhow::Result<Vec<PluginFile>> {
+//!         Ok(vec![PluginFile::new("scripts.txt", self.0.join("\n"))])
+//!     }
+//! }
+//!
+//! ImageProcessor::new(RegistrySource::new()?, Notifier::new(1))
+//!     .with_layer_processor(Scripts::default())
+//!     .convert("alpine:3.20", Path::new("./alpine"))?;
+//! # anyhow::Ok(())
+//! ```
+//!
+//! Files stay in the repository, and in later commits, until the plugin writes them again.
+//! Paths are relative to the repository root and can't be in the rootfs directory or take
+//! the names oci2git uses for its own metadata (`Image.md`, `.oci2git`, `layers`, ...).
+//! Layers matched at a branch point are not converted again, so plugins only see the layers
+//! after it, and `--squash` commits their files once, with all layers.
+
+use crate::convert_options::RESERVED_NAMES;
+use crate::extracted_image::Layer;
+use crate::layer_apply::{LayerObserver, ObservedEntry};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/// The layer a [`LayerProcessor`] callback is about.
+pub struct LayerInfo<'a> {
+    /// 1-based layer index
+    pub index: usize,
+    /// Number of layers of the image
+    pub total: usize,
+    pub layer: &'a Layer,
+}
+
+/// A file a [`LayerProcessor`] commits with a layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginFile {
+    /// Relative to the repository root, e.g. `sbom/spdx.json`
+    pub path: PathBuf,
+    pub content: Vec<u8>,
+}
+
+impl PluginFile {
+    pub fn new(path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        Self {
+            path: path.into(),
+            content: content.into(),
+        }
+    }
+}
+
+/// Callbacks of a plugin following every layer of a conversion; see the
+/// [module documentation](self).
+pub trait LayerProcessor: Send {
+    /// Name of the plugin, for messages
+    fn name(&self) -> &str;
+
+    /// `layer` is about to be applied.
+    ///
+    /// # Errors
+    /// - Fails the conversion.
+    fn on_layer_start(&mut self, _layer: &LayerInfo) -> Result<()> {
+        Ok(())
+    }
+
+    /// The current layer applied an entry at `path`, relative to the rootfs directory.
+    fn on_file(&mut self, _path: &Path, _entry: &ObservedEntry) {}
+
+    /// The current layer deleted `path` (and everything below it).
+    fn on_removed(&mut self, _path: &Path) {}
+
+    /// `layer` was applied and is about to be committed, with the files returned.
+    ///
+    /// # Errors
+    /// - Fails the conversion.
+    fn on_layer_commit(&mut self, _layer: &LayerInfo) -> Result<Vec<PluginFile>> {
+        Ok(Vec::new())
+    }
+}
+
+/// The plugins of a processor, each behind a lock since conversions only borrow the
+/// processor.
+#[derive(Default)]
+pub(crate) struct Plugins(Vec<Mutex<Box<dyn LayerProcessor>>>);
+
+impl Plugins {
+    pub(crate) fn push(&mut self, plugin: Box<dyn LayerProcessor>) {
+        self.0.push(Mutex::new(plugin));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn each(&self, mut f: impl FnMut(&mut dyn LayerProcessor) -> Result<()>) -> Result<()> {
+        for plugin in &self.0 {
+            let mut plugin = plugin
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let name = plugin.name().to_string();
+            f(plugin.as_mut()).with_context(|| format!("Plugin '{name}' failed"))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn layer_start(&self, layer: &LayerInfo) -> Result<()> {
+        self.each(|plugin| plugin.on_layer_start(layer))
+    }
+
+    /// Calls [`LayerProcessor::on_layer_commit`] and writes the files returned into
+    /// `repo_dir`, whose image filesystem is in `rootfs_dir`.
+    pub(crate) fn layer_commit(
+        &self,
+        layer: &LayerInfo,
+        repo_dir: &Path,
+        rootfs_dir: &str,
+    ) -> Result<()> {
+        self.each(|plugin| {
+            for file in plugin.on_layer_commit(layer)? {
+                check_path(&file.path, rootfs_dir)?;
+                let path = repo_dir.join(&file.path);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, &file.content)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Forwards what a layer applies to [`LayerProcessor::on_file`] and
+/// [`LayerProcessor::on_removed`].
+impl LayerObserver for &Plugins {
+    fn entry(&mut self, path: &Path, entry: &ObservedEntry) {
+        let _ = self.each(|plugin| {
+            plugin.on_file(path, entry);
+            Ok(())
+        });
+    }
+
+    fn removed(&mut self, path: &Path) {
+        let _ = self.each(|plugin| {
+            plugin.on_removed(path);
+            Ok(())
+        });
+    }
+
+    fn cleared(&mut self, _path: &Path) {}
+}
+
+/// Rejects plugin file paths outside the repository, in `rootfs_dir` or on the metadata.
+fn check_path(path: &Path, rootfs_dir: &str) -> Result<()> {
+    let mut components = path.components();
+    let first = match components.next() {
+        Some(Component::Normal(first)) => first.to_string_lossy(),
+        _ => bail!("Plugin file {} is not a relative path", path.display()),
+    };
+    if components.any(|component| !matches!(component, Component::Normal(_))) {
+        bail!(
+            "Plugin file {} is not a plain relative path",
+            path.display()
+        );
+    }
+    if first == rootfs_dir || RESERVED_NAMES.contains(&first.as_ref()) {
+        bail!(
+            "Plugin file {} would overwrite the {first} of the conversion",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_path() {
+        assert!(check_path(Path::new("sbom/spdx.json"), "rootfs").is_ok());
+        assert!(check_path(Path::new("index.txt"), "rootfs").is_ok());
+        assert!(check_path(Path::new("rootfs/etc/passwd"), "rootfs").is_err());
+        assert!(check_path(Path::new("fs/etc/passwd"), "fs").is_err());
+        assert!(check_path(Path::new("Image.md"), "rootfs").is_err());
+        assert!(check_path(Path::new(".git/config"), "rootfs").is_err());
+        assert!(check_path(Path::new(".oci2git/fsmeta.jsonl"), "rootfs").is_err());
+        assert!(check_path(Path::new("/etc/passwd"), "rootfs").is_err());
+        assert!(check_path(Path::new("sbom/../../x"), "rootfs").is_err());
+        assert!(check_path(Path::new(""), "rootfs").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//! The stable library surface: `use oci2git::prelude::*;`
+//!
+//! Everything re-exported here is covered by semver — it only changes incompatibly with a
+//! major (pre-1.0: minor) version bump. It is deliberately small:
+//! - the pipeline: [`ImageProcessor`] with its `with_*` option builders and per-conversion
+//!   [`ConvertOptions`], [`ImagePlan`] / [`BranchPlan`], [`materialize_rootfs`] with
+//!   [`RootfsOptions`] and the way back, [`export_branch`] with [`ExportOptions`],
+//! - image sources: the [`Source`] trait, [`Platform`] and every built-in source,
+//! - reading results: [`ConvertedRepo`] with [`LayerCommit`] and [`ListedImage`],
+//! - report types: the `Image.md` model ([`ImageMetadata`], [`BasicInfo`],
+//!   [`ContainerConfig`], [`LayerDigest`]), [`SquashedHistory`], [`ExportedImage`] and the
+//!   conversion summaries ([`ConversionSummary`], [`ConversionStatus`], [`LayerSummary`],
+//!   [`RunSummary`]),
+//! - errors: [`Oci2GitError`], returned by [`ImageProcessor`], and its exit codes,
+//! - plumbing needed to drive the above: [`Notifier`], [`ProgressSink`] with
+//!   [`ProgressEvent`], [`ConflictStrategy`], [`MetadataFormat`], [`GitRepo`].
+//!
+//! Modules hidden from the documentation (`digest_tracker`, `metadata`,
+//! `successor_navigator`, `tar_extractor`) are implementation details; they stay `pub`
+//! for the fuzz targets and may change in any release.
+//!
+//! ```no_run
+//! use oci2git::prelude::*;
+//! use std::path::Path;
+//!
+//! let processor = ImageProcessor::new(RegistrySource::new()?, Notifier::new(1))
+//!     .with_conflict_strategy(ConflictStrategy::Replace);
+//! let plan = processor.analyze("alpine:3.20", Path::new("./alpine"))?;
+//! println!("{} layers onto branch {}", plan.layers.len(), plan.branch.name);
+//! processor.materialize(&plan, Path::new("./alpine"))?;
+//! # anyhow::Ok(())
+//! ```
+
+pub use crate::convert_options::ConvertOptions;
+pub use crate::converted_repo::{ConvertedRepo, LayerCommit};
+pub use crate::error::Oci2GitError;
+pub use crate::git::GitRepo;
+pub use crate::git2oci::{export_branch, ExportOptions, ExportedImage};
+pub use crate::image_metadata::{
+    BasicInfo, ContainerConfig, ImageMetadata, LayerDigest, MetadataFormat,
+};
+pub use crate::layer_apply::ConflictStrategy;
+pub use crate::list::ListedImage;
+pub use crate::notifier::Notifier;
+pub use crate::plan::{BranchPlan, ImagePlan};
+pub use crate::processor::ImageProcessor;
+pub use crate::progress::{ProgressEvent, ProgressSink};
+pub use crate::rootfs::{materialize_rootfs, RootfsOptions};
+pub use crate::sources::{
+    ContainerdSource, DockerSource, NerdctlSource, Platform, RegistrySource, Source, StreamSource,
+    TarSource,
+};
+pub use crate::squash::SquashedHistory;
+pub use crate::summary::{ConversionStatus, ConversionSummary, LayerSummary, RunSummary};
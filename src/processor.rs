@@ -3,25 +3,184 @@
 //! This module provides [`ImageProcessor`], a high-level orchestrator that:
 //! - fetches an image tarball from a concrete [`crate::sources::Source`],
 //! - unpacks and replays the ordered filesystem layers into a working `rootfs/`,
-//! - commits each step into a Git branch (one commit per layer, preserving history),
+//! - commits each step into a Git branch (one commit per layer, preserving history,
+//!   with `Layer-Index`/`Layer-Digest` trailers — see [`crate::trailers`]; only the paths
+//!   a layer touched are staged, see [`crate::staging`]),
 //! - and finishes with a metadata commit (`Image.md`) that captures image basics,
-//!   container config, and the full layer digest chain.
+//!   container config, and the full layer digest chain, plus any signatures and
+//!   attestations found by the source (see [`crate::attestations`]). Declared volumes are added to
+//!   `rootfs/` as tracked marker directories (see [`crate::volumes`]).
+//! - Device nodes and FIFOs, which can't be extracted, are listed in a manifest committed
+//!   with each layer (see [`crate::special_files`]).
+//! - Afterwards the branch gets a description and the `README.md` listing all converted
+//!   images on the `main` branch is regenerated (see [`crate::repo_readme`]).
 //!
-//! Duplicate safety: if a matching branch exists and all layers match, conversion is skipped.
+//! Duplicate safety: if a matching branch exists and all layers match, conversion is skipped
+//! ([`ConversionStatus::SkippedDuplicate`]), unless [`ImageProcessor::with_force`] recreates
+//! the branch.
+//!
+//! The pipeline runs in two phases that can also be driven separately:
+//! - [`ImageProcessor::analyze`] — fetch and inspect the image, plan the target branch;
+//!   writes nothing to the output directory and returns an [`ImagePlan`].
+//! - [`ImageProcessor::materialize`] — execute a (possibly edited) plan.
+//!
+//! [`ImageProcessor::convert_with_options`] (and the `_with_options` variants of both
+//! phases) take per-conversion [`ConvertOptions`]: platform, branch name, file size limit,
+//! commit message template and metadata-only mode.
+//!
+//! [`ImageProcessor::convert_all_platforms`] repeats the conversion for every platform of
+//! a multi-platform image, one branch each.
+//!
+//! Errors are typed by pipeline stage ([`Oci2GitError`]: source, extraction, Git,
+//! metadata, ...).
+//!
+//! Conversions return a [`ConversionSummary`] (branch, layer commits, reused layers, bytes
+//! applied, elapsed time; see [`crate::summary`]).
+//!
+//! [`ImageProcessor::materialize_rootfs`] skips Git entirely and only unpacks the layers
+//! into a plain directory.
+//!
+//! Tracing: the phases run in `tracing` spans, `convert` (image) → `analyze` → `fetch`
+//! (source), then `materialize` (branch) → `layer` (index, total, digest, bytes) with
+//! `extract` and `commit`, `metadata` and `repack`; [`Notifier`] messages are events
+//! within them. Without a subscriber they cost next to nothing; the `otel` feature exports
+//! them (see `telemetry`).
+//!
+//! Temporary space: the image tarball obtained from the source is deleted as soon as it
+//! has been unpacked, and usage is accounted in [`TempUsage`]. An optional hard cap
+//! ([`ImageProcessor::with_max_temp_bytes`]) aborts the conversion before it is exceeded.
+//! Sources that provide a stream ([`Source::image_stream`], e.g.
+//! [`crate::sources::StreamSource`]) are unpacked without any tarball on disk.
 //!
 //! Construction helpers:
 //! - [`ImageProcessor::new`] — inject a concrete [`Source`] and a [`Notifier`].
+//! - [`ImageProcessor::with_max_temp_bytes`] — limit temporary disk usage.
+//! - [`ImageProcessor::with_conflict_strategy`] — how entry type changes between layers
+//!   are applied.
+//! - [`ImageProcessor::with_layer_mismatch`] — what to do when the image history doesn't
+//!   line up with its layers.
+//! - [`ImageProcessor::with_keep_failed`] — keep the extraction directory of a layer that
+//!   fails to extract, for debugging.
+//! - [`ImageProcessor::with_commit_partial`] — finish the branch at the last good layer
+//!   (marked `PARTIAL`) when a layer fails to extract.
+//! - [`ImageProcessor::with_fs_metadata`] — commit ownership, permissions and xattrs of
+//!   every path in a sidecar file (see [`crate::fsmeta`]).
+//! - [`ImageProcessor::with_metadata_format`] — write `Image.md`, `Image.json` or both.
+//! - [`ImageProcessor::with_env_scripts`] — commit the image environment and entrypoint
+//!   as shell scripts (see [`crate::env_scripts`]).
+//! - [`ImageProcessor::with_materialize_config`] — write the image environment, exposed
+//!   ports and volumes into the tree (see [`crate::materialize_config`]).
+//! - [`ImageProcessor::with_config_history`] — commit the image config as it was after
+//!   each layer (see [`crate::config_history`]).
+//! - [`ImageProcessor::with_layer_stats`] — commit per-layer size statistics (see
+//!   [`crate::layer_stats`]).
+//! - [`ImageProcessor::with_layer_graph`] — end `Image.md` with a Mermaid diagram of the
+//!   layer chain (see [`crate::layer_graph`]).
+//! - [`ImageProcessor::with_emit_readme`] — commit a `README.md` describing the image on
+//!   its branch (see [`crate::repo_readme`]).
+//! - [`ImageProcessor::with_html_report`] — commit an HTML summary of the image, with
+//!   [`ImageProcessor::with_report_commit_url`] linking its commits (see
+//!   [`crate::html_report`]).
+//! - [`ImageProcessor::with_max_file_size`] — commit oversized files as stubs (see
+//!   [`crate::large_files`]).
+//! - [`ImageProcessor::with_sparse_files`] — commit sparse files in full or as stubs (see
+//!   [`crate::sparse`]).
+//! - [`ImageProcessor::with_symlink_targets`] — commit symlinks with their in-image
+//!   targets (see [`crate::symlinks`]).
+//! - [`ImageProcessor::with_layer_records`] — record the layer chain in git notes instead
+//!   of `layers/` (see [`crate::layer_records`]).
+//! - [`ImageProcessor::with_layer_processor`] — run a plugin on every layer and commit the
+//!   files it emits (see [`crate::plugins`]).
+//! - [`ImageProcessor::with_exclude`] — leave paths out of the layer commits (see
+//!   [`crate::path_filter`]).
+//! - [`ImageProcessor::with_commit_identity`] — commit author and signing.
+//! - [`ImageProcessor::with_commit_message_template`] — subject of layer commits.
+//! - [`ImageProcessor::with_branch_naming`] — branch names from a template or a custom
+//!   strategy (see [`crate::branch_naming`]).
+//! - [`ImageProcessor::with_tag_template`] — annotated tag at the tip of each branch.
+//! - [`ImageProcessor::with_metadata_only`] — history and metadata only, no filesystem.
+//! - [`ImageProcessor::with_from_layer`] — leave the base image layers out of the
+//!   filesystem (see [`crate::from_layer`]).
+//! - [`ImageProcessor::with_workdir`] — where temporary data goes, and whether it is
+//!   kept (see [`crate::workdir`]).
+//! - [`ImageProcessor::with_disk_space_check`] — fail (or warn) early when the image
+//!   likely doesn't fit on disk (see [`crate::disk_space`]).
+//! - [`ImageProcessor::with_progress_sink`] — report progress to your own
+//!   [`ProgressSink`] instead of the notifier.
+//! - [`ImageProcessor::with_record_deletions`] — list the paths each layer deletes (see
+//!   [`crate::deleted_files`]).
+//! - [`ImageProcessor::with_selected_image`] — which image of a multi-image archive to
+//!   convert.
+//! - [`ImageProcessor::with_content_hashes`] — hash the files each layer writes, for
+//!   duplicate detection (see [`crate::content_hashes`]).
+//! - [`ImageProcessor::with_digest_verification`] — check every layer against its
+//!   `diff_id` while extracting it (see [`crate::layer_verification`]).
+//! - [`ImageProcessor::with_convert_base`] — convert the base image an image declares
+//!   before the image itself (see [`crate::base_image`]).
+//! - [`ImageProcessor::with_squash`] — one commit with the final filesystem instead of
+//!   one per layer.
+//! - [`ImageProcessor::with_gc`] — repack the repository after the conversion.
+//! - [`ImageProcessor::with_conversion_cache`] — reuse the layer commits of a conversion
+//!   of the same image into another repository (see [`crate::conversion_cache`]).
+//! - [`ImageProcessor::with_direct_commits`] — commit layers without unpacking them into
+//!   the worktree (see [`crate::tree_apply`]).
 
+use crate::attestations::{self, ATTESTATIONS_DIR};
+use crate::base_image::{self, BaseImage};
+use crate::blob_cache::digest_hex;
+use crate::branch_naming::{BranchNameInput, BranchNamingStrategy, BranchTemplate};
+use crate::build_provenance::BuildProvenance;
+use crate::config_history;
+use crate::content_hashes::{ContentHashes, CONTENT_HASHES_PATH};
+use crate::conversion_cache::{CachedConversion, ConversionCache};
+use crate::convert_options::{
+    validate_rootfs_dir, ConvertOptions, DEFAULT_ROOTFS_DIR, ROOTFS_DIR_PATH,
+};
+use crate::deleted_files::{DeletedFiles, DELETED_FILES_PATH};
 use crate::digest_tracker::DigestTracker;
-use crate::extracted_image::ExtractedImage;
-use crate::git::GitRepo;
-use crate::image_metadata::ImageMetadata;
+use crate::disk_space::{self, DiskSpaceCheck};
+use crate::env_scripts;
+use crate::error::Oci2GitError;
+use crate::estargz::{self, LAYER_FILES_PATH};
+use crate::extracted_image::{ExtractedImage, Layer, LayerMismatch};
+use crate::from_layer::{self, FromLayer, FROM_LAYER_SUFFIX};
+use crate::fsmeta::{FsMetadata, FSMETA_PATH};
+use crate::git::{CommitIdentity, GitRepo};
+use crate::html_report::HtmlReport;
+use crate::image_metadata::{ImageMetadata, MetadataFormat, IMAGE_JSON, IMAGE_MD};
+use crate::large_files::{LargeFiles, LARGE_FILES_PATH};
+use crate::layer_apply::ConflictStrategy;
+use crate::layer_apply::{LayerObserver, LayerOptions};
+use crate::layer_graph;
+use crate::layer_records::{self, LayerRecords};
+use crate::layer_stats::LayerStatsReport;
+use crate::layer_verification::DigestVerification;
+use crate::layout::{self, Layout, SUBDIR_BRANCH};
+use crate::materialize_config;
 use crate::notifier::Notifier;
-use crate::sources::Source;
+use crate::path_filter::PathFilter;
+use crate::perf::PhaseTimings;
+use crate::plan::{BranchPlan, ImagePlan};
+use crate::plugins::{LayerInfo, LayerProcessor, Plugins};
+use crate::progress::{ProgressEvent, ProgressSink};
+use crate::query;
+use crate::repo_readme;
+use crate::sources::{sanitize_branch_name, Platform, Source};
+use crate::sparse::SparseFiles;
+use crate::special_files::{SpecialFiles, SPECIAL_FILES_PATH};
+use crate::staging::TouchedPaths;
 use crate::successor_navigator::SuccessorNavigator;
-use anyhow::{Context, Result};
+use crate::summary::{ConversionStatus, ConversionSummary};
+use crate::symlinks::SymlinkTargets;
+use crate::temp_usage::{dir_size, TempUsage};
+use crate::trailers::LayerTrailers;
+use crate::tree_apply::RootfsTree;
+use crate::volumes;
+use crate::workdir::Workdir;
+use anyhow::{anyhow, bail, Context, Result};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 /// Orchestrates the OCI image to Git repo conversion pipeline for a concrete [`Source`].
 ///
@@ -41,8 +200,105 @@ pub struct ImageProcessor<S: Source> {
     /// The concrete image source (registry/daemon/nerdctl/tar, etc.).
     source: S,
     notifier: Notifier,
+    /// Maximum number of bytes of temporary data, `None` for no limit.
+    max_temp_bytes: Option<u64>,
+    /// How layers replacing an entry with one of a different type are applied.
+    conflict_strategy: ConflictStrategy,
+    /// How history entries and layers are paired when their counts differ.
+    layer_mismatch: LayerMismatch,
+    /// Where to keep the extraction directory of a layer that fails to extract.
+    keep_failed: Option<PathBuf>,
+    /// Whether layer commits carry the [`FSMETA_PATH`] sidecar.
+    fs_metadata: bool,
+    /// Whether a layer extraction failure still finishes the branch.
+    commit_partial: bool,
+    /// Which metadata files (`Image.md`, `Image.json`) are committed.
+    metadata_format: MetadataFormat,
+    /// Whether the metadata commit carries `env.sh` and `run.sh`.
+    env_scripts: bool,
+    /// Whether the metadata commit writes the environment, ports and volumes into the tree.
+    materialize_config: bool,
+    /// Whether every layer commit carries the image config of its history step.
+    config_history: bool,
+    /// Whether the metadata commit carries `LayerStats.md`/`LayerStats.json`.
+    layer_stats: bool,
+    /// Whether `Image.md` ends with a Mermaid diagram of the layer chain.
+    layer_graph: bool,
+    /// Whether the metadata commit carries a `README.md` describing the image.
+    emit_readme: bool,
+    /// Whether the metadata commit carries `report/index.html`.
+    html_report: bool,
+    /// Link of commits in the HTML report, `{commit}` replaced by the commit id.
+    report_commit_url: Option<String>,
+    /// Files larger than this many bytes are committed as stubs.
+    max_file_size: Option<u64>,
+    /// How sparse files are committed.
+    sparse_files: SparseFiles,
+    /// Directory holding the image filesystem unless [`ConvertOptions`] set one.
+    rootfs_dir: Option<String>,
+    /// How symlink targets are committed.
+    symlink_targets: SymlinkTargets,
+    /// Where layer commits record the layer chain.
+    layer_records: LayerRecords,
+    /// Plugins following every layer.
+    plugins: Plugins,
+    /// Paths left out of every layer.
+    exclude: PathFilter,
+    /// Author/committer of the created commits, and their signer.
+    commit_identity: CommitIdentity,
+    /// Subject of layer commits unless [`ConvertOptions`] set one.
+    commit_message_template: Option<String>,
+    /// Names branches instead of [`Source::branch_name`].
+    branch_naming: Option<Box<dyn BranchNamingStrategy>>,
+    /// Names the annotated tag created at the tip of each converted branch.
+    tag_template: Option<BranchTemplate>,
+    /// Whether layers are committed without their files.
+    metadata_only: bool,
+    /// Where the layers committed with their files start.
+    from_layer: Option<FromLayer>,
+    /// Whether layer commits list their whiteout deletions in [`DELETED_FILES_PATH`].
+    record_deletions: bool,
+    /// Whether layer commits list the hashes of their files in [`CONTENT_HASHES_PATH`].
+    content_hashes: bool,
+    /// Repo tag of the image to convert from an archive holding several.
+    selected_image: Option<String>,
+    /// Where temporary data is created.
+    workdir: Workdir,
+    /// What happens when the image likely doesn't fit on disk.
+    disk_space_check: DiskSpaceCheck,
+    /// Receives progress events instead of the notifier.
+    progress_sink: Option<Box<dyn ProgressSink>>,
+    /// What happens when a layer doesn't match its `diff_id`.
+    digest_verification: DigestVerification,
+    /// Whether the base image an image declares is converted before it.
+    convert_base: bool,
+    /// Whether all layers go into a single commit.
+    squash: bool,
+    /// Whether an existing branch of the image is recreated instead of skipped.
+    force: bool,
+    /// Whether the repository is repacked after a conversion.
+    gc: bool,
+    /// Where conversions are remembered across repositories.
+    conversion_cache: Option<ConversionCache>,
+    /// Whether layers are committed as trees built from the tarballs, without a worktree.
+    direct_commits: bool,
+    /// Threads writing and hashing the files of a layer.
+    jobs: usize,
+    /// Whether images get branches of their own or directories on one branch.
+    layout: Layout,
+    /// Whether image directories of [`Layout::Subdir`] name the platform.
+    platform_dirs: bool,
 }
 
+/// Appended to the branch name of [`ImageProcessor::with_metadata_only`] conversions.
+pub const METADATA_ONLY_SUFFIX: &str = "#metadata-only";
+
+/// Appended to the branch name of [`ImageProcessor::with_squash`] conversions.
+pub const SQUASHED_SUFFIX: &str = "#squashed";
+
+/// Report committed next to `Image.md` by [`ImageProcessor::with_commit_partial`].
+pub const PARTIAL_PATH: &str = "PARTIAL.md";
+
 impl<S: Source> ImageProcessor<S> {
     /// Constructs a new processor that will use the given [`Source`] and [`Notifier`].
     ///
@@ -52,8 +308,480 @@ impl<S: Source> ImageProcessor<S> {
     /// Check [`crate::notifier::VerbosityLevel`] for more verbosity levels params
     ///
     pub fn new(source: S, notifier: Notifier) -> Self {
-        Self { source, notifier }
+        Self {
+            source,
+            notifier,
+            max_temp_bytes: None,
+            conflict_strategy: ConflictStrategy::default(),
+            layer_mismatch: LayerMismatch::default(),
+            keep_failed: None,
+            fs_metadata: false,
+            commit_partial: false,
+            metadata_format: MetadataFormat::default(),
+            env_scripts: false,
+            materialize_config: false,
+            config_history: false,
+            layer_stats: false,
+            layer_graph: false,
+            emit_readme: false,
+            html_report: false,
+            report_commit_url: None,
+            max_file_size: None,
+            sparse_files: SparseFiles::default(),
+            rootfs_dir: None,
+            symlink_targets: SymlinkTargets::default(),
+            layer_records: LayerRecords::default(),
+            plugins: Plugins::default(),
+            exclude: PathFilter::default(),
+            commit_identity: CommitIdentity::default(),
+            commit_message_template: None,
+            branch_naming: None,
+            tag_template: None,
+            metadata_only: false,
+            from_layer: None,
+            record_deletions: false,
+            content_hashes: false,
+            selected_image: None,
+            workdir: Workdir::default(),
+            disk_space_check: DiskSpaceCheck::default(),
+            progress_sink: None,
+            digest_verification: DigestVerification::default(),
+            convert_base: false,
+            squash: false,
+            force: false,
+            gc: false,
+            conversion_cache: None,
+            direct_commits: false,
+            jobs: 1,
+            layout: Layout::default(),
+            platform_dirs: false,
+        }
+    }
+
+    /// Limit the temporary disk space (image tarball + unpacked blobs) used by a
+    /// conversion. [`ImageProcessor::analyze`] fails with an error instead of exceeding it.
+    pub fn with_max_temp_bytes(mut self, max_temp_bytes: Option<u64>) -> Self {
+        self.max_temp_bytes = max_temp_bytes;
+        self
+    }
+
+    /// Choose how a layer that changes the type of an existing entry (directory → file,
+    /// file → symlink, symlink → directory, …) is applied. See [`ConflictStrategy`].
+    pub fn with_conflict_strategy(mut self, conflict_strategy: ConflictStrategy) -> Self {
+        self.conflict_strategy = conflict_strategy;
+        self
+    }
+
+    /// Choose what to do when the non-empty history entries of an image don't line up
+    /// with its layers: fail, pair them as well as possible (the default) or ignore the
+    /// history. See [`LayerMismatch`]; either way the mismatch is warned about.
+    pub fn with_layer_mismatch(mut self, layer_mismatch: LayerMismatch) -> Self {
+        self.layer_mismatch = layer_mismatch;
+        self
+    }
+
+    /// When a layer fails to extract, keep the image's extraction directory (manifest,
+    /// config, layer tarballs) at `<dir>/<branch>/layer-<NNN>` instead of deleting it, and
+    /// name that path in the error. A `FAILED-LAYER.txt` inside describes the failure.
+    ///
+    /// The path is stable, so a rerun replaces the evidence of the previous one.
+    /// [`ImageProcessor::failed_extractions_dir`] is the usual choice for `dir`.
+    pub fn with_keep_failed(mut self, dir: Option<PathBuf>) -> Self {
+        self.keep_failed = dir;
+        self
+    }
+
+    /// Record owner, group, mode, mtime and xattrs of every path in `rootfs/` in
+    /// [`FSMETA_PATH`], updated and committed with each layer. Branches that reuse layer
+    /// commits of a conversion without this option only describe the layers added on top.
+    pub fn with_fs_metadata(mut self, fs_metadata: bool) -> Self {
+        self.fs_metadata = fs_metadata;
+        self
+    }
+
+    /// When a layer fails to extract, discard its half-applied changes and finish the
+    /// branch at the last good layer with a metadata commit marked `PARTIAL`, whose
+    /// [`PARTIAL_PATH`] describes the failure. The conversion still returns the error.
+    pub fn with_commit_partial(mut self, commit_partial: bool) -> Self {
+        self.commit_partial = commit_partial;
+        self
+    }
+
+    /// Choose the metadata files committed with every layer: `Image.md` (default),
+    /// `Image.json` or both. Reading prefers `Image.json`, so branches converted with
+    /// different formats still share layer commits.
+    pub fn with_metadata_format(mut self, metadata_format: MetadataFormat) -> Self {
+        self.metadata_format = metadata_format;
+        self
+    }
+
+    /// Add [`env_scripts::ENV_SH_PATH`] (exported image environment) and
+    /// [`env_scripts::RUN_SH_PATH`] (working directory, entrypoint and command) to the
+    /// metadata commit.
+    pub fn with_env_scripts(mut self, env_scripts: bool) -> Self {
+        self.env_scripts = env_scripts;
+        self
+    }
+
+    /// Add [`materialize_config::PROFILE_PATH`] (exported image environment) to the rootfs
+    /// and [`materialize_config::PORTS_TXT`] and [`materialize_config::VOLUMES_TXT`] to the
+    /// metadata commit.
+    pub fn with_materialize_config(mut self, materialize_config: bool) -> Self {
+        self.materialize_config = materialize_config;
+        self
+    }
+
+    /// Add the image config as it was after each step of the history (environment,
+    /// labels, entrypoint, ...) to every layer commit, below
+    /// [`config_history::CONFIG_DIR`], so the commits of `ENV` or `LABEL` layers show
+    /// what they changed.
+    pub fn with_config_history(mut self, config_history: bool) -> Self {
+        self.config_history = config_history;
+        self
+    }
+
+    /// Add per-layer statistics (files and bytes added and deleted, largest files) to the
+    /// metadata commit, as `LayerStats.md` and/or `LayerStats.json` following the
+    /// metadata format (see [`crate::layer_stats`]).
+    pub fn with_layer_stats(mut self, layer_stats: bool) -> Self {
+        self.layer_stats = layer_stats;
+        self
+    }
+
+    /// End the `Image.md` of the metadata commit with a Mermaid diagram of the layer
+    /// chain: the bytes every layer added, and the layers shared with the other image
+    /// branches of the repository (see [`crate::layer_graph`]). Has no effect on
+    /// `Image.json`.
+    pub fn with_layer_graph(mut self, layer_graph: bool) -> Self {
+        self.layer_graph = layer_graph;
+        self
+    }
+
+    /// Add a `README.md` to the metadata commit summarizing the image (name, digest,
+    /// platform, layer count, largest layers, entrypoint), so forges show it on the branch
+    /// page (see [`crate::repo_readme::render_branch`]).
+    pub fn with_emit_readme(mut self, emit_readme: bool) -> Self {
+        self.emit_readme = emit_readme;
+        self
+    }
+
+    /// Add `report/index.html` to the metadata commit: a self-contained page with the
+    /// layer sizes, biggest files, environment and labels of the image (see
+    /// [`crate::html_report`]).
+    pub fn with_html_report(mut self, html_report: bool) -> Self {
+        self.html_report = html_report;
+        self
+    }
+
+    /// Link the layer commits of the HTML report to `url` with `{commit}` replaced by the
+    /// commit id, e.g. `https://github.com/acme/images/commit/{commit}`.
+    pub fn with_report_commit_url(mut self, url: Option<String>) -> Self {
+        self.report_commit_url = url;
+        self
+    }
+
+    /// Convert the base image an image declares (`org.opencontainers.image.base.name`)
+    /// into the same repository before the image itself, so the image branches from the
+    /// layer commits of its base (see [`crate::base_image`]). A base that is already there
+    /// is skipped as a duplicate; failing to convert it only produces a warning.
+    pub fn with_convert_base(mut self, convert_base: bool) -> Self {
+        self.convert_base = convert_base;
+        self
+    }
+
+    /// List the paths each layer deletes with whiteouts and opaque markers in
+    /// [`DELETED_FILES_PATH`], committed with that layer (see [`crate::deleted_files`]).
+    pub fn with_record_deletions(mut self, record_deletions: bool) -> Self {
+        self.record_deletions = record_deletions;
+        self
+    }
+
+    /// Hash every file a layer writes while applying it and list path, size and SHA-256 in
+    /// [`CONTENT_HASHES_PATH`], committed with that layer (see [`crate::content_hashes`]).
+    pub fn with_content_hashes(mut self, content_hashes: bool) -> Self {
+        self.content_hashes = content_hashes;
+        self
+    }
+
+    /// Convert the image tagged `repo_tag` of an archive that holds several (`docker save
+    /// img1 img2`) instead of the first one (see [`ExtractedImage::select`]).
+    pub fn with_selected_image(mut self, repo_tag: Option<String>) -> Self {
+        self.selected_image = repo_tag;
+        self
+    }
+
+    /// Create temporary data (exported tarballs, unpacked images) in `workdir` instead of
+    /// the system temporary directory, for this processor and its source.
+    pub fn with_workdir(mut self, workdir: Workdir) -> Self {
+        self.source.set_workdir(workdir.clone());
+        self.workdir = workdir;
+        self
+    }
+
+    /// Before fetching an image, compare its estimated space needs in the temporary and
+    /// output directories with the free space there, and fail or warn if they exceed it.
+    /// Sources that can't estimate the image size are not checked.
+    pub fn with_disk_space_check(mut self, check: DiskSpaceCheck) -> Self {
+        self.disk_space_check = check;
+        self
+    }
+
+    /// Hash every layer while extracting it and compare the result with the layer's
+    /// `diff_id` from the image config, failing or warning on a mismatch. Each layer's
+    /// outcome is recorded in the `Verified` column of `Image.md`.
+    pub fn with_digest_verification(mut self, verification: DigestVerification) -> Self {
+        self.digest_verification = verification;
+        self
+    }
+
+    /// Send the [`ProgressEvent`]s of every conversion to `sink` instead of the notifier,
+    /// which keeps its other messages (see [`crate::progress`]).
+    pub fn with_progress_sink(mut self, sink: Option<Box<dyn ProgressSink>>) -> Self {
+        self.progress_sink = sink;
+        self
+    }
+
+    /// Commit regular files larger than `max_file_size` bytes as small text stubs (path,
+    /// size, SHA-256) listed in [`LARGE_FILES_PATH`], keeping huge binaries out of Git.
+    /// Layer commits shared with branches converted without a limit keep their files.
+    pub fn with_max_file_size(mut self, max_file_size: Option<u64>) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Commit sparse files (VM images, preallocated database files) with their full
+    /// content, as by default, or as stubs listed in [`LARGE_FILES_PATH`] with their
+    /// apparent size and bytes of data. Either way they keep their holes in the worktree.
+    pub fn with_sparse_files(mut self, sparse_files: SparseFiles) -> Self {
+        self.sparse_files = sparse_files;
+        self
+    }
+
+    /// Put the image filesystem of every conversion into the top-level directory
+    /// `rootfs_dir` (e.g. `fs` or `chroot`) instead of [`DEFAULT_ROOTFS_DIR`], unless its
+    /// [`ConvertOptions`] set one. Other names are recorded in [`ROOTFS_DIR_PATH`] for the
+    /// commands reading branches back, and layers are only shared with branches using the
+    /// same name.
+    pub fn with_rootfs_dir(mut self, rootfs_dir: Option<String>) -> Self {
+        self.rootfs_dir = rootfs_dir;
+        self
+    }
+
+    /// Commit symlinks with the targets the image gives them (`/usr/bin/python3`), so the
+    /// commits don't depend on where the repository was converted, instead of the
+    /// absolute paths below `rootfs/` they point to while layers are applied. Recorded in
+    /// [`crate::symlinks::SYMLINK_TARGETS_PATH`]; layers are only shared with branches
+    /// converted the same way.
+    pub fn with_symlink_targets(mut self, symlink_targets: SymlinkTargets) -> Self {
+        self.symlink_targets = symlink_targets;
+        self
+    }
+
+    /// Record the layer chain of layer commits as git notes in
+    /// [`crate::layer_records::LAYER_NOTES_REF`] instead of files in `layers/`, keeping
+    /// the worktree free of them. Layers are only shared with branches recording them the
+    /// same way.
+    pub fn with_layer_records(mut self, layer_records: LayerRecords) -> Self {
+        self.layer_records = layer_records;
+        self
+    }
+
+    /// Run `plugin` on every layer converted, committing the files it emits with the
+    /// layer (see [`crate::plugins`]). Plugins run in the order they were added.
+    pub fn with_layer_processor(mut self, plugin: impl LayerProcessor + 'static) -> Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    /// Skip every layer entry matching `exclude` (e.g. `usr/share/doc`, `**/__pycache__`),
+    /// so excluded paths never reach `rootfs/`. Layer commits shared with branches
+    /// converted without the same excludes keep their files.
+    pub fn with_exclude(mut self, exclude: PathFilter) -> Self {
+        self.exclude = exclude;
+        self
+    }
+
+    /// Subject of layer commits of every conversion, unless its [`ConvertOptions`] set
+    /// one; see [`ConvertOptions::with_commit_message_template`] for the placeholders.
+    pub fn with_commit_message_template(mut self, template: Option<String>) -> Self {
+        self.commit_message_template = template;
+        self
+    }
+
+    /// Author and commit as `identity` instead of `oci2git <oci2git@example.com>`, signing
+    /// every commit if it has a signer. Layer commits shared with branches converted
+    /// under another identity are reused as they are.
+    pub fn with_commit_identity(mut self, identity: CommitIdentity) -> Self {
+        self.commit_identity = identity;
+        self
+    }
+
+    /// Name branches with `strategy` (e.g. a [`BranchTemplate`]) instead of the source's
+    /// own scheme. [`ConvertOptions::with_branch_name`] still takes precedence.
+    pub fn with_branch_naming(mut self, strategy: Option<Box<dyn BranchNamingStrategy>>) -> Self {
+        self.branch_naming = strategy;
+        self
+    }
+
+    /// After the metadata commit, create an annotated tag named by `template` (e.g.
+    /// [`crate::branch_naming::DEFAULT_TAG_TEMPLATE`]) at the branch tip. The tag keeps
+    /// pointing at this conversion when the branch is extended later; an existing tag of
+    /// the same name is never moved.
+    pub fn with_tag_template(mut self, template: Option<BranchTemplate>) -> Self {
+        self.tag_template = template;
+        self
+    }
+
+    /// Skip the filesystem: every layer becomes a commit recording its digest and history
+    /// entry (in `Image.md`) without touching `rootfs/`. Much faster when only history,
+    /// provenance or layer commands matter. Such branches get the [`METADATA_ONLY_SUFFIX`]
+    /// and are never reused by full conversions. Sources still fetch the image; use
+    /// [`crate::sources::RegistrySource::with_metadata_only`] to skip the layer blobs too.
+    pub fn with_metadata_only(mut self, metadata_only: bool) -> Self {
+        self.metadata_only = metadata_only;
+        self
+    }
+
+    /// Commit the layers before `from_layer` without their files, like
+    /// [`ImageProcessor::with_metadata_only`] does for all of them, so `rootfs/` only holds
+    /// what the application layers wrote (see [`crate::from_layer`]). Branch names get
+    /// [`FROM_LAYER_SUFFIX`] and the index of the first materialized layer.
+    pub fn with_from_layer(mut self, from_layer: Option<FromLayer>) -> Self {
+        self.from_layer = from_layer;
+        self
+    }
+
+    /// Apply all layers and commit the final filesystem once (plus the metadata commit,
+    /// whose `Image.md` still lists every layer), skipping the intermediate commits. Much
+    /// faster when only the end state matters, e.g. to diff two images. Such branches get
+    /// the [`SQUASHED_SUFFIX`], start from scratch and never share commits with other
+    /// branches; [`ImageProcessor::with_commit_partial`] doesn't apply to them.
+    pub fn with_squash(mut self, squash: bool) -> Self {
+        self.squash = squash;
+        self
+    }
+
+    /// Convert the image again even if its branch already exists, also with identical
+    /// content: the branch is deleted and rebuilt from scratch as an orphan branch, so none
+    /// of its commits are shared with other branches. Useful after a conversion with an
+    /// older oci2git or with different options.
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Repack the repository after every conversion that wrote a branch (see
+    /// [`GitRepo::repack`]): layer commits of large binaries are left as loose,
+    /// zlib-compressed objects that never share any bytes, while a pack stores files that
+    /// change a little between layers as deltas. [`ImageProcessor::convert_all_platforms`]
+    /// repacks once, after the last platform. libgit2 uses a fixed delta window (10) and
+    /// depth (50); run `git gc --aggressive` for a denser pack.
+    pub fn with_gc(mut self, gc: bool) -> Self {
+        self.gc = gc;
+        self
+    }
+
+    /// Remember conversions in `conversion_cache`, and start conversions of an image
+    /// converted into another repository before from the layer commits of that
+    /// conversion (see [`crate::conversion_cache`]). Only [`ImageProcessor::convert`] and
+    /// [`ImageProcessor::convert_with_options`] use it; squashed conversions,
+    /// [`Layout::Subdir`] and [`LayerRecords::Notes`] don't.
+    pub fn with_conversion_cache(mut self, conversion_cache: Option<ConversionCache>) -> Self {
+        self.conversion_cache = conversion_cache;
+        self
+    }
+
+    /// Build the layer commits straight from the layer tarballs (see [`crate::tree_apply`])
+    /// instead of unpacking every layer into `rootfs/` and staging it.
+    /// Blobs stream from the tarball into the object database and only the directories a
+    /// layer touches are rewritten, which is much faster for large images. `rootfs/` is
+    /// checked out once, with the metadata commit; the commits are the same either way.
+    pub fn with_direct_commits(mut self, direct_commits: bool) -> Self {
+        self.direct_commits = direct_commits;
+        self
+    }
+
+    /// Write the files of a layer into `rootfs/` and hash them into Git objects on `jobs`
+    /// threads (see [`LayerApplier::with_jobs`] and [`GitRepo::with_jobs`]); 1, the default,
+    /// does everything on the calling thread. The commits are the same either way.
+    ///
+    /// [`LayerApplier::with_jobs`]: crate::layer_apply::LayerApplier::with_jobs
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Lay the images out as `layout` says (see [`crate::layout`]): a branch per image,
+    /// the default, or a directory per image on a single branch. With
+    /// [`Layout::Subdir`], the summary names that branch and the commits copied onto it,
+    /// and the repository README and branch descriptions are left alone.
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Default location for [`ImageProcessor::with_keep_failed`]: `oci2git-failed` in the
+    /// system temp directory.
+    pub fn failed_extractions_dir() -> PathBuf {
+        std::env::temp_dir().join("oci2git-failed")
+    }
+
+    /// Keeps the extraction directory of the image whose layer `index` (0-based) failed
+    /// with `error`, if enabled. Returns the error to report, naming the kept directory.
+    fn keep_failed_layer(
+        &self,
+        extracted_image: &ExtractedImage,
+        branch_name: &str,
+        index: usize,
+        layer: &Layer,
+        error: anyhow::Error,
+    ) -> anyhow::Error {
+        let Some(dir) = &self.keep_failed else {
+            return error;
+        };
+
+        let destination = dir
+            .join(sanitize_branch_name(branch_name))
+            .join(format!("layer-{:03}", index + 1));
+        let tarball = layer
+            .tarball_path
+            .as_deref()
+            .and_then(|path| path.strip_prefix(extracted_image.extract_dir()).ok())
+            .map_or_else(|| "-".to_string(), |path| path.display().to_string());
+        let report = format!(
+            "layer: {}\ncommand: {}\ndigest: {}\ntarball: {tarball}\nerror: {error:#}\n",
+            index + 1,
+            layer.command,
+            layer.digest,
+        );
+
+        let kept = extracted_image.retain(&destination).and_then(|()| {
+            fs::write(destination.join("FAILED-LAYER.txt"), report)
+                .context("Failed to write FAILED-LAYER.txt")
+        });
+        match kept {
+            Ok(()) => {
+                self.notifier.warn(&format!(
+                    "Kept extraction directory of failed layer {} at {}",
+                    index + 1,
+                    destination.display()
+                ));
+                error.context(format!(
+                    "Layer {} failed to extract; extraction directory kept at {}",
+                    index + 1,
+                    destination.display()
+                ))
+            }
+            Err(keep_error) => {
+                self.notifier.warn(&format!(
+                    "Failed to keep extraction directory of failed layer {}: {keep_error:#}",
+                    index + 1
+                ));
+                error
+            }
+        }
     }
+
     /// Convert an image into a Git repository at `output_dir`.
     ///
     /// This will:
@@ -70,7 +798,8 @@ impl<S: Source> ImageProcessor<S> {
     /// 6. **Finish** with a final metadata commit including basic info, container config,
     ///    and the complete digest history.
     ///
-    /// If a branch with matching content already exists, the conversion is skipped.
+    /// If a branch with matching content already exists, the conversion is skipped
+    /// ([`ConversionStatus::SkippedDuplicate`]) unless [`ImageProcessor::with_force`] is set.
     ///
     /// # Parameters
     /// - `image_name`: something your [`Source`] can resolve (e.g. `"alpine:3.20"` or
@@ -79,15 +808,17 @@ impl<S: Source> ImageProcessor<S> {
     ///   a working `rootfs/`, and `Image.md`.
     ///
     /// # Returns
-    /// `Ok(())` on success. On failure, returns an [`anyhow::Result`] describing
-    /// the error with context. You can bubble these up or downcast as needed.
+    /// A [`ConversionSummary`] of the branch on success, also when the image was already
+    /// converted (`status`). On failure, an [`Oci2GitError`] naming the failed stage,
+    /// wrapping the error with its context.
     ///
     /// # Errors
-    /// - Image fetch/extraction failures from the underlying [`Source`] or tar processing
-    ///   (I/O, format, missing layers).
-    /// - Git repository initialization/commit errors.
-    /// - Filesystem operations while applying layers (permissions, symlinks, deletions).
-    /// - Metadata serialization/parsing of `Image.md`.
+    /// - [`Oci2GitError::Source`]: image fetch failures from the underlying [`Source`] or
+    ///   unpacking the image (I/O, format, missing layers).
+    /// - [`Oci2GitError::Git`]: Git repository initialization/commit errors.
+    /// - [`Oci2GitError::Extraction`]: applying a layer failed (permissions, symlinks,
+    ///   deletions) or it doesn't match its digest.
+    /// - [`Oci2GitError::Metadata`]: metadata serialization/parsing of `Image.md`.
     ///
     /// # Panics
     /// This method is not intended to panic. If you observe a panic, please file a bug
@@ -99,15 +830,192 @@ impl<S: Source> ImageProcessor<S> {
     /// use oci2git::{DockerSource, ImageProcessor, Notifier};
     ///
     /// // Choose your source (e.g., Docker daemon/registry, nerdctl, tar file, etc.)
-    /// // let src = DockerSource;    // or TarSource::new("image.tar")?
-    /// let src = DockerSource;
+    /// // let src = DockerSource::new()?;    // or TarSource::new()?
+    /// let src = DockerSource::new()?;
     /// let notifier = Notifier::new(1);
     ///
     /// let p = ImageProcessor::new(src, notifier);
     /// p.convert("ubuntu:latest", Path::new("./ubuntu-image-repo"))?;
     /// # anyhow::Ok(())
     /// ```
-    pub fn convert(&self, image_name: &str, output_dir: &Path) -> Result<()> {
+    pub fn convert(
+        &self,
+        image_name: &str,
+        output_dir: &Path,
+    ) -> Result<ConversionSummary, Oci2GitError> {
+        self.convert_selected(image_name, output_dir, &ConvertOptions::default())
+    }
+
+    /// Same as [`ImageProcessor::convert`], configured by `options` (platform, branch name,
+    /// file size limit, commit messages, metadata-only mode; see [`ConvertOptions`]).
+    /// The platform is selected for this call only; the source's own is restored after.
+    ///
+    /// # Errors
+    /// - The source can't select `options.platform`, or `options.branch_name` isn't a
+    ///   valid branch name.
+    /// - Any error of [`ImageProcessor::convert`].
+    pub fn convert_with_options(
+        &mut self,
+        image_name: &str,
+        output_dir: &Path,
+        options: &ConvertOptions,
+    ) -> Result<ConversionSummary, Oci2GitError> {
+        let Some(platform) = &options.platform else {
+            return self.convert_selected(image_name, output_dir, options);
+        };
+        let previous = self.source.platform().cloned();
+        self.source
+            .select_platform(Some(platform.clone()))
+            .map_err(Oci2GitError::Source)?;
+        let converted = self.convert_selected(image_name, output_dir, options);
+        let restored = self
+            .source
+            .select_platform(previous)
+            .map_err(Oci2GitError::Source);
+        let summary = converted?;
+        restored?;
+        Ok(summary)
+    }
+
+    /// [`ImageProcessor::convert_with_options`] once the platform is selected; with default
+    /// options, [`ImageProcessor::convert`].
+    fn convert_selected(
+        &self,
+        image_name: &str,
+        output_dir: &Path,
+        options: &ConvertOptions,
+    ) -> Result<ConversionSummary, Oci2GitError> {
+        let _span = tracing::info_span!("convert", image = image_name).entered();
+        let started = Instant::now();
+        let mut plan = self.analyze_with_options(image_name, output_dir, options)?;
+        self.convert_base(&mut plan, output_dir, options)?;
+        self.seed_from_cache(&mut plan, output_dir, options)?;
+        let mut summary = self.materialize_with_options(&plan, output_dir, options)?;
+        self.remember_conversion(&plan, output_dir, &summary);
+        summary.elapsed_seconds = started.elapsed().as_secs_f64();
+        Ok(summary)
+    }
+
+    /// Convert every platform of a multi-platform image, each into its own branch.
+    ///
+    /// Lists the platforms with [`Source::platforms`], then selects and converts them one
+    /// after another (branch names include os/arch, so they don't collide). Layers shared
+    /// between platforms are matched like for any other image. A single-platform image is
+    /// converted once, as with [`ImageProcessor::convert`].
+    ///
+    /// Returns the converted platforms (empty for a single-platform image) with the summary
+    /// of each conversion. The source's own platform is restored after.
+    ///
+    /// # Errors
+    /// - The source cannot list or select platforms.
+    /// - Any error of [`ImageProcessor::convert`] for one of the platforms.
+    pub fn convert_all_platforms(
+        &mut self,
+        image_name: &str,
+        output_dir: &Path,
+    ) -> Result<(Vec<Platform>, Vec<ConversionSummary>), Oci2GitError> {
+        let platforms = self
+            .source
+            .platforms(image_name, &self.notifier)
+            .map_err(Oci2GitError::Source)?;
+        if platforms.is_empty() {
+            self.notifier.info(&format!(
+                "Image '{image_name}' has a single platform, converting it as is"
+            ));
+            let summary = self.convert(image_name, output_dir)?;
+            return Ok((platforms, vec![summary]));
+        }
+
+        let previous = self.source.platform().cloned();
+        // Repack once for all platforms instead of after each of them
+        let gc = std::mem::replace(&mut self.gc, false);
+        self.platform_dirs = true;
+        let mut summaries = Vec::new();
+        let mut converted = Ok(());
+        for (i, platform) in platforms.iter().enumerate() {
+            self.notifier.info(&format!(
+                "Platform {}/{}: {platform}",
+                i + 1,
+                platforms.len()
+            ));
+            converted = self
+                .source
+                .select_platform(Some(platform.clone()))
+                .map_err(Oci2GitError::Source)
+                .and_then(|()| self.convert(image_name, output_dir))
+                .map(|summary| summaries.push(summary))
+                .map_err(|error| error.context(format!("Failed to convert platform {platform}")));
+            if converted.is_err() {
+                break;
+            }
+        }
+        self.gc = gc;
+        self.platform_dirs = false;
+        let restored = self
+            .source
+            .select_platform(previous)
+            .map_err(Oci2GitError::Source);
+        converted?;
+        restored?;
+
+        if summaries
+            .iter()
+            .any(|summary| summary.status == ConversionStatus::Converted)
+        {
+            self.gc(&GitRepo::open(output_dir)?)?;
+        }
+        Ok((platforms, summaries))
+    }
+
+    /// Analyze an image without writing anything to `output_dir`.
+    ///
+    /// Fetches the image via `S`, reads its metadata and layers, and — if `output_dir`
+    /// already holds a repository — determines the branch point with
+    /// [`SuccessorNavigator`]. The returned [`ImagePlan`] can be inspected or edited
+    /// and then passed to [`ImageProcessor::materialize`].
+    ///
+    /// # Errors
+    /// - Image fetch/extraction failures from the underlying [`Source`] or tar processing.
+    /// - The temporary space limit set with [`ImageProcessor::with_max_temp_bytes`]
+    ///   would be exceeded.
+    /// - Failures reading an existing repository in `output_dir`.
+    ///
+    /// ### Examples
+    /// ```no_run
+    /// use std::path::Path;
+    /// use oci2git::{ImageProcessor, Notifier, TarSource};
+    ///
+    /// let p = ImageProcessor::new(TarSource::new()?, Notifier::new(1));
+    /// let output = Path::new("./ubuntu-image-repo");
+    ///
+    /// let mut plan = p.analyze("ubuntu.tar", output)?;
+    /// println!("{} layers, {} bytes", plan.layers.len(), plan.total_size());
+    ///
+    /// // Drop the newest layer before writing anything
+    /// plan.layers.pop();
+    /// p.materialize(&plan, output)?;
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn analyze(&self, image_name: &str, output_dir: &Path) -> Result<ImagePlan, Oci2GitError> {
+        self.analyze_with_options(image_name, output_dir, &ConvertOptions::default())
+    }
+
+    /// Same as [`ImageProcessor::analyze`], planning the branch `options.branch_name` if set
+    /// and leaving out the layer tarballs with `options.metadata_only`. `options.platform`
+    /// is ignored, select it on the source beforehand.
+    ///
+    /// # Errors
+    /// - `options.branch_name` isn't a valid branch name.
+    /// - Any error of [`ImageProcessor::analyze`].
+    pub fn analyze_with_options(
+        &self,
+        image_name: &str,
+        output_dir: &Path,
+        options: &ConvertOptions,
+    ) -> Result<ImagePlan, Oci2GitError> {
+        let _span = tracing::info_span!("analyze").entered();
+        let rootfs_dir = self.rootfs_dir(options);
+        validate_rootfs_dir(rootfs_dir).map_err(Oci2GitError::InvalidInput)?;
         self.notifier.info(&format!(
             "Starting conversion of image with {} source: {}",
             self.source.name(),
@@ -116,102 +1024,906 @@ impl<S: Source> ImageProcessor<S> {
         self.notifier
             .debug(&format!("Output directory: {}", output_dir.display()));
 
-        // Store all temporary directories we need to keep alive during processing
-        let mut temp_dirs: Vec<tempfile::TempDir> = Vec::new();
-
-        // Get the image tarball from the source
-        self.notifier.info(&format!(
-            "Getting image tarball using {} source...",
-            self.source.name()
-        ));
-
-        let (tarball_path, tarball_temp_dir) =
-            self.source.get_image_tarball(image_name, &self.notifier)?;
-
-        // Store the tarball temp dir if it exists
-        if let Some(temp_dir) = tarball_temp_dir {
-            temp_dirs.push(temp_dir);
-        }
-
-        // Extract the tarball and create ExtractedImage
-        self.notifier.info("Extracting image tarball...");
-
-        let extracted_image = ExtractedImage::from_tarball(&tarball_path, &self.notifier)?;
+        self.check_disk_space(image_name, output_dir)?;
+        let mut timings = PhaseTimings::default();
+        let (extracted_image, temp_usage) = self
+            .fetch_image(image_name, &mut timings)
+            .map_err(Oci2GitError::Source)?;
+        let analyzing = Instant::now();
 
         // Get the layers in chronological order (oldest to newest)
         self.notifier.info("Analyzing image layers...");
 
-        let layers = extracted_image.layers()?;
+        let mut layers = extracted_image.layers().map_err(Oci2GitError::Metadata)?;
         self.notifier
             .debug(&format!("Found {} layers in the image", layers.len()));
 
+        // Without their tarballs layers become commits that only record their digest, and
+        // never match (or get reused for) layers whose files were committed
+        let metadata_only = options.metadata_only || self.metadata_only;
+        if metadata_only {
+            for layer in &mut layers {
+                // The TOC of eStargz layers still tells which files they hold
+                if let Err(e) = estargz::keep_toc(extracted_image.extract_dir(), layer) {
+                    self.notifier.warn(&format!("{e:#}"));
+                }
+                layer.tarball_path = None;
+            }
+        }
+        let from_layer = match &self.from_layer {
+            Some(from_layer) => from_layer
+                .start(&layers, |base| {
+                    self.notifier.info(&format!(
+                        "Fetching base image '{base}' to skip its layers..."
+                    ));
+                    self.fetch_image(base, &mut PhaseTimings::default())?
+                        .0
+                        .layers()
+                })
+                .map_err(Oci2GitError::InvalidInput)?,
+            None => 0,
+        };
+        if from_layer > 0 {
+            self.notifier.info(&format!(
+                "Committing layers 1-{from_layer} without their files"
+            ));
+            from_layer::skip(&mut layers, from_layer);
+        }
+
         self.notifier.info("Extracting image metadata...");
 
-        let metadata = extracted_image.metadata(image_name)?;
+        let metadata = extracted_image
+            .metadata(image_name)
+            .map_err(Oci2GitError::Metadata)?;
         self.notifier.debug(&format!("Image ID: {}", metadata.id));
 
-        self.notifier.info("Initializing Git repository...");
-
         // Create branch name using polymorphic method from source
         let os_arch = format!("{}-{}", metadata.os, metadata.architecture);
         self.notifier.debug(&format!(
             "Creating branch name for image '{}' with os-arch '{}' and digest: '{}'",
             image_name, os_arch, metadata.id
         ));
-        let branch_name = self.source.branch_name(image_name, &os_arch, &metadata.id);
+        let branch_name = match &options.branch_name {
+            Some(branch_name) => {
+                if !git2::Branch::name_is_valid(branch_name)? {
+                    return Err(Oci2GitError::InvalidInput(anyhow!(
+                        "'{branch_name}' is not a valid branch name"
+                    )));
+                }
+                branch_name.clone()
+            }
+            None => match &self.branch_naming {
+                Some(strategy) => strategy
+                    .branch_name(&BranchNameInput::new(
+                        image_name,
+                        self.source.name(),
+                        &metadata,
+                    ))
+                    .map_err(Oci2GitError::InvalidInput)?,
+                None => self.source.branch_name(image_name, &os_arch, &metadata.id),
+            },
+        };
+        let branch_name = if from_layer > 0 && options.branch_name.is_none() {
+            format!("{branch_name}{FROM_LAYER_SUFFIX}{}", from_layer + 1)
+        } else {
+            branch_name
+        };
+        let branch_name = if metadata_only
+            && options.branch_name.is_none()
+            && !branch_name.ends_with(METADATA_ONLY_SUFFIX)
+        {
+            format!("{branch_name}{METADATA_ONLY_SUFFIX}")
+        } else {
+            branch_name
+        };
+        let branch_name = if self.squash
+            && options.branch_name.is_none()
+            && !branch_name.ends_with(SQUASHED_SUFFIX)
+        {
+            format!("{branch_name}{SQUASHED_SUFFIX}")
+        } else {
+            branch_name
+        };
         self.notifier
             .debug(&format!("Generated branch name: '{branch_name}'"));
 
-        // Initialize or open repository
-        let repo = GitRepo::init_with_branch(output_dir, None)?;
+        let branch = if self.squash {
+            self.plan_squashed_branch(branch_name, output_dir, &layers)
+        } else {
+            self.plan_branch(branch_name, output_dir, &layers, rootfs_dir)
+        }
+        .map_err(Oci2GitError::Git)?;
 
-        // Determine start commit and skip count using successor navigation
-        let (start_from_commit, skip_layers) = if repo.exists_and_has_commits() {
+        let mut plan = ImagePlan::new(
+            image_name,
+            metadata,
+            layers,
+            branch,
+            extracted_image,
+            temp_usage,
+        );
+        timings.analyze_seconds = analyzing.elapsed().as_secs_f64();
+        plan.timings = timings;
+
+        if plan.layers.is_empty() {
+            plan.warnings
+                .push("No layers found in the image".to_string());
+        }
+        if let Some(mismatch) = plan.extracted_image().history_mismatch() {
+            plan.warnings.push(mismatch.to_string());
+        }
+        for (i, layer) in plan.layers.iter().enumerate() {
+            if !metadata_only && i >= from_layer && !layer.is_empty && layer.tarball_path.is_none()
+            {
+                plan.warnings.push(format!(
+                    "Layer {} ({}) is not empty but has no tarball",
+                    i + 1,
+                    layer.command
+                ));
+            }
+        }
+        if plan.branch.is_duplicate {
+            plan.warnings.push(format!(
+                "Image '{image_name}' already exists as branch '{}' with identical content",
+                plan.branch.name
+            ));
+        }
+        for warning in &plan.warnings {
+            self.notifier.debug(&format!("Plan warning: {warning}"));
+        }
+
+        Ok(plan)
+    }
+
+    /// Apply every layer of an image into `dir` without creating a Git repository.
+    ///
+    /// Layers are replayed oldest → newest with the same hardened extractor used for
+    /// conversions (whiteouts, opaque directories, hardlinks). `dir` is created if needed;
+    /// existing content is treated as a lower layer.
+    ///
+    /// See [`crate::rootfs::materialize_rootfs`] for a convenience wrapper.
+    ///
+    /// # Errors
+    /// - Image fetch/extraction failures from the underlying [`Source`] or tar processing.
+    /// - The temporary space limit would be exceeded.
+    pub fn materialize_rootfs(&self, image_name: &str, dir: &Path) -> Result<(), Oci2GitError> {
+        let (extracted_image, _temp_usage) = self
+            .fetch_image(image_name, &mut PhaseTimings::default())
+            .map_err(Oci2GitError::Source)?;
+        let layers = extracted_image.layers().map_err(Oci2GitError::Metadata)?;
+
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create rootfs directory: {}", dir.display()))?;
+
+        for (i, layer) in layers.iter().enumerate() {
+            let Some(layer_tarball) = &layer.tarball_path else {
+                continue;
+            };
+            self.progress(ProgressEvent::LayerExtracting {
+                index: i + 1,
+                total: layers.len(),
+                bytes: layer.size,
+            });
+            extracted_image
+                .extract_layer_with(layer_tarball, dir, self.conflict_strategy)
+                .map_err(Oci2GitError::Extraction)?;
+        }
+
+        Ok(())
+    }
+
+    /// With [`ImageProcessor::with_convert_base`], converts the base `plan` declares (and
+    /// its own base) into `output_dir`, then plans the branch of `plan` again so it starts
+    /// from the layer commits of the base.
+    fn convert_base(
+        &self,
+        plan: &mut ImagePlan,
+        output_dir: &Path,
+        options: &ConvertOptions,
+    ) -> Result<(), Oci2GitError> {
+        if !self.convert_base || plan.branch.is_duplicate {
+            return Ok(());
+        }
+        let Some(reference) = BaseImage::declared(&plan.metadata).and_then(|base| base.reference())
+        else {
+            return Ok(());
+        };
+        if reference == plan.image_name {
+            return Ok(());
+        }
+        self.notifier.info(&format!(
+            "Converting base image '{reference}' of '{}'",
+            plan.image_name
+        ));
+        // Only settings that decide whether layer commits can be shared carry over
+        let base_options = ConvertOptions {
+            metadata_only: options.metadata_only,
+            rootfs_dir: options.rootfs_dir.clone(),
+            ..ConvertOptions::default()
+        };
+        let converted = self
+            .analyze_with_options(&reference, output_dir, &base_options)
+            .and_then(|mut base_plan| {
+                self.convert_base(&mut base_plan, output_dir, &base_options)?;
+                self.materialize_with_options(&base_plan, output_dir, &base_options)
+            });
+        match converted {
+            Ok(summary) if summary.status == ConversionStatus::Converted => {}
+            Ok(_) => return Ok(()),
+            Err(error) => {
+                self.notifier.warn(&format!(
+                    "Failed to convert base image '{reference}': {:#}",
+                    anyhow::Error::from(error)
+                ));
+                return Ok(());
+            }
+        }
+        if !self.squash {
+            plan.branch = self
+                .plan_branch(
+                    plan.branch.name.clone(),
+                    output_dir,
+                    &plan.layers,
+                    self.rootfs_dir(options),
+                )
+                .map_err(Oci2GitError::Git)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the conversion of `plan` goes through the conversion cache.
+    fn uses_conversion_cache(&self, plan: &ImagePlan) -> bool {
+        self.conversion_cache.is_some()
+            && digest_hex(&plan.metadata.id).is_ok()
+            && !self.squash
+            && self.layout != Layout::Subdir
+            && self.layer_records == LayerRecords::Files
+    }
+
+    /// Fetches the conversion of the same image into another repository remembered in the
+    /// conversion cache, and plans the branch again to start from its layer commits. The
+    /// fetched branch is deleted again right away; the commits the new branch builds on
+    /// become reachable when it is created. Failures only warn.
+    fn seed_from_cache(
+        &self,
+        plan: &mut ImagePlan,
+        output_dir: &Path,
+        options: &ConvertOptions,
+    ) -> Result<(), Oci2GitError> {
+        let Some(cache) = self
+            .conversion_cache
+            .as_ref()
+            .filter(|_| self.uses_conversion_cache(plan))
+        else {
+            return Ok(());
+        };
+        if plan.branch.is_duplicate
+            || plan.branch.recreate
+            || plan.branch.skip_layers >= plan.layers.len()
+        {
+            return Ok(());
+        }
+        let platform = format!("{}-{}", plan.metadata.os, plan.metadata.architecture);
+        let seeded = GitRepo::init_with_branch(output_dir, None).and_then(|repo| {
+            let seed = cache.seed(&repo, &plan.metadata.id, &platform)?;
+            Ok((repo, seed))
+        });
+        let (repo, seed) = match seeded {
+            Ok((repo, Some(seed))) => (repo, seed),
+            Ok((_, None)) => return Ok(()),
+            Err(error) => {
+                self.notifier
+                    .warn(&format!("Failed to use the conversion cache: {error:#}"));
+                return Ok(());
+            }
+        };
+        self.notifier.info(&format!(
+            "Fetched an earlier conversion of '{}' from the conversion cache",
+            plan.image_name
+        ));
+        let replanned = self.plan_branch(
+            plan.branch.name.clone(),
+            output_dir,
+            &plan.layers,
+            self.rootfs_dir(options),
+        );
+        repo.delete_branch(&seed).map_err(Oci2GitError::Git)?;
+        let branch = replanned.map_err(Oci2GitError::Git)?;
+        if branch.skip_layers > plan.branch.skip_layers {
+            plan.branch = branch;
+        }
+        Ok(())
+    }
+
+    /// Remembers a conversion in the conversion cache; failures only warn.
+    fn remember_conversion(
+        &self,
+        plan: &ImagePlan,
+        output_dir: &Path,
+        summary: &ConversionSummary,
+    ) {
+        let Some(cache) = self
+            .conversion_cache
+            .as_ref()
+            .filter(|_| self.uses_conversion_cache(plan))
+        else {
+            return;
+        };
+        let remembered = output_dir
+            .canonicalize()
+            .context("Failed to resolve the output directory")
+            .and_then(|repo| {
+                cache.put(&CachedConversion {
+                    image: plan.image_name.clone(),
+                    digest: plan.metadata.id.clone(),
+                    platform: format!("{}-{}", plan.metadata.os, plan.metadata.architecture),
+                    repo,
+                    branch: summary.branch.clone(),
+                    commit: summary.commit.clone(),
+                })
+            });
+        if let Err(error) = remembered {
             self.notifier
-                .info("Existing repository detected, finding optimal branch point...");
+                .warn(&format!("Failed to update the conversion cache: {error:#}"));
+        }
+    }
 
-            let (branch_commit, matched_layers) =
-                SuccessorNavigator::find_branch_point(&repo, output_dir, &layers)?;
-            match branch_commit {
-                Some(commit) => {
-                    self.notifier.info(&format!(
-                        "Found optimal branch point at commit {commit}, skipping {matched_layers} matched layers"
+    /// Applies [`ImageProcessor::with_disk_space_check`] to `image_name`.
+    fn check_disk_space(&self, image_name: &str, output_dir: &Path) -> Result<()> {
+        if self.disk_space_check == DiskSpaceCheck::Skip {
+            return Ok(());
+        }
+        let Some(estimate) = self.source.estimate_image_size(image_name) else {
+            self.notifier.debug(&format!(
+                "The {} source can't estimate the size of '{image_name}', skipping the disk space check",
+                self.source.name()
+            ));
+            return Ok(());
+        };
+        self.notifier.debug(&format!(
+            "Estimated image size: {}",
+            disk_space::format_bytes(estimate.image_bytes)
+        ));
+
+        let shortfalls =
+            disk_space::shortfalls(&estimate.requirements(&self.workdir.path(), output_dir));
+        if shortfalls.is_empty() {
+            return Ok(());
+        }
+        let message = format!(
+            "Not enough disk space to convert '{image_name}': {}",
+            shortfalls.join("; ")
+        );
+        match self.disk_space_check {
+            DiskSpaceCheck::Fail => bail!("{message}. Free up space, or use --force to try anyway"),
+            _ => self.notifier.warn(&message),
+        }
+        Ok(())
+    }
+
+    /// Obtain the image tarball from the source and unpack it, enforcing the temporary
+    /// space limit and recording the time it took in `timings`. The tarball itself is
+    /// removed as soon as it has been unpacked.
+    fn fetch_image(
+        &self,
+        image_name: &str,
+        timings: &mut PhaseTimings,
+    ) -> Result<(ExtractedImage, TempUsage)> {
+        let _span = tracing::info_span!("fetch", source = self.source.name()).entered();
+        // Get the image tarball from the source
+        self.progress(ProgressEvent::PullStarted {
+            image: image_name,
+            source: self.source.name(),
+        });
+
+        let mut temp_usage = TempUsage::new(self.max_temp_bytes);
+
+        // Streaming sources are unpacked directly, without a tarball on disk
+        let pulling = Instant::now();
+        let mut extracted_image = match self.source.image_stream(image_name, &self.notifier)? {
+            Some(stream) => {
+                self.notifier.info("Extracting image stream...");
+                let extracted_image =
+                    ExtractedImage::from_reader_in(stream, &self.workdir, &self.notifier)?;
+                timings.pull_seconds = pulling.elapsed().as_secs_f64();
+                temp_usage.charge("extracted image", dir_size(extracted_image.extract_dir()))?;
+                extracted_image
+            }
+            None => self.extract_tarball(image_name, &mut temp_usage, timings)?,
+        };
+
+        if self.layer_mismatch != LayerMismatch::default() {
+            extracted_image.set_layer_mismatch(self.layer_mismatch)?;
+        }
+        // OCI image layouts can hold several platforms of the image
+        if let Some(platform) = self.source.platform() {
+            extracted_image.select_platform(platform)?;
+        }
+        match &self.selected_image {
+            Some(repo_tag) => extracted_image.select(repo_tag)?,
+            None => {
+                let image_tags = extracted_image.image_tags()?;
+                if image_tags.len() > 1 {
+                    self.notifier.warn(&format!(
+                        "'{image_name}' contains {} images ({}), converting the first one; use --select to pick another",
+                        image_tags.len(),
+                        image_tags.concat().join(", ")
                     ));
-                    (Some(commit), matched_layers)
-                }
-                None => {
-                    self.notifier
-                        .info("No matching path found, creating orphaned branch");
-                    (None, 0)
                 }
             }
+        }
+
+        if let Some(platform) = self.source.platform() {
+            // Image configs rarely record the variant, so only os/arch are compared
+            let metadata = extracted_image.metadata(image_name)?;
+            if !platform.matches(
+                &metadata.os,
+                &metadata.architecture,
+                platform.variant.as_deref(),
+            ) {
+                bail!(
+                    "Image '{image_name}' is {}/{}, but platform {platform} was requested",
+                    metadata.os,
+                    metadata.architecture
+                );
+            }
+        }
+
+        if let Some(mismatch) = extracted_image.history_mismatch() {
+            self.notifier.warn(mismatch);
+        }
+
+        if self.workdir.keep() {
+            self.notifier.info(&format!(
+                "Keeping unpacked image {}",
+                extracted_image.extract_dir().display()
+            ));
+        }
+
+        self.notifier.debug(&format!(
+            "Temporary space: {} bytes in use, peak {} bytes",
+            temp_usage.current(),
+            temp_usage.peak()
+        ));
+
+        Ok((extracted_image, temp_usage))
+    }
+
+    /// Get the image tarball from the source and unpack it, deleting the tarball afterwards
+    /// if the source created it.
+    fn extract_tarball(
+        &self,
+        image_name: &str,
+        temp_usage: &mut TempUsage,
+        timings: &mut PhaseTimings,
+    ) -> Result<ExtractedImage> {
+        let pulling = Instant::now();
+        let (tarball_path, tarball_temp_dir) =
+            self.source.get_image_tarball(image_name, &self.notifier)?;
+        timings.pull_seconds = pulling.elapsed().as_secs_f64();
+
+        // Only tarballs exported into a temporary directory count against the limit
+        let tarball_size = fs::metadata(&tarball_path).map(|m| m.len()).unwrap_or(0);
+        if tarball_temp_dir.is_some() {
+            temp_usage.charge("image tarball", tarball_size)?;
+        }
+
+        // Unpacking needs roughly as much space as the tarball itself
+        temp_usage.check("extracted image", tarball_size)?;
+
+        // Extract the tarball and create ExtractedImage
+        self.notifier.info("Extracting image tarball...");
+        let unpacking = Instant::now();
+        let extracted_image =
+            ExtractedImage::from_tarball_in(&tarball_path, &self.workdir, &self.notifier)?;
+        timings.unpack_seconds = unpacking.elapsed().as_secs_f64();
+        temp_usage.charge("extracted image", dir_size(extracted_image.extract_dir()))?;
+
+        // The tarball is not needed once unpacked; free its space right away
+        if let Some(temp_dir) = tarball_temp_dir {
+            if self.workdir.keep() {
+                self.notifier
+                    .info(&format!("Keeping image tarball {}", tarball_path.display()));
+            } else {
+                temp_dir
+                    .close()
+                    .context("Failed to remove temporary image tarball")?;
+                temp_usage.release("image tarball");
+            }
+        }
+
+        Ok(extracted_image)
+    }
+
+    /// Determine the branch point for `layers` in an existing repository at `output_dir`.
+    ///
+    /// Read-only: if there is no repository yet, plans an orphan branch.
+    fn plan_branch(
+        &self,
+        branch_name: String,
+        output_dir: &Path,
+        layers: &[Layer],
+        rootfs_dir: &str,
+    ) -> Result<BranchPlan> {
+        let repo = if output_dir.join(".git").exists() {
+            Some(GitRepo::open(output_dir)?)
         } else {
-            self.notifier
-                .info("New repository, creating initial branch");
-            (None, 0)
+            None
+        };
+        if self.force
+            && repo
+                .as_ref()
+                .is_some_and(|repo| repo.branch_exists(&branch_name))
+        {
+            self.notifier.info(&format!(
+                "Branch '{branch_name}' exists, recreating it from scratch (--force)"
+            ));
+            return Ok(BranchPlan {
+                name: branch_name,
+                start_from: None,
+                skip_layers: 0,
+                is_duplicate: false,
+                recreate: true,
+            });
+        }
+
+        // Determine start commit and skip count using successor navigation
+        let (start_from_commit, skip_layers) = match &repo {
+            Some(repo) if repo.exists_and_has_commits() => {
+                self.notifier
+                    .info("Existing repository detected, finding optimal branch point...");
+
+                let (branch_commit, matched_layers) =
+                    SuccessorNavigator::find_branch_point(repo, output_dir, layers)?;
+                // Commits with the filesystem in another directory can't be built on
+                let branch_commit = match branch_commit {
+                    Some(commit) => {
+                        let tree = repo.repo.find_commit(commit)?.tree()?;
+                        let theirs = query::rootfs_dir(&repo.repo, &tree);
+                        if theirs != rootfs_dir {
+                            self.notifier.info(&format!(
+                                "Matching layers at commit {commit} keep the filesystem in {theirs}/, not {rootfs_dir}/"
+                            ));
+                            None
+                        } else if SymlinkTargets::of_tree(&tree) != self.symlink_targets {
+                            self.notifier.info(&format!(
+                                "Matching layers at commit {commit} were committed with other symlink targets"
+                            ));
+                            None
+                        } else if LayerRecords::of_commit(repo, commit)?
+                            .is_some_and(|theirs| theirs != self.layer_records)
+                        {
+                            self.notifier.info(&format!(
+                                "Matching layers at commit {commit} record their layers elsewhere"
+                            ));
+                            None
+                        } else {
+                            Some(commit)
+                        }
+                    }
+                    None => None,
+                };
+                match branch_commit {
+                    Some(commit) => {
+                        self.notifier.info(&format!(
+                            "Found optimal branch point at commit {commit}, skipping {matched_layers} matched layers"
+                        ));
+                        (Some(commit), matched_layers)
+                    }
+                    None => {
+                        self.notifier
+                            .info("No matching path found, creating orphaned branch");
+                        (None, 0)
+                    }
+                }
+            }
+            _ => {
+                self.notifier
+                    .info("New repository, creating initial branch");
+                (None, 0)
+            }
         };
 
         // Check if this is a duplicate image - if branch exists and we're skipping all layers,
         // it means we're processing the exact same image again
-        if repo.branch_exists(&branch_name) && skip_layers == layers.len() {
+        let is_duplicate = repo
+            .as_ref()
+            .is_some_and(|repo| repo.branch_exists(&branch_name))
+            && skip_layers == layers.len();
+
+        Ok(BranchPlan {
+            name: branch_name,
+            start_from: start_from_commit,
+            skip_layers,
+            is_duplicate,
+            recreate: false,
+        })
+    }
+
+    /// Plan for [`ImageProcessor::with_squash`]: always an orphan branch, a duplicate if
+    /// it exists already (recreated with [`ImageProcessor::with_force`]).
+    fn plan_squashed_branch(
+        &self,
+        branch_name: String,
+        output_dir: &Path,
+        layers: &[Layer],
+    ) -> Result<BranchPlan> {
+        let exists = output_dir.join(".git").exists()
+            && GitRepo::open(output_dir)?.branch_exists(&branch_name);
+        let is_duplicate = exists && !self.force;
+        Ok(BranchPlan {
+            name: branch_name,
+            start_from: None,
+            skip_layers: if is_duplicate { layers.len() } else { 0 },
+            is_duplicate,
+            recreate: exists && self.force,
+        })
+    }
+
+    /// Final commit: `Image.md` with complete metadata (basic info, container config and the
+    /// layer digests in `digest_tracker`), then the branch description and README.
+    fn commit_metadata(
+        &self,
+        repo: &GitRepo,
+        mut rootfs_tree: Option<&mut RootfsTree>,
+        plan: &ImagePlan,
+        output_dir: &Path,
+        digest_tracker: &DigestTracker,
+        message: &str,
+    ) -> Result<()> {
+        let _span = tracing::info_span!("metadata").entered();
+        self.progress(ProgressEvent::Committing {
+            index: None,
+            total: plan.layers.len(),
+        });
+
+        let mut complete_metadata =
+            ImageMetadata::from_legacy(&plan.metadata, digest_tracker, &plan.image_name);
+        let attestations_dir = plan.extracted_image().extract_dir().join(ATTESTATIONS_DIR);
+        complete_metadata.build = BuildProvenance::collect(&complete_metadata, &attestations_dir);
+        complete_metadata.base =
+            base_image::detect(repo, &plan.branch.name, &plan.metadata, &plan.layers)?;
+
+        // Declared volumes become tracked marker directories in rootfs/
+        if let Some(container_config) = &complete_metadata.container_config {
+            match rootfs_tree.as_deref_mut() {
+                Some(tree) => volumes::add_markers(tree, &container_config.volumes)?,
+                None => volumes::write_markers(
+                    &output_dir.join(repo.rootfs_dir()),
+                    &container_config.volumes,
+                )?,
+            }
+        }
+        complete_metadata.save(output_dir, self.metadata_format)?;
+        if self.layer_graph && self.metadata_format.writes_markdown() {
+            layer_graph::append(
+                repo,
+                &plan.branch.name,
+                &complete_metadata,
+                &output_dir.join(IMAGE_MD),
+            )?;
+        }
+        // Signatures and attestations the source found next to the image
+        attestations::copy(&attestations_dir, &output_dir.join(ATTESTATIONS_DIR))?;
+        if self.env_scripts {
+            env_scripts::write(output_dir, &plan.metadata.container_config)?;
+        }
+        if self.materialize_config {
+            materialize_config::write(
+                output_dir,
+                &output_dir.join(repo.rootfs_dir()),
+                rootfs_tree.as_deref_mut(),
+                &plan.metadata.container_config,
+            )?;
+        }
+        if self.layer_stats {
+            LayerStatsReport::collect(repo, &plan.branch.name, &complete_metadata)?
+                .save(output_dir, self.metadata_format)?;
+        }
+        if self.emit_readme {
+            let layers = LayerStatsReport::collect(repo, &plan.branch.name, &complete_metadata)?;
+            repo_readme::write_branch(output_dir, &complete_metadata, &layers, repo.rootfs_dir())?;
+        }
+        if self.html_report {
+            HtmlReport::collect(repo, &plan.branch.name, &complete_metadata)?
+                .save(output_dir, self.report_commit_url.as_deref())?;
+        }
+        // Base-* and Build-* trailers link the metadata commit to the base image and to
+        // the source the image was built from
+        let trailers = complete_metadata
+            .base
+            .as_ref()
+            .map(BaseImage::trailers)
+            .unwrap_or_default()
+            + &complete_metadata
+                .build
+                .as_ref()
+                .map(BuildProvenance::trailers)
+                .unwrap_or_default();
+        let message = if trailers.is_empty() {
+            message.to_string()
+        } else {
+            format!("{message}\n\n{trailers}")
+        };
+        commit(repo, rootfs_tree.as_deref_mut(), None, &message, None)?;
+        // The worktree still has symlinks pointing below rootfs/ with in-image targets
+        if rootfs_tree.is_some() || self.symlink_targets == SymlinkTargets::Image {
+            self.notifier.info("Checking out rootfs...");
+            repo.sync_worktree()?;
+        }
+
+        // Describe the branch and list it in the README on the default branch; the
+        // branches of the subdir layout are gone once the image is in place
+        if self.layout == Layout::Subdir {
+            return Ok(());
+        }
+        if let Some(basic_info) = &complete_metadata.basic_info {
+            repo.set_branch_description(
+                &plan.branch.name,
+                &repo_readme::branch_description(basic_info),
+            )?;
+        }
+        self.notifier.info("Updating repository README...");
+        repo_readme::update(repo)?;
+        Ok(())
+    }
+
+    /// The rootfs directory of a conversion with `options`.
+    fn rootfs_dir<'a>(&'a self, options: &'a ConvertOptions) -> &'a str {
+        options
+            .rootfs_dir
+            .as_deref()
+            .or(self.rootfs_dir.as_deref())
+            .unwrap_or(DEFAULT_ROOTFS_DIR)
+    }
+
+    /// Repacks `repo` if [`ImageProcessor::with_gc`] is set.
+    fn gc(&self, repo: &GitRepo) -> Result<()> {
+        if !self.gc {
+            return Ok(());
+        }
+        let _span = tracing::info_span!("repack").entered();
+        self.notifier.info("Repacking repository...");
+        let stats = repo.repack()?;
+        self.notifier.info(&format!(
+            "Repacked {} objects: {} -> {}",
+            stats.objects,
+            disk_space::format_bytes(stats.bytes_before),
+            disk_space::format_bytes(stats.bytes_after)
+        ));
+        Ok(())
+    }
+
+    /// Tags the tip of the converted image (`summary.commit`) if
+    /// [`ImageProcessor::with_tag_template`] is set. An existing tag on another commit is
+    /// reported and left alone.
+    fn tag_tip(&self, repo: &GitRepo, plan: &ImagePlan, summary: &ConversionSummary) -> Result<()> {
+        let Some(template) = &self.tag_template else {
+            return Ok(());
+        };
+        let input = BranchNameInput::new(&plan.image_name, self.source.name(), &plan.metadata);
+        let tag_name = template.branch_name(&input)?;
+        let Ok(tip) = git2::Oid::from_str(&summary.commit) else {
+            return Ok(());
+        };
+        let message = format!(
+            "{} ({}/{})\n\nBranch: {}\nImage-Id: {}\n",
+            plan.image_name,
+            plan.metadata.os,
+            plan.metadata.architecture,
+            summary.branch,
+            plan.metadata.id
+        );
+        match repo.create_tag(&tag_name, tip, &message) {
+            Ok(true) => self.notifier.info(&format!("Tagged {tip} as '{tag_name}'")),
+            Ok(false) => self
+                .notifier
+                .debug(&format!("Tag '{tag_name}' already points at {tip}")),
+            Err(error) => self.notifier.warn(&format!("{error:#}")),
+        }
+        Ok(())
+    }
+
+    /// Execute a plan produced by [`ImageProcessor::analyze`], writing the Git repository,
+    /// `rootfs/` and `Image.md` into `output_dir`. The returned summary's elapsed time
+    /// only covers this step.
+    ///
+    /// # Errors
+    /// - Git repository initialization/commit errors.
+    /// - Filesystem operations while applying layers (permissions, symlinks, deletions).
+    /// - Metadata serialization/parsing of `Image.md`.
+    pub fn materialize(
+        &self,
+        plan: &ImagePlan,
+        output_dir: &Path,
+    ) -> Result<ConversionSummary, Oci2GitError> {
+        self.materialize_with_options(plan, output_dir, &ConvertOptions::default())
+    }
+
+    /// Same as [`ImageProcessor::materialize`], with the file size limit and commit
+    /// messages of `options`. The branch name comes from the plan.
+    ///
+    /// # Errors
+    /// - Any error of [`ImageProcessor::materialize`].
+    pub fn materialize_with_options(
+        &self,
+        plan: &ImagePlan,
+        output_dir: &Path,
+        options: &ConvertOptions,
+    ) -> Result<ConversionSummary, Oci2GitError> {
+        let _span = tracing::info_span!("materialize", branch = %plan.branch.name).entered();
+        let started = Instant::now();
+        let max_file_size = options.max_file_size.or(self.max_file_size);
+        let options = &options.clone().with_commit_message_template(
+            options
+                .commit_message_template
+                .clone()
+                .or_else(|| self.commit_message_template.clone()),
+        );
+        let image_name = plan.image_name.as_str();
+        let layers = &plan.layers;
+        let extracted_image = plan.extracted_image();
+        let branch_name = plan.branch.name.as_str();
+        let start_from_commit = plan.branch.start_from;
+        let skip_layers = plan.branch.skip_layers.min(layers.len());
+        let mut timings = plan.timings.clone();
+
+        if plan.branch.is_duplicate {
             self.notifier.info(&format!(
                 "Image '{image_name}' already exists as branch '{branch_name}' with identical content. Skipping duplicate processing."
             ));
-            return Ok(());
+            let repo = GitRepo::open(output_dir)?.with_identity(self.commit_identity.clone())?;
+            let mut summary =
+                ConversionSummary::from_branch(&repo, image_name, branch_name, layers.len())?;
+            summary.status = ConversionStatus::SkippedDuplicate;
+            if self.layout == Layout::Subdir {
+                // The branch existed before, so it stays
+                summary = self.place_in_subdir(&repo, plan, summary, false)?;
+            } else {
+                repo_readme::update(&repo)?;
+            }
+            self.tag_tip(&repo, plan, &summary)?;
+            self.progress(ProgressEvent::Done {
+                image: image_name,
+                branch: &summary.branch,
+            });
+            summary.layers_skipped = layers.len();
+            summary.elapsed_seconds = started.elapsed().as_secs_f64();
+            summary.timings = timings;
+            return Ok(summary);
+        }
+
+        self.notifier.info("Initializing Git repository...");
+
+        // Initialize or open repository
+        let repo = GitRepo::init_with_branch(output_dir, None)?
+            .with_identity(self.commit_identity.clone())?
+            .with_jobs(self.jobs)
+            .with_rootfs_dir(self.rootfs_dir(options))
+            .with_symlink_targets(self.symlink_targets);
+
+        if plan.branch.recreate && repo.delete_branch(branch_name)? {
+            self.notifier.info(&format!(
+                "Deleted branch '{branch_name}' to convert it again"
+            ));
         }
 
         // Create the branch from the optimal point
-        repo.create_branch(&branch_name, start_from_commit)?;
+        repo.create_branch(branch_name, start_from_commit)?;
 
-        // Create the rootfs directory
-        let rootfs_dir = output_dir.join("rootfs");
+        // Create the rootfs directory, named for the readers if it isn't the default one
+        let rootfs_dir = output_dir.join(repo.rootfs_dir());
         fs::create_dir_all(&rootfs_dir)?;
+        if repo.rootfs_dir() != DEFAULT_ROOTFS_DIR {
+            let marker = output_dir.join(ROOTFS_DIR_PATH);
+            fs::create_dir_all(marker.parent().unwrap_or(output_dir))?;
+            fs::write(&marker, format!("{}\n", repo.rootfs_dir()))?;
+        }
+        self.symlink_targets.save(output_dir)?;
 
-        // If there are no layers, exit early
+        // An image without layers still gets its metadata commit
         if layers.is_empty() {
             self.notifier.warn("No layers found in the image");
-            self.notifier.info("Warning: No layers found in the image");
-            return Ok(());
         }
 
         // Count layers with tarballs for debugging info
@@ -230,6 +1942,10 @@ impl<S: Source> ImageProcessor<S> {
 
         // Extract layers directly to the target rootfs directory
         let rootfs_path = rootfs_dir.clone();
+        let mut rootfs_tree = self
+            .direct_commits
+            .then(|| RootfsTree::from_commit(&repo.repo, start_from_commit, &rootfs_path))
+            .transpose()?;
 
         // Each layer now contains its own tarball path and digest information
         self.notifier.debug(&format!(
@@ -240,37 +1956,95 @@ impl<S: Source> ImageProcessor<S> {
 
         // Initialize digest tracker for new commits
         let mut new_digest_tracker = if let Some(start_commit) = start_from_commit {
-            // Load existing digest tracker from the start commit Image.md
-            match repo.read_file_from_commit(start_commit, "Image.md") {
-                Ok(content) => {
-                    let image_metadata =
-                        crate::image_metadata::ImageMetadata::parse_markdown(&content)
-                            .context("Failed to parse existing Image.md")?;
-                    DigestTracker {
-                        layer_digests: image_metadata.layer_digests,
-                    }
-                }
+            // Load existing digest tracker from the layer chain the branch point was found
+            // by; none there means a fresh tracker
+            let layer_digests = layer_records::read_chain(&repo, start_commit)?.unwrap_or_default();
+            DigestTracker { layer_digests }
+        } else {
+            // Starting fresh, create new tracker
+            DigestTracker::new()
+        };
+
+        // Filesystem metadata continues from the start commit's sidecar
+        let mut fs_metadata = if !self.fs_metadata {
+            None
+        } else if let Some(start_commit) = start_from_commit {
+            match repo.read_file_from_commit(start_commit, FSMETA_PATH) {
+                Ok(content) => Some(FsMetadata::parse(&content)?),
                 Err(_) => {
-                    // No Image.md in the start commit, create new tracker
-                    DigestTracker::new()
+                    self.notifier.warn(&format!(
+                        "The {skip_layers} reused layers carry no {FSMETA_PATH}; it will only describe the layers added on top"
+                    ));
+                    Some(FsMetadata::new())
                 }
             }
         } else {
-            // Starting fresh, create new tracker
-            DigestTracker::new()
+            Some(FsMetadata::new())
         };
 
-        // Initialize structured image metadata with only layer data (no basic_info or container_config until final commit)
-        let mut structured_metadata = ImageMetadata::new(None, None);
-        structured_metadata.update_layer_digests(&new_digest_tracker);
+        let mut special_files = match start_from_commit.and_then(|start_commit| {
+            repo.read_file_from_commit(start_commit, SPECIAL_FILES_PATH)
+                .ok()
+        }) {
+            Some(content) => SpecialFiles::parse(&content)?,
+            None => SpecialFiles::new(),
+        };
+
+        // Per layer; always saved, so a list inherited from the branch point never lingers
+        let mut deleted_files = DeletedFiles::new();
+        let mut content_hashes = ContentHashes::new();
+        // Per layer as well; what gets staged for layer commits in the worktree
+        let mut touched = TouchedPaths::new(&rootfs_path);
+        let layer_options = LayerOptions {
+            strategy: self.conflict_strategy,
+            max_file_size,
+            exclude: self.exclude.clone(),
+            jobs: self.jobs,
+            sparse_files: self.sparse_files,
+            symlink_targets: self.symlink_targets,
+        };
+
+        let mut large_files = match start_from_commit.and_then(|start_commit| {
+            repo.read_file_from_commit(start_commit, LARGE_FILES_PATH)
+                .ok()
+        }) {
+            Some(content) => LargeFiles::parse(&content)?,
+            None => LargeFiles::new(),
+        };
+
+        // The image config after each step of the history, committed with its layer
+        let config_states = self.config_history.then(|| {
+            let commands: Vec<&str> = layers.iter().map(|layer| layer.command.as_str()).collect();
+            config_history::history(&plan.metadata.container_config, &commands)
+        });
+
+        // Layer commits record the layer chain in layers/ only; the full report is written
+        // by the metadata commit. Branch points of earlier versions carry a partial one.
+        for name in [IMAGE_MD, IMAGE_JSON] {
+            let path = output_dir.join(name);
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
 
         // Now process layers starting from the first unmatched layer
         let layers_to_process = layers.len() - skip_layers;
+        let mut layers_skipped = skip_layers;
+        let mut bytes_processed = 0;
         self.notifier.info(&format!(
             "Processing {layers_to_process} layers (skipping {skip_layers} matched layers)..."
         ));
 
         for (i, layer) in layers.iter().enumerate().skip(skip_layers) {
+            let _span = tracing::info_span!(
+                "layer",
+                index = i + 1,
+                total = layers.len(),
+                digest = %layer.digest,
+                bytes = layer.size
+            )
+            .entered();
             self.notifier.info(&format!(
                 "Layer {}/{}: {}",
                 i + 1,
@@ -288,54 +2062,202 @@ impl<S: Source> ImageProcessor<S> {
                     "Layer {} already exists with same digest, skipping unpacking",
                     i + 1
                 ));
+                layers_skipped += 1;
                 continue;
             }
 
+            // A squashed commit lists the deletions of every layer
+            if !self.squash {
+                deleted_files.paths.clear();
+                content_hashes.entries.clear();
+                touched.clear();
+            }
+            let layer_info = LayerInfo {
+                index: i + 1,
+                total: layers.len(),
+                layer,
+            };
+            self.plugins.layer_start(&layer_info)?;
+
             if layer.tarball_path.is_none() {
                 // Create an empty commit for layers without file changes
-                let commit_message = if layer.is_empty {
-                    format!("⚪️ - {}", layer.command)
-                } else {
-                    format!("⚫ - {}", layer.command)
-                };
+                let commit_message = options.layer_subject(layer, i + 1);
+                deleted_files.save(&output_dir.join(DELETED_FILES_PATH))?;
+                content_hashes.save(&output_dir.join(CONTENT_HASHES_PATH))?;
+                // Metadata-only commits of eStargz layers list the files of the layer
+                let toc = estargz::layer_toc(extracted_image.extract_dir(), layer)?;
+                estargz::save_listing(&output_dir.join(LAYER_FILES_PATH), toc.as_ref())?;
 
                 // Track empty layer in digest tracker
-                // Use the current length of the digest tracker as the new position
+                // Use the current length of the digest tracker as the new position.
+                // Recorded as empty: the commit carries the digest but no file changes
                 new_digest_tracker.add_layer(
                     new_digest_tracker.layer_digests.len(),
                     layer.digest.clone(),
                     layer.command.clone(),
                     layer.created_at.to_rfc3339(),
-                    layer.is_empty,
+                    true,
                     layer.comment.clone(),
                 );
+                self.plugins
+                    .layer_commit(&layer_info, output_dir, repo.rootfs_dir())?;
+
+                if self.squash {
+                    continue;
+                }
 
-                // Update structured metadata with current layer digests and save Image.md
-                structured_metadata.update_layer_digests(&new_digest_tracker);
-                let metadata_path = output_dir.join("Image.md");
-                structured_metadata.save_markdown(&metadata_path)?;
+                self.layer_records
+                    .save(output_dir, &new_digest_tracker.layer_digests)?;
+                if let Some(states) = &config_states {
+                    states[i].save(output_dir)?;
+                }
 
                 self.notifier.debug(&format!(
                     "Creating empty commit for layer: {}",
                     layer.command
                 ));
-                repo.commit_all_changes(&commit_message)?;
+                self.progress(ProgressEvent::Committing {
+                    index: Some(i + 1),
+                    total: layers.len(),
+                });
+                let committing = Instant::now();
+                commit(
+                    &repo,
+                    rootfs_tree.as_mut(),
+                    Some(&touched),
+                    &commit_message,
+                    Some(&layer_trailers(&new_digest_tracker)),
+                )?;
+                self.layer_records
+                    .annotate(&repo, &new_digest_tracker.layer_digests)?;
+                timings.layer(i + 1, &layer.digest, 0).commit_seconds =
+                    committing.elapsed().as_secs_f64();
                 continue;
             }
 
             let layer_tarball = layer.tarball_path.as_ref().unwrap();
+            bytes_processed += layer.size;
 
-            // Extract this layer to the temporary directory
-            self.notifier
-                .info(&format!("Extracting layer {}/{}", i + 1, layers.len()));
+            self.progress(ProgressEvent::LayerExtracting {
+                index: i + 1,
+                total: layers.len(),
+                bytes: layer.size,
+            });
 
             self.notifier
                 .debug(&format!("Extracting tarball: {layer_tarball:?}"));
             fs::create_dir_all(&rootfs_path)?;
 
-            // Extract the layer tarball directly to rootfs
-            // tar_extractor now handles: whiteouts, hardlinks, permission fixing, overlay behavior
-            extracted_image.extract_layer_to(layer_tarball, &rootfs_path)?;
+            // Entries stream from the layer tarball straight into rootfs, no staging copy;
+            // the applier handles whiteouts, hardlinks, permission fixing, overlay behavior
+            let mut observers: Vec<&mut dyn LayerObserver> =
+                vec![&mut special_files, &mut large_files];
+            if let Some(fs_metadata) = fs_metadata.as_mut() {
+                observers.push(fs_metadata);
+            }
+            if self.record_deletions {
+                observers.push(&mut deleted_files);
+            }
+            if self.content_hashes {
+                observers.push(&mut content_hashes);
+            }
+            if rootfs_tree.is_none() && !self.squash {
+                observers.push(&mut touched);
+            }
+            let mut plugins = &self.plugins;
+            if !plugins.is_empty() {
+                observers.push(&mut plugins);
+            }
+            let mut report_bytes = |read| {
+                self.progress(ProgressEvent::LayerBytes {
+                    index: i + 1,
+                    total: layers.len(),
+                    read,
+                    bytes: layer.size,
+                })
+            };
+            let extract_span = tracing::info_span!("extract").entered();
+            let applying = Instant::now();
+            let extracted = match rootfs_tree.as_mut() {
+                Some(tree) => extracted_image.apply_layer_to_tree(
+                    layer_tarball,
+                    tree,
+                    &layer_options,
+                    &mut observers,
+                    &mut report_bytes,
+                ),
+                None => extracted_image.extract_layer_observed(
+                    layer_tarball,
+                    &rootfs_path,
+                    &layer_options,
+                    &mut observers,
+                    &mut report_bytes,
+                ),
+            };
+            drop(extract_span);
+            let verified = extracted
+                .and_then(|digest| self.verify_layer_digest(layer, &digest, i + 1, layers.len()));
+            timings
+                .layer(i + 1, &layer.digest, layer.size)
+                .apply_seconds = applying.elapsed().as_secs_f64();
+            let verified = match verified {
+                Ok(verified) => verified,
+                Err(error) => {
+                    let error =
+                        self.keep_failed_layer(extracted_image, branch_name, i, layer, error);
+                    if !self.commit_partial || self.squash {
+                        return Err(Oci2GitError::Extraction(error));
+                    }
+
+                    self.notifier.warn(&format!(
+                        "Layer {}/{} failed to extract, committing a PARTIAL branch: {error:#}",
+                        i + 1,
+                        layers.len()
+                    ));
+                    repo.discard_changes()?;
+                    if rootfs_tree.is_some() {
+                        // Drop what the failed layer applied to the tree
+                        let head = repo.repo.head().ok().and_then(|head| head.target());
+                        rootfs_tree =
+                            Some(RootfsTree::from_commit(&repo.repo, head, &rootfs_path)?);
+                    }
+                    let (failed, total) = (i + 1, layers.len());
+                    let report = format!(
+                        "# PARTIAL conversion\n\n\
+                     Layer {failed} of {total} could not be extracted. This branch ends at the \
+                     last good layer; `{}/` and `Image.md` don't contain the failed layer \
+                     or any layer after it.\n\n\
+                     - **Failed layer**: {failed}/{total}\n\
+                     - **Command**: `{}`\n\
+                     - **Digest**: `{}`\n\
+                     - **Error**: {}\n",
+                        repo.rootfs_dir(),
+                        layer.command,
+                        layer.digest,
+                        format!("{error:#}").replace('\n', " "),
+                    );
+                    fs::write(output_dir.join(PARTIAL_PATH), report)?;
+                    self.commit_metadata(
+                        &repo,
+                        rootfs_tree.as_mut(),
+                        plan,
+                        output_dir,
+                        &new_digest_tracker,
+                        &format!("🛠️ - Metadata (PARTIAL: layer {failed}/{total} failed)"),
+                    )
+                    .map_err(Oci2GitError::Metadata)?;
+                    return Err(Oci2GitError::Extraction(error.context(format!(
+                    "Conversion of '{image_name}' is PARTIAL: branch '{branch_name}' ends before layer {failed}/{total}"
+                ))));
+                }
+            };
+            special_files.save(&output_dir.join(SPECIAL_FILES_PATH))?;
+            large_files.save(&output_dir.join(LARGE_FILES_PATH))?;
+            deleted_files.save(&output_dir.join(DELETED_FILES_PATH))?;
+            content_hashes.save(&output_dir.join(CONTENT_HASHES_PATH))?;
+            if let Some(fs_metadata) = &fs_metadata {
+                fs_metadata.save(&output_dir.join(FSMETA_PATH))?;
+            }
 
             // Track non-empty layer with digest
             // Use the current length of the digest tracker as the new position
@@ -347,38 +2269,241 @@ impl<S: Source> ImageProcessor<S> {
                 false,
                 layer.comment.clone(),
             );
+            new_digest_tracker.set_verified(new_digest_tracker.layer_digests.len() - 1, verified);
+            self.plugins
+                .layer_commit(&layer_info, output_dir, repo.rootfs_dir())?;
+
+            // The squashed commit carries no layer records, so branch point lookups never
+            // mistake it for a layer commit
+            if self.squash {
+                continue;
+            }
 
-            // Update structured metadata with current layer digests and save Image.md
-            structured_metadata.update_layer_digests(&new_digest_tracker);
-            let metadata_path = output_dir.join("Image.md");
-            structured_metadata.save_markdown(&metadata_path)?;
+            // Adds the file of this layer
+            self.layer_records
+                .save(output_dir, &new_digest_tracker.layer_digests)?;
+            if let Some(states) = &config_states {
+                states[i].save(output_dir)?;
+            }
 
             // Commit the changes for this layer
-            self.notifier
-                .info(&format!("Committing layer {}/{}", i + 1, layers.len()));
+            self.progress(ProgressEvent::Committing {
+                index: Some(i + 1),
+                total: layers.len(),
+            });
 
-            repo.commit_all_changes(&format!("🟢 - {}", layer.command))?;
+            let committing = Instant::now();
+            commit(
+                &repo,
+                rootfs_tree.as_mut(),
+                Some(&touched),
+                &options.layer_subject(layer, i + 1),
+                Some(&layer_trailers(&new_digest_tracker)),
+            )?;
+            self.layer_records
+                .annotate(&repo, &new_digest_tracker.layer_digests)?;
+            timings
+                .layer(i + 1, &layer.digest, layer.size)
+                .commit_seconds = committing.elapsed().as_secs_f64();
         }
 
         // Ownership fixup removed - files will maintain their permissions from extraction
 
-        // Final commit: Add Image.md with complete metadata (basic_info + container_config + layer digests)
-        self.notifier.info("Creating metadata commit...");
+        if self.squash {
+            if let Some(last) = config_states.as_ref().and_then(|states| states.last()) {
+                last.save(output_dir)?;
+            }
+            self.progress(ProgressEvent::Committing {
+                index: Some(layers.len()),
+                total: layers.len(),
+            });
+            let committing = Instant::now();
+            commit(
+                &repo,
+                rootfs_tree.as_mut(),
+                None,
+                &format!("📦 - Squashed {} layers", layers.len()),
+                None,
+            )?;
+            // The one commit of all layers counts for the last one
+            if let Some(last) = timings.layers.last_mut() {
+                last.commit_seconds = committing.elapsed().as_secs_f64();
+            }
+        }
 
-        // Create complete structured metadata with all information for final commit
-        let complete_metadata =
-            ImageMetadata::from_legacy(&metadata, &new_digest_tracker, image_name);
-        let metadata_path = output_dir.join("Image.md");
-        complete_metadata.save_markdown(&metadata_path)?;
-        repo.commit_all_changes("🛠️ - Metadata")?;
+        let finishing = Instant::now();
+        self.commit_metadata(
+            &repo,
+            rootfs_tree.as_mut(),
+            plan,
+            output_dir,
+            &new_digest_tracker,
+            "🛠️ - Metadata",
+        )
+        .map_err(Oci2GitError::Metadata)?;
+        let mut summary =
+            ConversionSummary::from_branch(&repo, image_name, branch_name, skip_layers)?;
+        if self.layout == Layout::Subdir {
+            summary = self.place_in_subdir(&repo, plan, summary, true)?;
+        }
+        self.tag_tip(&repo, plan, &summary)?;
+        timings.metadata_seconds = finishing.elapsed().as_secs_f64();
+        let repacking = Instant::now();
+        self.gc(&repo)?;
+        timings.gc_seconds = repacking.elapsed().as_secs_f64();
 
-        let msg = format!(
+        self.notifier.debug(&format!(
             "Successfully converted image '{}' to Git repository at '{}'",
             image_name,
             output_dir.display()
+        ));
+        self.progress(ProgressEvent::Done {
+            image: image_name,
+            branch: &summary.branch,
+        });
+
+        Ok(ConversionSummary {
+            layers_skipped,
+            bytes_processed,
+            elapsed_seconds: started.elapsed().as_secs_f64(),
+            timings,
+            ..summary
+        })
+    }
+
+    /// [`Layout::Subdir`]: copies the commits of the converted branch into the image's
+    /// directory on [`SUBDIR_BRANCH`], checks that out and deletes the branch with
+    /// `delete_branch`. `summary` of the branch becomes that of the copies; if the
+    /// directory already holds the image, nothing is copied and the summary names the
+    /// commits of its latest copy, as a duplicate.
+    fn place_in_subdir(
+        &self,
+        repo: &GitRepo,
+        plan: &ImagePlan,
+        summary: ConversionSummary,
+        delete_branch: bool,
+    ) -> Result<ConversionSummary> {
+        let input = BranchNameInput::new(&plan.image_name, self.source.name(), &plan.metadata);
+        let dir = layout::image_dir(&input, self.platform_dirs);
+        let commits = repo.get_branch_commits(&plan.branch.name)?;
+        let tip = *commits.last().context("Converted branch has no commits")?;
+
+        let (placed, status) = if layout::is_placed(repo, &dir, tip)? {
+            self.notifier.info(&format!(
+                "{} already holds this image, nothing to commit",
+                layout::dir_path(&dir).display()
+            ));
+            (
+                layout::placed_commits(repo, &dir, commits.len())?,
+                ConversionStatus::SkippedDuplicate,
+            )
+        } else {
+            self.notifier.info(&format!(
+                "Copying {} commits into {} on branch '{SUBDIR_BRANCH}'...",
+                commits.len(),
+                layout::dir_path(&dir).display()
+            ));
+            let placed = repo.replay_into_dir(
+                &commits,
+                SUBDIR_BRANCH,
+                &layout::dir_path(&dir),
+                &layout::subject_prefix(&dir),
+            )?;
+            layer_records::copy_notes(repo, &commits, &placed)?;
+            (placed, ConversionStatus::Converted)
+        };
+
+        repo.checkout_branch(SUBDIR_BRANCH)?;
+        if delete_branch {
+            repo.delete_branch(&plan.branch.name)?;
+        }
+        // The copies are new, unless the image was in place already
+        let reused = match status {
+            ConversionStatus::Converted => 0,
+            ConversionStatus::SkippedDuplicate => usize::MAX,
+        };
+        Ok(ConversionSummary {
+            status,
+            ..ConversionSummary::from_commits(repo, &summary.image, SUBDIR_BRANCH, &placed, reused)?
+        })
+    }
+
+    /// Applies [`ImageProcessor::with_digest_verification`] to a layer whose uncompressed
+    /// tar stream hashed to `digest`, returning what to record for it: `None` if the image
+    /// config lists no `diff_id` for the layer.
+    fn verify_layer_digest(
+        &self,
+        layer: &Layer,
+        digest: &str,
+        index: usize,
+        total: usize,
+    ) -> Result<Option<bool>> {
+        if self.digest_verification == DigestVerification::Skip {
+            return Ok(None);
+        }
+        // Images without `rootfs.diff_ids` can't be verified, which is no mismatch
+        let Some(expected) = layer.diff_id.as_deref() else {
+            self.notifier.debug(&format!(
+                "Layer {index}/{total} has no diff_id in the image config, not verifying it"
+            ));
+            return Ok(None);
+        };
+        if expected == digest {
+            return Ok(Some(true));
+        }
+
+        let message = format!(
+            "Layer {index}/{total} does not match its digest: expected {expected}, got {digest}"
         );
-        self.notifier.info(&msg);
+        match self.digest_verification {
+            DigestVerification::Fail => bail!("{message}"),
+            _ => {
+                self.notifier.warn(&message);
+                Ok(Some(false))
+            }
+        }
+    }
 
-        Ok(())
+    /// Reports `event` to the progress sink, or to the notifier if there is none.
+    fn progress(&self, event: ProgressEvent) {
+        match &self.progress_sink {
+            Some(sink) => sink.event(&event),
+            None => self.notifier.event(&event),
+        }
+    }
+}
+
+/// Commits on `HEAD` with the layer trailers of `layer`, if any: the worktree with the
+/// `rootfs/` of `rootfs_tree` for [`ImageProcessor::with_direct_commits`], the worktree
+/// with only the `touched` paths of `rootfs/` staged, or the whole worktree.
+fn commit(
+    repo: &GitRepo,
+    rootfs_tree: Option<&mut RootfsTree>,
+    touched: Option<&TouchedPaths>,
+    message: &str,
+    layer: Option<&LayerTrailers>,
+) -> Result<bool> {
+    let _span = tracing::info_span!("commit").entered();
+    match (rootfs_tree, touched, layer) {
+        (Some(tree), _, Some(layer)) => {
+            repo.commit_layer_with_rootfs_tree(message, layer, tree.write()?)
+        }
+        (Some(tree), _, None) => repo.commit_with_rootfs_tree(message, tree.write()?),
+        (None, Some(touched), Some(layer)) => repo.commit_layer_touched(message, layer, touched),
+        (None, Some(touched), None) => repo.commit_touched(message, touched),
+        (None, None, Some(layer)) => repo.commit_layer(message, layer),
+        (None, None, None) => repo.commit_all_changes(message),
+    }
+}
+
+/// Trailers of the layer just added to `digest_tracker`
+fn layer_trailers(digest_tracker: &DigestTracker) -> LayerTrailers {
+    let index = digest_tracker.layer_digests.len();
+    let layer = &digest_tracker.layer_digests[index - 1];
+    LayerTrailers {
+        index,
+        digest: layer.digest.clone(),
+        created: Some(layer.created.clone()),
+        empty: Some(layer.is_empty),
     }
 }
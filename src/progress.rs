@@ -0,0 +1,203 @@
+//! Progress events for applications embedding oci2git.
+//!
+//! [`ImageProcessor`](crate::processor::ImageProcessor) reports the milestones of a
+//! conversion as [`ProgressEvent`]s. By default they go to its [`Notifier`] (spinner or
+//! log lines, like every other message); a [`ProgressSink`] set with
+//! [`ImageProcessor::with_progress_sink`](crate::processor::ImageProcessor::with_progress_sink)
+//! receives them instead, so GUIs and services can render their own progress:
+//!
+//! ```no_run
+//! use oci2git::prelude::*;
+//! use std::path::Path;
+//! use std::sync::mpsc;
+//!
+//! struct Channel(mpsc::Sender<String>);
+//!
+//! impl ProgressSink for Channel {
+//!     fn event(&self, event: &ProgressEvent) {
+//!         let _ = self.0.send(format!("{event:?}"));
+//!     }
+//! }
+//!
+//! let (sender, receiver) = mpsc::channel();
+//! let mut processor = ImageProcessor::new(RegistrySource::new()?, Notifier::new(0))
+//!     .with_progress_sink(Some(Box::new(Channel(sender))));
+//! processor.convert("alpine:3.20", Path::new("./alpine"))?;
+//! for event in receiver.try_iter() {
+//!     println!("{event}");
+//! }
+//! # anyhow::Ok(())
+//! ```
+
+use crate::notifier::Notifier;
+use serde::Serialize;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+/// How often [`ProgressReader`] reports at most.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A milestone of a conversion, in the order they occur.
+///
+/// Serializes to an object of its fields; [`ProgressEvent::name`] tells the variant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum ProgressEvent<'a> {
+    /// The source starts fetching `image`.
+    PullStarted { image: &'a str, source: &'a str },
+    /// Layer `index` (1-based) of `total` is applied to `rootfs/`; `bytes` is the size of
+    /// its tarball.
+    LayerExtracting {
+        index: usize,
+        total: usize,
+        bytes: u64,
+    },
+    /// `read` of the `bytes` of layer `index`'s tarball have been applied; sent every
+    /// 100 ms or so while a layer is extracted, and once more when it is done.
+    LayerBytes {
+        index: usize,
+        total: usize,
+        read: u64,
+        bytes: u64,
+    },
+    /// The commit of layer `index` (1-based) of `total` is created, or the final metadata
+    /// commit for `index: None`.
+    Committing { index: Option<usize>, total: usize },
+    /// `image` has been converted into `branch` (or already was).
+    Done { image: &'a str, branch: &'a str },
+}
+
+impl ProgressEvent<'_> {
+    /// `snake_case` name of the variant, e.g. `layer_extracting`; the event of the JSON
+    /// log format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProgressEvent::PullStarted { .. } => "pull_started",
+            ProgressEvent::LayerExtracting { .. } => "layer_extracting",
+            ProgressEvent::LayerBytes { .. } => "layer_bytes",
+            ProgressEvent::Committing { .. } => "committing",
+            ProgressEvent::Done { .. } => "done",
+        }
+    }
+}
+
+/// Receives the [`ProgressEvent`]s of a conversion.
+///
+/// Events arrive on the thread running the conversion; implementations should return
+/// quickly, e.g. by forwarding them to a channel.
+pub trait ProgressSink: Send {
+    fn event(&self, event: &ProgressEvent);
+}
+
+/// The terminal UI: events update the spinner and the byte progress bar of the current
+/// layer (or become info logs, with their fields in the JSON log format).
+impl ProgressSink for Notifier {
+    fn event(&self, event: &ProgressEvent) {
+        let message = match *event {
+            ProgressEvent::PullStarted { source, .. } => {
+                format!("Getting image tarball using {source} source...")
+            }
+            ProgressEvent::LayerExtracting { index, total, .. } => {
+                format!("Extracting layer {index}/{total}")
+            }
+            ProgressEvent::LayerBytes {
+                index,
+                total,
+                read,
+                bytes,
+            } => {
+                self.byte_progress(read, bytes, &format!("Layer {index}/{total}"));
+                return;
+            }
+            ProgressEvent::Committing {
+                index: Some(index),
+                total,
+            } => format!("Committing layer {index}/{total}"),
+            ProgressEvent::Committing { index: None, .. } => {
+                "Creating metadata commit...".to_string()
+            }
+            ProgressEvent::Done { image, branch } => {
+                format!("Converted image '{image}' into branch '{branch}'")
+            }
+        };
+        if matches!(
+            event,
+            ProgressEvent::Committing { .. } | ProgressEvent::Done { .. }
+        ) {
+            self.finish_byte_progress();
+        }
+        let fields = serde_json::to_value(event).unwrap_or_default();
+        self.info_event(event.name(), fields, &message);
+    }
+}
+
+/// Passes reads through, reporting the number of bytes read so far to a callback every
+/// [`REPORT_INTERVAL`] and once more at the end of the stream.
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    read: u64,
+    reported: Instant,
+    /// The end of the stream has been reported
+    ended: bool,
+    report: &'a mut dyn FnMut(u64),
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub(crate) fn new(inner: R, report: &'a mut dyn FnMut(u64)) -> Self {
+        Self {
+            inner,
+            read: 0,
+            reported: Instant::now(),
+            ended: false,
+            report,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        let end = read == 0 && !buf.is_empty() && !self.ended;
+        if end || (read > 0 && self.reported.elapsed() >= REPORT_INTERVAL) {
+            self.ended |= end;
+            (self.report)(self.read);
+            self.reported = Instant::now();
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_fields() {
+        let event = ProgressEvent::Committing {
+            index: None,
+            total: 3,
+        };
+        assert_eq!(event.name(), "committing");
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({ "index": null, "total": 3 })
+        );
+    }
+
+    #[test]
+    fn test_progress_reader_reports_the_total_at_the_end() {
+        let data = vec![7u8; 100_000];
+        let mut reports = Vec::new();
+        let mut report = |read| reports.push(read);
+        let mut reader = ProgressReader::new(data.as_slice(), &mut report);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(reader.read(&mut [0; 16]).unwrap(), 0);
+        assert_eq!(out, data);
+        // The end is reported once
+        assert_eq!(reports.last(), Some(&100_000));
+        assert_eq!(reports.iter().filter(|&&read| read == 100_000).count(), 1);
+        assert!(reports.is_sorted());
+    }
+}
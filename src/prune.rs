@@ -0,0 +1,218 @@
+//! `oci2git prune`: delete old image branches and reclaim their space.
+//!
+//! A repository that mirrors frequently updated tags gains a branch for every new digest
+//! and grows without bounds. [`prune`] groups the image branches by image name (without
+//! the tag) and platform, as [`crate::list`] reports them, and deletes the branches that
+//! match **every** given criterion:
+//! - [`PruneOptions::keep_last`]: not among the N most recently converted of their group,
+//! - [`PruneOptions::older_than`]: converted longer ago than that.
+//!
+//! Afterwards the `README.md` on the `main` branch is regenerated, `HEAD` moves to `main`
+//! if it was on a deleted branch, and [`GitRepo::repack`] packs what is still reachable
+//! and deletes the rest, so the space is actually freed. Layer commits shared with a kept
+//! branch stay, as do tags (`--tag`) on deleted branches, which keep their commits alive.
+//! Branches without image metadata (interrupted conversions) are never deleted.
+
+use crate::git::{GitRepo, RepackStats};
+use crate::list::{list, ListedImage};
+use crate::repo_readme::{self, README_BRANCH};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+
+/// Conversion time and branch of an image.
+type Conversion<'a> = (DateTime<Utc>, &'a str);
+
+/// Which branches [`prune`] deletes; see the module documentation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneOptions {
+    /// Keep the N most recently converted branches of every image and platform.
+    pub keep_last: Option<usize>,
+    /// Only delete branches converted longer ago than this.
+    pub older_than: Option<Duration>,
+    /// Report what would be deleted without changing anything.
+    pub dry_run: bool,
+}
+
+/// What [`prune`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Deleted branches (to be deleted with [`PruneOptions::dry_run`]).
+    pub deleted: Vec<String>,
+    /// Image branches left in place.
+    pub kept: Vec<String>,
+    /// `None` if nothing was deleted, or with [`PruneOptions::dry_run`].
+    pub repack: Option<RepackStats>,
+}
+
+/// Parses an age like `90d`: a number followed by `s`, `m`, `h`, `d` or `w`.
+///
+/// # Errors
+/// - Missing or unknown unit, or not a number.
+pub fn parse_age(age: &str) -> Result<Duration> {
+    let age = age.trim();
+    let Some(unit) = age.chars().last().filter(char::is_ascii_alphabetic) else {
+        bail!("Age '{age}' has no unit; use s, m, h, d or w, e.g. 90d");
+    };
+    let value: i64 = age[..age.len() - 1]
+        .parse()
+        .with_context(|| format!("Invalid age '{age}'"))?;
+    Ok(match unit {
+        's' => Duration::seconds(value),
+        'm' => Duration::minutes(value),
+        'h' => Duration::hours(value),
+        'd' => Duration::days(value),
+        'w' => Duration::weeks(value),
+        _ => bail!("Unknown unit '{unit}' in age '{age}'; use s, m, h, d or w"),
+    })
+}
+
+/// Branches of `images` that `options` delete at time `now`.
+///
+/// # Errors
+/// - Neither criterion is set, or a conversion time can't be parsed.
+pub fn select(
+    images: &[ListedImage],
+    options: &PruneOptions,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>> {
+    if options.keep_last.is_none() && options.older_than.is_none() {
+        bail!("Nothing to prune by; give --keep-last and/or --older-than");
+    }
+
+    // (image, platform) -> (conversion time, branch)
+    let mut groups: BTreeMap<(&str, &str), Vec<Conversion>> = BTreeMap::new();
+    for image in images {
+        let converted_at = DateTime::parse_from_rfc3339(&image.converted_at)
+            .with_context(|| format!("Invalid conversion time of branch '{}'", image.branch))?
+            .with_timezone(&Utc);
+        groups
+            .entry((&image.image, &image.platform))
+            .or_default()
+            .push((converted_at, &image.branch));
+    }
+
+    let mut deleted = Vec::new();
+    for mut branches in groups.into_values() {
+        // Newest first
+        branches.sort_by(|a, b| b.cmp(a));
+        for (position, (converted_at, branch)) in branches.into_iter().enumerate() {
+            let beyond_last = options.keep_last.is_none_or(|keep| position >= keep);
+            let too_old = options
+                .older_than
+                .is_none_or(|age| converted_at < now - age);
+            if beyond_last && too_old {
+                deleted.push(branch.to_string());
+            }
+        }
+    }
+    deleted.sort();
+    Ok(deleted)
+}
+
+/// Deletes the branches [`select`] picks at time `now`, then cleans up; see the module
+/// documentation.
+///
+/// # Errors
+/// - See [`select`]; listing, deleting, `README.md` or repacking failures.
+pub fn prune(repo: &GitRepo, options: &PruneOptions, now: DateTime<Utc>) -> Result<PruneReport> {
+    let images = list(repo)?;
+    let deleted = select(&images, options, now)?;
+    let kept = images
+        .into_iter()
+        .map(|image| image.branch)
+        .filter(|branch| !deleted.contains(branch))
+        .collect();
+    if options.dry_run || deleted.is_empty() {
+        return Ok(PruneReport {
+            deleted,
+            kept,
+            repack: None,
+        });
+    }
+
+    let head_branch = repo
+        .repo
+        .head()
+        .ok()
+        .and_then(|head| head.shorthand().map(str::to_string));
+    for branch in &deleted {
+        repo.delete_branch(branch)?;
+    }
+    repo_readme::update(repo)?;
+    if head_branch.is_some_and(|head| deleted.contains(&head)) {
+        repo.repo
+            .set_head(&format!("refs/heads/{README_BRANCH}"))
+            .context("Failed to move HEAD off the deleted branches")?;
+        repo.discard_changes()?;
+    }
+
+    Ok(PruneReport {
+        deleted,
+        kept,
+        repack: Some(repo.repack()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(branch: &str, image: &str, days_ago: i64) -> ListedImage {
+        ListedImage {
+            branch: branch.to_string(),
+            image: image.to_string(),
+            tag: Some("latest".to_string()),
+            digest: String::new(),
+            platform: "linux/amd64".to_string(),
+            layers: 1,
+            converted_at: (now() - Duration::days(days_ago)).to_rfc3339(),
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-06-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90d").unwrap(), Duration::days(90));
+        assert_eq!(parse_age("2w").unwrap(), Duration::days(14));
+        assert_eq!(parse_age("36h").unwrap(), Duration::hours(36));
+        assert!(parse_age("90").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3y").is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let images = [
+            image("nginx-1", "nginx", 200),
+            image("nginx-2", "nginx", 100),
+            image("nginx-3", "nginx", 10),
+            image("redis-1", "redis", 300),
+        ];
+        let options = |keep_last, older_than: Option<i64>| PruneOptions {
+            keep_last,
+            older_than: older_than.map(Duration::days),
+            dry_run: false,
+        };
+
+        assert_eq!(
+            select(&images, &options(Some(1), None), now()).unwrap(),
+            vec!["nginx-1", "nginx-2"]
+        );
+        assert_eq!(
+            select(&images, &options(None, Some(90)), now()).unwrap(),
+            vec!["nginx-1", "nginx-2", "redis-1"]
+        );
+        // Both criteria: the last image of a group stays however old it is
+        assert_eq!(
+            select(&images, &options(Some(1), Some(150)), now()).unwrap(),
+            vec!["nginx-1"]
+        );
+        assert!(select(&images, &options(None, None), now()).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+//! `oci2git query`: the conversion data of a repository as one JSON document.
+//!
+//! [`collect`] gathers, per image branch, the image metadata (`Image.json` or `Image.md`),
+//! every layer commit with its recorded history entry and change statistics computed
+//! from the diff of `rootfs/` against the previous commit, and the special files left at
+//! the tip:
+//!
+//! ```text
+//! {"images": [{
+//!   "branch": "nginx#latest#linux-amd64#…",
+//!   "metadata": {"basic_info": {…}, "container_config": {…}, "layer_digests": […]},
+//!   "layers": [{"index": 1, "commit": "…", "digest": "sha256:…", "command": "…",
+//!               "created": "2024-03-01T00:00:00+00:00", "empty": false,
+//!               "files_added": 120, "files_modified": 0, "files_deleted": 0,
+//!               "bytes_added": 5242880}, …],
+//!   "special_files": [{"path": "/dev/null", "type": "char", …}]
+//! }]}
+//! ```
+//!
+//! [`jq`] filters that document through the `jq` binary, so questions like "layers adding
+//! more than 50MB since March" are one expression away:
+//!
+//! ```text
+//! oci2git query ./repo --jq '.images[].layers[] | select(.bytes_added > 50e6 and .created >= "2024-03")'
+//! ```
+
+use crate::convert_options::{DEFAULT_ROOTFS_DIR, ROOTFS_DIR_PATH};
+use crate::git::GitRepo;
+use crate::image_metadata::ImageMetadata;
+use crate::layer_stats::LayerStats;
+use crate::repo_readme::README_BRANCH;
+use crate::special_files::{SpecialFile, SpecialFiles, SPECIAL_FILES_PATH};
+use crate::trailers;
+use anyhow::{anyhow, bail, Context, Result};
+use git2::{ObjectType, Repository, Tree};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Everything [`collect`] found in a repository.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryDocument {
+    pub images: Vec<ImageRecord>,
+}
+
+/// One converted image (branch).
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageRecord {
+    pub branch: String,
+    /// Metadata at the branch tip, `None` if it has none
+    pub metadata: Option<ImageMetadata>,
+    pub layers: Vec<LayerRecord>,
+    pub special_files: Vec<SpecialFile>,
+}
+
+/// A layer commit with the changes it made to `rootfs/`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LayerRecord {
+    /// 1-based layer index
+    pub index: usize,
+    pub commit: String,
+    pub digest: String,
+    pub command: String,
+    pub created: String,
+    pub empty: bool,
+    pub files_added: usize,
+    pub files_modified: usize,
+    pub files_deleted: usize,
+    /// Size of the added and modified files
+    pub bytes_added: u64,
+}
+
+/// Collects the conversion data of `branch`, or of every image branch.
+///
+/// # Errors
+/// - `branch` doesn't exist, unparsable metadata or sidecars, or Git failures.
+pub fn collect(repo: &GitRepo, branch: Option<&str>) -> Result<QueryDocument> {
+    let branches = image_branches(repo, branch)?;
+
+    let mut images = Vec::new();
+    for branch in branches {
+        images.push(
+            collect_branch(repo, &branch)
+                .with_context(|| format!("Failed to collect branch '{branch}'"))?,
+        );
+    }
+    Ok(QueryDocument { images })
+}
+
+fn collect_branch(repo: &GitRepo, branch: &str) -> Result<ImageRecord> {
+    let commits = repo.get_branch_commits(branch)?;
+    let tip = commits.last().copied();
+    let metadata = match tip {
+        Some(tip) => ImageMetadata::read_from_commit(repo, tip)?,
+        None => None,
+    };
+    let special_files = match tip.map(|tip| repo.read_file_from_commit(tip, SPECIAL_FILES_PATH)) {
+        Some(Ok(content)) => SpecialFiles::parse(&content)?
+            .entries
+            .into_values()
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let mut layers = Vec::new();
+    let mut previous = None;
+    for oid in commits {
+        let commit = repo.repo.find_commit(oid)?;
+        let rootfs = rootfs_tree(&repo.repo, &commit.tree()?)?;
+        if let Some(layer) = trailers::parse(commit.message().unwrap_or_default())? {
+            let mut record = LayerRecord {
+                index: layer.index,
+                commit: oid.to_string(),
+                digest: layer.digest,
+                created: layer.created.unwrap_or_default(),
+                empty: layer.empty.unwrap_or_default(),
+                ..LayerRecord::default()
+            };
+            if let Some(recorded) = metadata.as_ref().and_then(|metadata| {
+                layer
+                    .index
+                    .checked_sub(1)
+                    .and_then(|index| metadata.layer_digests.get(index))
+            }) {
+                record.command = recorded.command.clone();
+                record.created = recorded.created.clone();
+                record.empty = recorded.is_empty;
+            }
+            count_changes(&repo.repo, previous.as_ref(), rootfs.as_ref(), &mut record)?;
+            layers.push(record);
+        }
+        previous = rootfs;
+    }
+
+    Ok(ImageRecord {
+        branch: branch.to_string(),
+        metadata,
+        layers,
+        special_files,
+    })
+}
+
+/// `[branch]` if it exists, or every image branch (all but [`README_BRANCH`]), sorted.
+pub(crate) fn image_branches(repo: &GitRepo, branch: Option<&str>) -> Result<Vec<String>> {
+    match branch {
+        Some(branch) => {
+            if !repo.branch_exists(branch) {
+                bail!("Branch '{branch}' doesn't exist");
+            }
+            Ok(vec![branch.to_string()])
+        }
+        None => {
+            let mut branches = repo.get_all_branches()?;
+            branches.retain(|branch| branch != README_BRANCH);
+            branches.sort();
+            Ok(branches)
+        }
+    }
+}
+
+/// Name of the rootfs directory of a commit tree: the one named in [`ROOTFS_DIR_PATH`],
+/// else [`DEFAULT_ROOTFS_DIR`]
+pub(crate) fn rootfs_dir(repo: &Repository, tree: &Tree) -> String {
+    tree.get_path(Path::new(ROOTFS_DIR_PATH))
+        .ok()
+        .and_then(|entry| repo.find_blob(entry.id()).ok())
+        .and_then(|blob| {
+            std::str::from_utf8(blob.content())
+                .ok()
+                .map(|name| name.trim().to_string())
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_ROOTFS_DIR.to_string())
+}
+
+/// The rootfs directory (see [`rootfs_dir`]) of a commit tree, if present
+pub(crate) fn rootfs_tree<'repo>(
+    repo: &'repo Repository,
+    tree: &Tree,
+) -> Result<Option<Tree<'repo>>> {
+    match tree.get_path(Path::new(&rootfs_dir(repo, tree))) {
+        Ok(entry) if entry.kind() == Some(ObjectType::Tree) => {
+            Ok(Some(repo.find_tree(entry.id())?))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn count_changes(
+    repo: &Repository,
+    old: Option<&Tree>,
+    new: Option<&Tree>,
+    record: &mut LayerRecord,
+) -> Result<()> {
+    let stats = LayerStats::between(repo, old, new)?;
+    record.files_added = stats.files_added;
+    record.files_modified = stats.files_modified;
+    record.files_deleted = stats.files_deleted;
+    record.bytes_added = stats.bytes_added;
+    Ok(())
+}
+
+/// Runs `jq` with `filter` (and `args`, e.g. `-r`) over `document`; returns its output.
+///
+/// # Errors
+/// - `jq` isn't installed, or rejects the filter.
+pub fn jq(document: &impl Serialize, filter: &str, args: &[&str]) -> Result<String> {
+    let input = serde_json::to_vec(document)?;
+    let mut child = Command::new("jq")
+        .args(args)
+        .arg(filter)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to execute jq; is it installed?")?;
+
+    // Write from a thread, jq may start printing before it has read everything
+    let mut stdin = child.stdin.take().context("Failed to open jq stdin")?;
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().context("Failed to run jq")?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("jq failed: {}", error.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trailers::LayerTrailers;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_layer_change_statistics() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("app")).unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::write(rootfs.join("a"), "12345").unwrap();
+        fs::write(rootfs.join("b"), "1").unwrap();
+        repo.commit_layer("ADD", &LayerTrailers::new(1, "sha256:1"))
+            .unwrap();
+        fs::write(rootfs.join("a"), "123").unwrap();
+        fs::remove_file(rootfs.join("b")).unwrap();
+        repo.commit_layer("RUN", &LayerTrailers::new(2, "sha256:2"))
+            .unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "not a layer").unwrap();
+        repo.commit_all_changes("Metadata").unwrap();
+
+        let document = collect(&repo, None).unwrap();
+        assert_eq!(document.images.len(), 1);
+        let layers = &document.images[0].layers;
+        assert_eq!(layers.len(), 2);
+        assert_eq!(
+            (
+                layers[0].files_added,
+                layers[0].bytes_added,
+                layers[0].digest.as_str()
+            ),
+            (2, 6, "sha256:1")
+        );
+        assert_eq!(
+            (
+                layers[1].files_modified,
+                layers[1].files_deleted,
+                layers[1].bytes_added
+            ),
+            (1, 1, 3)
+        );
+        assert!(collect(&repo, Some("missing")).is_err());
+
+        if Command::new("jq").arg("--version").output().is_err() {
+            println!("Skipping jq part: jq is not installed");
+            return;
+        }
+        let selected = jq(
+            &document,
+            ".images[].layers[] | select(.bytes_added > 4) | .digest",
+            &["-r"],
+        )
+        .unwrap();
+        assert_eq!(selected, "sha256:1\n");
+        assert!(jq(&document, ".images[", &[]).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! Top-level `README.md` listing every image converted into a repository.
+//!
+//! Each image lives on its own branch, so someone landing on a shared conversion repo
+//! would otherwise see nothing but branch names. After every conversion [`update`]:
+//! - reads `Image.md` from the tip of each branch that has one,
+//! - renders a table (image, tags, platform, digest, created date, link to the branch),
+//! - commits it as `README.md` on [`README_BRANCH`] — only when the content changed, and
+//!   without touching `HEAD` or the worktree.
+//!
+//! The README is generated solely from the repository contents, so regenerating it is
+//! idempotent. [`branch_description`] produces the matching `git branch` description.
+//!
+//! With [`crate::processor::ImageProcessor::with_emit_readme`] every image branch gets a
+//! `README.md` of its own as well ([`render_branch`]), so a forge shows the image name,
+//! digest, platform, layers and entrypoint on the branch page instead of the bare
+//! `rootfs/` tree.
+
+use crate::disk_space::format_bytes;
+use crate::git::GitRepo;
+use crate::image_metadata::{BasicInfo, ImageMetadata};
+use crate::layer_stats::LayerStatsReport;
+use crate::sources::extract_short_digest;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Branch holding the generated `README.md`.
+pub const README_BRANCH: &str = "main";
+/// README of an image branch, relative to the repository root.
+pub const BRANCH_README: &str = "README.md";
+/// How many of the layers adding the most bytes a branch README lists.
+pub const LARGEST_LAYERS: usize = 5;
+
+/// One converted image, as recorded in the `Image.md` of its branch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageEntry {
+    pub branch: String,
+    pub info: BasicInfo,
+}
+
+/// Description for the branch of a converted image, e.g. `nginx:latest (linux/amd64) sha256:…`.
+pub fn branch_description(info: &BasicInfo) -> String {
+    format!(
+        "{} ({}/{}) {}",
+        info.name, info.os, info.architecture, info.id
+    )
+}
+
+/// Collect the images of all branches whose tip has a complete `Image.md`, sorted by branch.
+///
+/// # Errors
+/// - Branch enumeration failures.
+pub fn collect_images(repo: &GitRepo) -> Result<Vec<ImageEntry>> {
+    let mut images = Vec::new();
+
+    for branch in repo.get_all_branches()? {
+        if branch == README_BRANCH {
+            continue;
+        }
+        let Some(&tip) = repo.get_branch_commits(&branch)?.last() else {
+            continue;
+        };
+        let info = ImageMetadata::read_from_commit(repo, tip)
+            .ok()
+            .flatten()
+            .and_then(|metadata| metadata.basic_info);
+        if let Some(info) = info {
+            images.push(ImageEntry { branch, info });
+        }
+    }
+
+    images.sort_by(|a, b| a.branch.cmp(&b.branch));
+    Ok(images)
+}
+
+/// Render the README for `images`.
+pub fn render(images: &[ImageEntry]) -> String {
+    let mut markdown = String::from("# Converted Images\n\n");
+    markdown.push_str(
+        "Generated by [oci2git](https://github.com/Virviil/oci2git). Each image is stored on \
+         its own branch, with `Image.md` (metadata) and `rootfs/` (filesystem) at the root; \
+         every commit is one layer.\n\n",
+    );
+
+    if images.is_empty() {
+        markdown.push_str("No images have been converted yet.\n");
+        return markdown;
+    }
+
+    markdown.push_str("| Image | Tags | Platform | Digest | Created | Branch |\n");
+    markdown.push_str("|-------|------|----------|--------|---------|--------|\n");
+    for ImageEntry { branch, info } in images {
+        let tags = if info.tags.is_empty() {
+            "-".to_string()
+        } else {
+            info.tags.join(", ")
+        };
+        let digest = extract_short_digest(&info.id).unwrap_or_else(|| info.id.clone());
+        markdown.push_str(&format!(
+            "| {} | {} | {}/{} | `{}` | {} | [`{}`](../../tree/{}) |\n",
+            escape_cell(&info.name),
+            escape_cell(&tags),
+            info.os,
+            info.architecture,
+            digest,
+            info.created,
+            escape_cell(branch),
+            branch.replace('%', "%25").replace('#', "%23"),
+        ));
+    }
+
+    markdown
+}
+
+/// Regenerate `README.md` on [`README_BRANCH`]. Returns `true` if it changed.
+///
+/// # Errors
+/// - Branch enumeration or commit failures.
+pub fn update(repo: &GitRepo) -> Result<bool> {
+    let readme = render(&collect_images(repo)?);
+    repo.commit_file_to_branch(README_BRANCH, "README.md", &readme, "📖 - Update README")
+}
+
+/// Render the README of the branch of the image described by `metadata`, whose layer
+/// commits have the statistics `layers` and whose filesystem is in `rootfs_dir`.
+pub fn render_branch(
+    metadata: &ImageMetadata,
+    layers: &LayerStatsReport,
+    rootfs_dir: &str,
+) -> String {
+    let name = metadata
+        .basic_info
+        .as_ref()
+        .map_or("Converted image", |info| info.name.as_str());
+    let mut markdown = format!("# {name}\n\n");
+    markdown.push_str(&format!(
+        "Converted by [oci2git](https://github.com/Virviil/oci2git): `{rootfs_dir}/` holds the \
+         image filesystem and every commit is one layer. [`Image.md`](Image.md) has the full \
+         metadata and history.\n\n"
+    ));
+
+    markdown.push_str("| | |\n|---|---|\n");
+    if let Some(info) = &metadata.basic_info {
+        if !info.tags.is_empty() {
+            markdown.push_str(&format!(
+                "| Tags | {} |\n",
+                escape_cell(&info.tags.join(", "))
+            ));
+        }
+        markdown.push_str(&format!("| Digest | `{}` |\n", info.id));
+        markdown.push_str(&format!(
+            "| Platform | {}/{} |\n",
+            info.os, info.architecture
+        ));
+        markdown.push_str(&format!("| Created | {} |\n", info.created));
+    }
+    let empty = metadata
+        .layer_digests
+        .iter()
+        .filter(|layer| layer.is_empty)
+        .count();
+    markdown.push_str(&format!(
+        "| Layers | {} ({empty} without file changes) |\n",
+        metadata.layer_digests.len()
+    ));
+    if let Some(config) = &metadata.container_config {
+        for (label, value) in [
+            ("Entrypoint", &config.entrypoint),
+            ("Command", &config.command),
+        ] {
+            if let Some(value) = value {
+                markdown.push_str(&format!("| {label} | `{}` |\n", escape_cell(value)));
+            }
+        }
+        if !config.exposed_ports.is_empty() {
+            markdown.push_str(&format!(
+                "| Exposed ports | {} |\n",
+                config.exposed_ports.join(", ")
+            ));
+        }
+    }
+
+    let mut largest: Vec<_> = layers
+        .layers
+        .iter()
+        .filter(|layer| layer.bytes_added > 0)
+        .collect();
+    largest.sort_by(|a, b| {
+        b.bytes_added
+            .cmp(&a.bytes_added)
+            .then(a.index.cmp(&b.index))
+    });
+    largest.truncate(LARGEST_LAYERS);
+    if !largest.is_empty() {
+        markdown.push_str("\n## Largest Layers\n\n");
+        markdown.push_str("| Layer | Size | Command |\n");
+        markdown.push_str("|-------|------|---------|\n");
+        for layer in largest {
+            markdown.push_str(&format!(
+                "| {} | {} | `{}` |\n",
+                layer.index,
+                format_bytes(layer.bytes_added),
+                escape_cell(&layer.command)
+            ));
+        }
+    }
+    markdown
+}
+
+/// Write [`BRANCH_README`] into `dir`, see [`render_branch`].
+///
+/// # Errors
+/// - Write failures.
+pub fn write_branch(
+    dir: &Path,
+    metadata: &ImageMetadata,
+    layers: &LayerStatsReport,
+    rootfs_dir: &str,
+) -> Result<()> {
+    let path = dir.join(BRANCH_README);
+    fs::write(&path, render_branch(metadata, layers, rootfs_dir))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub(crate) fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_metadata::LayerDigest;
+    use crate::layer_stats::LayerStats;
+    use tempfile::tempdir;
+
+    fn info(name: &str, arch: &str) -> BasicInfo {
+        BasicInfo {
+            name: name.to_string(),
+            id: "sha256:1234567890abcdef".to_string(),
+            tags: vec![name.to_string()],
+            created: "2024-01-01T00:00:00Z".to_string(),
+            architecture: arch.to_string(),
+            os: "linux".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_update_readme() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), None).unwrap();
+
+        for (branch, arch) in [
+            ("app#latest#linux-arm64#1234567890ab", "arm64"),
+            ("app#latest#linux-amd64#1234567890ab", "amd64"),
+        ] {
+            repo.create_branch(branch, None).unwrap();
+            ImageMetadata::new(Some(info("app:latest", arch)), None)
+                .save_markdown(&temp_dir.path().join("Image.md"))
+                .unwrap();
+            repo.commit_all_changes("🛠️ - Metadata").unwrap();
+        }
+        // Branches without Image.md are not images
+        repo.create_branch("scratch", None).unwrap();
+        fs::write(temp_dir.path().join("Image.md"), "not metadata").unwrap();
+        repo.commit_all_changes("scratch").unwrap();
+
+        let images = collect_images(&repo).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].branch, "app#latest#linux-amd64#1234567890ab");
+
+        assert!(update(&repo).unwrap());
+        assert!(!update(&repo).unwrap());
+
+        let tip = *repo
+            .get_branch_commits(README_BRANCH)
+            .unwrap()
+            .last()
+            .unwrap();
+        let readme = repo.read_file_from_commit(tip, "README.md").unwrap();
+        assert!(readme.contains(
+            "| app:latest | app:latest | linux/arm64 | `1234567890ab` | 2024-01-01T00:00:00Z | \
+             [`app#latest#linux-arm64#1234567890ab`](../../tree/app%23latest%23linux-arm64%231234567890ab) |"
+        ));
+        assert!(readme.find("linux/amd64").unwrap() < readme.find("linux/arm64").unwrap());
+    }
+
+    #[test]
+    fn test_render_branch() {
+        let mut metadata = ImageMetadata::new(Some(info("app:latest", "amd64")), None);
+        for (i, is_empty) in [false, true, false].into_iter().enumerate() {
+            metadata.layer_digests.push(LayerDigest {
+                digest: format!("sha256:{i}"),
+                command: String::new(),
+                created: String::new(),
+                is_empty,
+                comment: None,
+                verified: None,
+            });
+        }
+        let stats = |index, command: &str, bytes_added| LayerStats {
+            index,
+            command: command.to_string(),
+            bytes_added,
+            ..Default::default()
+        };
+        let layers = LayerStatsReport {
+            layers: vec![
+                stats(1, "ADD rootfs.tar /", 2048),
+                stats(2, "ENV A=1", 0),
+                stats(3, "RUN a | b", 4096),
+            ],
+        };
+
+        let readme = render_branch(&metadata, &layers, "fs");
+        assert!(readme.starts_with("# app:latest\n"));
+        assert!(readme.contains("`fs/` holds the image filesystem"));
+        assert!(readme.contains("| Digest | `sha256:1234567890abcdef` |"));
+        assert!(readme.contains("| Platform | linux/amd64 |"));
+        assert!(readme.contains("| Layers | 3 (1 without file changes) |"));
+        assert!(readme
+            .contains("| 3 | 4.0 KiB | `RUN a \\| b` |\n| 1 | 2.0 KiB | `ADD rootfs.tar /` |\n"));
+        assert!(!readme.contains("ENV A=1"));
+    }
+
+    #[test]
+    fn test_branch_description() {
+        assert_eq!(
+            branch_description(&info("nginx:1.25", "amd64")),
+            "nginx:1.25 (linux/amd64) sha256:1234567890abcdef"
+        );
+    }
+}
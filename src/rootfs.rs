@@ -0,0 +1,59 @@
+//! Standalone rootfs materialization — no Git involved.
+//!
+//! [`materialize_rootfs`] unpacks an image into a plain directory, replaying every layer
+//! with overlay/whiteout semantics using the same hardened extractor as the conversion
+//! pipeline. Useful when only a correct rootfs is needed.
+//!
+//! - A path to an existing file is read as an image tarball ([`TarSource`]).
+//! - Anything else is treated as an image reference and exported via [`DockerSource`].
+//!
+//! For other sources use [`crate::processor::ImageProcessor::materialize_rootfs`].
+
+use crate::layer_apply::ConflictStrategy;
+use crate::notifier::Notifier;
+use crate::processor::ImageProcessor;
+use crate::sources::{DockerSource, TarSource};
+use anyhow::Result;
+use std::path::Path;
+
+/// Options for [`materialize_rootfs`].
+#[derive(Debug, Clone, Default)]
+pub struct RootfsOptions {
+    /// Maximum number of bytes of temporary data, `None` for no limit.
+    pub max_temp_bytes: Option<u64>,
+    /// Verbosity passed to [`Notifier::new`] (0 = quiet).
+    pub verbosity: u8,
+    /// How entry type changes between layers are applied.
+    pub conflict_strategy: ConflictStrategy,
+}
+
+/// Unpack all layers of `image_or_tarball` into `dir`.
+///
+/// # Errors
+/// - Image fetch/extraction failures (missing image, malformed tarball, I/O).
+/// - The temporary space limit in `opts` would be exceeded.
+///
+/// ### Examples
+/// ```no_run
+/// use std::path::Path;
+/// use oci2git::{materialize_rootfs, RootfsOptions};
+///
+/// materialize_rootfs("alpine.tar", Path::new("./alpine-rootfs"), &RootfsOptions::default())?;
+/// # anyhow::Ok(())
+/// ```
+pub fn materialize_rootfs(image_or_tarball: &str, dir: &Path, opts: &RootfsOptions) -> Result<()> {
+    let notifier = Notifier::new(opts.verbosity);
+
+    if Path::new(image_or_tarball).is_file() {
+        ImageProcessor::new(TarSource::new()?, notifier)
+            .with_max_temp_bytes(opts.max_temp_bytes)
+            .with_conflict_strategy(opts.conflict_strategy)
+            .materialize_rootfs(image_or_tarball, dir)?;
+    } else {
+        ImageProcessor::new(DockerSource::new()?, notifier)
+            .with_max_temp_bytes(opts.max_temp_bytes)
+            .with_conflict_strategy(opts.conflict_strategy)
+            .materialize_rootfs(image_or_tarball, dir)?;
+    }
+    Ok(())
+}
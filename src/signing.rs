@@ -0,0 +1,182 @@
+//! Commit signing with GPG or SSH keys, the way `git commit -S` does it.
+//!
+//! A [`CommitSigner`] takes the raw commit object (as produced by
+//! [`git2::Repository::commit_create_buffer`]) and returns the detached armored signature
+//! Git stores in the `gpgsig` header. Signing shells out to the same programs Git uses:
+//! - [`SigningFormat::Openpgp`]: `gpg --status-fd=2 -bsau <key>` (`gpg -bsa` with the
+//!   default key when none is configured),
+//! - [`SigningFormat::Ssh`]: `ssh-keygen -Y sign -n git -f <key>`, where the key is a
+//!   private key file, or a public key file whose private half is in `ssh-agent`.
+//!
+//! [`CommitSigner::from_git_config`] fills in whatever isn't given explicitly from the
+//! user's Git configuration (`gpg.format`, `user.signingkey`, `gpg.program`,
+//! `gpg.ssh.program`), so a machine that already signs its own commits needs no extra
+//! options.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Kind of key a [`CommitSigner`] signs with, named after Git's `gpg.format` values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SigningFormat {
+    /// OpenPGP key, signed by `gpg`.
+    #[default]
+    Openpgp,
+    /// SSH key, signed by `ssh-keygen -Y sign`.
+    Ssh,
+}
+
+impl std::str::FromStr for SigningFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "openpgp" => Ok(Self::Openpgp),
+            "ssh" => Ok(Self::Ssh),
+            other => bail!("Unsupported signing format '{other}' (expected openpgp or ssh)"),
+        }
+    }
+}
+
+/// Signs commit objects with an external program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitSigner {
+    pub format: SigningFormat,
+    /// Key ID (OpenPGP) or key file (SSH); `None` uses the default `gpg` key
+    pub key: Option<String>,
+    /// Program to run instead of `gpg` / `ssh-keygen`
+    pub program: Option<String>,
+}
+
+impl CommitSigner {
+    /// A signer for `format` and `key`, using the default program.
+    pub fn new(format: SigningFormat, key: Option<String>) -> Self {
+        Self {
+            format,
+            key,
+            program: None,
+        }
+    }
+
+    /// A signer configured like `git commit -S` would be: `format` and `key` when given,
+    /// otherwise `gpg.format` and `user.signingkey` from the global Git configuration.
+    ///
+    /// # Errors
+    /// - Unreadable configuration, an unknown `gpg.format`, or SSH signing without a key.
+    pub fn from_git_config(format: Option<SigningFormat>, key: Option<String>) -> Result<Self> {
+        let config = git2::Config::open_default().context("Failed to read git config")?;
+        let format = match format {
+            Some(format) => format,
+            None => match config.get_string("gpg.format") {
+                Ok(format) => format.parse()?,
+                Err(_) => SigningFormat::default(),
+            },
+        };
+        let key = key.or_else(|| config.get_string("user.signingkey").ok());
+        let program_key = match format {
+            SigningFormat::Openpgp => "gpg.program",
+            SigningFormat::Ssh => "gpg.ssh.program",
+        };
+        let signer = Self {
+            format,
+            key,
+            program: config.get_string(program_key).ok(),
+        };
+        if signer.format == SigningFormat::Ssh && signer.key.is_none() {
+            bail!("SSH commit signing needs a key: set user.signingkey or pass --signing-key");
+        }
+        Ok(signer)
+    }
+
+    /// Detached armored signature of `content`.
+    ///
+    /// # Errors
+    /// - The signing program is missing or fails (unknown key, locked agent, …).
+    pub fn sign(&self, content: &str) -> Result<String> {
+        match self.format {
+            SigningFormat::Openpgp => self.sign_openpgp(content),
+            SigningFormat::Ssh => self.sign_ssh(content),
+        }
+    }
+
+    fn sign_openpgp(&self, content: &str) -> Result<String> {
+        let program = self.program.as_deref().unwrap_or("gpg");
+        let mut command = Command::new(program);
+        command.arg("--status-fd=2");
+        match &self.key {
+            Some(key) => command.args(["-bsau", key]),
+            None => command.arg("-bsa"),
+        };
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to execute {program}; is it installed?"))?;
+
+        let mut stdin = child.stdin.take().context("Failed to open gpg stdin")?;
+        let input = content.as_bytes().to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child
+            .wait_with_output()
+            .with_context(|| format!("Failed to run {program}"))?;
+        let _ = writer.join();
+
+        let status = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success()
+            || !status
+                .lines()
+                .any(|line| line.starts_with("[GNUPG:] SIG_CREATED "))
+        {
+            return Err(anyhow!(
+                "{program} failed to sign the commit: {}",
+                status.trim()
+            ));
+        }
+        String::from_utf8(output.stdout).context("gpg signature is not valid UTF-8")
+    }
+
+    fn sign_ssh(&self, content: &str) -> Result<String> {
+        let program = self.program.as_deref().unwrap_or("ssh-keygen");
+        let key = self
+            .key
+            .as_deref()
+            .context("SSH commit signing needs a key file")?;
+
+        // ssh-keygen signs files; `<file>.sig` receives the signature
+        let temp_dir = tempfile::tempdir().context("Failed to create temp dir for signing")?;
+        let buffer = temp_dir.path().join("commit");
+        fs::write(&buffer, content).context("Failed to write commit buffer")?;
+        let output = Command::new(program)
+            .args(["-Y", "sign", "-n", "git", "-f", key])
+            .arg(&buffer)
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to execute {program}; is it installed?"))?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!(
+                "{program} failed to sign the commit: {}",
+                error.trim()
+            ));
+        }
+        fs::read_to_string(buffer.with_extension("sig")).context("Failed to read SSH signature")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signing_format() {
+        assert_eq!("ssh".parse::<SigningFormat>().unwrap(), SigningFormat::Ssh);
+        assert_eq!(
+            "openpgp".parse::<SigningFormat>().unwrap(),
+            SigningFormat::Openpgp
+        );
+        assert!("x509".parse::<SigningFormat>().is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! containerd source: exports images with `ctr`, no Docker or nerdctl required.
+//!
+//! Meant for Kubernetes nodes that only run containerd. Images are looked up in a
+//! containerd namespace (`default` unless [`ContainerdSource::with_namespace`] says
+//! otherwise; Kubernetes uses `k8s.io`) and exported with `ctr images export`, which
+//! writes an OCI archive that also carries a `docker save` style `manifest.json`.
+//! Missing images are pulled with `ctr images pull` first, unless
+//! [`ContainerdSource::with_offline`] or [`ContainerdSource::with_pull`] forbid it.
+//!
+//! `ctr` does not apply Docker Hub defaults, so references are normalized
+//! (`alpine` → `docker.io/library/alpine:latest`) before they are passed on.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::PathBuf;
+use std::process::Command;
+use tempfile::TempDir;
+
+use super::registry::ImageReference;
+use super::{naming, Platform, Source};
+use crate::notifier::Notifier;
+use crate::workdir::Workdir;
+
+/// Namespace used by `ctr` when none is given.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// containerd implementation of the Source trait, driving the `ctr` CLI
+pub struct ContainerdSource {
+    namespace: String,
+    platform: Option<Platform>,
+    workdir: Workdir,
+    offline: bool,
+    pull: bool,
+}
+
+impl ContainerdSource {
+    pub fn new() -> Result<Self> {
+        let output = Command::new("ctr")
+            .arg("--version")
+            .output()
+            .context("Failed to execute ctr command. Is containerd installed?")?;
+
+        if !output.status.success() {
+            return Err(anyhow!("ctr is not available"));
+        }
+
+        Ok(Self {
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            platform: None,
+            workdir: Workdir::default(),
+            offline: false,
+            pull: true,
+        })
+    }
+
+    /// Look up images in containerd namespace `namespace` (e.g. `k8s.io`)
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Pull and export `platform` instead of the host platform
+    pub fn with_platform(mut self, platform: Option<Platform>) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Fail instead of pulling images missing from the namespace
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Fail instead of pulling images missing from the namespace
+    pub fn with_pull(mut self, pull: bool) -> Self {
+        self.pull = pull;
+        self
+    }
+
+    /// `ctr -n <namespace> images <args…>`, with `--platform` where it applies
+    fn images_args(&self, subcommand: &str, args: &[&str]) -> Vec<String> {
+        let mut command = vec![
+            "-n".to_string(),
+            self.namespace.clone(),
+            "images".to_string(),
+            subcommand.to_string(),
+        ];
+        if let Some(platform) = &self.platform {
+            command.extend(["--platform".to_string(), platform.to_string()]);
+        }
+        command.extend(args.iter().map(|arg| arg.to_string()));
+        command
+    }
+
+    fn run_ctr(&self, args: &[String]) -> Result<String> {
+        let output = Command::new("ctr")
+            .args(args)
+            .output()
+            .context(format!("Failed to execute ctr command: {args:?}"))?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!("ctr command failed: {error}"));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn image_exists(&self, reference: &str) -> bool {
+        self.run_ctr(&[
+            "-n".to_string(),
+            self.namespace.clone(),
+            "images".to_string(),
+            "ls".to_string(),
+            "-q".to_string(),
+            format!("name=={reference}"),
+        ])
+        .is_ok_and(|output| !output.trim().is_empty())
+    }
+}
+
+impl Source for ContainerdSource {
+    fn name(&self) -> &str {
+        "containerd"
+    }
+
+    fn get_image_tarball(
+        &self,
+        image_name: &str,
+        notifier: &Notifier,
+    ) -> Result<(PathBuf, Option<TempDir>)> {
+        let reference = ImageReference::parse(image_name)?.to_string();
+
+        if !self.image_exists(&reference) {
+            if self.offline || !self.pull {
+                bail!(
+                    "Image '{reference}' not found in containerd namespace '{}', and {} forbids pulling it",
+                    self.namespace,
+                    if self.offline { "--offline" } else { "--no-pull" }
+                );
+            }
+            notifier.info(&format!(
+                "Image '{reference}' not found in containerd namespace '{}', pulling...",
+                self.namespace
+            ));
+            self.run_ctr(&self.images_args("pull", &[&reference]))
+                .context(format!("Failed to pull image '{reference}'"))?;
+        }
+
+        let temp_dir = self.workdir.tempdir("oci2git-containerd-")?;
+        let tarball_path = temp_dir.path().join("image.tar");
+        let tarball = tarball_path.to_str().unwrap();
+
+        notifier.info(&format!(
+            "Exporting containerd image '{reference}' to tarball..."
+        ));
+        self.run_ctr(&self.images_args("export", &[tarball, &reference]))
+            .context(format!("Failed to export image '{reference}'"))?;
+
+        Ok((tarball_path, Some(temp_dir)))
+    }
+
+    fn branch_name(&self, image_name: &str, os_arch: &str, image_digest: &str) -> String {
+        let base_branch = naming::container_image_to_branch(image_name);
+        naming::combine_branch_with_digest(&base_branch, os_arch, image_digest)
+    }
+
+    fn select_platform(&mut self, platform: Option<Platform>) -> Result<()> {
+        self.platform = platform;
+        Ok(())
+    }
+
+    fn platform(&self) -> Option<&Platform> {
+        self.platform.as_ref()
+    }
+
+    fn set_workdir(&mut self, workdir: Workdir) {
+        self.workdir = workdir;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> ContainerdSource {
+        // Built directly so the tests don't need ctr installed
+        ContainerdSource {
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            platform: None,
+            workdir: Workdir::default(),
+            offline: false,
+            pull: true,
+        }
+    }
+
+    #[test]
+    fn test_images_args() {
+        assert_eq!(
+            source().images_args("pull", &["docker.io/library/alpine:latest"]),
+            [
+                "-n",
+                "default",
+                "images",
+                "pull",
+                "docker.io/library/alpine:latest"
+            ]
+        );
+
+        let source = source()
+            .with_namespace("k8s.io")
+            .with_platform(Some("linux/arm64".parse().unwrap()));
+        assert_eq!(
+            source.images_args("export", &["/tmp/image.tar", "docker.io/library/alpine:3"]),
+            [
+                "-n",
+                "k8s.io",
+                "images",
+                "export",
+                "--platform",
+                "linux/arm64",
+                "/tmp/image.tar",
+                "docker.io/library/alpine:3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_containerd_source_branch_name() {
+        assert_eq!(
+            source().branch_name("alpine", "linux-amd64", "sha256:1234567890abcdef"),
+            "alpine#latest#linux-amd64#1234567890ab"
+        );
+    }
+}
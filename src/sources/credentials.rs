@@ -0,0 +1,344 @@
+//! Registry credentials for private images.
+//!
+//! [`CredentialStore`] answers "which username and password for this registry?" the way
+//! the Docker CLI does, so a `docker login` (or a CI secret) is all a private registry
+//! needs:
+//! 1. credentials given explicitly ([`CredentialStore::with_credentials`], e.g.
+//!    `--username` and `--password-stdin`), used for every registry,
+//! 2. the credential helper configured for the registry in `credHelpers` of the Docker
+//!    config file (`docker-credential-<helper> get`),
+//! 3. the `auths` entry for the registry (base64 `user:password` in `auth`, or
+//!    `username`/`password`/`identitytoken`),
+//! 4. the default helper in `credsStore`.
+//!
+//! The Docker config file is `$DOCKER_CONFIG/config.json`, or `~/.docker/config.json`.
+//! Credentials are only looked up when a registry refuses an anonymous request, so public
+//! images never run a credential helper.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Key of Docker Hub in `auths` and the server URL its credential helpers expect.
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+
+/// Username and password (or identity token) for a registry.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Value of an `Authorization: Basic …` header.
+    pub fn basic_authorization(&self) -> String {
+        format!(
+            "Basic {}",
+            base64_encode(format!("{}:{}", self.username, self.password).as_bytes())
+        )
+    }
+}
+
+/// Never prints the password.
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The parts of Docker's `config.json` that hold credentials.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, AuthEntry>,
+    creds_store: Option<String>,
+    #[serde(default)]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AuthEntry {
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    identitytoken: Option<String>,
+}
+
+/// What a credential helper prints for `get`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperCredentials {
+    username: String,
+    secret: String,
+}
+
+/// Where credentials for a registry come from; see the module documentation.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialStore {
+    explicit: Option<Credentials>,
+    docker_config: Option<PathBuf>,
+}
+
+impl CredentialStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `credentials` for every registry instead of the Docker configuration.
+    pub fn with_credentials(mut self, credentials: Option<Credentials>) -> Self {
+        self.explicit = credentials;
+        self
+    }
+
+    /// Read `path` instead of the default Docker config file.
+    pub fn with_docker_config(mut self, path: Option<PathBuf>) -> Self {
+        self.docker_config = path;
+        self
+    }
+
+    /// `$DOCKER_CONFIG/config.json`, or `~/.docker/config.json`.
+    pub fn default_docker_config() -> Option<PathBuf> {
+        std::env::var_os("DOCKER_CONFIG")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".docker")))
+            .map(|dir| dir.join("config.json"))
+    }
+
+    /// Credentials for `registry` (as in [`super::registry::ImageReference::registry`],
+    /// e.g. `docker.io` or `ghcr.io`), `None` if there are none.
+    ///
+    /// # Errors
+    /// - The Docker config file can't be parsed, or a credential helper fails.
+    pub fn lookup(&self, registry: &str) -> Result<Option<Credentials>> {
+        if let Some(credentials) = &self.explicit {
+            return Ok(Some(credentials.clone()));
+        }
+        let Some(path) = self
+            .docker_config
+            .clone()
+            .or_else(Self::default_docker_config)
+            .filter(|path| path.exists())
+        else {
+            return Ok(None);
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let config: DockerConfig = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        let server = if registry == "docker.io" {
+            DOCKER_HUB_SERVER
+        } else {
+            registry
+        };
+        if let Some(helper) = config
+            .cred_helpers
+            .iter()
+            .find(|(key, _)| normalize_registry(key) == registry)
+            .map(|(_, helper)| helper)
+        {
+            return run_helper(helper, server);
+        }
+        if let Some(entry) = config
+            .auths
+            .iter()
+            .find(|(key, _)| normalize_registry(key) == registry)
+            .map(|(_, entry)| entry)
+        {
+            if let Some(credentials) = entry.credentials()? {
+                return Ok(Some(credentials));
+            }
+        }
+        match &config.creds_store {
+            Some(helper) => run_helper(helper, server),
+            None => Ok(None),
+        }
+    }
+}
+
+impl AuthEntry {
+    fn credentials(&self) -> Result<Option<Credentials>> {
+        if let Some(auth) = self.auth.as_deref().filter(|auth| !auth.is_empty()) {
+            let decoded = String::from_utf8(base64_decode(auth)?)
+                .context("Docker config auth entry is not UTF-8")?;
+            let (username, password) = decoded
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Docker config auth entry is not user:password"))?;
+            return Ok(Some(Credentials::new(username, password)));
+        }
+        if let Some(token) = &self.identitytoken {
+            return Ok(Some(Credentials::new("<token>", token.clone())));
+        }
+        Ok(match (&self.username, &self.password) {
+            (Some(username), Some(password)) => Some(Credentials::new(username, password)),
+            _ => None,
+        })
+    }
+}
+
+/// `https://index.docker.io/v1/` → `docker.io`, `https://ghcr.io` → `ghcr.io`.
+fn normalize_registry(key: &str) -> &str {
+    let host = key
+        .strip_prefix("https://")
+        .or_else(|| key.strip_prefix("http://"))
+        .unwrap_or(key);
+    let host = host.split('/').next().unwrap_or(host);
+    match host {
+        "index.docker.io" | "registry-1.docker.io" => "docker.io",
+        host => host,
+    }
+}
+
+/// Runs `docker-credential-<helper> get` for `server`; `None` if the helper has nothing.
+fn run_helper(helper: &str, server: &str) -> Result<Option<Credentials>> {
+    let program = format!("docker-credential-{helper}");
+    let mut child = Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to execute {program}; is it installed?"))?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open credential helper stdin")?
+        .write_all(server.as_bytes())?;
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run {program}"))?;
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stdout).to_string()
+            + &String::from_utf8_lossy(&output.stderr);
+        if message.contains("credentials not found") {
+            return Ok(None);
+        }
+        bail!("{program} failed for {server}: {}", message.trim());
+    }
+    let credentials: HelperCredentials = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Failed to parse the output of {program}"))?;
+    Ok(Some(Credentials::new(
+        credentials.username,
+        credentials.secret,
+    )))
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub(crate) fn base64_decode(text: &str) -> Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    let mut group = 0u32;
+    let mut bits = 0;
+    for c in text.trim().bytes().filter(|c| *c != b'=') {
+        let value = BASE64_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(|| anyhow!("Invalid base64 character '{}'", c as char))?;
+        group = group << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((group >> bits) as u8);
+            group &= (1 << bits) - 1;
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_base64() {
+        for text in ["", "u", "us", "user:pass", "user:password"] {
+            assert_eq!(
+                base64_decode(&base64_encode(text.as_bytes())).unwrap(),
+                text.as_bytes()
+            );
+        }
+        assert_eq!(base64_encode(b"user:pass"), "dXNlcjpwYXNz");
+        assert!(base64_decode("!!").is_err());
+    }
+
+    #[test]
+    fn test_lookup_docker_config() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("config.json");
+        fs::write(
+            &path,
+            r#"{
+                "auths": {
+                    "https://index.docker.io/v1/": {"auth": "dXNlcjpwYXNz"},
+                    "ghcr.io": {"username": "bot", "password": "ghp_x"},
+                    "quay.io": {}
+                }
+            }"#,
+        )
+        .unwrap();
+        let store = CredentialStore::new().with_docker_config(Some(path));
+
+        assert_eq!(
+            store.lookup("docker.io").unwrap(),
+            Some(Credentials::new("user", "pass"))
+        );
+        assert_eq!(
+            store.lookup("ghcr.io").unwrap(),
+            Some(Credentials::new("bot", "ghp_x"))
+        );
+        assert_eq!(store.lookup("quay.io").unwrap(), None);
+        assert_eq!(store.lookup("localhost:5000").unwrap(), None);
+
+        let explicit = store.with_credentials(Some(Credentials::new("ci", "secret")));
+        assert_eq!(
+            explicit.lookup("quay.io").unwrap(),
+            Some(Credentials::new("ci", "secret"))
+        );
+        assert_eq!(
+            explicit
+                .lookup("quay.io")
+                .unwrap()
+                .unwrap()
+                .basic_authorization(),
+            "Basic Y2k6c2VjcmV0"
+        );
+    }
+}
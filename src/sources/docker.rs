@@ -1,17 +1,86 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use std::path::PathBuf;
 use std::process::Command;
 use tempfile::TempDir;
 
-use super::{naming, Source};
+use super::{naming, Platform, Source};
+use crate::disk_space::SizeEstimate;
 use crate::notifier::Notifier;
+use crate::workdir::Workdir;
 
 /// Docker implementation of the Source trait
-pub struct DockerSource;
+pub struct DockerSource {
+    platform: Option<Platform>,
+    workdir: Workdir,
+    offline: bool,
+    pull: bool,
+}
 
 impl DockerSource {
     pub fn new() -> Result<Self> {
-        Ok(Self)
+        Ok(Self {
+            platform: None,
+            workdir: Workdir::default(),
+            offline: false,
+            pull: true,
+        })
+    }
+
+    /// Pull and export `platform` instead of the daemon's default platform
+    pub fn with_platform(mut self, platform: Option<Platform>) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Only export images already in the daemon: never pull, and fail instead of asking the
+    /// registry for platforms
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Fail instead of pulling images missing from the daemon; with a platform, export the
+    /// local image instead of pulling that platform first
+    pub fn with_pull(mut self, pull: bool) -> Self {
+        self.pull = pull;
+        self
+    }
+
+    /// The option that forbids pulling, if any
+    fn pull_forbidden_by(&self) -> Option<&'static str> {
+        if self.offline {
+            Some("--offline")
+        } else if !self.pull {
+            Some("--no-pull")
+        } else {
+            None
+        }
+    }
+
+    /// Exports `image_name` to `tarball_path` with `docker save`
+    fn save_image(&self, image_name: &str, tarball_path: &str) -> Result<String> {
+        match &self.platform {
+            Some(platform) => {
+                let platform = platform.to_string();
+                let args = [
+                    "save",
+                    "--platform",
+                    &platform,
+                    "-o",
+                    tarball_path,
+                    image_name,
+                ];
+                match self.run_command(&args) {
+                    // Older Docker releases can't select a platform on save; the image pulled
+                    // for the platform is the one saved, and the processor verifies it
+                    Err(e) if e.to_string().contains("unknown flag: --platform") => {
+                        self.run_command(&["save", "-o", tarball_path, image_name])
+                    }
+                    result => result,
+                }
+            }
+            None => self.run_command(&["save", "-o", tarball_path, image_name]),
+        }
     }
 
     fn run_command(&self, args: &[&str]) -> Result<String> {
@@ -40,8 +109,14 @@ impl DockerSource {
     fn pull_image(&self, image_name: &str, notifier: &Notifier) -> Result<()> {
         notifier.info(&format!("Pulling Docker image '{image_name}'..."));
 
+        let mut args = vec!["pull".to_string()];
+        if let Some(platform) = &self.platform {
+            args.extend(["--platform".to_string(), platform.to_string()]);
+        }
+        args.push(image_name.to_string());
+
         let output = Command::new("docker")
-            .args(["pull", image_name])
+            .args(&args)
             .output()
             .context("Failed to execute docker pull command")?;
 
@@ -66,8 +141,16 @@ impl Source for DockerSource {
         notifier: &Notifier,
     ) -> Result<(PathBuf, Option<TempDir>)> {
         // Create a temporary directory to save the image
-        let temp_dir = TempDir::new().context("Failed to create temporary directory")?;
+        let temp_dir = self.workdir.tempdir("oci2git-docker-")?;
         let tarball_path = temp_dir.path().join("image.tar");
+        let tarball = tarball_path.to_str().unwrap();
+
+        // The local image may belong to another platform; pull the requested one first.
+        // Without pulls, `docker save --platform` and the processor's check have to do
+        if self.platform.is_some() && self.pull_forbidden_by().is_none() {
+            self.pull_image(image_name, notifier)
+                .context(format!("Failed to pull image '{image_name}'"))?;
+        }
 
         // Use docker save to export the full image with all layers
         notifier.info(&format!(
@@ -75,8 +158,7 @@ impl Source for DockerSource {
         ));
 
         // Try to save the image first
-        let save_result =
-            self.run_command(&["save", "-o", tarball_path.to_str().unwrap(), image_name]);
+        let save_result = self.save_image(image_name, tarball);
 
         match save_result {
             Ok(_) => {
@@ -86,6 +168,9 @@ impl Source for DockerSource {
             Err(e) => {
                 // Save failed - check if it's because the image doesn't exist
                 if !self.image_exists(image_name) {
+                    if let Some(option) = self.pull_forbidden_by() {
+                        bail!("Image '{image_name}' not found locally, and {option} forbids pulling it");
+                    }
                     notifier.info(&format!(
                         "Image '{image_name}' not found locally, attempting to pull..."
                     ));
@@ -98,7 +183,7 @@ impl Source for DockerSource {
                     notifier.info(&format!(
                         "Retrying export of Docker image '{image_name}' to tarball..."
                     ));
-                    self.run_command(&["save", "-o", tarball_path.to_str().unwrap(), image_name])
+                    self.save_image(image_name, tarball)
                         .context(format!("Failed to save image '{image_name}' after pull"))?;
 
                     Ok((tarball_path, Some(temp_dir)))
@@ -114,6 +199,43 @@ impl Source for DockerSource {
         let base_branch = naming::container_image_to_branch(image_name);
         naming::combine_branch_with_digest(&base_branch, os_arch, image_digest)
     }
+
+    fn platforms(&self, image_name: &str, notifier: &Notifier) -> Result<Vec<Platform>> {
+        if self.offline {
+            bail!("Listing the platforms of '{image_name}' needs the registry, which --offline forbids");
+        }
+        notifier.info(&format!("Listing platforms of '{image_name}'..."));
+        let output = self
+            .run_command(&["manifest", "inspect", image_name])
+            .context(format!("Failed to inspect manifest of '{image_name}'"))?;
+        let manifest: serde_json::Value =
+            serde_json::from_str(&output).context("Failed to parse docker manifest output")?;
+        Ok(Platform::from_index(&manifest))
+    }
+
+    fn select_platform(&mut self, platform: Option<Platform>) -> Result<()> {
+        self.platform = platform;
+        Ok(())
+    }
+
+    /// Size reported by `docker image inspect`; unknown for images not pulled yet
+    fn estimate_image_size(&self, image_name: &str) -> Option<SizeEstimate> {
+        let output = self
+            .run_command(&["image", "inspect", "--format", "{{.Size}}", image_name])
+            .ok()?;
+        Some(SizeEstimate {
+            image_bytes: output.trim().parse().ok()?,
+            exported: true,
+        })
+    }
+
+    fn platform(&self) -> Option<&Platform> {
+        self.platform.as_ref()
+    }
+
+    fn set_workdir(&mut self, workdir: Workdir) {
+        self.workdir = workdir;
+    }
 }
 
 #[cfg(test)]
@@ -149,4 +271,23 @@ mod tests {
             "nginx#latest#linux-amd64#abcdef123456789"
         );
     }
+
+    #[test]
+    fn test_offline_platforms() {
+        let source = DockerSource::new().unwrap().with_offline(true);
+        let error = source.platforms("alpine", &Notifier::new(0)).unwrap_err();
+        assert!(error.to_string().contains("--offline"));
+    }
+
+    #[test]
+    fn test_pull_forbidden_by() {
+        let source = DockerSource::new().unwrap();
+        assert_eq!(source.pull_forbidden_by(), None);
+        let source = source.with_pull(false);
+        assert_eq!(source.pull_forbidden_by(), Some("--no-pull"));
+        assert_eq!(
+            source.with_offline(true).pull_forbidden_by(),
+            Some("--offline")
+        );
+    }
 }
@@ -1,8 +1,15 @@
 //! Source trait for getting OCI images from different container sources
 
+pub mod containerd;
+pub mod credentials;
 pub mod docker;
 pub mod nerdctl;
+pub mod platform;
+pub mod podman;
+pub mod registry;
+pub mod stream;
 pub mod tar;
+pub mod transport;
 
 // Naming utilities for branch name generation
 pub mod naming;
@@ -11,8 +18,13 @@ pub mod naming;
 mod source;
 pub use source::Source;
 
+pub use containerd::ContainerdSource;
 pub use docker::DockerSource;
 pub use nerdctl::NerdctlSource;
+pub use platform::Platform;
+pub use podman::PodmanSource;
+pub use registry::RegistrySource;
+pub use stream::StreamSource;
 pub use tar::TarSource;
 
 /// Sanitizes a string to be safe for Git branch naming
@@ -16,6 +16,28 @@ pub fn container_image_to_branch(image_name: &str) -> String {
         .replace("@", "-")
 }
 
+/// Converts an image name pinned by digest (`nginx@sha256:…`) to a Git branch name
+/// Uses the short digest in place of the tag (`nginx#sha256-1234567890ab`); any tag next
+/// to the digest is dropped since the digest decides what gets pulled
+/// Returns `None` for references without a digest
+pub fn digest_image_to_branch(image_name: &str) -> Option<String> {
+    let (name, digest) = image_name.split_once('@')?;
+    let (algorithm, hex) = digest.split_once(':')?;
+
+    // A tag can only follow the last path component (the registry may have a port)
+    let last_slash = name.rfind('/').map_or(0, |i| i + 1);
+    let name = match name[last_slash..].find(':') {
+        Some(i) => &name[..last_slash + i],
+        None => name,
+    };
+
+    Some(format!(
+        "{}#{algorithm}-{}",
+        name.replace(":", "#").replace("/", "-"),
+        &hex[..hex.len().min(12)]
+    ))
+}
+
 /// Extracts filename from a tar path and sanitizes it for Git branch naming
 /// Removes file extension and sanitizes problematic characters
 pub fn tar_path_to_branch(tar_path: &str) -> String {
@@ -79,6 +101,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_digest_image_to_branch() {
+        assert_eq!(
+            digest_image_to_branch("nginx@sha256:1234567890abcdef1234"),
+            Some("nginx#sha256-1234567890ab".to_string())
+        );
+        assert_eq!(
+            digest_image_to_branch("ghcr.io/org/app:1.2@sha256:abcdef"),
+            Some("ghcr.io-org-app#sha256-abcdef".to_string())
+        );
+        assert_eq!(
+            digest_image_to_branch("localhost:5000/app@sha256:1234567890abcdef"),
+            Some("localhost#5000-app#sha256-1234567890ab".to_string())
+        );
+        assert_eq!(digest_image_to_branch("nginx:latest"), None);
+        assert_eq!(digest_image_to_branch("nginx@latest"), None);
+    }
+
     #[test]
     fn test_tar_path_to_branch() {
         assert_eq!(tar_path_to_branch("/path/to/my-image.tar"), "my-image");
@@ -3,10 +3,12 @@ use std::path::PathBuf;
 use std::process::Command;
 use tempfile::TempDir;
 
-use super::Source;
+use super::{Platform, Source};
 use crate::notifier::Notifier;
 
-pub struct NerdctlSource;
+pub struct NerdctlSource {
+    platform: Option<Platform>,
+}
 
 impl NerdctlSource {
     pub fn new() -> Result<Self> {
@@ -19,7 +21,13 @@ impl NerdctlSource {
             return Err(anyhow!("nerdctl is not available"));
         }
 
-        Ok(Self)
+        Ok(Self { platform: None })
+    }
+
+    /// Export `platform` instead of the default platform
+    pub fn with_platform(mut self, platform: Option<Platform>) -> Self {
+        self.platform = platform;
+        self
     }
 }
 
@@ -58,6 +66,10 @@ impl Source for NerdctlSource {
             format!("{base_branch}#{os_arch}#{image_digest}")
         }
     }
+
+    fn platform(&self) -> Option<&Platform> {
+        self.platform.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -67,7 +79,7 @@ mod tests {
     #[test]
     fn test_nerdctl_source_branch_name() {
         // Create a source directly without checking if nerdctl is available
-        let source = NerdctlSource;
+        let source = NerdctlSource { platform: None };
         // The processor always provides the os_arch and digest extracted from image metadata
         assert_eq!(
             source.branch_name(
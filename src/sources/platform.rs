@@ -0,0 +1,168 @@
+//! Target platform (`os/architecture[/variant]`) for multi-platform images.
+//!
+//! Parsed from strings like `linux/arm64` or `linux/arm/v7` (the `--platform` flag), with
+//! common aliases normalized to OCI names (`x86_64` → `amd64`, `aarch64` → `arm64`).
+//! [`Platform::from_index`] lists the platforms of an image index / manifest list.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// An OCI platform used to select one image out of a manifest list / image index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    /// Linux on the architecture of the current machine.
+    pub fn host() -> Self {
+        Self {
+            os: "linux".to_string(),
+            architecture: normalize_arch(std::env::consts::ARCH).to_string(),
+            variant: None,
+        }
+    }
+
+    /// Platforms of the images in an OCI image index / Docker manifest list, in index
+    /// order without duplicates. Attestation manifests (`unknown/unknown`) are skipped.
+    pub fn from_index(index: &Value) -> Vec<Self> {
+        let mut platforms: Vec<Self> = Vec::new();
+        let descriptors = index["manifests"].as_array().into_iter().flatten();
+
+        for platform in descriptors.map(|descriptor| &descriptor["platform"]) {
+            let (Some(os), Some(architecture)) =
+                (platform["os"].as_str(), platform["architecture"].as_str())
+            else {
+                continue;
+            };
+            if os == "unknown" || architecture == "unknown" {
+                continue;
+            }
+
+            let platform = Self {
+                os: os.to_string(),
+                architecture: normalize_arch(architecture).to_string(),
+                variant: platform["variant"].as_str().map(str::to_string),
+            };
+            if !platforms.contains(&platform) {
+                platforms.push(platform);
+            }
+        }
+
+        platforms
+    }
+
+    /// Whether an image for `os`/`architecture`/`variant` satisfies this platform.
+    ///
+    /// A platform without variant matches any variant; `arm64` treats a missing
+    /// variant as `v8`.
+    pub fn matches(&self, os: &str, architecture: &str, variant: Option<&str>) -> bool {
+        let default_variant = |arch: &str| (arch == "arm64").then_some("v8");
+        let wanted = self.variant.as_deref();
+        let actual = variant.or(default_variant(architecture));
+
+        self.os == os
+            && self.architecture == normalize_arch(architecture)
+            && (wanted.is_none() || wanted == actual)
+    }
+}
+
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split('/').collect();
+        if parts.len() < 2 || parts.len() > 3 || parts.iter().any(|part| part.is_empty()) {
+            bail!("Invalid platform '{s}', expected os/arch[/variant] (e.g. linux/arm64)");
+        }
+
+        Ok(Self {
+            os: parts[0].to_lowercase(),
+            architecture: normalize_arch(&parts[1].to_lowercase()).to_string(),
+            variant: parts.get(2).map(|variant| variant.to_lowercase()),
+        })
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps Rust/uname architecture names to their OCI equivalents.
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" | "x86-64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" | "i386" | "i686" => "386",
+        "powerpc64le" => "ppc64le",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_platform() {
+        let platform: Platform = "linux/arm64".parse().unwrap();
+        assert_eq!(platform.os, "linux");
+        assert_eq!(platform.architecture, "arm64");
+        assert_eq!(platform.variant, None);
+
+        let platform: Platform = "linux/arm/v7".parse().unwrap();
+        assert_eq!(platform.variant.as_deref(), Some("v7"));
+        assert_eq!(platform.to_string(), "linux/arm/v7");
+
+        let platform: Platform = "Linux/x86_64".parse().unwrap();
+        assert_eq!(platform.to_string(), "linux/amd64");
+
+        assert!("arm64".parse::<Platform>().is_err());
+        assert!("linux//v7".parse::<Platform>().is_err());
+        assert!("linux/arm/v7/extra".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn test_platform_matches() {
+        let arm64: Platform = "linux/arm64".parse().unwrap();
+        assert!(arm64.matches("linux", "arm64", None));
+        assert!(arm64.matches("linux", "arm64", Some("v8")));
+        assert!(!arm64.matches("linux", "amd64", None));
+        assert!(!arm64.matches("windows", "arm64", None));
+
+        let arm64_v8: Platform = "linux/arm64/v8".parse().unwrap();
+        assert!(arm64_v8.matches("linux", "arm64", None));
+
+        let arm_v7: Platform = "linux/arm/v7".parse().unwrap();
+        assert!(arm_v7.matches("linux", "arm", Some("v7")));
+        assert!(!arm_v7.matches("linux", "arm", Some("v6")));
+    }
+
+    #[test]
+    fn test_platforms_from_index() {
+        let index = serde_json::json!({"manifests": [
+            {"digest": "sha256:1", "platform": {"os": "linux", "architecture": "amd64"}},
+            {"digest": "sha256:2", "platform": {"os": "linux", "architecture": "arm", "variant": "v7"}},
+            {"digest": "sha256:3", "platform": {"os": "unknown", "architecture": "unknown"}},
+            {"digest": "sha256:4", "platform": {"os": "linux", "architecture": "amd64"}},
+            {"digest": "sha256:5"},
+        ]});
+        let platforms: Vec<String> = Platform::from_index(&index)
+            .iter()
+            .map(Platform::to_string)
+            .collect();
+        assert_eq!(platforms, ["linux/amd64", "linux/arm/v7"]);
+
+        assert!(Platform::from_index(&serde_json::json!({"layers": []})).is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::PathBuf;
+use std::process::Command;
+use tempfile::TempDir;
+
+use super::{naming, Platform, Source};
+use crate::disk_space::SizeEstimate;
+use crate::notifier::Notifier;
+use crate::workdir::Workdir;
+
+/// Podman implementation of the Source trait
+///
+/// Images are exported with `podman save` as a Docker archive and go through the same tar
+/// backend as Docker's; no daemon or socket is needed.
+pub struct PodmanSource {
+    platform: Option<Platform>,
+    workdir: Workdir,
+    offline: bool,
+    pull: bool,
+}
+
+impl PodmanSource {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            platform: None,
+            workdir: Workdir::default(),
+            offline: false,
+            pull: true,
+        })
+    }
+
+    /// Pull and export `platform` instead of the host's platform
+    pub fn with_platform(mut self, platform: Option<Platform>) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Only export images already in local storage: never pull, and fail instead of asking
+    /// the registry for platforms
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Fail instead of pulling images missing from local storage; with a platform, export
+    /// the local image instead of pulling that platform first
+    pub fn with_pull(mut self, pull: bool) -> Self {
+        self.pull = pull;
+        self
+    }
+
+    /// The option that forbids pulling, if any
+    fn pull_forbidden_by(&self) -> Option<&'static str> {
+        if self.offline {
+            Some("--offline")
+        } else if !self.pull {
+            Some("--no-pull")
+        } else {
+            None
+        }
+    }
+
+    /// Exports `image_name` to `tarball_path` with `podman save`. It has no platform option:
+    /// the image pulled for the platform is the one saved, and the processor verifies it
+    fn save_image(&self, image_name: &str, tarball_path: &str) -> Result<String> {
+        self.run_command(&[
+            "save",
+            "--format",
+            "docker-archive",
+            "-o",
+            tarball_path,
+            image_name,
+        ])
+    }
+
+    fn run_command(&self, args: &[&str]) -> Result<String> {
+        let output = Command::new("podman")
+            .args(args)
+            .output()
+            .context(format!("Failed to execute podman command: {args:?}"))?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!("Podman command failed: {error}"));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        Ok(stdout)
+    }
+
+    fn image_exists(&self, image_name: &str) -> bool {
+        Command::new("podman")
+            .args(["image", "exists", image_name])
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    }
+
+    fn pull_args(&self, image_name: &str) -> Vec<String> {
+        let mut args = vec!["pull".to_string()];
+        if let Some(platform) = &self.platform {
+            args.extend(["--platform".to_string(), platform.to_string()]);
+        }
+        args.push(image_name.to_string());
+        args
+    }
+
+    fn pull_image(&self, image_name: &str, notifier: &Notifier) -> Result<()> {
+        notifier.info(&format!("Pulling Podman image '{image_name}'..."));
+
+        let output = Command::new("podman")
+            .args(self.pull_args(image_name))
+            .output()
+            .context("Failed to execute podman pull command")?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!("Podman pull failed: {error}"));
+        }
+
+        notifier.info(&format!("Successfully pulled Podman image '{image_name}'"));
+        Ok(())
+    }
+}
+
+impl Source for PodmanSource {
+    fn name(&self) -> &str {
+        "podman"
+    }
+
+    fn get_image_tarball(
+        &self,
+        image_name: &str,
+        notifier: &Notifier,
+    ) -> Result<(PathBuf, Option<TempDir>)> {
+        let temp_dir = self.workdir.tempdir("oci2git-podman-")?;
+        let tarball_path = temp_dir.path().join("image.tar");
+        let tarball = tarball_path.to_str().unwrap();
+
+        // The local image may belong to another platform; pull the requested one first.
+        // Without pulls, the processor's check has to do
+        if self.platform.is_some() && self.pull_forbidden_by().is_none() {
+            self.pull_image(image_name, notifier)
+                .context(format!("Failed to pull image '{image_name}'"))?;
+        } else if !self.image_exists(image_name) {
+            if let Some(option) = self.pull_forbidden_by() {
+                bail!("Image '{image_name}' not found locally, and {option} forbids pulling it");
+            }
+            notifier.info(&format!(
+                "Image '{image_name}' not found locally, attempting to pull..."
+            ));
+            self.pull_image(image_name, notifier)
+                .context(format!("Failed to pull image '{image_name}'"))?;
+        }
+
+        notifier.info(&format!(
+            "Exporting Podman image '{image_name}' to tarball..."
+        ));
+        self.save_image(image_name, tarball)
+            .context(format!("Failed to save image '{image_name}'"))?;
+
+        Ok((tarball_path, Some(temp_dir)))
+    }
+
+    fn branch_name(&self, image_name: &str, os_arch: &str, image_digest: &str) -> String {
+        let base_branch = naming::container_image_to_branch(image_name);
+        naming::combine_branch_with_digest(&base_branch, os_arch, image_digest)
+    }
+
+    fn platforms(&self, image_name: &str, notifier: &Notifier) -> Result<Vec<Platform>> {
+        if self.offline {
+            bail!("Listing the platforms of '{image_name}' needs the registry, which --offline forbids");
+        }
+        notifier.info(&format!("Listing platforms of '{image_name}'..."));
+        let output = self
+            .run_command(&["manifest", "inspect", image_name])
+            .context(format!("Failed to inspect manifest of '{image_name}'"))?;
+        let manifest: serde_json::Value =
+            serde_json::from_str(&output).context("Failed to parse podman manifest output")?;
+        Ok(Platform::from_index(&manifest))
+    }
+
+    fn select_platform(&mut self, platform: Option<Platform>) -> Result<()> {
+        self.platform = platform;
+        Ok(())
+    }
+
+    /// Size reported by `podman image inspect`; unknown for images not pulled yet
+    fn estimate_image_size(&self, image_name: &str) -> Option<SizeEstimate> {
+        let output = self
+            .run_command(&["image", "inspect", "--format", "{{.Size}}", image_name])
+            .ok()?;
+        Some(SizeEstimate {
+            image_bytes: output.trim().parse().ok()?,
+            exported: true,
+        })
+    }
+
+    fn platform(&self) -> Option<&Platform> {
+        self.platform.as_ref()
+    }
+
+    fn set_workdir(&mut self, workdir: Workdir) {
+        self.workdir = workdir;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_podman_source_branch_name() {
+        let source = PodmanSource::new().unwrap();
+        assert_eq!(source.name(), "podman");
+        assert_eq!(
+            source.branch_name(
+                "hello-world:latest",
+                "linux-amd64",
+                "sha256:1234567890abcdef"
+            ),
+            "hello-world#latest#linux-amd64#1234567890ab"
+        );
+        assert_eq!(
+            source.branch_name(
+                "quay.io/podman/hello",
+                "linux-arm64",
+                "sha256:9876543210fedcba"
+            ),
+            source.branch_name(
+                "quay.io/podman/hello:latest",
+                "linux-arm64",
+                "sha256:9876543210fedcba"
+            )
+        );
+    }
+
+    #[test]
+    fn test_offline_platforms() {
+        let source = PodmanSource::new().unwrap().with_offline(true);
+        let error = source.platforms("alpine", &Notifier::new(0)).unwrap_err();
+        assert!(error.to_string().contains("--offline"));
+    }
+
+    #[test]
+    fn test_pull_forbidden_by() {
+        let source = PodmanSource::new().unwrap();
+        assert_eq!(source.pull_forbidden_by(), None);
+        let source = source.with_pull(false);
+        assert_eq!(source.pull_forbidden_by(), Some("--no-pull"));
+        assert_eq!(
+            source.with_offline(true).pull_forbidden_by(),
+            Some("--offline")
+        );
+    }
+
+    #[test]
+    fn test_pull_args() {
+        let source = PodmanSource::new().unwrap();
+        assert_eq!(source.pull_args("alpine"), vec!["pull", "alpine"]);
+        let mut source = source.with_platform(Some("linux/arm64/v8".parse().unwrap()));
+        assert_eq!(
+            source.pull_args("alpine"),
+            vec!["pull", "--platform", "linux/arm64/v8", "alpine"]
+        );
+        source.select_platform(None).unwrap();
+        assert_eq!(source.platform(), None);
+    }
+}
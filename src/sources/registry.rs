@@ -0,0 +1,1939 @@
+//! Registry source: pulls images straight from an OCI/Docker registry over HTTP(S).
+//!
+//! No container engine is required. [`RegistrySource`] speaks the OCI distribution API:
+//! - resolves references like `alpine`, `ghcr.io/org/app:1.2` or `repo@sha256:…`
+//!   (Docker Hub names get the usual `docker.io/library/` defaults); images pinned by
+//!   digest are converted into branches named after the digest instead of a tag,
+//! - authenticates with bearer tokens when the registry asks for them
+//!   (`WWW-Authenticate: Bearer realm=…`), anonymously or with the credentials of a
+//!   [`CredentialStore`] (`docker login`, or given explicitly), and with basic
+//!   authentication for registries that ask for that,
+//! - goes through the proxy from the environment, and trusts extra CA certificates or
+//!   skips verification for insecure registries (see [`Transport`]),
+//! - picks the manifest for the requested [`Platform`] (default: the host) from image
+//!   indexes / manifest lists,
+//! - downloads the config and layer blobs and writes them into a `docker save` style
+//!   tarball (`manifest.json`, `index.json`, `blobs/sha256/…`) for the tar backend.
+//!
+//! Layers are stored uncompressed under their `diff_id`, exactly like `docker save`, so
+//! branches created from the registry and from a Docker daemon share layer digests.
+//!
+//! With [`RegistrySource::with_metadata_only`] only the manifest and config are
+//! fetched: the tarball lists the layers without their blobs, so each layer becomes a
+//! commit that records its digest but changes no files. Of eStargz layers only the
+//! footer and the table of contents are fetched, with range requests, into
+//! `estargz/<diff_id hex>.json` (see [`crate::estargz`]).
+//!
+//! Blobs are downloaded into a [`BlobCache`]: a download interrupted by a network error
+//! is retried and resumed with a `Range` request from where it stopped (also across
+//! runs), and every blob is checked against its sha256 digest before it is used.
+//! Plain HTTP is only used for `localhost` registries.
+//!
+//! Signatures, attestations (e.g. SLSA provenance) and SBOMs attached to the image, through
+//! BuildKit's attestation manifest in the image index, cosign's `sha256-<hex>.sig`/`.att`/
+//! `.sbom` tags or the OCI referrers API, are written into the tarball as well and end up under `attestations/` in the repository (see
+//! [`crate::attestations`]). Failing to fetch them only produces a warning.
+//!
+//! Before converting many related images, [`RegistrySource::prefetch`] downloads their
+//! blobs into the cache with several images in flight at once; blobs they share are
+//! downloaded once, and the conversions then read them from the cache.
+
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+use indicatif::ProgressBar;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tar_rs as tar;
+use tempfile::TempDir;
+
+use super::credentials::{CredentialStore, Credentials};
+use super::transport::Transport;
+use super::{naming, Platform, Source};
+use crate::attestations::{blob_file_name, cosign_tag, ATTESTATIONS_DIR, COSIGN_SUFFIXES};
+use crate::blob_cache::{digest_hex, BlobCache};
+use crate::estargz;
+use crate::notifier::Notifier;
+use crate::processor::METADATA_ONLY_SUFFIX;
+use crate::workdir::Workdir;
+
+const MANIFEST_MEDIA_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+application/vnd.docker.distribution.manifest.list.v2+json, \
+application/vnd.oci.image.manifest.v1+json, \
+application/vnd.docker.distribution.manifest.v2+json";
+
+/// Attempts per blob before a transient network failure is reported.
+const DOWNLOAD_ATTEMPTS: u32 = 5;
+/// Pause before retry `n` is `n * RETRY_DELAY`.
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// A parsed image reference: `[registry/]repository[:tag|@digest]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    /// Registry host (with optional port), e.g. `docker.io` or `localhost:5000`.
+    pub registry: String,
+    /// Repository path, e.g. `library/alpine`.
+    pub repository: String,
+    /// Tag or digest (`sha256:…`).
+    pub reference: String,
+}
+
+impl ImageReference {
+    /// Parses a reference using the same defaults as the Docker CLI.
+    pub fn parse(image: &str) -> Result<Self> {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (image, None),
+        };
+
+        // A tag can only follow the last path component (the registry may have a port)
+        let last_slash = name.rfind('/').map_or(0, |i| i + 1);
+        let (name, tag) = match name[last_slash..].rfind(':') {
+            Some(i) => (&name[..last_slash + i], Some(&name[last_slash + i + 1..])),
+            None => (name, None),
+        };
+
+        if name.is_empty() || tag == Some("") || digest == Some("") {
+            bail!("Invalid image reference: '{image}'");
+        }
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), rest.to_string())
+            }
+            _ => ("docker.io".to_string(), name.to_string()),
+        };
+        if repository.is_empty() {
+            bail!("Invalid image reference: '{image}'");
+        }
+        let repository = if registry == "docker.io" && !repository.contains('/') {
+            format!("library/{repository}")
+        } else {
+            repository
+        };
+
+        let reference = digest.or(tag).unwrap_or("latest").to_string();
+        Ok(Self {
+            registry,
+            repository,
+            reference,
+        })
+    }
+
+    /// `true` when the reference pins a digest rather than a tag.
+    pub fn is_digest(&self) -> bool {
+        self.reference.contains(':')
+    }
+
+    /// Host (with optional port) serving the registry API.
+    fn host(&self) -> &str {
+        if self.registry == "docker.io" {
+            "registry-1.docker.io"
+        } else {
+            self.registry.as_str()
+        }
+    }
+
+    /// Base URL of the registry API.
+    fn base_url(&self) -> String {
+        let host = self.host();
+        let hostname = host.rsplit_once(':').map_or(host, |(name, _)| name);
+        let scheme = if matches!(hostname, "localhost" | "127.0.0.1" | "[::1]") {
+            "http"
+        } else {
+            "https"
+        };
+        format!("{scheme}://{host}")
+    }
+}
+
+/// Fully qualified form, e.g. `docker.io/library/alpine:latest` or `ghcr.io/org/app@sha256:…`.
+impl fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.is_digest() { '@' } else { ':' };
+        write!(
+            f,
+            "{}/{}{separator}{}",
+            self.registry, self.repository, self.reference
+        )
+    }
+}
+
+/// Prefix selecting the registry engine from the image argument, e.g. `registry://nginx@sha256:…`.
+pub const REGISTRY_SCHEME: &str = "registry://";
+
+/// Pulls images directly from a registry, without Docker or nerdctl.
+pub struct RegistrySource {
+    transport: Transport,
+    platform: Option<Platform>,
+    blob_cache: BlobCache,
+    metadata_only: bool,
+    workdir: Workdir,
+    credentials: CredentialStore,
+}
+
+impl RegistrySource {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            transport: Transport::new()?,
+            platform: None,
+            blob_cache: BlobCache::new(BlobCache::default_dir()),
+            metadata_only: false,
+            workdir: Workdir::default(),
+            credentials: CredentialStore::default(),
+        })
+    }
+
+    /// Connect to registries through `transport` (proxy, CA certificates, insecure
+    /// registries) instead of one configured from the environment only
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Log in to registries that refuse anonymous pulls with credentials from
+    /// `credentials` instead of only the Docker config file
+    pub fn with_credentials(mut self, credentials: CredentialStore) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Download blobs into `blob_cache` instead of the per-user default cache
+    pub fn with_blob_cache(mut self, blob_cache: BlobCache) -> Self {
+        self.blob_cache = blob_cache;
+        self
+    }
+
+    /// Pull `platform` from multi-platform images instead of the host platform
+    pub fn with_platform(mut self, platform: Option<Platform>) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Fetch only the manifest and config, skipping the layer blobs.
+    ///
+    /// Converted images get their own `…#metadata-only` branch, so a later full
+    /// conversion of the same image never collides with it.
+    pub fn with_metadata_only(mut self, metadata_only: bool) -> Self {
+        self.metadata_only = metadata_only;
+        self
+    }
+
+    /// Downloads the blobs of `images` into the blob cache, working on up to `jobs` images
+    /// at a time, so converting them afterwards reads the cache instead of the network.
+    /// Blobs shared by several images are downloaded once; in metadata-only mode only the
+    /// configs are fetched.
+    ///
+    /// Returns the outcome for each image, in the order of `images`.
+    pub fn prefetch(&self, images: &[String], jobs: usize) -> Vec<Result<()>> {
+        let next = AtomicUsize::new(0);
+        let claimed = Mutex::new(HashSet::new());
+
+        let mut results: Vec<(usize, Result<()>)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs.clamp(1, images.len().max(1)))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(image) = images.get(index) else {
+                                break done;
+                            };
+                            let result = self
+                                .prefetch_image(image, &claimed)
+                                .with_context(|| format!("Failed to prefetch '{image}'"));
+                            done.push((index, result));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("Prefetch worker panicked"))
+                .collect()
+        });
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Client for the repository of `reference`.
+    fn client(&self, reference: &ImageReference) -> Result<RegistryClient> {
+        let agent = self
+            .transport
+            .agent(&reference.registry, reference.host())?;
+        let mut client = RegistryClient::new(agent, reference, self.credentials.clone());
+        client.http_fallback = self.transport.is_insecure(&reference.registry)
+            && client.base_url.starts_with("https:");
+        Ok(client)
+    }
+
+    /// Downloads the blobs of `image_name` that no other worker has `claimed`.
+    fn prefetch_image(&self, image_name: &str, claimed: &Mutex<HashSet<String>>) -> Result<()> {
+        let reference = ImageReference::parse(image_name)?;
+        let mut client = self.client(&reference)?;
+        let platform = self.platform.clone().unwrap_or_else(Platform::host);
+        let manifest = client.resolve_manifest(&reference, &platform)?.manifest;
+
+        let config = manifest["config"]["digest"].as_str();
+        let layers = manifest["layers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|layer| layer["digest"].as_str())
+            .filter(|_| !self.metadata_only);
+        for digest in config.into_iter().chain(layers) {
+            let first = claimed
+                .lock()
+                .map_err(|_| anyhow!("Prefetch lock is poisoned"))?
+                .insert(digest.to_string());
+            if first {
+                log::debug!("Prefetching {digest} for '{image_name}'");
+                client.download_blob(&self.blob_cache, digest, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Downloads config and layers of the `resolved` manifest into a `docker save` style tarball.
+    ///
+    /// In metadata-only mode the layers are listed in `manifest.json` but not written.
+    fn write_image_tarball(
+        &self,
+        client: &mut RegistryClient,
+        resolved: &ResolvedManifest,
+        repo_tag: Option<&str>,
+        tarball_path: &Path,
+        notifier: &Notifier,
+    ) -> Result<()> {
+        let staging_dir = tarball_path
+            .parent()
+            .ok_or_else(|| anyhow!("Tarball path has no parent directory"))?;
+        let manifest = &resolved.manifest;
+        let mut builder = tar::Builder::new(
+            File::create(tarball_path)
+                .with_context(|| format!("Failed to create {}", tarball_path.display()))?,
+        );
+
+        // Config
+        let config_digest = manifest["config"]["digest"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Image manifest has no config"))?;
+        let config_path = format!("blobs/sha256/{}", digest_hex(config_digest)?);
+        let config_bytes = fs::read(
+            client
+                .download_blob(&self.blob_cache, config_digest, None)
+                .context("Failed to download image config")?,
+        )?;
+        let config: Value =
+            serde_json::from_slice(&config_bytes).context("Failed to parse image config")?;
+        append_bytes(&mut builder, &config_path, &config_bytes)?;
+
+        // Layers, stored uncompressed under their diff_id
+        let layers = manifest["layers"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Image manifest has no layers"))?;
+        let diff_ids = config["rootfs"]["diff_ids"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Image config has no rootfs.diff_ids"))?;
+        if layers.len() != diff_ids.len() {
+            bail!(
+                "Image manifest lists {} layers but config has {} diff_ids",
+                layers.len(),
+                diff_ids.len()
+            );
+        }
+
+        let total_size: u64 = layers.iter().filter_map(|l| l["size"].as_u64()).sum();
+        let progress_bar = if self.metadata_only {
+            None
+        } else {
+            notifier.create_progress_bar(total_size, "Downloading layers")
+        };
+        let mut layer_paths = Vec::new();
+        let mut written = HashSet::new();
+
+        for (i, (layer, diff_id)) in layers.iter().zip(diff_ids).enumerate() {
+            let digest = layer["digest"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Layer {} has no digest", i + 1))?;
+            let diff_id = diff_id
+                .as_str()
+                .ok_or_else(|| anyhow!("Layer {} has no diff_id", i + 1))?;
+            let layer_path = format!("blobs/sha256/{}", digest_hex(diff_id)?);
+            layer_paths.push(layer_path.clone());
+
+            if !written.insert(layer_path.clone()) {
+                continue;
+            }
+            if self.metadata_only {
+                // eStargz layers list their files in a TOC, fetched on its own
+                if let Some(toc_digest) =
+                    layer["annotations"][estargz::TOC_DIGEST_ANNOTATION].as_str()
+                {
+                    match client.fetch_toc(digest, layer["size"].as_u64(), toc_digest) {
+                        Ok(toc) => append_bytes(
+                            &mut builder,
+                            &format!("{}/{}.json", estargz::TOC_DIR, digest_hex(diff_id)?),
+                            &toc,
+                        )?,
+                        Err(e) => notifier.warn(&format!(
+                            "Failed to fetch the eStargz TOC of layer {}: {e:#}",
+                            i + 1
+                        )),
+                    }
+                }
+                continue;
+            }
+
+            notifier.info(&format!(
+                "Downloading layer {}/{}: {digest}",
+                i + 1,
+                layers.len()
+            ));
+            let blob_path =
+                client.download_blob(&self.blob_cache, digest, progress_bar.as_ref())?;
+            let blob = File::open(&blob_path)
+                .with_context(|| format!("Failed to open {}", blob_path.display()))?;
+
+            let staged = staging_dir.join("layer.tar");
+            let mut out = File::create(&staged).context("Failed to create layer file")?;
+            io::copy(&mut decompress(Box::new(blob))?, &mut out)
+                .with_context(|| format!("Failed to unpack layer {digest}"))?;
+            drop(out);
+
+            builder
+                .append_path_with_name(&staged, &layer_path)
+                .context("Failed to write layer into image tarball")?;
+            fs::remove_file(&staged)?;
+        }
+        if let Some(progress_bar) = progress_bar {
+            progress_bar.finish_and_clear();
+        }
+
+        // docker save compatible manifest.json, plus index.json carrying the manifest digest
+        let docker_manifest = json!([{
+            "Config": config_path,
+            "RepoTags": repo_tag.into_iter().collect::<Vec<_>>(),
+            "Layers": layer_paths,
+        }]);
+        append_bytes(
+            &mut builder,
+            "manifest.json",
+            &serde_json::to_vec(&docker_manifest)?,
+        )?;
+
+        if let Some(manifest_digest) = resolved.digest.as_deref() {
+            match self.append_attestations(
+                client,
+                manifest_digest,
+                resolved.attestation_manifest.as_deref(),
+                &mut builder,
+            ) {
+                Ok(0) => {}
+                Ok(found) => notifier.info(&format!(
+                    "Found {found} signature/attestation artifact(s) attached to the image"
+                )),
+                Err(e) => notifier.warn(&format!(
+                    "Failed to fetch signatures and attestations of {manifest_digest}: {e:#}"
+                )),
+            }
+
+            let mut descriptor = json!({
+                "mediaType": manifest["mediaType"],
+                "digest": manifest_digest,
+            });
+            // e.g. org.opencontainers.image.base.name, see crate::base_image
+            if manifest["annotations"].is_object() {
+                descriptor["annotations"] = manifest["annotations"].clone();
+            }
+            let index = json!({"schemaVersion": 2, "manifests": [descriptor]});
+            append_bytes(&mut builder, "index.json", &serde_json::to_vec(&index)?)?;
+        }
+
+        builder
+            .into_inner()
+            .context("Failed to finish image tarball")?;
+        Ok(())
+    }
+
+    /// Writes the artifacts attached to the image `digest` (the BuildKit
+    /// `attestation_manifest` of the index, cosign tags, then referrers) into the tarball
+    /// under [`ATTESTATIONS_DIR`]: each one's manifest as it was served, plus its blobs.
+    /// Returns the number of artifacts.
+    fn append_attestations<W: io::Write>(
+        &self,
+        client: &mut RegistryClient,
+        digest: &str,
+        attestation_manifest: Option<&str>,
+        builder: &mut tar::Builder<W>,
+    ) -> Result<usize> {
+        let mut artifacts = Vec::new();
+        let mut seen = HashSet::new();
+        if let Some(attestation_manifest) = attestation_manifest {
+            if let Some(served) = client.find_manifest(attestation_manifest)? {
+                seen.insert(attestation_manifest.to_string());
+                artifacts.push(("buildkit".to_string(), served));
+            }
+        }
+        for suffix in COSIGN_SUFFIXES {
+            let tag = cosign_tag(digest, suffix)?;
+            if let Some(served) = client.find_manifest(&tag)? {
+                seen.extend(served.digest.clone());
+                artifacts.push((format!("cosign-{suffix}"), served));
+            }
+        }
+
+        let referrers = client.find_json(
+            &format!("referrers/{digest}"),
+            "application/vnd.oci.image.index.v1+json",
+        )?;
+        let descriptors = referrers
+            .as_ref()
+            .and_then(|index| index["manifests"].as_array())
+            .into_iter()
+            .flatten();
+        for descriptor in descriptors {
+            let Some(referrer) = descriptor["digest"].as_str() else {
+                continue;
+            };
+            if !seen.insert(referrer.to_string()) {
+                continue;
+            }
+            if let Some(served) = client.find_manifest(referrer)? {
+                artifacts.push((format!("referrers/{}", digest_hex(referrer)?), served));
+            }
+        }
+
+        for (dir, served) in &artifacts {
+            let dir = format!("{ATTESTATIONS_DIR}/{dir}");
+            append_bytes(builder, &format!("{dir}/manifest.json"), &served.raw)?;
+            for blob in served.manifest["layers"].as_array().into_iter().flatten() {
+                let blob_digest = blob["digest"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Artifact blob without digest in {dir}"))?;
+                let media_type = blob["mediaType"].as_str().unwrap_or_default();
+                let path = client.download_blob(&self.blob_cache, blob_digest, None)?;
+                builder
+                    .append_path_with_name(
+                        &path,
+                        format!("{dir}/{}", blob_file_name(blob_digest, media_type)?),
+                    )
+                    .context("Failed to write artifact blob into image tarball")?;
+            }
+        }
+        Ok(artifacts.len())
+    }
+}
+
+impl Source for RegistrySource {
+    fn name(&self) -> &str {
+        "registry"
+    }
+
+    fn get_image_tarball(
+        &self,
+        image_name: &str,
+        notifier: &Notifier,
+    ) -> Result<(PathBuf, Option<TempDir>)> {
+        let reference = ImageReference::parse(image_name)?;
+        let mut client = self.client(&reference)?;
+        let platform = self.platform.clone().unwrap_or_else(Platform::host);
+
+        notifier.info(&format!(
+            "Resolving '{}/{}:{}' for {}...",
+            reference.registry, reference.repository, reference.reference, platform
+        ));
+        let resolved = client.resolve_manifest(&reference, &platform)?;
+
+        let temp_dir = self.workdir.tempdir("oci2git-registry-")?;
+        let tarball_path = temp_dir.path().join("image.tar");
+        let repo_tag = (!reference.is_digest()).then_some(image_name);
+
+        self.write_image_tarball(&mut client, &resolved, repo_tag, &tarball_path, notifier)
+            .with_context(|| format!("Failed to pull image '{image_name}'"))?;
+
+        Ok((tarball_path, Some(temp_dir)))
+    }
+
+    fn branch_name(&self, image_name: &str, os_arch: &str, image_digest: &str) -> String {
+        let base_branch = naming::digest_image_to_branch(image_name)
+            .unwrap_or_else(|| naming::container_image_to_branch(image_name));
+        let branch = naming::combine_branch_with_digest(&base_branch, os_arch, image_digest);
+        if self.metadata_only {
+            format!("{branch}{METADATA_ONLY_SUFFIX}")
+        } else {
+            branch
+        }
+    }
+
+    fn platforms(&self, image_name: &str, notifier: &Notifier) -> Result<Vec<Platform>> {
+        let reference = ImageReference::parse(image_name)?;
+        let mut client = self.client(&reference)?;
+
+        notifier.info(&format!("Listing platforms of '{image_name}'..."));
+        let (manifest, _) = client.get_json(
+            &format!("manifests/{}", reference.reference),
+            MANIFEST_MEDIA_TYPES,
+        )?;
+        Ok(Platform::from_index(&manifest))
+    }
+
+    fn select_platform(&mut self, platform: Option<Platform>) -> Result<()> {
+        self.platform = platform;
+        Ok(())
+    }
+
+    fn platform(&self) -> Option<&Platform> {
+        self.platform.as_ref()
+    }
+
+    fn set_workdir(&mut self, workdir: Workdir) {
+        self.workdir = workdir;
+    }
+}
+
+/// Minimal distribution API client for a single repository.
+struct RegistryClient {
+    agent: ureq::Agent,
+    base_url: String,
+    repository: String,
+    registry: String,
+    credentials: CredentialStore,
+    /// `Authorization` header, once the registry asked for one
+    authorization: Option<String>,
+    /// Whether to switch to plain HTTP if the first HTTPS request can't connect
+    http_fallback: bool,
+}
+
+impl RegistryClient {
+    fn new(agent: ureq::Agent, reference: &ImageReference, credentials: CredentialStore) -> Self {
+        Self {
+            agent,
+            base_url: reference.base_url(),
+            repository: reference.repository.clone(),
+            registry: reference.registry.clone(),
+            credentials,
+            authorization: None,
+            http_fallback: false,
+        }
+    }
+
+    /// GET `/v2/<repository>/<path>`, authenticating once if the registry asks for it.
+    fn get(&mut self, path: &str, accept: &str) -> Result<ureq::Response> {
+        let url = self.url(path);
+        match self.send(path, accept, None)? {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(code, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                bail!("Registry returned HTTP {code} for {url}: {}", body.trim());
+            }
+            Err(e) => Err(e).with_context(|| format!("Request to {url} failed")),
+        }
+    }
+
+    /// Sends the GET (optionally for a byte range, open-ended or inclusive) and returns the raw
+    /// outcome; only failing to authenticate is an error here. A 401 answers the challenge once
+    /// per request, also when it rejects a token that has expired meanwhile; a second one is
+    /// returned.
+    fn send(
+        &mut self,
+        path: &str,
+        accept: &str,
+        range: Option<(u64, Option<u64>)>,
+    ) -> Result<Result<ureq::Response, ureq::Error>> {
+        let mut authenticated = false;
+        loop {
+            let url = self.url(path);
+            let mut request = self.agent.get(&url).set("Accept", accept);
+            if let Some(authorization) = &self.authorization {
+                request = request.set("Authorization", authorization);
+            }
+            match range {
+                Some((start, Some(end))) => {
+                    request = request.set("Range", &format!("bytes={start}-{end}"));
+                }
+                Some((start, None)) => {
+                    request = request.set("Range", &format!("bytes={start}-"));
+                }
+                None => {}
+            }
+
+            let result = request.call();
+            let http_fallback = std::mem::take(&mut self.http_fallback);
+            match result {
+                Err(ureq::Error::Transport(error)) if http_fallback => {
+                    log::warn!("{url}: {error}; trying plain HTTP (insecure registry)");
+                    self.base_url = self.base_url.replacen("https:", "http:", 1);
+                }
+                Err(ureq::Error::Status(401, response)) if !authenticated => {
+                    if self.authorization.take().is_some() {
+                        log::debug!("{url}: token rejected, authenticating again");
+                    }
+                    let challenge = response.header("WWW-Authenticate").unwrap_or_default();
+                    self.authorization = Some(self.authenticate(challenge)?);
+                    authenticated = true;
+                }
+                result => return Ok(result),
+            }
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v2/{}/{}", self.base_url, self.repository, path)
+    }
+
+    /// `Authorization` header answering `challenge`: a bearer token, obtained with the
+    /// registry's credentials if there are any, or basic authentication.
+    fn authenticate(&self, challenge: &str) -> Result<String> {
+        let credentials = self
+            .credentials
+            .lookup(&self.registry)
+            .with_context(|| format!("Failed to look up credentials for {}", self.registry))?;
+        let is_basic = challenge
+            .trim()
+            .get(..6)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("basic "));
+        if is_basic {
+            return credentials
+                .map(|credentials| credentials.basic_authorization())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Registry {} requires a login; run `docker login {}` or pass --username and --password-stdin",
+                        self.registry,
+                        self.registry
+                    )
+                });
+        }
+        let token = self.fetch_token(challenge, credentials.as_ref())?;
+        Ok(format!("Bearer {token}"))
+    }
+
+    /// Obtains a pull token for a `Bearer` challenge, anonymously without `credentials`.
+    fn fetch_token(&self, challenge: &str, credentials: Option<&Credentials>) -> Result<String> {
+        let params = parse_bearer_challenge(challenge).ok_or_else(|| {
+            anyhow!("Registry requires authentication that is not supported: '{challenge}'")
+        })?;
+        let realm = params
+            .iter()
+            .find(|(key, _)| key == "realm")
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| anyhow!("Authentication challenge without realm: '{challenge}'"))?;
+
+        let mut request = self.agent.get(realm);
+        let mut has_scope = false;
+        for (key, value) in &params {
+            if key == "service" || key == "scope" {
+                has_scope |= key == "scope";
+                request = request.query(key, value);
+            }
+        }
+        if !has_scope {
+            request = request.query("scope", &format!("repository:{}:pull", self.repository));
+        }
+        if let Some(credentials) = credentials {
+            request = request.set("Authorization", &credentials.basic_authorization());
+        }
+
+        let response = request.call().with_context(|| {
+            let hint = if credentials.is_none() {
+                format!(
+                    " (no credentials for {}; run `docker login` or pass --username and --password-stdin)",
+                    self.registry
+                )
+            } else {
+                String::new()
+            };
+            format!("Failed to obtain registry token from {realm}{hint}")
+        })?;
+        let response: Value = serde_json::from_reader(response.into_reader())
+            .context("Failed to parse registry token response")?;
+        response["token"]
+            .as_str()
+            .or_else(|| response["access_token"].as_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Registry token response contains no token"))
+    }
+
+    fn get_json(&mut self, path: &str, accept: &str) -> Result<(Value, Option<String>)> {
+        let response = self.get(path, accept)?;
+        let digest = response.header("Docker-Content-Digest").map(str::to_string);
+        let body: Value = serde_json::from_reader(response.into_reader())
+            .with_context(|| format!("Failed to parse registry response for {path}"))?;
+        Ok((body, digest))
+    }
+
+    /// GET like [`RegistryClient::get`], `None` if the registry answers 404.
+    fn find(&mut self, path: &str, accept: &str) -> Result<Option<ureq::Response>> {
+        let url = self.url(path);
+        match self.send(path, accept, None)? {
+            Ok(response) => Ok(Some(response)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(ureq::Error::Status(code, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                bail!("Registry returned HTTP {code} for {url}: {}", body.trim());
+            }
+            Err(e) => Err(e).with_context(|| format!("Request to {url} failed")),
+        }
+    }
+
+    /// JSON document at `path`, `None` if there is none.
+    fn find_json(&mut self, path: &str, accept: &str) -> Result<Option<Value>> {
+        let Some(response) = self.find(path, accept)? else {
+            return Ok(None);
+        };
+        let body = serde_json::from_reader(response.into_reader())
+            .with_context(|| format!("Failed to parse registry response for {path}"))?;
+        Ok(Some(body))
+    }
+
+    /// Manifest `reference` (tag or digest) as served and parsed, with its digest when
+    /// known; `None` if there is none.
+    fn find_manifest(&mut self, reference: &str) -> Result<Option<ServedManifest>> {
+        let path = format!("manifests/{reference}");
+        let Some(response) = self.find(&path, MANIFEST_MEDIA_TYPES)? else {
+            return Ok(None);
+        };
+        let digest = response.header("Docker-Content-Digest").map(str::to_string);
+        let mut raw = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut raw)
+            .with_context(|| format!("Failed to read registry response for {path}"))?;
+        let manifest = serde_json::from_slice(&raw)
+            .with_context(|| format!("Failed to parse registry response for {path}"))?;
+        Ok(Some(ServedManifest {
+            raw,
+            manifest,
+            digest,
+        }))
+    }
+
+    /// Fetches the image manifest for `platform`, following an index if necessary.
+    fn resolve_manifest(
+        &mut self,
+        reference: &ImageReference,
+        platform: &Platform,
+    ) -> Result<ResolvedManifest> {
+        let path = format!("manifests/{}", reference.reference);
+        let (manifest, digest) = self.get_json(&path, MANIFEST_MEDIA_TYPES)?;
+        let digest = digest.or_else(|| reference.is_digest().then(|| reference.reference.clone()));
+
+        if manifest.get("manifests").is_none() {
+            return Ok(ResolvedManifest {
+                manifest,
+                digest,
+                attestation_manifest: None,
+            });
+        }
+
+        let descriptor = select_platform_manifest(&manifest, platform)?;
+        let digest = descriptor["digest"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Image index entry without digest"))?
+            .to_string();
+        let attestation_manifest = attestation_manifest(&manifest, &digest);
+        let (manifest, _) = self.get_json(&format!("manifests/{digest}"), MANIFEST_MEDIA_TYPES)?;
+        Ok(ResolvedManifest {
+            manifest,
+            digest: Some(digest),
+            attestation_manifest,
+        })
+    }
+
+    /// Downloads blob `digest` into `cache` (unless already there) and returns its path.
+    ///
+    /// Transient failures (connection errors, HTTP 429/5xx, truncated bodies) are retried,
+    /// resuming from the bytes already on disk.
+    fn download_blob(
+        &mut self,
+        cache: &BlobCache,
+        digest: &str,
+        progress_bar: Option<&ProgressBar>,
+    ) -> Result<PathBuf> {
+        if let Some(path) = cache.get(digest)? {
+            if let Some(progress_bar) = progress_bar {
+                progress_bar.inc(fs::metadata(&path).map(|m| m.len()).unwrap_or(0));
+            }
+            return Ok(path);
+        }
+
+        let partial = cache.partial_path(digest)?;
+        let mut attempt = 1;
+        loop {
+            match self.download_attempt(digest, &partial, progress_bar)? {
+                Attempt::Done => break,
+                Attempt::Retry(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                    log::warn!("Download of {digest} interrupted ({e:#}), resuming...");
+                    thread::sleep(RETRY_DELAY * attempt);
+                    attempt += 1;
+                }
+                Attempt::Retry(e) => {
+                    return Err(e.context(format!(
+                        "Failed to download {digest} after {DOWNLOAD_ATTEMPTS} attempts"
+                    )))
+                }
+            }
+        }
+
+        cache.complete(digest)
+    }
+
+    /// Bytes `start..=end` of blob `digest`.
+    ///
+    /// # Errors
+    /// - The request fails, or the registry doesn't serve byte ranges.
+    fn blob_range(&mut self, digest: &str, start: u64, end: u64) -> Result<Vec<u8>> {
+        let path = format!("blobs/{digest}");
+        let url = self.url(&path);
+        let response = match self.send(&path, "*/*", Some((start, Some(end))))? {
+            Ok(response) => response,
+            Err(ureq::Error::Status(code, _)) => {
+                bail!("Registry returned HTTP {code} for a range of {url}")
+            }
+            Err(e) => return Err(e).with_context(|| format!("Request to {url} failed")),
+        };
+        if response.status() != 206 {
+            bail!("Registry doesn't serve byte ranges of {url}");
+        }
+        let mut bytes = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("Failed to read a range of {url}"))?;
+        Ok(bytes)
+    }
+
+    /// The TOC JSON of eStargz layer `digest` of `size` bytes, read from its footer and
+    /// TOC member only, and checked against `toc_digest`.
+    fn fetch_toc(&mut self, digest: &str, size: Option<u64>, toc_digest: &str) -> Result<Vec<u8>> {
+        let size = size
+            .filter(|&size| size >= estargz::FOOTER_SIZE)
+            .ok_or_else(|| anyhow!("Layer {digest} has no usable size"))?;
+        let footer = self.blob_range(digest, size - estargz::FOOTER_SIZE, size - 1)?;
+        let offset = estargz::toc_offset(&footer)
+            .filter(|&offset| offset < size)
+            .ok_or_else(|| anyhow!("Layer {digest} has no eStargz footer"))?;
+        let member = self.blob_range(digest, offset, size - 1)?;
+        let json = estargz::read_toc_json(&member[..])?;
+        estargz::verify_toc_digest(toc_digest, &json)?;
+        Ok(json)
+    }
+
+    /// One GET of the rest of `digest` into `partial`.
+    fn download_attempt(
+        &mut self,
+        digest: &str,
+        partial: &Path,
+        progress_bar: Option<&ProgressBar>,
+    ) -> Result<Attempt> {
+        let offset = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+        let path = format!("blobs/{digest}");
+        let url = self.url(&path);
+
+        let response = match self.send(&path, "*/*", (offset > 0).then_some((offset, None)))? {
+            Ok(response) => response,
+            // The partial file is already complete (or bogus, which verification catches)
+            Err(ureq::Error::Status(416, _)) if offset > 0 => return Ok(Attempt::Done),
+            Err(ureq::Error::Status(code, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                let error = anyhow!("Registry returned HTTP {code} for {url}: {}", body.trim());
+                return if code == 429 || code >= 500 {
+                    Ok(Attempt::Retry(error))
+                } else {
+                    Err(error)
+                };
+            }
+            Err(e) => {
+                return Ok(Attempt::Retry(
+                    anyhow!(e).context(format!("Request to {url} failed")),
+                ))
+            }
+        };
+
+        // Servers ignoring the Range header send the whole blob again
+        let resumed = offset > 0 && response.status() == 206;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(resumed)
+            .write(true)
+            .truncate(!resumed)
+            .open(partial)
+            .with_context(|| format!("Failed to open {}", partial.display()))?;
+        if let Some(progress_bar) = progress_bar {
+            if resumed {
+                progress_bar.inc(offset);
+            }
+        }
+
+        let mut body = response.into_reader();
+        let copied = match progress_bar {
+            Some(progress_bar) => io::copy(&mut progress_bar.wrap_read(&mut body), &mut file),
+            None => io::copy(&mut body, &mut file),
+        };
+        match copied {
+            Ok(_) => Ok(Attempt::Done),
+            Err(e) => Ok(Attempt::Retry(
+                anyhow!(e).context(format!("Download from {url} was interrupted")),
+            )),
+        }
+    }
+}
+
+/// A manifest as the registry served it.
+struct ServedManifest {
+    raw: Vec<u8>,
+    manifest: Value,
+    /// `Docker-Content-Digest` of the response
+    digest: Option<String>,
+}
+
+/// The image manifest picked for a platform.
+struct ResolvedManifest {
+    manifest: Value,
+    /// Digest of `manifest`, when known
+    digest: Option<String>,
+    /// Digest of the attestation manifest the image index lists for `manifest`
+    attestation_manifest: Option<String>,
+}
+
+/// Outcome of a single download request.
+enum Attempt {
+    Done,
+    Retry(anyhow::Error),
+}
+
+/// Parses `Bearer key="value",…` into key/value pairs.
+fn parse_bearer_challenge(challenge: &str) -> Option<Vec<(String, String)>> {
+    let (scheme, params) = challenge.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    let mut pairs = Vec::new();
+    let mut rest = params.trim();
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let key = key.trim().to_string();
+        let value = value.trim_start();
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        pairs.push((key, value.to_string()));
+        rest = remainder.trim_start_matches([',', ' ']);
+    }
+    Some(pairs)
+}
+
+/// Digest of the attestation manifest BuildKit added to `index` for the image `digest`
+/// (SLSA provenance, SBOMs), if any.
+fn attestation_manifest(index: &Value, digest: &str) -> Option<String> {
+    index["manifests"].as_array()?.iter().find(|descriptor| {
+        let annotations = &descriptor["annotations"];
+        annotations["vnd.docker.reference.type"] == "attestation-manifest"
+            && annotations["vnd.docker.reference.digest"] == digest
+    })?["digest"]
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Picks the descriptor for `platform` from an image index / manifest list.
+fn select_platform_manifest<'a>(index: &'a Value, platform: &Platform) -> Result<&'a Value> {
+    let manifests = index["manifests"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Invalid image index: 'manifests' is not a list"))?;
+
+    manifests
+        .iter()
+        .find(|descriptor| {
+            let p = &descriptor["platform"];
+            platform.matches(
+                p["os"].as_str().unwrap_or_default(),
+                p["architecture"].as_str().unwrap_or_default(),
+                p["variant"].as_str(),
+            )
+        })
+        .ok_or_else(|| {
+            let available: Vec<String> = manifests
+                .iter()
+                .filter_map(|descriptor| {
+                    let p = &descriptor["platform"];
+                    Some(format!(
+                        "{}/{}",
+                        p["os"].as_str()?,
+                        p["architecture"].as_str()?
+                    ))
+                })
+                .collect();
+            anyhow!(
+                "Image has no manifest for platform {platform} (available: {})",
+                available.join(", ")
+            )
+        })
+}
+
+/// Wraps a layer blob in a decompressor based on its magic bytes.
+fn decompress<'a>(blob: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(blob);
+    let magic = reader.fill_buf().context("Failed to read layer")?;
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        bail!("zstd compressed layers are not supported")
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn append_bytes<W: io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    data: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, path, data)
+        .with_context(|| format!("Failed to write {path} into image tarball"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extracted_image::ExtractedImage;
+    use crate::git::GitRepo;
+    use crate::processor::ImageProcessor;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    type Routes = HashMap<String, (u16, Vec<(String, String)>, Vec<u8>)>;
+
+    /// Route header (not sent) marking a blob whose first download breaks off
+    const FLAKY: &str = "x-mock-flaky";
+
+    /// Serves canned responses on localhost. Paths under `/v2/` require a bearer token
+    /// obtained from `/token`, like Docker Hub; a `/token` route hands out another one,
+    /// which is rejected.
+    fn serve(routes: Routes) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let realm = format!("http://{address}/token");
+
+        std::thread::spawn(move || {
+            let mut served_flaky = std::collections::HashSet::new();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut authorized = false;
+                let mut range_start = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let line = line.trim().to_ascii_lowercase();
+                    authorized |= line.starts_with("authorization: bearer t0k3n");
+                    if let Some(range) = line.strip_prefix("range: bytes=") {
+                        range_start = range.trim_end_matches('-').parse::<usize>().ok();
+                    }
+                }
+
+                let target = request_line.split(' ').nth(1).unwrap_or("/");
+                let path = target.split('?').next().unwrap();
+                let (status, headers, body) = if path.starts_with("/v2/") && !authorized {
+                    let challenge = format!(
+                        "Bearer realm=\"{realm}\",service=\"mock\",scope=\"repository:test/app:pull\""
+                    );
+                    (
+                        401,
+                        vec![("WWW-Authenticate".to_string(), challenge)],
+                        Vec::new(),
+                    )
+                } else if path == "/token" {
+                    routes.get(path).cloned().unwrap_or((
+                        200,
+                        Vec::new(),
+                        br#"{"token":"t0k3n"}"#.to_vec(),
+                    ))
+                } else {
+                    routes
+                        .get(path)
+                        .cloned()
+                        .unwrap_or((404, Vec::new(), b"not found".to_vec()))
+                };
+
+                // Flaky routes cut the first response off halfway and then only serve
+                // ranged requests, so a client has to resume
+                let flaky = headers.iter().any(|(name, _)| name == FLAKY);
+                let (status, body, truncate) = match (flaky, range_start) {
+                    (true, None) if served_flaky.insert(path.to_string()) => {
+                        (status, body.clone(), body.len() / 2)
+                    }
+                    (true, None) => (500, b"range required".to_vec(), usize::MAX),
+                    (_, Some(start)) if status == 200 && start <= body.len() => {
+                        (206, body[start..].to_vec(), usize::MAX)
+                    }
+                    _ => (status, body, usize::MAX),
+                };
+
+                let mut response = format!(
+                    "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    body.len()
+                );
+                for (name, value) in headers.iter().filter(|(name, _)| name != FLAKY) {
+                    response.push_str(&format!("{name}: {value}\r\n"));
+                }
+                response.push_str("\r\n");
+                let _ = stream.write_all(response.as_bytes());
+                let _ = stream.write_all(&body[..truncate.min(body.len())]);
+            }
+        });
+
+        address
+    }
+
+    fn gzip_layer(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn json_route(
+        body: Value,
+        headers: Vec<(String, String)>,
+    ) -> (u16, Vec<(String, String)>, Vec<u8>) {
+        (200, headers, serde_json::to_vec(&body).unwrap())
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        let hex: String = Sha256::digest(data)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("sha256:{hex}")
+    }
+
+    /// Adds a blob route and returns the blob digest
+    fn blob_route(routes: &mut Routes, data: Vec<u8>) -> String {
+        let digest = sha256(&data);
+        routes.insert(
+            format!("/v2/test/app/blobs/{digest}"),
+            (200, Vec::new(), data),
+        );
+        digest
+    }
+
+    /// A two-layer image behind a two-platform index at `test/app:latest`
+    fn mock_routes() -> Routes {
+        let mut routes = Routes::new();
+
+        let config = json!({
+            "architecture": Platform::host().architecture,
+            "os": "linux",
+            "config": {"Env": ["PATH=/bin"]},
+            "rootfs": {"type": "layers", "diff_ids": [
+                format!("sha256:{}", "a".repeat(64)),
+                format!("sha256:{}", "b".repeat(64)),
+            ]},
+            "history": [
+                {"created": "2024-01-01T00:00:00Z", "created_by": "ADD base /"},
+                {"created": "2024-01-02T00:00:00Z", "created_by": "RUN echo hi"},
+            ],
+        });
+        let mut windows_config = config.clone();
+        windows_config["os"] = json!("windows");
+        windows_config["architecture"] = json!("amd64");
+
+        let config_digest = blob_route(&mut routes, serde_json::to_vec(&config).unwrap());
+        let windows_config_digest =
+            blob_route(&mut routes, serde_json::to_vec(&windows_config).unwrap());
+        let layer1 = blob_route(&mut routes, gzip_layer(&[("etc/hostname", b"base")]));
+        let layer2 = blob_route(
+            &mut routes,
+            gzip_layer(&[("etc/.wh.hostname", b""), ("hi", b"hi")]),
+        );
+
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {"digest": config_digest, "size": 1},
+            "layers": [
+                {"digest": layer1, "size": 10},
+                {"digest": layer2, "size": 10},
+            ],
+        });
+        let mut windows_manifest = manifest.clone();
+        windows_manifest["config"]["digest"] = json!(windows_config_digest);
+
+        let index = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {"digest": format!("sha256:{}", "e".repeat(64)),
+                 "platform": {"os": "windows", "architecture": "amd64"}},
+                {"digest": format!("sha256:{}", "d".repeat(64)),
+                 "platform": {"os": "linux", "architecture": Platform::host().architecture}},
+            ],
+        });
+
+        routes.insert(
+            "/v2/test/app/manifests/latest".to_string(),
+            json_route(index, Vec::new()),
+        );
+        routes.insert(
+            format!("/v2/test/app/manifests/sha256:{}", "e".repeat(64)),
+            json_route(windows_manifest, Vec::new()),
+        );
+        routes.insert(
+            format!("/v2/test/app/manifests/sha256:{}", "d".repeat(64)),
+            json_route(manifest, Vec::new()),
+        );
+        routes
+    }
+
+    fn mock_registry() -> String {
+        serve(mock_routes())
+    }
+
+    /// A registry source with its own empty blob cache
+    fn registry_source() -> (RegistrySource, tempfile::TempDir) {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let source = RegistrySource::new()
+            .unwrap()
+            .with_blob_cache(BlobCache::new(cache_dir.path()));
+        (source, cache_dir)
+    }
+
+    #[test]
+    fn test_parse_image_reference() {
+        let parse = |s: &str| {
+            let r = ImageReference::parse(s).unwrap();
+            (r.registry, r.repository, r.reference)
+        };
+        let owned = |a: &str, b: &str, c: &str| (a.to_string(), b.to_string(), c.to_string());
+
+        assert_eq!(
+            parse("alpine"),
+            owned("docker.io", "library/alpine", "latest")
+        );
+        assert_eq!(
+            parse("nginx:1.25"),
+            owned("docker.io", "library/nginx", "1.25")
+        );
+        assert_eq!(parse("user/app:v1"), owned("docker.io", "user/app", "v1"));
+        assert_eq!(
+            parse("ghcr.io/org/team/app:2.0"),
+            owned("ghcr.io", "org/team/app", "2.0")
+        );
+        assert_eq!(
+            parse("localhost:5000/app"),
+            owned("localhost:5000", "app", "latest")
+        );
+        assert_eq!(
+            parse("alpine@sha256:abcd"),
+            owned("docker.io", "library/alpine", "sha256:abcd")
+        );
+        assert_eq!(
+            ImageReference::parse("alpine").unwrap().to_string(),
+            "docker.io/library/alpine:latest"
+        );
+        assert_eq!(
+            ImageReference::parse("localhost:5000/app@sha256:abcd")
+                .unwrap()
+                .to_string(),
+            "localhost:5000/app@sha256:abcd"
+        );
+        assert!(ImageReference::parse("alpine:").is_err());
+        assert!(ImageReference::parse("").is_err());
+    }
+
+    #[test]
+    fn test_base_url() {
+        let url = |s: &str| ImageReference::parse(s).unwrap().base_url();
+        assert_eq!(url("alpine"), "https://registry-1.docker.io");
+        assert_eq!(url("ghcr.io/org/app"), "https://ghcr.io");
+        assert_eq!(url("localhost:5000/app"), "http://localhost:5000");
+        assert_eq!(url("127.0.0.1:5000/app"), "http://127.0.0.1:5000");
+    }
+
+    #[test]
+    fn test_basic_authentication() {
+        let reference = ImageReference::parse("registry.example.com/team/app:1").unwrap();
+        let agent = ureq::AgentBuilder::new().build();
+        let store = CredentialStore::new().with_docker_config(Some(PathBuf::from("/nonexistent")));
+
+        let client = RegistryClient::new(agent.clone(), &reference, store.clone());
+        let error = client.authenticate("Basic realm=\"Registry\"").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("registry.example.com requires a login"));
+
+        let store = store.with_credentials(Some(Credentials::new("user", "pass")));
+        let client = RegistryClient::new(agent, &reference, store);
+        assert_eq!(
+            client.authenticate("Basic realm=\"Registry\"").unwrap(),
+            "Basic dXNlcjpwYXNz"
+        );
+    }
+
+    #[test]
+    fn test_expired_token_is_renewed() {
+        let address = mock_registry();
+        let reference = ImageReference::parse(&format!("{address}/test/app:latest")).unwrap();
+        let store = CredentialStore::new().with_docker_config(Some(PathBuf::from("/nonexistent")));
+        let mut client = RegistryClient::new(ureq::AgentBuilder::new().build(), &reference, store);
+
+        // A token from an earlier request that the registry no longer accepts
+        client.authorization = Some("Bearer expired".to_string());
+        client
+            .get("manifests/latest", MANIFEST_MEDIA_TYPES)
+            .unwrap();
+        assert_eq!(client.authorization.as_deref(), Some("Bearer t0k3n"));
+
+        // The renewed token is rejected as well: no second attempt
+        let mut routes = mock_routes();
+        routes.insert(
+            "/token".to_string(),
+            json_route(serde_json::json!({"token": "stale"}), Vec::new()),
+        );
+        let address = serve(routes);
+        let reference = ImageReference::parse(&format!("{address}/test/app:latest")).unwrap();
+        let store = CredentialStore::new().with_docker_config(Some(PathBuf::from("/nonexistent")));
+        let mut client = RegistryClient::new(ureq::AgentBuilder::new().build(), &reference, store);
+        client.authorization = Some("Bearer expired".to_string());
+        let error = client
+            .get("manifests/latest", MANIFEST_MEDIA_TYPES)
+            .unwrap_err();
+        assert!(error.to_string().contains("HTTP 401"), "{error}");
+    }
+
+    #[test]
+    fn test_parse_bearer_challenge() {
+        let params = parse_bearer_challenge(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/alpine:pull""#,
+        )
+        .unwrap();
+        assert_eq!(
+            params,
+            vec![
+                (
+                    "realm".to_string(),
+                    "https://auth.docker.io/token".to_string()
+                ),
+                ("service".to_string(), "registry.docker.io".to_string()),
+                (
+                    "scope".to_string(),
+                    "repository:library/alpine:pull".to_string()
+                ),
+            ]
+        );
+        assert!(parse_bearer_challenge(r#"Basic realm="x""#).is_none());
+    }
+
+    #[test]
+    fn test_select_platform_manifest() {
+        let index = json!({"manifests": [
+            {"digest": "sha256:1", "platform": {"os": "linux", "architecture": "amd64"}},
+            {"digest": "sha256:2", "platform": {"os": "linux", "architecture": "arm", "variant": "v7"}},
+        ]});
+        let platform = |arch: &str, variant: Option<&str>| Platform {
+            os: "linux".to_string(),
+            architecture: arch.to_string(),
+            variant: variant.map(str::to_string),
+        };
+
+        let found = select_platform_manifest(&index, &platform("arm", Some("v7"))).unwrap();
+        assert_eq!(found["digest"], "sha256:2");
+        let found = select_platform_manifest(&index, &platform("amd64", None)).unwrap();
+        assert_eq!(found["digest"], "sha256:1");
+
+        let err = select_platform_manifest(&index, &platform("s390x", None)).unwrap_err();
+        assert!(err.to_string().contains("linux/amd64, linux/arm"));
+    }
+
+    #[test]
+    fn test_pull_from_mock_registry() {
+        let address = mock_registry();
+        let image = format!("{address}/test/app:latest");
+        let notifier = Notifier::new(0);
+
+        let (source, _cache_dir) = registry_source();
+        let (tarball, _temp_dir) = source.get_image_tarball(&image, &notifier).unwrap();
+
+        let extracted = ExtractedImage::from_tarball(&tarball, &notifier).unwrap();
+        let metadata = extracted.metadata(&image).unwrap();
+        assert_eq!(metadata.id, format!("sha256:{}", "d".repeat(64)));
+
+        let layers = extracted.layers().unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].digest, format!("sha256:{}", "a".repeat(64)));
+        assert_eq!(layers[1].command, "RUN echo hi");
+
+        let rootfs = tempfile::tempdir().unwrap();
+        for layer in &layers {
+            extracted
+                .extract_layer_to(layer.tarball_path.as_ref().unwrap(), rootfs.path())
+                .unwrap();
+        }
+        assert!(!rootfs.path().join("etc/hostname").exists());
+        assert_eq!(fs::read(rootfs.path().join("hi")).unwrap(), b"hi");
+    }
+
+    #[test]
+    fn test_pull_requested_platform() {
+        let address = mock_registry();
+        let image = format!("{address}/test/app:latest");
+        let notifier = Notifier::new(0);
+
+        let (source, _cache_dir) = registry_source();
+        let source = source.with_platform(Some("windows/amd64".parse().unwrap()));
+        let (tarball, _temp_dir) = source.get_image_tarball(&image, &notifier).unwrap();
+
+        let extracted = ExtractedImage::from_tarball(&tarball, &notifier).unwrap();
+        let metadata = extracted.metadata(&image).unwrap();
+        assert_eq!(metadata.id, format!("sha256:{}", "e".repeat(64)));
+        assert_eq!(metadata.os, "windows");
+
+        let (source, _cache_dir) = registry_source();
+        let source = source.with_platform(Some("linux/s390x".parse().unwrap()));
+        let err = source.get_image_tarball(&image, &notifier).unwrap_err();
+        assert!(format!("{err:#}").contains("no manifest for platform linux/s390x"));
+    }
+
+    #[test]
+    fn test_convert_pinned_digest() {
+        let address = mock_registry();
+        let output = tempfile::tempdir().unwrap();
+
+        let (source, _cache_dir) = registry_source();
+        let processor = ImageProcessor::new(source, Notifier::new(0));
+        processor
+            .convert(
+                &format!("{address}/test/app@sha256:{}", "d".repeat(64)),
+                output.path(),
+            )
+            .unwrap();
+
+        let repo = GitRepo::open(output.path()).unwrap();
+        let branches = repo.get_all_branches().unwrap();
+        assert!(branches
+            .iter()
+            .any(|b| b.contains("-test-app#sha256-dddddddddddd#linux-")));
+        assert!(!branches.iter().any(|b| b.contains("latest")));
+    }
+
+    #[test]
+    fn test_attestations_are_committed() {
+        let mut routes = mock_routes();
+        let image_digest = format!("sha256:{}", "d".repeat(64));
+
+        // cosign signature under the sha256-<hex>.sig tag
+        let payload = br#"{"critical":{"image":{"docker-manifest-digest":"sha256:ddd"}}}"#;
+        let payload_digest = blob_route(&mut routes, payload.to_vec());
+        let signature = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "layers": [{
+                "mediaType": "application/vnd.dev.cosign.simplesigning.v1+json",
+                "digest": payload_digest,
+                "annotations": {"dev.cosignproject.cosign/signature": "MEUCIQ=="},
+            }],
+        });
+        let signature_bytes = serde_json::to_vec(&signature).unwrap();
+        routes.insert(
+            format!("/v2/test/app/manifests/sha256-{}.sig", "d".repeat(64)),
+            (200, Vec::new(), signature_bytes.clone()),
+        );
+
+        // An SBOM only known to the referrers API
+        let sbom_digest = blob_route(&mut routes, br#"{"spdxVersion":"SPDX-2.3"}"#.to_vec());
+        let sbom_manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "artifactType": "application/spdx+json",
+            "layers": [{"mediaType": "application/spdx+json", "digest": sbom_digest}],
+        });
+        let sbom_manifest_digest = format!("sha256:{}", "5".repeat(64));
+        routes.insert(
+            format!("/v2/test/app/manifests/{sbom_manifest_digest}"),
+            json_route(sbom_manifest, Vec::new()),
+        );
+        routes.insert(
+            format!("/v2/test/app/referrers/{image_digest}"),
+            json_route(
+                json!({"schemaVersion": 2, "manifests": [{"digest": sbom_manifest_digest}]}),
+                Vec::new(),
+            ),
+        );
+
+        let address = serve(routes);
+        let output = tempfile::tempdir().unwrap();
+        let (source, _cache_dir) = registry_source();
+        ImageProcessor::new(source, Notifier::new(0))
+            .convert(&format!("{address}/test/app:latest"), output.path())
+            .unwrap();
+
+        let attestations = output.path().join(ATTESTATIONS_DIR);
+        assert_eq!(
+            fs::read(attestations.join("cosign-sig/manifest.json")).unwrap(),
+            signature_bytes
+        );
+        assert_eq!(
+            fs::read(attestations.join(format!(
+                "cosign-sig/{}.json",
+                digest_hex(&payload_digest).unwrap()
+            )))
+            .unwrap(),
+            payload
+        );
+        let sbom_dir = attestations.join(format!("referrers/{}", "5".repeat(64)));
+        assert!(sbom_dir.join("manifest.json").exists());
+        assert!(sbom_dir
+            .join(format!("{}.json", digest_hex(&sbom_digest).unwrap()))
+            .exists());
+        assert!(!attestations.join("cosign-att").exists());
+
+        // Committed with the metadata
+        let repo = GitRepo::open(output.path()).unwrap();
+        let branch = repo
+            .get_all_branches()
+            .unwrap()
+            .into_iter()
+            .find(|b| b.contains("test-app"))
+            .unwrap();
+        let commits = repo.get_branch_commits(&branch).unwrap();
+        assert!(repo
+            .read_file_from_commit(
+                *commits.last().unwrap(),
+                "attestations/cosign-sig/manifest.json"
+            )
+            .is_ok());
+        assert!(repo
+            .read_file_from_commit(commits[0], "attestations/cosign-sig/manifest.json")
+            .is_err());
+    }
+
+    #[test]
+    fn test_buildkit_provenance() {
+        let mut routes = mock_routes();
+        let image_digest = format!("sha256:{}", "d".repeat(64));
+
+        let statement = json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "predicateType": "https://slsa.dev/provenance/v0.2",
+            "predicate": {
+                "builder": {"id": ""},
+                "invocation": {
+                    "configSource": {"entryPoint": "Dockerfile.release"},
+                    "parameters": {"args": {"build-arg:VERSION": "1.2.3"}},
+                },
+                "metadata": {"https://mobyproject.org/buildkit@v1#metadata": {"vcs": {
+                    "source": "https://github.com/example/app",
+                    "revision": "0123456789abcdef",
+                }}},
+            },
+        });
+        let statement_digest = blob_route(&mut routes, serde_json::to_vec(&statement).unwrap());
+        let attestation_manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "layers": [{
+                "mediaType": "application/vnd.in-toto+json",
+                "digest": statement_digest,
+                "annotations": {"in-toto.io/predicate-type": "https://slsa.dev/provenance/v0.2"},
+            }],
+        });
+        let attestation_digest = format!("sha256:{}", "7".repeat(64));
+        routes.insert(
+            format!("/v2/test/app/manifests/{attestation_digest}"),
+            json_route(attestation_manifest, Vec::new()),
+        );
+        routes.insert(
+            "/v2/test/app/manifests/latest".to_string(),
+            json_route(
+                json!({
+                    "schemaVersion": 2,
+                    "mediaType": "application/vnd.oci.image.index.v1+json",
+                    "manifests": [
+                        {"digest": image_digest,
+                         "platform": {"os": "linux", "architecture": Platform::host().architecture}},
+                        {"digest": attestation_digest,
+                         "platform": {"os": "unknown", "architecture": "unknown"},
+                         "annotations": {
+                             "vnd.docker.reference.type": "attestation-manifest",
+                             "vnd.docker.reference.digest": image_digest,
+                         }},
+                    ],
+                }),
+                Vec::new(),
+            ),
+        );
+
+        let address = serve(routes);
+        let output = tempfile::tempdir().unwrap();
+        let (source, _cache_dir) = registry_source();
+        ImageProcessor::new(source, Notifier::new(0))
+            .convert(&format!("{address}/test/app:latest"), output.path())
+            .unwrap();
+
+        assert!(output
+            .path()
+            .join(ATTESTATIONS_DIR)
+            .join("buildkit/manifest.json")
+            .exists());
+        let image_md = fs::read_to_string(output.path().join("Image.md")).unwrap();
+        assert!(image_md.contains("## Build Provenance"));
+        assert!(image_md.contains("| `VERSION` | `1.2.3` |"));
+
+        let repo = GitRepo::open(output.path()).unwrap();
+        let branch = repo
+            .get_all_branches()
+            .unwrap()
+            .into_iter()
+            .find(|b| b.contains("test-app"))
+            .unwrap();
+        let tip = *repo.get_branch_commits(&branch).unwrap().last().unwrap();
+        let message = repo.commit_message(tip).unwrap();
+        assert!(message.contains("\nBuild-Source: https://github.com/example/app\n"));
+        assert!(message.contains("\nBuild-Revision: 0123456789abcdef\n"));
+        assert!(message.contains("\nBuild-Dockerfile: Dockerfile.release\n"));
+    }
+
+    #[test]
+    fn test_list_platforms() {
+        let address = mock_registry();
+        let (source, _cache_dir) = registry_source();
+        let platforms = source
+            .platforms(&format!("{address}/test/app:latest"), &Notifier::new(0))
+            .unwrap();
+        assert_eq!(
+            platforms,
+            ["windows/amd64".parse().unwrap(), Platform::host()]
+        );
+    }
+
+    #[test]
+    fn test_convert_all_platforms() {
+        let address = mock_registry();
+        let output = tempfile::tempdir().unwrap();
+
+        let (source, _cache_dir) = registry_source();
+        let mut processor = ImageProcessor::new(source, Notifier::new(0));
+        let (platforms, _) = processor
+            .convert_all_platforms(&format!("{address}/test/app:latest"), output.path())
+            .unwrap();
+        assert_eq!(platforms.len(), 2);
+
+        let repo = GitRepo::open(output.path()).unwrap();
+        let mut branches = repo.get_all_branches().unwrap();
+        branches.retain(|branch| branch != crate::repo_readme::README_BRANCH);
+        assert_eq!(branches.len(), 2);
+        assert!(branches
+            .iter()
+            .any(|b| b.contains("#windows-amd64#eeeeeeeeeeee")));
+        assert!(branches.iter().any(|b| b.contains("#linux-")));
+    }
+
+    #[test]
+    fn test_convert_all_platforms_of_two_images() {
+        let mut routes = mock_routes();
+        // The same index with the host platform first, so the foreign one is converted last
+        let (_, _, index) = &routes["/v2/test/app/manifests/latest"];
+        let mut index: Value = serde_json::from_slice(index).unwrap();
+        index["manifests"].as_array_mut().unwrap().reverse();
+        routes.insert(
+            "/v2/test/app/manifests/reversed".to_string(),
+            json_route(index, Vec::new()),
+        );
+        let address = serve(routes);
+        let output = tempfile::tempdir().unwrap();
+
+        let (source, _cache_dir) = registry_source();
+        let mut processor = ImageProcessor::new(source, Notifier::new(0));
+        let (platforms, _) = processor
+            .convert_all_platforms(&format!("{address}/test/app:reversed"), output.path())
+            .unwrap();
+        assert_eq!(platforms.len(), 2);
+
+        // A single-platform image after it is converted for the host, not for windows/amd64
+        let single = format!("{address}/test/app@sha256:{}", "d".repeat(64));
+        let (platforms, summaries) = processor
+            .convert_all_platforms(&single, output.path())
+            .unwrap();
+        assert!(platforms.is_empty());
+        assert!(!summaries[0].branch.contains("windows"));
+    }
+
+    #[test]
+    fn test_metadata_only_conversion() {
+        let routes = mock_routes();
+        // Layer blobs are unavailable; only manifests and configs can be fetched
+        let without_layers = routes
+            .clone()
+            .into_iter()
+            .filter(|(_, (_, _, body))| !body.starts_with(&[0x1f, 0x8b]))
+            .collect();
+        let image = format!("{}/test/app:latest", serve(without_layers));
+        let output = tempfile::tempdir().unwrap();
+
+        let (source, _cache_dir) = registry_source();
+        let processor = ImageProcessor::new(source.with_metadata_only(true), Notifier::new(0));
+        processor.convert(&image, output.path()).unwrap();
+
+        let repo = GitRepo::open(output.path()).unwrap();
+        let branch = repo
+            .get_all_branches()
+            .unwrap()
+            .into_iter()
+            .find(|branch| branch.ends_with("#metadata-only"))
+            .unwrap();
+        let commits = repo.get_branch_commits(&branch).unwrap();
+        assert_eq!(commits.len(), 3);
+        let image_md = repo
+            .read_file_from_commit(*commits.last().unwrap(), "Image.md")
+            .unwrap();
+        assert!(image_md.contains(&format!("sha256:{}", "b".repeat(64))));
+        assert!(!output.path().join("rootfs/hi").exists());
+
+        // A full conversion later gets its own history instead of reusing the
+        // commits that carry no files
+        let (source, _cache_dir) = registry_source();
+        let processor = ImageProcessor::new(source, Notifier::new(0));
+        processor
+            .convert(&format!("{}/test/app:latest", serve(routes)), output.path())
+            .unwrap();
+        let full_branch = repo
+            .get_all_branches()
+            .unwrap()
+            .into_iter()
+            .find(|b| b.contains("#linux-") && !b.ends_with("#metadata-only"))
+            .unwrap();
+        let full_commits = repo.get_branch_commits(&full_branch).unwrap();
+        assert_ne!(full_commits[0], commits[0]);
+        assert!(output.path().join("rootfs/hi").exists());
+    }
+
+    #[test]
+    fn test_interrupted_download_is_resumed() {
+        let mut routes = mock_routes();
+        for (path, (_, headers, body)) in routes.iter_mut() {
+            if path.contains("/blobs/") && body.starts_with(&[0x1f, 0x8b]) {
+                headers.push((FLAKY.to_string(), String::new()));
+            }
+        }
+        let image = format!("{}/test/app:latest", serve(routes));
+        let notifier = Notifier::new(0);
+
+        let (source, _cache_dir) = registry_source();
+        let (tarball, _temp_dir) = source.get_image_tarball(&image, &notifier).unwrap();
+        let extracted = ExtractedImage::from_tarball(&tarball, &notifier).unwrap();
+        assert_eq!(extracted.layers().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_cached_blobs_are_reused() {
+        let routes = mock_routes();
+        let image = format!("{}/test/app:latest", serve(routes.clone()));
+        let notifier = Notifier::new(0);
+
+        let (source, cache_dir) = registry_source();
+        source.get_image_tarball(&image, &notifier).unwrap();
+
+        // Same manifests, but every blob is gone from the registry
+        let manifests_only = routes
+            .into_iter()
+            .filter(|(path, _)| !path.contains("/blobs/"))
+            .collect();
+        let image = format!("{}/test/app:latest", serve(manifests_only));
+        let source = RegistrySource::new()
+            .unwrap()
+            .with_blob_cache(BlobCache::new(cache_dir.path()));
+        source.get_image_tarball(&image, &notifier).unwrap();
+    }
+
+    #[test]
+    fn test_prefetch_fills_the_cache() {
+        let routes = mock_routes();
+        let address = serve(routes.clone());
+        let images = [
+            format!("{address}/test/app:latest"),
+            format!("{address}/test/app:latest"),
+            format!("{address}/test/app:missing"),
+        ];
+
+        let (source, cache_dir) = registry_source();
+        let results = source.prefetch(&images, 2);
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(format!("{:#}", results[2].as_ref().unwrap_err()).contains("test/app:missing"));
+
+        // Converting needs the manifests only
+        let manifests_only = routes
+            .into_iter()
+            .filter(|(path, _)| !path.contains("/blobs/"))
+            .collect();
+        let image = format!("{}/test/app:latest", serve(manifests_only));
+        let source = RegistrySource::new()
+            .unwrap()
+            .with_blob_cache(BlobCache::new(cache_dir.path()));
+        source.get_image_tarball(&image, &Notifier::new(0)).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_blob_is_rejected() {
+        let mut routes = mock_routes();
+        for (path, (_, _, body)) in routes.iter_mut() {
+            if path.contains("/blobs/") && body.starts_with(&[0x1f, 0x8b]) {
+                body.push(0);
+            }
+        }
+        let image = format!("{}/test/app:latest", serve(routes));
+
+        let (source, cache_dir) = registry_source();
+        let err = source
+            .get_image_tarball(&image, &Notifier::new(0))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("Digest mismatch"));
+
+        // Nothing unverified is left behind in the cache
+        let cached: Vec<_> = fs::read_dir(cache_dir.path().join("sha256"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert!(cached.iter().all(|name| !name.ends_with(".partial")));
+    }
+
+    #[test]
+    fn test_missing_image_is_an_error() {
+        let address = mock_registry();
+        let (source, _cache_dir) = registry_source();
+        let err = source
+            .get_image_tarball(&format!("{address}/test/app:nope"), &Notifier::new(0))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("404"));
+    }
+}
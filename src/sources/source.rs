@@ -1,8 +1,12 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use std::io::Read;
 use std::path::PathBuf;
 use tempfile::TempDir;
 
+use super::Platform;
+use crate::disk_space::SizeEstimate;
 use crate::notifier::Notifier;
+use crate::workdir::Workdir;
 
 /// Source trait for getting OCI images from different container sources
 pub trait Source {
@@ -25,6 +29,91 @@ pub trait Source {
     /// Each source type implements its own naming strategy
     /// The os_arch and image_digest parameters are mandatory and provided by the processor after extracting metadata
     fn branch_name(&self, image_name: &str, os_arch: &str, image_digest: &str) -> String;
+
+    /// Optionally provides the image tarball as a stream instead of a file
+    /// When this returns `Some`, the processor unpacks the stream directly and
+    /// `get_image_tarball` is not called
+    fn image_stream(
+        &self,
+        _image_name: &str,
+        _notifier: &Notifier,
+    ) -> Result<Option<Box<dyn Read + '_>>> {
+        Ok(None)
+    }
+
+    /// Lists the platforms of a multi-platform image
+    /// Returns an empty list for single-platform images
+    fn platforms(&self, _image_name: &str, _notifier: &Notifier) -> Result<Vec<Platform>> {
+        bail!("The {} source cannot list image platforms", self.name())
+    }
+
+    /// Selects the platform fetched by subsequent `get_image_tarball` calls
+    fn select_platform(&mut self, _platform: Option<Platform>) -> Result<()> {
+        bail!("The {} source cannot select an image platform", self.name())
+    }
+
+    /// The platform this source was asked to fetch, if any
+    /// The processor verifies that the fetched image was built for it
+    fn platform(&self) -> Option<&Platform> {
+        None
+    }
+
+    /// Estimates the size of the image without fetching it, for the disk space check
+    /// Returns `None` when that isn't possible cheaply
+    fn estimate_image_size(&self, _image_name: &str) -> Option<SizeEstimate> {
+        None
+    }
+
+    /// Creates the temporary data of subsequent fetches (exported tarballs) in `workdir`
+    /// Sources that don't write any ignore it
+    fn set_workdir(&mut self, _workdir: Workdir) {}
+}
+
+/// Forwards to the boxed source, so the engine can be chosen at runtime
+impl<T: Source + ?Sized> Source for Box<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn get_image_tarball(
+        &self,
+        image_name: &str,
+        notifier: &Notifier,
+    ) -> Result<(PathBuf, Option<TempDir>)> {
+        (**self).get_image_tarball(image_name, notifier)
+    }
+
+    fn branch_name(&self, image_name: &str, os_arch: &str, image_digest: &str) -> String {
+        (**self).branch_name(image_name, os_arch, image_digest)
+    }
+
+    fn image_stream(
+        &self,
+        image_name: &str,
+        notifier: &Notifier,
+    ) -> Result<Option<Box<dyn Read + '_>>> {
+        (**self).image_stream(image_name, notifier)
+    }
+
+    fn platforms(&self, image_name: &str, notifier: &Notifier) -> Result<Vec<Platform>> {
+        (**self).platforms(image_name, notifier)
+    }
+
+    fn select_platform(&mut self, platform: Option<Platform>) -> Result<()> {
+        (**self).select_platform(platform)
+    }
+
+    fn platform(&self) -> Option<&Platform> {
+        (**self).platform()
+    }
+
+    fn estimate_image_size(&self, image_name: &str) -> Option<SizeEstimate> {
+        (**self).estimate_image_size(image_name)
+    }
+
+    fn set_workdir(&mut self, workdir: Workdir) {
+        (**self).set_workdir(workdir)
+    }
 }
 
 #[cfg(test)]
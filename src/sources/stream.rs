@@ -0,0 +1,124 @@
+//! Source adapter for image tarballs that are already in memory or arrive as a stream.
+//!
+//! [`StreamSource`] wraps any [`Read`] (a `Vec<u8>` cursor, a gRPC/HTTP body, a pipe…)
+//! holding a `docker save`/OCI image tarball, plain or gzipped. The processor unpacks it
+//! straight from the stream via [`Source::image_stream`], so no temporary copy of the
+//! tarball is written. The stream can be consumed only once.
+//!
+//! The `image_name` given to the processor is only a label; it names the branch.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tempfile::TempDir;
+
+use super::{naming, Source};
+use crate::notifier::Notifier;
+use crate::workdir::Workdir;
+
+/// Single-use [`Source`] reading the image tarball from `R`
+pub struct StreamSource<R> {
+    reader: Mutex<Option<R>>,
+    workdir: Workdir,
+}
+
+impl<R: Read> StreamSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: Mutex::new(Some(reader)),
+            workdir: Workdir::default(),
+        }
+    }
+
+    fn take_reader(&self) -> Result<R> {
+        self.reader
+            .lock()
+            .map_err(|_| anyhow!("Image stream lock is poisoned"))?
+            .take()
+            .ok_or_else(|| anyhow!("Image stream has already been consumed"))
+    }
+}
+
+impl<R: Read> Source for StreamSource<R> {
+    fn name(&self) -> &str {
+        "stream"
+    }
+
+    /// Fallback for callers that need a file: spools the stream into a temporary tarball
+    fn get_image_tarball(
+        &self,
+        _image_name: &str,
+        notifier: &Notifier,
+    ) -> Result<(PathBuf, Option<TempDir>)> {
+        let mut reader = self.take_reader()?;
+
+        let temp_dir = self.workdir.tempdir("oci2git-stream-")?;
+        let tarball_path = temp_dir.path().join("image.tar");
+        notifier.debug(&format!(
+            "Writing image stream to {}",
+            tarball_path.display()
+        ));
+
+        let mut file = File::create(&tarball_path).context("Failed to create image tarball")?;
+        io::copy(&mut reader, &mut file).context("Failed to write image stream to disk")?;
+
+        Ok((tarball_path, Some(temp_dir)))
+    }
+
+    fn image_stream(
+        &self,
+        _image_name: &str,
+        _notifier: &Notifier,
+    ) -> Result<Option<Box<dyn Read + '_>>> {
+        Ok(Some(Box::new(self.take_reader()?)))
+    }
+
+    fn branch_name(&self, image_name: &str, os_arch: &str, image_digest: &str) -> String {
+        let base_branch = super::sanitize_branch_name(image_name);
+        naming::combine_branch_with_digest(&base_branch, os_arch, image_digest)
+    }
+
+    fn set_workdir(&mut self, workdir: Workdir) {
+        self.workdir = workdir;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_stream_is_single_use() {
+        let source = StreamSource::new(Cursor::new(b"data".to_vec()));
+        let notifier = Notifier::new(0);
+
+        let mut stream = source.image_stream("app", &notifier).unwrap().unwrap();
+        let mut data = String::new();
+        stream.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "data");
+        drop(stream);
+
+        let err = source.get_image_tarball("app", &notifier).unwrap_err();
+        assert!(err.to_string().contains("already been consumed"));
+    }
+
+    #[test]
+    fn test_stream_spooled_to_tarball() {
+        let source = StreamSource::new(Cursor::new(b"data".to_vec()));
+        let (path, temp_dir) = source.get_image_tarball("app", &Notifier::new(0)).unwrap();
+        assert!(temp_dir.is_some());
+        assert_eq!(std::fs::read(path).unwrap(), b"data");
+    }
+
+    #[test]
+    fn test_stream_source_branch_name() {
+        let source = StreamSource::new(io::empty());
+        assert_eq!(
+            source.branch_name("my app:1.0", "linux-amd64", "sha256:1234567890abcdef"),
+            "my-app-1-0#linux-amd64#1234567890ab"
+        );
+    }
+}
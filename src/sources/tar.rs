@@ -2,7 +2,8 @@ use anyhow::{anyhow, Result};
 use std::path::PathBuf;
 use tempfile::TempDir;
 
-use super::Source;
+use super::{Platform, Source};
+use crate::disk_space::SizeEstimate;
 use crate::notifier::Notifier;
 
 /// Extracts filename from a tar path and sanitizes it for Git branch naming
@@ -18,11 +19,20 @@ fn tar_to_branch(tar_path: &str) -> String {
 }
 
 /// Tar implementation of the Source trait for pre-downloaded tarballs
-pub struct TarSource;
+pub struct TarSource {
+    platform: Option<Platform>,
+}
 
 impl TarSource {
     pub fn new() -> Result<Self> {
-        Ok(Self)
+        Ok(Self { platform: None })
+    }
+
+    /// Convert `platform` out of a multi-platform OCI image layout (`buildx --output
+    /// type=oci`) instead of the host platform
+    pub fn with_platform(mut self, platform: Option<Platform>) -> Self {
+        self.platform = platform;
+        self
     }
 }
 
@@ -75,6 +85,24 @@ impl Source for TarSource {
             format!("{base_branch}#{os_arch}#{image_digest}")
         }
     }
+
+    fn select_platform(&mut self, platform: Option<Platform>) -> Result<()> {
+        self.platform = platform;
+        Ok(())
+    }
+
+    fn platform(&self) -> Option<&Platform> {
+        self.platform.as_ref()
+    }
+
+    fn estimate_image_size(&self, image_path: &str) -> Option<SizeEstimate> {
+        // The tarball is read in place, only its unpacked copy takes scratch space
+        let size = std::fs::metadata(image_path).ok()?.len();
+        Some(SizeEstimate {
+            image_bytes: size,
+            exported: false,
+        })
+    }
 }
 
 #[cfg(test)]
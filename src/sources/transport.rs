@@ -0,0 +1,287 @@
+//! HTTP transport of the registry source: proxies and TLS trust.
+//!
+//! [`Transport`] builds the [`ureq::Agent`] used to talk to a registry host:
+//! - requests go through the proxy from `HTTPS_PROXY`, `HTTP_PROXY` or `ALL_PROXY`
+//!   (upper or lower case), except to hosts listed in `NO_PROXY` and to loopback
+//!   addresses,
+//! - certificates are checked against the built-in web roots plus the CA certificates
+//!   given with [`Transport::with_ca_certificates`] (PEM files), e.g. of a corporate CA or
+//!   a self-signed internal registry,
+//! - registries marked insecure ([`Transport::with_insecure_registries`]) are reached over
+//!   HTTPS without verifying their certificate and, if that fails, over plain HTTP, like
+//!   Docker's `insecure-registries`.
+
+use anyhow::{bail, Context, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Agents for registry hosts; see the module documentation.
+#[derive(Clone)]
+pub struct Transport {
+    tls: Arc<ClientConfig>,
+    ca_certificates: Vec<PathBuf>,
+    insecure_registries: Vec<String>,
+    proxy: Option<String>,
+    no_proxy: Vec<String>,
+}
+
+impl Transport {
+    /// Verifies certificates against the built-in roots and takes the proxy settings from
+    /// the environment.
+    pub fn new() -> Result<Self> {
+        let env = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        };
+        let proxy = env(&[
+            "HTTPS_PROXY",
+            "https_proxy",
+            "HTTP_PROXY",
+            "http_proxy",
+            "ALL_PROXY",
+            "all_proxy",
+        ]);
+        let no_proxy = env(&["NO_PROXY", "no_proxy"]).unwrap_or_default();
+        Ok(Self {
+            tls: tls_config(&[])?,
+            ca_certificates: Vec::new(),
+            insecure_registries: Vec::new(),
+            proxy: None,
+            no_proxy: Vec::new(),
+        }
+        .with_proxy(proxy, &no_proxy))
+    }
+
+    /// Trust the certificates in the PEM files `paths` as well.
+    ///
+    /// # Errors
+    /// - A file can't be read or holds no certificate.
+    pub fn with_ca_certificates(mut self, paths: Vec<PathBuf>) -> Result<Self> {
+        self.tls = tls_config(&paths)?;
+        self.ca_certificates = paths;
+        Ok(self)
+    }
+
+    /// Don't verify the certificates of `registries` (hosts with optional port, as in the
+    /// image reference) and fall back to plain HTTP for them.
+    pub fn with_insecure_registries(mut self, registries: Vec<String>) -> Self {
+        self.insecure_registries = registries;
+        self
+    }
+
+    /// Use `proxy` (`http://[user:password@]host:port`) except for the hosts in `no_proxy`
+    /// (comma separated; `example.com` also covers its subdomains, `*` every host).
+    pub fn with_proxy(mut self, proxy: Option<String>, no_proxy: &str) -> Self {
+        self.proxy = proxy;
+        self.no_proxy = no_proxy
+            .split(',')
+            .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|entry| !entry.is_empty())
+            .collect();
+        self
+    }
+
+    /// Whether `registry` was marked insecure.
+    pub fn is_insecure(&self, registry: &str) -> bool {
+        self.insecure_registries.iter().any(|host| host == registry)
+    }
+
+    /// Whether requests to `host` (with optional port) bypass the proxy.
+    pub fn bypasses_proxy(&self, host: &str) -> bool {
+        let hostname = match host.rsplit_once(':') {
+            Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+            _ => host,
+        }
+        .trim_matches(['[', ']'])
+        .to_ascii_lowercase();
+        matches!(hostname.as_str(), "localhost" | "127.0.0.1" | "::1")
+            || self.no_proxy.iter().any(|entry| {
+                let entry = entry.split(':').next().unwrap_or(entry);
+                entry == "*"
+                    || hostname == entry
+                    || hostname
+                        .strip_suffix(entry)
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+    }
+
+    /// Agent for requests to `host`, skipping certificate verification if `registry` was
+    /// marked insecure.
+    ///
+    /// # Errors
+    /// - The proxy URL is invalid.
+    pub fn agent(&self, registry: &str, host: &str) -> Result<ureq::Agent> {
+        let tls = if self.is_insecure(registry) {
+            insecure_tls_config()?
+        } else {
+            self.tls.clone()
+        };
+        let mut builder = ureq::AgentBuilder::new()
+            .tls_config(tls)
+            .try_proxy_from_env(false);
+        if let Some(proxy) = self.proxy.as_deref().filter(|_| !self.bypasses_proxy(host)) {
+            builder = builder.proxy(
+                ureq::Proxy::new(proxy).with_context(|| format!("Invalid proxy '{proxy}'"))?,
+            );
+        }
+        Ok(builder.build())
+    }
+}
+
+impl std::fmt::Debug for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transport")
+            .field("ca_certificates", &self.ca_certificates)
+            .field("insecure_registries", &self.insecure_registries)
+            .field("no_proxy", &self.no_proxy)
+            .finish_non_exhaustive()
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Client config trusting the web roots and the certificates in `ca_files`.
+fn tls_config(ca_files: &[PathBuf]) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    for path in ca_files {
+        let certificates = CertificateDer::pem_file_iter(path)
+            .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("Failed to read CA certificates from {}", path.display()))?;
+        if certificates.is_empty() {
+            bail!("{} contains no PEM certificate", path.display());
+        }
+        for certificate in certificates {
+            roots
+                .add(certificate)
+                .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+        }
+    }
+    Ok(Arc::new(
+        ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .context("Failed to configure TLS")?
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    ))
+}
+
+/// Client config accepting any certificate, for insecure registries.
+fn insecure_tls_config() -> Result<Arc<ClientConfig>> {
+    let provider = provider();
+    Ok(Arc::new(
+        ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .context("Failed to configure TLS")?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth(),
+    ))
+}
+
+/// Accepts every server certificate; handshake signatures are still checked.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_no_proxy() {
+        let transport = Transport::new().unwrap().with_proxy(
+            Some("http://proxy:3128".to_string()),
+            "internal.example, .corp:5000",
+        );
+        assert!(transport.bypasses_proxy("localhost:5000"));
+        assert!(transport.bypasses_proxy("registry.internal.example"));
+        assert!(transport.bypasses_proxy("corp"));
+        assert!(!transport.bypasses_proxy("notinternal.example"));
+        assert!(!transport.bypasses_proxy("registry-1.docker.io"));
+        assert!(transport
+            .clone()
+            .with_proxy(None, "*")
+            .bypasses_proxy("ghcr.io"));
+        assert!(transport.agent("ghcr.io", "ghcr.io").is_ok());
+    }
+
+    #[test]
+    fn test_ca_certificates() {
+        let temp_dir = tempdir().unwrap();
+        let empty = temp_dir.path().join("empty.pem");
+        std::fs::write(&empty, "not a certificate\n").unwrap();
+        let error = Transport::new()
+            .unwrap()
+            .with_ca_certificates(vec![empty])
+            .unwrap_err();
+        assert!(error.to_string().contains("contains no PEM certificate"));
+        assert!(Transport::new()
+            .unwrap()
+            .with_ca_certificates(vec![temp_dir.path().join("missing.pem")])
+            .is_err());
+
+        let transport = Transport::new()
+            .unwrap()
+            .with_insecure_registries(vec!["registry.lan:5000".to_string()]);
+        assert!(transport.is_insecure("registry.lan:5000"));
+        assert!(!transport.is_insecure("registry.lan"));
+        assert!(transport
+            .agent("registry.lan:5000", "registry.lan:5000")
+            .is_ok());
+    }
+}
@@ -0,0 +1,386 @@
+//! Sparse files in layers (`--sparse-files`).
+//!
+//! VM images, preallocated database files and the like are mostly holes, and tar stores
+//! only their data: GNU tar either as a `GNUSparse` (`S`) entry or, in PAX archives, as a
+//! regular entry whose `GNU.sparse.*` records describe the holes (formats 0.0, 0.1 and
+//! 1.0, the latter with the map in front of the data and the real name in
+//! `GNU.sparse.name`). [`SparseEntry::detect`] recognises all of them and
+//! [`SparseEntry::open`] reads the file back with its holes filled in.
+//!
+//! With [`SparseFiles::Expand`] (the default) the file is committed with its full
+//! content; on disk it's written with holes again (see [`write_sparse`]), so the worktree
+//! doesn't grow by the apparent size. [`SparseFiles::Stub`] commits a stub instead,
+//! listed in `.oci2git/large-files.jsonl` with the apparent size and the bytes of data
+//! (see [`crate::large_files`]).
+
+use anyhow::{bail, Context, Result};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use tar_rs as tar;
+
+/// Granularity of the holes [`write_sparse`] leaves.
+const HOLE_BLOCK: usize = 4096;
+
+/// How sparse files are committed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SparseFiles {
+    /// Full content; holes are kept on disk
+    #[default]
+    Expand,
+    /// A stub with the apparent size and the bytes of data
+    Stub,
+}
+
+/// Where the holes of a sparse entry are described.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Format {
+    /// `GNUSparse` entry; the tar reader fills in the holes itself
+    Gnu,
+    /// PAX 0.0 and 0.1: `(offset, length)` of every data block from the PAX records
+    PaxMap(Vec<(u64, u64)>),
+    /// PAX 1.0: the map precedes the data
+    PaxInline,
+}
+
+/// A sparse file entry of a layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseEntry {
+    /// Path of the file when the entry's own path is a placeholder (PAX 1.0)
+    pub name: Option<PathBuf>,
+    /// Apparent size of the file
+    pub size: u64,
+    format: Format,
+}
+
+impl SparseEntry {
+    /// The sparse file described by `header` and its PAX records, if it is one.
+    ///
+    /// # Errors
+    /// - Sparse records that are missing or malformed.
+    pub fn detect(header: &tar::Header, pax: &[(String, Vec<u8>)]) -> Result<Option<Self>> {
+        if header.entry_type().is_gnu_sparse() {
+            let size = header
+                .as_gnu()
+                .context("Sparse entry without GNU header")?
+                .real_size()
+                .context("Invalid sparse file size")?;
+            return Ok(Some(Self {
+                name: None,
+                size,
+                format: Format::Gnu,
+            }));
+        }
+        if !pax.iter().any(|(key, _)| key.starts_with("GNU.sparse.")) {
+            return Ok(None);
+        }
+
+        let text = |key: &str| {
+            pax.iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .and_then(|(_, value)| std::str::from_utf8(value).ok())
+        };
+        let number = |key: &str| -> Result<Option<u64>> {
+            text(key)
+                .map(|value| {
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid {key} PAX record: '{value}'"))
+                })
+                .transpose()
+        };
+
+        if text("GNU.sparse.major") == Some("1") {
+            let size = number("GNU.sparse.realsize")?
+                .context("Sparse file without GNU.sparse.realsize")?;
+            return Ok(Some(Self {
+                name: text("GNU.sparse.name").map(PathBuf::from),
+                size,
+                format: Format::PaxInline,
+            }));
+        }
+
+        let size = number("GNU.sparse.size")?.context("Sparse file without GNU.sparse.size")?;
+        let map = if let Some(map) = text("GNU.sparse.map") {
+            // 0.1: "offset,length,offset,length,..."
+            let numbers = map
+                .split(',')
+                .map(|n| n.trim().parse::<u64>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid GNU.sparse.map PAX record: '{map}'"))?;
+            if numbers.len() % 2 != 0 {
+                bail!("Invalid GNU.sparse.map PAX record: '{map}'");
+            }
+            numbers.chunks(2).map(|pair| (pair[0], pair[1])).collect()
+        } else {
+            // 0.0: repeated GNU.sparse.offset / GNU.sparse.numbytes records
+            let mut map = Vec::new();
+            let mut offset = None;
+            for (key, value) in pax {
+                let value = std::str::from_utf8(value).ok().and_then(|v| v.parse().ok());
+                match key.as_str() {
+                    "GNU.sparse.offset" => offset = value,
+                    "GNU.sparse.numbytes" => {
+                        let (Some(start), Some(length)) = (offset.take(), value) else {
+                            bail!("Invalid GNU.sparse.offset/numbytes PAX records");
+                        };
+                        map.push((start, length));
+                    }
+                    _ => {}
+                }
+            }
+            map
+        };
+        Ok(Some(Self {
+            name: text("GNU.sparse.name").map(PathBuf::from),
+            size,
+            format: Format::PaxMap(map),
+        }))
+    }
+
+    /// Reads the file from the data of its tar `entry`, holes filled with zeros.
+    ///
+    /// # Errors
+    /// - The map of a PAX 1.0 entry can't be read or doesn't fit the file.
+    pub fn open<R: Read>(&self, mut entry: R, stored: u64) -> Result<SparseReader<R>> {
+        let (map, data_size) = match &self.format {
+            Format::Gnu => (None, stored),
+            Format::PaxMap(map) => (Some(map.clone()), stored),
+            Format::PaxInline => {
+                let (map, map_size) = read_inline_map(&mut entry)?;
+                (Some(map), stored.saturating_sub(map_size))
+            }
+        };
+        if let Some(map) = &map {
+            let mut end = 0;
+            for (offset, length) in map {
+                if *offset < end || offset.saturating_add(*length) > self.size {
+                    bail!("Sparse map doesn't fit a file of {} bytes", self.size);
+                }
+                end = offset + length;
+            }
+        }
+        Ok(SparseReader {
+            inner: entry,
+            map: map.map(|map| map.into_iter().collect()),
+            position: 0,
+            size: self.size,
+            data_size,
+        })
+    }
+
+    /// [`SparseEntry::open`] on the tar entry this was detected on.
+    ///
+    /// # Errors
+    /// - See [`SparseEntry::open`].
+    pub fn open_entry<'e, 'a, R: Read>(
+        &self,
+        entry: &'e mut tar::Entry<'a, R>,
+    ) -> Result<SparseReader<&'e mut tar::Entry<'a, R>>> {
+        // The tar reader reports the apparent size of GNU sparse entries
+        let stored = match self.format {
+            Format::Gnu => entry.header().entry_size().context("Invalid entry size")?,
+            _ => entry.size(),
+        };
+        self.open(entry, stored)
+    }
+}
+
+/// Reads the map of a PAX 1.0 sparse entry: the number of blocks, then offset and length
+/// of each, one decimal number per line, padded to 512 bytes. Returns the map and the
+/// bytes it took.
+fn read_inline_map<R: Read>(entry: &mut R) -> Result<(Vec<(u64, u64)>, u64)> {
+    let mut consumed = 0u64;
+    let mut next_number = || -> Result<u64> {
+        let mut digits = String::new();
+        loop {
+            let mut byte = [0u8];
+            entry
+                .read_exact(&mut byte)
+                .context("Truncated sparse map")?;
+            consumed += 1;
+            match byte[0] {
+                b'\n' => break,
+                b'0'..=b'9' if digits.len() < 20 => digits.push(byte[0] as char),
+                _ => bail!("Invalid sparse map"),
+            }
+        }
+        digits.parse().context("Invalid sparse map")
+    };
+
+    let count = next_number()?;
+    let mut map = Vec::new();
+    for _ in 0..count {
+        map.push((next_number()?, next_number()?));
+    }
+    let padding = consumed.next_multiple_of(512) - consumed;
+    io::copy(&mut entry.take(padding), &mut io::sink()).context("Truncated sparse map")?;
+    Ok((map, consumed + padding))
+}
+
+/// The content of a sparse file (see [`SparseEntry::open`]).
+pub struct SparseReader<R> {
+    inner: R,
+    /// Data blocks still ahead; `None` when `inner` fills the holes itself
+    map: Option<VecDeque<(u64, u64)>>,
+    position: u64,
+    size: u64,
+    data_size: u64,
+}
+
+impl<R> SparseReader<R> {
+    /// Apparent size of the file
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes of data outside the holes, as stored in the layer
+    pub fn data_size(&self) -> u64 {
+        self.data_size
+    }
+}
+
+impl<R: Read> Read for SparseReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(map) = self.map.as_mut() else {
+            return self.inner.read(buf);
+        };
+        if buf.is_empty() || self.position >= self.size {
+            return Ok(0);
+        }
+        while map
+            .front()
+            .is_some_and(|(offset, length)| self.position >= offset + length)
+        {
+            map.pop_front();
+        }
+        let read = match map.front() {
+            Some(&(offset, length)) if self.position >= offset => {
+                let available = (offset + length - self.position).min(buf.len() as u64);
+                let read = self.inner.read(&mut buf[..available as usize])?;
+                if read == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                read
+            }
+            // A hole, up to the next block or the end of the file
+            next => {
+                let end = next.map_or(self.size, |(offset, _)| *offset);
+                let hole = (end - self.position).min(buf.len() as u64) as usize;
+                buf[..hole].fill(0);
+                hole
+            }
+        };
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+/// Copies `reader` into `file`, seeking over blocks of zeros instead of writing them so
+/// they stay holes. Returns the bytes copied.
+pub fn write_sparse<R: Read + ?Sized>(reader: &mut R, file: &mut File) -> io::Result<u64> {
+    let mut buffer = vec![0u8; 16 * HOLE_BLOCK];
+    let mut total = 0u64;
+    loop {
+        // Fill the buffer, so blocks line up with the file
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        for block in buffer[..filled].chunks(HOLE_BLOCK) {
+            if block.iter().all(|byte| *byte == 0) {
+                file.seek(SeekFrom::Current(block.len() as i64))?;
+            } else {
+                file.write_all(block)?;
+            }
+        }
+        total += filled as u64;
+    }
+    // A trailing hole only moved the position
+    file.set_len(total)?;
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(records: &[(&str, &str)]) -> Vec<(String, Vec<u8>)> {
+        records
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_pax_formats() {
+        let header = tar::Header::new_ustar();
+        assert_eq!(SparseEntry::detect(&header, &[]).unwrap(), None);
+
+        let expected = b"\0\0ab\0\0\0cd\0";
+        // 0.1 and 0.0: map in the PAX records, data as is
+        for pax in [
+            records(&[("GNU.sparse.size", "10"), ("GNU.sparse.map", "2,2,7,2")]),
+            records(&[
+                ("GNU.sparse.size", "10"),
+                ("GNU.sparse.offset", "2"),
+                ("GNU.sparse.numbytes", "2"),
+                ("GNU.sparse.offset", "7"),
+                ("GNU.sparse.numbytes", "2"),
+            ]),
+        ] {
+            let sparse = SparseEntry::detect(&header, &pax).unwrap().unwrap();
+            let mut reader = sparse.open(&b"abcd"[..], 4).unwrap();
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content).unwrap();
+            assert_eq!(content, expected);
+            assert_eq!((reader.size(), reader.data_size()), (10, 4));
+        }
+
+        // 1.0: the map comes first, padded to 512 bytes
+        let pax = records(&[
+            ("GNU.sparse.major", "1"),
+            ("GNU.sparse.minor", "0"),
+            ("GNU.sparse.name", "var/disk.img"),
+            ("GNU.sparse.realsize", "10"),
+        ]);
+        let sparse = SparseEntry::detect(&header, &pax).unwrap().unwrap();
+        assert_eq!(sparse.name, Some(PathBuf::from("var/disk.img")));
+        let mut data = b"2\n2\n2\n7\n2\n".to_vec();
+        data.resize(512, 0);
+        data.extend_from_slice(b"abcd");
+        let mut reader = sparse.open(&data[..], data.len() as u64).unwrap();
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, expected);
+        assert_eq!(reader.data_size(), 4);
+
+        // Blocks beyond the end of the file
+        let pax = records(&[("GNU.sparse.size", "4"), ("GNU.sparse.map", "2,4")]);
+        let sparse = SparseEntry::detect(&header, &pax).unwrap().unwrap();
+        assert!(sparse.open(&b"abcd"[..], 4).is_err());
+    }
+
+    #[test]
+    fn test_write_sparse() {
+        let mut content = vec![0u8; 3 * HOLE_BLOCK];
+        content[HOLE_BLOCK + 1] = 1;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("disk.img");
+        let mut file = File::create(&path).unwrap();
+        let written = write_sparse(&mut &content[..], &mut file).unwrap();
+        assert_eq!(written, content.len() as u64);
+        drop(file);
+        assert_eq!(std::fs::read(&path).unwrap(), content);
+    }
+}
@@ -0,0 +1,295 @@
+//! Special files manifest: `.oci2git/special-files.jsonl`.
+//!
+//! Character and block devices and FIFOs can't be extracted without privileges, and Git
+//! couldn't store them anyway, so they never appear in `rootfs/`. To keep conversions of
+//! base OS images lossless, every commit whose filesystem contains such nodes also carries
+//! [`SPECIAL_FILES_PATH`], one JSON object per node, sorted by path:
+//!
+//! ```text
+//! {"path":"/dev/null","type":"char","major":1,"minor":3,"mode":"0666","uid":0,"gid":0}
+//! {"path":"/run/initctl","type":"fifo","major":0,"minor":0,"mode":"0600","uid":0,"gid":0}
+//! ```
+//!
+//! The file is removed again once later layers have deleted all of them. Sockets can't be
+//! stored in layer tarballs, so they never show up. [`SpecialFiles::append_to`] turns the
+//! manifest back into tar entries, for exporting a branch as an image layer.
+
+use crate::fsmeta::{self, FileType, FsEntry};
+use crate::layer_apply::{LayerObserver, ObservedEntry};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use tar_rs as tar;
+
+/// Location of the manifest, relative to the repository root.
+pub const SPECIAL_FILES_PATH: &str = ".oci2git/special-files.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecialFileType {
+    Char,
+    Block,
+    Fifo,
+}
+
+/// A device node or FIFO of the image filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecialFile {
+    /// Absolute path inside the image, e.g. `/dev/null`
+    pub path: String,
+    #[serde(rename = "type")]
+    pub file_type: SpecialFileType,
+    /// Device numbers (0 for FIFOs)
+    pub major: u32,
+    pub minor: u32,
+    /// Permission bits in octal (e.g. `0666`)
+    pub mode: String,
+    pub uid: u64,
+    pub gid: u64,
+}
+
+impl SpecialFile {
+    fn from_fs_entry(entry: FsEntry) -> Option<Self> {
+        let file_type = match entry.file_type {
+            FileType::Char => SpecialFileType::Char,
+            FileType::Block => SpecialFileType::Block,
+            FileType::Fifo => SpecialFileType::Fifo,
+            _ => return None,
+        };
+        let (major, minor) = entry.device.unwrap_or_default();
+        Some(Self {
+            path: entry.path,
+            file_type,
+            major,
+            minor,
+            mode: entry.mode,
+            uid: entry.uid,
+            gid: entry.gid,
+        })
+    }
+}
+
+/// The special files of a `rootfs/`, keyed by image path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpecialFiles {
+    pub entries: BTreeMap<String, SpecialFile>,
+}
+
+impl SpecialFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the contents of a manifest file.
+    ///
+    /// # Errors
+    /// - A line that isn't a valid entry.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: SpecialFile = serde_json::from_str(line).with_context(|| {
+                format!(
+                    "Invalid entry on line {} of {SPECIAL_FILES_PATH}",
+                    number + 1
+                )
+            })?;
+            entries.insert(entry.path.clone(), entry);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Writes the manifest to `path`, one entry per line in path order, or removes it if
+    /// there are no special files (left).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.entries.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut content = String::new();
+        for entry in self.entries.values() {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Appends every special file to `builder` as a tar entry (paths without the leading
+    /// `/`), restoring what extraction left out.
+    ///
+    /// # Errors
+    /// - An invalid mode, or tar write failures.
+    pub fn append_to<W: Write>(&self, builder: &mut tar::Builder<W>) -> Result<()> {
+        for file in self.entries.values() {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(match file.file_type {
+                SpecialFileType::Char => tar::EntryType::Char,
+                SpecialFileType::Block => tar::EntryType::Block,
+                SpecialFileType::Fifo => tar::EntryType::Fifo,
+            });
+            header.set_mode(
+                u32::from_str_radix(&file.mode, 8)
+                    .with_context(|| format!("Invalid mode of {}: {}", file.path, file.mode))?,
+            );
+            header.set_uid(file.uid);
+            header.set_gid(file.gid);
+            header.set_device_major(file.major)?;
+            header.set_device_minor(file.minor)?;
+            header.set_size(0);
+            builder
+                .append_data(
+                    &mut header,
+                    file.path.trim_start_matches('/'),
+                    std::io::empty(),
+                )
+                .with_context(|| format!("Failed to append {}", file.path))?;
+        }
+        Ok(())
+    }
+}
+
+impl LayerObserver for SpecialFiles {
+    fn entry(&mut self, path: &Path, entry: &ObservedEntry) {
+        let Some(fs_entry) = FsEntry::from_observed(path, entry) else {
+            return;
+        };
+        let path = fs_entry.path.clone();
+        // A directory keeps its contents, anything else replaces the whole subtree
+        if fs_entry.file_type != FileType::Dir {
+            fsmeta::remove_descendants(&mut self.entries, &path);
+        }
+        match SpecialFile::from_fs_entry(fs_entry) {
+            Some(special_file) => {
+                self.entries.insert(path, special_file);
+            }
+            None => {
+                self.entries.remove(&path);
+            }
+        }
+    }
+
+    fn removed(&mut self, path: &Path) {
+        let path = fsmeta::image_path(path);
+        fsmeta::remove_descendants(&mut self.entries, &path);
+        self.entries.remove(&path);
+    }
+
+    fn cleared(&mut self, path: &Path) {
+        fsmeta::remove_descendants(&mut self.entries, &fsmeta::image_path(path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_apply::{ConflictStrategy, LayerApplier};
+    use tempfile::tempdir;
+
+    fn apply(root: &Path, special_files: &mut SpecialFiles, data: &[u8]) {
+        LayerApplier::new(root, ConflictStrategy::default())
+            .unwrap()
+            .with_observer(special_files)
+            .apply_archive(&mut tar::Archive::new(data))
+            .unwrap();
+    }
+
+    fn node(
+        builder: &mut tar::Builder<Vec<u8>>,
+        path: &str,
+        entry_type: tar::EntryType,
+        device: (u32, u32),
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(0o620);
+        header.set_gid(5);
+        header.set_device_major(device.0).unwrap();
+        header.set_device_minor(device.1).unwrap();
+        header.set_size(0);
+        builder.append_data(&mut header, path, &[][..]).unwrap();
+    }
+
+    #[test]
+    fn test_records_and_restores_special_files() {
+        let temp_dir = tempdir().unwrap();
+        let mut special_files = SpecialFiles::new();
+
+        let mut builder = tar::Builder::new(Vec::new());
+        node(&mut builder, "dev/tty1", tar::EntryType::Char, (4, 1));
+        node(&mut builder, "dev/sda", tar::EntryType::Block, (8, 0));
+        node(&mut builder, "run/initctl", tar::EntryType::Fifo, (0, 0));
+        apply(
+            temp_dir.path(),
+            &mut special_files,
+            &builder.into_inner().unwrap(),
+        );
+
+        assert_eq!(
+            special_files.entries["/dev/tty1"],
+            SpecialFile {
+                path: "/dev/tty1".to_string(),
+                file_type: SpecialFileType::Char,
+                major: 4,
+                minor: 1,
+                mode: "0620".to_string(),
+                uid: 0,
+                gid: 5,
+            }
+        );
+        assert!(!temp_dir.path().join("dev/tty1").exists());
+
+        // Manifest round trip
+        let manifest = temp_dir.path().join(SPECIAL_FILES_PATH);
+        special_files.save(&manifest).unwrap();
+        let content = fs::read_to_string(&manifest).unwrap();
+        assert!(content.starts_with("{\"path\":\"/dev/sda\",\"type\":\"block\",\"major\":8"));
+        assert_eq!(SpecialFiles::parse(&content).unwrap(), special_files);
+
+        // Restored entries describe the same nodes
+        let mut builder = tar::Builder::new(Vec::new());
+        special_files.append_to(&mut builder).unwrap();
+        let mut restored = SpecialFiles::new();
+        apply(
+            &temp_dir.path().join("restored"),
+            &mut restored,
+            &builder.into_inner().unwrap(),
+        );
+        assert_eq!(restored, special_files);
+
+        // Later layers replace and delete nodes; an empty manifest is removed
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut file = tar::Header::new_gnu();
+        file.set_size(1);
+        file.set_mode(0o644);
+        builder
+            .append_data(&mut file, "dev/tty1", &b"x"[..])
+            .unwrap();
+        node(
+            &mut builder,
+            "run/.wh..wh..opq",
+            tar::EntryType::Regular,
+            (0, 0),
+        );
+        node(&mut builder, "dev/.wh.sda", tar::EntryType::Regular, (0, 0));
+        apply(
+            temp_dir.path(),
+            &mut special_files,
+            &builder.into_inner().unwrap(),
+        );
+        assert!(special_files.entries.is_empty());
+        special_files.save(&manifest).unwrap();
+        assert!(!manifest.exists());
+    }
+}
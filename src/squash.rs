@@ -0,0 +1,232 @@
+//! `oci2git squash-history`: show the first layers of a branch as a single commit.
+//!
+//! Long histories (100+ layers) are unwieldy for some consumers, but rewriting the commits
+//! would cut them off from the layer digests they were built from. [`squash_history`]
+//! uses a `git replace` graft instead: the commit of layer N is made to look like a root
+//! commit, so `git log` and friends show layers 1..=N as one commit whose tree is the
+//! filesystem after layer N. All original commits stay in the repository.
+//!
+//! Before grafting, the layer trailers (see [`crate::trailers`]) of every squashed commit
+//! are checked against the digest chain recorded at the graft point (`layers/`, or
+//! `Image.md` on branches of earlier versions), and the squashed layers are listed in a
+//! note on that commit ([`SQUASH_NOTES_REF`]):
+//!
+//! ```text
+//! Squashed-Layers: 1-3
+//! 1 sha256:… <commit>
+//! 2 empty <commit>
+//! 3 sha256:… <commit>
+//! ```
+//!
+//! `git replace -d <commit>` restores the full history. Conversions keep seeing the
+//! original commits (libgit2 ignores replace refs), so layer deduplication still works.
+
+use crate::git::GitRepo;
+use crate::layer_records;
+use crate::trailers;
+use anyhow::{bail, Context, Result};
+
+/// Notes ref listing the layers hidden behind a graft.
+pub const SQUASH_NOTES_REF: &str = "refs/notes/oci2git-squash";
+
+/// A layer commit hidden by [`squash_history`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SquashedLayer {
+    /// 1-based layer index
+    pub index: usize,
+    pub digest: String,
+    pub commit: git2::Oid,
+}
+
+/// Result of [`squash_history`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SquashedHistory {
+    /// Commit of the last squashed layer, now grafted as a root
+    pub commit: git2::Oid,
+    /// Parentless copy of `commit` that `refs/replace/<commit>` points to
+    pub replacement: git2::Oid,
+    pub layers: Vec<SquashedLayer>,
+}
+
+/// Squash layers `1..=layers` of `branch` into a single (grafted) root commit.
+///
+/// # Errors
+/// - Fewer than 2 layers requested, or the branch doesn't have that many layer commits
+///   (e.g. it was converted before commits carried layer trailers).
+/// - Trailers that disagree with the digest chain recorded at the graft point.
+/// - The graft point is already replaced, or Git failures.
+pub fn squash_history(repo: &GitRepo, branch: &str, layers: usize) -> Result<SquashedHistory> {
+    if layers < 2 {
+        bail!("Squashing needs at least 2 layers, got {layers}");
+    }
+
+    let mut squashed = Vec::new();
+    for commit in repo.get_branch_commits(branch)? {
+        let Some(layer) = trailers::parse(&repo.commit_message(commit)?)? else {
+            continue;
+        };
+        if layer.index > layers {
+            break;
+        }
+        if layer.index != squashed.len() + 1 {
+            bail!(
+                "Commit {commit} on branch '{branch}' is layer {}, expected layer {}",
+                layer.index,
+                squashed.len() + 1
+            );
+        }
+        squashed.push(SquashedLayer {
+            index: layer.index,
+            digest: layer.digest,
+            commit,
+        });
+    }
+    if squashed.len() != layers {
+        bail!(
+            "Branch '{branch}' has {} layer commits with layer trailers, cannot squash {layers}",
+            squashed.len()
+        );
+    }
+
+    // The graft point records the digest chain up to this layer
+    let graft_point = squashed[layers - 1].commit;
+    let chain = layer_records::read_chain(repo, graft_point)?
+        .with_context(|| format!("No layer metadata at the graft point {graft_point}"))?;
+    if chain.len() != layers {
+        bail!(
+            "Commit {graft_point} records {} layers, expected {layers}",
+            chain.len()
+        );
+    }
+    for (layer, recorded) in squashed.iter().zip(&chain) {
+        if layer.digest != recorded.digest {
+            bail!(
+                "Commit {} claims layer {} is {}, but the graft point records {}",
+                layer.commit,
+                layer.index,
+                layer.digest,
+                recorded.digest
+            );
+        }
+    }
+
+    let replacement = repo.graft_as_root(graft_point)?;
+
+    let mut note = format!("Squashed-Layers: 1-{layers}\n");
+    for layer in &squashed {
+        note.push_str(&format!(
+            "{} {} {}\n",
+            layer.index, layer.digest, layer.commit
+        ));
+    }
+    repo.set_note(SQUASH_NOTES_REF, graft_point, &note)?;
+
+    Ok(SquashedHistory {
+        commit: graft_point,
+        replacement,
+        layers: squashed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest_tracker::DigestTracker;
+    use crate::image_metadata::ImageMetadata;
+    use crate::trailers::LayerTrailers;
+    use std::fs;
+    use tempfile::tempdir;
+
+    /// A branch with three layer commits and a metadata commit, like a conversion produces
+    fn converted_repo(path: &std::path::Path) -> GitRepo {
+        let repo = GitRepo::init_with_branch(path, Some("app")).unwrap();
+        let mut tracker = DigestTracker::new();
+        for (i, digest) in ["sha256:aaa", "empty", "sha256:ccc"].iter().enumerate() {
+            tracker.add_layer(
+                i,
+                digest.to_string(),
+                format!("RUN step {i}"),
+                "2024-01-01T00:00:00+00:00".to_string(),
+                *digest == "empty",
+                None,
+            );
+            let mut metadata = ImageMetadata::new(None, None);
+            metadata.update_layer_digests(&tracker);
+            metadata.save_markdown(&path.join("Image.md")).unwrap();
+            fs::write(path.join(format!("file{i}")), "x").unwrap();
+            repo.commit_layer(
+                &format!("🟢 - RUN step {i}"),
+                &LayerTrailers::new(i + 1, digest),
+            )
+            .unwrap();
+        }
+        repo.commit_all_changes("🛠️ - Metadata").unwrap();
+        repo
+    }
+
+    #[test]
+    fn test_squash_history() {
+        let temp_dir = tempdir().unwrap();
+        let repo = converted_repo(temp_dir.path());
+        let commits = repo.get_branch_commits("app").unwrap();
+
+        let squashed = squash_history(&repo, "app", 2).unwrap();
+        assert_eq!(squashed.commit, commits[1]);
+        assert_eq!(squashed.layers.len(), 2);
+        assert_eq!(squashed.layers[1].digest, "empty");
+
+        let replacement = repo.repo.find_commit(squashed.replacement).unwrap();
+        let original = repo.repo.find_commit(commits[1]).unwrap();
+        assert_eq!(replacement.parent_count(), 0);
+        assert_eq!(replacement.tree_id(), original.tree_id());
+        assert_eq!(replacement.message(), original.message());
+        assert_eq!(
+            repo.repo
+                .refname_to_id(&format!("refs/replace/{}", commits[1]))
+                .unwrap(),
+            squashed.replacement
+        );
+        assert_eq!(
+            repo.read_note(SQUASH_NOTES_REF, commits[1]).unwrap(),
+            format!(
+                "Squashed-Layers: 1-2\n1 sha256:aaa {}\n2 empty {}\n",
+                commits[0], commits[1]
+            )
+        );
+
+        // The original history is untouched
+        assert_eq!(repo.get_branch_commits("app").unwrap(), commits);
+        assert!(squash_history(&repo, "app", 2).is_err());
+    }
+
+    #[test]
+    fn test_squash_history_checks_digest_chain() {
+        let temp_dir = tempdir().unwrap();
+        let repo = converted_repo(temp_dir.path());
+
+        assert!(squash_history(&repo, "app", 1).is_err());
+        assert!(squash_history(&repo, "app", 4).is_err());
+
+        // A layer commit whose trailer disagrees with the recorded digest chain
+        let mut tracker = DigestTracker::load_from_file(temp_dir.path().join("Image.md")).unwrap();
+        tracker.add_layer(
+            3,
+            "sha256:eee".to_string(),
+            "RUN forged".to_string(),
+            "2024-01-01T00:00:00+00:00".to_string(),
+            false,
+            None,
+        );
+        let mut metadata = ImageMetadata::new(None, None);
+        metadata.update_layer_digests(&tracker);
+        metadata
+            .save_markdown(&temp_dir.path().join("Image.md"))
+            .unwrap();
+        repo.commit_layer("🟢 - RUN forged", &LayerTrailers::new(4, "sha256:ddd"))
+            .unwrap();
+        let err = squash_history(&repo, "app", 4).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("claims layer 4 is sha256:ddd, but the graft point records sha256:eee"));
+    }
+}
@@ -0,0 +1,242 @@
+//! Selective staging: the paths a layer touched.
+//!
+//! Staging the whole worktree after every layer compares every file of `rootfs/` with the
+//! index, so conversions get quadratically slower with the number of layers and files.
+//! [`TouchedPaths`] observes a layer being applied (see [`crate::layer_apply`]) and
+//! remembers what it added, replaced or deleted; [`GitRepo::commit_touched`] then stages
+//! just those paths.
+//!
+//! Paths are recorded where they ended up on disk: an entry below a symlinked directory
+//! (`lib/x` with `lib -> usr/lib`) is staged as `usr/lib/x`. Directory entries only stage
+//! the directory itself (the files in it are entries of their own), whereas whiteouts and
+//! opaque markers stage everything below the path.
+//!
+//! [`GitRepo::commit_touched`]: crate::git::GitRepo::commit_touched
+
+use crate::layer_apply::{LayerObserver, ObservedEntry};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Paths below the rootfs touched by the layers applied since the last
+/// [`TouchedPaths::clear`].
+#[derive(Debug, Clone, Default)]
+pub struct TouchedPaths {
+    root: PathBuf,
+    /// Canonical `root`, once it exists
+    canonical_root: Option<PathBuf>,
+    /// Path relative to the root → whether everything below it is to be staged too
+    paths: BTreeMap<PathBuf, bool>,
+}
+
+impl TouchedPaths {
+    /// Paths below the rootfs directory `root`, which doesn't have to exist yet.
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    /// Touched paths relative to the root, in sorted order, with whether everything
+    /// below them was touched as well.
+    pub fn paths(&self) -> impl Iterator<Item = (&Path, bool)> {
+        self.paths
+            .iter()
+            .map(|(path, recursive)| (path.as_path(), *recursive))
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+    }
+
+    fn touch(&mut self, path: PathBuf, recursive: bool) {
+        *self.paths.entry(path).or_default() |= recursive;
+    }
+
+    /// `path` with its symlinks resolved, the last component too with `follow_last`;
+    /// unchanged if it can't be resolved within the root.
+    fn resolve(&mut self, path: &Path, follow_last: bool) -> PathBuf {
+        if self.canonical_root.is_none() {
+            self.canonical_root = fs::canonicalize(&self.root).ok();
+        }
+        let Some(root) = &self.canonical_root else {
+            return path.to_path_buf();
+        };
+        let canonical = |path: &Path| {
+            fs::canonicalize(root.join(path))
+                .ok()
+                .and_then(|resolved| resolved.strip_prefix(root).ok().map(Path::to_path_buf))
+        };
+
+        let resolved = if follow_last {
+            canonical(path)
+        } else {
+            match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => canonical(parent).map(|parent| parent.join(name)),
+                _ => None,
+            }
+        };
+        resolved.unwrap_or_else(|| path.to_path_buf())
+    }
+}
+
+impl LayerObserver for TouchedPaths {
+    fn entry(&mut self, path: &Path, _entry: &ObservedEntry) {
+        let path = self.resolve(path, false);
+        self.touch(path, false);
+    }
+
+    fn removed(&mut self, path: &Path) {
+        let path = self.resolve(path, false);
+        self.touch(path, true);
+    }
+
+    fn cleared(&mut self, path: &Path) {
+        let path = self.resolve(path, true);
+        self.touch(path, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::GitRepo;
+    use crate::layer_apply::{ConflictStrategy, LayerApplier};
+    use git2::IndexAddOption;
+    use tar_rs as tar;
+    use tempfile::tempdir;
+
+    fn append(builder: &mut tar::Builder<Vec<u8>>, path: &str, kind: tar::EntryType, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_mode(if kind == tar::EntryType::Directory {
+            0o755
+        } else {
+            0o644
+        });
+        header.set_size(data.len() as u64);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+
+    fn symlink(builder: &mut tar::Builder<Vec<u8>>, path: &str, target: &str) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, path, target).unwrap();
+    }
+
+    fn apply(root: &Path, touched: &mut TouchedPaths, tarball: Vec<u8>) {
+        LayerApplier::new(root, ConflictStrategy::default())
+            .unwrap()
+            .with_observer(touched)
+            .apply_archive(&mut tar::Archive::new(tarball.as_slice()))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_only_touched_paths_are_staged() {
+        stage_layers(1);
+    }
+
+    #[test]
+    fn test_parallel_hashing_stages_the_same() {
+        stage_layers(4);
+    }
+
+    fn stage_layers(jobs: usize) {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(temp_dir.path(), Some("main"))
+            .unwrap()
+            .with_jobs(jobs);
+        let root = temp_dir.path().join("rootfs");
+        fs::create_dir_all(&root).unwrap();
+        let mut touched = TouchedPaths::new(&root);
+
+        let mut layer = tar::Builder::new(Vec::new());
+        append(&mut layer, "usr/lib/", tar::EntryType::Directory, b"");
+        append(
+            &mut layer,
+            "usr/lib/libc.so",
+            tar::EntryType::Regular,
+            b"libc",
+        );
+        symlink(&mut layer, "lib", "usr/lib");
+        append(&mut layer, "var/cache/", tar::EntryType::Directory, b"");
+        append(&mut layer, "var/cache/a", tar::EntryType::Regular, b"a");
+        append(&mut layer, "var/cache/b", tar::EntryType::Regular, b"b");
+        append(&mut layer, "etc/old/x", tar::EntryType::Regular, b"x");
+        apply(&root, &mut touched, layer.into_inner().unwrap());
+        fs::write(temp_dir.path().join("Image.md"), "# one").unwrap();
+        assert!(repo.commit_touched("one", &touched).unwrap());
+
+        touched.clear();
+        let mut layer = tar::Builder::new(Vec::new());
+        // Below the symlinked directory
+        append(&mut layer, "lib/libm.so", tar::EntryType::Regular, b"libm");
+        append(
+            &mut layer,
+            "var/cache/.wh..wh..opq",
+            tar::EntryType::Regular,
+            b"",
+        );
+        append(&mut layer, "var/cache/c", tar::EntryType::Regular, b"c");
+        append(&mut layer, "etc/.wh.old", tar::EntryType::Regular, b"");
+        // A directory replaced by a file
+        append(&mut layer, "var/cache", tar::EntryType::Regular, b"file");
+        apply(&root, &mut touched, layer.into_inner().unwrap());
+        fs::remove_file(temp_dir.path().join("Image.md")).unwrap();
+        fs::write(temp_dir.path().join("Image.json"), "{}").unwrap();
+        // Not touched by the layer, so not staged
+        fs::write(root.join("usr/lib/stray"), "stray").unwrap();
+
+        assert_eq!(
+            touched.paths().collect::<Vec<_>>(),
+            vec![
+                (Path::new("etc/old"), true),
+                (Path::new("usr/lib/libm.so"), false),
+                (Path::new("var/cache"), true),
+                (Path::new("var/cache/c"), false),
+            ]
+        );
+        assert!(repo.commit_touched("two", &touched).unwrap());
+
+        let head = repo.repo.head().unwrap().peel_to_tree().unwrap();
+        let mut paths = Vec::new();
+        head.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                paths.push(format!("{dir}{}", entry.name().unwrap()));
+            }
+            git2::TreeWalkResult::Ok
+        })
+        .unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                "Image.json",
+                "rootfs/lib",
+                "rootfs/usr/lib/libc.so",
+                "rootfs/usr/lib/libm.so",
+                "rootfs/var/cache",
+            ]
+        );
+
+        // The same as staging everything, apart from the untouched stray file
+        fs::remove_file(root.join("usr/lib/stray")).unwrap();
+        assert!(repo.repo.statuses(None).unwrap().is_empty());
+        let mut index = repo.repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.update_all(["*"].iter(), None).unwrap();
+        assert_eq!(index.write_tree().unwrap(), head.id());
+    }
+}
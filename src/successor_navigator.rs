@@ -10,19 +10,21 @@
 //! How it works:
 //! 1) Start either at all root commits (no parents) or the previously matched commit’s successors.
 //! 2) For each layer position `i`, scan candidate commits and pick the first whose recorded
-//!    `Image.md` matches the expected layer at `i` (via
+//!    layer chain matches the expected layer at `i` (via
 //!    [`crate::digest_tracker::DigestTracker::layer_matches`]).
 //! 3) Stop at the first mismatch and return the current commit and number of matched layers.
 //! 4) If all layers match, return the final commit and `new_layers.len()`.
 //!
 //! Internals:
-//! - Per-commit digests are loaded from `Image.md` using
-//!   [`crate::image_metadata::ImageMetadata::parse_markdown`] and wrapped into a
-//!   [`crate::digest_tracker::DigestTracker`].
+//! - Per-commit digests are loaded from `layers/`, falling back to `Image.json` or
+//!   `Image.md` for commits of earlier versions, using
+//!   [`crate::layer_records::read_chain`] and wrapped into a
+//!   [`crate::digest_tracker::DigestTracker`]. A report that doesn't parse makes the
+//!   commit unmatched instead of failing the conversion.
 
 use crate::digest_tracker::DigestTracker;
 use crate::git::GitRepo;
-use anyhow::{Context, Result};
+use anyhow::Result;
 use std::path::Path;
 
 pub struct SuccessorNavigator;
@@ -89,28 +91,19 @@ impl SuccessorNavigator {
         layer_position: usize,
         expected_layer: &crate::extracted_image::Layer,
     ) -> Result<bool> {
-        // Read digests.json from the specific commit
+        // Read the layer chain of the specific commit
         let digest_tracker = Self::read_digests_from_commit(repo, commit_oid)?;
 
         // Check if this tracker has a matching layer at the given position
         Ok(digest_tracker.layer_matches(layer_position, expected_layer))
     }
 
-    /// Read digest info from Image.md content from a specific commit
+    /// Read the layer chain recorded at a specific commit (see
+    /// [`crate::layer_records::read_chain`])
     fn read_digests_from_commit(repo: &GitRepo, commit_oid: git2::Oid) -> Result<DigestTracker> {
-        match repo.read_file_from_commit(commit_oid, "Image.md") {
-            Ok(content) => {
-                let image_metadata = crate::image_metadata::ImageMetadata::parse_markdown(&content)
-                    .context("Failed to parse Image.md from commit")?;
-                Ok(DigestTracker {
-                    layer_digests: image_metadata.layer_digests,
-                })
-            }
-            Err(_) => {
-                // No Image.md in this commit, return empty tracker
-                Ok(DigestTracker::new())
-            }
-        }
+        // No chain in this commit means an empty tracker
+        let layer_digests = crate::layer_records::read_chain(repo, commit_oid)?.unwrap_or_default();
+        Ok(DigestTracker { layer_digests })
     }
 }
 
@@ -0,0 +1,212 @@
+//! Machine-readable summary of a conversion run.
+//!
+//! Every conversion ([`crate::processor::ImageProcessor::convert`] and friends) returns a
+//! [`ConversionSummary`]: whether the image was converted or skipped as a duplicate
+//! ([`ConversionStatus`]), the branch, the commit of every layer (read back from the
+//! `Layer-Index`/`Layer-Digest` trailers, see [`crate::trailers`], so layers reused from an
+//! earlier image are listed too), how many layers were reused instead of applied, the bytes
+//! of the layer tarballs applied and the time it took. A [`RunSummary`] collects those of
+//! all images of a run, plus the images that failed; `--summary`/`--summary-file` print it
+//! as JSON so CI jobs don't have to scrape progress output:
+//!
+//! ```text
+//! {"images": [{
+//!   "image": "nginx:1.27",
+//!   "branch": "nginx#1.27#linux-amd64#…",
+//!   "commit": "…",
+//!   "status": "converted",
+//!   "layers": [{"index": 1, "digest": "sha256:…", "commit": "…", "reused": true}, …],
+//!   "layers_skipped": 3,
+//!   "bytes_processed": 52428800,
+//!   "elapsed_seconds": 4.2
+//! }],
+//!  "failed": [{"image": "app:broken", "error": "…"}],
+//!  "bytes_processed": 52428800,
+//!  "layers_skipped": 3,
+//!  "elapsed_seconds": 5.1}
+//! ```
+
+use crate::git::GitRepo;
+use crate::perf::PhaseTimings;
+use crate::trailers;
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// The outcome of converting one image into one branch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConversionSummary {
+    /// The image reference (or tarball path) that was converted.
+    pub image: String,
+    pub branch: String,
+    /// Tip of the branch after the conversion.
+    pub commit: String,
+    pub status: ConversionStatus,
+    /// Layer commits of the branch, oldest first.
+    pub layers: Vec<LayerSummary>,
+    /// Layers reused from the branch point or already present, not applied again.
+    pub layers_skipped: usize,
+    /// Size of the layer tarballs applied.
+    pub bytes_processed: u64,
+    pub elapsed_seconds: f64,
+    /// Time spent per phase, for `--perf-report` (see [`crate::perf`]); not part of the
+    /// summary JSON.
+    #[serde(skip)]
+    pub timings: PhaseTimings,
+}
+
+/// What a conversion did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConversionStatus {
+    /// The branch was created (or recreated with
+    /// [`crate::processor::ImageProcessor::with_force`]).
+    Converted,
+    /// The branch already existed with identical content; nothing changed.
+    SkippedDuplicate,
+}
+
+/// A layer commit of a converted branch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayerSummary {
+    /// 1-based layer index
+    pub index: usize,
+    pub digest: String,
+    pub commit: String,
+    /// `true` if the commit was there before this conversion (shared with another image).
+    pub reused: bool,
+}
+
+/// An image of a run that failed to convert.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FailedConversion {
+    pub image: String,
+    pub error: String,
+}
+
+/// All conversions of a run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub images: Vec<ConversionSummary>,
+    pub failed: Vec<FailedConversion>,
+    /// Sum over [`RunSummary::images`].
+    pub bytes_processed: u64,
+    /// Sum over [`RunSummary::images`].
+    pub layers_skipped: usize,
+    pub elapsed_seconds: f64,
+}
+
+impl ConversionSummary {
+    /// Reads the layer commits of `branch`; those up to `reused_layers` are reported as
+    /// reused. Counters start at zero.
+    ///
+    /// # Errors
+    /// - `branch` doesn't exist, or a commit can't be read.
+    pub fn from_branch(
+        repo: &GitRepo,
+        image: &str,
+        branch: &str,
+        reused_layers: usize,
+    ) -> Result<Self> {
+        let commits = repo
+            .get_branch_commits(branch)
+            .with_context(|| format!("Failed to read branch '{branch}'"))?;
+        Self::from_commits(repo, image, branch, &commits, reused_layers)
+    }
+
+    /// Same as [`ConversionSummary::from_branch`], for the image's `commits` (oldest
+    /// first) on `branch`; the last one is the tip.
+    ///
+    /// # Errors
+    /// - A commit can't be read.
+    pub fn from_commits(
+        repo: &GitRepo,
+        image: &str,
+        branch: &str,
+        commits: &[git2::Oid],
+        reused_layers: usize,
+    ) -> Result<Self> {
+        let mut layers = Vec::new();
+        for oid in commits {
+            if let Some(layer) = trailers::parse(&repo.commit_message(*oid)?)? {
+                layers.push(LayerSummary {
+                    index: layer.index,
+                    digest: layer.digest,
+                    commit: oid.to_string(),
+                    reused: layer.index <= reused_layers,
+                });
+            }
+        }
+        Ok(Self {
+            image: image.to_string(),
+            branch: branch.to_string(),
+            commit: commits.last().map(ToString::to_string).unwrap_or_default(),
+            status: ConversionStatus::Converted,
+            layers,
+            layers_skipped: 0,
+            bytes_processed: 0,
+            elapsed_seconds: 0.0,
+            timings: PhaseTimings::default(),
+        })
+    }
+}
+
+impl RunSummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a converted image to the totals.
+    pub fn push(&mut self, summary: ConversionSummary) {
+        self.bytes_processed += summary.bytes_processed;
+        self.layers_skipped += summary.layers_skipped;
+        self.images.push(summary);
+    }
+
+    /// Records an image that failed with `error`.
+    pub fn push_failed(&mut self, image: &str, error: &anyhow::Error) {
+        self.failed.push(FailedConversion {
+            image: image.to_string(),
+            error: format!("{error:#}"),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(bytes_processed: u64, layers_skipped: usize) -> ConversionSummary {
+        ConversionSummary {
+            image: "app:latest".to_string(),
+            branch: "app#latest".to_string(),
+            commit: "0".repeat(40),
+            status: ConversionStatus::Converted,
+            layers: vec![LayerSummary {
+                index: 1,
+                digest: "sha256:aa".to_string(),
+                commit: "0".repeat(40),
+                reused: false,
+            }],
+            layers_skipped,
+            bytes_processed,
+            elapsed_seconds: 1.5,
+            timings: PhaseTimings::default(),
+        }
+    }
+
+    #[test]
+    fn test_run_summary_totals() {
+        let mut run = RunSummary::new();
+        run.push(summary(100, 1));
+        run.push(summary(50, 2));
+        run.push_failed("broken", &anyhow::anyhow!("inner").context("outer"));
+        assert_eq!(run.bytes_processed, 150);
+        assert_eq!(run.layers_skipped, 3);
+        assert_eq!(run.failed[0].error, "outer: inner");
+
+        let json = serde_json::to_value(&run).unwrap();
+        assert_eq!(json["images"][0]["layers"][0]["digest"], "sha256:aa");
+        assert_eq!(json["failed"][0]["image"], "broken");
+        assert_eq!(json["images"][0]["status"], "converted");
+    }
+}
@@ -0,0 +1,192 @@
+//! Symlink targets in commits (`--symlink-targets`).
+//!
+//! Layers are applied to a directory on the host, where an absolute target like
+//! `/usr/bin/python3` would point out of the image. So both appliers point symlinks at
+//! the same path below the rootfs directory instead (`/home/me/repo/rootfs/usr/bin/python3`)
+//! and, with [`SymlinkTargets::Host`] (the default), commit that target as is. Such a
+//! repository only works where it was converted, and converting the same image elsewhere
+//! gives different commits.
+//!
+//! [`SymlinkTargets::Image`] commits the targets as the image has them. Absolute targets
+//! are recorded normalized (`/usr/bin/python3`); relative ones as they are, unless they
+//! climb out of the directory they're resolved in or pass through a symlinked directory,
+//! which only works inside the image. Those are recorded as normalized absolute paths as
+//! well. On disk, absolute targets still point below the rootfs directory while layers are
+//! applied: [`GitRepo`](crate::git::GitRepo) translates them when staging (see
+//! [`in_image`]), and [`reroot`] points them back below the rootfs directory after a
+//! branch point was checked out. Once the conversion is done the worktree is checked out
+//! again, with the in-image targets.
+//!
+//! Commits of the two modes don't mix: a branch converted with [`SymlinkTargets::Image`]
+//! records it in [`SYMLINK_TARGETS_PATH`], and only shares layer commits with branches
+//! converted the same way.
+
+use anyhow::{Context, Result};
+use git2::Tree;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Marks commits whose symlinks have their in-image targets, relative to the repository
+/// root.
+pub const SYMLINK_TARGETS_PATH: &str = ".oci2git/symlink-targets";
+
+/// How symlink targets are committed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkTargets {
+    /// Below the rootfs directory of the converting machine
+    #[default]
+    Host,
+    /// As in the image, e.g. `/usr/bin/python3`
+    Image,
+}
+
+impl SymlinkTargets {
+    /// The mode of the commit `tree`: [`SymlinkTargets::Image`] if it has
+    /// [`SYMLINK_TARGETS_PATH`].
+    pub(crate) fn of_tree(tree: &Tree) -> Self {
+        match tree.get_path(Path::new(SYMLINK_TARGETS_PATH)) {
+            Ok(_) => Self::Image,
+            Err(_) => Self::Host,
+        }
+    }
+
+    /// Records `self` in the worktree at `workdir`, so readers and later conversions see
+    /// it; [`SymlinkTargets::Host`] leaves no file behind.
+    pub(crate) fn save(self, workdir: &Path) -> Result<()> {
+        let marker = workdir.join(SYMLINK_TARGETS_PATH);
+        match self {
+            Self::Host => {
+                if marker.exists() {
+                    fs::remove_file(&marker)
+                        .with_context(|| format!("Failed to remove {}", marker.display()))?;
+                }
+            }
+            Self::Image => {
+                fs::create_dir_all(marker.parent().unwrap_or(workdir))?;
+                fs::write(&marker, "image\n")
+                    .with_context(|| format!("Failed to write {}", marker.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the relative `target` of a symlink in a directory `depth` levels below the
+/// root can be recorded as is: leading `..` only, no more of them than `depth`.
+pub(crate) fn keeps_relative(target: &Path, depth: usize) -> bool {
+    let mut parents = 0;
+    let mut descended = false;
+    for component in target.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if !descended => parents += 1,
+            Component::Normal(_) => descended = true,
+            _ => return false,
+        }
+    }
+    !target.as_os_str().is_empty() && parents <= depth
+}
+
+/// The in-image form of a symlink `target` recorded below `root`
+/// (`<root>/usr/bin/python3` → `/usr/bin/python3`), `None` for other targets.
+pub(crate) fn in_image(root: &[u8], target: &[u8]) -> Option<Vec<u8>> {
+    match target.strip_prefix(root)? {
+        b"" => Some(b"/".to_vec()),
+        rest if rest.starts_with(b"/") => Some(rest.to_vec()),
+        _ => None,
+    }
+}
+
+/// Points the symlinks below `root` with absolute in-image targets (as checked out from a
+/// [`SymlinkTargets::Image`] commit) below `root` again, so the layers applied on top
+/// resolve them inside the image. Returns the number of symlinks changed.
+///
+/// # Errors
+/// - `root` can't be read, or a symlink can't be replaced.
+pub fn reroot(root: &Path) -> Result<usize> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve rootfs: {}", root.display()))?;
+    let mut changed = 0;
+    let mut pending = vec![root.clone()];
+    while let Some(dir) = pending.pop() {
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+            if !file_type.is_symlink() {
+                continue;
+            }
+            let path = entry.path();
+            let target = fs::read_link(&path)?;
+            if !target.is_absolute() || target.starts_with(&root) {
+                continue;
+            }
+            let rerooted: PathBuf = root.join(target.strip_prefix("/").unwrap_or(&target));
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to replace symlink {}", path.display()))?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&rerooted, &path)
+                .with_context(|| format!("Failed to replace symlink {}", path.display()))?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_keeps_relative() {
+        assert!(keeps_relative(Path::new("python3"), 0));
+        assert!(keeps_relative(Path::new("./python3"), 0));
+        assert!(keeps_relative(Path::new("../lib/libc.so"), 1));
+        assert!(!keeps_relative(Path::new("../lib/libc.so"), 0));
+        assert!(!keeps_relative(Path::new("lib/../../etc"), 5));
+        assert!(!keeps_relative(Path::new(""), 1));
+        assert!(!keeps_relative(Path::new("/usr/bin"), 1));
+    }
+
+    #[test]
+    fn test_in_image() {
+        let root = b"/repo/rootfs";
+        assert_eq!(
+            in_image(root, b"/repo/rootfs/usr/bin/python3"),
+            Some(b"/usr/bin/python3".to_vec())
+        );
+        assert_eq!(in_image(root, b"/repo/rootfs"), Some(b"/".to_vec()));
+        assert_eq!(in_image(root, b"/repo/rootfs2/x"), None);
+        assert_eq!(in_image(root, b"python3"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reroot() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        std::os::unix::fs::symlink("/usr/lib", root.join("lib")).unwrap();
+        std::os::unix::fs::symlink("python3", root.join("usr/bin/python")).unwrap();
+        let inside = root.join("usr/bin/python3");
+        std::os::unix::fs::symlink(&inside, root.join("usr/bin/py")).unwrap();
+
+        assert_eq!(reroot(&root).unwrap(), 1);
+        assert_eq!(
+            fs::read_link(root.join("lib")).unwrap(),
+            root.join("usr/lib")
+        );
+        assert_eq!(
+            fs::read_link(root.join("usr/bin/python")).unwrap(),
+            Path::new("python3")
+        );
+        assert_eq!(fs::read_link(root.join("usr/bin/py")).unwrap(), inside);
+    }
+}
@@ -1,396 +1,295 @@
+//! Unpacks tar archives (plain or gzip-compressed) from disk or from a stream.
+//!
+//! Both image tarballs and layer tarballs go through [`extract_tar`] (or
+//! [`extract_reader_with`] for in-memory/streamed archives); the per-entry overlay
+//! logic (whiteouts, links, type changes, permissions) lives in [`crate::layer_apply`].
+//! [`apply_tar_to_tree`] applies a layer tarball to a [`RootfsTree`] instead.
+//!
+//! Gzip streams may be made of several members, as eStargz layers are; their TOC and
+//! landmark entries are left out of the tree (see [`crate::estargz`]).
+
+use crate::estargz;
+use crate::layer_apply::{ConflictStrategy, LayerApplier, LayerObserver, LayerOptions};
+use crate::layer_verification::HashingReader;
+use crate::progress::ProgressReader;
+use crate::tree_apply::RootfsTree;
 use anyhow::{Context, Result};
-use flate2::read::GzDecoder;
-use std::fs::{self, File};
-use std::io::{BufReader, Read};
-use std::path::{Component, Path, PathBuf};
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read};
+use std::path::Path;
 use tar_rs as tar;
 
-/// Normalizes a path from a tar archive to be safe for extraction
-/// Removes any attempts to escape the root directory
-fn normalize_tar_path(p: &Path) -> PathBuf {
-    let mut out = PathBuf::new();
-
-    for comp in p.components() {
-        match comp {
-            Component::CurDir => { /* skip "." */ }
-            Component::ParentDir => {
-                // Don't allow escaping the rootfs
-                out.pop();
-            }
-            Component::Normal(c) => out.push(c),
-            // Ignore absolute paths and Windows prefixes
-            Component::RootDir | Component::Prefix(_) => { /* ignore */ }
-        }
-    }
-
-    out
-}
-
-/// Attempts to create a hardlink, falling back to copy if hardlinks aren't supported
-/// Returns Ok(()) if successful, Err if the target doesn't exist (caller should skip)
-fn try_link_or_copy(target: &Path, dest: &Path) -> Result<()> {
-    if !target.exists() {
-        // Target doesn't exist - this can happen if:
-        // 1. The target was removed by a whiteout in this layer
-        // 2. The target was replaced/removed in a previous layer
-        // 3. The tar archive has broken hardlinks
-        // We should skip this hardlink rather than failing
-        return Err(anyhow::anyhow!(
-            "hardlink target missing: {}",
-            target.display()
-        ));
-    }
-
-    // Remove destination if it already exists
-    if dest.exists() {
-        fs::remove_file(dest)
-            .with_context(|| format!("Failed to remove existing file: {}", dest.display()))?;
-    }
-
-    // Try to create a hardlink first
-    if let Err(e) = fs::hard_link(target, dest) {
-        // Filesystem might not support hardlinks - fall back to copy
-        log::debug!(
-            "hardlink failed ({}), falling back to copy: {} -> {}",
-            e,
-            target.display(),
-            dest.display()
-        );
-        fs::copy(target, dest).with_context(|| {
-            format!("Failed to copy {} to {}", target.display(), dest.display())
-        })?;
-    }
-
-    Ok(())
+/// Extracts a tar archive (plain or gzipped) to the specified directory
+/// Handles hardlinks, permissions, and whiteouts in a single pass
+pub fn extract_tar(tar_path: &Path, extract_dir: &Path) -> Result<()> {
+    extract_tar_with(tar_path, extract_dir, ConflictStrategy::default())
 }
 
-struct PendingHardlink {
-    dest: PathBuf,
-    target: PathBuf,
+/// Same as [`extract_tar`], resolving type changes with the given [`ConflictStrategy`]
+pub fn extract_tar_with(
+    tar_path: &Path,
+    extract_dir: &Path,
+    strategy: ConflictStrategy,
+) -> Result<()> {
+    let file = File::open(tar_path)
+        .with_context(|| format!("Failed to open tar file: {}", tar_path.display()))?;
+    extract_reader_with(BufReader::new(file), extract_dir, strategy)
 }
 
-struct PendingSymlink {
-    dest: PathBuf,
-    target: PathBuf,
+/// Same as [`extract_tar_with`], applying the layer with `options` (stubs for large
+/// files, see [`crate::large_files`], excluded paths, writing threads) and reporting every
+/// applied change to `observer`; `progress` is called with the number of bytes of the
+/// tarball read so far, a few times a second
+///
+/// Returns the `sha256:<hex>` digest of the uncompressed tar stream, i.e. the layer's
+/// `diff_id` if the archive is intact (see [`crate::layer_verification`]).
+pub fn extract_tar_observed(
+    tar_path: &Path,
+    extract_dir: &Path,
+    options: &LayerOptions,
+    observer: &mut dyn LayerObserver,
+    progress: &mut dyn FnMut(u64),
+) -> Result<String> {
+    let estargz = estargz::read_toc_offset(tar_path)?.is_some();
+    let file = File::open(tar_path)
+        .with_context(|| format!("Failed to open tar file: {}", tar_path.display()))?;
+    let file = ProgressReader::new(file, progress);
+    let mut reader = HashingReader::new(decompressed(BufReader::new(file))?);
+    options
+        .applier(extract_dir)?
+        .with_estargz(estargz)
+        .with_observer(observer)
+        .apply_archive(&mut tar::Archive::new(&mut reader))?;
+    // The digest covers the padding after the end-of-archive marker too
+    io::copy(&mut reader, &mut io::sink()).context("Failed to read tar file")?;
+    Ok(reader.finish())
 }
 
-/// Extracts a tar archive (plain or gzipped) to the specified directory
-/// Handles hardlinks, permissions, and whiteouts in a single pass
-pub fn extract_tar(tar_path: &Path, extract_dir: &Path) -> Result<()> {
-    // Detect if the file is gzip compressed
+/// Same as [`extract_tar_observed`], applying the layer to a [`RootfsTree`] instead of a
+/// directory (see [`crate::tree_apply`])
+pub fn apply_tar_to_tree(
+    tar_path: &Path,
+    tree: &mut RootfsTree,
+    options: &LayerOptions,
+    observer: &mut dyn LayerObserver,
+    progress: &mut dyn FnMut(u64),
+) -> Result<String> {
+    let estargz = estargz::read_toc_offset(tar_path)?.is_some();
     let file = File::open(tar_path)
         .with_context(|| format!("Failed to open tar file: {}", tar_path.display()))?;
+    let file = ProgressReader::new(file, progress);
+    let mut reader = HashingReader::new(decompressed(BufReader::new(file))?);
+    tree.applier(options.strategy)
+        .with_estargz(estargz)
+        .with_max_file_size(options.max_file_size)
+        .with_sparse_files(options.sparse_files)
+        .with_symlink_targets(options.symlink_targets)
+        .with_exclude(options.exclude.clone())
+        .with_observer(observer)
+        .apply_archive(&mut tar::Archive::new(&mut reader))?;
+    io::copy(&mut reader, &mut io::sink()).context("Failed to read tar file")?;
+    Ok(reader.finish())
+}
+
+/// Extracts a tar archive (plain or gzipped) read from a stream, without a file on disk
+pub fn extract_reader_with<R: Read>(
+    reader: R,
+    extract_dir: &Path,
+    strategy: ConflictStrategy,
+) -> Result<()> {
+    LayerApplier::new(extract_dir, strategy)?
+        .apply_archive(&mut tar::Archive::new(decompressed(reader)?))
+}
 
-    let mut buf_reader = BufReader::new(file);
+/// The tar stream of `reader`, gunzipped if it is gzip compressed
+fn decompressed<'a, R: Read + 'a>(mut reader: R) -> Result<Box<dyn Read + 'a>> {
+    // Detect if the stream is gzip compressed, then put the magic bytes back in front
     let mut magic_bytes = [0u8; 2];
-    buf_reader
+    reader
         .read_exact(&mut magic_bytes)
         .context("Failed to read magic bytes from tar file")?;
+    let reader = Cursor::new(magic_bytes).chain(reader);
 
-    // Reopen the file since we consumed some bytes
-    let file = File::open(tar_path)?;
-
-    let mut archive: tar::Archive<Box<dyn Read>> = if magic_bytes == [0x1f, 0x8b] {
-        // Gzip compressed
-        tar::Archive::new(Box::new(GzDecoder::new(file)))
+    Ok(if magic_bytes == [0x1f, 0x8b] {
+        // Gzip compressed, in as many members as it takes (eStargz has one per file)
+        Box::new(MultiGzDecoder::new(reader))
     } else {
         // Plain tar
-        tar::Archive::new(Box::new(file))
-    };
-
-    // First pass: extract all regular files, directories, and symlinks
-    // Store hardlinks and failed symlinks for second pass
-    let mut pending_hardlinks: Vec<PendingHardlink> = Vec::new();
-    let mut pending_symlinks: Vec<PendingSymlink> = Vec::new();
-
-    for entry_result in archive.entries()? {
-        let mut entry = entry_result.context("Failed to read tar entry")?;
-        let header = entry.header();
-        let entry_type = header.entry_type();
-
-        let tar_path = entry.path().context("Failed to get entry path")?;
-        let rel_path = normalize_tar_path(&tar_path);
-
-        // Check for whiteout files (overlay filesystem markers)
-        if let Some(file_name) = rel_path.file_name().and_then(|n| n.to_str()) {
-            if file_name == ".wh..wh..opq" {
-                // Opaque directory marker - remove all contents of parent directory
-                if let Some(parent) = rel_path.parent() {
-                    let opaque_dir = extract_dir.join(parent);
-                    if opaque_dir.exists() && opaque_dir.is_dir() {
-                        log::debug!(
-                            "Found opaque directory marker, clearing: {}",
-                            opaque_dir.display()
-                        );
-                        for entry in fs::read_dir(&opaque_dir)? {
-                            let entry = entry?;
-                            let path = entry.path();
-                            if path.is_dir() {
-                                fs::remove_dir_all(&path).ok();
-                            } else {
-                                fs::remove_file(&path).ok();
-                            }
-                        }
-                    }
-                }
-                continue; // Skip the marker file itself
-            } else if let Some(deleted_name) = file_name.strip_prefix(".wh.") {
-                // Whiteout marker - delete the target file/directory
-                // Remove ".wh." prefix
-                if let Some(parent) = rel_path.parent() {
-                    let deleted_path = extract_dir.join(parent).join(deleted_name);
-                    if deleted_path.exists() {
-                        log::debug!(
-                            "Found whiteout marker, deleting: {}",
-                            deleted_path.display()
-                        );
-                        if deleted_path.is_dir() {
-                            fs::remove_dir_all(&deleted_path).ok();
-                        } else {
-                            fs::remove_file(&deleted_path).ok();
-                        }
-                    }
-                }
-                continue; // Skip the whiteout marker itself
-            }
-        }
-
-        let dest = extract_dir.join(&rel_path);
-
-        // Create parent directories and ensure they're writable
-        if let Some(parent) = dest.parent() {
-            log::debug!("Creating parent directory: {}", parent.display());
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
-
-            // Verify parent exists after creation
-            if !parent.exists() {
-                anyhow::bail!(
-                    "Parent directory doesn't exist after create_dir_all: {}",
-                    parent.display()
-                );
-            }
-
-            // Always set writable permissions on parent (simple and safe)
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let perms_result = fs::set_permissions(parent, fs::Permissions::from_mode(0o755));
-                if let Err(e) = perms_result {
-                    log::warn!("Failed to set permissions on {}: {}", parent.display(), e);
-                }
+        Box::new(reader)
+    })
+}
 
-                // Verify parent is actually a directory
-                if !parent.is_dir() {
-                    anyhow::bail!(
-                        "Parent path exists but is not a directory: {}",
-                        parent.display()
-                    );
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    /// Appends an entry with a raw (unvalidated) path, so tests can craft hostile archives
+    fn append_raw(
+        builder: &mut tar::Builder<Vec<u8>>,
+        path: &str,
+        entry_type: tar::EntryType,
+        link_name: Option<&str>,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        if let Some(link_name) = link_name {
+            header.set_link_name(link_name).unwrap();
         }
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
 
-        match entry_type {
-            tar::EntryType::Directory => {
-                fs::create_dir_all(&dest)
-                    .with_context(|| format!("Failed to create directory: {}", dest.display()))?;
-
-                // Always set writable permissions on directories (0755 minimum)
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::PermissionsExt;
-                    let mode = header.mode().unwrap_or(0o755);
-                    // Ensure directory is at least writable/executable by owner
-                    let safe_mode = mode | 0o700;
-                    let _ = fs::set_permissions(&dest, fs::Permissions::from_mode(safe_mode));
-                }
-            }
-            tar::EntryType::Regular => {
-                // Get mode before consuming entry
-                #[cfg(unix)]
-                let mode = header.mode().ok();
-
-                // Delete existing file if it exists (overlay behavior)
-                // Use symlink_metadata to detect symlinks even if they're broken
-                if let Ok(metadata) = fs::symlink_metadata(&dest) {
-                    log::debug!(
-                        "Removing existing entry at: {} (is_symlink: {})",
-                        dest.display(),
-                        metadata.is_symlink()
-                    );
-                    if metadata.is_dir() && !metadata.is_symlink() {
-                        fs::remove_dir_all(&dest).ok();
-                    } else {
-                        // Remove file or symlink
-                        fs::remove_file(&dest).ok();
-                    }
-                }
-
-                log::debug!("Creating file: {}", dest.display());
-                let mut out_file = File::create(&dest).with_context(|| {
-                    let parent_info = if let Some(parent) = dest.parent() {
-                        format!(
-                            " (parent: {}, exists: {}, is_dir: {})",
-                            parent.display(),
-                            parent.exists(),
-                            parent.is_dir()
-                        )
-                    } else {
-                        String::from(" (no parent)")
-                    };
-                    format!("Failed to create file: {}{}", dest.display(), parent_info)
-                })?;
-
-                std::io::copy(&mut entry, &mut out_file)
-                    .with_context(|| format!("Failed to write file: {}", dest.display()))?;
-
-                // Set permissions - ensure file is at least readable by owner for git
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::PermissionsExt;
-                    if let Some(mut mode) = mode {
-                        // Ensure owner can read (add 0o400 if not present)
-                        if mode & 0o400 == 0 {
-                            mode |= 0o400;
-                            log::debug!(
-                                "Fixed unreadable file during extraction: {}",
-                                dest.display()
-                            );
-                        }
-                        let perms = fs::Permissions::from_mode(mode);
-                        let _ = fs::set_permissions(&dest, perms);
-                    }
-                }
-            }
-            tar::EntryType::Symlink => {
-                let link_name = header
-                    .link_name()
-                    .context("Failed to get symlink target")?
-                    .ok_or_else(|| anyhow::anyhow!("Symlink without target"))?;
+    fn write_tar(dir: &Path, build: impl FnOnce(&mut tar::Builder<Vec<u8>>)) -> PathBuf {
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        let tar_path = dir.join("layer.tar");
+        fs::write(&tar_path, builder.into_inner().unwrap()).unwrap();
+        tar_path
+    }
 
-                // ALWAYS resolve target path relative to extract_dir (rootfs) as ABSOLUTE path
-                let target_path = if link_name.is_absolute() {
-                    // Absolute symlink like /usr/share/foo -> extract_dir/usr/share/foo
-                    let normalized = normalize_tar_path(&link_name);
-                    extract_dir.join(normalized)
-                } else {
-                    // Relative symlink - resolve from the symlink's parent directory
-                    if let Some(parent) = dest.parent() {
-                        parent.join(&link_name)
-                    } else {
-                        extract_dir.join(&link_name)
-                    }
-                };
+    #[test]
+    fn test_relative_symlink_cannot_escape_root() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        let tar_path = write_tar(temp_dir.path(), |b| {
+            append_raw(b, "evil", tar::EntryType::Symlink, Some("../../.."), b"");
+            append_raw(b, "evil/pwned.txt", tar::EntryType::Regular, None, b"x");
+        });
 
-                // Canonicalize extract_dir to ensure we have an absolute path
-                let absolute_target = if target_path.is_absolute() {
-                    target_path
-                } else {
-                    // Make relative paths absolute by joining with extract_dir
-                    std::env::current_dir()
-                        .ok()
-                        .and_then(|cwd| cwd.join(&target_path).canonicalize().ok())
-                        .unwrap_or(target_path)
-                };
+        extract_tar(&tar_path, &rootfs).unwrap();
 
-                #[cfg(unix)]
-                {
-                    // Try to create symlink with the absolute target path
-                    if let Err(e) = std::os::unix::fs::symlink(&absolute_target, &dest) {
-                        log::debug!(
-                            "Failed to create symlink {} -> {}: {}. Will try to copy target...",
-                            dest.display(),
-                            absolute_target.display(),
-                            e
-                        );
+        // The link is clamped at the root, so the file lands inside rootfs
+        assert!(rootfs.join("pwned.txt").exists());
+        assert!(!temp_dir.path().join("pwned.txt").exists());
+    }
 
-                        // Save for retry after all files are extracted
-                        pending_symlinks.push(PendingSymlink {
-                            dest,
-                            target: absolute_target,
-                        });
-                    }
-                }
+    #[cfg(unix)]
+    #[test]
+    fn test_refuses_to_write_through_outside_symlink() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        // e.g. left behind by an earlier layer
+        std::os::unix::fs::symlink(&outside, rootfs.join("lib")).unwrap();
+
+        let tar_path = write_tar(temp_dir.path(), |b| {
+            append_raw(b, "lib/passwd", tar::EntryType::Regular, None, b"x");
+        });
+
+        assert!(extract_tar(&tar_path, &rootfs).is_err());
+        assert!(!outside.join("passwd").exists());
+    }
 
-                #[cfg(not(unix))]
-                {
-                    log::warn!(
-                        "Symlink support not implemented on this platform: {}",
-                        dest.display()
-                    );
-                }
-            }
-            tar::EntryType::Link => {
-                // Hardlink - save for second pass
-                let link_name = header
-                    .link_name()
-                    .context("Failed to get hardlink target")?
-                    .ok_or_else(|| anyhow::anyhow!("Hardlink without target"))?;
+    #[test]
+    fn test_rejects_parent_dir_whiteout() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        let sibling = temp_dir.path().join("sibling.txt");
+        fs::write(&sibling, "keep me").unwrap();
 
-                let target_rel = normalize_tar_path(&link_name);
-                let target = extract_dir.join(&target_rel);
+        let tar_path = write_tar(temp_dir.path(), |b| {
+            append_raw(b, ".wh...", tar::EntryType::Regular, None, b"");
+        });
 
-                pending_hardlinks.push(PendingHardlink { dest, target });
-            }
-            _ => {
-                // Other entry types (char device, block device, fifo, etc.)
-                log::debug!("Skipping unsupported entry type: {entry_type:?}");
-            }
-        }
+        assert!(extract_tar(&tar_path, &rootfs).is_err());
+        assert!(rootfs.exists());
+        assert!(sibling.exists());
     }
 
-    // Second pass: create hardlinks (with retry queue for missing targets)
-    let mut failed_hardlinks = Vec::new();
-    for hardlink in pending_hardlinks {
-        if let Err(e) = try_link_or_copy(&hardlink.target, &hardlink.dest) {
-            log::debug!(
-                "Hardlink target not found yet, will retry: {} -> {}: {}",
-                hardlink.dest.display(),
-                hardlink.target.display(),
-                e
-            );
-            // Add to dead letter queue - target might be extracted later
-            failed_hardlinks.push(hardlink);
-        }
+    #[test]
+    fn test_whiteout_removes_dangling_symlink() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        let first = write_tar(temp_dir.path(), |b| {
+            append_raw(b, "dangling", tar::EntryType::Symlink, Some("missing"), b"");
+        });
+        extract_tar(&first, &rootfs).unwrap();
+        assert!(fs::symlink_metadata(rootfs.join("dangling")).is_ok());
+
+        let second = write_tar(temp_dir.path(), |b| {
+            append_raw(b, ".wh.dangling", tar::EntryType::Regular, None, b"");
+        });
+        extract_tar(&second, &rootfs).unwrap();
+        assert!(fs::symlink_metadata(rootfs.join("dangling")).is_err());
     }
 
-    // Third pass: retry failed hardlinks (targets might now exist)
-    for hardlink in failed_hardlinks {
-        if let Err(e) = try_link_or_copy(&hardlink.target, &hardlink.dest) {
-            log::warn!(
-                "Skipping broken hardlink (target still missing): {} -> {}: {}",
-                hardlink.dest.display(),
-                hardlink.target.display(),
-                e
-            );
-            // Skip this hardlink - the target truly doesn't exist
-        }
+    #[cfg(unix)]
+    #[test]
+    fn test_refuses_hardlink_to_outside_file() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret"), "secret").unwrap();
+        std::os::unix::fs::symlink(&outside, rootfs.join("etc")).unwrap();
+
+        let tar_path = write_tar(temp_dir.path(), |b| {
+            append_raw(b, "leak", tar::EntryType::Link, Some("etc/secret"), b"");
+        });
+
+        assert!(extract_tar(&tar_path, &rootfs).is_err());
+        assert!(!rootfs.join("leak").exists());
     }
 
-    // Fourth pass: retry failed symlinks (copy target files)
-    for symlink in pending_symlinks {
-        if symlink.target.exists() {
-            log::debug!(
-                "Retrying symlink by copying: {} -> {}",
-                symlink.target.display(),
-                symlink.dest.display()
-            );
-            if let Err(e) = fs::copy(&symlink.target, &symlink.dest) {
-                log::warn!(
-                    "Failed to copy symlink target {} -> {}: {}. Skipping.",
-                    symlink.target.display(),
-                    symlink.dest.display(),
-                    e
-                );
-            }
-        } else {
-            log::debug!(
-                "Symlink target still does not exist: {} -> {}. Skipping.",
-                symlink.dest.display(),
-                symlink.target.display()
-            );
-        }
+    #[test]
+    fn test_truncated_archive_is_an_error() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        let tar_path = write_tar(temp_dir.path(), |b| {
+            append_raw(b, "file.txt", tar::EntryType::Regular, None, &[b'a'; 4096]);
+        });
+        let bytes = fs::read(&tar_path).unwrap();
+        fs::write(&tar_path, &bytes[..1024]).unwrap();
+
+        assert!(extract_tar(&tar_path, &rootfs).is_err());
     }
 
-    Ok(())
+    #[test]
+    fn test_observed_extraction_digest() {
+        let temp_dir = tempdir().unwrap();
+        let tar_path = write_tar(temp_dir.path(), |b| {
+            append_raw(b, "file.txt", tar::EntryType::Regular, None, b"hello");
+        });
+        let expected = crate::blob_cache::sha256_file(&tar_path).unwrap();
+        let mut observer: Vec<&mut dyn LayerObserver> = Vec::new();
+        let digest = extract_tar_observed(
+            &tar_path,
+            &temp_dir.path().join("plain"),
+            &LayerOptions::default(),
+            &mut observer,
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!(digest, expected);
+
+        // Compressed layers hash to their diff_id, the digest of the uncompressed stream
+        let gz_path = temp_dir.path().join("layer.tar.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&gz_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        io::copy(&mut File::open(&tar_path).unwrap(), &mut encoder).unwrap();
+        encoder.finish().unwrap();
+        let digest = extract_tar_observed(
+            &gz_path,
+            &temp_dir.path().join("gzipped"),
+            &LayerOptions::default(),
+            &mut observer,
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!(digest, expected);
+        assert!(temp_dir.path().join("gzipped/file.txt").exists());
+    }
 }
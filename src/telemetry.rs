@@ -0,0 +1,89 @@
+//! OpenTelemetry export of the conversion spans (feature `otel`).
+//!
+//! [`crate::processor`] runs the phases of a conversion in `tracing` spans (per image,
+//! fetch, layer, extraction, commit, ...), with the [`crate::notifier::Notifier`]
+//! messages as events within them. [`init_from_env`] sets up an exporter whose
+//! [`Telemetry::layer`] is added to the notifier (see
+//! [`crate::notifier::Notifier::with_tracing_layer`]), exporting them as OTLP traces over
+//! HTTP, so slow layers and phases of fleet conversions show up in Jaeger, Tempo,
+//! Honeycomb and the like:
+//!
+//! ```bash
+//! cargo install oci2git --features otel
+//! OTEL_EXPORTER_OTLP_ENDPOINT=http://localhost:4318 oci2git -o ./repo nginx:latest
+//! ```
+//!
+//! The exporter is configured by the standard `OTEL_*` environment variables
+//! (`OTEL_EXPORTER_OTLP_ENDPOINT`, `OTEL_EXPORTER_OTLP_HEADERS`, `OTEL_SERVICE_NAME`, ...);
+//! the service name defaults to `oci2git`. Spans and events below [`MAX_LEVEL`] are not
+//! exported, nor are the events the log filter of the notifier (`-v`, `--log`, `RUST_LOG`)
+//! drops.
+
+use crate::notifier::TracingLayer;
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::Layer;
+
+/// Most detailed level exported.
+pub const MAX_LEVEL: LevelFilter = LevelFilter::INFO;
+
+/// Environment variables that enable the export, either of them.
+const ENDPOINT_VARIABLES: &[&str] = &[
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+];
+
+/// Exports spans until dropped; dropping it flushes those not sent yet.
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(error) = self.provider.shutdown() {
+            log::warn!("Failed to export the remaining spans: {error}");
+        }
+    }
+}
+
+/// Sets up the export to the OTLP endpoint of the environment; `None` if no endpoint is
+/// set.
+///
+/// # Errors
+/// - The exporter can't be created.
+pub fn init_from_env() -> Result<Option<Telemetry>> {
+    if !ENDPOINT_VARIABLES
+        .iter()
+        .any(|variable| std::env::var_os(variable).is_some())
+    {
+        return Ok(None);
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .context("Failed to create the OTLP exporter")?;
+    let mut resource = Resource::builder();
+    if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+        resource = resource.with_service_name(env!("CARGO_PKG_NAME"));
+    }
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+
+    Ok(Some(Telemetry { provider }))
+}
+
+impl Telemetry {
+    /// A layer exporting the spans and events it sees, for
+    /// [`crate::notifier::Notifier::with_tracing_layer`] with [`MAX_LEVEL`].
+    pub fn layer(&self) -> TracingLayer {
+        tracing_opentelemetry::layer()
+            .with_tracer(self.provider.tracer(env!("CARGO_PKG_NAME")))
+            .boxed()
+    }
+}
@@ -0,0 +1,140 @@
+//! Accounting for scratch space used during a conversion.
+//!
+//! A conversion keeps intermediate data in temporary directories: the image tarball
+//! exported by a source and the unpacked image (config + layer blobs). [`TempUsage`]
+//! records how many bytes each of those occupies, tracks the current and peak totals,
+//! and enforces an optional hard cap (`--max-temp-bytes`).
+//!
+//! - [`TempUsage::check`] — fail early if an upcoming allocation would exceed the cap.
+//! - [`TempUsage::charge`] / [`TempUsage::release`] — record and forget usage.
+//! - [`dir_size`] — measure a directory tree without following symlinks.
+
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::Path;
+
+/// Running totals of temporary disk usage with an optional hard limit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TempUsage {
+    limit: Option<u64>,
+    items: Vec<(String, u64)>,
+    peak: u64,
+}
+
+impl TempUsage {
+    /// Create an empty tracker; `limit` is the maximum number of bytes allowed at once.
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit,
+            items: Vec::new(),
+            peak: 0,
+        }
+    }
+
+    /// Return an error if adding `bytes` for `label` would exceed the limit.
+    pub fn check(&self, label: &str, bytes: u64) -> Result<()> {
+        if let Some(limit) = self.limit {
+            let total = self.current().saturating_add(bytes);
+            if total > limit {
+                return Err(anyhow!(
+                    "Temporary space limit exceeded: {label} needs {bytes} bytes, \
+                     {} bytes already in use, limit is {limit} bytes",
+                    self.current()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Record `bytes` of temporary data under `label`.
+    ///
+    /// The usage is recorded even when the limit is exceeded, so the returned error and
+    /// [`TempUsage::peak`] reflect what actually hit the disk.
+    pub fn charge(&mut self, label: &str, bytes: u64) -> Result<()> {
+        let result = self.check(label, bytes);
+        self.items.push((label.to_string(), bytes));
+        self.peak = self.peak.max(self.current());
+        result
+    }
+
+    /// Forget the usage recorded under `label` (its temporary data has been removed).
+    pub fn release(&mut self, label: &str) {
+        self.items.retain(|(item, _)| item != label);
+    }
+
+    /// Bytes currently in use.
+    pub fn current(&self) -> u64 {
+        self.items.iter().map(|(_, bytes)| bytes).sum()
+    }
+
+    /// Highest number of bytes in use at any point.
+    pub fn peak(&self) -> u64 {
+        self.peak
+    }
+
+    /// The configured limit, if any.
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+}
+
+/// Total size in bytes of all files below `path`. Symlinks are not followed and
+/// unreadable entries are ignored.
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| dir_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_charge_release_and_peak() {
+        let mut usage = TempUsage::new(None);
+        usage.charge("tarball", 100).unwrap();
+        usage.charge("extracted", 150).unwrap();
+        assert_eq!(usage.current(), 250);
+
+        usage.release("tarball");
+        assert_eq!(usage.current(), 150);
+        assert_eq!(usage.peak(), 250);
+    }
+
+    #[test]
+    fn test_limit_is_enforced() {
+        let mut usage = TempUsage::new(Some(200));
+        usage.charge("tarball", 150).unwrap();
+        assert!(usage.check("extracted", 50).is_ok());
+        assert!(usage.check("extracted", 51).is_err());
+
+        let err = usage.charge("extracted", 100).unwrap_err();
+        assert!(err.to_string().contains("limit is 200 bytes"));
+        assert_eq!(usage.peak(), 250);
+    }
+
+    #[test]
+    fn test_dir_size() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("a"), [0u8; 10]).unwrap();
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        fs::write(temp_dir.path().join("sub/b"), [0u8; 5]).unwrap();
+
+        assert_eq!(dir_size(temp_dir.path()), 15);
+        assert_eq!(dir_size(&temp_dir.path().join("missing")), 0);
+    }
+}
@@ -0,0 +1,151 @@
+//! Git trailers recorded on layer commits.
+//!
+//! Every layer commit ends with trailers naming the layer it represents:
+//!
+//! ```text
+//! 🟢 - RUN apk add curl
+//!
+//! Layer-Index: 3
+//! Layer-Digest: sha256:…
+//! Layer-Created: 2024-03-01T12:00:00+00:00
+//! Layer-Empty: false
+//! ```
+//!
+//! `Layer-Index` is 1-based and counts every history entry, empty layers included, so it
+//! matches the position of the layer in the `Image.md` layer table. The trailers travel
+//! with the commit, which lets history rewrites (see [`crate::squash`]) check a commit
+//! against the digest chain recorded in `Image.md` without relying on commit positions,
+//! and lets `git log` answer provenance questions directly:
+//!
+//! ```text
+//! git log --format='%(trailers:key=Layer-Created,valueonly)%x09%s'
+//! ```
+//!
+//! Commits are written through [`crate::git::GitRepo::commit_layer`]. `Layer-Created` and
+//! `Layer-Empty` were added later; commits converted before that only carry the first two.
+//!
+//! The metadata commit carries no layer trailers, but `Base-Image` and `Base-Branch` when
+//! the image's base is known (see [`crate::base_image`]), and `Build-Source`,
+//! `Build-Revision` and `Build-Dockerfile` when its build provenance is (see
+//! [`crate::build_provenance`]).
+
+use anyhow::{Context, Result};
+
+/// 1-based position of the layer in the image history.
+pub const LAYER_INDEX: &str = "Layer-Index";
+/// Digest of the layer (`empty` for layers without filesystem changes).
+pub const LAYER_DIGEST: &str = "Layer-Digest";
+/// Creation time of the history entry (RFC 3339).
+pub const LAYER_CREATED: &str = "Layer-Created";
+/// `true` for history entries without a filesystem layer.
+pub const LAYER_EMPTY: &str = "Layer-Empty";
+
+/// Layer information recorded in (and parsed from) the trailers of a commit message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerTrailers {
+    pub index: usize,
+    pub digest: String,
+    /// `None` on commits without a `Layer-Created` trailer
+    pub created: Option<String>,
+    /// `None` on commits without a `Layer-Empty` trailer
+    pub empty: Option<bool>,
+}
+
+impl LayerTrailers {
+    /// Trailers naming layer `index` (1-based) and its digest only.
+    pub fn new(index: usize, digest: &str) -> Self {
+        Self {
+            index,
+            digest: digest.to_string(),
+            created: None,
+            empty: None,
+        }
+    }
+}
+
+/// Commit message for a layer: `subject` followed by the layer trailers.
+pub fn layer_message(subject: &str, layer: &LayerTrailers) -> String {
+    let mut message = format!(
+        "{subject}\n\n{LAYER_INDEX}: {}\n{LAYER_DIGEST}: {}\n",
+        layer.index, layer.digest
+    );
+    if let Some(created) = &layer.created {
+        message.push_str(&format!("{LAYER_CREATED}: {created}\n"));
+    }
+    if let Some(empty) = layer.empty {
+        message.push_str(&format!("{LAYER_EMPTY}: {empty}\n"));
+    }
+    message
+}
+
+/// Parses the layer trailers of `message`; `None` if it isn't a layer commit.
+///
+/// # Errors
+/// - Trailer parsing failures, a `Layer-Index` that isn't a number or a `Layer-Empty`
+///   that isn't `true`/`false`.
+pub fn parse(message: &str) -> Result<Option<LayerTrailers>> {
+    let trailers = git2::message_trailers_strs(message).context("Failed to parse trailers")?;
+
+    let mut index = None;
+    let mut digest = None;
+    let mut created = None;
+    let mut empty = None;
+    for (key, value) in trailers.iter() {
+        match key {
+            LAYER_INDEX => {
+                index = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid {LAYER_INDEX} trailer: '{value}'"))?,
+                )
+            }
+            LAYER_DIGEST => digest = Some(value.to_string()),
+            LAYER_CREATED => created = Some(value.to_string()),
+            LAYER_EMPTY => {
+                empty = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid {LAYER_EMPTY} trailer: '{value}'"))?,
+                )
+            }
+            _ => {}
+        }
+    }
+
+    Ok(index.zip(digest).map(|(index, digest)| LayerTrailers {
+        index,
+        digest,
+        created,
+        empty,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_message_round_trip() {
+        let layer = LayerTrailers {
+            created: Some("2024-03-01T12:00:00+00:00".to_string()),
+            empty: Some(false),
+            ..LayerTrailers::new(2, "sha256:abc")
+        };
+        let message = layer_message("🟢 - RUN echo hi", &layer);
+        assert_eq!(
+            message,
+            "🟢 - RUN echo hi\n\nLayer-Index: 2\nLayer-Digest: sha256:abc\n\
+             Layer-Created: 2024-03-01T12:00:00+00:00\nLayer-Empty: false\n"
+        );
+        assert_eq!(parse(&message).unwrap(), Some(layer));
+
+        // Commits from before Layer-Created/Layer-Empty
+        assert_eq!(
+            parse("x\n\nLayer-Index: 2\nLayer-Digest: empty\n").unwrap(),
+            Some(LayerTrailers::new(2, "empty"))
+        );
+        assert_eq!(parse("🛠️ - Metadata").unwrap(), None);
+        assert!(parse("x\n\nLayer-Index: two\nLayer-Digest: empty\n").is_err());
+        assert!(parse("x\n\nLayer-Index: 2\nLayer-Digest: empty\nLayer-Empty: yes\n").is_err());
+    }
+}
@@ -0,0 +1,908 @@
+//! Layer application straight into Git trees, without a worktree.
+//!
+//! [`crate::layer_apply::LayerApplier`] unpacks every layer into `rootfs/` and the whole
+//! worktree is staged afterwards, so each file is written to disk, read back and hashed,
+//! and every layer commit scans the complete index. [`RootfsTree`] keeps `rootfs/` as an
+//! in-memory Git tree instead: the blobs of regular files are streamed from the tar entry
+//! into the object database, and only the directories a layer touches are loaded, changed
+//! and written back. Unchanged subtrees stay as they are, by id.
+//!
+//! [`TreeApplier`] follows the same overlay rules as the worktree applier, so both produce
+//! identical trees:
+//! - whiteouts (`.wh.<name>`) and opaque directories (`.wh..wh..opq`),
+//! - entry type changes according to a [`ConflictStrategy`],
+//! - symlinks in parent directories are followed, never leaving the root,
+//! - symlink targets are recorded as absolute paths below the rootfs directory, like the
+//!   worktree applier creates them,
+//! - hardlinks become copies of their target (resolved after all other entries),
+//! - the executable bit is taken from the owner permissions; empty directories are
+//!   dropped, as Git can't track them.
+//!
+//! [`LayerObserver`]s, [`TreeApplier::with_max_file_size`],
+//! [`TreeApplier::with_sparse_files`], [`TreeApplier::with_symlink_targets`] and
+//! [`TreeApplier::with_exclude`] behave exactly as for the worktree applier.
+
+use crate::estargz::Bookkeeping;
+use crate::fsmeta;
+use crate::large_files::FileStub;
+use crate::layer_apply::{
+    normalize_tar_path, pax_records, ConflictStrategy, EntryAction, EntryKind, LayerObserver,
+    ObservedEntry,
+};
+use crate::layer_verification::HashingReader;
+use crate::path_filter::PathFilter;
+use crate::sparse::{SparseEntry, SparseFiles};
+use crate::symlinks::{self, SymlinkTargets};
+use anyhow::{bail, Context, Result};
+use git2::{FileMode, Oid, Repository};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tar_rs as tar;
+
+/// Symlinks followed while resolving one path before giving up, like `ELOOP`.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// A directory of a [`RootfsTree`]: a stored tree that hasn't been needed yet, or its
+/// entries, loaded for changes.
+enum Dir {
+    Stored(Oid),
+    Loaded(BTreeMap<Vec<u8>, Node>),
+}
+
+enum Node {
+    Dir(Dir),
+    File {
+        blob: Oid,
+        executable: bool,
+    },
+    Symlink(Oid),
+    /// Anything else a stored tree may hold (submodules), kept as is.
+    Other {
+        id: Oid,
+        mode: i32,
+    },
+}
+
+impl Node {
+    fn kind(&self) -> EntryKind {
+        match self {
+            Self::Dir(_) => EntryKind::Directory,
+            Self::Symlink(_) => EntryKind::Symlink,
+            Self::File { .. } | Self::Other { .. } => EntryKind::File,
+        }
+    }
+}
+
+impl Dir {
+    fn empty() -> Self {
+        Self::Loaded(BTreeMap::new())
+    }
+
+    /// The entries of this directory, reading them from the stored tree if needed.
+    fn load(&mut self, repo: &Repository) -> Result<&mut BTreeMap<Vec<u8>, Node>> {
+        if let Self::Stored(id) = *self {
+            let tree = repo
+                .find_tree(id)
+                .with_context(|| format!("Failed to read tree {id}"))?;
+            let mut entries = BTreeMap::new();
+            for entry in tree.iter() {
+                let node = match entry.filemode() {
+                    mode if mode == i32::from(FileMode::Tree) => Node::Dir(Dir::Stored(entry.id())),
+                    mode if mode == i32::from(FileMode::Link) => Node::Symlink(entry.id()),
+                    mode if mode == i32::from(FileMode::BlobExecutable) => Node::File {
+                        blob: entry.id(),
+                        executable: true,
+                    },
+                    mode if mode == i32::from(FileMode::Blob) => Node::File {
+                        blob: entry.id(),
+                        executable: false,
+                    },
+                    mode => Node::Other {
+                        id: entry.id(),
+                        mode,
+                    },
+                };
+                entries.insert(entry.name_bytes().to_vec(), node);
+            }
+            *self = Self::Loaded(entries);
+        }
+        match self {
+            Self::Loaded(entries) => Ok(entries),
+            Self::Stored(_) => unreachable!("directory was just loaded"),
+        }
+    }
+
+    /// Writes the loaded directories below and including this one, and forgets their
+    /// entries. `None` for a directory without any files.
+    fn write(&mut self, repo: &Repository) -> Result<Option<Oid>> {
+        let entries = match self {
+            // Stored trees are never empty
+            Self::Stored(id) => return Ok(Some(*id)),
+            Self::Loaded(entries) => entries,
+        };
+        let mut builder = repo.treebuilder(None)?;
+        for (name, node) in entries.iter_mut() {
+            let (id, mode) = match node {
+                Node::Dir(dir) => match dir.write(repo)? {
+                    Some(id) => (id, FileMode::Tree.into()),
+                    None => continue,
+                },
+                Node::File {
+                    blob,
+                    executable: true,
+                } => (*blob, FileMode::BlobExecutable.into()),
+                Node::File { blob, .. } => (*blob, FileMode::Blob.into()),
+                Node::Symlink(blob) => (*blob, FileMode::Link.into()),
+                Node::Other { id, mode } => (*id, *mode),
+            };
+            builder
+                .insert(name.as_slice(), id, mode)
+                .context("Failed to add tree entry")?;
+        }
+        if builder.is_empty() {
+            // Keep the (empty) directory for later layers, but never write it
+            return Ok(None);
+        }
+        let id = builder.write().context("Failed to write tree")?;
+        *self = Self::Stored(id);
+        Ok(Some(id))
+    }
+}
+
+/// The `rootfs/` of a branch as an in-memory Git tree; see the module documentation.
+pub struct RootfsTree<'r> {
+    repo: &'r Repository,
+    /// Canonical rootfs directory that symlink targets are recorded below.
+    root: PathBuf,
+    dir: Dir,
+}
+
+impl<'r> RootfsTree<'r> {
+    /// The rootfs directory of `commit`, or an empty tree for `None` (or a commit
+    /// without one). `root` is the rootfs directory of the worktree, which symlink targets
+    /// are recorded below, and names the directory in the commit's tree (`rootfs` unless
+    /// set otherwise, see [`crate::convert_options::ConvertOptions::with_rootfs_dir`]);
+    /// it is created if needed.
+    ///
+    /// # Errors
+    /// - The commit can't be read, or `root` can't be created.
+    pub fn from_commit(repo: &'r Repository, commit: Option<Oid>, root: &Path) -> Result<Self> {
+        let name = root
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("Invalid rootfs directory: {}", root.display()))?
+            .to_string();
+        std::fs::create_dir_all(root)
+            .with_context(|| format!("Failed to create directory: {}", root.display()))?;
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve rootfs: {}", root.display()))?;
+        let dir = match commit {
+            Some(commit) => repo
+                .find_commit(commit)?
+                .tree()?
+                .get_name(&name)
+                .filter(|entry| entry.filemode() == i32::from(FileMode::Tree))
+                .map_or_else(Dir::empty, |entry| Dir::Stored(entry.id())),
+            None => Dir::empty(),
+        };
+        Ok(Self { repo, root, dir })
+    }
+
+    /// Writes the changed directories and returns the id of the rootfs tree, `None` if
+    /// it holds no files at all.
+    ///
+    /// # Errors
+    /// - Writing a tree fails.
+    pub fn write(&mut self) -> Result<Option<Oid>> {
+        self.dir.write(self.repo)
+    }
+
+    /// An applier for one layer.
+    pub fn applier(&mut self, strategy: ConflictStrategy) -> TreeApplier<'_, 'r> {
+        TreeApplier {
+            tree: self,
+            strategy,
+            pending_hardlinks: Vec::new(),
+            observer: None,
+            max_file_size: None,
+            sparse_files: SparseFiles::default(),
+            symlink_targets: SymlinkTargets::default(),
+            exclude: PathFilter::default(),
+            estargz: Bookkeeping::default(),
+        }
+    }
+
+    /// Adds a regular file at `path`, creating its parent directories; used for files
+    /// oci2git adds itself, like volume markers. A directory in the way is left alone.
+    ///
+    /// # Errors
+    /// - A parent is not a directory, or the blob can't be written.
+    pub fn add_file(&mut self, path: &Path, content: &[u8]) -> Result<()> {
+        let (parent, name) = self.split(path, true)?;
+        let blob = self.repo.blob(content).context("Failed to write blob")?;
+        let entries = self.entries(&parent, true)?.expect("parents were created");
+        if !matches!(entries.get(&name), Some(Node::Dir(_))) {
+            entries.insert(
+                name,
+                Node::File {
+                    blob,
+                    executable: false,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// The kind of entry at `path`, following symlinks in its parents but not at `path`
+    /// itself; `None` if there is nothing.
+    ///
+    /// # Errors
+    /// - Trees or symlink targets can't be read.
+    pub fn entry_kind(&mut self, path: &Path) -> Result<Option<EntryKind>> {
+        if path.as_os_str().is_empty() {
+            return Ok(Some(EntryKind::Directory));
+        }
+        let (parent, name) = self.split(path, false)?;
+        Ok(self
+            .entries(&parent, false)?
+            .and_then(|entries| entries.get(&name))
+            .map(Node::kind))
+    }
+
+    /// Whether `path`, following all symlinks, is a directory.
+    fn is_dir(&mut self, path: Vec<Vec<u8>>) -> Result<bool> {
+        let resolved = self.resolve(path)?;
+        let Some((name, parent)) = resolved.split_last() else {
+            return Ok(true);
+        };
+        Ok(matches!(
+            self.entries(parent, false)?
+                .and_then(|entries| entries.get(name)),
+            Some(Node::Dir(_))
+        ))
+    }
+
+    /// The parent directory of `path` with symlinks resolved, and the final name.
+    fn split(&mut self, path: &Path, create: bool) -> Result<(Vec<Vec<u8>>, Vec<u8>)> {
+        let mut components = components(path);
+        let name = components.pop().context("Path has no file name")?;
+        let parent = self.resolve(components)?;
+        if create {
+            self.entries(&parent, true)?;
+        }
+        Ok((parent, name))
+    }
+
+    /// Follows symlinks in every component of `path`, inside the root.
+    fn resolve(&mut self, path: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+        let mut pending: Vec<Vec<u8>> = path.into_iter().rev().collect();
+        let mut resolved: Vec<Vec<u8>> = Vec::new();
+        let mut hops = 0;
+        while let Some(name) = pending.pop() {
+            match name.as_slice() {
+                b"" | b"." => continue,
+                b".." => {
+                    resolved.pop();
+                    continue;
+                }
+                _ => {}
+            }
+            let link = match self
+                .entries(&resolved, false)?
+                .and_then(|entries| entries.get(&name))
+            {
+                Some(Node::Symlink(blob)) => *blob,
+                _ => {
+                    resolved.push(name);
+                    continue;
+                }
+            };
+            hops += 1;
+            if hops > MAX_SYMLINK_HOPS {
+                bail!("Too many levels of symbolic links resolving a layer path");
+            }
+            let target = self.repo.find_blob(link)?.content().to_vec();
+            let target = self.in_root(&target);
+            if target.starts_with(b"/") {
+                resolved.clear();
+            }
+            pending.extend(target.split(|&byte| byte == b'/').rev().map(<[u8]>::to_vec));
+        }
+        Ok(resolved)
+    }
+
+    /// A symlink target as a path inside the image: targets below the rootfs directory
+    /// (as recorded by both appliers) lose that prefix.
+    fn in_root<'t>(&self, target: &'t [u8]) -> &'t [u8] {
+        let root = self.root.as_os_str().as_encoded_bytes();
+        match target.strip_prefix(root) {
+            Some(b"") => b"/",
+            Some(rest) if rest.starts_with(b"/") => rest,
+            _ => target,
+        }
+    }
+
+    /// The entries of the (already resolved) directory `path`; missing directories are
+    /// created with `create`, `None` otherwise.
+    fn entries(
+        &mut self,
+        path: &[Vec<u8>],
+        create: bool,
+    ) -> Result<Option<&mut BTreeMap<Vec<u8>, Node>>> {
+        let repo = self.repo;
+        let mut dir = &mut self.dir;
+        for name in path {
+            let entries = dir.load(repo)?;
+            if !entries.contains_key(name) {
+                if !create {
+                    return Ok(None);
+                }
+                entries.insert(name.clone(), Node::Dir(Dir::empty()));
+            }
+            match entries.get_mut(name) {
+                Some(Node::Dir(child)) => dir = child,
+                _ if create => bail!(
+                    "Parent path exists but is not a directory: {}",
+                    String::from_utf8_lossy(&path.join(&b'/'))
+                ),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(dir.load(repo)?))
+    }
+}
+
+/// The components of a normalized relative path, as bytes.
+fn components(path: &Path) -> Vec<Vec<u8>> {
+    path.components()
+        .map(|component| component.as_os_str().as_encoded_bytes().to_vec())
+        .collect()
+}
+
+struct PendingHardlink {
+    parent: Vec<Vec<u8>>,
+    name: Vec<u8>,
+    target: PathBuf,
+}
+
+/// Applies the entries of one layer to a [`RootfsTree`]; the counterpart of
+/// [`crate::layer_apply::LayerApplier`].
+///
+/// Feed entries with [`TreeApplier::apply`] and call [`TreeApplier::finish`] once the layer
+/// is exhausted to resolve deferred hardlinks, or use [`TreeApplier::apply_archive`] for a
+/// whole archive. The tree is only written by [`RootfsTree::write`].
+pub struct TreeApplier<'t, 'r> {
+    tree: &'t mut RootfsTree<'r>,
+    strategy: ConflictStrategy,
+    pending_hardlinks: Vec<PendingHardlink>,
+    observer: Option<&'t mut dyn LayerObserver>,
+    max_file_size: Option<u64>,
+    sparse_files: SparseFiles,
+    symlink_targets: SymlinkTargets,
+    exclude: PathFilter,
+    estargz: Bookkeeping,
+}
+
+impl<'t, 'r> TreeApplier<'t, 'r> {
+    /// Reports every change to the tree to `observer`.
+    pub fn with_observer(mut self, observer: &'t mut dyn LayerObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Stores regular files larger than `max_file_size` bytes as stubs (see
+    /// [`crate::large_files`]).
+    pub fn with_max_file_size(mut self, max_file_size: Option<u64>) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Stores sparse files with their full content or as stubs (see [`crate::sparse`]).
+    pub fn with_sparse_files(mut self, sparse_files: SparseFiles) -> Self {
+        self.sparse_files = sparse_files;
+        self
+    }
+
+    /// Records symlink targets below the root or as in the image (see
+    /// [`crate::symlinks`]).
+    pub fn with_symlink_targets(mut self, symlink_targets: SymlinkTargets) -> Self {
+        self.symlink_targets = symlink_targets;
+        self
+    }
+
+    /// Skips entries whose path matches `exclude`. Whiteouts still apply.
+    pub fn with_exclude(mut self, exclude: PathFilter) -> Self {
+        self.exclude = exclude;
+        self
+    }
+
+    /// Treats the layer as an eStargz layer from the start (see
+    /// [`crate::estargz::Bookkeeping`]).
+    pub fn with_estargz(mut self, estargz: bool) -> Self {
+        self.estargz = Bookkeeping::new(estargz);
+        self
+    }
+
+    /// Applies every entry of `archive` and finishes the layer.
+    pub fn apply_archive<R: Read>(mut self, archive: &mut tar::Archive<R>) -> Result<()> {
+        for entry_result in archive.entries()? {
+            let mut entry = entry_result.context("Failed to read tar entry")?;
+            self.apply(&mut entry)?;
+        }
+        self.finish()
+    }
+
+    /// Applies a single entry. Hardlinks are queued for [`TreeApplier::finish`].
+    pub fn apply<R: Read>(&mut self, entry: &mut tar::Entry<R>) -> Result<()> {
+        let pax = pax_records(entry)?;
+        let sparse = SparseEntry::detect(entry.header(), &pax)?;
+        let size = sparse.as_ref().map_or(entry.size(), |sparse| sparse.size);
+        let mut observed = match self.observer {
+            Some(_) => Some(ObservedEntry::new(entry, pax, size)?),
+            None => None,
+        };
+        let hash_content = self
+            .observer
+            .as_deref()
+            .is_some_and(|observer| observer.wants_content_hash());
+        let entry_type = match sparse {
+            Some(_) => tar::EntryType::Regular,
+            None => entry.header().entry_type(),
+        };
+        let mut stub = None;
+
+        let tar_path = match sparse.as_ref().and_then(|sparse| sparse.name.clone()) {
+            Some(name) => name,
+            None => entry
+                .path()
+                .context("Failed to get entry path")?
+                .into_owned(),
+        };
+        let rel_path = normalize_tar_path(&tar_path);
+        if rel_path.as_os_str().is_empty() {
+            log::debug!("Skipping entry that resolves to the root: {tar_path:?}");
+            return Ok(());
+        }
+
+        if let Some(file_name) = rel_path.file_name().and_then(|n| n.to_str()) {
+            if file_name == ".wh..wh..opq" {
+                let parent = rel_path.parent().unwrap_or(Path::new(""));
+                let resolved = self.tree.resolve(components(parent))?;
+                if let Some(entries) = self.tree.entries(&resolved, false)? {
+                    log::debug!("Clearing opaque directory: {}", parent.display());
+                    entries.clear();
+                }
+                if let Some(observer) = self.observer.as_deref_mut() {
+                    observer.cleared(parent);
+                }
+                return Ok(());
+            } else if let Some(deleted_name) = file_name.strip_prefix(".wh.") {
+                if deleted_name.is_empty() || deleted_name == "." || deleted_name == ".." {
+                    bail!("Invalid whiteout entry: {}", rel_path.display());
+                }
+                let parent = rel_path.parent().unwrap_or(Path::new(""));
+                let resolved = self.tree.resolve(components(parent))?;
+                if let Some(entries) = self.tree.entries(&resolved, false)? {
+                    log::debug!("Whiting out: {}", parent.join(deleted_name).display());
+                    entries.remove(deleted_name.as_bytes());
+                }
+                if let Some(observer) = self.observer.as_deref_mut() {
+                    observer.removed(&parent.join(deleted_name));
+                }
+                return Ok(());
+            }
+        }
+
+        if self.exclude.matches(&rel_path) {
+            log::debug!("Excluding entry: {}", rel_path.display());
+            return Ok(());
+        }
+        if self
+            .estargz
+            .skips(&rel_path, entry_type == tar::EntryType::Regular, size)
+        {
+            log::debug!("Skipping eStargz entry: {}", rel_path.display());
+            return Ok(());
+        }
+
+        let (parent, name) = self.tree.split(&rel_path, true)?;
+        match entry_type {
+            tar::EntryType::Directory => {
+                if self.make_room(&parent, &name, EntryKind::Directory)? == EntryAction::Create {
+                    self.insert(&parent, name, Node::Dir(Dir::empty()))?;
+                }
+            }
+            tar::EntryType::Regular => {
+                let executable = entry.header().mode().is_ok_and(|mode| mode & 0o100 != 0);
+                self.make_room(&parent, &name, EntryKind::File)?;
+
+                let mut sparse_reader;
+                let (content, data_size): (&mut dyn Read, _) = match &sparse {
+                    Some(sparse) => {
+                        sparse_reader = sparse.open_entry(entry)?;
+                        let data_size = sparse_reader.data_size();
+                        (&mut sparse_reader, Some(data_size))
+                    }
+                    None => (entry, None),
+                };
+                let blob = if self.max_file_size.is_some_and(|max| size > max)
+                    || (data_size.is_some() && self.sparse_files == SparseFiles::Stub)
+                {
+                    let mut hasher = Sha256::new();
+                    std::io::copy(content, &mut hasher)
+                        .with_context(|| format!("Failed to read file: {}", rel_path.display()))?;
+                    let file_stub = FileStub {
+                        path: fsmeta::image_path(&rel_path),
+                        size,
+                        sha256: format!("{:x}", hasher.finalize()),
+                        data_size,
+                    };
+                    log::debug!("Writing stub for {} ({size} bytes)", file_stub.path);
+                    let blob = self.tree.repo.blob(file_stub.render().as_bytes())?;
+                    stub = Some(file_stub);
+                    blob
+                } else {
+                    let mut writer = self
+                        .tree
+                        .repo
+                        .blob_writer(None)
+                        .context("Failed to create blob")?;
+                    if hash_content {
+                        let mut reader = HashingReader::new(&mut *content);
+                        std::io::copy(&mut reader, &mut writer).with_context(|| {
+                            format!("Failed to write file: {}", rel_path.display())
+                        })?;
+                        if let Some(observed) = observed.as_mut() {
+                            observed.sha256 = Some(reader.finish_hex());
+                        }
+                    } else {
+                        std::io::copy(content, &mut writer).with_context(|| {
+                            format!("Failed to write file: {}", rel_path.display())
+                        })?;
+                    }
+                    writer.flush()?;
+                    writer.commit().context("Failed to write blob")?
+                };
+                self.insert(&parent, name, Node::File { blob, executable })?;
+            }
+            tar::EntryType::Symlink => {
+                let link_name = entry
+                    .link_name()
+                    .context("Failed to get symlink target")?
+                    .context("Symlink without target")?;
+                // Recorded like the worktree applier creates it (and GitRepo stages it):
+                // absolute, below the root, or as in the image (see crate::symlinks)
+                let link_parent = rel_path.parent().unwrap_or(Path::new(""));
+                let normalized = if link_name.is_absolute() {
+                    normalize_tar_path(&link_name)
+                } else {
+                    normalize_tar_path(&link_parent.join(&link_name))
+                };
+                let target = match self.symlink_targets {
+                    SymlinkTargets::Host => self.tree.root.join(normalized),
+                    SymlinkTargets::Image
+                        if link_name.is_relative()
+                            && symlinks::keeps_relative(
+                                &link_name,
+                                link_parent.components().count(),
+                            )
+                            && parent == components(link_parent) =>
+                    {
+                        link_name.into_owned()
+                    }
+                    SymlinkTargets::Image => Path::new("/").join(normalized),
+                };
+                self.make_room(&parent, &name, EntryKind::Symlink)?;
+                let blob = self.tree.repo.blob(target.as_os_str().as_encoded_bytes())?;
+                self.insert(&parent, name, Node::Symlink(blob))?;
+            }
+            tar::EntryType::Link => {
+                let link_name = entry
+                    .link_name()
+                    .context("Failed to get hardlink target")?
+                    .context("Hardlink without target")?;
+                let target = normalize_tar_path(&link_name);
+                self.make_room(&parent, &name, EntryKind::Hardlink)?;
+                self.pending_hardlinks.push(PendingHardlink {
+                    parent,
+                    name,
+                    target,
+                });
+            }
+            tar::EntryType::Char | tar::EntryType::Block | tar::EntryType::Fifo => {
+                // Not stored, but the node still takes the place of the previous entry
+                log::debug!(
+                    "Not storing special file: {entry_type:?} {}",
+                    rel_path.display()
+                );
+                if let Some(entries) = self.tree.entries(&parent, false)? {
+                    entries.remove(&name);
+                }
+            }
+            _ => {
+                log::debug!("Skipping unsupported entry type: {entry_type:?}");
+            }
+        }
+
+        if let (Some(observer), Some(mut observed)) = (self.observer.as_deref_mut(), observed) {
+            if let Some(stub) = &stub {
+                observed.sha256 = Some(stub.sha256.clone());
+            }
+            observer.entry(&rel_path, &observed);
+            if let Some(stub) = &stub {
+                observer.stubbed(stub);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies queued hardlinks from their targets; links whose target is missing at the
+    /// end of the layer are skipped.
+    pub fn finish(self) -> Result<()> {
+        for hardlink in self.pending_hardlinks {
+            if hardlink.target.as_os_str().is_empty() {
+                log::warn!("Skipping hardlink to the root directory");
+                continue;
+            }
+            let (parent, name) = self.tree.split(&hardlink.target, false)?;
+            let node = match self
+                .tree
+                .entries(&parent, false)?
+                .and_then(|entries| entries.get(&name))
+            {
+                Some(Node::File { blob, executable }) => Node::File {
+                    blob: *blob,
+                    executable: *executable,
+                },
+                Some(Node::Symlink(blob)) => Node::Symlink(*blob),
+                _ => {
+                    log::warn!(
+                        "Skipping broken hardlink (target missing): {} -> {}",
+                        String::from_utf8_lossy(&hardlink.name),
+                        hardlink.target.display()
+                    );
+                    continue;
+                }
+            };
+            if let Some(entries) = self.tree.entries(&hardlink.parent, true)? {
+                entries.insert(hardlink.name, node);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the overlay rules for an `incoming` entry at `name` in `parent`, like
+    /// [`crate::layer_apply::apply_entry`] does on disk.
+    fn make_room(
+        &mut self,
+        parent: &[Vec<u8>],
+        name: &[u8],
+        incoming: EntryKind,
+    ) -> Result<EntryAction> {
+        let Some(existing) = self
+            .tree
+            .entries(parent, false)?
+            .and_then(|entries| entries.get(name))
+            .map(Node::kind)
+        else {
+            return Ok(EntryAction::Create);
+        };
+        let mut path = parent.to_vec();
+        path.push(name.to_vec());
+
+        if incoming == EntryKind::Directory {
+            if existing == EntryKind::Directory {
+                return Ok(EntryAction::Merge);
+            }
+            if existing == EntryKind::Symlink
+                && self.strategy == ConflictStrategy::FollowSymlinks
+                && self.tree.is_dir(path.clone())?
+            {
+                return Ok(EntryAction::Merge);
+            }
+        }
+
+        let same_type = existing == incoming
+            || (existing == EntryKind::File && incoming == EntryKind::Hardlink);
+        if !same_type && self.strategy == ConflictStrategy::Abort {
+            bail!(
+                "Layer replaces {existing:?} with {incoming:?} at {}",
+                String::from_utf8_lossy(&path.join(&b'/'))
+            );
+        }
+        if let Some(entries) = self.tree.entries(parent, false)? {
+            entries.remove(name);
+        }
+        Ok(EntryAction::Create)
+    }
+
+    fn insert(&mut self, parent: &[Vec<u8>], name: Vec<u8>, node: Node) -> Result<()> {
+        if let Some(entries) = self.tree.entries(parent, true)? {
+            entries.insert(name, node);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::{tempdir, TempDir};
+
+    fn append(
+        builder: &mut tar::Builder<Vec<u8>>,
+        path: &str,
+        entry_type: tar::EntryType,
+        link_name: Option<&str>,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_size(data.len() as u64);
+        header.set_mode(if path.starts_with("bin/") {
+            0o755
+        } else {
+            0o644
+        });
+        if let Some(link_name) = link_name {
+            header.set_link_name(link_name).unwrap();
+        }
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+
+    fn repo() -> (TempDir, Repository) {
+        let temp_dir = tempdir().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        (temp_dir, repo)
+    }
+
+    /// Applies a layer built in memory and writes the tree
+    fn apply_layer(
+        tree: &mut RootfsTree,
+        strategy: ConflictStrategy,
+        build: impl FnOnce(&mut tar::Builder<Vec<u8>>),
+    ) -> Result<Option<Oid>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        let mut archive = tar::Archive::new(Cursor::new(builder.into_inner().unwrap()));
+        tree.applier(strategy).apply_archive(&mut archive)?;
+        tree.write()
+    }
+
+    /// Content and file mode at `path` of the tree `id`
+    fn read(repo: &Repository, id: Oid, path: &str) -> Option<(String, i32)> {
+        let entry = repo.find_tree(id).unwrap().get_path(Path::new(path)).ok()?;
+        let blob = repo.find_blob(entry.id()).ok()?;
+        Some((
+            String::from_utf8_lossy(blob.content()).into_owned(),
+            entry.filemode(),
+        ))
+    }
+
+    #[test]
+    fn test_files_directories_and_modes() {
+        let (dir, repo) = repo();
+        let mut tree = RootfsTree::from_commit(&repo, None, &dir.path().join("rootfs")).unwrap();
+        let id = apply_layer(&mut tree, ConflictStrategy::default(), |b| {
+            append(b, "etc/", tar::EntryType::Directory, None, b"");
+            append(b, "etc/hostname", tar::EntryType::Regular, None, b"box");
+            append(b, "bin/tool", tar::EntryType::Regular, None, b"bin");
+            append(b, "empty/", tar::EntryType::Directory, None, b"");
+        })
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            read(&repo, id, "etc/hostname"),
+            Some(("box".to_string(), FileMode::Blob.into()))
+        );
+        assert_eq!(
+            read(&repo, id, "bin/tool"),
+            Some(("bin".to_string(), FileMode::BlobExecutable.into()))
+        );
+        // Git can't track empty directories
+        assert!(repo.find_tree(id).unwrap().get_name("empty").is_none());
+    }
+
+    #[test]
+    fn test_whiteouts_hardlinks_and_symlinked_parents() {
+        let (dir, repo) = repo();
+        let rootfs = dir.path().join("rootfs");
+        let mut tree = RootfsTree::from_commit(&repo, None, &rootfs).unwrap();
+        let first = apply_layer(&mut tree, ConflictStrategy::default(), |b| {
+            append(b, "usr/lib/libc.so", tar::EntryType::Regular, None, b"libc");
+            append(b, "lib", tar::EntryType::Symlink, Some("usr/lib"), b"");
+            append(b, "a/keep", tar::EntryType::Regular, None, b"");
+            append(b, "a/gone", tar::EntryType::Regular, None, b"");
+            append(b, "b/old/nested", tar::EntryType::Regular, None, b"");
+        })
+        .unwrap()
+        .unwrap();
+        let root = rootfs.canonicalize().unwrap();
+        assert_eq!(
+            read(&repo, first, "lib"),
+            Some((
+                root.join("usr/lib").display().to_string(),
+                FileMode::Link.into()
+            ))
+        );
+
+        // A new tree starting from the written one, like the next conversion would
+        let mut tree = RootfsTree::from_commit(&repo, None, &rootfs).unwrap();
+        tree.dir = Dir::Stored(first);
+        let second = apply_layer(&mut tree, ConflictStrategy::default(), |b| {
+            append(b, "a/.wh.gone", tar::EntryType::Regular, None, b"");
+            append(b, "b/.wh..wh..opq", tar::EntryType::Regular, None, b"");
+            append(b, "b/new", tar::EntryType::Regular, None, b"");
+            append(b, "lib/libm.so", tar::EntryType::Regular, None, b"libm");
+            // Link appears before its target
+            append(b, "bin/sh", tar::EntryType::Link, Some("bin/bash"), b"");
+            append(b, "bin/bash", tar::EntryType::Regular, None, b"bash");
+            append(
+                b,
+                "bin/broken",
+                tar::EntryType::Link,
+                Some("bin/missing"),
+                b"",
+            );
+        })
+        .unwrap()
+        .unwrap();
+
+        assert!(read(&repo, second, "a/keep").is_some());
+        assert!(read(&repo, second, "a/gone").is_none());
+        assert!(read(&repo, second, "b/old/nested").is_none());
+        assert!(read(&repo, second, "b/new").is_some());
+        assert_eq!(read(&repo, second, "usr/lib/libm.so").unwrap().0, "libm");
+        assert_eq!(read(&repo, second, "bin/sh").unwrap().0, "bash");
+        assert!(read(&repo, second, "bin/broken").is_none());
+        // Untouched entries keep their ids
+        let lib = |id: Oid| repo.find_tree(id).unwrap().get_name("lib").unwrap().id();
+        assert_eq!(lib(first), lib(second));
+    }
+
+    #[test]
+    fn test_conflict_strategies() {
+        let base = |b: &mut tar::Builder<Vec<u8>>| {
+            append(b, "usr/lib/", tar::EntryType::Directory, None, b"");
+            append(b, "usr/lib/keep", tar::EntryType::Regular, None, b"");
+            append(b, "lib", tar::EntryType::Symlink, Some("usr/lib"), b"");
+        };
+        let update = |b: &mut tar::Builder<Vec<u8>>| {
+            append(b, "lib/", tar::EntryType::Directory, None, b"");
+            append(b, "lib/libc.so", tar::EntryType::Regular, None, b"x");
+        };
+
+        // Replace: the directory entry wins over the symlink
+        let (dir, repo) = repo();
+        let mut tree = RootfsTree::from_commit(&repo, None, &dir.path().join("rootfs")).unwrap();
+        apply_layer(&mut tree, ConflictStrategy::Replace, base).unwrap();
+        let id = apply_layer(&mut tree, ConflictStrategy::Replace, update)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            read(&repo, id, "lib/libc.so").unwrap().1,
+            i32::from(FileMode::Blob)
+        );
+        assert!(read(&repo, id, "usr/lib/libc.so").is_none());
+
+        // FollowSymlinks: merge through the symlink
+        let mut tree = RootfsTree::from_commit(&repo, None, &dir.path().join("rootfs")).unwrap();
+        apply_layer(&mut tree, ConflictStrategy::FollowSymlinks, base).unwrap();
+        let id = apply_layer(&mut tree, ConflictStrategy::FollowSymlinks, update)
+            .unwrap()
+            .unwrap();
+        assert_eq!(read(&repo, id, "lib").unwrap().1, i32::from(FileMode::Link));
+        assert!(read(&repo, id, "usr/lib/libc.so").is_some());
+
+        // Abort: any type change is an error
+        let mut tree = RootfsTree::from_commit(&repo, None, &dir.path().join("rootfs")).unwrap();
+        apply_layer(&mut tree, ConflictStrategy::Abort, base).unwrap();
+        assert!(apply_layer(&mut tree, ConflictStrategy::Abort, update).is_err());
+    }
+}
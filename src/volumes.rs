@@ -0,0 +1,106 @@
+//! Tracked markers for `VOLUME` declarations.
+//!
+//! Volumes only exist in the image config, so nothing about them would show up in the
+//! converted tree. [`write_markers`] materializes each declared volume as a directory in
+//! `rootfs/` containing a [`VOLUME_MARKER`] file, which makes Git track the (otherwise
+//! empty) directory and lets diffs show when an image gains or loses a volume.
+//! [`add_markers`] does the same for a [`RootfsTree`].
+
+use crate::layer_apply::{ensure_within_root, normalize_tar_path, EntryKind};
+use crate::tree_apply::RootfsTree;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Name of the marker file placed inside every declared volume directory.
+pub const VOLUME_MARKER: &str = ".oci2git-volume";
+
+/// Creates a marker for each volume path below `rootfs`.
+///
+/// Paths are resolved inside `rootfs` (never outside of it). Volumes whose path is
+/// occupied by something other than a directory are skipped with a warning.
+pub fn write_markers(rootfs: &Path, volumes: &[String]) -> Result<()> {
+    let root = rootfs
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve rootfs: {}", rootfs.display()))?;
+
+    for volume in volumes {
+        let relative = normalize_tar_path(Path::new(volume));
+        if relative.as_os_str().is_empty() {
+            log::warn!("Skipping volume marker for the root directory: {volume}");
+            continue;
+        }
+
+        let dir = root.join(&relative);
+        ensure_within_root(&root, &dir)?;
+        if fs::symlink_metadata(&dir).is_ok_and(|metadata| !metadata.is_dir()) {
+            log::warn!("Skipping volume marker, path is not a directory: {volume}");
+            continue;
+        }
+
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create volume directory: {}", dir.display()))?;
+        fs::write(dir.join(VOLUME_MARKER), format!("VOLUME {volume}\n"))
+            .with_context(|| format!("Failed to write volume marker for {volume}"))?;
+    }
+
+    Ok(())
+}
+
+/// Same as [`write_markers`], adding the markers to a [`RootfsTree`] instead of a
+/// directory (see [`crate::tree_apply`]).
+pub fn add_markers(tree: &mut RootfsTree, volumes: &[String]) -> Result<()> {
+    for volume in volumes {
+        let relative = normalize_tar_path(Path::new(volume));
+        if relative.as_os_str().is_empty() {
+            log::warn!("Skipping volume marker for the root directory: {volume}");
+            continue;
+        }
+        if tree
+            .entry_kind(&relative)?
+            .is_some_and(|kind| kind != EntryKind::Directory)
+        {
+            log::warn!("Skipping volume marker, path is not a directory: {volume}");
+            continue;
+        }
+
+        tree.add_file(
+            &relative.join(VOLUME_MARKER),
+            format!("VOLUME {volume}\n").as_bytes(),
+        )
+        .with_context(|| format!("Failed to add volume marker for {volume}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_markers() {
+        let temp_dir = tempdir().unwrap();
+        let rootfs = temp_dir.path();
+        fs::write(rootfs.join("file"), "x").unwrap();
+
+        let volumes = [
+            "/data".to_string(),
+            "/var/lib/db/".to_string(),
+            "/file".to_string(),
+            "/../../escape".to_string(),
+        ];
+        write_markers(rootfs, &volumes).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(rootfs.join("data").join(VOLUME_MARKER)).unwrap(),
+            "VOLUME /data\n"
+        );
+        assert!(rootfs.join("var/lib/db").join(VOLUME_MARKER).exists());
+        assert!(rootfs.join("file").is_file());
+        // `..` is clamped at the root
+        assert!(rootfs.join("escape").join(VOLUME_MARKER).exists());
+        assert!(!temp_dir.path().parent().unwrap().join("escape").exists());
+    }
+}
@@ -0,0 +1,86 @@
+//! Scratch space of a conversion.
+//!
+//! Sources export image tarballs and [`crate::extracted_image::ExtractedImage`] unpacks
+//! them into temporary directories, by default under the system temporary directory
+//! (`TMPDIR`). A [`Workdir`] set with
+//! [`crate::processor::ImageProcessor::with_workdir`] (`--workdir`) puts them on another
+//! disk, e.g. a large fast one, for a single run. Every directory is removed once it is
+//! no longer needed, unless [`Workdir::with_keep`] (`--keep-workdir`) keeps them around for
+//! debugging.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// Where temporary directories are created, and whether they are cleaned up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Workdir {
+    dir: Option<PathBuf>,
+    keep: bool,
+}
+
+impl Workdir {
+    /// Temporary directories under `dir`, the system temporary directory for `None`.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir, keep: false }
+    }
+
+    /// Leave the temporary directories (exported tarballs, unpacked images) behind.
+    pub fn with_keep(mut self, keep: bool) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    /// The directory temporary directories are created in.
+    pub fn path(&self) -> PathBuf {
+        self.dir.clone().unwrap_or_else(std::env::temp_dir)
+    }
+
+    /// Whether temporary directories are kept.
+    pub fn keep(&self) -> bool {
+        self.keep
+    }
+
+    /// Creates a temporary directory named `<prefix><random>`, creating the workdir
+    /// itself if needed.
+    ///
+    /// # Errors
+    /// - The workdir or the directory can't be created.
+    pub fn tempdir(&self, prefix: &str) -> Result<TempDir> {
+        let base = self.path();
+        fs::create_dir_all(&base)
+            .with_context(|| format!("Failed to create work directory {}", base.display()))?;
+        let mut temp_dir = tempfile::Builder::new()
+            .prefix(prefix)
+            .tempdir_in(&base)
+            .with_context(|| {
+                format!("Failed to create temporary directory in {}", base.display())
+            })?;
+        temp_dir.disable_cleanup(self.keep);
+        Ok(temp_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_workdir_cleanup() {
+        let base = tempdir().unwrap();
+        let workdir = Workdir::new(Some(base.path().join("scratch")));
+
+        let temp_dir = workdir.tempdir("oci2git-test-").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        assert!(path.starts_with(base.path().join("scratch")));
+        drop(temp_dir);
+        assert!(!path.exists());
+
+        let temp_dir = workdir.with_keep(true).tempdir("oci2git-test-").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        drop(temp_dir);
+        assert!(path.exists());
+    }
+}
@@ -0,0 +1,259 @@
+//! `oci2git checkout`: image branches checked out side by side.
+//!
+//! Comparing the filesystems of several converted images means checking out more than
+//! one branch at a time. [`checkout`] adds a Git worktree for a branch in a directory of
+//! its own, so `diff -r a/rootfs b/rootfs` or any other tool works on plain directories,
+//! while the objects stay in the converted repository.
+//!
+//! The worktrees are managed by oci2git: they are named `oci2git-<dir>` (see
+//! [`WORKTREE_PREFIX`]) and their `HEAD` is detached at the tip of the branch, so the branch
+//! stays free for further conversions and worktrees of other tools are left alone.
+//! [`list`] shows them, [`remove`] deletes one with its directory, and worktrees whose
+//! directory was deleted by hand are pruned whenever one is added or listed. The repack
+//! after a conversion or `prune` keeps the commits they are on.
+
+use crate::git::GitRepo;
+use crate::query::rootfs_dir;
+use anyhow::{bail, Context, Result};
+use git2::{BranchType, Repository, Worktree, WorktreeAddOptions, WorktreePruneOptions};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name prefix of the worktrees oci2git manages.
+pub const WORKTREE_PREFIX: &str = "oci2git-";
+
+/// File in the administrative directory of a worktree naming its branch.
+const BRANCH_FILE: &str = "oci2git-branch";
+
+/// A worktree added by [`checkout`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManagedWorktree {
+    /// Worktree name, `oci2git-<dir>`
+    pub name: String,
+    pub path: PathBuf,
+    /// Branch that was checked out
+    pub branch: Option<String>,
+    /// Commit `HEAD` is detached at
+    pub commit: Option<String>,
+    /// Directory holding the image filesystem, e.g. `<path>/rootfs`
+    pub rootfs: Option<PathBuf>,
+}
+
+/// Checks out the tip of `branch` into the new directory `to`, as a worktree of `repo`.
+///
+/// # Errors
+/// - `branch` doesn't exist, or `to` exists and isn't an empty directory.
+/// - Adding the worktree or checking it out fails.
+pub fn checkout(repo: &GitRepo, branch: &str, to: &Path) -> Result<ManagedWorktree> {
+    let git = &repo.repo;
+    let commit = git
+        .find_branch(branch, BranchType::Local)
+        .with_context(|| format!("Branch '{branch}' does not exist"))?
+        .get()
+        .peel_to_commit()?;
+    if to.exists() {
+        let is_empty_dir = fs::read_dir(to).is_ok_and(|mut entries| entries.next().is_none());
+        if !is_empty_dir {
+            bail!("{} already exists and is not empty", to.display());
+        }
+        fs::remove_dir(to).with_context(|| format!("Failed to remove {}", to.display()))?;
+    }
+    if let Some(parent) = to.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    prune_stale(git)?;
+
+    let name = worktree_name(git, to)?;
+    // libgit2 only adds worktrees on a branch, and refuses one checked out elsewhere:
+    // start on a throwaway branch, then detach
+    let temporary = git.branch(&format!("{name}/checkout"), &commit, true)?;
+    let added = git
+        .worktree(
+            &name,
+            to,
+            Some(WorktreeAddOptions::new().reference(Some(temporary.get()))),
+        )
+        .with_context(|| format!("Failed to add worktree at {}", to.display()));
+    let detached = added.and_then(|worktree| {
+        Repository::open_from_worktree(&worktree)?.set_head_detached(commit.id())?;
+        Ok(worktree)
+    });
+    temporary.into_reference().delete()?;
+    let worktree = detached?;
+    fs::write(admin_dir(git, &name).join(BRANCH_FILE), branch)?;
+    describe(git, &worktree)
+}
+
+/// The worktrees added by [`checkout`], by name.
+///
+/// # Errors
+/// - The worktrees can't be read.
+pub fn list(repo: &GitRepo) -> Result<Vec<ManagedWorktree>> {
+    let git = &repo.repo;
+    prune_stale(git)?;
+    managed_names(git)?
+        .iter()
+        .map(|name| describe(git, &git.find_worktree(name)?))
+        .collect()
+}
+
+/// Deletes the worktree added by [`checkout`] at `path` (or named `path`), with its
+/// directory.
+///
+/// # Errors
+/// - No such worktree, or it can't be deleted.
+pub fn remove(repo: &GitRepo, path: &Path) -> Result<ManagedWorktree> {
+    let target = path.canonicalize().ok();
+    let removed = list(repo)?
+        .into_iter()
+        .find(|worktree| {
+            Path::new(&worktree.name) == path || worktree.path.canonicalize().ok() == target
+        })
+        .with_context(|| format!("{} is no worktree added by oci2git", path.display()))?;
+    prune(&repo.repo.find_worktree(&removed.name)?, true)?;
+    Ok(removed)
+}
+
+/// Deletes every worktree added by [`checkout`], with their directories.
+///
+/// # Errors
+/// - The worktrees can't be read or deleted.
+pub fn remove_all(repo: &GitRepo) -> Result<Vec<ManagedWorktree>> {
+    let removed = list(repo)?;
+    for worktree in &removed {
+        prune(&repo.repo.find_worktree(&worktree.name)?, true)?;
+    }
+    Ok(removed)
+}
+
+fn managed_names(git: &Repository) -> Result<Vec<String>> {
+    let mut names: Vec<String> = git
+        .worktrees()?
+        .iter()
+        .flatten()
+        .filter(|name| name.starts_with(WORKTREE_PREFIX))
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Forgets managed worktrees whose directory is gone.
+fn prune_stale(git: &Repository) -> Result<()> {
+    for name in managed_names(git)? {
+        let worktree = git.find_worktree(&name)?;
+        if worktree.validate().is_err() {
+            log::debug!("Pruning worktree '{name}', its directory is gone");
+            prune(&worktree, false)?;
+        }
+    }
+    Ok(())
+}
+
+fn prune(worktree: &Worktree, working_tree: bool) -> Result<()> {
+    worktree
+        .prune(Some(
+            WorktreePruneOptions::new()
+                .valid(true)
+                .working_tree(working_tree),
+        ))
+        .with_context(|| format!("Failed to remove worktree at {}", worktree.path().display()))
+}
+
+fn admin_dir(git: &Repository, name: &str) -> PathBuf {
+    git.path().join("worktrees").join(name)
+}
+
+/// `oci2git-<dir name>`, with a number appended while taken.
+fn worktree_name(git: &Repository, to: &Path) -> Result<String> {
+    let base: String = to
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let base = format!("{WORKTREE_PREFIX}{}", base.trim_matches('-'));
+    let taken = git.worktrees()?;
+    let taken: Vec<&str> = taken.iter().flatten().collect();
+    Ok((1..)
+        .map(|n| match n {
+            1 => base.clone(),
+            n => format!("{base}-{n}"),
+        })
+        .find(|name| !taken.contains(&name.as_str()) && !admin_dir(git, name).exists())
+        .expect("unbounded"))
+}
+
+fn describe(git: &Repository, worktree: &Worktree) -> Result<ManagedWorktree> {
+    let name = worktree
+        .name()
+        .context("Worktree name is not UTF-8")?
+        .to_string();
+    let branch = fs::read_to_string(admin_dir(git, &name).join(BRANCH_FILE))
+        .ok()
+        .map(|branch| branch.trim().to_string());
+    let head = Repository::open_from_worktree(worktree)
+        .ok()
+        .and_then(|repo| repo.head().ok().and_then(|head| head.target()));
+    let rootfs = head
+        .and_then(|oid| git.find_commit(oid).ok())
+        .and_then(|commit| commit.tree().ok())
+        .map(|tree| worktree.path().join(rootfs_dir(git, &tree)));
+    Ok(ManagedWorktree {
+        name,
+        path: worktree.path().to_path_buf(),
+        branch,
+        commit: head.map(|oid| oid.to_string()),
+        rootfs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_checkout_list_remove() {
+        let temp_dir = tempdir().unwrap();
+        let repo = GitRepo::init_with_branch(&temp_dir.path().join("repo"), Some("app#1")).unwrap();
+        let rootfs = temp_dir.path().join("repo/rootfs");
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::write(rootfs.join("hello.txt"), "v1").unwrap();
+        assert!(repo.commit_all_changes("layer").unwrap());
+        assert!(checkout(&repo, "missing", &temp_dir.path().join("x")).is_err());
+
+        // The branch checked out in the repository itself works too, twice
+        let a = checkout(&repo, "app#1", &temp_dir.path().join("a")).unwrap();
+        assert_eq!(a.name, "oci2git-a");
+        assert_eq!(a.branch.as_deref(), Some("app#1"));
+        let rootfs_a = a.rootfs.clone().unwrap();
+        assert_eq!(
+            fs::read_to_string(rootfs_a.join("hello.txt")).unwrap(),
+            "v1"
+        );
+        let b = checkout(&repo, "app#1", &temp_dir.path().join("other/a")).unwrap();
+        assert_eq!(b.name, "oci2git-a-2");
+        // No branch is left behind
+        assert_eq!(repo.get_all_branches().unwrap(), vec!["app#1".to_string()]);
+        assert!(checkout(&repo, "app#1", &temp_dir.path().join("a")).is_err());
+
+        assert_eq!(list(&repo).unwrap(), vec![a.clone(), b.clone()]);
+        assert_eq!(remove(&repo, &temp_dir.path().join("a")).unwrap(), a);
+        assert!(!temp_dir.path().join("a").exists());
+        assert!(remove(&repo, &temp_dir.path().join("a")).is_err());
+
+        // Deleted by hand: forgotten on the next listing
+        fs::remove_dir_all(temp_dir.path().join("other")).unwrap();
+        assert_eq!(list(&repo).unwrap(), vec![]);
+    }
+}
@@ -1,5 +1,6 @@
 //! Common utilities for integration tests
 
+pub mod multiarch;
 pub mod tar_processing;
 
 /// Common test images used across integration tests
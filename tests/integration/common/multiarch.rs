@@ -0,0 +1,227 @@
+//! Synthetic multi-platform images served by a local registry
+//!
+//! Platform features (listing, selection, one branch per platform) are exercised against
+//! an image index that is generated on the fly, so tests neither pull real images nor
+//! need QEMU. The fixture image `multiarch/app:latest` has:
+//! - one manifest per platform in [`PLATFORMS`], each with its own config (architecture,
+//!   variant, `ARCH` environment variable),
+//! - a base layer shared by all platforms (identical blob and diff ID),
+//! - a platform specific layer writing `etc/arch`,
+//! - a BuildKit style attestation manifest (`unknown/unknown`) that isn't a platform.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+
+/// Platforms of the fixture image
+pub const PLATFORMS: &[&str] = &["linux/amd64", "linux/arm64", "linux/arm/v7"];
+
+/// Repository and tag of the fixture image
+pub const IMAGE: &str = "multiarch/app:latest";
+
+/// Contents of the shared base layer
+pub const BASE_FILE: (&str, &[u8]) = ("etc/os-release", b"ID=synthetic\n");
+
+/// A running fixture registry
+pub struct MultiArchRegistry {
+    /// `host:port` of the registry
+    pub address: String,
+    /// Manifest digest per platform, in [`PLATFORMS`] order
+    pub manifest_digests: Vec<String>,
+}
+
+impl MultiArchRegistry {
+    /// The fixture image reference on this registry
+    pub fn image(&self) -> String {
+        format!("{}/{IMAGE}", self.address)
+    }
+
+    /// Manifest digest of `platform` (one of [`PLATFORMS`])
+    pub fn manifest_digest(&self, platform: &str) -> &str {
+        let index = PLATFORMS.iter().position(|p| *p == platform).unwrap();
+        &self.manifest_digests[index]
+    }
+}
+
+/// `sha256:<hex>` of `data`
+fn sha256(data: &[u8]) -> String {
+    let hex: String = Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256:{hex}")
+}
+
+/// Plain tar with the given files, and its gzipped blob
+fn layer(files: &[(&str, &[u8])]) -> (Vec<u8>, Vec<u8>) {
+    let mut builder = tar_rs::Builder::new(Vec::new());
+    for (path, data) in files {
+        let mut header = tar_rs::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, *data).unwrap();
+    }
+    let tar = builder.into_inner().unwrap();
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::fast());
+    gzip.write_all(&tar).unwrap();
+    (tar, gzip.finish().unwrap())
+}
+
+type Routes = HashMap<String, (String, Vec<u8>)>;
+
+/// Stores `data` as a blob or manifest of the fixture repository and returns its digest
+fn add(routes: &mut Routes, kind: &str, content_type: &str, data: Vec<u8>) -> String {
+    let digest = sha256(&data);
+    let repository = IMAGE.split(':').next().unwrap();
+    routes.insert(
+        format!("/v2/{repository}/{kind}/{digest}"),
+        (content_type.to_string(), data),
+    );
+    digest
+}
+
+/// Generates the fixture image and serves it on localhost until the test process exits
+pub fn serve_multiarch() -> MultiArchRegistry {
+    let mut routes = Routes::new();
+    let (base_tar, base_blob) = layer(&[BASE_FILE]);
+    let base_digest = add(&mut routes, "blobs", "application/octet-stream", base_blob);
+
+    let mut descriptors = Vec::new();
+    let mut manifest_digests = Vec::new();
+    for platform in PLATFORMS {
+        let mut parts = platform.split('/');
+        let (os, architecture, variant) =
+            (parts.next().unwrap(), parts.next().unwrap(), parts.next());
+        let arch = &platform[os.len() + 1..];
+
+        let (arch_tar, arch_blob) = layer(&[("etc/arch", arch.as_bytes())]);
+        let arch_digest = add(&mut routes, "blobs", "application/octet-stream", arch_blob);
+
+        let mut config = json!({
+            "architecture": architecture,
+            "os": os,
+            "config": {"Env": ["PATH=/bin", format!("ARCH={arch}")]},
+            "rootfs": {"type": "layers", "diff_ids": [sha256(&base_tar), sha256(&arch_tar)]},
+            "history": [
+                {"created": "2024-01-01T00:00:00Z", "created_by": "ADD os-release /etc/"},
+                {"created": "2024-01-02T00:00:00Z", "created_by": format!("RUN echo {arch} > /etc/arch")},
+            ],
+        });
+        if let Some(variant) = variant {
+            config["variant"] = json!(variant);
+        }
+        let config_digest = add(
+            &mut routes,
+            "blobs",
+            "application/vnd.oci.image.config.v1+json",
+            serde_json::to_vec(&config).unwrap(),
+        );
+
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {"mediaType": "application/vnd.oci.image.config.v1+json", "digest": config_digest, "size": 1},
+            "layers": [
+                {"mediaType": "application/vnd.oci.image.layer.v1.tar+gzip", "digest": base_digest, "size": 1},
+                {"mediaType": "application/vnd.oci.image.layer.v1.tar+gzip", "digest": arch_digest, "size": 1},
+            ],
+        });
+        let manifest_digest = add(
+            &mut routes,
+            "manifests",
+            "application/vnd.oci.image.manifest.v1+json",
+            serde_json::to_vec(&manifest).unwrap(),
+        );
+
+        let mut platform_json = json!({"os": os, "architecture": architecture});
+        if let Some(variant) = variant {
+            platform_json["variant"] = json!(variant);
+        }
+        descriptors.push(json!({
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "digest": manifest_digest,
+            "size": 1,
+            "platform": platform_json,
+        }));
+        manifest_digests.push(manifest_digest);
+    }
+
+    // BuildKit attaches provenance as an extra manifest without a real platform
+    let attestation = json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "config": {"digest": sha256(b"{}"), "size": 2},
+        "layers": [],
+    });
+    let attestation_digest = add(
+        &mut routes,
+        "manifests",
+        "application/vnd.oci.image.manifest.v1+json",
+        serde_json::to_vec(&attestation).unwrap(),
+    );
+    descriptors.push(json!({
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "digest": attestation_digest,
+        "size": 1,
+        "platform": {"os": "unknown", "architecture": "unknown"},
+        "annotations": {"vnd.docker.reference.type": "attestation-manifest"},
+    }));
+
+    let index: Value = json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": descriptors,
+    });
+    let (repository, tag) = IMAGE.split_once(':').unwrap();
+    routes.insert(
+        format!("/v2/{repository}/manifests/{tag}"),
+        (
+            "application/vnd.oci.image.index.v1+json".to_string(),
+            serde_json::to_vec(&index).unwrap(),
+        ),
+    );
+
+    MultiArchRegistry {
+        address: serve(routes),
+        manifest_digests,
+    }
+}
+
+/// Minimal anonymous registry: answers `GET`s from `routes`, 404 otherwise
+fn serve(routes: Routes) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line.trim().is_empty() {
+                    break;
+                }
+            }
+
+            let path = request_line.split(' ').nth(1).unwrap_or("/");
+            let (status, content_type, body) = match routes.get(path) {
+                Some((content_type, body)) => (200, content_type.as_str(), body.as_slice()),
+                None => (404, "text/plain", &b"not found"[..]),
+            };
+            let response = format!(
+                "HTTP/1.1 {status} X\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(response.as_bytes());
+            let _ = stream.write_all(body);
+        }
+    });
+
+    address
+}
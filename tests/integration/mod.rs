@@ -5,5 +5,7 @@
 
 pub mod common;
 pub mod docker;
+pub mod multiarch;
 pub mod nerdctl;
+pub mod podman;
 pub mod tar;
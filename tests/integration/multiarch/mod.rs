@@ -0,0 +1,282 @@
+//! Multi-platform integration tests
+//!
+//! Runs the registry engine against the synthetic image index from
+//! [`crate::integration::common::multiarch`], covering platform listing and selection,
+//! branch naming per platform and converting a whole index into one repository.
+
+use crate::integration::common::multiarch::{serve_multiarch, BASE_FILE, PLATFORMS};
+use anyhow::Result;
+use oci2git::blob_cache::BlobCache;
+use oci2git::branch_naming::BranchTemplate;
+use oci2git::convert_options::ConvertOptions;
+use oci2git::git::GitRepo;
+use oci2git::image_diff::diff_branches;
+use oci2git::notifier::Notifier;
+use oci2git::processor::ImageProcessor;
+use oci2git::repo_readme::README_BRANCH;
+use oci2git::sources::{Platform, Source};
+use oci2git::RegistrySource;
+use std::fs;
+use tempfile::TempDir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A registry source with its own empty blob cache
+    fn registry_source(platform: Option<&str>) -> Result<(RegistrySource, TempDir)> {
+        let cache_dir = TempDir::new()?;
+        let source = RegistrySource::new()?
+            .with_blob_cache(BlobCache::new(cache_dir.path()))
+            .with_platform(platform.map(str::parse).transpose()?);
+        Ok((source, cache_dir))
+    }
+
+    /// Image branches of the repository at `output`
+    fn image_branches(output: &TempDir) -> Result<Vec<String>> {
+        let mut branches = GitRepo::open(output.path())?.get_all_branches()?;
+        branches.retain(|branch| branch != README_BRANCH);
+        Ok(branches)
+    }
+
+    /// `#<os>-<arch>#<short manifest digest>`, the platform part of a branch name
+    fn branch_suffix(platform: &str, manifest_digest: &str) -> String {
+        let platform: Platform = platform.parse().unwrap();
+        let hex = manifest_digest.strip_prefix("sha256:").unwrap();
+        format!("#{}-{}#{}", platform.os, platform.architecture, &hex[..12])
+    }
+
+    #[test]
+    fn test_list_platforms_skips_attestations() -> Result<()> {
+        let registry = serve_multiarch();
+        let (source, _cache_dir) = registry_source(None)?;
+
+        let platforms = source.platforms(&registry.image(), &Notifier::new(0))?;
+        let expected: Vec<Platform> = PLATFORMS.iter().map(|p| p.parse().unwrap()).collect();
+        assert_eq!(platforms, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_selected_platform() -> Result<()> {
+        let registry = serve_multiarch();
+
+        for platform in PLATFORMS {
+            let output = TempDir::new()?;
+            let (source, _cache_dir) = registry_source(Some(platform))?;
+            ImageProcessor::new(source, Notifier::new(0))
+                .convert(&registry.image(), output.path())?;
+
+            let branches = image_branches(&output)?;
+            assert_eq!(branches.len(), 1);
+            assert!(
+                branches[0].ends_with(&branch_suffix(platform, registry.manifest_digest(platform))),
+                "unexpected branch {} for {platform}",
+                branches[0]
+            );
+
+            let arch = fs::read_to_string(output.path().join("rootfs/etc/arch"))?;
+            assert_eq!(&arch, &platform["linux/".len()..]);
+            let image_md = fs::read_to_string(output.path().join("Image.md"))?;
+            assert!(image_md.contains(&format!("ARCH={arch}")));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_platform_is_host() -> Result<()> {
+        let host = Platform::host();
+        let Some(platform) = PLATFORMS
+            .iter()
+            .find(|p| p.parse::<Platform>().unwrap() == host)
+        else {
+            println!("Skipping test: host platform {host} is not part of the fixture");
+            return Ok(());
+        };
+
+        let registry = serve_multiarch();
+        let output = TempDir::new()?;
+        let (source, _cache_dir) = registry_source(None)?;
+        ImageProcessor::new(source, Notifier::new(0)).convert(&registry.image(), output.path())?;
+
+        let branches = image_branches(&output)?;
+        assert!(branches[0].ends_with(&branch_suffix(platform, registry.manifest_digest(platform))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_platform_lists_available_ones() -> Result<()> {
+        let registry = serve_multiarch();
+        let output = TempDir::new()?;
+        let (source, _cache_dir) = registry_source(Some("linux/s390x"))?;
+
+        let err = ImageProcessor::new(source, Notifier::new(0))
+            .convert(&registry.image(), output.path())
+            .unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("no manifest for platform linux/s390x"));
+        assert!(message.contains("linux/amd64, linux/arm64, linux/arm"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_index_into_one_repository() -> Result<()> {
+        let registry = serve_multiarch();
+        let output = TempDir::new()?;
+        let (source, _cache_dir) = registry_source(None)?;
+
+        let mut processor = ImageProcessor::new(source, Notifier::new(0));
+        let (platforms, summaries) =
+            processor.convert_all_platforms(&registry.image(), output.path())?;
+        assert_eq!(platforms.len(), PLATFORMS.len());
+        assert_eq!(summaries.len(), PLATFORMS.len());
+
+        // One branch per platform, named after its own manifest
+        let branches = image_branches(&output)?;
+        assert_eq!(branches.len(), PLATFORMS.len());
+        for platform in PLATFORMS {
+            let suffix = branch_suffix(platform, registry.manifest_digest(platform));
+            assert!(
+                branches.iter().any(|branch| branch.ends_with(&suffix)),
+                "no branch for {platform} in {branches:?}"
+            );
+        }
+
+        // The shared base layer is a single commit all platforms build on
+        let repo = GitRepo::open(output.path())?;
+        let mut base_commits = Vec::new();
+        for branch in &branches {
+            let commits = repo.get_branch_commits(branch)?;
+            assert_eq!(
+                commits.len(),
+                3,
+                "base, platform layer and metadata on {branch}"
+            );
+            assert!(repo
+                .read_file_from_commit(commits[0], "layers/0001.json")
+                .is_ok());
+            base_commits.push(commits[0]);
+            assert_ne!(commits[1], base_commits[0]);
+        }
+        base_commits.dedup();
+        assert_eq!(base_commits.len(), 1);
+
+        // The README on the main branch lists every platform
+        let readme_commit = *repo.get_branch_commits(README_BRANCH)?.last().unwrap();
+        let readme = repo.read_file_from_commit(readme_commit, "README.md")?;
+        for branch in &branches {
+            assert!(
+                readme.contains(branch.as_str()),
+                "{branch} missing from README"
+            );
+        }
+
+        // The base file is identical everywhere
+        let base =
+            repo.read_file_from_commit(base_commits[0], &format!("rootfs/{}", BASE_FILE.0))?;
+        assert_eq!(base.as_bytes(), BASE_FILE.1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_with_options() -> Result<()> {
+        let registry = serve_multiarch();
+        let output = TempDir::new()?;
+        let (source, _cache_dir) = registry_source(None)?;
+
+        let options = ConvertOptions::new()
+            .with_platform(Some("linux/arm64".parse()?))
+            .with_branch_name(Some("multiarch/arm64".to_string()))
+            .with_commit_message_template(Some("{index}: {command}".to_string()));
+        ImageProcessor::new(source, Notifier::new(0)).convert_with_options(
+            &registry.image(),
+            output.path(),
+            &options,
+        )?;
+
+        assert_eq!(
+            image_branches(&output)?,
+            vec!["multiarch/arm64".to_string()]
+        );
+        assert_eq!(
+            fs::read_to_string(output.path().join("rootfs/etc/arch"))?,
+            "arm64"
+        );
+        let repo = GitRepo::open(output.path())?;
+        let commits = repo.get_branch_commits("multiarch/arm64")?;
+        let subject = repo.commit_message(commits[1])?;
+        assert!(subject.starts_with("2: RUN echo arm64 > /etc/arch\n"));
+
+        let (source, _cache_dir) = registry_source(None)?;
+        let invalid = ConvertOptions::new().with_branch_name(Some("bad..name".to_string()));
+        assert!(ImageProcessor::new(source, Notifier::new(0))
+            .convert_with_options(&registry.image(), output.path(), &invalid)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_platforms() -> Result<()> {
+        let registry = serve_multiarch();
+        let output = TempDir::new()?;
+        for (platform, branch) in [("linux/amd64", "amd64"), ("linux/arm64", "arm64")] {
+            let (source, _cache_dir) = registry_source(None)?;
+            let options = ConvertOptions::new()
+                .with_platform(Some(platform.parse()?))
+                .with_branch_name(Some(branch.to_string()));
+            ImageProcessor::new(source, Notifier::new(0)).convert_with_options(
+                &registry.image(),
+                output.path(),
+                &options,
+            )?;
+        }
+
+        let diff = diff_branches(&GitRepo::open(output.path())?, "amd64", "arm64")?;
+        assert_eq!(diff.shared_layers, 1);
+        assert_eq!((diff.layers_a.len(), diff.layers_b.len()), (1, 1));
+        assert!(diff.files.added.is_empty() && diff.files.removed.is_empty());
+        assert_eq!(diff.files.changed.len(), 1);
+        assert_eq!(diff.files.changed[0].path, "/etc/arch");
+        assert_eq!(diff.size_a, diff.size_b);
+        let platform = diff
+            .metadata
+            .iter()
+            .find(|change| change.field == "platform")
+            .unwrap();
+        assert_eq!(
+            (platform.a.as_str(), platform.b.as_str()),
+            ("linux/amd64", "linux/arm64")
+        );
+
+        let markdown = diff.render_markdown();
+        assert!(markdown.contains("| changed | `/etc/arch` | 5 | 5 |"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_branch_template() -> Result<()> {
+        let registry = serve_multiarch();
+        let output = TempDir::new()?;
+        let (source, _cache_dir) = registry_source(None)?;
+
+        let template = BranchTemplate::new("images/{name}/{tag}/{arch}")?;
+        ImageProcessor::new(source, Notifier::new(0))
+            .with_branch_naming(Some(Box::new(template)))
+            .convert_all_platforms(&registry.image(), output.path())?;
+
+        let mut branches = image_branches(&output)?;
+        branches.sort();
+        let host = registry
+            .image()
+            .split('/')
+            .next()
+            .unwrap()
+            .replace(':', "-");
+        let expected: Vec<String> = ["amd64", "arm", "arm64"]
+            .iter()
+            .map(|arch| format!("images/{host}/multiarch/app/latest/{arch}"))
+            .collect();
+        assert_eq!(branches, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+//! Podman integration tests
+//!
+//! Tests the Podman provider's ability to export images as tar files.
+//! Like Docker, `podman save` produces tar files that get processed through
+//! the universal tar processing backend.
+
+#[cfg(all(test, feature = "podman"))]
+mod tests {
+    use crate::integration::common::*;
+    use oci2git::notifier::Notifier;
+    use oci2git::processor::ImageProcessor;
+    use oci2git::sources::{PodmanSource, Source};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_podman_image_download_hello_world() {
+        let image_name = "quay.io/podman/hello:latest";
+        let podman_source = PodmanSource::new().expect("Should create PodmanSource");
+        let (tarball_path, temp_dir) = podman_source
+            .get_image_tarball(image_name, &Notifier::new(0))
+            .expect("Should successfully export image tarball");
+
+        assert!(tarball_path.exists(), "Tarball file should exist");
+        assert!(fs::metadata(&tarball_path).unwrap().len() > 0);
+        assert!(temp_dir.is_some(), "TempDir should be provided");
+    }
+
+    #[test]
+    fn test_podman_image_download_nonexistent() {
+        let notifier = Notifier::new(0);
+        let podman_source = PodmanSource::new().expect("Should create PodmanSource");
+        assert!(podman_source
+            .get_image_tarball(NONEXISTENT_IMAGE, &notifier)
+            .is_err());
+
+        // Without pulls, the missing image is reported before podman is asked for it
+        let podman_source = podman_source.with_pull(false);
+        let error = podman_source
+            .get_image_tarball(NONEXISTENT_IMAGE, &notifier)
+            .unwrap_err();
+        assert!(error.to_string().contains("--no-pull"), "{error}");
+    }
+
+    #[test]
+    fn test_podman_tar_processing_produces_git_repo() {
+        let output_dir = TempDir::new().expect("Should create temp output dir");
+        let podman_source = PodmanSource::new().expect("Should create PodmanSource");
+        let processor = ImageProcessor::new(podman_source, Notifier::new(0));
+
+        let result = processor.convert("quay.io/podman/hello:latest", output_dir.path());
+        assert!(result.is_ok(), "Should process Podman image: {result:?}");
+
+        tar_processing::verify_git_structure(output_dir.path())
+            .expect("Should have proper git structure");
+    }
+}
@@ -6,11 +6,51 @@
 
 use crate::integration::common::tar_processing;
 use anyhow::Result;
+use oci2git::analyze;
+use oci2git::branch_naming::BranchTemplate;
+use oci2git::config_file::{ConfigFile, CONFIG_FILE};
+use oci2git::config_history::CONFIG_DIR;
+use oci2git::content_hashes::CONTENT_HASHES_PATH;
+use oci2git::conversion_cache::{ConversionCache, SEED_BRANCH_PREFIX};
+use oci2git::convert_options::{ConvertOptions, ROOTFS_DIR_PATH};
+use oci2git::converted_repo::ConvertedRepo;
+use oci2git::deleted_files::DELETED_FILES_PATH;
+use oci2git::error::Oci2GitError;
+use oci2git::estargz;
+use oci2git::extracted_image::{ExtractedImage, LayerMismatch};
+use oci2git::file_log::{self, PathChange};
+use oci2git::from_layer::{FromLayer, FROM_LAYER_SUFFIX};
+use oci2git::fsmeta::{FsMetadata, FSMETA_PATH};
+use oci2git::git::GitRepo;
+use oci2git::git2oci::{export_branch, ExportOptions};
+use oci2git::html_report::REPORT_PATH;
+use oci2git::image_metadata::{ImageMetadata, MetadataFormat, IMAGE_JSON, IMAGE_MD};
+use oci2git::inspect::Inspection;
+use oci2git::large_files::{LargeFiles, LARGE_FILES_PATH};
+use oci2git::layer_apply::ObservedEntry;
+use oci2git::layer_records::{self, LayerRecords, LAYERS_DIR, LAYER_NOTES_REF};
+use oci2git::layer_stats::{LayerStatsReport, LAYER_STATS_JSON, LAYER_STATS_MD};
+use oci2git::layer_verification::DigestVerification;
+use oci2git::layout::{Layout, SUBDIR_BRANCH};
+use oci2git::list;
+use oci2git::materialize_config::{PORTS_TXT, PROFILE_PATH, VOLUMES_TXT};
 use oci2git::notifier::Notifier;
-use oci2git::processor::ImageProcessor;
-use oci2git::sources::{Source, TarSource};
+use oci2git::path_filter::PathFilter;
+use oci2git::plugins::{LayerInfo, LayerProcessor, PluginFile};
+use oci2git::processor::{ImageProcessor, METADATA_ONLY_SUFFIX, PARTIAL_PATH, SQUASHED_SUFFIX};
+use oci2git::progress::{ProgressEvent, ProgressSink};
+use oci2git::prune::{self, PruneOptions};
+use oci2git::repo_readme::{BRANCH_README, README_BRANCH};
+use oci2git::rootfs::{materialize_rootfs, RootfsOptions};
+use oci2git::sources::{Source, StreamSource, TarSource};
+use oci2git::summary::{ConversionStatus, ConversionSummary, LayerSummary};
+use oci2git::symlinks::{SymlinkTargets, SYMLINK_TARGETS_PATH};
+use oci2git::trailers;
+use oci2git::workdir::Workdir;
+use oci2git::worktrees;
 use std::io::Write;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use tempfile::{NamedTempFile, TempDir};
 
 #[cfg(test)]
@@ -203,6 +243,2112 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_analyze_writes_nothing() -> Result<()> {
+        if !Path::new(FIXTURE_TAR_PATH).exists() {
+            println!("Skipping test: fixture tar file not found at {FIXTURE_TAR_PATH}");
+            return Ok(());
+        }
+
+        let temp_dir = TempDir::new()?;
+        let output_dir = temp_dir.path().join("repo");
+        let processor = ImageProcessor::new(TarSource::new()?, Notifier::new(0));
+
+        let plan = processor.analyze(FIXTURE_TAR_PATH, &output_dir)?;
+
+        assert!(!output_dir.exists(), "analyze() must not write the output");
+        assert!(!plan.layers.is_empty());
+        assert!(plan.total_size() > 0);
+        assert_eq!(plan.branch.start_from, None);
+        assert_eq!(plan.branch.skip_layers, 0);
+        assert!(!plan.branch.is_duplicate);
+        assert_eq!(plan.layers_to_process(), plan.layers.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_temp_bytes() -> Result<()> {
+        if !Path::new(FIXTURE_TAR_PATH).exists() {
+            println!("Skipping test: fixture tar file not found at {FIXTURE_TAR_PATH}");
+            return Ok(());
+        }
+
+        let temp_dir = TempDir::new()?;
+        let output_dir = temp_dir.path().join("repo");
+
+        let processor =
+            ImageProcessor::new(TarSource::new()?, Notifier::new(0)).with_max_temp_bytes(Some(1));
+        let err = processor
+            .analyze(FIXTURE_TAR_PATH, &output_dir)
+            .err()
+            .expect("a 1 byte limit must be exceeded");
+        assert!(err.to_string().contains("Temporary space limit exceeded"));
+
+        let processor = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .with_max_temp_bytes(Some(u64::MAX));
+        let plan = processor.analyze(FIXTURE_TAR_PATH, &output_dir)?;
+        assert!(plan.temp_usage.peak() > 0);
+        assert!(plan.temp_usage.peak() >= plan.total_size());
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_from_stream() -> Result<()> {
+        if !Path::new(FIXTURE_TAR_PATH).exists() {
+            println!("Skipping test: fixture tar file not found at {FIXTURE_TAR_PATH}");
+            return Ok(());
+        }
+
+        let bytes = std::fs::read(FIXTURE_TAR_PATH)?;
+        let from_file = ExtractedImage::from_tarball(FIXTURE_TAR_PATH, &Notifier::new(0))?;
+
+        // Gzipped streams are detected like gzipped files
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&bytes)?;
+        let gzipped = encoder.finish()?;
+        let from_stream = ExtractedImage::from_reader(gzipped.as_slice(), &Notifier::new(0))?;
+        assert_eq!(
+            from_stream.metadata("stream")?.id,
+            from_file.metadata(FIXTURE_TAR_PATH)?.id
+        );
+        assert_eq!(from_stream.layers()?.len(), from_file.layers()?.len());
+
+        let temp_dir = TempDir::new()?;
+        let output_dir = temp_dir.path().join("repo");
+        let source = StreamSource::new(std::io::Cursor::new(bytes));
+        ImageProcessor::new(source, Notifier::new(0)).convert("in-memory", &output_dir)?;
+
+        assert!(output_dir.join("Image.md").exists());
+        tar_processing::verify_git_structure(&output_dir)?;
+        let repo = oci2git::GitRepo::open(&output_dir)?;
+        let head = repo.repo.head()?;
+        assert!(head.shorthand().unwrap().starts_with("in-memory#"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_materialize_rootfs_matches_converted_rootfs() -> Result<()> {
+        if !Path::new(FIXTURE_TAR_PATH).exists() {
+            println!("Skipping test: fixture tar file not found at {FIXTURE_TAR_PATH}");
+            return Ok(());
+        }
+
+        let rootfs_dir = TempDir::new()?;
+        materialize_rootfs(
+            FIXTURE_TAR_PATH,
+            rootfs_dir.path(),
+            &RootfsOptions::default(),
+        )?;
+        assert!(!rootfs_dir.path().join(".git").exists());
+        assert!(!rootfs_dir.path().join("Image.md").exists());
+
+        let repo_dir = TempDir::new()?;
+        let processor = ImageProcessor::new(TarSource::new()?, Notifier::new(0));
+        processor.convert(FIXTURE_TAR_PATH, repo_dir.path())?;
+
+        let listing = |root: &Path| -> Vec<std::path::PathBuf> {
+            let mut paths = Vec::new();
+            let mut stack = vec![root.to_path_buf()];
+            while let Some(dir) = stack.pop() {
+                for entry in std::fs::read_dir(&dir).unwrap() {
+                    let path = entry.unwrap().path();
+                    if path.is_dir() && !path.is_symlink() {
+                        stack.push(path.clone());
+                    }
+                    paths.push(path.strip_prefix(root).unwrap().to_path_buf());
+                }
+            }
+            paths.sort();
+            paths
+        };
+        let materialized = listing(rootfs_dir.path());
+        assert!(!materialized.is_empty());
+        assert_eq!(materialized, listing(&repo_dir.path().join("rootfs")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_materialize_plan_and_detect_duplicate() -> Result<()> {
+        if !Path::new(FIXTURE_TAR_PATH).exists() {
+            println!("Skipping test: fixture tar file not found at {FIXTURE_TAR_PATH}");
+            return Ok(());
+        }
+
+        let output_dir = TempDir::new()?;
+        let processor = ImageProcessor::new(TarSource::new()?, Notifier::new(0));
+
+        let plan = processor.analyze(FIXTURE_TAR_PATH, output_dir.path())?;
+        processor.materialize(&plan, output_dir.path())?;
+        tar_processing::verify_git_structure(output_dir.path())?;
+        assert!(output_dir.path().join("Image.md").exists());
+
+        // Analyzing the same image again finds every layer already in place
+        let again = processor.analyze(FIXTURE_TAR_PATH, output_dir.path())?;
+        assert!(again.branch.is_duplicate);
+        assert_eq!(again.branch.name, plan.branch.name);
+        assert_eq!(again.branch.skip_layers, again.layers.len());
+        assert_eq!(again.layers_to_process(), 0);
+        assert!(!again.warnings.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_converted_images() -> Result<()> {
+        if !Path::new(FIXTURE_TAR_PATH).exists() {
+            println!("Skipping test: fixture tar file not found at {FIXTURE_TAR_PATH}");
+            return Ok(());
+        }
+
+        let output_dir = TempDir::new()?;
+        let summary = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .convert(FIXTURE_TAR_PATH, output_dir.path())?;
+        let images = list::list(&GitRepo::open(output_dir.path())?)?;
+        assert_eq!(images.len(), 1);
+        let image = &images[0];
+        assert_eq!(image.branch, summary.branch);
+        assert_eq!(image.image, "oci2git-test");
+        assert_eq!(image.tag.as_deref(), Some("latest"));
+        assert!(image.digest.starts_with("sha256:"));
+        assert_eq!(image.platform, "linux/arm64");
+        assert_eq!(image.layers, 13);
+        assert!(!image.converted_at.is_empty());
+
+        let table = list::render_table(&images);
+        assert_eq!(table.lines().count(), 2);
+        assert!(table
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("oci2git-test  latest  "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect_layer_history() -> Result<()> {
+        if !Path::new(FIXTURE_TAR_PATH).exists() {
+            println!("Skipping test: fixture tar file not found at {FIXTURE_TAR_PATH}");
+            return Ok(());
+        }
+
+        let scratch = TempDir::new()?;
+        let plan = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .analyze(FIXTURE_TAR_PATH, scratch.path())?;
+        let inspection = Inspection::new(FIXTURE_TAR_PATH, plan.extracted_image())?;
+        assert!(!scratch.path().join(".git").exists());
+        assert_eq!(inspection.tags, vec!["oci2git-test:latest"]);
+        assert_eq!(inspection.layers.len(), plan.layers.len());
+        assert_eq!(inspection.layers[0].index, 1);
+        assert!(inspection.layers[0].size > 0);
+        assert!(inspection
+            .layers
+            .iter()
+            .any(|layer| layer.empty && layer.digest == "empty"));
+
+        let table = inspection.render_table();
+        assert!(table.contains("Tags:     oci2git-test:latest"));
+        assert!(table.contains("COPY hello.txt /app/hello.txt"));
+        assert_eq!(table.lines().count(), 7 + inspection.layers.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_failed_layer_extraction() -> Result<()> {
+        // An image whose only layer is not a tar archive
+        let config = serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": {"type": "layers", "diff_ids": [format!("sha256:{}", "a".repeat(64))]},
+            "history": [{"created": "2024-01-01T00:00:00Z", "created_by": "ADD broken /"}],
+        });
+        let manifest = serde_json::json!([{
+            "Config": "config.json",
+            "RepoTags": [],
+            "Layers": [format!("blobs/sha256/{}", "a".repeat(64))],
+        }]);
+        let mut builder = tar_rs::Builder::new(Vec::new());
+        for (path, data) in [
+            ("config.json".to_string(), serde_json::to_vec(&config)?),
+            ("manifest.json".to_string(), serde_json::to_vec(&manifest)?),
+            (format!("blobs/sha256/{}", "a".repeat(64)), vec![b'x'; 2048]),
+        ] {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, data.as_slice())?;
+        }
+        let image = builder.into_inner()?;
+
+        let temp_dir = TempDir::new()?;
+        let keep_dir = temp_dir.path().join("failed");
+        let err = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_keep_failed(Some(keep_dir.clone()))
+        .convert("broken", &temp_dir.path().join("repo"))
+        .unwrap_err();
+
+        let kept = std::fs::read_dir(&keep_dir)?
+            .next()
+            .unwrap()?
+            .path()
+            .join("layer-001");
+        assert!(format!("{err:#}").contains(&kept.display().to_string()));
+        assert!(kept.join("manifest.json").exists());
+        assert!(kept.join("blobs/sha256").join("a".repeat(64)).exists());
+        let report = std::fs::read_to_string(kept.join("FAILED-LAYER.txt"))?;
+        assert!(report.contains("command: ADD broken /"));
+        assert!(report.contains(&format!("tarball: blobs/sha256/{}", "a".repeat(64))));
+        Ok(())
+    }
+
+    fn layer(build: impl FnOnce(&mut tar_rs::Builder<Vec<u8>>)) -> Vec<u8> {
+        let mut builder = tar_rs::Builder::new(Vec::new());
+        build(&mut builder);
+        builder.into_inner().unwrap()
+    }
+
+    /// A `docker save` style image tagged `app:latest` with one layer per `(command, tar)`
+    fn image_with_layers(layers: Vec<(&str, Vec<u8>)>) -> Result<Vec<u8>> {
+        image_with_config(layers, serde_json::json!({}))
+    }
+
+    /// [`image_with_layers`] with `container_config` as the `config` of the image
+    fn image_with_config(
+        layers: Vec<(&str, Vec<u8>)>,
+        container_config: serde_json::Value,
+    ) -> Result<Vec<u8>> {
+        let digests: Vec<String> = (0..layers.len())
+            .map(|i| format!("sha256:{}", i.to_string().repeat(64)))
+            .collect();
+        let history: Vec<_> = layers
+            .iter()
+            .map(|(command, _)| {
+                serde_json::json!({"created": "2024-01-01T00:00:00Z", "created_by": command})
+            })
+            .collect();
+        let config = serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "config": container_config,
+            "rootfs": {"type": "layers", "diff_ids": digests},
+            "history": history,
+        });
+        let layer_paths: Vec<String> = (0..layers.len()).map(|i| format!("layer{i}.tar")).collect();
+        let manifest = serde_json::json!([{
+            "Config": "config.json",
+            "RepoTags": ["app:latest"],
+            "Layers": layer_paths,
+        }]);
+
+        let mut builder = tar_rs::Builder::new(Vec::new());
+        let mut entries = vec![
+            ("config.json".to_string(), serde_json::to_vec(&config)?),
+            ("manifest.json".to_string(), serde_json::to_vec(&manifest)?),
+        ];
+        entries.extend(
+            layer_paths
+                .into_iter()
+                .zip(layers.into_iter().map(|(_, tar)| tar)),
+        );
+        for (path, data) in entries {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, data.as_slice())?;
+        }
+        Ok(builder.into_inner()?)
+    }
+
+    /// The image branch of a repository holding a single conversion
+    fn image_branch(repo: &GitRepo) -> Result<String> {
+        Ok(repo
+            .get_all_branches()?
+            .into_iter()
+            .find(|b| b != "main")
+            .unwrap())
+    }
+
+    #[test]
+    fn test_fs_metadata_sidecar() -> Result<()> {
+        let file = |mode, uid| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(mode);
+            header.set_uid(uid);
+            header
+        };
+        let image = image_with_layers(vec![
+            (
+                "ADD tool /",
+                layer(|b| {
+                    b.append_data(&mut file(0o4755, 0), "bin/tool", &b"x"[..])
+                        .unwrap();
+                    b.append_data(&mut file(0o600, 1000), "home/app/key", &b"x"[..])
+                        .unwrap();
+                }),
+            ),
+            (
+                "RUN rm key",
+                layer(|b| {
+                    let mut whiteout = file(0o644, 0);
+                    whiteout.set_size(0);
+                    b.append_data(&mut whiteout, "home/app/.wh.key", &[][..])
+                        .unwrap();
+                }),
+            ),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_fs_metadata(true)
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        let first = FsMetadata::parse(&repo.read_file_from_commit(commits[0], FSMETA_PATH)?)?;
+        assert_eq!(first.entries["/bin/tool"].mode, "4755");
+        assert_eq!(first.entries["/home/app/key"].uid, 1000);
+
+        let last = FsMetadata::parse(&std::fs::read_to_string(
+            output_dir.path().join(FSMETA_PATH),
+        )?)?;
+        assert!(last.entries.contains_key("/bin/tool"));
+        assert!(!last.entries.contains_key("/home/app/key"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_partial() -> Result<()> {
+        let image = image_with_layers(vec![
+            (
+                "ADD app /",
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(1);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, "app", &b"x"[..]).unwrap();
+                }),
+            ),
+            // Starts like a tar header but is cut short
+            (
+                "RUN corrupt",
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(4096);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, "half-written", &[b'x'; 4096][..])
+                        .unwrap();
+                })[..1024]
+                    .to_vec(),
+            ),
+            ("RUN never", layer(|_| {})),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        let err = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_commit_partial(true)
+        .with_rootfs_dir(Some("fs".to_string()))
+        .convert("app:latest", output_dir.path())
+        .unwrap_err();
+        assert!(err.to_string().contains("is PARTIAL"));
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        assert_eq!(commits.len(), 2);
+        let layer = trailers::parse(&repo.commit_message(commits[0])?)?.unwrap();
+        assert_eq!(
+            (layer.index, layer.created.as_deref(), layer.empty),
+            (1, Some("2024-01-01T00:00:00+00:00"), Some(false))
+        );
+        assert_eq!(
+            repo.commit_message(commits[1])?,
+            "🛠️ - Metadata (PARTIAL: layer 2/3 failed)"
+        );
+        let report = repo.read_file_from_commit(commits[1], PARTIAL_PATH)?;
+        assert!(report.contains("- **Failed layer**: 2/3"));
+        assert!(report.contains("- **Command**: `RUN corrupt`"));
+        assert!(report.contains("`fs/` and `Image.md` don't contain"));
+
+        // The half-extracted layer is gone, Image.md lists the good layer only
+        assert!(output_dir.path().join("fs/app").exists());
+        assert!(!output_dir.path().join("fs/half-written").exists());
+        let metadata = std::fs::read_to_string(output_dir.path().join("Image.md"))?;
+        assert!(metadata.contains("ADD app /"));
+        assert!(!metadata.contains("RUN corrupt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_metadata_format() -> Result<()> {
+        let image = || {
+            image_with_layers(vec![(
+                "ADD app /",
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(1);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, "app", &b"x"[..]).unwrap();
+                }),
+            )])
+        };
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_metadata_format(MetadataFormat::Json)
+        .convert("app:latest", output_dir.path())?;
+
+        assert!(!output_dir.path().join(IMAGE_MD).exists());
+        let metadata = ImageMetadata::load_json(&output_dir.path().join(IMAGE_JSON))?;
+        assert_eq!(metadata.layer_digests.len(), 1);
+        assert_eq!(metadata.layer_digests[0].command, "ADD app /");
+
+        // Layer matching reads Image.json: converting again with Markdown reuses the layer
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .convert("app:latest", output_dir.path())?;
+        let repo = GitRepo::open(output_dir.path())?;
+        assert_eq!(repo.get_branch_commits(&image_branch(&repo)?)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tag_tip() -> Result<()> {
+        let image = || {
+            image_with_layers(vec![(
+                "ADD app /",
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(1);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, "app", &b"x"[..]).unwrap();
+                }),
+            )])
+        };
+        let convert = |output: &Path| -> Result<ConversionSummary> {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image()?)),
+                Notifier::new(0),
+            )
+            .with_tag_template(Some(BranchTemplate::new("{name}/{tag}/{arch}")?))
+            .convert("app:latest", output)
+            .map_err(Into::into)
+        };
+
+        let output_dir = TempDir::new()?;
+        convert(output_dir.path())?;
+        // Converting the same image again keeps the tag where it is
+        convert(output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let tags = repo.repo.tag_names(None)?;
+        assert_eq!(tags.len(), 1);
+        let name = tags.get(0).unwrap();
+        assert_eq!(name, "app/latest/amd64");
+        let tag = repo
+            .repo
+            .find_reference(&format!("refs/tags/{name}"))?
+            .peel_to_tag()?;
+        let tip = *repo
+            .get_branch_commits(&image_branch(&repo)?)?
+            .last()
+            .unwrap();
+        assert_eq!(tag.target_id(), tip);
+        assert!(tag
+            .message()
+            .unwrap()
+            .starts_with("app:latest (linux/amd64)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_only() -> Result<()> {
+        let image = || {
+            image_with_layers(vec![(
+                "ADD app /",
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(1);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, "app", &b"x"[..]).unwrap();
+                }),
+            )])
+        };
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_metadata_only(true)
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let branch = image_branch(&repo)?;
+        assert!(branch.ends_with(METADATA_ONLY_SUFFIX), "{branch}");
+        let commits = repo.get_branch_commits(&branch)?;
+        assert_eq!(commits.len(), 2);
+        assert!(repo
+            .read_file_from_commit(commits[0], "rootfs/app")
+            .is_err());
+        let metadata = ImageMetadata::read_from_commit(&repo, commits[1])?.unwrap();
+        assert_eq!(metadata.layer_digests[0].command, "ADD app /");
+
+        // A full conversion doesn't reuse the commits without files
+        let mut processor = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        );
+        processor.convert_with_options(
+            "app:latest",
+            output_dir.path(),
+            &ConvertOptions::new().with_branch_name(Some("full".to_string())),
+        )?;
+        let full = repo.get_branch_commits("full")?;
+        assert_ne!(full[0], commits[0]);
+        assert!(repo.read_file_from_commit(full[0], "rootfs/app").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_file_size_stubs() -> Result<()> {
+        let image = image_with_layers(vec![(
+            "ADD model /opt/",
+            layer(|b| {
+                for (path, data) in [("opt/model.bin", &b"0123456789"[..]), ("opt/README", b"hi")] {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(data.len() as u64);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, path, data).unwrap();
+                }
+            }),
+        )])?;
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_max_file_size(Some(4))
+        .convert("model:latest", output_dir.path())?;
+
+        let rootfs = output_dir.path().join("rootfs/opt");
+        assert_eq!(std::fs::read_to_string(rootfs.join("README"))?, "hi");
+        let stub = std::fs::read_to_string(rootfs.join("model.bin"))?;
+        assert!(stub.contains("path: /opt/model.bin\nsize: 10\n"));
+        let manifest = LargeFiles::parse(&std::fs::read_to_string(
+            output_dir.path().join(LARGE_FILES_PATH),
+        )?)?;
+        assert_eq!(manifest.entries.len(), 1);
+        assert_eq!(manifest.entries["/opt/model.bin"].size, 10);
+
+        // The content isn't in the repository, so there's nothing to export
+        let repo = GitRepo::open(output_dir.path())?;
+        let archive = output_dir.path().join("exported.tar");
+        let error = export_branch(
+            &repo,
+            &image_branch(&repo)?,
+            &archive,
+            &ExportOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("--max-file-size"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_deletions() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, &b"x"[..]).unwrap();
+        };
+        let image = image_with_layers(vec![
+            (
+                "ADD app /",
+                layer(|b| {
+                    file(b, "app");
+                    file(b, "cache/index");
+                }),
+            ),
+            (
+                "RUN rm -rf /app /cache/*",
+                layer(|b| {
+                    file(b, ".wh.app");
+                    file(b, "cache/.wh..wh..opq");
+                }),
+            ),
+            ("RUN true", layer(|b| file(b, "done"))),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_record_deletions(true)
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        assert!(repo
+            .read_file_from_commit(commits[0], DELETED_FILES_PATH)
+            .is_err());
+        assert_eq!(
+            repo.read_file_from_commit(commits[1], DELETED_FILES_PATH)?,
+            "/app\n/cache/*\n"
+        );
+        assert!(repo
+            .read_file_from_commit(commits[1], "rootfs/app")
+            .is_err());
+        // The next layer deletes nothing
+        assert!(repo
+            .read_file_from_commit(commits[2], DELETED_FILES_PATH)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_history() -> Result<()> {
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .with_config_history(true)
+            .convert(FIXTURE_TAR_PATH, output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        let config = |step: usize, name: &str| {
+            repo.read_file_from_commit(commits[step], &format!("{CONFIG_DIR}/{name}"))
+        };
+        // CMD ["/bin/sh"] of the base image
+        assert_eq!(config(1, "cmd.txt")?, "[\"/bin/sh\"]\n");
+        assert_eq!(config(4, "workdir.txt")?, "");
+        assert_eq!(config(5, "workdir.txt")?, "/app\n");
+        assert_eq!(config(6, "env.txt")?, "APP_NAME=test-app\n");
+        assert_eq!(
+            config(7, "env.txt")?,
+            "APP_NAME=test-app\nAPP_VERSION=1.0.0\n"
+        );
+        // ENTRYPOINT drops the CMD of the base image, the next layer sets it again
+        assert_eq!(config(11, "entrypoint.txt")?, "[\"/app/script.sh\"]\n");
+        assert_eq!(config(11, "cmd.txt")?, "");
+        assert_eq!(config(12, "cmd.txt")?, "[\"--help\"]\n");
+        assert!(config(12, "env.txt")?.starts_with("PATH=/app:"));
+        assert_eq!(config(12, "labels.json")?, "{}\n");
+
+        // The ENV layer changes config/env.txt and nothing else
+        let tree = |step: usize| repo.repo.find_commit(commits[step])?.tree();
+        let diff = repo
+            .repo
+            .diff_tree_to_tree(Some(&tree(6)?), Some(&tree(7)?), None)?;
+        let changed: Vec<_> = diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+            .filter(|path| !path.starts_with(".oci2git") && !path.starts_with(LAYERS_DIR))
+            .collect();
+        assert_eq!(changed, vec![Path::new("config/env.txt")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_records() -> Result<()> {
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .convert(FIXTURE_TAR_PATH, output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let (metadata_commit, layer_commits) = repo
+            .get_branch_commits(&image_branch(&repo)?)?
+            .split_last()
+            .map(|(last, rest)| (*last, rest.to_vec()))
+            .unwrap();
+
+        // Every layer commit adds the file of its layer and no report
+        let mut parent_tree = None;
+        for (i, commit) in layer_commits.iter().enumerate() {
+            let tree = repo.repo.find_commit(*commit)?.tree()?;
+            assert!(tree.get_path(Path::new(IMAGE_MD)).is_err());
+            let diff = repo
+                .repo
+                .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+            let records: Vec<_> = diff
+                .deltas()
+                .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+                .filter(|path| path.starts_with(LAYERS_DIR))
+                .collect();
+            assert_eq!(
+                records,
+                vec![Path::new(LAYERS_DIR).join(layer_records::file_name(i + 1))]
+            );
+            parent_tree = Some(tree);
+        }
+
+        // The metadata commit adds the full report, which agrees with the layer files
+        let metadata = ImageMetadata::read_from_commit(&repo, metadata_commit)?.unwrap();
+        assert!(metadata.basic_info.is_some());
+        let last_layer = *layer_commits.last().unwrap();
+        let recorded = ImageMetadata::read_from_commit(&repo, last_layer)?.unwrap();
+        assert!(recorded.basic_info.is_none());
+        assert_eq!(recorded.layer_digests, metadata.layer_digests);
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_records_in_notes() -> Result<()> {
+        let output_dir = TempDir::new()?;
+        let convert = |branch: &str, layer_records: LayerRecords| {
+            ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+                .with_layer_records(layer_records)
+                .convert_with_options(
+                    FIXTURE_TAR_PATH,
+                    output_dir.path(),
+                    &ConvertOptions::new().with_branch_name(Some(branch.to_string())),
+                )
+        };
+        let notes = convert("notes", LayerRecords::Notes)?;
+        assert!(!output_dir.path().join(LAYERS_DIR).exists());
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits("notes")?;
+        let (metadata_commit, layer_commits) = commits.split_last().unwrap();
+        for commit in layer_commits {
+            let tree = repo.repo.find_commit(*commit)?.tree()?;
+            assert!(tree.get_path(Path::new(LAYERS_DIR)).is_err());
+            assert!(repo.read_note(LAYER_NOTES_REF, *commit).is_some());
+        }
+        assert!(repo.read_note(LAYER_NOTES_REF, *metadata_commit).is_none());
+        let metadata = ImageMetadata::read_from_commit(&repo, *metadata_commit)?.unwrap();
+        assert_eq!(
+            layer_records::read_chain(&repo, *layer_commits.last().unwrap())?.unwrap(),
+            metadata.layer_digests
+        );
+
+        // Found again through the notes, but not by conversions recording files
+        let again = convert("notes-again", LayerRecords::Notes)?;
+        assert_eq!(again.layers_skipped, layer_commits.len());
+        let files = convert("files", LayerRecords::Files)?;
+        assert_eq!(files.layers_skipped, 0);
+        assert_ne!(files.layers[0].commit, notes.layers[0].commit);
+        Ok(())
+    }
+
+    #[test]
+    fn test_converted_repo() -> Result<()> {
+        let output_dir = TempDir::new()?;
+        let summary = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .convert(FIXTURE_TAR_PATH, output_dir.path())?;
+
+        let repo = ConvertedRepo::open(output_dir.path())?;
+        assert_eq!(repo.branches()?, vec![summary.branch.clone()]);
+        let images = repo.images()?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].branch, summary.branch);
+        let metadata = repo.metadata(&summary.branch)?.unwrap();
+        assert!(metadata.basic_info.is_some());
+
+        let layers = repo.layers(&summary.branch)?;
+        assert_eq!(layers.len(), metadata.layer_digests.len());
+        for (layer, recorded) in layers.iter().zip(&metadata.layer_digests) {
+            assert_eq!(&layer.layer, recorded);
+            assert_eq!(repo.layer_of(layer.commit)?, Some(layer.index));
+            assert_eq!(
+                repo.layer_commit(&summary.branch, layer.index)?,
+                Some(layer.commit)
+            );
+        }
+        let tip = *repo
+            .git()
+            .get_branch_commits(&summary.branch)?
+            .last()
+            .unwrap();
+        assert_eq!(repo.layer_of(tip)?, None);
+
+        // The file appears with one of the layers and stays
+        let hello = |index| repo.read_file(&summary.branch, index, "/app/hello.txt");
+        assert_eq!(hello(1)?, None);
+        let last = layers.last().unwrap().index;
+        assert!(String::from_utf8(hello(last)?.unwrap())?.contains("Hello from oci2git"));
+        assert_eq!(repo.read_file(&summary.branch, last, "app")?, None);
+        assert!(repo
+            .read_file(&summary.branch, last + 1, "app/hello.txt")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_log() -> Result<()> {
+        let output_dir = TempDir::new()?;
+        let summary = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .convert(FIXTURE_TAR_PATH, output_dir.path())?;
+        let repo = ConvertedRepo::open(output_dir.path())?;
+
+        // COPY script.sh, then RUN chmod +x: same content, new mode
+        let log = file_log::file_log(&repo, &summary.branch, "/app/script.sh")?;
+        let changes: Vec<_> = log
+            .iter()
+            .map(|entry| (entry.layer, entry.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![(5, PathChange::Created), (11, PathChange::Modified)]
+        );
+        assert!(log[0].command.starts_with("COPY script.sh"));
+        assert!(log[1].command.starts_with("RUN chmod +x"));
+        assert_eq!(log[0].size, log[1].size);
+        assert_eq!(log[0].kind, "file");
+        assert_eq!(
+            log[1].commit,
+            repo.layer_commit(&summary.branch, 11)?.unwrap().to_string()
+        );
+
+        let dir = file_log::file_log(&repo, &summary.branch, "app")?;
+        assert_eq!(dir.len(), 4);
+        assert!(dir
+            .iter()
+            .all(|entry| entry.kind == "dir" && entry.size.is_none()));
+        assert!(file_log::file_log(&repo, &summary.branch, "/no/such/file")?.is_empty());
+        Ok(())
+    }
+
+    /// Records what each layer applies in `plugins/layer.txt`
+    #[derive(Default)]
+    struct LayerLog {
+        lines: Vec<String>,
+        path: &'static str,
+    }
+
+    impl LayerProcessor for LayerLog {
+        fn name(&self) -> &str {
+            "layer-log"
+        }
+
+        fn on_layer_start(&mut self, layer: &LayerInfo) -> Result<()> {
+            self.lines = vec![format!("{}/{}", layer.index, layer.total)];
+            Ok(())
+        }
+
+        fn on_file(&mut self, path: &Path, _entry: &ObservedEntry) {
+            self.lines.push(format!("+{}", path.display()));
+        }
+
+        fn on_removed(&mut self, path: &Path) {
+            self.lines.push(format!("-{}", path.display()));
+        }
+
+        fn on_layer_commit(&mut self, _layer: &LayerInfo) -> Result<Vec<PluginFile>> {
+            Ok(vec![PluginFile::new(self.path, self.lines.join(" "))])
+        }
+    }
+
+    #[test]
+    fn test_layer_processor() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, &b"x"[..]).unwrap();
+        };
+        let image = || {
+            image_with_layers(vec![
+                (
+                    "ADD app /",
+                    layer(|b| {
+                        file(b, "app");
+                        file(b, "cache");
+                    }),
+                ),
+                ("RUN rm /cache", layer(|b| file(b, ".wh.cache"))),
+            ])
+        };
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_layer_processor(LayerLog {
+            path: "plugins/layer.txt",
+            ..Default::default()
+        })
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        let logs: Vec<String> = commits
+            .iter()
+            .map(|commit| repo.read_file_from_commit(*commit, "plugins/layer.txt"))
+            .collect::<Result<_>>()?;
+        // Both layer commits, then the metadata commit keeping the last one
+        assert_eq!(logs, ["1/2 +app +cache", "2/2 -cache", "2/2 -cache"]);
+
+        // Plugins can't overwrite the conversion's own files
+        let output_dir = TempDir::new()?;
+        let error = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_layer_processor(LayerLog {
+            path: "rootfs/app",
+            ..Default::default()
+        })
+        .convert("app:latest", output_dir.path())
+        .unwrap_err();
+        assert!(format!("{error:#}").contains("Plugin 'layer-log' failed"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_stats() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str, content: &[u8]| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, content).unwrap();
+        };
+        let image = image_with_layers(vec![
+            (
+                "ADD app /",
+                layer(|b| {
+                    file(b, "app", b"0123456789");
+                    file(b, "cache", b"xyz");
+                }),
+            ),
+            ("RUN rm /cache", layer(|b| file(b, ".wh.cache", b""))),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_layer_stats(true)
+        .with_metadata_format(MetadataFormat::Both)
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        let tip = *commits.last().unwrap();
+        let markdown = repo.read_file_from_commit(tip, LAYER_STATS_MD)?;
+        assert!(markdown.contains("| 2 | `RUN rm /cache` | 0 | 0 | 1 | 0 B | 3 B |"));
+        let report: LayerStatsReport =
+            serde_json::from_str(&repo.read_file_from_commit(tip, LAYER_STATS_JSON)?)?;
+        assert_eq!(report.layers.len(), 2);
+        assert_eq!(report.layers[0].bytes_added, 13);
+        assert_eq!(report.layers[0].largest_files[0].path, "/app");
+        // Layer commits don't carry the report
+        assert!(repo
+            .read_file_from_commit(commits[0], LAYER_STATS_MD)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_graph() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(3);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, &b"abc"[..]).unwrap();
+        };
+        let layers = || {
+            vec![
+                ("ADD base /", layer(|b| file(b, "base"))),
+                ("COPY app /", layer(|b| file(b, "app"))),
+            ]
+        };
+        let mut extended = layers();
+        extended.push(("COPY \"extra\" /", layer(|b| file(b, "extra"))));
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image_with_layers(layers())?)),
+            Notifier::new(0),
+        )
+        .convert("app:1", output_dir.path())?;
+        let summary = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image_with_layers(extended)?)),
+            Notifier::new(0),
+        )
+        .with_layer_graph(true)
+        .convert("app:2", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let base = list::list(&repo)?
+            .into_iter()
+            .find(|image| image.branch != summary.branch)
+            .unwrap()
+            .branch;
+        let tip = *repo.get_branch_commits(&summary.branch)?.last().unwrap();
+        let markdown = repo.read_file_from_commit(tip, IMAGE_MD)?;
+        let graph = &markdown[markdown.find("## Layer Graph").unwrap()..];
+        assert!(graph.contains("L3[\"3: COPY #quot;extra#quot; /<br/>3 B\"]"));
+        assert!(graph.contains("L2 --> L3"));
+        assert!(graph.contains(&format!(
+            "B1([\"{}<br/>shares 2 layers\"])\n    L2 -.-> B1",
+            base.replace('#', "#35;")
+        )));
+        // Still reads back like any Image.md
+        let metadata = ImageMetadata::read_from_commit(&repo, tip)?.unwrap();
+        assert_eq!(metadata.layer_digests.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_base_image() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(3);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, &b"abc"[..]).unwrap();
+        };
+        let layers = || vec![("ADD base /", layer(|b| file(b, "base")))];
+        let mut extended = layers();
+        extended.push(("COPY app /", layer(|b| file(b, "app"))));
+
+        let output_dir = TempDir::new()?;
+        let base = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image_with_layers(layers())?)),
+            Notifier::new(0),
+        )
+        .convert("base:1", output_dir.path())?;
+        let app = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image_with_layers(extended)?)),
+            Notifier::new(0),
+        )
+        .convert("app:1", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let tip = *repo.get_branch_commits(&app.branch)?.last().unwrap();
+        let metadata = ImageMetadata::read_from_commit(&repo, tip)?.unwrap();
+        let recorded = metadata.base.unwrap();
+        assert_eq!(recorded.branch.as_deref(), Some(base.branch.as_str()));
+        assert_eq!(recorded.layers, Some(1));
+        assert!(recorded.name.is_some());
+        let message = repo.commit_message(tip)?;
+        assert!(message.contains(&format!("\nBase-Branch: {}\n", base.branch)));
+
+        // The base itself has none
+        let tip = *repo.get_branch_commits(&base.branch)?.last().unwrap();
+        let metadata = ImageMetadata::read_from_commit(&repo, tip)?.unwrap();
+        assert_eq!(metadata.base, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_layer() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(3);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, &b"abc"[..]).unwrap();
+        };
+        let images = TempDir::new()?;
+        let base = images.path().join("base.tar");
+        let other = images.path().join("other.tar");
+        let app = images.path().join("app.tar");
+        std::fs::write(
+            &base,
+            image_with_layers(vec![("ADD base /", layer(|b| file(b, "base")))])?,
+        )?;
+        std::fs::write(
+            &other,
+            image_with_layers(vec![("ADD other /", layer(|b| file(b, "other")))])?,
+        )?;
+        std::fs::write(
+            &app,
+            image_with_layers(vec![
+                ("ADD base /", layer(|b| file(b, "base"))),
+                ("COPY app /", layer(|b| file(b, "app"))),
+            ])?,
+        )?;
+
+        let output_dir = TempDir::new()?;
+        let summary = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .with_from_layer(Some(FromLayer::Base(base.to_string_lossy().into_owned())))
+            .convert(app.to_str().unwrap(), output_dir.path())?;
+        assert!(summary.branch.ends_with(&format!("{FROM_LAYER_SUFFIX}2")));
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&summary.branch)?;
+        assert_eq!(commits.len(), 3);
+        let rootfs = output_dir.path().join("rootfs");
+        assert!(rootfs.join("app").exists());
+        assert!(!rootfs.join("base").exists());
+        let metadata = ImageMetadata::read_from_commit(&repo, commits[2])?.unwrap();
+        assert_eq!(metadata.layer_digests.len(), 2);
+
+        // The image isn't built on another image
+        let result = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .with_from_layer(Some(FromLayer::Base(other.to_string_lossy().into_owned())))
+            .convert(app.to_str().unwrap(), TempDir::new()?.path());
+        assert!(matches!(result, Err(Oci2GitError::InvalidInput(_))));
+        let result = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .with_from_layer(Some(FromLayer::Index(3)))
+            .convert(app.to_str().unwrap(), TempDir::new()?.path());
+        assert!(matches!(result, Err(Oci2GitError::InvalidInput(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_readme() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str, content: &[u8]| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, content).unwrap();
+        };
+        let image = image_with_layers(vec![
+            ("ADD app /", layer(|b| file(b, "app", b"0123456789"))),
+            (
+                "COPY big /data/",
+                layer(|b| file(b, "data/big", &[0; 4096])),
+            ),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_emit_readme(true)
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        let readme = repo.read_file_from_commit(*commits.last().unwrap(), BRANCH_README)?;
+        assert!(readme.contains("| Layers | 2 (0 without file changes) |"));
+        assert!(readme.contains("| 2 | 4.0 KiB | `COPY big /data/` |\n| 1 | 10 B | `ADD app /` |"));
+        // The repository README on main is still the list of images
+        let main = repo.get_branch_commits(README_BRANCH)?;
+        let listing = repo.read_file_from_commit(*main.last().unwrap(), "README.md")?;
+        assert!(listing.starts_with("# Converted Images"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_html_report() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str, content: &[u8]| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, content).unwrap();
+        };
+        let image = image_with_layers(vec![
+            ("ADD app /", layer(|b| file(b, "app", b"0123456789"))),
+            (
+                "COPY big /data/",
+                layer(|b| file(b, "data/big", &[0; 4096])),
+            ),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_html_report(true)
+        .with_report_commit_url(Some("https://git.example.com/c/{commit}".to_string()))
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        let html = repo.read_file_from_commit(*commits.last().unwrap(), REPORT_PATH)?;
+        assert!(html.contains(&format!("https://git.example.com/c/{}", commits[1])));
+        assert!(html.contains("<code>COPY big /data/</code>"));
+        // The bigger layer fills the chart, the biggest file comes first
+        assert!(html.contains("<div style=\"width: 100%\">"));
+        let big = html.find("<code>/data/big</code>").unwrap();
+        assert!(big < html.find("<code>/app</code>").unwrap());
+        assert!(repo.read_file_from_commit(commits[1], REPORT_PATH).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_materialize_config() -> Result<()> {
+        let image = image_with_config(
+            vec![(
+                "ADD etc /",
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_entry_type(tar_rs::EntryType::Directory);
+                    header.set_size(0);
+                    header.set_mode(0o755);
+                    b.append_data(&mut header, "etc/", &[][..]).unwrap();
+                }),
+            )],
+            serde_json::json!({
+                "Env": ["PATH=/usr/local/bin:/usr/bin", "GREETING=it's me"],
+                "ExposedPorts": {"8080/tcp": {}, "53/udp": {}},
+                "Volumes": {"/var/lib/data": {}},
+            }),
+        )?;
+        let output_dir = TempDir::new()?;
+        let converted = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_materialize_config(true)
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let tip = *repo.get_branch_commits(&converted.branch)?.last().unwrap();
+        assert_eq!(
+            repo.read_file_from_commit(tip, PORTS_TXT)?,
+            "53/udp\n8080/tcp\n"
+        );
+        assert_eq!(
+            repo.read_file_from_commit(tip, VOLUMES_TXT)?,
+            "/var/lib/data\n"
+        );
+        let profile = repo.read_file_from_commit(tip, &format!("rootfs/{PROFILE_PATH}"))?;
+        assert!(profile.contains("export GREETING='it'\\''s me'\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hashes_find_duplicates() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str, content: &[u8]| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, content).unwrap();
+        };
+        let image = image_with_layers(vec![
+            (
+                "COPY vendor /app/vendor",
+                layer(|b| file(b, "app/vendor/lib.js", b"library")),
+            ),
+            ("RUN true", layer(|b| file(b, ".wh.nothing", b""))),
+            (
+                "COPY vendor /srv/vendor",
+                layer(|b| file(b, "srv/vendor/lib.js", b"library")),
+            ),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_content_hashes(true)
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        assert!(repo
+            .read_file_from_commit(commits[0], CONTENT_HASHES_PATH)?
+            .contains("\"path\":\"/app/vendor/lib.js\""));
+        // A layer that writes no files doesn't inherit the list
+        assert!(repo
+            .read_file_from_commit(commits[1], CONTENT_HASHES_PATH)
+            .is_err());
+
+        let analysis = analyze::analyze(&repo, None, 5)?;
+        let image = &analysis.images[0];
+        assert_eq!(image.duplicate_bytes, 7);
+        assert_eq!(image.duplicates.len(), 1);
+        let copies: Vec<_> = image.duplicates[0]
+            .copies
+            .iter()
+            .map(|copy| (copy.layer, copy.path.as_str()))
+            .collect();
+        assert_eq!(
+            copies,
+            vec![(1, "/app/vendor/lib.js"), (3, "/srv/vendor/lib.js")]
+        );
+        assert!(analysis
+            .render_markdown()
+            .contains("### Duplicated Content"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_image_of_multi_image_archive() -> Result<()> {
+        // What `docker save a:1 b:1` writes: one manifest entry and config per image
+        let mut builder = tar_rs::Builder::new(Vec::new());
+        let mut manifest = Vec::new();
+        for name in ["a", "b"] {
+            let config = serde_json::json!({
+                "architecture": "amd64",
+                "os": "linux",
+                "rootfs": {"type": "layers", "diff_ids": []},
+                "history": [{"created": "2024-01-01T00:00:00Z", "created_by": format!("ADD {name} /")}],
+            });
+            manifest.push(serde_json::json!({
+                "Config": format!("{name}.json"),
+                "RepoTags": [format!("{name}:1")],
+                "Layers": [format!("{name}.tar")],
+            }));
+            let layer_tar = layer(|b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, name, &b"x"[..]).unwrap();
+            });
+            for (path, data) in [
+                (format!("{name}.json"), serde_json::to_vec(&config)?),
+                (format!("{name}.tar"), layer_tar),
+            ] {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                builder.append_data(&mut header, path, data.as_slice())?;
+            }
+        }
+        let manifest = serde_json::to_vec(&manifest)?;
+        let mut header = tar_rs::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "manifest.json", manifest.as_slice())?;
+        let archive = builder.into_inner()?;
+
+        let convert = |select: Option<&str>, output: &Path| {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(archive.clone())),
+                Notifier::new(0),
+            )
+            .with_selected_image(select.map(str::to_string))
+            .convert("images.tar", output)
+        };
+
+        let output_dir = TempDir::new()?;
+        convert(Some("b:1"), output_dir.path())?;
+        let repo = GitRepo::open(output_dir.path())?;
+        let tip = *repo
+            .get_branch_commits(&image_branch(&repo)?)?
+            .last()
+            .unwrap();
+        assert!(repo.read_file_from_commit(tip, "rootfs/b").is_ok());
+        assert!(repo.read_file_from_commit(tip, "rootfs/a").is_err());
+
+        // The first image without --select
+        let output_dir = TempDir::new()?;
+        convert(None, output_dir.path())?;
+        let repo = GitRepo::open(output_dir.path())?;
+        let tip = *repo
+            .get_branch_commits(&image_branch(&repo)?)?
+            .last()
+            .unwrap();
+        assert!(repo.read_file_from_commit(tip, "rootfs/a").is_ok());
+
+        let error = convert(Some("c:1"), TempDir::new()?.path()).unwrap_err();
+        assert!(format!("{error:#}").contains("it contains: a:1, b:1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_oci_index() -> Result<()> {
+        use sha2::{Digest, Sha256};
+        // What `buildx --output type=oci` writes for two platforms: no manifest.json, and
+        // index.json pointing at an image index with a manifest per platform
+        let mut blobs: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut add_blob = |data: Vec<u8>| {
+            let digest = format!("sha256:{:x}", Sha256::digest(&data));
+            let size = data.len();
+            blobs.push((digest.clone(), data));
+            serde_json::json!({"digest": digest, "size": size})
+        };
+        let mut platform_manifests = Vec::new();
+        for architecture in ["amd64", "arm64"] {
+            let layer_tar = layer(|b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, architecture, &b"x"[..]).unwrap();
+            });
+            let layer = add_blob(layer_tar);
+            let config = add_blob(serde_json::to_vec(&serde_json::json!({
+                "architecture": architecture,
+                "os": "linux",
+                "rootfs": {"type": "layers", "diff_ids": [layer["digest"]]},
+                "history": [{"created": "2024-01-01T00:00:00Z", "created_by": format!("ADD {architecture} /")}],
+            }))?);
+            let manifest = add_blob(serde_json::to_vec(&serde_json::json!({
+                "schemaVersion": 2,
+                "config": config,
+                "layers": [layer],
+            }))?);
+            let mut descriptor = manifest;
+            descriptor["platform"] =
+                serde_json::json!({"os": "linux", "architecture": architecture});
+            platform_manifests.push(descriptor);
+        }
+        let index = add_blob(serde_json::to_vec(
+            &serde_json::json!({"schemaVersion": 2, "manifests": platform_manifests}),
+        )?);
+        let top = serde_json::json!({"schemaVersion": 2, "manifests": [index]});
+
+        let mut builder = tar_rs::Builder::new(Vec::new());
+        let mut entries = vec![
+            ("index.json".to_string(), serde_json::to_vec(&top)?),
+            (
+                "oci-layout".to_string(),
+                br#"{"imageLayoutVersion":"1.0.0"}"#.to_vec(),
+            ),
+        ];
+        entries.extend(
+            blobs
+                .into_iter()
+                .map(|(digest, data)| (format!("blobs/sha256/{}", &digest[7..]), data)),
+        );
+        for (path, data) in entries {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, data.as_slice())?;
+        }
+        let images = TempDir::new()?;
+        let archive = images.path().join("app.tar");
+        std::fs::write(&archive, builder.into_inner()?)?;
+
+        let output_dir = TempDir::new()?;
+        let arm64 = "linux/arm64".parse()?;
+        let summary = ImageProcessor::new(
+            TarSource::new()?.with_platform(Some(arm64)),
+            Notifier::new(0),
+        )
+        .convert(archive.to_str().unwrap(), output_dir.path())?;
+        // Named after the index, like `docker image inspect` shows its ID
+        let index_digest = top["manifests"][0]["digest"].as_str().unwrap();
+        assert!(summary.branch.contains("linux-arm64"));
+        assert!(summary.branch.ends_with(&index_digest[7..19]));
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let tip = *repo.get_branch_commits(&summary.branch)?.last().unwrap();
+        assert!(repo.read_file_from_commit(tip, "rootfs/arm64").is_ok());
+        assert!(repo.read_file_from_commit(tip, "rootfs/amd64").is_err());
+
+        let error = ImageProcessor::new(
+            TarSource::new()?.with_platform(Some("linux/s390x".parse()?)),
+            Notifier::new(0),
+        )
+        .convert(archive.to_str().unwrap(), TempDir::new()?.path())
+        .unwrap_err();
+        assert!(format!("{error:#}").contains("available: linux/amd64, linux/arm64"));
+
+        // A per-call platform doesn't stick to the processor, whether it worked or not
+        let mut processor = ImageProcessor::new(
+            TarSource::new()?.with_platform(Some("linux/s390x".parse()?)),
+            Notifier::new(0),
+        );
+        let options = ConvertOptions {
+            platform: Some("linux/arm64".parse()?),
+            ..ConvertOptions::default()
+        };
+        processor.convert_with_options(
+            archive.to_str().unwrap(),
+            TempDir::new()?.path(),
+            &options,
+        )?;
+        assert!(processor
+            .convert(archive.to_str().unwrap(), TempDir::new()?.path())
+            .is_err());
+        let mut processor = ImageProcessor::new(
+            TarSource::new()?.with_platform(Some("linux/arm64".parse()?)),
+            Notifier::new(0),
+        );
+        let options = ConvertOptions {
+            platform: Some("linux/s390x".parse()?),
+            ..ConvertOptions::default()
+        };
+        assert!(processor
+            .convert_with_options(archive.to_str().unwrap(), TempDir::new()?.path(), &options)
+            .is_err());
+        processor.convert(archive.to_str().unwrap(), TempDir::new()?.path())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_docker_save_layout() -> Result<()> {
+        use sha2::{Digest, Sha256};
+        let layers: Vec<_> = ["base", "app"]
+            .into_iter()
+            .map(|name| {
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(1);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, name, &b"x"[..]).unwrap();
+                })
+            })
+            .collect();
+        let diff_ids: Vec<_> = layers
+            .iter()
+            .map(|tar| format!("sha256:{:x}", Sha256::digest(tar)))
+            .collect();
+        let config = serde_json::to_vec(&serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": {"type": "layers", "diff_ids": diff_ids},
+            "history": [
+                {"created": "2024-01-01T00:00:00Z", "created_by": "ADD base /"},
+                {"created": "2024-01-02T00:00:00Z", "created_by": "COPY app /"},
+            ],
+        }))?;
+        let config_hex = format!("{:x}", Sha256::digest(&config));
+
+        let archive = |entries: Vec<(String, Vec<u8>)>| -> Result<Vec<u8>> {
+            let mut builder = tar_rs::Builder::new(Vec::new());
+            for (path, data) in entries {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                builder.append_data(&mut header, path, data.as_slice())?;
+            }
+            Ok(builder.into_inner()?)
+        };
+
+        // Docker before 25: <v1 id>/{VERSION,json,layer.tar} and <config hex>.json
+        let v1_ids = [format!("{:0>64}", "1"), format!("{:0>64}", "2")];
+        let mut entries = vec![(format!("{config_hex}.json"), config.clone())];
+        for (i, (v1_id, tar)) in v1_ids.iter().zip(&layers).enumerate() {
+            let parent = i.checked_sub(1).map(|p| v1_ids[p].as_str());
+            let json = serde_json::json!({"id": v1_id, "parent": parent});
+            entries.push((format!("{v1_id}/VERSION"), b"1.0".to_vec()));
+            entries.push((format!("{v1_id}/json"), serde_json::to_vec(&json)?));
+            entries.push((format!("{v1_id}/layer.tar"), tar.clone()));
+        }
+        let manifest = serde_json::json!([{
+            "Config": format!("{config_hex}.json"),
+            "RepoTags": ["app:1"],
+            "Layers": v1_ids.iter().map(|id| format!("{id}/layer.tar")).collect::<Vec<_>>(),
+        }]);
+        entries.push(("manifest.json".to_string(), serde_json::to_vec(&manifest)?));
+        entries.push((
+            "repositories".to_string(),
+            serde_json::to_vec(&serde_json::json!({"app": {"1": v1_ids[1]}}))?,
+        ));
+        let legacy = archive(entries)?;
+
+        // Docker 25+: blobs/sha256/<digest>, uncompressed layers named by their diff_id
+        let mut entries = vec![(format!("blobs/sha256/{config_hex}"), config)];
+        for (diff_id, tar) in diff_ids.iter().zip(&layers) {
+            entries.push((format!("blobs/sha256/{}", &diff_id[7..]), tar.clone()));
+        }
+        let manifest = serde_json::json!([{
+            "Config": format!("blobs/sha256/{config_hex}"),
+            "RepoTags": ["app:1"],
+            "Layers": diff_ids.iter().map(|id| format!("blobs/sha256/{}", &id[7..])).collect::<Vec<_>>(),
+        }]);
+        entries.push(("manifest.json".to_string(), serde_json::to_vec(&manifest)?));
+        let blobs = archive(entries)?;
+
+        let images = TempDir::new()?;
+        let legacy_path = images.path().join("legacy.tar");
+        std::fs::write(&legacy_path, legacy)?;
+        let extracted = ExtractedImage::from_tarball(&legacy_path, &Notifier::new(0))?;
+        let digests: Vec<_> = extracted.layers()?.into_iter().map(|l| l.digest).collect();
+        assert_eq!(digests, diff_ids);
+        assert_eq!(
+            extracted.metadata("legacy")?.id,
+            format!("sha256:{config_hex}")
+        );
+
+        let output_dir = TempDir::new()?;
+        let first = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .convert(legacy_path.to_str().unwrap(), output_dir.path())?;
+        let repo = GitRepo::open(output_dir.path())?;
+        let tip = *repo.get_branch_commits(&first.branch)?.last().unwrap();
+        assert!(repo.read_file_from_commit(tip, "rootfs/base").is_ok());
+        assert!(repo.read_file_from_commit(tip, "rootfs/app").is_ok());
+
+        // The same image in the current layout shares both layer commits
+        let second = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(blobs)),
+            Notifier::new(0),
+        )
+        .convert("app.tar", output_dir.path())?;
+        let first_commits = repo.get_branch_commits(&first.branch)?;
+        let second_commits = repo.get_branch_commits(&second.branch)?;
+        assert_ne!(first.branch, second.branch);
+        assert_eq!(first_commits[..2], second_commits[..2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_without_history() -> Result<()> {
+        let archive = |layers: Vec<Vec<u8>>| -> Result<Vec<u8>> {
+            // No `history` in the config, as some tools write for `FROM scratch` images
+            let config = serde_json::json!({
+                "architecture": "amd64",
+                "os": "linux",
+                "created": "2024-03-01T00:00:00Z",
+                "rootfs": {"type": "layers", "diff_ids": []},
+            });
+            let paths: Vec<_> = (0..layers.len()).map(|i| format!("layer{i}.tar")).collect();
+            let manifest = serde_json::json!([{
+                "Config": "config.json",
+                "RepoTags": ["scratch-app:latest"],
+                "Layers": paths,
+            }]);
+            let mut builder = tar_rs::Builder::new(Vec::new());
+            let mut entries = vec![
+                ("config.json".to_string(), serde_json::to_vec(&config)?),
+                ("manifest.json".to_string(), serde_json::to_vec(&manifest)?),
+            ];
+            entries.extend(paths.into_iter().zip(layers));
+            for (path, data) in entries {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                builder.append_data(&mut header, path, data.as_slice())?;
+            }
+            Ok(builder.into_inner()?)
+        };
+        let convert = |archive: Vec<u8>, output: &Path| {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(archive)),
+                Notifier::new(0),
+            )
+            .convert("scratch-app.tar", output)
+        };
+
+        let binary = layer(|b| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(3);
+            header.set_mode(0o755);
+            b.append_data(&mut header, "app", &b"elf"[..]).unwrap();
+        });
+        let output_dir = TempDir::new()?;
+        let summary = convert(archive(vec![binary.clone()])?, output_dir.path())?;
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&summary.branch)?;
+        let tip = *commits.last().unwrap();
+        assert!(repo.read_file_from_commit(tip, "rootfs/app").is_ok());
+        assert!(commits.iter().any(|commit| repo
+            .commit_message(*commit)
+            .is_ok_and(|message| message.contains("Layer 1/1 (no history entry)"))));
+
+        // Without diff_ids there is nothing to verify the layers against
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(archive(vec![binary])?)),
+            Notifier::new(0),
+        )
+        .with_digest_verification(DigestVerification::Fail)
+        .convert("scratch-app.tar", output_dir.path())?;
+        let metadata = ImageMetadata::load_markdown(&output_dir.path().join(IMAGE_MD))?;
+        assert_eq!(metadata.layer_digests[0].verified, None);
+
+        // Not a single layer
+        let output_dir = TempDir::new()?;
+        let summary = convert(archive(Vec::new())?, output_dir.path())?;
+        let repo = GitRepo::open(output_dir.path())?;
+        assert!(!repo.get_branch_commits(&summary.branch)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_mismatch() -> Result<()> {
+        // Two layers, but a history with a single non-empty entry
+        let config = serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": {"type": "layers", "diff_ids": []},
+            "history": [
+                {"created": "2024-01-01T00:00:00Z", "created_by": "ENV A=1", "empty_layer": true},
+                {"created": "2024-01-02T00:00:00Z", "created_by": "COPY b /"},
+            ],
+        });
+        let manifest = serde_json::json!([{
+            "Config": "config.json",
+            "RepoTags": ["app:latest"],
+            "Layers": ["a.tar", "b.tar"],
+        }]);
+        let mut builder = tar_rs::Builder::new(Vec::new());
+        let mut entries = vec![
+            ("config.json".to_string(), serde_json::to_vec(&config)?),
+            ("manifest.json".to_string(), serde_json::to_vec(&manifest)?),
+        ];
+        for name in ["a", "b"] {
+            let tar = layer(|b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, name, &b"x"[..]).unwrap();
+            });
+            entries.push((format!("{name}.tar"), tar));
+        }
+        for (path, data) in entries {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, data.as_slice())?;
+        }
+        let archive = builder.into_inner()?;
+        let processor = |layer_mismatch| {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(archive.clone())),
+                Notifier::new(0),
+            )
+            .with_layer_mismatch(layer_mismatch)
+        };
+        let output_dir = TempDir::new()?;
+
+        // The default pairs from the newest layer and warns
+        let plan = processor(LayerMismatch::BestEffort).analyze("app.tar", output_dir.path())?;
+        let commands: Vec<_> = plan.layers.iter().map(|l| l.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            ["Layer 1/2 (no history entry)", "ENV A=1", "COPY b /"]
+        );
+        assert!(plan
+            .warnings
+            .iter()
+            .any(|w| w.contains("1 non-empty entries but the manifest lists 2 layers")));
+
+        let plan = processor(LayerMismatch::ManifestOnly).analyze("app.tar", output_dir.path())?;
+        let commands: Vec<_> = plan.layers.iter().map(|l| l.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            [
+                "Layer 1/2 (no history entry)",
+                "Layer 2/2 (no history entry)"
+            ]
+        );
+
+        let error = processor(LayerMismatch::Error)
+            .convert("app.tar", output_dir.path())
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("manifest lists 2 layers"));
+        assert!(!output_dir.path().join(".git").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_squash() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, &b"x"[..]).unwrap();
+        };
+        let image = || {
+            image_with_layers(vec![
+                ("ADD base /", layer(|b| file(b, "base"))),
+                ("RUN rm /base", layer(|b| file(b, ".wh.base"))),
+                ("ADD app /", layer(|b| file(b, "app"))),
+            ])
+        };
+        let output_dir = TempDir::new()?;
+        let convert = |squash| -> Result<ConversionSummary> {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image()?)),
+                Notifier::new(0),
+            )
+            .with_squash(squash)
+            .convert("app:latest", output_dir.path())
+            .map_err(Into::into)
+        };
+
+        // A regular branch first, which the squashed one must not build on
+        convert(false)?;
+        convert(true)?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let branches = repo.get_all_branches()?;
+        let squashed = branches
+            .iter()
+            .find(|b| b.ends_with(SQUASHED_SUFFIX))
+            .unwrap();
+        let regular = branches
+            .iter()
+            .find(|b| *b != "main" && *b != squashed)
+            .unwrap();
+        assert_eq!(repo.get_branch_commits(regular)?.len(), 4);
+
+        let commits = repo.get_branch_commits(squashed)?;
+        assert_eq!(commits.len(), 2);
+        assert_eq!(repo.read_file_from_commit(commits[0], "rootfs/app")?, "x");
+        assert!(repo
+            .read_file_from_commit(commits[0], "rootfs/base")
+            .is_err());
+        assert!(repo.read_file_from_commit(commits[0], IMAGE_MD).is_err());
+        let metadata =
+            ImageMetadata::parse_markdown(&repo.read_file_from_commit(commits[1], IMAGE_MD)?)?;
+        assert_eq!(metadata.layer_digests.len(), 3);
+
+        // Converting it again finds the squashed branch
+        let plan = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_squash(true)
+        .analyze("app:latest", output_dir.path())?;
+        assert!(plan.branch.is_duplicate);
+        assert_eq!(&plan.branch.name, squashed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_digests() -> Result<()> {
+        // A real image: every layer matches its diff_id
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .with_digest_verification(DigestVerification::Fail)
+            .convert(FIXTURE_TAR_PATH, output_dir.path())?;
+        let metadata = ImageMetadata::load_markdown(&output_dir.path().join(IMAGE_MD))?;
+        let layers: Vec<_> = metadata
+            .layer_digests
+            .iter()
+            .filter(|layer| !layer.is_empty)
+            .collect();
+        assert!(!layers.is_empty());
+        assert!(layers.iter().all(|layer| layer.verified == Some(true)));
+
+        // image_with_layers lists made-up diff_ids
+        let image = || {
+            image_with_layers(vec![(
+                "ADD app /",
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(1);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, "app", &b"x"[..]).unwrap();
+                }),
+            )])
+        };
+        let output_dir = TempDir::new()?;
+        let err = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_digest_verification(DigestVerification::Fail)
+        .convert("app:latest", output_dir.path())
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("Layer 1/1 does not match its digest"));
+
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_digest_verification(DigestVerification::Warn)
+        .convert("app:latest", output_dir.path())?;
+        let metadata = ImageMetadata::load_markdown(&output_dir.path().join(IMAGE_MD))?;
+        assert_eq!(metadata.layer_digests[0].verified, Some(false));
+        assert!(output_dir.path().join("rootfs/app").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_workdir() -> Result<()> {
+        let image = || {
+            image_with_layers(vec![(
+                "ADD app /",
+                layer(|b| {
+                    let mut header = tar_rs::Header::new_gnu();
+                    header.set_size(1);
+                    header.set_mode(0o644);
+                    b.append_data(&mut header, "app", &b"x"[..]).unwrap();
+                }),
+            )])
+        };
+        let scratch = TempDir::new()?;
+        let workdir = scratch.path().join("work");
+        let entries = || -> Result<Vec<std::fs::DirEntry>> {
+            Ok(std::fs::read_dir(&workdir)?.collect::<std::io::Result<_>>()?)
+        };
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_workdir(Workdir::new(Some(workdir.clone())))
+        .convert("app:latest", output_dir.path())?;
+        assert!(output_dir.path().join("rootfs/app").exists());
+        assert!(entries()?.is_empty());
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_workdir(Workdir::new(Some(workdir.clone())).with_keep(true))
+        .convert("app:latest", output_dir.path())?;
+        let kept = entries()?;
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].path().join("extracted/manifest.json").exists());
+        Ok(())
+    }
+
+    /// Records events as short strings
+    struct RecordingSink(Arc<Mutex<Vec<String>>>);
+
+    impl ProgressSink for RecordingSink {
+        fn event(&self, event: &ProgressEvent) {
+            let event = match event {
+                ProgressEvent::PullStarted { image, .. } => format!("pull {image}"),
+                ProgressEvent::LayerExtracting {
+                    index,
+                    total,
+                    bytes,
+                } => {
+                    assert!(*bytes > 0);
+                    format!("extract {index}/{total}")
+                }
+                ProgressEvent::LayerBytes {
+                    index,
+                    total,
+                    read,
+                    bytes,
+                } => {
+                    assert!(read <= bytes);
+                    // Reports in between depend on timing
+                    if read < bytes {
+                        return;
+                    }
+                    format!("read {index}/{total}")
+                }
+                ProgressEvent::Committing {
+                    index: Some(index),
+                    total,
+                } => format!("commit {index}/{total}"),
+                ProgressEvent::Committing { index: None, .. } => "commit metadata".to_string(),
+                ProgressEvent::Done { branch, .. } => format!("done {branch}"),
+            };
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn test_progress_sink() -> Result<()> {
+        let file = |path: &'static str| {
+            layer(move |b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, path, &b"x"[..]).unwrap();
+            })
+        };
+        let image = image_with_layers(vec![("ADD a /", file("a")), ("ADD b /", file("b"))])?;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_progress_sink(Some(Box::new(RecordingSink(events.clone()))))
+        .with_branch_naming(Some(Box::new(BranchTemplate::new("app")?)))
+        .convert("app:latest", output_dir.path())?;
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "pull app:latest",
+                "extract 1/2",
+                "read 1/2",
+                "commit 1/2",
+                "extract 2/2",
+                "read 2/2",
+                "commit 2/2",
+                "commit metadata",
+                "done app",
+            ]
+        );
+        Ok(())
+    }
+
+    /// Tree of `rootfs/` at the tip of the image branch
+    fn rootfs_tree(repo: &GitRepo) -> Result<git2::Oid> {
+        let tip = *repo
+            .get_branch_commits(&image_branch(repo)?)?
+            .last()
+            .unwrap();
+        let tree = repo.repo.find_commit(tip)?.tree()?;
+        Ok(tree.get_path(Path::new("rootfs"))?.id())
+    }
+
+    #[test]
+    fn test_export_round_trip() -> Result<()> {
+        let file = |mode, size| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(size);
+            header.set_mode(mode);
+            header
+        };
+        let image = image_with_layers(vec![
+            (
+                "ADD app /",
+                layer(|b| {
+                    b.append_data(&mut file(0o4755, 1), "bin/tool", &b"x"[..])
+                        .unwrap();
+                    b.append_data(&mut file(0o644, 1), "etc/app.conf", &b"a"[..])
+                        .unwrap();
+                    b.append_data(&mut file(0o644, 1), "opt/data/blob", &b"b"[..])
+                        .unwrap();
+                }),
+            ),
+            (
+                "RUN rm -rf /opt",
+                layer(|b| {
+                    b.append_data(&mut file(0o644, 0), ".wh.opt", &[][..])
+                        .unwrap();
+                }),
+            ),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_fs_metadata(true)
+        .convert("app:latest", output_dir.path())?;
+
+        // Patch a file in Git on top of the conversion
+        let repo = GitRepo::open(output_dir.path())?;
+        std::fs::write(output_dir.path().join("rootfs/etc/app.conf"), "patched")?;
+        assert!(repo.commit_all_changes("Patch app.conf")?);
+
+        let exported = TempDir::new()?;
+        let tarball = exported.path().join("app.tar");
+        let image = export_branch(
+            &repo,
+            &image_branch(&repo)?,
+            &tarball,
+            &ExportOptions::default(),
+        )?;
+        assert_eq!(image.layers.len(), 3);
+        assert_eq!(image.tag.as_deref(), Some("app:latest"));
+
+        // Converting the export again yields the same filesystem and metadata
+        let reconverted = TempDir::new()?;
+        ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .with_fs_metadata(true)
+            .convert(tarball.to_str().unwrap(), reconverted.path())?;
+        let repo2 = GitRepo::open(reconverted.path())?;
+        assert_eq!(rootfs_tree(&repo2)?, rootfs_tree(&repo)?);
+        assert_eq!(
+            std::fs::read_to_string(reconverted.path().join("rootfs/etc/app.conf"))?,
+            "patched"
+        );
+        let fsmeta = FsMetadata::parse(&std::fs::read_to_string(
+            reconverted.path().join(FSMETA_PATH),
+        )?)?;
+        assert_eq!(fsmeta.entries["/bin/tool"].mode, "4755");
+        assert!(!fsmeta.entries.contains_key("/opt"));
+
+        let metadata = std::fs::read_to_string(reconverted.path().join("Image.md"))?;
+        assert!(metadata.contains("RUN rm -rf /opt"));
+        assert!(metadata.contains("oci2git export: Patch app.conf"));
+        Ok(())
+    }
+
     #[test]
     fn test_tar_with_hardlinks() -> Result<()> {
         // Test extraction of Docker image with hardlinks
@@ -346,4 +2492,1000 @@ mod tests {
         println!("✅ All comprehensive link extraction tests passed!");
         Ok(())
     }
+
+    #[test]
+    fn test_config_file_excludes_and_commit_template() -> Result<()> {
+        let file = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            b.append_data(&mut header, path, &b"x"[..]).unwrap();
+        };
+        let image = image_with_layers(vec![(
+            "RUN install",
+            layer(|b| {
+                file(b, "usr/bin/app");
+                file(b, "usr/share/doc/app/README");
+                file(b, "app/lib/__pycache__/mod.pyc");
+            }),
+        )])?;
+
+        let output_dir = TempDir::new()?;
+        std::fs::write(
+            output_dir.path().join(CONFIG_FILE),
+            "exclude = ['/usr/share/doc', '**/__pycache__']\ncommit-template = 'layer {index}: {command}'\n",
+        )?;
+        let config = ConfigFile::load(&output_dir.path().join(CONFIG_FILE))?.unwrap();
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image)),
+            Notifier::new(0),
+        )
+        .with_exclude(PathFilter::new(&config.exclude))
+        .with_commit_message_template(config.commit_template)
+        .convert("app:latest", output_dir.path())?;
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&image_branch(&repo)?)?;
+        assert!(repo
+            .commit_message(commits[0])?
+            .starts_with("layer 1: RUN install"));
+        assert!(repo
+            .read_file_from_commit(commits[0], "rootfs/usr/bin/app")
+            .is_ok());
+        for excluded in [
+            "rootfs/usr/share/doc/app/README",
+            "rootfs/app/lib/__pycache__/mod.pyc",
+            CONFIG_FILE,
+        ] {
+            assert!(repo
+                .read_file_from_commit(*commits.last().unwrap(), excluded)
+                .is_err());
+        }
+        // The config file survives the conversion, ready for the next one
+        assert!(output_dir.path().join(CONFIG_FILE).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_rootfs_dir() -> Result<()> {
+        let file = |name: &'static str| {
+            layer(move |b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, name, &b"x"[..]).unwrap();
+            })
+        };
+        let image = || image_with_layers(vec![("ADD a /", file("a")), ("ADD b /", file("b"))]);
+        let output_dir = TempDir::new()?;
+
+        let mut processor = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_direct_commits(true);
+        let options = ConvertOptions::new().with_rootfs_dir(Some("fs".to_string()));
+        let fs_summary =
+            processor.convert_with_options("app:latest", output_dir.path(), &options)?;
+        assert!(output_dir.path().join("fs/a").is_file());
+        assert!(!output_dir.path().join("rootfs").exists());
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let first = repo.get_branch_commits(&fs_summary.branch)?[0];
+        assert_eq!(repo.read_file_from_commit(first, ROOTFS_DIR_PATH)?, "fs\n");
+        assert!(repo.read_file_from_commit(first, "fs/a").is_ok());
+        // Read back through the recorded name
+        let exported = TempDir::new()?;
+        let image_tar = export_branch(
+            &repo,
+            &fs_summary.branch,
+            &exported.path().join("app.tar"),
+            &ExportOptions::default(),
+        )?;
+        assert_eq!(image_tar.layers.len(), 2);
+
+        // The same layers in rootfs/ don't build on the fs/ commits
+        let rootfs_summary = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .convert_with_options(
+            "app:latest",
+            output_dir.path(),
+            &ConvertOptions::new().with_branch_name(Some("app-rootfs".to_string())),
+        )?;
+        assert_eq!(rootfs_summary.layers_skipped, 0);
+        assert_ne!(rootfs_summary.layers[0].commit, fs_summary.layers[0].commit);
+        let first = repo.get_branch_commits("app-rootfs")?[0];
+        assert!(repo.read_file_from_commit(first, ROOTFS_DIR_PATH).is_err());
+        assert!(repo.read_file_from_commit(first, "rootfs/a").is_ok());
+
+        let error = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image()?)),
+            Notifier::new(0),
+        )
+        .with_rootfs_dir(Some(".git".to_string()))
+        .convert("app:latest", output_dir.path())
+        .unwrap_err();
+        assert!(matches!(error, Oci2GitError::InvalidInput(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_subdir_layout() -> Result<()> {
+        let file = |name: &'static str| {
+            layer(move |b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, name, &b"x"[..]).unwrap();
+            })
+        };
+        let convert = |layers: Vec<(&str, Vec<u8>)>, output: &Path| {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image_with_layers(layers)?)),
+                Notifier::new(0),
+            )
+            .with_layout(Layout::Subdir)
+            .convert("app:latest", output)
+        };
+
+        let output_dir = TempDir::new()?;
+        let first = convert(vec![("ADD a /", file("a"))], output_dir.path())?;
+        assert_eq!(first.branch, SUBDIR_BRANCH);
+        assert_eq!(first.status, ConversionStatus::Converted);
+        let repo = GitRepo::open(output_dir.path())?;
+        // No branch of its own and no README branch
+        assert_eq!(repo.get_all_branches()?, vec![SUBDIR_BRANCH]);
+        let commits = repo.get_branch_commits(SUBDIR_BRANCH)?;
+        assert_eq!(first.commit, commits.last().unwrap().to_string());
+        assert_eq!(first.layers.len(), 1);
+        assert_eq!(first.layers[0].commit, commits[0].to_string());
+        assert!(repo
+            .commit_message(commits[0])?
+            .starts_with("app-latest: 🟢 - ADD a /"));
+
+        let image_dir = output_dir.path().join("images/app-latest");
+        assert!(image_dir.join("rootfs/a").is_file());
+        assert!(image_dir.join(IMAGE_MD).is_file());
+        assert!(!output_dir.path().join("rootfs").exists());
+        assert!(!output_dir.path().join(IMAGE_MD).exists());
+
+        // Converted again with another layer, the directory is rebuilt on top
+        let second = convert(
+            vec![("ADD a /", file("a")), ("ADD b /", file("b"))],
+            output_dir.path(),
+        )?;
+        assert_eq!(second.status, ConversionStatus::Converted);
+        assert_eq!(second.layers.len(), 2);
+        let commits = repo.get_branch_commits(SUBDIR_BRANCH)?;
+        assert_eq!(commits.len(), 5);
+        assert_eq!(second.layers[0].commit, commits[2].to_string());
+        assert!(image_dir.join("rootfs/b").is_file());
+        assert_eq!(repo.get_all_branches()?, vec![SUBDIR_BRANCH]);
+
+        // Unchanged, nothing is committed
+        let third = convert(
+            vec![("ADD a /", file("a")), ("ADD b /", file("b"))],
+            output_dir.path(),
+        )?;
+        assert_eq!(third.status, ConversionStatus::SkippedDuplicate);
+        assert_eq!(repo.get_branch_commits(SUBDIR_BRANCH)?, commits);
+        assert_eq!(
+            third.layers,
+            second
+                .layers
+                .iter()
+                .map(|layer| LayerSummary {
+                    reused: true,
+                    ..layer.clone()
+                })
+                .collect::<Vec<_>>()
+        );
+        assert!(image_dir.join("rootfs/b").is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn test_conversion_summary() -> Result<()> {
+        let file = |name: &'static str| {
+            layer(move |b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, name, &b"x"[..]).unwrap();
+            })
+        };
+        let convert = |image: &str, layers: Vec<(&str, Vec<u8>)>, output: &Path| {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image_with_layers(layers)?)),
+                Notifier::new(0),
+            )
+            .convert(image, output)
+        };
+
+        let output_dir = TempDir::new()?;
+        let base = convert("app:1", vec![("ADD a /", file("a"))], output_dir.path())?;
+        assert_eq!(base.status, ConversionStatus::Converted);
+        assert_eq!(base.layers_skipped, 0);
+        assert!(base.bytes_processed > 0);
+        assert_eq!(base.layers.len(), 1);
+        assert!(!base.layers[0].reused);
+
+        // The second image builds on the first one's layer commit
+        let extended = convert(
+            "app:2",
+            vec![("ADD a /", file("a")), ("ADD b /", file("b"))],
+            output_dir.path(),
+        )?;
+        assert_ne!(extended.branch, base.branch);
+        assert_eq!(extended.layers_skipped, 1);
+        assert_eq!(extended.layers.len(), 2);
+        assert!(extended.layers[0].reused);
+        assert_eq!(extended.layers[0].commit, base.layers[0].commit);
+        assert_eq!(extended.layers[1].index, 2);
+        assert!(!extended.layers[1].reused);
+        // Only the layer applied has timings
+        let timed: Vec<_> = extended.timings.layers.iter().map(|l| l.index).collect();
+        assert_eq!(timed, vec![2]);
+        assert_eq!(extended.timings.layers[0].digest, extended.layers[1].digest);
+        let repo = GitRepo::open(output_dir.path())?;
+        assert_eq!(
+            extended.commit,
+            repo.get_branch_commits(&extended.branch)?
+                .last()
+                .unwrap()
+                .to_string()
+        );
+
+        let again = convert("app:1", vec![("ADD a /", file("a"))], output_dir.path())?;
+        assert_eq!(again.status, ConversionStatus::SkippedDuplicate);
+        assert_eq!(again.commit, base.commit);
+        assert_eq!(again.bytes_processed, 0);
+
+        // --force converts the duplicate again, on a recreated branch
+        let forced = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image_with_layers(vec![(
+                "ADD a /",
+                file("a"),
+            )])?)),
+            Notifier::new(0),
+        )
+        .with_force(true)
+        .convert("app:1", output_dir.path())?;
+        assert_eq!(forced.status, ConversionStatus::Converted);
+        assert_eq!(forced.branch, base.branch);
+        assert_eq!(forced.layers_skipped, 0);
+        assert!(forced.bytes_processed > 0);
+        assert!(!forced.layers[0].reused);
+        assert_eq!(repo.get_branch_commits(&forced.branch)?.len(), 2);
+        assert_eq!(
+            repo.read_file_from_commit(repo.get_branch_commits(&forced.branch)?[0], "rootfs/a")?,
+            "x"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_old_branches() -> Result<()> {
+        let file = |name: &'static str| {
+            layer(move |b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, name, &b"x"[..]).unwrap();
+            })
+        };
+        let output_dir = TempDir::new()?;
+        for image in ["app:1", "app:2"] {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image_with_layers(vec![(
+                    "ADD file /",
+                    file("a"),
+                )])?)),
+                Notifier::new(0),
+            )
+            .convert(image, output_dir.path())?;
+        }
+        let repo = GitRepo::open(output_dir.path())?;
+        let images = list::list(&repo)?;
+        assert_eq!(images.len(), 2);
+
+        let options = PruneOptions {
+            keep_last: Some(1),
+            older_than: None,
+            dry_run: true,
+        };
+        let report = prune::prune(&repo, &options, chrono::Utc::now())?;
+        assert_eq!(report.deleted.len(), 1);
+        assert!(report.repack.is_none());
+        assert_eq!(list::list(&repo)?.len(), 2);
+
+        let report = prune::prune(
+            &repo,
+            &PruneOptions {
+                dry_run: false,
+                ..options
+            },
+            chrono::Utc::now(),
+        )?;
+        assert!(!repo.branch_exists(&report.deleted[0]));
+        assert_eq!(report.kept.len(), 1);
+        let repack = report.repack.unwrap();
+        assert!(repack.objects > 0);
+
+        // The kept branch is intact, loose objects are gone
+        let repo = GitRepo::open(output_dir.path())?;
+        let tip = *repo.get_branch_commits(&report.kept[0])?.last().unwrap();
+        assert_eq!(repo.read_file_from_commit(tip, "rootfs/a")?, "x");
+        assert!(repo.read_file_from_commit(tip, IMAGE_MD).is_ok());
+        let loose = std::fs::read_dir(output_dir.path().join(".git/objects"))?
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .is_ok_and(|entry| entry.file_name().len() == 2)
+            })
+            .count();
+        assert_eq!(loose, 0);
+        let readme = repo.read_file_from_commit(
+            *repo.get_branch_commits("main")?.last().unwrap(),
+            "README.md",
+        )?;
+        assert!(!readme.contains(&report.deleted[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_after_conversion() -> Result<()> {
+        let file = layer(|b| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            b.append_data(&mut header, "a", &b"x"[..]).unwrap();
+        });
+        let output_dir = TempDir::new()?;
+        ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image_with_layers(vec![(
+                "ADD file /",
+                file,
+            )])?)),
+            Notifier::new(0),
+        )
+        .with_gc(true)
+        .convert("app:1", output_dir.path())?;
+
+        let objects = output_dir.path().join(".git/objects");
+        let loose = std::fs::read_dir(&objects)?
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .is_ok_and(|entry| entry.file_name().len() == 2)
+            })
+            .count();
+        assert_eq!(loose, 0);
+        let packs = std::fs::read_dir(objects.join("pack"))?
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .is_ok_and(|entry| entry.path().extension().is_some_and(|ext| ext == "pack"))
+            })
+            .count();
+        assert_eq!(packs, 1);
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let branch = list::list(&repo)?.remove(0).branch;
+        let tip = *repo.get_branch_commits(&branch)?.last().unwrap();
+        assert_eq!(repo.read_file_from_commit(tip, "rootfs/a")?, "x");
+        Ok(())
+    }
+
+    #[test]
+    fn test_direct_commits_match_worktree_commits() -> Result<()> {
+        /// Tree ids of the commits of the only image branch, oldest first
+        fn commit_trees(output: &Path) -> Result<Vec<git2::Oid>> {
+            let repo = GitRepo::open(output)?;
+            let branch = list::list(&repo)?.remove(0).branch;
+            repo.get_branch_commits(&branch)?
+                .into_iter()
+                .map(|commit| Ok(repo.repo.find_commit(commit)?.tree_id()))
+                .collect()
+        }
+        let entry = |b: &mut tar_rs::Builder<Vec<u8>>,
+                     path: &str,
+                     entry_type: tar_rs::EntryType,
+                     link: Option<&str>,
+                     mode: u32,
+                     data: &[u8]| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(data.len() as u64);
+            header.set_mode(mode);
+            if let Some(link) = link {
+                header.set_link_name(link).unwrap();
+            }
+            b.append_data(&mut header, path, data).unwrap();
+        };
+        let image = image_with_layers(vec![
+            (
+                "ADD rootfs /",
+                layer(|b| {
+                    entry(
+                        b,
+                        "usr/lib/",
+                        tar_rs::EntryType::Directory,
+                        None,
+                        0o755,
+                        b"",
+                    );
+                    entry(
+                        b,
+                        "usr/lib/libc.so",
+                        tar_rs::EntryType::Regular,
+                        None,
+                        0o644,
+                        b"libc",
+                    );
+                    entry(
+                        b,
+                        "lib",
+                        tar_rs::EntryType::Symlink,
+                        Some("usr/lib"),
+                        0o777,
+                        b"",
+                    );
+                    entry(
+                        b,
+                        "bin/tool",
+                        tar_rs::EntryType::Regular,
+                        None,
+                        0o755,
+                        b"tool",
+                    );
+                    entry(
+                        b,
+                        "etc/app/a.conf",
+                        tar_rs::EntryType::Regular,
+                        None,
+                        0o644,
+                        b"a",
+                    );
+                    entry(
+                        b,
+                        "etc/app/b.conf",
+                        tar_rs::EntryType::Regular,
+                        None,
+                        0o644,
+                        b"b",
+                    );
+                    entry(b, "empty/", tar_rs::EntryType::Directory, None, 0o755, b"");
+                }),
+            ),
+            (
+                "RUN update",
+                layer(|b| {
+                    // Through the symlink into usr/lib
+                    entry(
+                        b,
+                        "lib/libm.so",
+                        tar_rs::EntryType::Regular,
+                        None,
+                        0o644,
+                        b"libm",
+                    );
+                    entry(
+                        b,
+                        "bin/alias",
+                        tar_rs::EntryType::Link,
+                        Some("bin/tool"),
+                        0o755,
+                        b"",
+                    );
+                    entry(
+                        b,
+                        "etc/app/.wh..wh..opq",
+                        tar_rs::EntryType::Regular,
+                        None,
+                        0o644,
+                        b"",
+                    );
+                    entry(
+                        b,
+                        "etc/app/c.conf",
+                        tar_rs::EntryType::Regular,
+                        None,
+                        0o644,
+                        b"c",
+                    );
+                    entry(
+                        b,
+                        "usr/lib/.wh.libc.so",
+                        tar_rs::EntryType::Regular,
+                        None,
+                        0o644,
+                        b"",
+                    );
+                    entry(
+                        b,
+                        "etc/app/b.conf",
+                        tar_rs::EntryType::Symlink,
+                        Some("/usr/lib/libm.so"),
+                        0o777,
+                        b"",
+                    );
+                }),
+            ),
+        ])?;
+
+        let output_dir = TempDir::new()?;
+        let convert = |direct: bool| {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image.clone())),
+                Notifier::new(0),
+            )
+            .with_fs_metadata(true)
+            .with_force(true)
+            .with_direct_commits(direct)
+            .convert("app:latest", output_dir.path())
+        };
+        convert(false)?;
+        let worktree = commit_trees(output_dir.path())?;
+        // Recreated from scratch, into the same directory: symlink targets are the same
+        convert(true)?;
+        let direct = commit_trees(output_dir.path())?;
+        assert_eq!(direct, worktree);
+
+        // rootfs/ is checked out and nothing is left unstaged
+        let repo = GitRepo::open(output_dir.path())?;
+        assert!(repo.repo.statuses(None)?.is_empty());
+        let rootfs = output_dir.path().join("rootfs");
+        assert_eq!(
+            std::fs::read_to_string(rootfs.join("usr/lib/libm.so"))?,
+            "libm"
+        );
+        assert_eq!(std::fs::read_to_string(rootfs.join("bin/alias"))?, "tool");
+        assert!(!rootfs.join("etc/app/a.conf").exists());
+        assert_eq!(
+            std::fs::read_to_string(rootfs.join("etc/app/b.conf"))?,
+            "libm"
+        );
+
+        // The fixture image, too, and with files written and hashed on several threads
+        let output_dir = TempDir::new()?;
+        let convert = |direct: bool, jobs: usize| {
+            ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+                .with_force(true)
+                .with_direct_commits(direct)
+                .with_jobs(jobs)
+                .convert(FIXTURE_TAR_PATH, output_dir.path())
+                .map_err(anyhow::Error::from)
+        };
+        convert(false, 1)?;
+        let worktree = commit_trees(output_dir.path())?;
+        convert(true, 1)?;
+        assert_eq!(commit_trees(output_dir.path())?, worktree);
+        convert(false, 4)?;
+        assert_eq!(commit_trees(output_dir.path())?, worktree);
+        assert!(GitRepo::open(output_dir.path())?
+            .repo
+            .statuses(None)?
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_image_symlink_targets() -> Result<()> {
+        let entry =
+            |b: &mut tar_rs::Builder<Vec<u8>>, path: &str, link: Option<&str>, data: &[u8]| {
+                let mut header = tar_rs::Header::new_gnu();
+                match link {
+                    Some(link) => {
+                        header.set_entry_type(tar_rs::EntryType::Symlink);
+                        header.set_link_name(link).unwrap();
+                        header.set_mode(0o777);
+                    }
+                    None if path.ends_with('/') => {
+                        header.set_entry_type(tar_rs::EntryType::Directory);
+                        header.set_mode(0o755);
+                    }
+                    None => header.set_mode(0o644),
+                }
+                header.set_size(data.len() as u64);
+                b.append_data(&mut header, path, data).unwrap();
+            };
+        let base = || {
+            layer(|b| {
+                entry(b, "usr/bin/python3.12", None, b"python");
+                entry(b, "usr/bin/python3", Some("python3.12"), b"");
+                entry(b, "usr/bin/python", Some("/usr/bin/python3"), b"");
+                entry(b, "opt/", None, b"");
+                entry(b, "usr/local", Some("/opt"), b"");
+                entry(b, "etc/escape", Some("../../../x"), b"");
+            })
+        };
+        let app = || {
+            layer(|b| {
+                // Through the absolute symlink of the first layer into opt/
+                entry(b, "usr/local/app", None, b"app");
+                entry(b, "usr/local/bin/", None, b"");
+                entry(b, "usr/local/bin/tool", Some("../app"), b"");
+            })
+        };
+        let convert = |layers: Vec<(&str, Vec<u8>)>,
+                       output: &Path,
+                       branch: &str,
+                       symlink_targets: SymlinkTargets,
+                       direct: bool| {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image_with_layers(layers)?)),
+                Notifier::new(0),
+            )
+            .with_symlink_targets(symlink_targets)
+            .with_direct_commits(direct)
+            .convert_with_options(
+                "app:latest",
+                output,
+                &ConvertOptions::new().with_branch_name(Some(branch.to_string())),
+            )
+            .map_err(anyhow::Error::from)
+        };
+        let trees = |output: &Path, branch: &str| -> Result<Vec<git2::Oid>> {
+            let repo = GitRepo::open(output)?;
+            repo.get_branch_commits(branch)?
+                .into_iter()
+                .map(|commit| Ok(repo.repo.find_commit(commit)?.tree_id()))
+                .collect()
+        };
+
+        // The app builds on the base, checked out with in-image targets
+        let output_dir = TempDir::new()?;
+        convert(
+            vec![("ADD base /", base())],
+            output_dir.path(),
+            "base",
+            SymlinkTargets::Image,
+            false,
+        )?;
+        let summary = convert(
+            vec![("ADD base /", base()), ("ADD app /", app())],
+            output_dir.path(),
+            "app",
+            SymlinkTargets::Image,
+            false,
+        )?;
+        assert_eq!(summary.layers_skipped, 1);
+
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits("app")?;
+        assert_eq!(
+            repo.read_file_from_commit(commits[0], SYMLINK_TARGETS_PATH)?,
+            "image\n"
+        );
+        let tip = *commits.last().unwrap();
+        for (path, target) in [
+            ("rootfs/usr/bin/python3", "python3.12"),
+            ("rootfs/usr/bin/python", "/usr/bin/python3"),
+            ("rootfs/usr/local", "/opt"),
+            ("rootfs/etc/escape", "/x"),
+            ("rootfs/opt/bin/tool", "/usr/local/app"),
+            ("rootfs/opt/app", "app"),
+        ] {
+            assert_eq!(repo.read_file_from_commit(tip, path)?, target, "{path}");
+        }
+        let rootfs = output_dir.path().join("rootfs");
+        assert_eq!(
+            std::fs::read_link(rootfs.join("usr/bin/python"))?,
+            Path::new("/usr/bin/python3")
+        );
+        assert!(repo.repo.statuses(None)?.is_empty());
+
+        // Elsewhere, and applied to the tree directly, the commits are the same
+        let elsewhere = TempDir::new()?;
+        convert(
+            vec![("ADD base /", base())],
+            elsewhere.path(),
+            "base",
+            SymlinkTargets::Image,
+            true,
+        )?;
+        convert(
+            vec![("ADD base /", base()), ("ADD app /", app())],
+            elsewhere.path(),
+            "app",
+            SymlinkTargets::Image,
+            true,
+        )?;
+        assert_eq!(
+            trees(elsewhere.path(), "app")?,
+            trees(output_dir.path(), "app")?
+        );
+
+        // Host targets don't build on them
+        let summary = convert(
+            vec![("ADD base /", base()), ("ADD app /", app())],
+            output_dir.path(),
+            "app-host",
+            SymlinkTargets::Host,
+            false,
+        )?;
+        assert_eq!(summary.layers_skipped, 0);
+        let tip = *repo.get_branch_commits("app-host")?.last().unwrap();
+        assert!(repo
+            .read_file_from_commit(tip, SYMLINK_TARGETS_PATH)
+            .is_err());
+        assert!(repo
+            .read_file_from_commit(tip, "rootfs/usr/bin/python")?
+            .ends_with("/rootfs/usr/bin/python3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_errors() -> Result<()> {
+        let output_dir = TempDir::new()?;
+        let missing = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .convert("/nonexistent/image.tar", output_dir.path())
+            .unwrap_err();
+        assert!(matches!(missing, Oci2GitError::Source(_)), "{missing:?}");
+        assert_eq!(missing.exit_code(), 4);
+
+        // A corrupt layer fails the extraction stage
+        let corrupt = ImageProcessor::new(
+            StreamSource::new(std::io::Cursor::new(image_with_layers(vec![(
+                "ADD a /",
+                b"not a tarball".to_vec(),
+            )])?)),
+            Notifier::new(0),
+        )
+        .convert("app:latest", output_dir.path())
+        .unwrap_err();
+        assert!(
+            matches!(corrupt, Oci2GitError::Extraction(_)),
+            "{corrupt:?}"
+        );
+        assert_eq!(corrupt.exit_code(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_estargz_layer() -> Result<()> {
+        use flate2::{write::GzEncoder, Compression};
+        use sha2::{Digest, Sha256};
+        use std::io::Write;
+        let gzip = |data: &[u8]| -> Result<Vec<u8>> {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        };
+        let append = |b: &mut tar_rs::Builder<Vec<u8>>, path: &str, data: &[u8]| {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            b.append_data(&mut header, path, data).unwrap();
+        };
+
+        // eStargz as `nerdctl image convert --estargz` writes it: the files, then the TOC
+        // in a gzip member of its own, then the footer pointing at it
+        let mut files = tar_rs::Builder::new(Vec::new());
+        append(&mut files, estargz::NO_PREFETCH_LANDMARK, &[0x0f]);
+        append(&mut files, "bin/sh", b"#!/bin/true\n");
+        let toc = serde_json::to_vec(&serde_json::json!({"version": 1, "entries": [
+            {"name": estargz::NO_PREFETCH_LANDMARK, "type": "reg", "size": 1},
+            {"name": "bin/", "type": "dir"},
+            {"name": "bin/sh", "type": "reg", "size": 12},
+        ]}))?;
+        let mut toc_tar = tar_rs::Builder::new(Vec::new());
+        append(&mut toc_tar, estargz::TOC_NAME, &toc);
+        let toc_tar = toc_tar.into_inner()?;
+        let uncompressed = [files.get_ref().as_slice(), &toc_tar].concat();
+        let mut blob = gzip(files.get_ref())?;
+        let toc_offset = blob.len();
+        blob.extend(gzip(&toc_tar)?);
+        blob.extend([
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 26, 0, b'S', b'G', 22, 0,
+        ]);
+        blob.extend(format!("{toc_offset:016x}STARGZ").into_bytes());
+        blob.extend([0x01, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let digest = |data: &[u8]| format!("sha256:{:x}", Sha256::digest(data));
+        let config = serde_json::to_vec(&serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": {"type": "layers", "diff_ids": [digest(&uncompressed)]},
+            "history": [{"created": "2024-01-01T00:00:00Z", "created_by": "COPY sh /bin/"}],
+        }))?;
+        let manifest = serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "config": {"digest": digest(&config), "size": config.len()},
+            "layers": [{"digest": digest(&blob), "size": blob.len(),
+                        "annotations": {estargz::TOC_DIGEST_ANNOTATION: digest(&toc)}}],
+        }))?;
+        let index = serde_json::json!({"schemaVersion": 2, "manifests": [
+            {"digest": digest(&manifest), "size": manifest.len()},
+        ]});
+        let mut builder = tar_rs::Builder::new(Vec::new());
+        append(&mut builder, "index.json", &serde_json::to_vec(&index)?);
+        for data in [&config, &manifest, &blob] {
+            append(
+                &mut builder,
+                &format!("blobs/sha256/{}", &digest(data)[7..]),
+                data,
+            );
+        }
+        let images = TempDir::new()?;
+        let archive = images.path().join("app.tar");
+        std::fs::write(&archive, builder.into_inner()?)?;
+
+        // Every member is applied, the bookkeeping entries are left out
+        let output_dir = TempDir::new()?;
+        let summary = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .convert(archive.to_str().unwrap(), output_dir.path())?;
+        let repo = GitRepo::open(output_dir.path())?;
+        let tip = *repo.get_branch_commits(&summary.branch)?.last().unwrap();
+        assert_eq!(
+            repo.read_file_from_commit(tip, "rootfs/bin/sh")?,
+            "#!/bin/true\n"
+        );
+        assert!(repo
+            .read_file_from_commit(tip, &format!("rootfs/{}", estargz::TOC_NAME))
+            .is_err());
+        assert!(repo
+            .read_file_from_commit(tip, &format!("rootfs/{}", estargz::NO_PREFETCH_LANDMARK))
+            .is_err());
+        assert!(repo
+            .read_file_from_commit(tip, estargz::LAYER_FILES_PATH)
+            .is_err());
+
+        // Without the blob, the TOC still lists the files of the layer
+        let output_dir = TempDir::new()?;
+        let summary = ImageProcessor::new(TarSource::new()?, Notifier::new(0))
+            .with_metadata_only(true)
+            .convert(archive.to_str().unwrap(), output_dir.path())?;
+        let repo = GitRepo::open(output_dir.path())?;
+        let commits = repo.get_branch_commits(&summary.branch)?;
+        assert_eq!(
+            repo.read_file_from_commit(commits[0], estargz::LAYER_FILES_PATH)?,
+            "/bin/\n/bin/sh\n"
+        );
+        assert!(repo
+            .read_file_from_commit(commits[0], "rootfs/bin/sh")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_conversion_cache() -> Result<()> {
+        use sha2::{Digest, Sha256};
+        let file = |path: &str| {
+            layer(|b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                b.append_data(&mut header, path, &b"x"[..]).unwrap();
+            })
+        };
+        // The config stored under its digest, which becomes the image ID
+        let config = serde_json::to_vec(&serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": {"type": "layers", "diff_ids": []},
+            "history": [
+                {"created": "2024-01-01T00:00:00Z", "created_by": "ADD base /"},
+                {"created": "2024-01-01T00:00:00Z", "created_by": "ADD app /"},
+            ],
+        }))?;
+        let config_path = format!("{:x}.json", Sha256::digest(&config));
+        let manifest = serde_json::json!([{
+            "Config": config_path,
+            "RepoTags": ["app:latest"],
+            "Layers": ["layer0.tar", "layer1.tar"],
+        }]);
+        let mut builder = tar_rs::Builder::new(Vec::new());
+        for (path, data) in [
+            (config_path, config),
+            ("manifest.json".to_string(), serde_json::to_vec(&manifest)?),
+            ("layer0.tar".to_string(), file("base")),
+            ("layer1.tar".to_string(), file("app")),
+        ] {
+            let mut header = tar_rs::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, data.as_slice())?;
+        }
+        let image = builder.into_inner()?;
+        let cache_dir = TempDir::new()?;
+        let convert = |output: &Path| {
+            ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image.clone())),
+                Notifier::new(0),
+            )
+            .with_conversion_cache(Some(ConversionCache::new(cache_dir.path())))
+            .convert("app.tar", output)
+        };
+
+        let first = TempDir::new()?;
+        let converted = convert(first.path())?;
+        assert_eq!(converted.layers_skipped, 0);
+        let first_commits = GitRepo::open(first.path())?.get_branch_commits(&converted.branch)?;
+
+        // Another repository fetches the layer commits instead of committing them again
+        let second = TempDir::new()?;
+        let summary = convert(second.path())?;
+        assert_eq!(summary.branch, converted.branch);
+        assert_eq!(summary.layers_skipped, 2);
+        let repo = GitRepo::open(second.path())?;
+        let commits = repo.get_branch_commits(&summary.branch)?;
+        assert_eq!(commits[..2], first_commits[..2]);
+        assert_eq!(repo.read_file_from_commit(commits[2], "rootfs/app")?, "x");
+        assert!(repo
+            .get_all_branches()?
+            .iter()
+            .all(|branch| !branch.starts_with(SEED_BRANCH_PREFIX)));
+
+        // The cache now points at the second repository; without it, nothing is fetched
+        std::fs::remove_dir_all(second.path())?;
+        let third = TempDir::new()?;
+        assert_eq!(convert(third.path())?.layers_skipped, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkout_worktrees() -> Result<()> {
+        let file = |content: &'static [u8]| {
+            layer(move |b| {
+                let mut header = tar_rs::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                b.append_data(&mut header, "etc/version", content).unwrap();
+            })
+        };
+        let output_dir = TempDir::new()?;
+        let mut branches = Vec::new();
+        // Layer digests are made up: app:2 shares the first layer and overwrites the file
+        for (image, contents) in [("app:1", vec![&b"1"[..]]), ("app:2", vec![b"1", b"2"])] {
+            let layers = contents
+                .into_iter()
+                .map(|content| ("ADD version /etc/", file(content)))
+                .collect();
+            let summary = ImageProcessor::new(
+                StreamSource::new(std::io::Cursor::new(image_with_layers(layers)?)),
+                Notifier::new(0),
+            )
+            .convert(image, output_dir.path())?;
+            branches.push(summary.branch);
+        }
+
+        // Both images side by side, next to the repository
+        let checkouts = TempDir::new()?;
+        let repo = GitRepo::open(output_dir.path())?;
+        let mut rootfs = Vec::new();
+        for (branch, dir) in branches.iter().zip(["app-1", "app-2"]) {
+            let worktree = worktrees::checkout(&repo, branch, &checkouts.path().join(dir))?;
+            rootfs.push(worktree.rootfs.unwrap());
+        }
+        assert_eq!(std::fs::read_to_string(rootfs[0].join("etc/version"))?, "1");
+        assert_eq!(std::fs::read_to_string(rootfs[1].join("etc/version"))?, "2");
+        assert_eq!(worktrees::list(&repo)?.len(), 2);
+
+        // The worktree of a pruned branch keeps its commit through the repack
+        let report = prune::prune(
+            &repo,
+            &PruneOptions {
+                keep_last: Some(1),
+                older_than: None,
+                dry_run: false,
+            },
+            chrono::Utc::now(),
+        )?;
+        assert_eq!(report.deleted.len(), 1);
+        for worktree in worktrees::list(&repo)? {
+            let commit = git2::Oid::from_str(worktree.commit.as_deref().unwrap())?;
+            assert!(repo
+                .read_file_from_commit(commit, "rootfs/etc/version")
+                .is_ok());
+        }
+
+        assert_eq!(worktrees::remove_all(&repo)?.len(), 2);
+        assert!(!rootfs[0].exists() && !rootfs[1].exists());
+        assert!(worktrees::list(&repo)?.is_empty());
+        Ok(())
+    }
 }